@@ -0,0 +1,52 @@
+//! Embeds the MCP server with a preconfigured connection and drives it with
+//! JSON-RPC messages directly, the way a host application would, instead of
+//! serving stdio. It also adds a tool of its own next to the built-in ones.
+//!
+//!     cargo run --example embed_server
+
+use mcp_test::prelude::*;
+use serde_json::json;
+use std::sync::Arc;
+use std::time::Duration;
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let config = ClickHouseConfig::from_env()?;
+    let client = ClickHouseClient::new(&config.url, &config.database, &config.username, &config.password)
+        .with_retry_config(1, Duration::from_millis(200))
+        .with_case_insensitive_resolution(true);
+    let connection = Arc::new(ConnectionManager::new(client));
+    // Tool calls report the connection error themselves if the server is down
+    if let Err(e) = connection.connect().await {
+        println!("ClickHouse is unreachable ({}), tool calls will return errors", e);
+    }
+
+    let mut server = McpServer::new()
+        .with_connection(connection)
+        .with_tool_prefix("ch_")?
+        .with_retry_budget(Duration::from_secs(5));
+    server.register_tool(
+        "app_version",
+        json!({"description": "Show the version of the host application"}),
+        |_arguments| async { Ok(format!("embed-example {}\n", env!("CARGO_PKG_VERSION"))) },
+    )?;
+
+    let messages = [
+        json!({"jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {"protocolVersion": "2024-11-05", "capabilities": {}, "clientInfo": {"name": "embed-example", "version": "0.1.0"}}}),
+        json!({"jsonrpc": "2.0", "method": "notifications/initialized"}),
+        json!({"jsonrpc": "2.0", "id": 2, "method": "tools/list"}),
+        json!({"jsonrpc": "2.0", "id": 3, "method": "tools/call", "params": {"name": "ch_list_databases", "arguments": {}}}),
+        json!({"jsonrpc": "2.0", "id": 4, "method": "tools/call", "params": {"name": "ch_app_version"}}),
+    ];
+
+    for message in messages {
+        let line = message.to_string();
+        println!("-> {}", line);
+        match server.handle_line(&line).await? {
+            Some(response) => println!("<- {}\n", response),
+            None => println!("   (notification, no response)\n"),
+        }
+    }
+
+    Ok(())
+}
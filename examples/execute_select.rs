@@ -0,0 +1,41 @@
+//! Runs a read-only query with row, size and time limits and prints the rows
+//! as JSON.
+//!
+//!     cargo run --example execute_select -- "SELECT name, engine FROM system.tables"
+
+use mcp_test::prelude::*;
+use mcp_test::{build_select_query, redact_url};
+use std::time::Duration;
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let sql = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| "SELECT name, value FROM system.settings WHERE changed".to_string());
+    let limits = SelectLimits {
+        max_rows: 20,
+        max_result_bytes: Some(1024 * 1024),
+        timeout: Some(Duration::from_secs(5)),
+    };
+
+    let config = ClickHouseConfig::from_env()?;
+    let client = ClickHouseClient::new(&config.url, &config.database, &config.username, &config.password)
+        .with_retry_config(1, Duration::from_millis(200));
+
+    if let Err(e) = client.health_check().await {
+        println!("ClickHouse at {} is unreachable: {}", redact_url(&config.url), e);
+        println!("With a server this example would send:");
+        println!("  {}", build_select_query(&sql, &limits)?);
+        return Ok(());
+    }
+
+    let result = client.execute_select(&sql, &limits).await?;
+    for row in &result.rows {
+        println!("{}", row);
+    }
+    if result.truncated {
+        println!("(more than {} rows, output truncated)", limits.max_rows);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,41 @@
+//! Lists every table in the configured database with its columns.
+//!
+//! Reads CLICKHOUSE_URL, CLICKHOUSE_DATABASE, CLICKHOUSE_USERNAME and
+//! CLICKHOUSE_PASSWORD like the server does:
+//!
+//!     CLICKHOUSE_URL=http://localhost:8123 cargo run --example list_schemas
+
+use mcp_test::prelude::*;
+use mcp_test::redact_url;
+use std::time::Duration;
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let config = ClickHouseConfig::from_env()?;
+    // Fail fast instead of the default three retries with exponential backoff
+    let client = ClickHouseClient::new(&config.url, &config.database, &config.username, &config.password)
+        .with_retry_config(1, Duration::from_millis(200))
+        .with_query_timeout(Duration::from_secs(10));
+
+    if let Err(e) = client.health_check().await {
+        println!("ClickHouse at {} is unreachable: {}", redact_url(&config.url), e);
+        println!("With a server this example would:");
+        println!("  1. list the tables of '{}' (list_tables)", config.database);
+        println!("  2. read the columns of each table (get_table_schema)");
+        return Ok(());
+    }
+
+    for table in client.list_tables(&config.database).await? {
+        println!("{}.{} ({})", table.database, table.name, table.engine);
+        match client.get_table_schema(&table.database, &table.name).await {
+            Ok(columns) => {
+                for column in columns {
+                    println!("  {} {}", column.name, column.r#type);
+                }
+            }
+            Err(e) => println!("  could not read columns: {}", e),
+        }
+    }
+
+    Ok(())
+}
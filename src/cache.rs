@@ -0,0 +1,184 @@
+//! Client-side cache of table schemas, so tools looking at the same table
+//! repeatedly don't query `system.columns` each time.
+
+use crate::ColumnInfo;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// The most schemas kept; the oldest entry is evicted to make room.
+pub const MAX_SCHEMA_CACHE_ENTRIES: usize = 1000;
+
+/// Which cached schemas to invalidate.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CacheScope {
+    All,
+    Database(String),
+    Table { database: String, table: String },
+}
+
+impl CacheScope {
+    fn matches(&self, database: &str, table: &str) -> bool {
+        match self {
+            CacheScope::All => true,
+            CacheScope::Database(scope) => scope == database,
+            CacheScope::Table { database: scope_database, table: scope_table } => {
+                scope_database == database && scope_table == table
+            }
+        }
+    }
+}
+
+/// A schema as cached: the names it was resolved to and when it was fetched.
+#[derive(Debug, Clone)]
+pub struct CachedSchema {
+    pub database: String,
+    pub table: String,
+    pub columns: Vec<ColumnInfo>,
+    pub fetched_at: DateTime<Utc>,
+    loaded: Instant,
+}
+
+impl CachedSchema {
+    /// A schema fetched just now.
+    pub fn new(database: &str, table: &str, columns: Vec<ColumnInfo>) -> Self {
+        Self {
+            database: database.to_string(),
+            table: table.to_string(),
+            columns,
+            fetched_at: Utc::now(),
+            loaded: Instant::now(),
+        }
+    }
+}
+
+/// Counters describing how well the cache is doing.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct CacheStats {
+    pub entries: usize,
+    pub hits: u64,
+    pub misses: u64,
+    pub invalidations: u64,
+    pub oldest_entry_age: Option<Duration>,
+}
+
+impl CacheStats {
+    /// The share of lookups answered from the cache, `None` before any.
+    pub fn hit_rate(&self) -> Option<f64> {
+        let lookups = self.hits + self.misses;
+        (lookups > 0).then(|| self.hits as f64 / lookups as f64)
+    }
+}
+
+/// Schemas keyed by the database and table names they were requested
+/// with, which may differ in case from the resolved names they hold.
+/// Entries expire after the TTL.
+#[derive(Debug)]
+pub struct SchemaCache {
+    ttl: Duration,
+    entries: Mutex<HashMap<(String, String), CachedSchema>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    invalidations: AtomicU64,
+}
+
+impl SchemaCache {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            entries: Mutex::new(HashMap::new()),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            invalidations: AtomicU64::new(0),
+        }
+    }
+
+    /// Returns the cached schema unless it is missing or expired, counting
+    /// the lookup as a hit or miss. Expired entries are kept for
+    /// [`get_stale`](Self::get_stale) until replaced or evicted.
+    pub fn get(&self, database: &str, table: &str) -> Option<CachedSchema> {
+        let entries = self.entries.lock().unwrap();
+        match entries.get(&(database.to_string(), table.to_string())) {
+            Some(entry) if self.is_fresh(entry) => {
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                Some(entry.clone())
+            }
+            _ => {
+                self.misses.fetch_add(1, Ordering::Relaxed);
+                None
+            }
+        }
+    }
+
+    /// Returns the cached schema even if it has expired, for when ClickHouse
+    /// can't be asked. Not counted as a hit or miss.
+    pub fn get_stale(&self, database: &str, table: &str) -> Option<CachedSchema> {
+        self.entries.lock().unwrap().get(&(database.to_string(), table.to_string())).cloned()
+    }
+
+    fn is_fresh(&self, entry: &CachedSchema) -> bool {
+        entry.loaded.elapsed() < self.ttl
+    }
+
+    /// Caches `schema` under the names it was requested with.
+    pub fn insert(&self, requested: (&str, &str), schema: CachedSchema) {
+        let mut entries = self.entries.lock().unwrap();
+        let key = (requested.0.to_string(), requested.1.to_string());
+        if entries.len() >= MAX_SCHEMA_CACHE_ENTRIES && !entries.contains_key(&key) {
+            let oldest = entries.iter().min_by_key(|(_, entry)| entry.loaded).map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                entries.remove(&oldest);
+            }
+        }
+        entries.insert(key, schema);
+    }
+
+    /// Drops the entries in `scope`, matched against both the requested
+    /// and the resolved names, and returns the resolved `(database, table)`
+    /// names of the dropped entries without duplicates.
+    pub fn invalidate(&self, scope: &CacheScope) -> Vec<(String, String)> {
+        let mut entries = self.entries.lock().unwrap();
+        let mut removed: Vec<(String, String)> = Vec::new();
+        entries.retain(|(database, table), entry| {
+            if !scope.matches(database, table) && !scope.matches(&entry.database, &entry.table) {
+                return true;
+            }
+            let name = (entry.database.clone(), entry.table.clone());
+            if !removed.contains(&name) {
+                removed.push(name);
+            }
+            false
+        });
+        removed.sort();
+        self.invalidations.fetch_add(removed.len() as u64, Ordering::Relaxed);
+        removed
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        let entries = self.entries.lock().unwrap();
+        let fresh = || entries.values().filter(|entry| self.is_fresh(entry));
+        CacheStats {
+            entries: fresh().count(),
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            invalidations: self.invalidations.load(Ordering::Relaxed),
+            oldest_entry_age: fresh().map(|entry| entry.loaded.elapsed()).max(),
+        }
+    }
+}
+
+/// A schema re-fetched by [`ClickHouseClient::refresh_metadata`](crate::ClickHouseClient::refresh_metadata).
+#[derive(Debug)]
+pub struct RefreshedSchema {
+    pub database: String,
+    pub table: String,
+    /// Whether the schema was cached before the refresh.
+    pub was_cached: bool,
+    /// The fresh schema, or why it couldn't be fetched, e.g. because the
+    /// table was dropped.
+    pub outcome: Result<CachedSchema, crate::ClickHouseError>,
+}
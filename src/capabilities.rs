@@ -0,0 +1,164 @@
+//! Which optional parts of ClickHouse a server has, so tools that can't work
+//! on it aren't offered. Some system tables only exist with some
+//! configurations (`system.zookeeper` needs Keeper) or versions, and
+//! functions come and go between releases.
+//!
+//! The server probes for everything in [`TOOL_REQUIREMENTS`] when it
+//! connects and on `refresh_metadata`, and `tools/list` hides or marks the
+//! tools whose requirements are missing, see [`apply_capabilities`].
+
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeSet;
+use std::fmt;
+
+/// Something a tool needs from the server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Requirement {
+    /// A table of the `system` database, by name.
+    SystemTable(&'static str),
+    /// A function in `system.functions`, by name.
+    Function(&'static str),
+}
+
+impl fmt::Display for Requirement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Requirement::SystemTable(table) => write!(f, "system.{}", table),
+            Requirement::Function(function) => write!(f, "{}()", function),
+        }
+    }
+}
+
+/// The requirements of the tools that have any. Tools not listed work on
+/// every server.
+pub const TOOL_REQUIREMENTS: &[(&str, &[Requirement])] = &[
+    ("column_histogram", &[Requirement::Function("histogram")]),
+    ("insert_pressure", &[Requirement::SystemTable("merge_tree_settings")]),
+    ("keeper_info", &[Requirement::SystemTable("zookeeper")]),
+    ("recent_queries", &[Requirement::SystemTable("query_log")]),
+    ("server_metrics", &[Requirement::SystemTable("asynchronous_metrics")]),
+];
+
+/// What the description of an unavailable tool ends with when such tools
+/// are listed.
+pub const UNAVAILABLE_SUFFIX: &str = " (unavailable on this server)";
+
+/// The requirements of `tool`, empty for tools without any.
+pub fn tool_requirements(tool: &str) -> &'static [Requirement] {
+    TOOL_REQUIREMENTS
+        .iter()
+        .find(|(name, _)| *name == tool)
+        .map_or(&[], |(_, requirements)| requirements)
+}
+
+/// The system tables to probe for, sorted.
+pub fn probed_system_tables() -> Vec<&'static str> {
+    let tables: BTreeSet<&str> = all_requirements()
+        .filter_map(|requirement| match requirement {
+            Requirement::SystemTable(table) => Some(table),
+            Requirement::Function(_) => None,
+        })
+        .collect();
+    tables.into_iter().collect()
+}
+
+/// The functions to probe for, sorted.
+pub fn probed_functions() -> Vec<&'static str> {
+    let functions: BTreeSet<&str> = all_requirements()
+        .filter_map(|requirement| match requirement {
+            Requirement::Function(function) => Some(function),
+            Requirement::SystemTable(_) => None,
+        })
+        .collect();
+    functions.into_iter().collect()
+}
+
+fn all_requirements() -> impl Iterator<Item = Requirement> {
+    TOOL_REQUIREMENTS.iter().flat_map(|(_, requirements)| requirements.iter().copied())
+}
+
+/// What a probe found: the probed system tables and functions the server
+/// has.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct Capabilities {
+    pub system_tables: BTreeSet<String>,
+    pub functions: BTreeSet<String>,
+}
+
+impl Capabilities {
+    pub fn has(&self, requirement: Requirement) -> bool {
+        match requirement {
+            Requirement::SystemTable(table) => self.system_tables.contains(table),
+            Requirement::Function(function) => self.functions.contains(function),
+        }
+    }
+
+    /// The requirements of `tool` the server lacks, empty if it can run.
+    pub fn missing(&self, tool: &str) -> Vec<Requirement> {
+        tool_requirements(tool).iter().copied().filter(|requirement| !self.has(*requirement)).collect()
+    }
+
+    /// The tools with requirements that the server meets, sorted.
+    pub fn available_tools(&self) -> Vec<&'static str> {
+        let mut tools: Vec<&str> =
+            TOOL_REQUIREMENTS.iter().map(|(tool, _)| *tool).filter(|tool| self.missing(tool).is_empty()).collect();
+        tools.sort_unstable();
+        tools
+    }
+}
+
+/// What `tools/list` does with tools the server can't run.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum UnavailableTools {
+    /// Leave them out.
+    #[default]
+    Hide,
+    /// List them with [`UNAVAILABLE_SUFFIX`] appended to their description.
+    Describe,
+}
+
+impl UnavailableTools {
+    /// Parses `hide` or `describe`, ignoring case.
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.to_ascii_lowercase().as_str() {
+            "hide" => Some(Self::Hide),
+            "describe" => Some(Self::Describe),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Hide => "hide",
+            Self::Describe => "describe",
+        }
+    }
+}
+
+/// Applies `capabilities` to tool definitions as listed: tools whose
+/// requirements are missing are dropped or marked, as `mode` says. Without
+/// capabilities, before a probe succeeded, every tool is listed as is.
+pub fn apply_capabilities(tools: Vec<Value>, capabilities: Option<&Capabilities>, mode: UnavailableTools) -> Vec<Value> {
+    let Some(capabilities) = capabilities else {
+        return tools;
+    };
+
+    tools
+        .into_iter()
+        .filter_map(|mut tool| {
+            let name = tool["name"].as_str().unwrap_or_default();
+            if capabilities.missing(name).is_empty() {
+                return Some(tool);
+            }
+            match mode {
+                UnavailableTools::Hide => None,
+                UnavailableTools::Describe => {
+                    let description = format!("{}{}", tool["description"].as_str().unwrap_or_default(), UNAVAILABLE_SUFFIX);
+                    tool["description"] = Value::String(description);
+                    Some(tool)
+                }
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,178 @@
+//! Catalog versions, so clients keeping a local copy of the schema can tell
+//! cheaply whether it changed and fetch only what did.
+//!
+//! A version token carries the whole snapshot it was made from, LZ4
+//! compressed: every table with the time its metadata last changed. The
+//! server can then diff against any earlier token without keeping state,
+//! across restarts too. Tokens are opaque to clients; the checksum in them
+//! catches tokens damaged in transit, but is no signature.
+
+use crate::ClickHouseError;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use clickhouse::Row;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// The format marker at the start of every token.
+pub const CATALOG_VERSION_PREFIX: &str = "cv3";
+
+/// The largest snapshot a token may decompress to, so a crafted token
+/// can't make the server allocate without bound.
+pub const MAX_CATALOG_SNAPSHOT_BYTES: usize = 64 * 1024 * 1024;
+
+/// A table as tracked by catalog versions: `metadata_modification_time`
+/// from `system.tables`, as a Unix timestamp.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, Row)]
+pub struct CatalogEntry {
+    pub database: String,
+    pub table: String,
+    pub modified: u32,
+}
+
+/// A table by name.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub struct TableRef {
+    pub database: String,
+    pub table: String,
+}
+
+/// What changed between two snapshots.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct CatalogDiff {
+    /// Tables that are new or whose metadata changed, sorted by name.
+    pub changed: Vec<CatalogEntry>,
+    /// Tables that are gone, sorted by name. A renamed table is deleted
+    /// under its old name and new under its new one.
+    pub deleted: Vec<TableRef>,
+}
+
+impl CatalogDiff {
+    /// The databases with changed or deleted tables.
+    pub fn databases(&self) -> BTreeSet<&str> {
+        self.changed
+            .iter()
+            .map(|entry| entry.database.as_str())
+            .chain(self.deleted.iter().map(|table| table.database.as_str()))
+            .collect()
+    }
+}
+
+/// The current catalog version, as returned by `catalog_version`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CatalogVersion {
+    pub version: String,
+    pub tables: usize,
+}
+
+/// A column of an exported table.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ExportedColumn {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub r#type: String,
+}
+
+/// A table of an exported catalog.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ExportedTable {
+    pub database: String,
+    pub name: String,
+    pub engine: String,
+    /// `metadata_modification_time` as a Unix timestamp.
+    pub modified: u32,
+    pub columns: Vec<ExportedColumn>,
+}
+
+/// The catalog, or with `since_version` only what changed since.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CatalogExport {
+    /// The version of the catalog exported, for the next `since_version`.
+    pub version: String,
+    pub since_version: Option<String>,
+    pub tables: Vec<ExportedTable>,
+    /// Tables dropped since `since_version`; empty for full exports.
+    pub deleted: Vec<TableRef>,
+}
+
+/// The snapshot in a canonical form: sorted `[database, table, modified]`
+/// triples as JSON.
+fn canonical_payload(entries: &[CatalogEntry]) -> String {
+    let mut triples: Vec<(&str, &str, u32)> =
+        entries.iter().map(|entry| (entry.database.as_str(), entry.table.as_str(), entry.modified)).collect();
+    triples.sort_unstable();
+    triples.dedup();
+    serde_json::to_string(&triples).unwrap_or_default()
+}
+
+/// 64-bit FNV-1a, which is stable across Rust versions, unlike `std`'s
+/// `DefaultHasher`.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3))
+}
+
+/// A hash of the snapshot; equal for equal sets of entries in any order.
+pub fn catalog_hash(entries: &[CatalogEntry]) -> u64 {
+    fnv1a(canonical_payload(entries).as_bytes())
+}
+
+/// The version token of a snapshot: `cv3.<checksum>.<snapshot>`, the
+/// snapshot LZ4 compressed and encoded as base64url, so tokens survive
+/// being put in URLs and JSON unescaped. Equal snapshots give equal tokens.
+pub fn encode_catalog_version(entries: &[CatalogEntry]) -> String {
+    let payload = canonical_payload(entries);
+    let compressed = lz4_flex::compress_prepend_size(payload.as_bytes());
+    format!("{}.{:016x}.{}", CATALOG_VERSION_PREFIX, fnv1a(payload.as_bytes()), URL_SAFE_NO_PAD.encode(compressed))
+}
+
+/// The snapshot a token was made from, sorted. Fails with
+/// `InvalidArgument` for tokens not made by [`encode_catalog_version`] or
+/// damaged since.
+pub fn decode_catalog_version(token: &str) -> Result<Vec<CatalogEntry>, ClickHouseError> {
+    let invalid = |reason: &str| ClickHouseError::InvalidArgument {
+        message: format!("Invalid catalog version: {}", reason),
+    };
+
+    let mut parts = token.trim().splitn(3, '.');
+    let (Some(prefix), Some(hash), Some(payload)) = (parts.next(), parts.next(), parts.next()) else {
+        return Err(invalid("expected a token returned by catalog_version"));
+    };
+    if prefix != CATALOG_VERSION_PREFIX {
+        return Err(invalid(&format!("unsupported format '{}'", prefix)));
+    }
+    let compressed = URL_SAFE_NO_PAD.decode(payload).map_err(|_| invalid("malformed encoding"))?;
+    // The uncompressed size comes first, as a little-endian u32
+    let size = compressed.get(..4).map(|size| u32::from_le_bytes([size[0], size[1], size[2], size[3]]) as usize);
+    if size.is_none_or(|size| size > MAX_CATALOG_SNAPSHOT_BYTES) {
+        return Err(invalid("malformed snapshot"));
+    }
+    let payload = lz4_flex::decompress_size_prepended(&compressed).map_err(|_| invalid("malformed snapshot"))?;
+    if u64::from_str_radix(hash, 16).ok() != Some(fnv1a(&payload)) {
+        return Err(invalid("checksum mismatch"));
+    }
+    let triples: Vec<(String, String, u32)> = serde_json::from_slice(&payload).map_err(|_| invalid("malformed snapshot"))?;
+    Ok(triples.into_iter().map(|(database, table, modified)| CatalogEntry { database, table, modified }).collect())
+}
+
+/// Tables new or modified in `current`, and tables of `previous` gone from
+/// it.
+pub fn diff_catalog(previous: &[CatalogEntry], current: &[CatalogEntry]) -> CatalogDiff {
+    let key = |entry: &CatalogEntry| (entry.database.clone(), entry.table.clone());
+    let previous: BTreeMap<(String, String), u32> = previous.iter().map(|entry| (key(entry), entry.modified)).collect();
+    let current_keys: BTreeSet<(String, String)> = current.iter().map(key).collect();
+
+    let mut changed: Vec<CatalogEntry> = current
+        .iter()
+        .filter(|entry| previous.get(&key(entry)) != Some(&entry.modified))
+        .cloned()
+        .collect();
+    changed.sort();
+    changed.dedup();
+    let deleted = previous
+        .into_keys()
+        .filter(|name| !current_keys.contains(name))
+        .map(|(database, table)| TableRef { database, table })
+        .collect();
+
+    CatalogDiff { changed, deleted }
+}
@@ -0,0 +1,256 @@
+use crate::{TransferCompression, UnavailableTools};
+use anyhow::Result;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// The `--help` text.
+pub const USAGE: &str = "\
+Usage: mcp-test [OPTIONS] [COMMAND]
+
+An MCP server for exploring ClickHouse over JSON-RPC on stdin/stdout.
+
+Commands:
+  serve                      Run the MCP server (the default)
+  doctor                     Check the configuration and the ClickHouse connection, then exit
+  replay <FILE>              Replay a session transcript against a local ClickHouse, writes and admin_execute off, and report responses that differ
+
+Options:
+  --url <URL>                ClickHouse HTTP URL
+  --database <NAME>          Default database
+  --username <NAME>          ClickHouse user (set the password via CLICKHOUSE_PASSWORD)
+  --env-file <PATH>          Read settings from a .env file
+  --config <PATH>            Read settings from a JSON config file
+  --print-config             Print the effective settings and their sources, then exit
+  --tool-prefix <PREFIX>     Prefix every advertised tool name, e.g. clickhouse_
+  --metrics-listen <ADDR>    Serve Prometheus metrics at http://ADDR/metrics
+  --allow-writes             Let the run_script tool change data and schema
+  --ascii-identifiers-only   Reject database, table and column names with non-ASCII characters
+  --enable-admin             Offer the admin_execute tool, which runs arbitrary SQL after confirmation
+  --unavailable-tools <MODE> List tools this ClickHouse can't run: hide (the default) or describe
+  --ch-compression <MODE>    Compress transfers with ClickHouse: lz4 (the default) or none
+  --health-interval <TIME>   Check the ClickHouse connection in the background every TIME, e.g. 60s
+  --record-session <DIR>     Record every message of the session to DIR/session-<id>.jsonl, secrets redacted
+  --against-live             Let replay run against a ClickHouse that isn't on this machine
+  --keepalive-interval <TIME>
+                             Send the client a ping after TIME without output, e.g. 30s (default off)
+  --timezone <TZ>            Run queries in this time zone, e.g. UTC, instead of the server's (ClickHouse 23.6+)
+  --show-system-databases    List system and INFORMATION_SCHEMA in database and table listings by default
+  -h, --help                 Print this help and exit
+  -V, --version              Print the version and exit
+
+Options take their value as the next argument or after '=' (--url=http://ch:8123).
+
+Environment:
+  CLICKHOUSE_URL             ClickHouse HTTP URL (default http://localhost:8123)
+  CLICKHOUSE_DATABASE        Default database (default default)
+  CLICKHOUSE_USERNAME        ClickHouse user (default default)
+  CLICKHOUSE_PASSWORD        ClickHouse password (default empty)
+  CLICKHOUSE_BEARER_TOKEN    Send 'Authorization: Bearer <token>' instead of the user and password
+  MCP_MAX_LINE_LENGTH        Longest accepted input line in bytes (default 4 MiB)
+  MCP_MAX_STRUCTURED_BYTES   Largest structuredContent sent in bytes, text only beyond (default 1 MiB)
+  MCP_RECONNECT_DEADLINE_MS  How long a tool call waits for ClickHouse to come back (default 2000)
+  MCP_SCHEMA_CACHE_TTL_SECS  How long table schemas stay cached, 0 to disable (default 300)
+  MCP_RESULT_TTL_SECS        How long results too long to return inline stay readable as resources (default 900)
+  MCP_MAX_STORED_RESULTS     How many such results are kept, the oldest dropped first (default 20)
+  MCP_ADMIN_MAX_ROWS         The most rows admin_execute reads of a SELECT (default 10000)
+  MCP_MAX_IDENTIFIER_LENGTH  Longest accepted database, table or column name in bytes (default 206)
+  MCP_QUERY_TAGGING          Tag queries with the session, request and tool in query_log (default true)
+  MCP_USER_AGENT             User-Agent sent to ClickHouse (default mcp-test/<version>)
+  MCP_VERBOSE_ERRORS         Show ClickHouse's own message on errors that leave it out, for debugging (default false)
+  RUST_LOG                   Log level, e.g. info or debug (logs go to stderr)
+";
+
+/// The `--version` text.
+pub fn version_string() -> String {
+    format!("mcp-test {}", env!("CARGO_PKG_VERSION"))
+}
+
+/// What the binary does.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    #[default]
+    Serve,
+    /// Checks the configuration and connection, and exits non-zero if a
+    /// critical check fails.
+    Doctor,
+    /// Replays the transcript in [`CliOptions::replay_file`] and exits
+    /// non-zero if a response differs.
+    Replay,
+}
+
+/// Command line options for the server binary.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct CliOptions {
+    pub command: Command,
+    pub tool_prefix: Option<String>,
+    pub url: Option<String>,
+    pub database: Option<String>,
+    pub username: Option<String>,
+    pub env_file: Option<PathBuf>,
+    pub config_file: Option<PathBuf>,
+    pub print_config: bool,
+    /// Whether tools may run statements that change data or schema.
+    pub allow_writes: bool,
+    /// Whether identifiers are restricted to ASCII.
+    pub ascii_identifiers_only: bool,
+    /// Whether the `admin_execute` tool is offered.
+    pub enable_admin: bool,
+    pub show_help: bool,
+    pub show_version: bool,
+    /// Where to serve Prometheus metrics, e.g. `127.0.0.1:9464`.
+    pub metrics_listen: Option<SocketAddr>,
+    pub ch_compression: Option<TransferCompression>,
+    /// What to list for tools the server lacks the system tables or
+    /// functions for.
+    pub unavailable_tools: Option<UnavailableTools>,
+    /// How often to check the connection in the background.
+    pub health_interval: Option<Duration>,
+    /// Whether listings show the system databases by default.
+    pub show_system_databases: bool,
+    /// After how long without output to ping the client.
+    pub keepalive_interval: Option<Duration>,
+    /// Where to record session transcripts.
+    pub record_session: Option<PathBuf>,
+    /// The transcript the `replay` command replays.
+    pub replay_file: Option<PathBuf>,
+    /// Whether `replay` may run against a ClickHouse that isn't local.
+    pub against_live: bool,
+    /// The `session_timezone` queries over user data run with.
+    pub timezone: Option<String>,
+}
+
+impl CliOptions {
+    /// Parses the arguments following the program name. Options accept their
+    /// value either as the next argument or after `=`.
+    pub fn parse<I>(args: I) -> Result<Self>
+    where
+        I: IntoIterator<Item = String>,
+    {
+        let mut options = Self::default();
+        let mut command = None;
+        let mut args = args.into_iter();
+
+        while let Some(arg) = args.next() {
+            let (flag, inline_value) = match arg.split_once('=') {
+                Some((flag, value)) if flag.starts_with("--") => (flag.to_string(), Some(value.to_string())),
+                _ => (arg, None),
+            };
+
+            match flag.as_str() {
+                "serve" | "doctor" | "replay" => {
+                    if let Some(previous) = command {
+                        return Err(anyhow::anyhow!("Unexpected command '{}' after '{}'", flag, previous));
+                    }
+                    options.command = match flag.as_str() {
+                        "doctor" => Command::Doctor,
+                        "replay" => {
+                            let file = args.next().ok_or_else(|| anyhow::anyhow!("replay requires a transcript file"))?;
+                            options.replay_file = Some(PathBuf::from(file));
+                            Command::Replay
+                        }
+                        _ => Command::Serve,
+                    };
+                    command = Some(flag);
+                }
+                "--print-config" | "--allow-writes" | "--ascii-identifiers-only" | "--enable-admin" | "--show-system-databases" | "--against-live"
+                | "--help" | "-h" | "--version" | "-V" => {
+                    if inline_value.is_some() {
+                        return Err(anyhow::anyhow!("{} does not take a value", flag));
+                    }
+                    match flag.as_str() {
+                        "--print-config" => options.print_config = true,
+                        "--allow-writes" => options.allow_writes = true,
+                        "--ascii-identifiers-only" => options.ascii_identifiers_only = true,
+                        "--enable-admin" => options.enable_admin = true,
+                        "--show-system-databases" => options.show_system_databases = true,
+                        "--against-live" => options.against_live = true,
+                        "--help" | "-h" => options.show_help = true,
+                        _ => options.show_version = true,
+                    }
+                }
+                "--tool-prefix" | "--url" | "--database" | "--username" | "--env-file" | "--config"
+                | "--metrics-listen" | "--ch-compression" | "--unavailable-tools" | "--health-interval"
+                | "--keepalive-interval" | "--record-session" | "--timezone" => {
+                    let value = inline_value
+                        .or_else(|| args.next())
+                        .ok_or_else(|| anyhow::anyhow!("{} requires a value", flag))?;
+                    match flag.as_str() {
+                        "--tool-prefix" => options.tool_prefix = Some(value),
+                        "--url" => options.url = Some(value),
+                        "--database" => options.database = Some(value),
+                        "--username" => options.username = Some(value),
+                        "--env-file" => options.env_file = Some(PathBuf::from(value)),
+                        "--record-session" => options.record_session = Some(PathBuf::from(value)),
+                        "--metrics-listen" => {
+                            let address = value
+                                .parse()
+                                .map_err(|_| anyhow::anyhow!("--metrics-listen expects HOST:PORT, got '{}'", value))?;
+                            options.metrics_listen = Some(address);
+                        }
+                        "--ch-compression" => {
+                            let compression = TransferCompression::parse(&value)
+                                .ok_or_else(|| anyhow::anyhow!("--ch-compression expects lz4 or none, got '{}'", value))?;
+                            options.ch_compression = Some(compression);
+                        }
+                        "--unavailable-tools" => {
+                            let mode = UnavailableTools::parse(&value).ok_or_else(|| {
+                                anyhow::anyhow!("--unavailable-tools expects hide or describe, got '{}'", value)
+                            })?;
+                            options.unavailable_tools = Some(mode);
+                        }
+                        "--health-interval" => {
+                            let interval = parse_duration(&value).ok_or_else(|| {
+                                anyhow::anyhow!("--health-interval expects a duration such as 60s or 500ms, got '{}'", value)
+                            })?;
+                            options.health_interval = Some(interval);
+                        }
+                        "--keepalive-interval" => {
+                            let interval = parse_duration(&value).ok_or_else(|| {
+                                anyhow::anyhow!("--keepalive-interval expects a duration such as 30s, got '{}'", value)
+                            })?;
+                            options.keepalive_interval = Some(interval);
+                        }
+                        "--timezone" => {
+                            if !is_timezone_name(&value) {
+                                return Err(anyhow::anyhow!(
+                                    "--timezone expects a time zone name such as UTC or Europe/Stockholm, got '{}'",
+                                    value
+                                ));
+                            }
+                            options.timezone = Some(value);
+                        }
+                        _ => options.config_file = Some(PathBuf::from(value)),
+                    }
+                }
+                _ => return Err(anyhow::anyhow!("Unknown argument: {}", flag)),
+            }
+        }
+
+        Ok(options)
+    }
+}
+
+/// Whether `value` looks like a time zone name, e.g. `UTC`, `Etc/GMT+2` or
+/// `America/Argentina/Buenos_Aires`. Whether the server knows it is only
+/// found out by the first query.
+pub fn is_timezone_name(value: &str) -> bool {
+    value.starts_with(|c: char| c.is_ascii_alphabetic())
+        && value.chars().all(|c| c.is_ascii_alphanumeric() || "_+-/".contains(c))
+}
+
+/// Parses a positive duration: a number with an `ms`, `s`, `m` or `h` unit,
+/// or a bare number of seconds.
+pub fn parse_duration(value: &str) -> Option<Duration> {
+    let split = value.find(|c: char| !c.is_ascii_digit()).unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let number: u64 = number.parse().ok()?;
+    let duration = match unit {
+        "ms" => Duration::from_millis(number),
+        "" | "s" => Duration::from_secs(number),
+        "m" => Duration::from_secs(number.checked_mul(60)?),
+        "h" => Duration::from_secs(number.checked_mul(3600)?),
+        _ => return None,
+    };
+    (!duration.is_zero()).then_some(duration)
+}
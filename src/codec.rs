@@ -0,0 +1,133 @@
+use std::io;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt};
+
+/// Default cap on a single input line; large enough for any realistic request.
+pub const DEFAULT_MAX_LINE_LENGTH: usize = 4 * 1024 * 1024;
+
+const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+
+/// One newline-delimited frame read from the client.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Frame {
+    /// A line with the line terminator (`\n` or `\r\n`) and any leading BOM removed.
+    Line(String),
+    /// A line that is not valid UTF-8, lossily decoded for logging.
+    InvalidUtf8(String),
+    /// A line longer than the configured maximum; its contents were discarded.
+    TooLong { length: usize },
+}
+
+/// Reads newline-delimited frames as raw bytes so that a single bad line
+/// (invalid UTF-8, oversized) is reported instead of ending the session.
+pub struct LineReader<R> {
+    reader: R,
+    max_line_length: usize,
+    /// The bytes of the last frame as read, if kept.
+    raw: Option<Vec<u8>>,
+}
+
+impl<R: AsyncBufRead + Unpin> LineReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            max_line_length: DEFAULT_MAX_LINE_LENGTH,
+            raw: None,
+        }
+    }
+
+    pub fn with_max_line_length(mut self, max_line_length: usize) -> Self {
+        self.max_line_length = max_line_length;
+        self
+    }
+
+    /// Keeps the bytes of each frame exactly as read, terminator, `\r` and
+    /// BOM included, for [`Self::last_raw`]. The bytes of a
+    /// [`Frame::TooLong`] are not kept.
+    pub fn with_raw_capture(mut self) -> Self {
+        self.raw = Some(Vec::new());
+        self
+    }
+
+    /// The bytes of the frame last returned as read, with raw capture on.
+    pub fn last_raw(&self) -> Option<&[u8]> {
+        self.raw.as_deref()
+    }
+
+    /// Returns the next frame, or `None` at end of input. A final line
+    /// without a trailing newline is still returned.
+    pub async fn next_frame(&mut self) -> io::Result<Option<Frame>> {
+        let mut line = Vec::new();
+        let mut length = 0;
+        let mut read_any = false;
+        let mut too_long = false;
+        if let Some(raw) = &mut self.raw {
+            raw.clear();
+        }
+
+        loop {
+            let available = self.reader.fill_buf().await?;
+            if available.is_empty() {
+                if !read_any {
+                    return Ok(None);
+                }
+                break;
+            }
+            read_any = true;
+
+            let (chunk, consumed, complete) = match available.iter().position(|&b| b == b'\n') {
+                Some(pos) => (&available[..pos], pos + 1, true),
+                None => (available, available.len(), false),
+            };
+
+            length += chunk.len();
+            if !too_long {
+                if length > self.max_line_length {
+                    // Stop buffering but keep consuming up to the newline so
+                    // the next frame starts at a message boundary.
+                    too_long = true;
+                    line = Vec::new();
+                    if let Some(raw) = &mut self.raw {
+                        *raw = Vec::new();
+                    }
+                } else {
+                    line.extend_from_slice(chunk);
+                    if let Some(raw) = &mut self.raw {
+                        raw.extend_from_slice(&available[..consumed]);
+                    }
+                }
+            }
+
+            self.reader.consume(consumed);
+            if complete {
+                break;
+            }
+        }
+
+        if too_long {
+            return Ok(Some(Frame::TooLong { length }));
+        }
+
+        if line.last() == Some(&b'\r') {
+            line.pop();
+        }
+        if line.starts_with(UTF8_BOM) {
+            line.drain(..UTF8_BOM.len());
+        }
+
+        match String::from_utf8(line) {
+            Ok(line) => Ok(Some(Frame::Line(line))),
+            Err(e) => Ok(Some(Frame::InvalidUtf8(String::from_utf8_lossy(e.as_bytes()).into_owned()))),
+        }
+    }
+}
+
+/// Frames a serialized message as a single NDJSON line. The message must be
+/// compact JSON (`serde_json::to_string`), which escapes any newlines inside
+/// strings, so the only raw newline is the terminator.
+pub fn encode_frame(message: &str) -> Vec<u8> {
+    debug_assert!(!message.contains('\n'), "framed message must be a single line");
+    let mut frame = Vec::with_capacity(message.len() + 1);
+    frame.extend_from_slice(message.as_bytes());
+    frame.push(b'\n');
+    frame
+}
@@ -0,0 +1,319 @@
+use crate::metrics::state_label;
+use crate::{CacheStats, Capabilities, ClickHouseClient, ClickHouseError};
+use chrono::{DateTime, SecondsFormat, Utc};
+use log::{debug, info, warn};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{broadcast, oneshot};
+use tokio::task::JoinHandle;
+use tokio::time::{sleep, timeout};
+
+/// How many state transitions are kept for [`ConnectionManager::transitions`].
+const MAX_TRANSITIONS: usize = 100;
+
+/// While the server is down, the health probe backs off up to this many
+/// times its interval.
+const MAX_PROBE_BACKOFF: u32 = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionState {
+    /// The last health check succeeded and no failures have been reported since.
+    Connected,
+    /// A tool call hit a connectivity error; calls still go through while a
+    /// background probe decides whether the server is really gone.
+    Degraded,
+    /// The server could not be reached; calls re-attempt the connection first.
+    Disconnected,
+}
+
+#[derive(Debug, Clone)]
+pub struct ConnectionStatus {
+    pub state: ConnectionState,
+    pub unreachable_since: Option<DateTime<Utc>>,
+    pub last_error: Option<String>,
+}
+
+/// A change of the connection state.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StateTransition {
+    pub from: ConnectionState,
+    pub to: ConnectionState,
+    pub at: DateTime<Utc>,
+    /// The error that moved the connection away from `Connected`.
+    pub error: Option<String>,
+}
+
+/// A running periodic health check, see
+/// [`ConnectionManager::start_health_probe`].
+pub struct HealthProbe {
+    shutdown: oneshot::Sender<()>,
+    task: JoinHandle<()>,
+}
+
+impl HealthProbe {
+    /// Stops the probe, abandoning a check in progress, and waits for its
+    /// task to finish.
+    pub async fn stop(self) {
+        let _ = self.shutdown.send(());
+        let _ = self.task.await;
+    }
+}
+
+pub struct ConnectionManager {
+    client: ClickHouseClient,
+    status: Mutex<ConnectionStatus>,
+    reconnect_base_delay: Duration,
+    reconnect_max_delay: Duration,
+    reconnecting: AtomicBool,
+    transitions: Mutex<VecDeque<StateTransition>>,
+    events: broadcast::Sender<StateTransition>,
+}
+
+impl ConnectionManager {
+    pub fn new(client: ClickHouseClient) -> Self {
+        Self {
+            client,
+            status: Mutex::new(ConnectionStatus {
+                state: ConnectionState::Disconnected,
+                unreachable_since: None,
+                last_error: None,
+            }),
+            reconnect_base_delay: Duration::from_secs(1),
+            reconnect_max_delay: Duration::from_secs(60),
+            reconnecting: AtomicBool::new(false),
+            transitions: Mutex::new(VecDeque::new()),
+            events: broadcast::channel(16).0,
+        }
+    }
+
+    pub fn with_reconnect_backoff(mut self, base_delay: Duration, max_delay: Duration) -> Self {
+        self.reconnect_base_delay = base_delay;
+        self.reconnect_max_delay = max_delay;
+        self
+    }
+
+    pub fn state(&self) -> ConnectionState {
+        self.status.lock().unwrap().state
+    }
+
+    pub fn status(&self) -> ConnectionStatus {
+        self.status.lock().unwrap().clone()
+    }
+
+    /// The most recent state transitions, oldest first.
+    pub fn transitions(&self) -> Vec<StateTransition> {
+        self.transitions.lock().unwrap().iter().cloned().collect()
+    }
+
+    /// Receives every state transition from now on.
+    pub fn subscribe(&self) -> broadcast::Receiver<StateTransition> {
+        self.events.subscribe()
+    }
+
+    /// The client settings for logging, with credentials redacted.
+    pub fn client_config(&self) -> Vec<(&'static str, String)> {
+        self.client.effective_config()
+    }
+
+    /// The client's schema cache counters, `None` if caching is off.
+    pub fn schema_cache_stats(&self) -> Option<CacheStats> {
+        self.client.schema_cache_stats()
+    }
+
+    /// The time zone the client's queries over user data run in, if known,
+    /// see [`ClickHouseClient::effective_timezone`].
+    pub fn timezone(&self) -> Option<String> {
+        self.client.effective_timezone().map(str::to_string)
+    }
+
+    /// What the client's last capability probe found, if one succeeded.
+    pub fn capabilities(&self) -> Option<Capabilities> {
+        self.client.capabilities()
+    }
+
+    /// Runs a health check and updates the connection state from its outcome.
+    pub async fn connect(&self) -> Result<(), ClickHouseError> {
+        match self.client.health_check().await {
+            Ok(()) => {
+                self.mark_connected();
+                Ok(())
+            }
+            Err(e) => {
+                self.mark_unreachable(&e);
+                Err(e)
+            }
+        }
+    }
+
+    /// Returns the client, re-attempting the connection first when the
+    /// server is known to be unreachable.
+    pub async fn client(&self) -> Result<&ClickHouseClient, ClickHouseError> {
+        if self.state() == ConnectionState::Disconnected {
+            debug!("ClickHouse is disconnected, re-attempting connection before tool call");
+            if self.connect().await.is_err() {
+                return Err(self.unreachable_error());
+            }
+        }
+
+        Ok(&self.client)
+    }
+
+    /// Keeps re-attempting the connection, `reconnect_base_delay` apart,
+    /// until the server is reachable or `deadline` has passed. Fails with
+    /// the unreachable error otherwise.
+    pub async fn wait_until_ready(&self, deadline: Duration) -> Result<(), ClickHouseError> {
+        let attempts = async {
+            while self.connect().await.is_err() {
+                sleep(self.reconnect_base_delay).await;
+            }
+        };
+        if self.state() == ConnectionState::Connected || timeout(deadline, attempts).await.is_ok() {
+            return Ok(());
+        }
+
+        debug!("ClickHouse not ready after {:?}", deadline);
+        Err(self.unreachable_error())
+    }
+
+    /// Records the outcome of an operation so connectivity failures move the
+    /// manager out of the `Connected` state.
+    pub fn report_error(&self, error: &ClickHouseError) {
+        if !is_connectivity_error(error) {
+            return;
+        }
+
+        let mut status = self.status.lock().unwrap();
+        if status.state == ConnectionState::Connected {
+            self.record_transition(status.state, ConnectionState::Degraded, Some(error));
+            status.state = ConnectionState::Degraded;
+        }
+        status.last_error = Some(error.to_string());
+    }
+
+    /// Starts a task that runs the health check every `interval`, so an
+    /// unreachable server is noticed without a tool call. While the server is
+    /// down the checks back off, up to eight times the interval. The probe
+    /// shares this manager's client and state with tool calls.
+    pub fn start_health_probe(self: &Arc<Self>, interval: Duration) -> HealthProbe {
+        let (shutdown, mut stopped) = oneshot::channel();
+        let manager = Arc::clone(self);
+        let task = tokio::spawn(async move {
+            let mut delay = interval;
+            loop {
+                let check = async {
+                    sleep(delay).await;
+                    manager.connect().await
+                };
+                tokio::select! {
+                    _ = &mut stopped => break,
+                    outcome = check => {
+                        delay = match outcome {
+                            Ok(()) => interval,
+                            Err(_) => (delay * 2).min(interval * MAX_PROBE_BACKOFF),
+                        };
+                    }
+                }
+            }
+            debug!("ClickHouse health probe stopped");
+        });
+        HealthProbe { shutdown, task }
+    }
+
+    /// Starts a background task that keeps probing the server with
+    /// exponential backoff until it is reachable again. Does nothing if the
+    /// manager is connected or a probe is already running.
+    pub fn ensure_background_reconnect(self: &Arc<Self>) {
+        if self.state() == ConnectionState::Connected {
+            return;
+        }
+        if self.reconnecting.swap(true, Ordering::SeqCst) {
+            return;
+        }
+
+        let manager = Arc::clone(self);
+        tokio::spawn(async move {
+            let mut delay = manager.reconnect_base_delay;
+            while manager.state() != ConnectionState::Connected {
+                sleep(delay).await;
+                // A tool call may have reconnected while we were sleeping
+                if manager.state() == ConnectionState::Connected {
+                    break;
+                }
+                debug!("Background ClickHouse reconnect attempt");
+                if manager.connect().await.is_err() {
+                    delay = (delay * 2).min(manager.reconnect_max_delay);
+                }
+            }
+            manager.reconnecting.store(false, Ordering::SeqCst);
+        });
+    }
+
+    fn mark_connected(&self) {
+        let mut status = self.status.lock().unwrap();
+        if status.state != ConnectionState::Connected {
+            self.record_transition(status.state, ConnectionState::Connected, None);
+        }
+        status.state = ConnectionState::Connected;
+        status.unreachable_since = None;
+        status.last_error = None;
+    }
+
+    fn mark_unreachable(&self, error: &ClickHouseError) {
+        let mut status = self.status.lock().unwrap();
+        if status.state != ConnectionState::Disconnected {
+            self.record_transition(status.state, ConnectionState::Disconnected, Some(error));
+            status.unreachable_since = Some(Utc::now());
+        } else if status.unreachable_since.is_none() {
+            warn!("ClickHouse unreachable: {}", error);
+            status.unreachable_since = Some(Utc::now());
+        }
+        status.state = ConnectionState::Disconnected;
+        status.last_error = Some(error.to_string());
+    }
+
+    /// Logs a state change, keeps it for [`Self::transitions`] and sends it
+    /// to subscribers.
+    fn record_transition(&self, from: ConnectionState, to: ConnectionState, error: Option<&ClickHouseError>) {
+        let (from_label, to_label) = (state_label(from), state_label(to));
+        match error {
+            Some(error) => warn!("ClickHouse connection {} -> {}: {}", from_label, to_label, error),
+            None => info!("ClickHouse connection {} -> {}", from_label, to_label),
+        }
+
+        let transition = StateTransition { from, to, at: Utc::now(), error: error.map(|e| e.to_string()) };
+        let mut transitions = self.transitions.lock().unwrap();
+        if transitions.len() == MAX_TRANSITIONS {
+            transitions.pop_front();
+        }
+        transitions.push_back(transition.clone());
+        // Nobody may be listening
+        let _ = self.events.send(transition);
+    }
+
+    fn unreachable_error(&self) -> ClickHouseError {
+        let status = self.status();
+        let since = status
+            .unreachable_since
+            .map(|t| t.to_rfc3339_opts(SecondsFormat::Secs, true))
+            .unwrap_or_else(|| "unknown".to_string());
+        let last_error = status.last_error.unwrap_or_else(|| "none".to_string());
+
+        ClickHouseError::ServiceUnavailable {
+            message: format!("ClickHouse unreachable since {}, last error: {}", since, last_error),
+        }
+    }
+}
+
+fn is_connectivity_error(error: &ClickHouseError) -> bool {
+    match error {
+        ClickHouseError::RetryBudgetExhausted { last_error, .. } => is_connectivity_error(last_error),
+        error => matches!(
+            error,
+            ClickHouseError::ConnectionFailed { .. }
+                | ClickHouseError::NetworkError { .. }
+                | ClickHouseError::ServiceUnavailable { .. }
+        ),
+    }
+}
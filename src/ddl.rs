@@ -0,0 +1,62 @@
+//! Ordering the `CREATE` statements of a database so they can be replayed:
+//! a table comes before the views reading from it, the materialized views
+//! writing into it and the Distributed tables forwarding to it.
+
+use crate::distributed::parse_distributed_engine;
+use crate::lineage::parse_materialized_view;
+use std::collections::BTreeSet;
+
+/// The tables of the same database that the statement `ddl` creating
+/// `database.name` refers to: the `TO` table and the sources of a view, and
+/// the local table of a Distributed table. Tables of other databases and
+/// references that can't be parsed are left out.
+pub fn ddl_dependencies(database: &str, name: &str, ddl: &str) -> BTreeSet<String> {
+    let mut dependencies = BTreeSet::new();
+    let prefix = format!("{}.", database);
+
+    if let Some(view) = parse_materialized_view(database, name, ddl) {
+        let sources = view.sources.into_iter().filter_map(|source| source.table);
+        dependencies.extend(view.target.into_iter().chain(sources).filter_map(|table| table.strip_prefix(&prefix).map(str::to_string)));
+    }
+    if let Some(engine) = ddl.find("ENGINE = Distributed").map(|at| &ddl[at + "ENGINE = ".len()..]) {
+        if let Some(target) = parse_distributed_engine(engine, database) {
+            if target.database == database {
+                dependencies.insert(target.table);
+            }
+        }
+    }
+    dependencies.remove(name);
+    dependencies
+}
+
+/// Sorts `(table, ddl)` pairs of `database` so every table comes after the
+/// tables it depends on (see [`ddl_dependencies`]), otherwise by name.
+/// Tables in a dependency cycle keep their name order at the end.
+pub fn order_ddl(database: &str, mut tables: Vec<(String, String)>) -> Vec<(String, String)> {
+    tables.sort_by(|a, b| a.0.cmp(&b.0));
+    let names: BTreeSet<&str> = tables.iter().map(|(name, _)| name.as_str()).collect();
+    let mut pending: Vec<(usize, BTreeSet<String>)> = tables
+        .iter()
+        .enumerate()
+        .map(|(index, (name, ddl))| {
+            let mut dependencies = ddl_dependencies(database, name, ddl);
+            dependencies.retain(|dependency| names.contains(dependency.as_str()));
+            (index, dependencies)
+        })
+        .collect();
+
+    let mut order = Vec::with_capacity(tables.len());
+    while !pending.is_empty() {
+        // The first table by name whose dependencies are all placed
+        let ready = pending.iter().position(|(_, dependencies)| dependencies.is_empty()).unwrap_or(0);
+        let (index, _) = pending.remove(ready);
+        let placed = &tables[index].0;
+        for (_, dependencies) in &mut pending {
+            dependencies.remove(placed);
+        }
+        order.push(index);
+    }
+
+    let mut tables: Vec<Option<(String, String)>> = tables.into_iter().map(Some).collect();
+    order.into_iter().filter_map(|index| tables[index].take()).collect()
+}
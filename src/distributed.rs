@@ -0,0 +1,146 @@
+//! Distributed tables hold no data of their own: they forward queries to a
+//! local table on every shard of a cluster. Parts and keys live on that
+//! local table, so tools reading them need to know where it is.
+
+use serde::Serialize;
+
+/// The local table a Distributed table forwards to, from the parameters of
+/// `ENGINE = Distributed(cluster, database, table[, sharding_key[, policy]])`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DistributedTarget {
+    pub cluster: String,
+    pub database: String,
+    pub table: String,
+    /// The sharding key expression as written, if any.
+    pub sharding_key: Option<String>,
+}
+
+/// How stats of a Distributed table were gathered.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DistributedScope {
+    /// The Distributed table asked about, as `database.table`.
+    pub table: String,
+    pub target: DistributedTarget,
+    /// Whether the stats cover every replica in the cluster; `false` when
+    /// reading the other replicas was denied and only this server's shard
+    /// was read.
+    pub cluster_wide: bool,
+}
+
+impl DistributedScope {
+    /// Describes what the stats cover, for tool output.
+    pub fn label(&self) -> String {
+        let target = format!("{}.{}", self.target.database, self.target.table);
+        if self.cluster_wide {
+            format!(
+                "{} is Distributed; cluster-wide figures for {} across all replicas of cluster '{}'",
+                self.table, target, self.target.cluster
+            )
+        } else {
+            format!(
+                "{} is Distributed; figures for {} on this server's shard only, as reading the other replicas of cluster '{}' was denied",
+                self.table, target, self.target.cluster
+            )
+        }
+    }
+}
+
+/// Parses the target of a Distributed table from its `engine_full` in
+/// `system.tables`, e.g. `Distributed('main', 'web', 'events_local', rand())`.
+/// Parameters may be string literals, quoted or bare identifiers, and a
+/// `currentDatabase()` database resolves to `own_database`, the database of
+/// the Distributed table itself. Returns `None` for other engines and for
+/// parameters that can't be parsed.
+pub fn parse_distributed_engine(engine_full: &str, own_database: &str) -> Option<DistributedTarget> {
+    let rest = engine_full.trim_start().strip_prefix("Distributed")?.trim_start();
+    let args = split_arguments(rest.strip_prefix('(')?)?;
+    if args.len() < 3 {
+        return None;
+    }
+
+    let cluster = parameter_name(args[0])?;
+    let database = if args[1].replace(' ', "") == "currentDatabase()" {
+        own_database.to_string()
+    } else {
+        parameter_name(args[1])?
+    };
+    let table = parameter_name(args[2])?;
+    let sharding_key = args.get(3).map(|key| key.to_string());
+
+    Some(DistributedTarget { cluster, database, table, sharding_key })
+}
+
+/// Splits the arguments after an opening parenthesis at top-level commas,
+/// up to the matching closing parenthesis. Commas inside nested calls and
+/// quotes don't split. Returns `None` if the parenthesis is never closed.
+fn split_arguments(input: &str) -> Option<Vec<&str>> {
+    let mut args = Vec::new();
+    let mut depth = 0usize;
+    let mut quote: Option<char> = None;
+    let mut escaped = false;
+    let mut start = 0;
+
+    for (i, c) in input.char_indices() {
+        if let Some(q) = quote {
+            if escaped {
+                escaped = false;
+            } else if c == '\\' {
+                escaped = true;
+            } else if c == q {
+                // A doubled quote is an escaped one and toggles back on the next character
+                quote = None;
+            }
+            continue;
+        }
+        match c {
+            '\'' | '"' | '`' => quote = Some(c),
+            '(' | '[' => depth += 1,
+            ')' | ']' if depth > 0 => depth -= 1,
+            ')' => {
+                let last = input[start..i].trim();
+                if !last.is_empty() || !args.is_empty() {
+                    args.push(last);
+                }
+                return Some(args);
+            }
+            ',' if depth == 0 => {
+                args.push(input[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// The name in a cluster, database or table parameter: a string literal, a
+/// quoted identifier or a bare identifier.
+fn parameter_name(arg: &str) -> Option<String> {
+    match arg.chars().next()? {
+        q @ ('\'' | '"' | '`') => unquote(arg, q),
+        _ if arg.chars().all(|c| c.is_alphanumeric() || c == '_') => Some(arg.to_string()),
+        _ => None,
+    }
+}
+
+/// Removes the quotes around `arg`, resolving backslash escapes and doubled
+/// quotes.
+pub(crate) fn unquote(arg: &str, quote: char) -> Option<String> {
+    let inner = arg.strip_prefix(quote)?.strip_suffix(quote)?;
+    let mut name = String::with_capacity(inner.len());
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => name.push(chars.next()?),
+            c if c == quote => {
+                // Only a doubled quote may appear inside the quotes
+                if chars.next()? != quote {
+                    return None;
+                }
+                name.push(quote);
+            }
+            c => name.push(c),
+        }
+    }
+    Some(name)
+}
@@ -0,0 +1,214 @@
+//! The `doctor` command: one-shot checks of the configuration and the
+//! ClickHouse connection for operators setting up the server.
+
+use crate::humanize::human_duration;
+use crate::{quote_identifier, ClickHouseClient, ClickHouseConfig, ClickHouseError};
+use serde::Serialize;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// Round trips slower than this fail the latency check.
+pub const SLOW_ROUND_TRIP: Duration = Duration::from_millis(500);
+
+/// The most database names suggested when the default one is missing.
+const MAX_SUGGESTED_DATABASES: usize = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum CheckOutcome {
+    Pass,
+    Fail,
+    /// Not run because a check it depends on failed.
+    Skipped,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DoctorCheck {
+    pub name: String,
+    pub outcome: CheckOutcome,
+    /// Whether a failure makes the doctor exit non-zero.
+    pub critical: bool,
+    pub detail: String,
+    /// What to change to make a failed check pass.
+    pub hint: Option<String>,
+}
+
+impl DoctorCheck {
+    pub fn pass(name: &str, critical: bool, detail: impl Into<String>) -> Self {
+        Self { name: name.to_string(), outcome: CheckOutcome::Pass, critical, detail: detail.into(), hint: None }
+    }
+
+    pub fn fail(name: &str, critical: bool, detail: impl Into<String>, hint: impl Into<String>) -> Self {
+        Self {
+            name: name.to_string(),
+            outcome: CheckOutcome::Fail,
+            critical,
+            detail: detail.into(),
+            hint: Some(hint.into()),
+        }
+    }
+
+    pub fn skipped(name: &str, critical: bool) -> Self {
+        Self {
+            name: name.to_string(),
+            outcome: CheckOutcome::Skipped,
+            critical,
+            detail: "skipped, an earlier check failed".to_string(),
+            hint: None,
+        }
+    }
+
+    /// The label shown in the report. Failures of checks that are not
+    /// critical are only warnings.
+    fn label(&self) -> &'static str {
+        match (self.outcome, self.critical) {
+            (CheckOutcome::Pass, _) => "PASS",
+            (CheckOutcome::Fail, true) => "FAIL",
+            (CheckOutcome::Fail, false) => "WARN",
+            (CheckOutcome::Skipped, _) => "SKIP",
+        }
+    }
+}
+
+/// The outcome of all checks against one server, printed by `mcp-test doctor`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DoctorReport {
+    /// Where the checks connected to, with credentials redacted.
+    pub target: String,
+    pub checks: Vec<DoctorCheck>,
+}
+
+impl DoctorReport {
+    /// Whether every critical check passed. Skipped critical checks count as
+    /// failed, as nothing is known about them.
+    pub fn passed(&self) -> bool {
+        self.checks
+            .iter()
+            .all(|check| !check.critical || check.outcome == CheckOutcome::Pass)
+    }
+}
+
+impl fmt::Display for DoctorReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Checking {}", self.target)?;
+        writeln!(f)?;
+        for check in &self.checks {
+            writeln!(f, "[{}] {}: {}", check.label(), check.name, check.detail)?;
+            if let Some(hint) = &check.hint {
+                writeln!(f, "       hint: {}", hint)?;
+            }
+        }
+        writeln!(f)?;
+
+        let count = |label: &str| self.checks.iter().filter(|check| check.label() == label).count();
+        let (failed, warnings) = (count("FAIL"), count("WARN"));
+        match (self.passed(), warnings) {
+            (true, 0) => writeln!(f, "All checks passed"),
+            (true, warnings) => writeln!(f, "All critical checks passed, {} warning(s)", warnings),
+            (false, _) if failed == 0 => writeln!(f, "Critical checks were skipped"),
+            (false, _) => writeln!(f, "{} critical check(s) failed", failed),
+        }
+    }
+}
+
+/// What to look at when the connection check fails with `error`.
+fn connection_hint(error: &ClickHouseError) -> &'static str {
+    match error {
+        ClickHouseError::AuthenticationFailed { .. } => {
+            "check CLICKHOUSE_USERNAME (--username) and CLICKHOUSE_PASSWORD"
+        }
+        ClickHouseError::PermissionDenied { .. } => "the user may not run queries; check its grants and profile",
+        _ => "check that ClickHouse is running and CLICKHOUSE_URL (--url) points at its HTTP port, usually 8123",
+    }
+}
+
+/// Runs the checks against `client`: the health check, round-trip latency,
+/// the visible databases, the default database and `system.columns`
+/// access. Checks that need a connection are skipped without one.
+pub async fn run_checks(client: &ClickHouseClient) -> DoctorReport {
+    let config = client.effective_config();
+    let setting = |key: &str| {
+        config
+            .iter()
+            .find(|(name, _)| *name == key)
+            .map(|(_, value)| value.as_str())
+            .unwrap_or_default()
+    };
+    let target = format!("ClickHouse at {} as {}", setting("url"), setting("username"));
+    let mut checks = Vec::new();
+
+    if let Err(e) = client.health_check().await {
+        checks.push(DoctorCheck::fail("connection", true, e.to_string(), connection_hint(&e)));
+        for (name, critical) in [("latency", false), ("databases", true), ("default database", true), ("system.columns", false)] {
+            checks.push(DoctorCheck::skipped(name, critical));
+        }
+        return DoctorReport { target, checks };
+    }
+    checks.push(DoctorCheck::pass("connection", true, "SELECT 1 succeeded"));
+
+    // Timed separately, so connection setup isn't counted
+    let started = Instant::now();
+    let round_trip = client.health_check().await.map(|_| started.elapsed());
+    checks.push(match round_trip {
+        Ok(elapsed) if elapsed <= SLOW_ROUND_TRIP => {
+            DoctorCheck::pass("latency", false, format!("{} round trip", human_duration(elapsed)))
+        }
+        Ok(elapsed) => DoctorCheck::fail(
+            "latency",
+            false,
+            format!("{} round trip, more than {}", human_duration(elapsed), human_duration(SLOW_ROUND_TRIP)),
+            "tool calls will be slow; run the server closer to ClickHouse",
+        ),
+        Err(e) => DoctorCheck::fail("latency", false, e.to_string(), connection_hint(&e)),
+    });
+
+    let database = client.default_database();
+    match client.list_databases().await {
+        Ok(databases) => {
+            checks.push(DoctorCheck::pass("databases", true, format!("{} databases visible", databases.len())));
+            if databases.iter().any(|d| d.name == database) {
+                checks.push(DoctorCheck::pass("default database", true, format!("'{}' exists", database)));
+            } else {
+                let names: Vec<&str> =
+                    databases.iter().take(MAX_SUGGESTED_DATABASES).map(|d| d.name.as_str()).collect();
+                checks.push(DoctorCheck::fail(
+                    "default database",
+                    true,
+                    format!("'{}' not found", database),
+                    format!(
+                        "create it, or set CLICKHOUSE_DATABASE (--database) to one of: {}",
+                        if names.is_empty() { "(none visible)".to_string() } else { names.join(", ") }
+                    ),
+                ));
+            }
+        }
+        Err(e) => {
+            checks.push(DoctorCheck::fail(
+                "databases",
+                true,
+                e.to_string(),
+                "grant the user SHOW DATABASES, or SELECT on system.databases",
+            ));
+            checks.push(DoctorCheck::skipped("default database", true));
+        }
+    }
+
+    checks.push(match client.can_read_system_table("columns").await {
+        Ok(true) => DoctorCheck::pass("system.columns", false, "readable"),
+        Ok(false) => DoctorCheck::fail(
+            "system.columns",
+            false,
+            "not readable; schemas fall back to DESCRIBE TABLE, without key columns",
+            format!("GRANT SELECT ON system.columns TO {}", quote_identifier(setting("username"))),
+        ),
+        Err(e) => DoctorCheck::fail("system.columns", false, e.to_string(), "check the ClickHouse server log"),
+    });
+
+    DoctorReport { target, checks }
+}
+
+/// Connects with `config` the way the server does and runs the checks.
+pub async fn run(config: &ClickHouseConfig) -> DoctorReport {
+    let client = ClickHouseClient::from_config(config)
+        .with_retry_config(3, Duration::from_millis(100));
+    run_checks(&client).await
+}
@@ -0,0 +1,100 @@
+//! Short, actionable guidance for well-known ClickHouse errors, so the model
+//! changes its query instead of retrying the same one.
+
+use crate::ClickHouseError;
+use chrono::NaiveDateTime;
+use serde::Serialize;
+
+/// Guidance for one ClickHouse error code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct ErrorHint {
+    pub code: u32,
+    /// The error code's name in ClickHouse, e.g. `MEMORY_LIMIT_EXCEEDED`.
+    pub name: &'static str,
+    pub hint: &'static str,
+}
+
+/// The curated errors that get a hint.
+pub const ERROR_HINTS: &[ErrorHint] = &[
+    ErrorHint {
+        code: 43,
+        name: "ILLEGAL_TYPE_OF_ARGUMENT",
+        hint: "a function got an argument of the wrong type, often a comparison between a string and a number; \
+               check the column types with get_table_schema and cast one side, e.g. toString(x) or toInt64(x)",
+    },
+    ErrorHint {
+        code: 53,
+        name: "TYPE_MISMATCH",
+        hint: "a value does not match the column's type; check the types with get_table_schema and cast the value",
+    },
+    ErrorHint {
+        code: 159,
+        name: "TIMEOUT_EXCEEDED",
+        hint: "the query ran into its time limit; narrow it with a WHERE filter on the sorting key or add a LIMIT \
+               instead of retrying it unchanged",
+    },
+    ErrorHint {
+        code: 164,
+        name: "READONLY",
+        hint: "this connection is read-only, so statements that change data or schema cannot run; use a SELECT",
+    },
+    ErrorHint {
+        code: 202,
+        name: "TOO_MANY_SIMULTANEOUS_QUERIES",
+        hint: "the server is running too many queries at once; wait a few seconds before retrying and don't issue \
+               queries in parallel",
+    },
+    ErrorHint {
+        code: 241,
+        name: "MEMORY_LIMIT_EXCEEDED",
+        hint: "the query used too much memory; add a WHERE filter on the partition key, select fewer columns or \
+               group by fewer keys",
+    },
+    ErrorHint {
+        code: 252,
+        name: "TOO_MANY_PARTS",
+        hint: "the table has too many parts and rejects inserts until merges catch up; insert less often in \
+               larger batches, and check insert_pressure",
+    },
+    ErrorHint {
+        code: 386,
+        name: "NO_COMMON_TYPE",
+        hint: "the compared values have no common type; check the column types with get_table_schema and cast \
+               one side, e.g. toString(x) or toUInt64(x)",
+    },
+];
+
+/// The hint for `code`, if it is one of the [`ERROR_HINTS`].
+pub fn hint_for_code(code: u32) -> Option<&'static ErrorHint> {
+    ERROR_HINTS.iter().find(|hint| hint.code == code)
+}
+
+/// Extracts the code from a ClickHouse exception message such as
+/// `Code: 241. DB::Exception: Memory limit (for query) exceeded`.
+pub fn error_code(message: &str) -> Option<u32> {
+    let start = message.find("Code: ")? + "Code: ".len();
+    let digits: String = message[start..].chars().take_while(|c| c.is_ascii_digit()).collect();
+    digits.parse().ok()
+}
+
+/// The end of the quota interval from a `QUOTA_EXCEEDED` message, e.g.
+/// `Quota for user `default` for 3600s has been exceeded: read_rows =
+/// 1000001/1000000. Interval will end at 2024-05-01 14:00:00.`, in the time
+/// zone of the server.
+pub fn quota_reset_time(message: &str) -> Option<NaiveDateTime> {
+    const PREFIX: &str = "Interval will end at ";
+    let start = message.find(PREFIX)? + PREFIX.len();
+    let time = message.get(start..start + "2024-05-01 14:00:00".len())?;
+    NaiveDateTime::parse_from_str(time, "%Y-%m-%d %H:%M:%S").ok()
+}
+
+/// The hint for `error`: by the exception code in its message, or for
+/// errors this client raises itself, by its kind.
+pub fn explain_error(error: &ClickHouseError) -> Option<&'static ErrorHint> {
+    match error {
+        ClickHouseError::QueryTimeout { .. } => hint_for_code(159),
+        // Readonly violations are mapped before the code is seen
+        ClickHouseError::PermissionDenied { operation, .. } if operation == "write" => hint_for_code(164),
+        _ => error_code(&error.to_string()).and_then(hint_for_code),
+    }
+}
@@ -0,0 +1,169 @@
+//! Text formatting of tool output. Prompt templates and clients read these
+//! formats, so they are kept free of I/O and covered by the golden files in
+//! tests/golden/.
+
+use crate::protocol::truncation_notice;
+use crate::schema_summary::SchemaSummary;
+use crate::{parse_datetime_type, ColumnInfo, DatabaseInfo, TableInfo};
+use std::collections::BTreeSet;
+
+/// Output of `list_databases`.
+pub fn format_databases(databases: &[DatabaseInfo]) -> String {
+    let mut result = String::from("Available databases:\n");
+    for db in databases {
+        result.push_str(&format!("- {}\n", db.name));
+    }
+
+    result
+}
+
+/// Output of `list_tables` for the tables of `database`.
+pub fn format_tables(database: &str, tables: &[TableInfo]) -> String {
+    let mut result = format!("Tables in database '{}':\n", database);
+    for table in tables {
+        // Engines are unknown when listed without access to system.tables
+        if table.engine.is_empty() {
+            result.push_str(&format!("- {}\n", table.name));
+        } else {
+            result.push_str(&format!("- {} (Engine: {})\n", table.name, table.engine));
+        }
+    }
+
+    result
+}
+
+/// Output of `list_all_tables`, grouped by database. `tables` must be sorted
+/// by database, as returned by `ClickHouseClient::list_all_tables`, and
+/// `truncated` says whether it left some out.
+pub fn format_all_tables(tables: &[TableInfo], truncated: bool) -> String {
+    if tables.is_empty() {
+        return "No tables found\n".to_string();
+    }
+
+    let databases = tables.iter().map(|t| t.database.as_str()).collect::<BTreeSet<_>>().len();
+    let mut result = format!("{} tables in {} databases:\n", tables.len(), databases);
+    let mut current_database = None;
+    for table in tables {
+        if current_database != Some(table.database.as_str()) {
+            result.push_str(&format!("\n{}:\n", table.database));
+            current_database = Some(table.database.as_str());
+        }
+        result.push_str(&format!("- {} (Engine: {})\n", table.name, table.engine));
+    }
+    if truncated {
+        result.push_str(&format!(
+            "\n{}\nUse list_tables for the remaining databases\n",
+            truncation_notice(tables.len(), None, "row_limit")
+        ));
+    }
+
+    result
+}
+
+/// Output of `get_table_schema`.
+pub fn format_table_schema(database: &str, table: &str, columns: &[ColumnInfo]) -> String {
+    let mut result = format!("Schema for table '{}.{}':\n", database, table);
+    result.push_str("\nColumns:\n");
+    result.push_str(&format_columns(columns));
+
+    result
+}
+
+/// The most families and columns listed per type in a schema summary.
+const MAX_LISTED_PER_TYPE: usize = 8;
+
+/// Output of `get_table_schema` with `compact`.
+pub fn format_schema_summary(database: &str, table: &str, summary: &SchemaSummary) -> String {
+    let mut result = format!("Schema summary for table '{}.{}': {} columns\n", database, table, summary.total_columns);
+
+    if !summary.key_columns.is_empty() {
+        result.push_str("\nKey columns:\n");
+        result.push_str(&format_columns(&summary.key_columns));
+    }
+
+    if !summary.type_groups.is_empty() {
+        result.push_str("\nColumns by type:\n");
+    }
+    for group in &summary.type_groups {
+        let mut listed: Vec<String> = group
+            .families
+            .iter()
+            .map(|family| format!("{} ({})", family.pattern, family.count))
+            .chain(group.others.iter().cloned())
+            .collect();
+        let more = listed.len().saturating_sub(MAX_LISTED_PER_TYPE);
+        listed.truncate(MAX_LISTED_PER_TYPE);
+        if more > 0 {
+            listed.push(format!("and {} more", more));
+        }
+        result.push_str(&format!("- {} ({}): {}\n", group.base_type, group.count, listed.join(", ")));
+    }
+
+    if !summary.leading_columns.is_empty() {
+        result.push_str(&format!("\nFirst {} columns:\n", summary.leading_columns.len()));
+        result.push_str(&format_columns(&summary.leading_columns));
+    }
+    if summary.leading_columns.len() < summary.total_columns {
+        result.push_str(&format!(
+            "\n{} more columns not listed; raise max_columns or call get_table_schema without compact to see them all\n",
+            summary.total_columns - summary.leading_columns.len()
+        ));
+    }
+
+    result
+}
+
+/// Renders columns one per line with their comment and key memberships,
+/// followed by the allowed values of Enum columns and the time zone of date
+/// and time columns, if known.
+pub fn format_columns(columns: &[ColumnInfo]) -> String {
+    let mut result = String::new();
+
+    for col in columns {
+        result.push_str(&format!("- {}: {}", col.name, col.r#type));
+
+        if !col.comment.is_empty() {
+            result.push_str(&format!(" -- {}", col.comment));
+        }
+
+        let mut key_info = Vec::new();
+        if col.is_in_primary_key == 1 {
+            key_info.push("PRIMARY KEY");
+        }
+        if col.is_in_sorting_key == 1 {
+            key_info.push("SORTING KEY");
+        }
+        if col.is_in_partition_key == 1 {
+            key_info.push("PARTITION KEY");
+        }
+        if col.is_in_sampling_key == 1 {
+            key_info.push("SAMPLING KEY");
+        }
+
+        if !key_info.is_empty() {
+            result.push_str(&format!(" [{}]", key_info.join(", ")));
+        }
+
+        result.push('\n');
+
+        if let Some(values) = &col.enum_values {
+            let listed: Vec<String> = values.iter().map(|(name, value)| format!("{:?} = {}", name, value)).collect();
+            result.push_str(&format!("  values: {}\n", listed.join(", ")));
+        }
+
+        if let Some(timezone) = &col.timezone {
+            // Without a time zone in the type, values follow the session's,
+            // which is the server's unless --timezone sets it
+            let named = parse_datetime_type(&col.r#type).is_some_and(|datetime| datetime.timezone.is_some());
+            let source = if named { "" } else { " (default)" };
+            result.push_str(&format!("  time zone: {}{}\n", timezone, source));
+        }
+    }
+
+    result
+}
+
+/// Makes free text safe to put in a markdown table cell.
+pub fn markdown_cell(text: &str) -> String {
+    text.replace('|', "\\|").replace(['\r', '\n'], " ")
+}
@@ -0,0 +1,297 @@
+//! The ClickHouse gRPC interface, an alternative to HTTP for deployments
+//! that have standardized on gRPC. Built with the `grpc` cargo feature and
+//! used through [`ClickHouseClient::new_grpc`](crate::ClickHouseClient::new_grpc).
+//!
+//! Only the unary `ExecuteQuery` call is used, with the few fields of
+//! ClickHouse's `clickhouse_grpc.proto` that are needed declared by hand, so
+//! no `protoc` is required to build. Parameters are bound on the client with
+//! the HTTP driver's own escaping. Rows are read as `JSONCompactEachRow` and
+//! deserialized with serde rather than from RowBinary, so row types must also
+//! deserialize from JSON: a `bool` field needs a `Bool` column rather than a
+//! `UInt8` one, for instance.
+
+use clickhouse::error::Error;
+use clickhouse::sql::Bind;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+use tonic::codec::ProstCodec;
+use tonic::codegen::http::uri::PathAndQuery;
+use tonic::transport::{Channel, Endpoint};
+
+/// The port ClickHouse listens on for gRPC when `grpc_port` is configured
+/// as in its documentation.
+pub const DEFAULT_GRPC_PORT: u16 = 9100;
+
+/// The output format rows are requested in.
+pub const OUTPUT_FORMAT: &str = "JSONCompactEachRow";
+
+const EXECUTE_QUERY: &str = "/clickhouse.grpc.ClickHouse/ExecuteQuery";
+
+/// How long connecting may take before the attempt counts as a network
+/// error, which is retried.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The part of `QueryInfo` that is sent.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct QueryInfo {
+    #[prost(string, tag = "1")]
+    pub query: String,
+    #[prost(string, tag = "2")]
+    pub query_id: String,
+    #[prost(map = "string, string", tag = "3")]
+    pub settings: HashMap<String, String>,
+    #[prost(string, tag = "4")]
+    pub database: String,
+    #[prost(string, tag = "7")]
+    pub output_format: String,
+    #[prost(string, tag = "9")]
+    pub user_name: String,
+    #[prost(string, tag = "10")]
+    pub password: String,
+}
+
+/// The part of `Result` that is read.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct QueryResult {
+    #[prost(bytes = "vec", tag = "1")]
+    pub output: Vec<u8>,
+    #[prost(message, optional, tag = "7")]
+    pub exception: Option<QueryException>,
+    #[prost(bool, tag = "8")]
+    pub cancelled: bool,
+}
+
+/// `Exception`, an error raised by the server while running the query.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct QueryException {
+    #[prost(int32, tag = "1")]
+    pub code: i32,
+    #[prost(string, tag = "2")]
+    pub name: String,
+    #[prost(string, tag = "3")]
+    pub display_text: String,
+}
+
+/// The error of a query the server failed, worded as the HTTP interface
+/// words it (`Code: N. DB::Exception: ...`) so error codes and hints are
+/// found the same way for both transports.
+pub fn exception_error(exception: &QueryException) -> Error {
+    if exception.display_text.starts_with("Code: ") {
+        Error::BadResponse(exception.display_text.clone())
+    } else {
+        Error::BadResponse(format!("Code: {}. DB::Exception: {}", exception.code, exception.display_text))
+    }
+}
+
+/// Maps a failed call onto the driver's errors: failures to reach the server
+/// are network errors and deadlines are timeouts, which are both retried.
+pub fn status_error(status: tonic::Status) -> Error {
+    match status.code() {
+        tonic::Code::Unavailable | tonic::Code::Unknown | tonic::Code::Cancelled | tonic::Code::Aborted => Error::Network(Box::new(status)),
+        tonic::Code::DeadlineExceeded => Error::TimedOut,
+        tonic::Code::InvalidArgument => Error::InvalidParams(Box::new(status)),
+        _ => Error::BadResponse(status.message().to_string()),
+    }
+}
+
+/// Deserializes `JSONCompactEachRow` output into rows. Each row is a JSON
+/// array; a single-column row is also accepted as its only value, so
+/// `fetch_one::<u8>()` works as over HTTP.
+pub fn decode_rows<T: DeserializeOwned>(output: &[u8]) -> Result<Vec<T>, Error> {
+    let text = std::str::from_utf8(output)?;
+    text.lines()
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            let row: Value = serde_json::from_str(line).map_err(|e| Error::Custom(e.to_string()))?;
+            match serde_json::from_value(row.clone()) {
+                Ok(decoded) => Ok(decoded),
+                Err(error) => match row {
+                    Value::Array(mut values) if values.len() == 1 => {
+                        serde_json::from_value(values.remove(0)).map_err(|e| Error::Custom(e.to_string()))
+                    }
+                    _ => Err(Error::Custom(error.to_string())),
+                },
+            }
+        })
+        .collect()
+}
+
+/// A connection to one server's gRPC interface. Clones share the channel,
+/// which connects on first use.
+#[derive(Clone)]
+pub struct GrpcTransport {
+    shared: Arc<Shared>,
+    database: String,
+}
+
+struct Shared {
+    /// An invalid endpoint fails every query, as an invalid URL does over HTTP.
+    endpoint: Result<Endpoint, String>,
+    channel: OnceLock<Channel>,
+    username: String,
+    password: String,
+}
+
+impl GrpcTransport {
+    /// A transport to `endpoint`, such as `http://ch:9100`.
+    pub fn new(endpoint: &str, database: &str, username: &str, password: &str) -> Self {
+        let endpoint = Endpoint::from_shared(endpoint.to_string())
+            .map(|endpoint| endpoint.connect_timeout(CONNECT_TIMEOUT))
+            .map_err(|e| format!("invalid gRPC endpoint '{}': {}", endpoint, e));
+        Self {
+            shared: Arc::new(Shared {
+                endpoint,
+                channel: OnceLock::new(),
+                username: username.to_string(),
+                password: password.to_string(),
+            }),
+            database: database.to_string(),
+        }
+    }
+
+    pub fn with_database(mut self, database: &str) -> Self {
+        self.database = database.to_string();
+        self
+    }
+
+    /// Sets the `User-Agent` of the channel, which tonic follows with its
+    /// own name. A value that isn't a valid header fails every query.
+    pub fn with_user_agent(self, user_agent: &str) -> Self {
+        let shared = Shared {
+            endpoint: self.shared.endpoint.clone().and_then(|endpoint| {
+                endpoint.user_agent(user_agent.to_string()).map_err(|e| format!("invalid user agent '{}': {}", user_agent, e))
+            }),
+            channel: OnceLock::new(),
+            username: self.shared.username.clone(),
+            password: self.shared.password.clone(),
+        };
+        Self { shared: Arc::new(shared), database: self.database }
+    }
+
+    pub fn query(&self, sql: &str) -> GrpcQuery {
+        GrpcQuery {
+            transport: self.clone(),
+            parts: sql.split('?').map(str::to_string).collect(),
+            error: None,
+            options: Vec::new(),
+        }
+    }
+
+    fn channel(&self) -> Result<Channel, Error> {
+        let endpoint = self.shared.endpoint.as_ref().map_err(|e| Error::InvalidParams(e.clone().into()))?;
+        Ok(self.shared.channel.get_or_init(|| endpoint.connect_lazy()).clone())
+    }
+
+    async fn execute_query(&self, info: QueryInfo) -> Result<Vec<u8>, Error> {
+        let mut grpc = tonic::client::Grpc::new(self.channel()?).max_decoding_message_size(usize::MAX);
+        grpc.ready().await.map_err(|e| Error::Network(Box::new(e)))?;
+        let codec: ProstCodec<QueryInfo, QueryResult> = ProstCodec::default();
+        let result = grpc
+            .unary(tonic::Request::new(info), PathAndQuery::from_static(EXECUTE_QUERY), codec)
+            .await
+            .map_err(status_error)?
+            .into_inner();
+        match result.exception {
+            Some(exception) => Err(exception_error(&exception)),
+            None if result.cancelled => Err(Error::BadResponse("query was cancelled".to_string())),
+            None => Ok(result.output),
+        }
+    }
+}
+
+/// A query over gRPC, with the methods of [`clickhouse::query::Query`].
+#[derive(Clone)]
+pub struct GrpcQuery {
+    transport: GrpcTransport,
+    /// The SQL split at the `?` placeholders still to bind.
+    parts: Vec<String>,
+    error: Option<String>,
+    options: Vec<(String, String)>,
+}
+
+impl GrpcQuery {
+    /// Binds `value` to the next `?`, escaped as the HTTP driver escapes it.
+    pub fn bind(mut self, value: impl Bind) -> Self {
+        if self.error.is_some() {
+            return self;
+        }
+        if self.parts.len() < 2 {
+            self.error = Some("invalid SQL: unexpected bind(), all arguments are already bound".to_string());
+            return self;
+        }
+        let mut literal = String::new();
+        match value.write(&mut literal) {
+            Ok(()) => {
+                let next = self.parts.remove(1);
+                self.parts[0].push_str(&literal);
+                self.parts[0].push_str(&next);
+            }
+            Err(e) => self.error = Some(format!("invalid SQL: invalid argument: {}", e)),
+        }
+        self
+    }
+
+    /// Sets a setting for this query. `query_id` and `database` fill the
+    /// fields of the same name instead.
+    pub fn with_option(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.options.push((name.into(), value.into()));
+        self
+    }
+
+    /// The SQL with all arguments bound.
+    pub fn sql(&self) -> Result<String, Error> {
+        match &self.error {
+            Some(error) => Err(Error::InvalidParams(error.clone().into())),
+            None if self.parts.len() > 1 => Err(Error::InvalidParams("invalid SQL: unbound query argument".into())),
+            None => Ok(self.parts.concat()),
+        }
+    }
+
+    fn query_info(&self, read_only: bool) -> Result<QueryInfo, Error> {
+        let shared = &self.transport.shared;
+        let mut info = QueryInfo {
+            query: self.sql()?,
+            database: self.transport.database.clone(),
+            output_format: OUTPUT_FORMAT.to_string(),
+            user_name: shared.username.clone(),
+            password: shared.password.clone(),
+            ..QueryInfo::default()
+        };
+        if read_only {
+            // As a GET over HTTP: read-only, but settings may still be changed
+            info.settings.insert("readonly".to_string(), "2".to_string());
+        }
+        info.settings.insert("output_format_json_quote_64bit_integers".to_string(), "0".to_string());
+        for (name, value) in &self.options {
+            match name.as_str() {
+                "query_id" => info.query_id = value.clone(),
+                "database" => info.database = value.clone(),
+                _ => {
+                    info.settings.insert(name.clone(), value.clone());
+                }
+            }
+        }
+        Ok(info)
+    }
+
+    pub async fn execute(self) -> Result<(), Error> {
+        let info = self.query_info(false)?;
+        self.transport.execute_query(info).await.map(drop)
+    }
+
+    pub async fn fetch_all<T: DeserializeOwned>(self) -> Result<Vec<T>, Error> {
+        let info = self.query_info(true)?;
+        decode_rows(&self.transport.execute_query(info).await?)
+    }
+
+    pub async fn fetch_optional<T: DeserializeOwned>(self) -> Result<Option<T>, Error> {
+        Ok(self.fetch_all().await?.into_iter().next())
+    }
+
+    pub async fn fetch_one<T: DeserializeOwned>(self) -> Result<T, Error> {
+        self.fetch_optional().await?.ok_or(Error::RowNotFound)
+    }
+}
@@ -0,0 +1,79 @@
+//! The queries a session ran through the SQL-taking tools, so the model can
+//! look back at what it already tried and how that went.
+
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// The most queries kept per session; older ones are dropped.
+pub const MAX_QUERY_HISTORY: usize = 100;
+
+/// A query run by a tool call.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct QueryHistoryEntry {
+    pub tool: String,
+    /// The SQL as given to the tool; the statements of a script are joined
+    /// with `;` and a newline.
+    pub sql: String,
+    /// `ok`, or `error` with the message in `error`.
+    pub status: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub elapsed_ms: u64,
+    /// When the call finished, as RFC 3339 UTC.
+    pub finished_at: String,
+}
+
+impl QueryHistoryEntry {
+    /// An entry for a call of `tool` with `sql` that took `elapsed` and
+    /// failed with `error`, if it did.
+    pub fn new(tool: &str, sql: String, elapsed: Duration, error: Option<String>) -> Self {
+        Self {
+            tool: tool.to_string(),
+            sql,
+            status: if error.is_some() { "error" } else { "ok" },
+            error,
+            elapsed_ms: elapsed.as_millis().min(u128::from(u64::MAX)) as u64,
+            finished_at: chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+        }
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        Duration::from_millis(self.elapsed_ms)
+    }
+}
+
+/// A ring buffer of the last [`MAX_QUERY_HISTORY`] queries of a session.
+#[derive(Debug, Default)]
+pub struct QueryHistory {
+    entries: Mutex<VecDeque<QueryHistoryEntry>>,
+}
+
+impl QueryHistory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds `entry`, dropping the oldest one if the buffer is full.
+    pub fn record(&self, entry: QueryHistoryEntry) {
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() == MAX_QUERY_HISTORY {
+            entries.pop_front();
+        }
+        entries.push_back(entry);
+    }
+
+    /// The last `limit` entries, newest first.
+    pub fn recent(&self, limit: usize) -> Vec<QueryHistoryEntry> {
+        self.entries.lock().unwrap().iter().rev().take(limit).cloned().collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
@@ -0,0 +1,89 @@
+//! Human-readable sizes, counts and durations for tool output. The output
+//! is the same in every locale: binary size units, `,` as the thousands
+//! separator and `.` as the decimal point.
+//!
+//! Clients that parse the text can pass `raw: true` to a tool; its output
+//! is then rendered inside [`with_raw_numbers`] and carries plain numbers.
+
+use std::future::Future;
+use std::time::Duration;
+
+tokio::task_local! {
+    static RAW_NUMBERS: bool;
+}
+
+/// Runs `future` with the functions of this module returning plain numbers
+/// if `raw` is set: bytes as `<n> bytes`, counts as digits only and
+/// durations as `<n> ms`.
+pub async fn with_raw_numbers<F: Future>(raw: bool, future: F) -> F::Output {
+    RAW_NUMBERS.scope(raw, future).await
+}
+
+fn raw_numbers() -> bool {
+    RAW_NUMBERS.try_with(|raw| *raw).unwrap_or(false)
+}
+
+/// A byte count in binary units with one decimal, e.g. `1023 B`, `1.0 KiB`
+/// or `180.5 GiB`.
+pub fn human_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 7] = ["B", "KiB", "MiB", "GiB", "TiB", "PiB", "EiB"];
+
+    if raw_numbers() {
+        return format!("{} bytes", bytes);
+    }
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    // Values that would round up to 1024.0 move to the next unit
+    while value >= 1023.95 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, UNITS[unit])
+}
+
+/// A count with thousands separators, e.g. `193,862,482,391`.
+pub fn human_count(count: u64) -> String {
+    let digits = count.to_string();
+    if raw_numbers() {
+        return digits;
+    }
+
+    let mut grouped = String::with_capacity(digits.len() + digits.len() / 3);
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            grouped.push(',');
+        }
+        grouped.push(digit);
+    }
+    grouped
+}
+
+/// A duration in its two largest units, e.g. `250 ms`, `4.2 s`,
+/// `3 min 20 s`, `5 h 12 min` or `2 d 3 h`.
+pub fn human_duration(duration: Duration) -> String {
+    if raw_numbers() {
+        return format!("{} ms", duration.as_millis());
+    }
+
+    let millis = duration.as_millis();
+    if millis < 1000 {
+        return format!("{} ms", millis);
+    }
+    let tenths = (millis + 50) / 100;
+    if tenths < 600 {
+        return format!("{}.{} s", tenths / 10, tenths % 10);
+    }
+
+    let seconds = (millis + 500) / 1000;
+    if seconds < 3600 {
+        format!("{} min {} s", seconds / 60, seconds % 60)
+    } else if seconds < 86_400 {
+        format!("{} h {} min", seconds / 3600, seconds / 60 % 60)
+    } else {
+        format!("{} d {} h", seconds / 86_400, seconds / 3600 % 24)
+    }
+}
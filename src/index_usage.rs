@@ -0,0 +1,153 @@
+//! Reading index usage out of the text of `EXPLAIN indexes = 1`, where each
+//! `ReadFromMergeTree` step lists the indexes it applied in order, e.g.:
+//!
+//! ```text
+//! ReadFromMergeTree (web.events)
+//! Indexes:
+//!   PrimaryKey
+//!     Keys:
+//!       user_id
+//!     Condition: (user_id in [42, 42])
+//!     Parts: 3/12
+//!     Granules: 5/1480
+//! ```
+
+use serde::Serialize;
+
+/// One index applied while reading a table.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct IndexStep {
+    /// `MinMax`, `Partition`, `PrimaryKey` or `Skip`.
+    pub kind: String,
+    /// The table read, as shown by `ReadFromMergeTree`.
+    pub table: String,
+    /// The name of a skip index.
+    pub name: Option<String>,
+    pub keys: Vec<String>,
+    /// The condition the index was checked with; `true` when the query
+    /// doesn't filter on the index.
+    pub condition: Option<String>,
+    pub parts_selected: u64,
+    pub parts_total: u64,
+    pub granules_selected: u64,
+    pub granules_total: u64,
+}
+
+impl IndexStep {
+    /// Whether the index could be checked against a condition of the query.
+    /// Skip indexes don't show their condition, so this is `false` for them.
+    pub fn is_used(&self) -> bool {
+        self.condition.as_deref().is_some_and(|condition| condition != "true")
+    }
+}
+
+/// How a query uses the indexes of the MergeTree tables it reads.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct IndexUsage {
+    /// Whether the primary key of any table was checked against a condition
+    /// of the query.
+    pub pk_used: bool,
+    /// Granules left to read after all indexes, summed over the tables.
+    pub granules_selected: u64,
+    /// Granules before any index was applied, summed over the tables.
+    pub granules_total: u64,
+    /// The names of the skip indexes that were used.
+    pub skip_indexes_used: Vec<String>,
+    pub indexes: Vec<IndexStep>,
+}
+
+impl IndexUsage {
+    /// Parses the lines of `EXPLAIN indexes = 1`. Tables that aren't MergeTree
+    /// have no `Indexes:` section and add nothing.
+    pub fn from_explain(lines: &[String]) -> Self {
+        // The indexes of each `ReadFromMergeTree` step
+        let mut reads: Vec<Vec<IndexStep>> = Vec::new();
+        let mut table = String::new();
+        let mut in_indexes = false;
+        // The indentation of a `Keys:` label whose keys are being read
+        let mut keys_indent: Option<usize> = None;
+
+        for line in lines {
+            let indent = line.len() - line.trim_start().len();
+            let text = line.trim();
+
+            if let Some(keys_at) = keys_indent {
+                if indent > keys_at {
+                    if let Some(step) = reads.last_mut().and_then(|read| read.last_mut()) {
+                        step.keys.push(text.to_string());
+                    }
+                    continue;
+                }
+                keys_indent = None;
+            }
+
+            if let Some(read) = text.strip_prefix("ReadFromMergeTree") {
+                table = read.trim().trim_start_matches('(').trim_end_matches(')').to_string();
+                reads.push(Vec::new());
+                in_indexes = false;
+                continue;
+            }
+            if text == "Indexes:" {
+                in_indexes = true;
+                continue;
+            }
+            let Some(read) = reads.last_mut().filter(|_| in_indexes) else { continue };
+
+            match text {
+                "MinMax" | "Partition" | "PrimaryKey" | "Skip" => {
+                    read.push(IndexStep { kind: text.to_string(), table: table.clone(), ..IndexStep::default() });
+                    continue;
+                }
+                "Keys:" => {
+                    keys_indent = Some(indent);
+                    continue;
+                }
+                _ => {}
+            }
+
+            let Some(step) = read.last_mut() else { continue };
+            let Some((label, value)) = text.split_once(':') else { continue };
+            let value = value.trim();
+            match label {
+                "Name" => step.name = Some(value.to_string()),
+                "Condition" => step.condition = Some(value.to_string()),
+                "Parts" => (step.parts_selected, step.parts_total) = fraction(value),
+                "Granules" => (step.granules_selected, step.granules_total) = fraction(value),
+                _ => {}
+            }
+        }
+
+        Self::from_reads(reads)
+    }
+
+    fn from_reads(reads: Vec<Vec<IndexStep>>) -> Self {
+        let mut usage = IndexUsage::default();
+
+        // A read's indexes apply one after another: the first sees all
+        // granules, the last leaves what is read
+        for read in &reads {
+            if let (Some(first), Some(last)) = (read.first(), read.last()) {
+                usage.granules_total += first.granules_total;
+                usage.granules_selected += last.granules_selected;
+            }
+        }
+
+        let indexes: Vec<IndexStep> = reads.into_iter().flatten().collect();
+        usage.pk_used = indexes.iter().any(|step| step.kind == "PrimaryKey" && step.is_used());
+        // Skip indexes are listed without a condition; they count as used
+        // when they dropped granules
+        usage.skip_indexes_used = indexes
+            .iter()
+            .filter(|step| step.kind == "Skip" && step.granules_selected < step.granules_total)
+            .filter_map(|step| step.name.clone())
+            .collect();
+        usage.indexes = indexes;
+        usage
+    }
+}
+
+/// Parses `selected/total`, e.g. `5/1480`.
+fn fraction(value: &str) -> (u64, u64) {
+    let (selected, total) = value.split_once('/').unwrap_or((value, value));
+    (selected.trim().parse().unwrap_or(0), total.trim().parse().unwrap_or(0))
+}
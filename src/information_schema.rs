@@ -0,0 +1,218 @@
+//! Table and column metadata in the shape of the SQL standard's
+//! `INFORMATION_SCHEMA.TABLES` and `INFORMATION_SCHEMA.COLUMNS`, for BI tools
+//! that read that shape rather than [`ColumnInfo`].
+//!
+//! ClickHouse types are mapped to standard type names by [`sql_type`], and
+//! back by [`clickhouse_type`]. Types without a standard counterpart (arrays,
+//! maps, tuples, IP addresses, ...) keep their ClickHouse name and are
+//! flagged with `TYPE_MAPPED: false`. As in ClickHouse's own
+//! `INFORMATION_SCHEMA`, the catalog and the schema are both the database.
+
+use crate::{is_nullable_type, parse_string_literal, parse_type, unwrap_type_modifiers, ColumnInfo, TableInfo};
+use serde::Serialize;
+
+/// ClickHouse types without parameters and the standard types they map to.
+/// Each pair maps both ways.
+pub const TYPE_NAMES: &[(&str, &str)] = &[
+    ("String", "VARCHAR"),
+    ("Int8", "TINYINT"),
+    ("Int16", "SMALLINT"),
+    ("Int32", "INTEGER"),
+    ("Int64", "BIGINT"),
+    ("UInt8", "TINYINT UNSIGNED"),
+    ("UInt16", "SMALLINT UNSIGNED"),
+    ("UInt32", "INTEGER UNSIGNED"),
+    ("UInt64", "BIGINT UNSIGNED"),
+    ("Float32", "REAL"),
+    ("Float64", "DOUBLE PRECISION"),
+    ("Bool", "BOOLEAN"),
+    ("Date", "DATE"),
+    ("DateTime", "TIMESTAMP"),
+    ("UUID", "UUID"),
+];
+
+/// The precision of the `Decimal32` to `Decimal256` shorthands.
+const DECIMAL_PRECISIONS: &[(&str, u32)] = &[("Decimal32", 9), ("Decimal64", 18), ("Decimal128", 38), ("Decimal256", 76)];
+
+/// A column type as `INFORMATION_SCHEMA.COLUMNS` describes it.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct SqlType {
+    /// The standard type name, or the ClickHouse one when `mapped` is false.
+    pub data_type: String,
+    pub character_maximum_length: Option<u64>,
+    pub numeric_precision: Option<u32>,
+    pub numeric_scale: Option<u32>,
+    pub datetime_precision: Option<u32>,
+    /// Whether `data_type` is a standard type name.
+    pub mapped: bool,
+}
+
+impl SqlType {
+    fn mapped(data_type: &str) -> Self {
+        Self { data_type: data_type.to_string(), mapped: true, ..Self::default() }
+    }
+}
+
+/// Maps a ClickHouse type to its standard counterpart, ignoring `Nullable`
+/// and `LowCardinality`. Enums map to `VARCHAR`, as their values are
+/// strings. Unmappable types keep their ClickHouse name, unmapped.
+pub fn sql_type(clickhouse_type: &str) -> SqlType {
+    let base = unwrap_type_modifiers(clickhouse_type);
+    let unmapped = || SqlType { data_type: base.to_string(), ..SqlType::default() };
+    let Some(parsed) = parse_type(base) else {
+        return unmapped();
+    };
+    let name = parsed.name;
+    let parameters: Vec<String> = parsed
+        .parameters
+        .iter()
+        .map(|parameter| parse_string_literal(parameter).unwrap_or_else(|| parameter.to_string()))
+        .collect();
+    let number = |index: usize| parameters.get(index).and_then(|p| p.parse::<u32>().ok());
+    let decimal = |precision, scale| SqlType { numeric_precision: Some(precision), numeric_scale: scale, ..SqlType::mapped("DECIMAL") };
+
+    match (name, parameters.len()) {
+        // The time zone only affects how values are shown
+        ("DateTime", 0 | 1) => SqlType { datetime_precision: Some(0), ..SqlType::mapped("TIMESTAMP") },
+        ("Date32", 0) => SqlType::mapped("DATE"),
+        (name, 0) => match TYPE_NAMES.iter().find(|(clickhouse, _)| *clickhouse == name) {
+            Some((_, standard)) => SqlType::mapped(standard),
+            None => unmapped(),
+        },
+        ("FixedString", 1) => match parameters[0].parse() {
+            Ok(length) => SqlType { character_maximum_length: Some(length), ..SqlType::mapped("CHAR") },
+            Err(_) => unmapped(),
+        },
+        ("Decimal", count @ (1 | 2)) => match (number(0), if count == 2 { number(1) } else { Some(0) }) {
+            (Some(precision), scale @ Some(_)) => decimal(precision, scale),
+            _ => unmapped(),
+        },
+        (name, 1) if name.starts_with("Decimal") => {
+            let precision = DECIMAL_PRECISIONS.iter().find(|(shorthand, _)| *shorthand == name).map(|(_, precision)| *precision);
+            match (precision, number(0)) {
+                (Some(precision), scale @ Some(_)) => decimal(precision, scale),
+                _ => unmapped(),
+            }
+        }
+        ("DateTime64", 1 | 2) => match number(0) {
+            Some(precision) => SqlType { datetime_precision: Some(precision), ..SqlType::mapped("TIMESTAMP") },
+            None => unmapped(),
+        },
+        ("Enum8" | "Enum16", _) => SqlType::mapped("VARCHAR"),
+        _ => unmapped(),
+    }
+}
+
+/// The ClickHouse type a standard type maps back to, the reverse of
+/// [`sql_type`]. Several ClickHouse types can map to one standard type, so
+/// this picks one: `VARCHAR` is `String`, `DECIMAL` is `Decimal(P, S)` and
+/// `TIMESTAMP` is `DateTime`, or `DateTime64` with a sub-second precision.
+/// Unmapped types are taken as ClickHouse names. `None` for standard types
+/// with no counterpart or missing parameters.
+pub fn clickhouse_type(sql: &SqlType) -> Option<String> {
+    if !sql.mapped {
+        return Some(sql.data_type.clone());
+    }
+    let data_type = sql.data_type.to_ascii_uppercase();
+    match data_type.as_str() {
+        "CHAR" => sql.character_maximum_length.map(|length| format!("FixedString({})", length)),
+        "DECIMAL" => sql.numeric_precision.map(|precision| format!("Decimal({}, {})", precision, sql.numeric_scale.unwrap_or(0))),
+        "TIMESTAMP" => match sql.datetime_precision {
+            Some(precision) if precision > 0 => Some(format!("DateTime64({})", precision)),
+            _ => Some("DateTime".to_string()),
+        },
+        standard => TYPE_NAMES.iter().find(|(_, name)| *name == standard).map(|(clickhouse, _)| clickhouse.to_string()),
+    }
+}
+
+/// A row of `INFORMATION_SCHEMA.TABLES`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub struct TableRow {
+    pub table_catalog: String,
+    pub table_schema: String,
+    pub table_name: String,
+    /// `VIEW` for views of any kind, otherwise `BASE TABLE`.
+    pub table_type: &'static str,
+    /// The ClickHouse engine, which the standard has no column for.
+    pub engine: String,
+}
+
+/// A row of `INFORMATION_SCHEMA.COLUMNS`, with the ClickHouse type and
+/// whether it was mapped added at the end.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub struct ColumnRow {
+    pub table_catalog: String,
+    pub table_schema: String,
+    pub table_name: String,
+    pub column_name: String,
+    /// 1-based.
+    pub ordinal_position: usize,
+    pub column_default: Option<String>,
+    /// `YES` or `NO`.
+    pub is_nullable: &'static str,
+    pub data_type: String,
+    pub character_maximum_length: Option<u64>,
+    pub numeric_precision: Option<u32>,
+    pub numeric_scale: Option<u32>,
+    pub datetime_precision: Option<u32>,
+    pub column_comment: String,
+    pub clickhouse_type: String,
+    pub type_mapped: bool,
+}
+
+/// Whether tables of `engine` are views.
+fn is_view_engine(engine: &str) -> bool {
+    matches!(engine, "View" | "MaterializedView" | "LiveView" | "WindowView")
+}
+
+/// `tables` as `INFORMATION_SCHEMA.TABLES` rows.
+pub fn table_rows(tables: &[TableInfo]) -> Vec<TableRow> {
+    tables
+        .iter()
+        .map(|table| TableRow {
+            table_catalog: table.database.clone(),
+            table_schema: table.database.clone(),
+            table_name: table.name.clone(),
+            table_type: if is_view_engine(&table.engine) { "VIEW" } else { "BASE TABLE" },
+            engine: table.engine.clone(),
+        })
+        .collect()
+}
+
+/// The columns of `database.table` as `INFORMATION_SCHEMA.COLUMNS` rows.
+pub fn column_rows(database: &str, table: &str, columns: &[ColumnInfo]) -> Vec<ColumnRow> {
+    columns
+        .iter()
+        .enumerate()
+        .map(|(index, column)| {
+            let sql = sql_type(&column.r#type);
+            ColumnRow {
+                table_catalog: database.to_string(),
+                table_schema: database.to_string(),
+                table_name: table.to_string(),
+                column_name: column.name.clone(),
+                ordinal_position: index + 1,
+                column_default: (!column.default_expression.is_empty()).then(|| column.default_expression.clone()),
+                is_nullable: if is_nullable_type(&column.r#type) { "YES" } else { "NO" },
+                data_type: sql.data_type,
+                character_maximum_length: sql.character_maximum_length,
+                numeric_precision: sql.numeric_precision,
+                numeric_scale: sql.numeric_scale,
+                datetime_precision: sql.datetime_precision,
+                column_comment: column.comment.clone(),
+                clickhouse_type: column.r#type.clone(),
+                type_mapped: sql.mapped,
+            }
+        })
+        .collect()
+}
+
+/// `rows` as text, one JSON object per line, as `JSONEachRow` would print
+/// them.
+pub fn json_lines<T: Serialize>(rows: &[T]) -> String {
+    rows.iter()
+        .map(|row| serde_json::to_string(row).unwrap_or_default() + "\n")
+        .collect()
+}
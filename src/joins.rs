@@ -0,0 +1,153 @@
+use crate::{is_nullable_type, unwrap_type_modifiers, ColumnInfo};
+use serde::Serialize;
+use std::fmt;
+
+/// How likely a suggested pair of columns is a real join key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub enum JoinConfidence {
+    High,
+    Medium,
+    Low,
+}
+
+impl fmt::Display for JoinConfidence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JoinConfidence::High => write!(f, "high"),
+            JoinConfidence::Medium => write!(f, "medium"),
+            JoinConfidence::Low => write!(f, "low"),
+        }
+    }
+}
+
+/// A pair of columns that look like they could join two tables.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct JoinCandidate {
+    pub left_column: String,
+    pub right_column: String,
+    pub confidence: JoinConfidence,
+    pub reason: String,
+}
+
+/// The kinds of values that make sensible join keys. Dates, floats,
+/// decimals and compound types are left out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum KeyKind {
+    Integer,
+    Text,
+    Uuid,
+}
+
+fn key_kind(type_name: &str) -> Option<KeyKind> {
+    let base = unwrap_type_modifiers(type_name);
+    let sized = |prefix: &str| {
+        base.strip_prefix(prefix)
+            .is_some_and(|bits| !bits.is_empty() && bits.chars().all(|c| c.is_ascii_digit()))
+    };
+
+    if sized("Int") || sized("UInt") {
+        Some(KeyKind::Integer)
+    } else if base == "String" || base.starts_with("FixedString(") {
+        Some(KeyKind::Text)
+    } else if base == "UUID" {
+        Some(KeyKind::Uuid)
+    } else {
+        None
+    }
+}
+
+/// Whether the columns can be compared as keys: `None` if they don't fit
+/// together, otherwise whether their base types are identical and what to
+/// watch out for when joining on them.
+fn compatibility(left: &ColumnInfo, right: &ColumnInfo) -> Option<(bool, Vec<String>)> {
+    let left_kind = key_kind(&left.r#type)?;
+    if key_kind(&right.r#type)? != left_kind {
+        return None;
+    }
+
+    let (left_base, right_base) = (unwrap_type_modifiers(&left.r#type), unwrap_type_modifiers(&right.r#type));
+    let same_type = left_base == right_base;
+    let mut notes = Vec::new();
+    if !same_type {
+        notes.push(format!("{} vs {}, cast to a common type", left_base, right_base));
+    }
+    if is_nullable_type(&left.r#type) != is_nullable_type(&right.r#type) {
+        notes.push("Nullable on one side only, NULL keys never match".to_string());
+    }
+    Some((same_type, notes))
+}
+
+/// The `<table>_id` names a column referencing `table` would have, e.g.
+/// `user_id` and `users_id` for `users`.
+fn reference_names(table: &str) -> Vec<String> {
+    let table = table.to_ascii_lowercase();
+    let singular = if let Some(stem) = table.strip_suffix("ies") {
+        format!("{}y", stem)
+    } else if let Some(stem) = table.strip_suffix('s') {
+        stem.to_string()
+    } else {
+        table.clone()
+    };
+
+    let mut names = vec![format!("{}_id", singular)];
+    if singular != table {
+        names.push(format!("{}_id", table));
+    }
+    names
+}
+
+/// Suggests columns to join `left_table` and `right_table` on, from their
+/// schemas alone:
+///
+/// - the same name and key type (high), or compatible types such as
+///   `UInt32` and `UInt64` (medium);
+/// - a `<table>_id` column on one side and `id` on the other (medium);
+/// - `id` on both sides (low, as it usually names each table's own key).
+///
+/// `LowCardinality` and `Nullable` wrappers are ignored for matching, with
+/// nullability mismatches noted. Only integer, string and UUID columns are
+/// considered. These are guesses to verify, not declared relationships.
+pub fn suggest_joins(
+    left_table: &str,
+    left: &[ColumnInfo],
+    right_table: &str,
+    right: &[ColumnInfo],
+) -> Vec<JoinCandidate> {
+    let left_references = reference_names(right_table);
+    let right_references = reference_names(left_table);
+    let mut candidates = Vec::new();
+
+    for left_column in left {
+        for right_column in right {
+            let Some((same_type, notes)) = compatibility(left_column, right_column) else {
+                continue;
+            };
+            let (left_name, right_name) = (left_column.name.to_ascii_lowercase(), right_column.name.to_ascii_lowercase());
+
+            let (confidence, reason) = if left_name == right_name && left_name == "id" {
+                (JoinConfidence::Low, "both are named id, which is usually each table's own key".to_string())
+            } else if left_name == right_name {
+                let confidence = if same_type { JoinConfidence::High } else { JoinConfidence::Medium };
+                (confidence, "same name".to_string())
+            } else if right_name == "id" && left_references.contains(&left_name) {
+                (JoinConfidence::Medium, format!("{}.{} looks like a reference to {}.id", left_table, left_column.name, right_table))
+            } else if left_name == "id" && right_references.contains(&right_name) {
+                (JoinConfidence::Medium, format!("{}.{} looks like a reference to {}.id", right_table, right_column.name, left_table))
+            } else {
+                continue;
+            };
+
+            let reason = std::iter::once(reason).chain(notes).collect::<Vec<_>>().join("; ");
+            candidates.push(JoinCandidate {
+                left_column: left_column.name.clone(),
+                right_column: right_column.name.clone(),
+                confidence,
+                reason,
+            });
+        }
+    }
+
+    // Stable, so columns keep their schema order within a confidence level
+    candidates.sort_by_key(|candidate| candidate.confidence);
+    candidates
+}
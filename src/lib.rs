@@ -1,21 +1,202 @@
 use anyhow::Result;
-use clickhouse::{Client, Row};
-use log::{debug, error, info, warn};
+use clickhouse::{Client, Compression, Row};
+use log::{debug, info, warn};
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 use thiserror::Error;
-use tokio::time::sleep;
+use tokio::sync::OnceCell;
+use tokio::time::{sleep, Instant};
+use transport::Transport;
+
+pub mod cache;
+pub mod capabilities;
+pub mod catalog;
+pub mod cli;
+pub mod codec;
+pub mod config;
+pub mod connection;
+pub mod ddl;
+pub mod distributed;
+pub mod doctor;
+pub mod error_hints;
+pub mod format;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+pub mod history;
+pub mod humanize;
+pub mod index_usage;
+pub mod information_schema;
+pub mod joins;
+pub mod lineage;
+pub mod metrics;
+pub mod ordering;
+pub mod prelude;
+pub mod prompts;
+pub mod protocol;
+pub mod pruning;
+pub mod results;
+pub mod schema_summary;
+pub mod server;
+pub mod sql;
+pub mod transcript;
+mod transport;
+pub mod version;
+pub mod visibility;
+pub mod workspace;
+
+pub use catalog::{
+    decode_catalog_version, diff_catalog, encode_catalog_version, CatalogDiff, CatalogEntry, CatalogExport, CatalogVersion, ExportedColumn,
+    ExportedTable, TableRef,
+};
+pub use capabilities::{apply_capabilities, Capabilities, Requirement, UnavailableTools, TOOL_REQUIREMENTS};
+pub use cache::{CacheScope, CacheStats, CachedSchema, RefreshedSchema, SchemaCache};
+pub use cli::CliOptions;
+pub use codec::{encode_frame, Frame, LineReader, DEFAULT_MAX_LINE_LENGTH};
+pub use config::{parse_bool, parse_connection_url, ClickHouseConfig, ConfigLayer, ConfigSource, ConnectionUrl};
+pub use connection::{ConnectionManager, ConnectionState, ConnectionStatus, HealthProbe, StateTransition};
+pub use ddl::{ddl_dependencies, order_ddl};
+pub use distributed::{parse_distributed_engine, DistributedScope, DistributedTarget};
+pub use doctor::{CheckOutcome, DoctorCheck, DoctorReport};
+pub use error_hints::{explain_error, ErrorHint, ERROR_HINTS};
+pub use format::{format_all_tables, format_columns, format_databases, format_schema_summary, format_table_schema, format_tables};
+pub use history::{QueryHistory, QueryHistoryEntry, MAX_QUERY_HISTORY};
+pub use humanize::{human_bytes, human_count, human_duration, with_raw_numbers};
+pub use index_usage::{IndexStep, IndexUsage};
+pub use joins::{suggest_joins, JoinCandidate, JoinConfidence};
+pub use lineage::{column_lineage, parse_materialized_view, ColumnLineage, ColumnSource, MaterializedView, SelectItem, SourceKind, SourceTable};
+pub use metrics::{Metrics, MetricsSnapshot};
+pub use ordering::{advise_ordering, ColumnCardinality, OrderingAdvice};
+pub use protocol::{truncation_notice, Content, StructuredOmission, ToolResult};
+pub use pruning::{PartTotals, PartitionPruning, PruningVerdict, PRUNING_EXPLAIN_TIMEOUT};
+pub use schema_summary::{summarize_schema, ColumnFamily, SchemaSummary, TypeGroup, DEFAULT_VERBATIM_COLUMNS};
+pub use server::{JsonRpcRequest, JsonRpcResponse, McpServer, ToolHandler};
+pub use sql::{classify_statement, coerce_bound_value, statement_table, BoundValue, StatementKind, ALLOWED_SETTINGS};
+pub use transcript::{diff_outputs, is_local_backend, read_transcript, replay, Direction, ReplayMismatch, ReplayReport, SessionRecorder, TranscriptEntry};
+pub use version::{Feature, Version};
+pub use visibility::DatabaseVisibility;
+
+/// The default longest accepted identifier, in bytes. ClickHouse itself has
+/// no name length setting; names are limited by the 255-byte file names of
+/// the metadata and data directories minus the suffixes ClickHouse adds,
+/// which leaves about this much.
+pub const DEFAULT_MAX_IDENTIFIER_LENGTH: usize = 206;
+
+/// The longest a client waits for a `Retry-After` found in a response body
+/// unless told otherwise.
+pub const DEFAULT_MAX_RETRY_DELAY: Duration = Duration::from_secs(30);
+
+/// Which failures of an operation are retried.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RetryPolicy {
+    /// Every retryable failure, for light queries such as introspection.
+    #[default]
+    Always,
+    /// Only transient error responses, which ClickHouse or a proxy sends
+    /// instead of a result. Network errors and timeouts may hit after part
+    /// of a large result was read, and retrying would read it all again.
+    LightOnly,
+}
+
+impl RetryPolicy {
+    /// Whether the policy allows retrying after `error`, once
+    /// [`ClickHouseClient::is_retryable_error`] says it is worth it.
+    pub fn allows(self, error: &clickhouse::error::Error) -> bool {
+        match self {
+            RetryPolicy::Always => true,
+            RetryPolicy::LightOnly => matches!(error, clickhouse::error::Error::BadResponse(_)),
+        }
+    }
+}
+
+/// The most tables [`ClickHouseClient::get_table_schemas`] fetches at once.
+pub const MAX_TABLE_SCHEMAS: usize = 20;
+
+tokio::task_local! {
+    static QUERY_COMMENT: String;
+    static QUERY_IDS: Arc<QueryIds>;
+    static RETRY_BUDGET: Arc<RetryBudget>;
+}
+
+/// Time budget shared by every retry loop within one tool call.
+struct RetryBudget {
+    deadline: Instant,
+    attempts: AtomicU32,
+    operations: AtomicU32,
+}
+
+/// Runs `future` with a total time budget for retries: once the next retry
+/// would start after the budget runs out, operations fail with
+/// [`ClickHouseError::RetryBudgetExhausted`] instead of retrying. Without a
+/// budget in scope, retries are only bounded by the client's retry count.
+pub async fn with_retry_budget<F: std::future::Future>(budget: Duration, future: F) -> F::Output {
+    let budget = Arc::new(RetryBudget {
+        deadline: Instant::now() + budget,
+        attempts: AtomicU32::new(0),
+        operations: AtomicU32::new(0),
+    });
+    RETRY_BUDGET.scope(budget, future).await
+}
+
+/// The query ids handed out within one [`with_query_id`] scope.
+struct QueryIds {
+    prefix: String,
+    issued: AtomicU32,
+}
+
+/// Runs `future` with every ClickHouse query it issues tagged with a
+/// `query_id` of the form `<prefix>:<n>`, numbered from 1, so the queries
+/// can be found in `system.query_log` and stopped with
+/// [`ClickHouseClient::kill_queries`].
+pub async fn with_query_id<F: std::future::Future>(prefix: String, future: F) -> F::Output {
+    QUERY_IDS.scope(Arc::new(QueryIds { prefix, issued: AtomicU32::new(0) }), future).await
+}
+
+/// The prefix given to [`with_query_id`] for the current task, if any.
+pub fn query_id_prefix() -> Option<String> {
+    QUERY_IDS.try_with(|ids| ids.prefix.clone()).ok()
+}
+
+/// Runs `future` with `comment` appended to every query it issues as a
+/// trailing `/* ... */` comment and sent as its `log_comment` setting, both
+/// of which show up in `system.query_log`. Characters other than letters, digits, spaces and
+/// `=_-.:` are replaced with `_` so the comment cannot end early or add bind
+/// placeholders.
+pub async fn with_query_comment<F: std::future::Future>(comment: &str, future: F) -> F::Output {
+    let comment = comment
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || " =_-.:".contains(c) { c } else { '_' })
+        .collect();
+    QUERY_COMMENT.scope(comment, future).await
+}
 
 #[derive(Debug, Error)]
 pub enum ClickHouseError {
     #[error("Connection failed: {message}")]
     ConnectionFailed { message: String },
     #[error("Database '{database}' not found")]
-    DatabaseNotFound { database: String },
+    DatabaseNotFound {
+        database: String,
+        /// The ClickHouse error this was mapped from, with
+        /// [`ClickHouseClient::with_verbose_errors`].
+        raw: Option<String>,
+    },
     #[error("Table '{table}' not found in database '{database}'")]
-    TableNotFound { database: String, table: String },
+    TableNotFound {
+        database: String,
+        table: String,
+        /// As for [`Self::DatabaseNotFound`].
+        raw: Option<String>,
+    },
     #[error("Permission denied for operation: {operation}")]
-    PermissionDenied { operation: String },
+    PermissionDenied {
+        operation: String,
+        /// As for [`Self::DatabaseNotFound`].
+        raw: Option<String>,
+    },
     #[error("Query timeout after {timeout}s")]
     QueryTimeout { timeout: u64 },
     #[error("Invalid identifier '{identifier}': {reason}")]
@@ -28,15 +209,87 @@ pub enum ClickHouseError {
     QueryFailed { message: String },
     #[error("Service unavailable: {message}")]
     ServiceUnavailable { message: String },
+    #[error("Column '{column}' not found in table '{database}.{table}'")]
+    ColumnNotFound { database: String, table: String, column: String },
+    #[error("Invalid argument: {message}")]
+    InvalidArgument { message: String },
+    #[error("This server has no Keeper/ZooKeeper configured")]
+    KeeperNotConfigured {
+        /// As for [`Self::DatabaseNotFound`].
+        raw: Option<String>,
+    },
+    #[error("{last_error} (retry budget exhausted after {attempts} attempts across {operations} operations)")]
+    RetryBudgetExhausted {
+        attempts: u32,
+        operations: u32,
+        last_error: Box<ClickHouseError>,
+    },
+    #[error(
+        "{feature} requires ClickHouse >= {}.{}, server is {}.{}",
+        .required.major, .required.minor, .actual.major, .actual.minor
+    )]
+    UnsupportedVersion {
+        feature: Feature,
+        required: Version,
+        actual: Version,
+    },
+    #[error("Tool '{tool}' is unavailable on this server, which lacks {missing}")]
+    ToolUnavailable { tool: String, missing: String },
+    #[error("Quota exceeded: {message}")]
+    QuotaExceeded {
+        message: String,
+        /// When the quota interval ends, in the server's time zone, if
+        /// ClickHouse said so.
+        resets_at: Option<chrono::NaiveDateTime>,
+    },
+    #[error("Writes are disabled for this client")]
+    WritesDisabled,
+    #[error(
+        "Statement {} of {total} failed after {} succeeded, which were not rolled back: {source}",
+        .index + 1, .completed.len()
+    )]
+    ScriptFailed {
+        /// The position of the failed statement in the script.
+        index: usize,
+        total: usize,
+        /// The statements that ran before the failure.
+        completed: Vec<ExecResult>,
+        source: Box<ClickHouseError>,
+    },
     #[error("Internal error: {message}")]
     InternalError { message: String },
 }
 
+impl ClickHouseError {
+    /// The ClickHouse error message behind a variant whose own message
+    /// leaves it out, if [`ClickHouseClient::with_verbose_errors`] kept it.
+    /// Variants with a `message` carry it unchanged there instead.
+    pub fn raw(&self) -> Option<&str> {
+        match self {
+            Self::DatabaseNotFound { raw, .. }
+            | Self::TableNotFound { raw, .. }
+            | Self::PermissionDenied { raw, .. }
+            | Self::KeeperNotConfigured { raw } => raw.as_deref(),
+            Self::RetryBudgetExhausted { last_error: error, .. } | Self::ScriptFailed { source: error, .. } => error.raw(),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Row)]
 pub struct DatabaseInfo {
     pub name: String,
 }
 
+/// A data format from `system.formats` and whether the server reads
+/// (`INSERT ... FORMAT`) and writes (`SELECT ... FORMAT`) it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Row)]
+pub struct FormatInfo {
+    pub name: String,
+    pub is_input: bool,
+    pub is_output: bool,
+}
+
 #[derive(Debug, Serialize, Deserialize, Row)]
 pub struct TableInfo {
     pub name: String,
@@ -44,7 +297,7 @@ pub struct TableInfo {
     pub engine: String,
 }
 
-#[derive(Debug, Serialize, Deserialize, Row)]
+#[derive(Debug, Clone, Serialize, Deserialize, Row)]
 pub struct ColumnInfo {
     pub name: String,
     pub r#type: String,
@@ -55,287 +308,4455 @@ pub struct ColumnInfo {
     pub is_in_sorting_key: u8,
     pub is_in_primary_key: u8,
     pub is_in_sampling_key: u8,
+    /// The names and values of an `Enum8`/`Enum16` column, parsed from its
+    /// type; not a column of `system.columns`.
+    #[serde(skip_deserializing, default, skip_serializing_if = "Option::is_none")]
+    pub enum_values: Option<Vec<(String, i16)>>,
+    /// The time zone of a `DateTime`/`DateTime64` column: the one its type
+    /// names, otherwise the default given to [`Self::with_timezone`]; not a
+    /// column of `system.columns`.
+    #[serde(skip_deserializing, default, skip_serializing_if = "Option::is_none")]
+    pub timezone: Option<String>,
 }
 
-pub struct ClickHouseClient {
-    client: Client,
-    max_retries: u32,
-    base_delay: Duration,
-}
-
-impl ClickHouseClient {
-    pub fn new(url: &str, database: &str, username: &str, password: &str) -> Self {
-        let client = Client::default()
-            .with_url(url)
-            .with_database(database)
-            .with_user(username)
-            .with_password(password);
-        
-        Self { 
-            client,
-            max_retries: 3,
-            base_delay: Duration::from_millis(100),
-        }
+impl ColumnInfo {
+    /// Fills in [`Self::enum_values`] from the type.
+    pub fn with_enum_values(mut self) -> Self {
+        self.enum_values = parse_enum_values(&self.r#type);
+        self
     }
     
-    pub fn with_retry_config(mut self, max_retries: u32, base_delay: Duration) -> Self {
-        self.max_retries = max_retries;
-        self.base_delay = base_delay;
+    /// Fills in [`Self::timezone`] from the type, falling back to
+    /// `default_timezone` (the session's or the server's) for date and time
+    /// columns whose type names none.
+    pub fn with_timezone(mut self, default_timezone: Option<&str>) -> Self {
+        self.timezone = parse_datetime_type(&self.r#type)
+            .and_then(|datetime| datetime.timezone.or_else(|| default_timezone.map(str::to_string)));
         self
     }
     
-    fn validate_identifier(identifier: &str) -> Result<(), ClickHouseError> {
-        if identifier.is_empty() {
-            return Err(ClickHouseError::InvalidIdentifier {
-                identifier: identifier.to_string(),
-                reason: "Identifier cannot be empty".to_string(),
-            });
+    /// Whether `SELECT *` returns the column, which it doesn't for
+    /// `MATERIALIZED`, `ALIAS` and `EPHEMERAL` ones.
+    pub fn is_ordinary(&self) -> bool {
+        !matches!(self.default_type.as_str(), "MATERIALIZED" | "ALIAS" | "EPHEMERAL")
+    }
+}
+
+/// Table-level metadata from `system.tables` used for documentation.
+#[derive(Debug, Serialize, Deserialize, Row)]
+pub struct TableMetadata {
+    pub name: String,
+    pub engine: String,
+    pub comment: String,
+    pub sorting_key: String,
+    pub partition_key: String,
+    pub primary_key: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TableDocumentation {
+    pub table: TableMetadata,
+    pub columns: Vec<ColumnInfo>,
+}
+
+impl TableDocumentation {
+    pub fn commented_columns(&self) -> usize {
+        self.columns.iter().filter(|c| !c.comment.trim().is_empty()).count()
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DatabaseDocumentation {
+    pub name: String,
+    pub comment: String,
+    pub tables: Vec<TableDocumentation>,
+}
+
+/// A `system.columns` row together with the `database.table` it belongs
+/// to, used when reading the columns of many tables in one query.
+#[derive(Debug, Deserialize, Row)]
+struct TableColumnInfo {
+    qualified_name: String,
+    name: String,
+    r#type: String,
+    default_type: String,
+    default_expression: String,
+    comment: String,
+    is_in_partition_key: u8,
+    is_in_sorting_key: u8,
+    is_in_primary_key: u8,
+    is_in_sampling_key: u8,
+}
+
+/// A row of `DESCRIBE TABLE`, used instead of `system.columns` when the
+/// user may not read system tables.
+#[derive(Debug, Serialize, Deserialize, Row)]
+pub struct DescribeRow {
+    pub name: String,
+    pub r#type: String,
+    pub default_type: String,
+    pub default_expression: String,
+    pub comment: String,
+    pub codec_expression: String,
+    pub ttl_expression: String,
+}
+
+/// `DESCRIBE` does not report key membership, so those flags are all zero.
+impl From<DescribeRow> for ColumnInfo {
+    fn from(row: DescribeRow) -> Self {
+        let enum_values = parse_enum_values(&row.r#type);
+        Self {
+            name: row.name,
+            r#type: row.r#type,
+            default_type: row.default_type,
+            default_expression: row.default_expression,
+            comment: row.comment,
+            is_in_partition_key: 0,
+            is_in_sorting_key: 0,
+            is_in_primary_key: 0,
+            is_in_sampling_key: 0,
+            enum_values,
+            timezone: None,
         }
-        
-        if identifier.len() > 64 {
-            return Err(ClickHouseError::InvalidIdentifier {
-                identifier: identifier.to_string(),
-                reason: "Identifier cannot be longer than 64 characters".to_string(),
-            });
+        .with_timezone(None)
+    }
+}
+
+impl From<TableColumnInfo> for ColumnInfo {
+    fn from(column: TableColumnInfo) -> Self {
+        let enum_values = parse_enum_values(&column.r#type);
+        Self {
+            name: column.name,
+            r#type: column.r#type,
+            default_type: column.default_type,
+            default_expression: column.default_expression,
+            comment: column.comment,
+            is_in_partition_key: column.is_in_partition_key,
+            is_in_sorting_key: column.is_in_sorting_key,
+            is_in_primary_key: column.is_in_primary_key,
+            is_in_sampling_key: column.is_in_sampling_key,
+            enum_values,
+            timezone: None,
         }
-        
-        if !identifier.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '-') {
-            return Err(ClickHouseError::InvalidIdentifier {
-                identifier: identifier.to_string(),
-                reason: "Identifier can only contain alphanumeric characters, underscore, and hyphen".to_string(),
-            });
+        .with_timezone(None)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Row)]
+pub struct KeeperNode {
+    pub name: String,
+    pub value: String,
+    pub num_children: i32,
+    pub data_length: i32,
+    /// As ISO-8601 in UTC, see [`utc_timestamp_sql`].
+    pub mtime: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Row)]
+pub struct KeeperEventCount {
+    pub event: String,
+    pub value: u64,
+}
+
+/// Limits for [`ClickHouseClient::execute_select`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SelectLimits {
+    /// The most rows returned; a query producing more is marked truncated.
+    pub max_rows: u64,
+    /// Fails the query once its result grows past this many bytes.
+    pub max_result_bytes: Option<u64>,
+    /// Overrides the client's query timeout for this query.
+    pub timeout: Option<Duration>,
+}
+
+impl Default for SelectLimits {
+    fn default() -> Self {
+        Self { max_rows: 1000, max_result_bytes: None, timeout: None }
+    }
+}
+
+/// The rows of a query run through [`ClickHouseClient::execute_select`].
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SelectResult {
+    /// The column names in the query's order; empty without rows.
+    pub columns: Vec<String>,
+    pub rows: Vec<serde_json::Value>,
+    /// Set when the query produced more than `max_rows` rows.
+    pub truncated: bool,
+}
+
+/// Rows sampled by [`ClickHouseClient::sample_rows`], rendered as text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SampledRows {
+    pub text: String,
+    pub row_count: usize,
+}
+
+/// The most statements [`ClickHouseClient::run_script`] accepts.
+pub const MAX_SCRIPT_STATEMENTS: usize = 100;
+
+/// A statement run by [`ClickHouseClient::run_script`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExecResult {
+    pub statement: String,
+    pub kind: StatementKind,
+    pub elapsed: Duration,
+}
+
+/// The most rows [`ClickHouseClient::execute_raw`] returns of a SELECT
+/// unless [`ClickHouseClient::with_max_raw_rows`] sets another.
+/// `admin_execute` shows the first ones and stores the rest as a resource,
+/// see [`crate::results`].
+pub const DEFAULT_MAX_RAW_ROWS: u64 = 10_000;
+
+/// The outcome of [`ClickHouseClient::execute_raw`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum RawResult {
+    /// The first rows of a SELECT.
+    Rows(SelectResult),
+    /// A statement without a result, such as `SYSTEM FLUSH LOGS`. The HTTP
+    /// client doesn't expose ClickHouse's progress summary, so the number of
+    /// rows written isn't known.
+    Executed(ExecResult),
+}
+
+/// One row of `EXPLAIN ESTIMATE`: what a query would read from one table.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Row)]
+pub struct TableEstimate {
+    pub database: String,
+    pub table: String,
+    pub parts: u64,
+    pub rows: u64,
+    pub marks: u64,
+}
+
+/// The estimated cost of a query, summed over the tables it reads.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct CostEstimate {
+    pub rows: u64,
+    pub parts: u64,
+    pub marks: u64,
+    pub tables: Vec<TableEstimate>,
+}
+
+impl CostEstimate {
+    pub fn from_tables(tables: Vec<TableEstimate>) -> Self {
+        Self {
+            rows: tables.iter().map(|t| t.rows).sum(),
+            parts: tables.iter().map(|t| t.parts).sum(),
+            marks: tables.iter().map(|t| t.marks).sum(),
+            tables,
         }
+    }
+}
+
+/// A metric row from `system.metrics` or `system.asynchronous_metrics`.
+#[derive(Debug, Serialize, Deserialize, Row)]
+pub struct MetricValue {
+    pub metric: String,
+    pub value: f64,
+}
+
+/// A snapshot of the server's resource usage. Asynchronous metrics are
+/// recomputed periodically and some are OS-specific, so they may be missing.
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+pub struct ServerMetrics {
+    /// Memory currently allocated by the server (`MemoryTracking`).
+    pub memory_tracking_bytes: i64,
+    /// Number of queries currently executing (`Query`).
+    pub running_queries: i64,
+    pub memory_total_bytes: Option<f64>,
+    /// The 1, 5 and 15 minute load averages.
+    pub load_average: Option<(f64, f64, f64)>,
+    /// Share of CPU time spent in user and kernel space, from 0 to 1 per core.
+    pub cpu_usage: Option<f64>,
+    pub uptime_seconds: Option<f64>,
+}
+
+impl ServerMetrics {
+    /// Builds the snapshot from metric rows, ignoring metrics it doesn't know.
+    pub fn from_values(values: &[MetricValue]) -> Self {
+        let get = |name: &str| values.iter().find(|v| v.metric == name).map(|v| v.value);
         
-        if identifier.starts_with(|c: char| c.is_ascii_digit()) {
-            return Err(ClickHouseError::InvalidIdentifier {
-                identifier: identifier.to_string(),
-                reason: "Identifier cannot start with a digit".to_string(),
-            });
-        }
+        let load_average = match (get("LoadAverage1"), get("LoadAverage5"), get("LoadAverage15")) {
+            (Some(one), Some(five), Some(fifteen)) => Some((one, five, fifteen)),
+            _ => None,
+        };
+        let cpu_usage = match (get("OSUserTimeNormalized"), get("OSSystemTimeNormalized")) {
+            (Some(user), Some(system)) => Some(user + system),
+            _ => None,
+        };
         
-        Ok(())
+        Self {
+            memory_tracking_bytes: get("MemoryTracking").unwrap_or(0.0) as i64,
+            running_queries: get("Query").unwrap_or(0.0) as i64,
+            memory_total_bytes: get("OSMemoryTotal"),
+            load_average,
+            cpu_usage,
+            uptime_seconds: get("Uptime"),
+        }
+    }
+}
+
+/// A query from `system.query_log` that has finished or failed.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Row)]
+pub struct RecentQuery {
+    pub query_id: String,
+    /// `QueryFinish`, `ExceptionBeforeStart` or `ExceptionWhileProcessing`.
+    pub r#type: String,
+    /// When the query started, as ISO-8601 in UTC (see [`utc_timestamp_sql`]).
+    pub event_time: String,
+    pub duration_ms: u64,
+    pub read_rows: u64,
+    /// The first [`RECENT_QUERY_TEXT_LENGTH`] bytes of the query.
+    pub query: String,
+    /// Empty unless the query failed.
+    pub exception: String,
+}
+
+/// How much of each query's text [`ClickHouseClient::recent_queries`]
+/// returns.
+pub const RECENT_QUERY_TEXT_LENGTH: usize = 500;
+
+/// The most queries [`ClickHouseClient::recent_queries`] returns.
+pub const MAX_RECENT_QUERIES: u64 = 100;
+
+/// The server's clock and time zone, which date and time functions use
+/// unless given another time zone.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ServerTime {
+    /// The current time as ISO-8601 with the server's UTC offset, e.g.
+    /// `2024-05-01T14:00:00+02:00`.
+    pub now: String,
+    /// The IANA time zone name, e.g. `Europe/Berlin`.
+    pub timezone: String,
+}
+
+/// The on-disk size of one column, from `system.columns`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ColumnCompression {
+    pub column: String,
+    pub compressed: u64,
+    pub uncompressed: u64,
+    /// Uncompressed over compressed size, e.g. 4.0 for a column stored in a
+    /// quarter of its size. `None` when nothing is stored, e.g. for empty
+    /// tables or engines that don't track column sizes.
+    pub ratio: Option<f64>,
+}
+
+/// The most tables [`ClickHouseClient::insert_pressure`] reports, those
+/// with the most parts in a single partition first.
+pub const MAX_PRESSURE_TABLES: usize = 20;
+
+/// The share of `parts_to_delay_insert` from which a table is reported as
+/// approaching too many parts.
+pub const APPROACHING_PARTS_RATIO: f64 = 0.5;
+
+/// Active part counts of a table from `system.parts`. Insert thresholds
+/// apply per partition, so the fullest partition is what matters.
+#[derive(Debug, Serialize, Deserialize, Row)]
+pub struct TablePartCount {
+    pub database: String,
+    pub table: String,
+    pub active_parts: u64,
+    pub max_partition_parts: u64,
+}
+
+/// A table's part counts with the thresholds that apply to it, after
+/// table-level `SETTINGS` overrides.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TablePartPressure {
+    pub database: String,
+    pub table: String,
+    pub active_parts: u64,
+    pub max_partition_parts: u64,
+    pub parts_to_delay_insert: u64,
+    pub parts_to_throw_insert: u64,
+}
+
+/// An estimated row count, see [`ClickHouseClient::count_rows_approx`].
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RowEstimate {
+    pub rows: u64,
+    /// Set when the table asked about is Distributed and the rows are those
+    /// of its local table.
+    pub distributed: Option<DistributedTarget>,
+    /// Whether the rows of a Distributed table cover every shard; `false`
+    /// when reading the other shards was denied and only this server's
+    /// parts were counted.
+    pub cluster_wide: bool,
+}
+
+/// Whether inserts are being slowed down or rejected because of too many
+/// parts, see [`ClickHouseClient::insert_pressure`].
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct InsertPressure {
+    pub tables: Vec<TablePartPressure>,
+    /// Inserts delayed since the server started (`DelayedInserts`).
+    pub delayed_inserts: u64,
+    /// Inserts rejected since the server started (`RejectedInserts`).
+    pub rejected_inserts: u64,
+    /// Running merges and mutations, and the pool size; unknown on servers
+    /// without the `BackgroundMergesAndMutationsPool*` metrics.
+    pub merge_pool_tasks: Option<u64>,
+    pub merge_pool_size: Option<u64>,
+    /// Set when the table asked about is Distributed and the part counts
+    /// are those of its local table.
+    pub distributed: Option<DistributedScope>,
+}
+
+impl InsertPressure {
+    /// The share of the merge pool in use, from 0 to 1.
+    pub fn merge_pool_saturation(&self) -> Option<f64> {
+        match (self.merge_pool_tasks, self.merge_pool_size) {
+            (Some(tasks), Some(size)) if size > 0 => Some(tasks as f64 / size as f64),
+            _ => None,
+        }
     }
     
-    async fn with_retry<F, T, Fut>(&self, operation: F) -> Result<T, ClickHouseError> 
-    where
-        F: Fn() -> Fut,
-        Fut: std::future::Future<Output = Result<T, clickhouse::error::Error>>,
-    {
-        let mut last_error = None;
+    /// A one-line summary: `healthy`, or the worst state reached, naming the
+    /// tables in it (e.g. `approaching too-many-parts on analytics.events`).
+    /// The insert counters are cumulative, so they don't affect the verdict.
+    pub fn verdict(&self) -> String {
+        let names = |matches: &dyn Fn(&TablePartPressure) -> bool| {
+            self.tables
+                .iter()
+                .filter(|t| matches(t))
+                .map(|t| format!("{}.{}", t.database, t.table))
+                .collect::<Vec<_>>()
+        };
         
-        for attempt in 0..=self.max_retries {
-            if attempt > 0 {
-                let delay = self.base_delay * (2_u32.pow(attempt - 1));
-                debug!("Retrying ClickHouse operation after {}ms (attempt {})", delay.as_millis(), attempt);
-                sleep(delay).await;
-            }
-            
-            match operation().await {
-                Ok(result) => return Ok(result),
-                Err(error) => {
-                    last_error = Some(error);
-                    if attempt == self.max_retries {
-                        break;
-                    }
-                    
-                    // Check if error is retryable
-                    if !self.is_retryable_error(&last_error.as_ref().unwrap()) {
-                        break;
-                    }
-                    
-                    warn!("ClickHouse operation failed (attempt {}): {}", attempt + 1, last_error.as_ref().unwrap());
-                }
-            }
+        let throwing = names(&|t| t.max_partition_parts >= t.parts_to_throw_insert);
+        let delaying = names(&|t| t.max_partition_parts >= t.parts_to_delay_insert && t.max_partition_parts < t.parts_to_throw_insert);
+        let approaching = names(&|t| {
+            t.max_partition_parts as f64 >= t.parts_to_delay_insert as f64 * APPROACHING_PARTS_RATIO
+                && t.max_partition_parts < t.parts_to_delay_insert
+        });
+        
+        let mut findings = Vec::new();
+        if !throwing.is_empty() {
+            findings.push(format!("rejecting inserts (too many parts) on {}", throwing.join(", ")));
+        }
+        if !delaying.is_empty() {
+            findings.push(format!("delaying inserts (too many parts) on {}", delaying.join(", ")));
+        }
+        if !approaching.is_empty() {
+            findings.push(format!("approaching too-many-parts on {}", approaching.join(", ")));
+        }
+        if self.merge_pool_saturation().is_some_and(|saturation| saturation >= 0.9) {
+            findings.push("merge pool saturated".to_string());
         }
         
-        // Convert clickhouse error to our error type
-        if let Some(error) = last_error {
-            Err(self.convert_clickhouse_error(error))
+        if findings.is_empty() {
+            "healthy".to_string()
         } else {
-            Err(ClickHouseError::InternalError {
-                message: "Retry loop completed without error".to_string(),
-            })
+            findings.join("; ")
         }
     }
-    
-    fn is_retryable_error(&self, error: &clickhouse::error::Error) -> bool {
-        match error {
-            clickhouse::error::Error::Network(_) => true,
-            clickhouse::error::Error::BadResponse(_) => false, // Don't retry auth/permission errors
-            clickhouse::error::Error::InvalidParams(_) => false, // Don't retry invalid queries
-            _ => true, // Retry other errors (like timeouts)
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ProjectionInfo {
+    pub name: String,
+    pub query: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Dependencies {
+    /// Tables this table reads from, as `database.table`.
+    pub depends_on: Vec<String>,
+    /// Tables (typically materialized views) that read from this table.
+    pub dependents: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HistogramBucket {
+    pub lower: f64,
+    pub upper: f64,
+    pub height: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ColumnHistogram {
+    pub column: String,
+    pub column_type: String,
+    pub buckets: Vec<HistogramBucket>,
+    pub sample_ratio: Option<f64>,
+}
+
+/// The most tables [`ClickHouseClient::list_all_tables`] returns.
+pub const MAX_ALL_TABLES: usize = 5000;
+
+/// The tables found by [`ClickHouseClient::list_all_tables`].
+#[derive(Debug, Serialize)]
+pub struct TableListing {
+    pub tables: Vec<TableInfo>,
+    /// Set when there were more than [`MAX_ALL_TABLES`] tables.
+    pub truncated: bool,
+}
+
+/// The ClickHouse error code raised once a quota is used up.
+pub const QUOTA_EXCEEDED: u32 = 201;
+
+/// Databases holding ClickHouse's own metadata.
+pub const SYSTEM_DATABASES: &[&str] = &["system", "INFORMATION_SCHEMA", "information_schema"];
+
+/// The most columns [`ClickHouseClient::table_quality_check`] checks for
+/// nulls, and the most key columns it accepts.
+pub const MAX_QUALITY_CHECK_COLUMNS: usize = 20;
+
+/// `uniq()` is exact below this many distinct values.
+const UNIQ_EXACT_LIMIT: u64 = 65536;
+
+/// The single row returned by [`build_quality_check_query`].
+#[derive(Debug, Deserialize, Row)]
+struct QualityCheckRow {
+    total_rows: u64,
+    distinct_keys: u64,
+    null_counts: Vec<u64>,
+    min_date: String,
+    max_date: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum CheckStatus {
+    Pass,
+    Warn,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct QualityCheck {
+    pub name: String,
+    pub status: CheckStatus,
+    pub detail: String,
+}
+
+/// The outcome of [`ClickHouseClient::table_quality_check`]. Counts are over
+/// the sample when `sample_ratio` is set.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct QualityReport {
+    pub total_rows: u64,
+    pub sample_ratio: Option<f64>,
+    pub checks: Vec<QualityCheck>,
+    /// Nullable columns beyond [`MAX_QUALITY_CHECK_COLUMNS`] that were not checked.
+    pub skipped_columns: Vec<String>,
+    /// Set when sampling was requested but the table has no sampling key.
+    pub sampling_unavailable: bool,
+}
+
+/// Output format for tools that return table rows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ResponseFormat {
+    #[default]
+    Json,
+    Csv,
+}
+
+impl std::str::FromStr for ResponseFormat {
+    type Err = ClickHouseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "json" => Ok(ResponseFormat::Json),
+            "csv" => Ok(ResponseFormat::Csv),
+            other => Err(ClickHouseError::InvalidArgument {
+                message: format!("unsupported format '{}', expected 'json' or 'csv'", other),
+            }),
         }
     }
-    
-    fn convert_clickhouse_error(&self, error: clickhouse::error::Error) -> ClickHouseError {
-        match error {
-            clickhouse::error::Error::Network(e) => ClickHouseError::NetworkError {
-                message: e.to_string(),
-            },
-            clickhouse::error::Error::InvalidParams(e) => ClickHouseError::QueryFailed {
-                message: e.to_string(),
-            },
-            clickhouse::error::Error::BadResponse(e) => {
-                let error_msg = e.to_string();
-                if error_msg.contains("Authentication failed") {
-                    ClickHouseError::AuthenticationFailed { message: error_msg }
-                } else if error_msg.contains("doesn't exist") {
-                    if error_msg.contains("Database") {
-                        ClickHouseError::DatabaseNotFound {
-                            database: "unknown".to_string(),
-                        }
-                    } else {
-                        ClickHouseError::TableNotFound {
-                            database: "unknown".to_string(),
-                            table: "unknown".to_string(),
+}
+
+/// Bucket width accepted by the time-series tools.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeInterval {
+    Hour,
+    Day,
+    Week,
+    Month,
+}
+
+impl TimeInterval {
+    fn sql_unit(self) -> &'static str {
+        match self {
+            TimeInterval::Hour => "HOUR",
+            TimeInterval::Day => "DAY",
+            TimeInterval::Week => "WEEK",
+            TimeInterval::Month => "MONTH",
+        }
+    }
+}
+
+impl std::fmt::Display for TimeInterval {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.sql_unit().to_ascii_lowercase())
+    }
+}
+
+impl std::str::FromStr for TimeInterval {
+    type Err = ClickHouseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "hour" => Ok(TimeInterval::Hour),
+            "day" => Ok(TimeInterval::Day),
+            "week" => Ok(TimeInterval::Week),
+            "month" => Ok(TimeInterval::Month),
+            other => Err(ClickHouseError::InvalidArgument {
+                message: format!("unsupported interval '{}', expected hour, day, week or month", other),
+            }),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Row)]
+pub struct TimeBucketCount {
+    pub bucket: String,
+    pub count: u64,
+}
+
+/// A column with a `DEFAULT`, `MATERIALIZED`, `ALIAS` or `EPHEMERAL`
+/// expression and what it evaluates to, from
+/// [`ClickHouseClient::explain_column_defaults`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ColumnDefaultPreview {
+    pub column: String,
+    pub default_type: String,
+    pub expression: String,
+    /// The value for a sampled row as ClickHouse prints it, `NULL` for
+    /// null; `None` if it could not be evaluated.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub value: Option<String>,
+    /// Why there is no `value`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// A default expression evaluated by
+/// [`ClickHouseClient::explain_column_defaults`], `None` for null.
+#[derive(Debug, Deserialize, Row)]
+struct DefaultValue {
+    value: Option<String>,
+}
+
+/// The most rows [`ClickHouseClient::poll_new_rows`] returns per call.
+pub const MAX_POLL_ROWS: u64 = 1000;
+
+/// What [`ClickHouseClient::poll_new_rows`] found.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PolledRows {
+    /// The column names in the table's order; empty without rows.
+    pub columns: Vec<String>,
+    /// The new rows, oldest first, as JSON objects.
+    pub rows: Vec<serde_json::Value>,
+    /// The time column of the last row as ClickHouse prints it, to poll on
+    /// from; `None` if there were no new rows.
+    pub max_timestamp: Option<String>,
+}
+
+/// The most columns [`ClickHouseClient::recommend_ordering`] measures.
+pub const MAX_ORDERING_COLUMNS: usize = 20;
+
+#[derive(Debug, Deserialize, Row)]
+struct OrderingStats {
+    rows: u64,
+    distinct: Vec<u64>,
+}
+
+/// The most tables [`ClickHouseClient::recently_modified_tables`] returns.
+pub const MAX_RECENTLY_MODIFIED_LIMIT: u32 = 1000;
+
+/// When a table's data last changed: the newest modification time of its
+/// active parts, as ISO-8601 in UTC (see [`utc_timestamp_sql`]).
+#[derive(Debug, Clone, Serialize, Deserialize, Row)]
+pub struct TableModification {
+    pub database: String,
+    pub table: String,
+    pub last_modified: String,
+}
+
+/// The tables found by [`ClickHouseClient::recently_modified_tables`].
+#[derive(Debug, Serialize)]
+pub struct RecentModifications {
+    pub tables: Vec<TableModification>,
+    /// Set when more tables than the limit have parts.
+    pub truncated: bool,
+}
+
+/// The most groups [`ClickHouseClient::group_count`] returns.
+pub const MAX_GROUP_COUNT_LIMIT: u32 = 1000;
+
+/// Columns with more distinct values than this (by a `uniq` estimate) are
+/// not grouped, as nearly every group would hold a handful of rows.
+pub const MAX_GROUP_COUNT_CARDINALITY: u64 = 100_000;
+
+/// The aggregate functions [`ClickHouseClient::aggregate`] applies.
+pub const AGGREGATE_FUNCTIONS: &[&str] = &["count", "sum", "avg", "min", "max", "uniq", "median"];
+
+/// Prefixes the alias of each result of [`build_aggregate_query`]. An alias
+/// named after the function, such as `sum`, would refer back to an aggregate
+/// of itself on a column of that name, which ClickHouse refuses.
+pub const AGGREGATE_ALIAS_PREFIX: &str = "__agg_";
+
+/// The [`AGGREGATE_FUNCTIONS`] that only apply to numeric columns.
+const NUMERIC_AGGREGATE_FUNCTIONS: &[&str] = &["sum", "avg", "median"];
+
+/// Restricts [`ClickHouseClient::group_count`] to recent rows.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimeFilter {
+    pub column: String,
+    pub last_n_days: u32,
+}
+
+#[derive(Debug, Serialize, Deserialize, Row)]
+pub struct GroupCount {
+    /// The value as text, `None` for NULL.
+    pub value: Option<String>,
+    pub count: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct GroupCounts {
+    pub column: String,
+    pub column_type: String,
+    /// Rows matching the time filter, across all groups.
+    pub total_rows: u64,
+    /// Estimated number of distinct values.
+    pub distinct_values: u64,
+    /// The largest groups, biggest first.
+    pub groups: Vec<GroupCount>,
+}
+
+impl GroupCounts {
+    /// The share of `total_rows` in `group`, in percent.
+    pub fn percentage(&self, group: &GroupCount) -> f64 {
+        if self.total_rows == 0 {
+            0.0
+        } else {
+            group.count as f64 * 100.0 / self.total_rows as f64
+        }
+    }
+}
+
+/// Compression of the HTTP transfers between the client and ClickHouse.
+/// ClickHouse compresses results itself, so this mostly matters for large
+/// results over slow links.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransferCompression {
+    None,
+    Lz4,
+}
+
+impl TransferCompression {
+    /// Parses `none` or `lz4`, ignoring case.
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.to_ascii_lowercase().as_str() {
+            "none" => Some(Self::None),
+            "lz4" => Some(Self::Lz4),
+            _ => None,
+        }
+    }
+    
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::None => "none",
+            Self::Lz4 => "lz4",
+        }
+    }
+}
+
+/// How the server can pretty-print SQL, probed once per client.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum QueryFormatter {
+    FormatQuery,
+    FormatQuerySingleLine,
+    /// Servers older than 23.11 only re-print SELECTs via `EXPLAIN SYNTAX`.
+    ExplainSyntax,
+}
+
+/// Which kinds of system table lookups were denied to the user and now go
+/// through `SHOW`, `EXISTS` and `DESCRIBE` statements instead.
+#[derive(Debug, Default)]
+struct SystemTableFallback {
+    databases: AtomicBool,
+    tables: AtomicBool,
+    columns: AtomicBool,
+}
+
+/// The `User-Agent` requests are sent with unless
+/// [`ClickHouseClient::with_user_agent`] sets another.
+pub const DEFAULT_USER_AGENT: &str = concat!("mcp-test/", env!("CARGO_PKG_VERSION"));
+
+pub struct ClickHouseClient {
+    client: Transport,
+    url: String,
+    user_agent: String,
+    username: String,
+    password_set: bool,
+    bearer_token_set: bool,
+    default_database: String,
+    max_retries: u32,
+    base_delay: Duration,
+    max_delay: Duration,
+    query_timeout: Duration,
+    query_formatter: OnceCell<QueryFormatter>,
+    server_version: OnceCell<Version>,
+    server_timezone: OnceCell<String>,
+    session_timezone: Option<String>,
+    /// The outcome of the last [`Self::probe_capabilities`].
+    capabilities: Mutex<Option<Capabilities>>,
+    case_insensitive_resolution: bool,
+    strict_identifiers: bool,
+    ascii_identifiers_only: bool,
+    max_identifier_length: usize,
+    max_raw_rows: u64,
+    writes_enabled: bool,
+    verbose_errors: bool,
+    compression: TransferCompression,
+    schema_cache: Option<SchemaCache>,
+    /// Table names that resolved to other names ignoring case, see
+    /// [`Self::actual_table_name`].
+    resolved_names: Mutex<HashMap<(String, String), (String, String)>>,
+    metrics: Option<Arc<Metrics>>,
+    system_table_fallback: SystemTableFallback,
+}
+
+/// The most case-insensitive table name resolutions a client remembers;
+/// the memory is emptied when it is full.
+const MAX_RESOLVED_NAMES: usize = 256;
+
+impl ClickHouseClient {
+    pub fn new(url: &str, database: &str, username: &str, password: &str) -> Self {
+        let client = Client::default()
+            .with_url(url)
+            .with_database(database)
+            .with_user(username)
+            .with_password(password);
+        
+        Self::from_driver(Transport::Http(client), url, database, username, !password.is_empty(), false)
+    }
+    
+    /// A client that authenticates with an `Authorization: Bearer` header,
+    /// for deployments behind a gateway that doesn't accept ClickHouse
+    /// credentials. No username or password is sent.
+    pub fn with_bearer_token(url: &str, database: &str, token: &str) -> Self {
+        let client = Client::default()
+            .with_url(url)
+            .with_database(database)
+            .with_header("Authorization", format!("Bearer {}", token));
+        
+        Self::from_driver(Transport::Http(client), url, database, "", false, true)
+    }
+    
+    /// A client that talks to ClickHouse's gRPC interface at `endpoint`,
+    /// such as `http://ch:9100`, instead of HTTP. All query methods work the
+    /// same; transfers are not compressed. See [`grpc`] for how rows are read.
+    #[cfg(feature = "grpc")]
+    pub fn new_grpc(endpoint: &str, database: &str, username: &str, password: &str) -> Self {
+        let transport = grpc::GrpcTransport::new(endpoint, database, username, password);
+        let mut client = Self::from_driver(Transport::Grpc(transport), endpoint, database, username, !password.is_empty(), false);
+        client.compression = TransferCompression::None;
+        client
+    }
+
+    /// A client for `config`, preferring its bearer token over the username
+    /// and password when one is set.
+    pub fn from_config(config: &ClickHouseConfig) -> Self {
+        if config.bearer_token.is_empty() {
+            Self::new(&config.url, &config.database, &config.username, &config.password)
+        } else {
+            Self::with_bearer_token(&config.url, &config.database, &config.bearer_token)
+        }
+    }
+    
+    fn from_driver(
+        client: Transport,
+        url: &str,
+        database: &str,
+        username: &str,
+        password_set: bool,
+        bearer_token_set: bool,
+    ) -> Self {
+        Self { 
+            client: client.with_user_agent(DEFAULT_USER_AGENT),
+            url: url.to_string(),
+            user_agent: DEFAULT_USER_AGENT.to_string(),
+            username: username.to_string(),
+            password_set,
+            bearer_token_set,
+            default_database: database.to_string(),
+            max_retries: 3,
+            base_delay: Duration::from_millis(100),
+            max_delay: DEFAULT_MAX_RETRY_DELAY,
+            query_timeout: Duration::from_secs(30),
+            query_formatter: OnceCell::new(),
+            server_version: OnceCell::new(),
+            server_timezone: OnceCell::new(),
+            session_timezone: None,
+            capabilities: Mutex::new(None),
+            case_insensitive_resolution: false,
+            strict_identifiers: false,
+            ascii_identifiers_only: false,
+            max_identifier_length: DEFAULT_MAX_IDENTIFIER_LENGTH,
+            max_raw_rows: DEFAULT_MAX_RAW_ROWS,
+            writes_enabled: false,
+            verbose_errors: false,
+            // The driver's default: LZ4, except in its test mode
+            compression: if Compression::default() == Compression::None {
+                TransferCompression::None
+            } else {
+                TransferCompression::Lz4
+            },
+            schema_cache: None,
+            resolved_names: Mutex::new(HashMap::new()),
+            metrics: None,
+            system_table_fallback: SystemTableFallback::default(),
+        }
+    }
+    
+    pub fn with_retry_config(mut self, max_retries: u32, base_delay: Duration) -> Self {
+        self.max_retries = max_retries;
+        self.base_delay = base_delay;
+        self
+    }
+    
+    /// Caps the wait before a retry asked for by a `Retry-After` in a 429
+    /// response body (see [`retry_after_from_message`]), so a proxy can't
+    /// stall a tool call for long. Defaults to
+    /// [`DEFAULT_MAX_RETRY_DELAY`].
+    pub fn with_max_retry_delay(mut self, max_delay: Duration) -> Self {
+        self.max_delay = max_delay;
+        self
+    }
+    
+    /// Sets the `max_execution_time` applied to data queries.
+    pub fn with_query_timeout(mut self, timeout: Duration) -> Self {
+        self.query_timeout = timeout;
+        self
+    }
+    
+    /// When a database or table name does not exist, falls back to the one
+    /// name that matches it ignoring case. Identifiers are case-sensitive in
+    /// ClickHouse, so this is off by default. Only ASCII letters are folded,
+    /// as ClickHouse's `lower()` does: `Заказы` never matches `заказы`.
+    pub fn with_case_insensitive_resolution(mut self, enabled: bool) -> Self {
+        self.case_insensitive_resolution = enabled;
+        self
+    }
+    
+    /// Restricts identifiers to `[A-Za-z_][A-Za-z0-9_]*`, rejecting the
+    /// hyphens and non-ASCII letters accepted by default, which need
+    /// quoting everywhere they are used.
+    pub fn with_strict_identifiers(mut self, enabled: bool) -> Self {
+        self.strict_identifiers = enabled;
+        self
+    }
+    
+    /// Restricts identifiers to ASCII letters, digits, `_` and `-`, rejecting
+    /// the non-ASCII names accepted by default, such as Cyrillic or CJK ones.
+    pub fn with_ascii_identifiers_only(mut self, enabled: bool) -> Self {
+        self.ascii_identifiers_only = enabled;
+        self
+    }
+    
+    /// Sets the longest accepted identifier in bytes, by default
+    /// [`DEFAULT_MAX_IDENTIFIER_LENGTH`].
+    pub fn with_max_identifier_length(mut self, max_length: usize) -> Self {
+        self.max_identifier_length = max_length;
+        self
+    }
+    
+    /// The most rows [`Self::execute_raw`] returns of a SELECT, by default
+    /// [`DEFAULT_MAX_RAW_ROWS`].
+    pub fn with_max_raw_rows(mut self, max_rows: u64) -> Self {
+        self.max_raw_rows = max_rows.max(1);
+        self
+    }
+    
+    /// Allows statements that change data or schema, such as
+    /// [`run_script`](Self::run_script). Off by default.
+    pub fn with_writes_enabled(mut self, enabled: bool) -> Self {
+        self.writes_enabled = enabled;
+        self
+    }
+    
+    /// Runs queries over user data with `session_timezone` set to
+    /// `timezone`, e.g. `UTC`, so `DateTime` values without a time zone of
+    /// their own, `now()` and `toDate()` give the same results whatever the
+    /// server's default. Needs ClickHouse 23.6 or later; by default the
+    /// server's time zone applies.
+    pub fn with_session_timezone(mut self, timezone: impl Into<String>) -> Self {
+        self.session_timezone = Some(timezone.into());
+        self
+    }
+    
+    /// Keeps the ClickHouse error message on the errors whose own message
+    /// drops it, such as [`ClickHouseError::PermissionDenied`], as `raw`
+    /// (see [`ClickHouseError::raw`]). Off by default; meant for debugging.
+    pub fn with_verbose_errors(mut self, enabled: bool) -> Self {
+        self.verbose_errors = enabled;
+        self
+    }
+    
+    /// Sets how transfers with ClickHouse are compressed. LZ4 by default.
+    pub fn with_compression(mut self, compression: TransferCompression) -> Self {
+        self.client = self.client.with_compression(match compression {
+            TransferCompression::None => Compression::None,
+            TransferCompression::Lz4 => Compression::Lz4,
+        });
+        self.compression = compression;
+        self
+    }
+    
+    /// Sets the `User-Agent` header sent with every request, by default
+    /// [`DEFAULT_USER_AGENT`], so the traffic can be told apart in
+    /// ClickHouse's `system.query_log` (`http_user_agent`) and in proxy logs.
+    pub fn with_user_agent(mut self, user_agent: impl Into<String>) -> Self {
+        self.user_agent = user_agent.into();
+        self.client = self.client.with_user_agent(&self.user_agent);
+        self
+    }
+    
+    pub fn writes_enabled(&self) -> bool {
+        self.writes_enabled
+    }
+    
+    /// Caches table schemas for `ttl`, so repeated lookups of the same table
+    /// skip `system.columns`. Off by default, as cached schemas miss
+    /// changes made meanwhile until they expire or
+    /// [`refresh_metadata`](Self::refresh_metadata) is called.
+    pub fn with_schema_cache(mut self, ttl: Duration) -> Self {
+        self.schema_cache = Some(SchemaCache::new(ttl));
+        self
+    }
+    
+    /// The schema cache's counters, `None` if caching is off.
+    pub fn schema_cache_stats(&self) -> Option<CacheStats> {
+        self.schema_cache.as_ref().map(SchemaCache::stats)
+    }
+    
+    /// The last schema fetched for the table under these names, even if it
+    /// has expired, for when ClickHouse can't be asked, e.g. while a quota
+    /// is used up.
+    pub fn cached_table_schema(&self, database: &str, table: &str) -> Option<CachedSchema> {
+        self.schema_cache.as_ref().and_then(|cache| cache.get_stale(database, table))
+    }
+    
+    /// Records query latencies and retries in `metrics`.
+    pub fn with_metrics(mut self, metrics: Arc<Metrics>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+    
+    /// The database the client was constructed with.
+    pub fn default_database(&self) -> &str {
+        &self.default_database
+    }
+    
+    /// The client settings as key/value pairs for logging. Credentials are
+    /// redacted.
+    pub fn effective_config(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("url", redact_url(&self.url)),
+            ("transport", self.client.name().to_string()),
+            ("user_agent", self.user_agent.clone()),
+            ("database", self.default_database.clone()),
+            ("username", self.username.clone()),
+            ("password", if self.password_set { "<set>" } else { "<empty>" }.to_string()),
+            ("bearer_token", if self.bearer_token_set { "<set>" } else { "<empty>" }.to_string()),
+            ("max_retries", self.max_retries.to_string()),
+            ("retry_base_delay", format!("{:?}", self.base_delay)),
+            ("retry_after_max_delay", format!("{:?}", self.max_delay)),
+            ("query_timeout", format!("{:?}", self.query_timeout)),
+            ("case_insensitive_resolution", self.case_insensitive_resolution.to_string()),
+            ("strict_identifiers", self.strict_identifiers.to_string()),
+            ("ascii_identifiers_only", self.ascii_identifiers_only.to_string()),
+            ("max_identifier_length", self.max_identifier_length.to_string()),
+            ("max_raw_rows", self.max_raw_rows.to_string()),
+            ("verbose_errors", self.verbose_errors.to_string()),
+            ("session_timezone", self.session_timezone.clone().unwrap_or_else(|| "<server default>".to_string())),
+            ("compression", self.compression.as_str().to_string()),
+        ]
+    }
+    
+    /// Returns `database` if given, falling back to the configured default.
+    pub fn resolve_database<'a>(&'a self, database: Option<&'a str>) -> &'a str {
+        database.unwrap_or(&self.default_database)
+    }
+    
+    /// Makes `database` the default for queries and for
+    /// [`Self::resolve_database`], after checking that it exists (ignoring
+    /// case if enabled). The driver client is rebuilt from the current one,
+    /// so credentials, compression and all other settings are kept.
+    pub async fn switch_database(&mut self, database: &str) -> Result<(), ClickHouseError> {
+        self.validate_identifier(database)?;
+        let database = self.resolve_database_name(database).await?;
+        
+        info!("Switching default database from '{}' to '{}'", self.default_database, database);
+        self.client = self.client.clone().with_database(&database);
+        self.default_database = database;
+        Ok(())
+    }
+    
+    /// Keeper paths are bound as parameters, but `system.zookeeper` treats
+    /// some characters as patterns, so only plain absolute paths are allowed.
+    fn validate_keeper_path(path: &str) -> Result<(), ClickHouseError> {
+        let reason = if !path.starts_with('/') {
+            Some("must be an absolute path starting with '/'")
+        } else if path.len() > 1024 {
+            Some("must not be longer than 1024 characters")
+        } else if path.len() > 1 && path.ends_with('/') {
+            Some("must not end with '/'")
+        } else if path.contains(['*', '?', '%', '[', ']']) {
+            Some("must not contain wildcards")
+        } else if path.chars().any(|c| c.is_control()) {
+            Some("must not contain control characters")
+        } else if path.len() > 1 && path[1..].split('/').any(|segment| segment.is_empty() || segment == "." || segment == "..") {
+            Some("must not contain empty, '.' or '..' segments")
+        } else {
+            None
+        };
+        
+        match reason {
+            Some(reason) => Err(ClickHouseError::InvalidArgument {
+                message: format!("Keeper path '{}' {}", path, reason),
+            }),
+            None => Ok(()),
+        }
+    }
+    
+    fn validate_identifier(&self, identifier: &str) -> Result<(), ClickHouseError> {
+        if identifier.is_empty() {
+            return Err(ClickHouseError::InvalidIdentifier {
+                identifier: identifier.to_string(),
+                reason: "Identifier cannot be empty".to_string(),
+            });
+        }
+        
+        if identifier.len() > self.max_identifier_length {
+            return Err(ClickHouseError::InvalidIdentifier {
+                identifier: identifier.to_string(),
+                reason: format!(
+                    "Identifier is {} bytes long, the limit is {} bytes",
+                    identifier.len(),
+                    self.max_identifier_length
+                ),
+            });
+        }
+        
+        if self.strict_identifiers {
+            if !identifier.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+                return Err(ClickHouseError::InvalidIdentifier {
+                    identifier: identifier.to_string(),
+                    reason: "Identifier can only contain ASCII letters, digits and underscore".to_string(),
+                });
+            }
+        } else if self.ascii_identifiers_only {
+            if !identifier.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+                return Err(ClickHouseError::InvalidIdentifier {
+                    identifier: identifier.to_string(),
+                    reason: "Identifier can only contain ASCII letters, digits, underscore, and hyphen".to_string(),
+                });
+            }
+        } else if !identifier.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-' || is_identifier_unicode(c)) {
+            return Err(ClickHouseError::InvalidIdentifier {
+                identifier: identifier.to_string(),
+                reason: "Identifier can only contain letters, digits, underscore, hyphen and other non-ASCII characters \
+                         except whitespace, invisible and control characters"
+                    .to_string(),
+            });
+        }
+        
+        if identifier.starts_with(|c: char| c.is_ascii_digit()) {
+            return Err(ClickHouseError::InvalidIdentifier {
+                identifier: identifier.to_string(),
+                reason: "Identifier cannot start with a digit".to_string(),
+            });
+        }
+        
+        Ok(())
+    }
+    
+    /// Starts a query, tagged with the current tool call's query id and
+    /// comment if they are in scope (see [`with_query_id`] and
+    /// [`with_query_comment`]). Every query goes through here.
+    fn query(&self, sql: &str) -> transport::Query {
+        let query = match QUERY_COMMENT.try_with(|comment| (format!("{} /* {} */", sql, comment), comment.clone())) {
+            Ok((sql, comment)) => self.client.query(&sql).with_option("log_comment", comment),
+            Err(_) => self.client.query(sql),
+        };
+        let query_id = QUERY_IDS.try_with(|ids| format!("{}:{}", ids.prefix, ids.issued.fetch_add(1, Ordering::SeqCst) + 1));
+        match query_id {
+            Ok(query_id) => query.with_option("query_id", query_id),
+            Err(_) => query,
+        }
+    }
+    
+    /// Builds a query over user data with the shared guardrail settings
+    /// applied. Date and time values in text results are written as
+    /// ISO-8601 in UTC with a `Z`, so they can't be misread in another time
+    /// zone.
+    fn guarded_query(&self, sql: &str) -> transport::Query {
+        let query = self.query(sql)
+            .with_option("max_execution_time", self.query_timeout.as_secs().max(1).to_string())
+            .with_option("date_time_output_format", "iso");
+        match &self.session_timezone {
+            Some(timezone) => query.with_option("session_timezone", timezone.as_str()),
+            None => query,
+        }
+    }
+    
+    async fn with_retry<F, T, Fut>(&self, operation: F) -> Result<T, ClickHouseError> 
+    where
+        F: Fn() -> Fut,
+        Fut: std::future::Future<Output = Result<T, clickhouse::error::Error>>,
+    {
+        self.with_retry_policy(RetryPolicy::Always, operation).await
+    }
+    
+    /// Runs `operation`, retrying the failures `policy` allows.
+    async fn with_retry_policy<F, T, Fut>(&self, policy: RetryPolicy, operation: F) -> Result<T, ClickHouseError> 
+    where
+        F: Fn() -> Fut,
+        Fut: std::future::Future<Output = Result<T, clickhouse::error::Error>>,
+    {
+        let mut last_error = None;
+        let budget = RETRY_BUDGET.try_with(Arc::clone).ok();
+        if let Some(budget) = &budget {
+            budget.operations.fetch_add(1, Ordering::SeqCst);
+        }
+        
+        let mut delay = Duration::ZERO;
+        for attempt in 0..=self.max_retries {
+            if attempt > 0 {
+                debug!("Retrying ClickHouse operation after {}ms (attempt {})", delay.as_millis(), attempt);
+                sleep(delay).await;
+                if let Some(metrics) = &self.metrics {
+                    metrics.record_retry();
+                }
+            }
+            if let Some(budget) = &budget {
+                budget.attempts.fetch_add(1, Ordering::SeqCst);
+            }
+            
+            let started = Instant::now();
+            let outcome = operation().await;
+            if let Some(metrics) = &self.metrics {
+                metrics.record_query(started.elapsed());
+            }
+            match outcome {
+                Ok(result) => return Ok(result),
+                Err(error) => {
+                    last_error = Some(error);
+                    if attempt == self.max_retries {
+                        break;
+                    }
+                    
+                    // Check if error is retryable
+                    if !self.is_retryable_error(last_error.as_ref().unwrap()) || !policy.allows(last_error.as_ref().unwrap()) {
+                        break;
+                    }
+                    
+                    delay = self.retry_delay(last_error.as_ref().unwrap(), attempt);
+                    // Don't schedule a retry that would start after the budget runs out
+                    if let Some(budget) = &budget {
+                        if Instant::now() + delay >= budget.deadline {
+                            let error = self.convert_clickhouse_error(last_error.take().unwrap());
+                            return Err(ClickHouseError::RetryBudgetExhausted {
+                                attempts: budget.attempts.load(Ordering::SeqCst),
+                                operations: budget.operations.load(Ordering::SeqCst),
+                                last_error: Box::new(error),
+                            });
                         }
                     }
-                } else if error_msg.contains("Access denied") {
-                    ClickHouseError::PermissionDenied {
-                        operation: "query".to_string(),
+                    
+                    warn!("ClickHouse operation failed (attempt {}): {}", attempt + 1, last_error.as_ref().unwrap());
+                }
+            }
+        }
+        
+        // Convert clickhouse error to our error type
+        if let Some(error) = last_error {
+            Err(self.convert_clickhouse_error(error))
+        } else {
+            Err(ClickHouseError::InternalError {
+                message: "Retry loop completed without error".to_string(),
+            })
+        }
+    }
+    
+    /// How long to wait before retry `attempt + 1` after `error`: for a 429
+    /// whose body repeats a `Retry-After`, that, capped by the maximum delay,
+    /// otherwise exponential backoff. A `Retry-After` sent only as a header
+    /// isn't seen.
+    fn retry_delay(&self, error: &clickhouse::error::Error, attempt: u32) -> Duration {
+        let retry_after = match error {
+            clickhouse::error::Error::BadResponse(message) if http_status_from_message(message) == Some(429) => {
+                retry_after_from_message(message)
+            }
+            _ => None,
+        };
+        match retry_after {
+            Some(retry_after) => {
+                debug!("ClickHouse asked to retry after {}ms", retry_after.as_millis());
+                retry_after.min(self.max_delay)
+            }
+            None => self.base_delay * 2_u32.pow(attempt),
+        }
+    }
+    
+    /// Whether `error` is worth retrying. Error responses are only retried
+    /// when they carry a transient HTTP status (see [`is_retryable_status`]);
+    /// ClickHouse exceptions such as auth or syntax errors are not.
+    pub fn is_retryable_error(&self, error: &clickhouse::error::Error) -> bool {
+        match error {
+            clickhouse::error::Error::Network(_) => true,
+            // Retrying can't help until the quota interval ends
+            clickhouse::error::Error::BadResponse(message) if error_hints::error_code(message) == Some(QUOTA_EXCEEDED) => false,
+            clickhouse::error::Error::BadResponse(message) => http_status_from_message(message).is_some_and(is_retryable_status),
+            clickhouse::error::Error::InvalidParams(_) => false, // Don't retry invalid queries
+            _ => true, // Retry other errors (like timeouts)
+        }
+    }
+    
+    /// Maps an error from the ClickHouse driver onto [`ClickHouseError`].
+    pub fn convert_clickhouse_error(&self, error: clickhouse::error::Error) -> ClickHouseError {
+        match error {
+            clickhouse::error::Error::Network(e) => ClickHouseError::NetworkError {
+                message: e.to_string(),
+            },
+            clickhouse::error::Error::InvalidParams(e) => ClickHouseError::QueryFailed {
+                message: e.to_string(),
+            },
+            clickhouse::error::Error::BadResponse(e) => {
+                let error_msg = e.to_string();
+                let raw = self.verbose_errors.then(|| error_msg.clone());
+                if error_hints::error_code(&error_msg) == Some(QUOTA_EXCEEDED) {
+                    ClickHouseError::QuotaExceeded {
+                        resets_at: error_hints::quota_reset_time(&error_msg),
+                        message: error_msg,
+                    }
+                } else if error_msg.contains("Authentication failed") {
+                    ClickHouseError::AuthenticationFailed { message: error_msg }
+                } else if error_msg.contains("Cannot execute query in readonly mode") {
+                    ClickHouseError::PermissionDenied {
+                        operation: "write".to_string(),
+                        raw,
+                    }
+                } else if error_msg.contains("NO_ZOOKEEPER") || error_msg.contains("There is no ZooKeeper") {
+                    ClickHouseError::KeeperNotConfigured { raw }
+                } else if error_msg.contains("doesn't exist") {
+                    if error_msg.contains("Database") {
+                        ClickHouseError::DatabaseNotFound {
+                            database: "unknown".to_string(),
+                            raw,
+                        }
+                    } else {
+                        ClickHouseError::TableNotFound {
+                            database: "unknown".to_string(),
+                            table: "unknown".to_string(),
+                            raw,
+                        }
+                    }
+                } else if error_msg.contains("Access denied")
+                    || error_msg.contains("Not enough privileges")
+                    || error_msg.contains("ACCESS_DENIED")
+                {
+                    ClickHouseError::PermissionDenied {
+                        operation: "query".to_string(),
+                        raw,
+                    }
+                } else {
+                    ClickHouseError::QueryFailed { message: error_msg }
+                }
+            },
+            _ => ClickHouseError::InternalError {
+                message: error.to_string(),
+            },
+        }
+    }
+    
+    /// Runs `query`, which reads system tables, unless an earlier query of
+    /// the same kind was denied. Once denied, `fallback` is used instead for
+    /// the rest of the client's lifetime to avoid a wasted round trip per call.
+    async fn with_system_fallback<T, Q, QF, F, FF>(&self, denied: &AtomicBool, query: Q, fallback: F) -> Result<T, ClickHouseError>
+    where
+        Q: FnOnce() -> QF,
+        QF: Future<Output = Result<T, ClickHouseError>>,
+        F: FnOnce() -> FF,
+        FF: Future<Output = Result<T, ClickHouseError>>,
+    {
+        if !denied.load(Ordering::Relaxed) {
+            match query().await {
+                Err(ClickHouseError::PermissionDenied { .. }) => {
+                    warn!("Reading system tables was denied, falling back to SHOW/DESCRIBE statements");
+                    denied.store(true, Ordering::Relaxed);
+                }
+                result => return result,
+            }
+        }
+        
+        fallback().await
+    }
+    
+    /// `SHOW DATABASES`, for users who may not read `system.databases`.
+    async fn show_databases(&self) -> Result<Vec<String>, ClickHouseError> {
+        self.with_retry(|| async {
+            self.query("SHOW DATABASES").fetch_all().await
+        }).await
+    }
+    
+    /// `SHOW TABLES FROM database`, for users who may not read `system.tables`.
+    async fn show_tables(&self, database: &str) -> Result<Vec<String>, ClickHouseError> {
+        let sql = format!("SHOW TABLES FROM {}", quote_identifier(database));
+        self.with_retry(|| async {
+            self.query(&sql).fetch_all().await
+        }).await
+    }
+    
+    /// Whether `database` exists, matched exactly. Unlike the tools that
+    /// need the database, a missing one is `false` rather than an error.
+    pub async fn database_exists(&self, database: &str) -> Result<bool, ClickHouseError> {
+        self.validate_identifier(database)?;
+        self.lookup_database(database).await
+    }
+    
+    /// Whether `database.table` exists, matched exactly; `false` if the
+    /// database doesn't exist either.
+    pub async fn table_exists(&self, database: &str, table: &str) -> Result<bool, ClickHouseError> {
+        self.validate_identifier(database)?;
+        self.validate_identifier(table)?;
+        self.lookup_table(database, table).await
+    }
+    
+    async fn lookup_database(&self, database: &str) -> Result<bool, ClickHouseError> {
+        let db_exists: u8 = self.with_system_fallback(
+            &self.system_table_fallback.databases,
+            || self.with_retry(|| async {
+                self.query("SELECT count(*) > 0 FROM system.databases WHERE name = ?")
+                    .bind(database)
+                    .fetch_one()
+                    .await
+            }),
+            || async {
+                let sql = format!("EXISTS DATABASE {}", quote_identifier(database));
+                self.with_retry(|| async { self.query(&sql).fetch_one().await }).await
+            },
+        ).await?;
+        Ok(db_exists != 0)
+    }
+    
+    async fn lookup_table(&self, database: &str, table: &str) -> Result<bool, ClickHouseError> {
+        let table_exists: u8 = self.with_system_fallback(
+            &self.system_table_fallback.tables,
+            || self.with_retry(|| async {
+                self.query("SELECT count(*) > 0 FROM system.tables WHERE database = ? AND name = ?")
+                    .bind(database)
+                    .bind(table)
+                    .fetch_one()
+                    .await
+            }),
+            || async {
+                let sql = format!("EXISTS TABLE {}.{}", quote_identifier(database), quote_identifier(table));
+                self.with_retry(|| async { self.query(&sql).fetch_one().await }).await
+            },
+        ).await?;
+        Ok(table_exists != 0)
+    }
+    
+    async fn ensure_database_exists(&self, database: &str) -> Result<(), ClickHouseError> {
+        if !self.lookup_database(database).await? {
+            return Err(ClickHouseError::DatabaseNotFound {
+                database: database.to_string(),
+                raw: None,
+            });
+        }
+        
+        Ok(())
+    }
+    
+    async fn ensure_table_exists(&self, database: &str, table: &str) -> Result<(), ClickHouseError> {
+        if !self.lookup_table(database, table).await? {
+            return Err(ClickHouseError::TableNotFound {
+                database: database.to_string(),
+                table: table.to_string(),
+                raw: None,
+            });
+        }
+        
+        Ok(())
+    }
+    
+    /// Checks that `database` exists and returns its actual name, see
+    /// [`Self::with_case_insensitive_resolution`].
+    async fn resolve_database_name(&self, database: &str) -> Result<String, ClickHouseError> {
+        match self.ensure_database_exists(database).await {
+            Err(not_found @ ClickHouseError::DatabaseNotFound { .. }) if self.case_insensitive_resolution => {
+                let candidates: Vec<String> = self.with_system_fallback(
+                    &self.system_table_fallback.databases,
+                    || self.with_retry(|| async {
+                        self.query("SELECT name FROM system.databases WHERE lower(name) = lower(?) ORDER BY name")
+                            .bind(database)
+                            .fetch_all()
+                            .await
+                    }),
+                    || async { Ok(matching_ignoring_case(self.show_databases().await?, database)) },
+                ).await?;
+                Self::single_candidate("Database", database, candidates, not_found)
+            }
+            result => result.map(|_| database.to_string()),
+        }
+    }
+    
+    /// Checks that `database.table` exists and returns the actual names, see
+    /// [`Self::with_case_insensitive_resolution`].
+    async fn resolve_table_name(&self, database: &str, table: &str) -> Result<(String, String), ClickHouseError> {
+        let given_database = database;
+        let database = self.resolve_database_name(database).await?;
+        
+        match self.ensure_table_exists(&database, table).await {
+            Err(not_found @ ClickHouseError::TableNotFound { .. }) if self.case_insensitive_resolution => {
+                let candidates: Vec<String> = self.with_system_fallback(
+                    &self.system_table_fallback.tables,
+                    || self.with_retry(|| async {
+                        self.query("SELECT name FROM system.tables WHERE database = ? AND lower(name) = lower(?) ORDER BY name")
+                            .bind(database.as_str())
+                            .bind(table)
+                            .fetch_all()
+                            .await
+                    }),
+                    || async { Ok(matching_ignoring_case(self.show_tables(&database).await?, table)) },
+                ).await?;
+                let qualified = format!("{}.{}", database, table);
+                let table = Self::single_candidate("Table", &qualified, candidates, not_found)?;
+                Ok((database, table))
+            }
+            result => result.map(|_| (database, table.to_string())),
+        }
+        .inspect(|actual| self.remember_resolution(given_database, table, actual))
+    }
+    
+    fn remember_resolution(&self, database: &str, table: &str, actual: &(String, String)) {
+        if (database, table) == (actual.0.as_str(), actual.1.as_str()) {
+            return;
+        }
+        let mut resolved = self.resolved_names.lock().unwrap();
+        if resolved.len() >= MAX_RESOLVED_NAMES {
+            resolved.clear();
+        }
+        resolved.insert((database.to_string(), table.to_string()), actual.clone());
+    }
+    
+    /// The names `database.table` resolved to when a call last looked the
+    /// table up, see [`Self::with_case_insensitive_resolution`]; names that
+    /// were never resolved to others are returned as they are.
+    pub fn actual_table_name(&self, database: &str, table: &str) -> (String, String) {
+        self.resolved_names
+            .lock()
+            .unwrap()
+            .get(&(database.to_string(), table.to_string()))
+            .cloned()
+            .unwrap_or_else(|| (database.to_string(), table.to_string()))
+    }
+    
+    fn single_candidate(
+        kind: &str,
+        name: &str,
+        mut candidates: Vec<String>,
+        not_found: ClickHouseError,
+    ) -> Result<String, ClickHouseError> {
+        match candidates.len() {
+            0 => Err(not_found),
+            1 => {
+                let candidate = candidates.remove(0);
+                info!("{} '{}' not found, using case-insensitive match '{}'", kind, name, candidate);
+                Ok(candidate)
+            }
+            _ => Err(ClickHouseError::InvalidArgument {
+                message: format!(
+                    "{} '{}' not found; several names match it ignoring case: {}",
+                    kind,
+                    name,
+                    candidates.join(", ")
+                ),
+            }),
+        }
+    }
+    
+    /// The latest finished or failed queries of the last day from
+    /// `system.query_log`, newest first; with `query_id_prefix`, only those
+    /// whose `query_id` starts with it. `limit` is capped at
+    /// [`MAX_RECENT_QUERIES`].
+    pub async fn recent_queries(&self, limit: u64, query_id_prefix: Option<&str>) -> Result<Vec<RecentQuery>, ClickHouseError> {
+        let limit = limit.clamp(1, MAX_RECENT_QUERIES);
+        info!("Reading the latest {} queries from system.query_log", limit);
+        
+        let filter = if query_id_prefix.is_some() { " AND startsWith(query_id, ?)" } else { "" };
+        let sql = format!(
+            "SELECT query_id, toString(type), {}, query_duration_ms, read_rows, \
+             substring(query, 1, {}), exception FROM system.query_log \
+             WHERE event_date >= yesterday() AND type != 'QueryStart'{} \
+             ORDER BY event_time_microseconds DESC LIMIT {}",
+            utc_timestamp_sql("query_start_time"), RECENT_QUERY_TEXT_LENGTH, filter, limit
+        );
+        self.with_retry(|| async {
+            let query = self.query(&sql);
+            match query_id_prefix {
+                Some(prefix) => query.bind(prefix),
+                None => query,
+            }
+            .fetch_all()
+            .await
+        }).await
+    }
+    
+    /// Asks the server to stop all running queries tagged with `prefix` by
+    /// [`with_query_id`].
+    pub async fn kill_queries(&self, prefix: &str) -> Result<(), ClickHouseError> {
+        info!("Killing queries with id prefix '{}'", prefix);
+        
+        let prefix = format!("{}:", prefix);
+        self.client
+            .query("KILL QUERY WHERE startsWith(query_id, ?) ASYNC")
+            .bind(prefix.as_str())
+            .execute()
+            .await
+            .map_err(|e| self.convert_clickhouse_error(e))
+    }
+    
+    /// The server's version, fetched on first use and cached.
+    pub async fn server_version(&self) -> Result<Version, ClickHouseError> {
+        self.server_version.get_or_try_init(|| async {
+            let version: String = self.with_retry(|| async {
+                self.query("SELECT version()").fetch_one().await
+            }).await?;
+            
+            info!("ClickHouse server version is {}", version);
+            Version::parse(&version).ok_or_else(|| ClickHouseError::InternalError {
+                message: format!("Unrecognized ClickHouse version '{}'", version),
+            })
+        }).await.copied()
+    }
+    
+    /// The server's default time zone, from `SELECT timezone()`. Fetched on
+    /// first use, or by [`Self::server_time`], and cached.
+    pub async fn server_timezone(&self) -> Result<String, ClickHouseError> {
+        self.server_timezone.get_or_try_init(|| async {
+            let timezone: String = self.with_retry(|| async {
+                self.query("SELECT timezone()").fetch_one().await
+            }).await?;
+            
+            info!("ClickHouse time zone is {}", timezone);
+            Ok(timezone)
+        }).await.cloned()
+    }
+    
+    /// The time zone queries over user data run in: the session time zone
+    /// if set, otherwise the server's once [`Self::server_timezone`] has
+    /// been read.
+    pub fn effective_timezone(&self) -> Option<&str> {
+        self.session_timezone.as_ref().or(self.server_timezone.get()).map(String::as_str)
+    }
+    
+    /// Checks which of the system tables and functions that tools need (see
+    /// [`capabilities::TOOL_REQUIREMENTS`]) the server has, with one query
+    /// for each kind, and keeps the outcome for [`Self::capabilities`].
+    pub async fn probe_capabilities(&self) -> Result<Capabilities, ClickHouseError> {
+        // The names are constants of this crate, so listing them inline is safe
+        let in_list = |names: Vec<&str>| names.iter().map(|name| format!("'{}'", name)).collect::<Vec<_>>().join(", ");
+        let tables_sql = format!(
+            "SELECT name FROM system.tables WHERE database = 'system' AND name IN ({})",
+            in_list(capabilities::probed_system_tables())
+        );
+        let functions_sql = format!("SELECT name FROM system.functions WHERE name IN ({})", in_list(capabilities::probed_functions()));
+        
+        let system_tables: Vec<String> = self.with_retry(|| async { self.query(&tables_sql).fetch_all().await }).await?;
+        let functions: Vec<String> = self.with_retry(|| async { self.query(&functions_sql).fetch_all().await }).await?;
+        let capabilities = Capabilities {
+            system_tables: system_tables.into_iter().collect(),
+            functions: functions.into_iter().collect(),
+        };
+        
+        info!(
+            "Server has system tables {:?} and functions {:?} of those probed",
+            capabilities.system_tables, capabilities.functions
+        );
+        *self.capabilities.lock().unwrap() = Some(capabilities.clone());
+        Ok(capabilities)
+    }
+    
+    /// What the last [`Self::probe_capabilities`] found, if one succeeded.
+    pub fn capabilities(&self) -> Option<Capabilities> {
+        self.capabilities.lock().unwrap().clone()
+    }
+    
+    /// Reads the server's current time and time zone. ClickHouse has no
+    /// ISO-8601 output with an offset on all versions, so the offset is
+    /// worked out from the local time and the Unix timestamp.
+    pub async fn server_time(&self) -> Result<ServerTime, ClickHouseError> {
+        info!("Reading server time");
+        
+        let (local, timestamp, timezone): (String, u32, String) = self.with_retry(|| async {
+            self.query("SELECT toString(now()), toUnixTimestamp(now()), timezone()").fetch_one().await
+        }).await?;
+        
+        let unrecognized = || ClickHouseError::InternalError {
+            message: format!("Unrecognized server time '{}'", local),
+        };
+        let local_time = chrono::NaiveDateTime::parse_from_str(&local, "%Y-%m-%d %H:%M:%S").map_err(|_| unrecognized())?;
+        let utc = chrono::DateTime::from_timestamp(i64::from(timestamp), 0).ok_or_else(unrecognized)?;
+        let offset = chrono::FixedOffset::east_opt((local_time - utc.naive_utc()).num_seconds() as i32).ok_or_else(unrecognized)?;
+        let now = utc.with_timezone(&offset).to_rfc3339_opts(chrono::SecondsFormat::Secs, false);
+        
+        debug!("Server time is {} ({})", now, timezone);
+        let _ = self.server_timezone.set(timezone.clone());
+        Ok(ServerTime { now, timezone })
+    }
+    
+    /// Fails with [`ClickHouseError::UnsupportedVersion`] if the server is
+    /// too old for `feature`.
+    pub async fn require_feature(&self, feature: Feature) -> Result<(), ClickHouseError> {
+        let version = self.server_version().await?;
+        if !feature.is_supported_by(version) {
+            return Err(ClickHouseError::UnsupportedVersion {
+                feature,
+                required: feature.min_version(),
+                actual: version,
+            });
+        }
+        
+        Ok(())
+    }
+    
+    pub async fn health_check(&self) -> Result<(), ClickHouseError> {
+        info!("Performing ClickHouse health check");
+        
+        self.with_retry(|| async {
+            self.query("SELECT 1")
+                .fetch_one::<u8>()
+                .await
+        }).await?;
+        // Every guarded query sends the setting, which older servers reject
+        if self.session_timezone.is_some() {
+            self.require_feature(Feature::SessionTimezone).await?;
+        }
+        
+        info!("ClickHouse health check passed");
+        Ok(())
+    }
+
+    /// Whether the user may read `system.<table>`, without falling back to
+    /// other statements the way the listing methods do.
+    pub async fn can_read_system_table(&self, table: &str) -> Result<bool, ClickHouseError> {
+        self.validate_identifier(table)?;
+        
+        let sql = format!("SELECT 1 FROM system.{} LIMIT 1", quote_identifier(table));
+        match self.with_retry(|| async { self.query(&sql).fetch_all::<u8>().await }).await {
+            Ok(_) => Ok(true),
+            Err(ClickHouseError::PermissionDenied { .. }) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub async fn list_databases(&self) -> Result<Vec<DatabaseInfo>, ClickHouseError> {
+        info!("Listing databases");
+        
+        let databases = self.with_system_fallback(
+            &self.system_table_fallback.databases,
+            || self.with_retry_policy(RetryPolicy::Always, || async {
+                self.query("SELECT name FROM system.databases ORDER BY name")
+                    .fetch_all()
+                    .await
+            }),
+            || async {
+                let names = self.show_databases().await?;
+                Ok(names.into_iter().map(|name| DatabaseInfo { name }).collect())
+            },
+        ).await?;
+        
+        debug!("Found {} databases", databases.len());
+        Ok(databases)
+    }
+
+    /// The data formats the server supports, by name.
+    pub async fn list_formats(&self) -> Result<Vec<FormatInfo>, ClickHouseError> {
+        info!("Listing formats");
+        
+        let formats: Vec<FormatInfo> = self.with_retry(|| async {
+            self.query("SELECT name, is_input, is_output FROM system.formats ORDER BY name")
+                .fetch_all()
+                .await
+        }).await?;
+        
+        debug!("Found {} formats", formats.len());
+        Ok(formats)
+    }
+
+    /// The grants of the connected user, one `GRANT` statement each, as
+    /// `SHOW GRANTS` prints them. Any user may see their own grants.
+    pub async fn current_grants(&self) -> Result<Vec<String>, ClickHouseError> {
+        info!("Listing the current user's grants");
+        
+        let grants: Vec<String> = self.with_retry(|| async {
+            self.query("SHOW GRANTS").fetch_all().await
+        }).await?;
+        
+        debug!("Found {} grants", grants.len());
+        Ok(grants)
+    }
+
+    pub async fn list_tables(&self, database: &str) -> Result<Vec<TableInfo>, ClickHouseError> {
+        self.validate_identifier(database)?;
+        info!("Listing tables in database '{}'", database);
+        
+        let database = self.resolve_database_name(database).await?;
+        let database = database.as_str();
+        
+        let tables = self.with_system_fallback(
+            &self.system_table_fallback.tables,
+            || self.with_retry(|| async {
+                self.query("SELECT name, database, engine FROM system.tables WHERE database = ? ORDER BY name")
+                    .bind(database)
+                    .fetch_all()
+                    .await
+            }),
+            // SHOW TABLES does not report engines, so those are left empty
+            || async {
+                let names = self.show_tables(database).await?;
+                Ok(names
+                    .into_iter()
+                    .map(|name| TableInfo { name, database: database.to_string(), engine: String::new() })
+                    .collect())
+            },
+        ).await.map_err(|e| {
+            if let ClickHouseError::QueryFailed { message } = &e {
+                if message.contains("doesn't exist") {
+                    return ClickHouseError::DatabaseNotFound {
+                        database: database.to_string(),
+                        raw: self.verbose_errors.then(|| message.clone()),
+                    };
+                }
+            }
+            e
+        })?;
+        
+        debug!("Found {} tables in database '{}'", tables.len(), database);
+        Ok(tables)
+    }
+
+    /// Lists the tables of every database, ordered by database and name, up
+    /// to [`MAX_ALL_TABLES`] of them. The [`SYSTEM_DATABASES`] are left out
+    /// unless `include_system` is set.
+    pub async fn list_all_tables(&self, include_system: bool) -> Result<TableListing, ClickHouseError> {
+        info!("Listing tables in all databases (include_system: {})", include_system);
+        
+        let filter = if include_system { "" } else { "WHERE NOT has(?, database) " };
+        let sql = format!(
+            "SELECT name, database, engine FROM system.tables {}ORDER BY database, name LIMIT {}",
+            filter,
+            // One more than is returned tells whether there are more
+            MAX_ALL_TABLES + 1
+        );
+        let mut tables: Vec<TableInfo> = self.with_retry(|| async {
+            let query = self.guarded_query(&sql);
+            let query = if include_system { query } else { query.bind(SYSTEM_DATABASES) };
+            query.fetch_all().await
+        }).await?;
+        
+        let truncated = tables.len() > MAX_ALL_TABLES;
+        if truncated {
+            tables.truncate(MAX_ALL_TABLES);
+            warn!("Table listing reached the limit of {} tables", MAX_ALL_TABLES);
+        }
+        debug!("Found {} tables across all databases", tables.len());
+        Ok(TableListing { tables, truncated })
+    }
+    
+    /// The database, name, engine and metadata modification time of every
+    /// table outside `hidden_databases`.
+    async fn catalog_tables(&self, hidden_databases: &[&str]) -> Result<Vec<(String, String, String, u32)>, ClickHouseError> {
+        self.with_retry(|| async {
+            self.query(
+                "SELECT database, name, engine, toUnixTimestamp(metadata_modification_time) FROM system.tables \
+                 WHERE NOT has(?, database) AND NOT is_temporary ORDER BY database, name",
+            )
+            .bind(hidden_databases)
+            .fetch_all()
+            .await
+        }).await
+    }
+    
+    /// The version token of the current catalog outside `hidden_databases`,
+    /// see [`catalog`].
+    pub async fn catalog_version(&self, hidden_databases: &[&str]) -> Result<CatalogVersion, ClickHouseError> {
+        info!("Computing the catalog version");
+        
+        let entries: Vec<CatalogEntry> = self
+            .catalog_tables(hidden_databases)
+            .await?
+            .into_iter()
+            .map(|(database, table, _, modified)| CatalogEntry { database, table, modified })
+            .collect();
+        Ok(CatalogVersion { version: encode_catalog_version(&entries), tables: entries.len() })
+    }
+    
+    /// Exports the tables outside `hidden_databases` with their columns.
+    /// With `since_version`, a token from [`Self::catalog_version`] or an
+    /// earlier export, only tables created or altered since are exported,
+    /// plus the ones dropped.
+    pub async fn export_catalog(&self, since_version: Option<&str>, hidden_databases: &[&str]) -> Result<CatalogExport, ClickHouseError> {
+        let previous = since_version.map(decode_catalog_version).transpose()?;
+        info!("Exporting the catalog{}", if previous.is_some() { " changes" } else { "" });
+        
+        let tables = self.catalog_tables(hidden_databases).await?;
+        let entries: Vec<CatalogEntry> = tables
+            .iter()
+            .map(|(database, table, _, modified)| CatalogEntry { database: database.clone(), table: table.clone(), modified: *modified })
+            .collect();
+        let version = encode_catalog_version(&entries);
+        let (changed, deleted) = match &previous {
+            Some(previous) => {
+                let diff = diff_catalog(previous, &entries);
+                let changed: BTreeSet<(String, String)> = diff.changed.into_iter().map(|entry| (entry.database, entry.table)).collect();
+                (Some(changed), diff.deleted)
+            }
+            None => (None, Vec::new()),
+        };
+        let included: Vec<(String, String, String, u32)> = tables
+            .into_iter()
+            .filter(|(database, table, _, _)| {
+                changed.as_ref().is_none_or(|changed| changed.contains(&(database.clone(), table.clone())))
+            })
+            .collect();
+        
+        let databases: Vec<&str> = included.iter().map(|(database, ..)| database.as_str()).collect::<BTreeSet<_>>().into_iter().collect();
+        let mut columns: BTreeMap<(String, String), Vec<ExportedColumn>> = BTreeMap::new();
+        if !databases.is_empty() {
+            let rows: Vec<(String, String, String, String)> = self.with_retry(|| async {
+                self.query("SELECT database, table, name, type FROM system.columns WHERE has(?, database) ORDER BY database, table, position")
+                    .bind(&databases)
+                    .fetch_all()
+                    .await
+            }).await?;
+            for (database, table, name, r#type) in rows {
+                columns.entry((database, table)).or_default().push(ExportedColumn { name, r#type });
+            }
+        }
+        
+        let tables: Vec<ExportedTable> = included
+            .into_iter()
+            .map(|(database, name, engine, modified)| {
+                let columns = columns.remove(&(database.clone(), name.clone())).unwrap_or_default();
+                ExportedTable { database, name, engine, modified, columns }
+            })
+            .collect();
+        debug!("Exported {} tables, {} deleted", tables.len(), deleted.len());
+        Ok(CatalogExport { version, since_version: since_version.map(str::to_string), tables, deleted })
+    }
+    
+    pub async fn get_table_schema(&self, database: &str, table: &str) -> Result<Vec<ColumnInfo>, ClickHouseError> {
+        let (_, _, columns) = self.table_schema(database, table).await?;
+        Ok(columns)
+    }
+    
+    /// Like [`Self::get_table_schema`], but also returns the resolved
+    /// database and table names for follow-up queries.
+    async fn table_schema(&self, database: &str, table: &str) -> Result<(String, String, Vec<ColumnInfo>), ClickHouseError> {
+        self.validate_identifier(database)?;
+        self.validate_identifier(table)?;
+        if let Some(cached) = self.schema_cache.as_ref().and_then(|cache| cache.get(database, table)) {
+            debug!("Using cached schema for table '{}.{}'", cached.database, cached.table);
+            return Ok((cached.database, cached.table, self.with_timezones(cached.columns)));
+        }
+        info!("Getting schema for table '{}.{}'", database, table);
+        
+        let requested = (database, table);
+        let (database, table) = self.resolve_table_name(database, table).await?;
+        let (database, table) = (database.as_str(), table.as_str());
+        
+        let columns = self.with_system_fallback(
+            &self.system_table_fallback.columns,
+            || self.with_retry(|| async {
+                self.query("SELECT name, type, default_kind as default_type, default_expression, comment, is_in_partition_key, is_in_sorting_key, is_in_primary_key, is_in_sampling_key FROM system.columns WHERE database = ? AND table = ? ORDER BY position")
+                    .bind(database)
+                    .bind(table)
+                    .fetch_all::<ColumnInfo>()
+                    .await
+                    .map(|columns| columns.into_iter().map(ColumnInfo::with_enum_values).collect::<Vec<_>>())
+            }),
+            || async {
+                let sql = format!("DESCRIBE TABLE {}.{}", quote_identifier(database), quote_identifier(table));
+                let rows: Vec<DescribeRow> = self.with_retry(|| async { self.query(&sql).fetch_all().await }).await?;
+                Ok(rows.into_iter().map(ColumnInfo::from).collect())
+            },
+        ).await.map_err(|e| {
+            if let ClickHouseError::QueryFailed { message } = &e {
+                if message.contains("doesn't exist") {
+                    return ClickHouseError::TableNotFound {
+                        database: database.to_string(),
+                        table: table.to_string(),
+                        raw: self.verbose_errors.then(|| message.clone()),
+                    };
+                }
+            }
+            e
+        })?;
+        
+        if columns.is_empty() {
+            return Err(ClickHouseError::TableNotFound {
+                database: database.to_string(),
+                table: table.to_string(),
+                raw: None,
+            });
+        }
+        
+        debug!("Found {} columns in table '{}.{}'", columns.len(), database, table);
+        if let Some(cache) = &self.schema_cache {
+            cache.insert(requested, CachedSchema::new(database, table, columns.clone()));
+        }
+        Ok((database.to_string(), table.to_string(), self.with_timezones(columns)))
+    }
+    
+    /// Fills in the time zone of date and time columns, see
+    /// [`ColumnInfo::with_timezone`]. Done on every lookup rather than when
+    /// caching, as the server's time zone may only be read later.
+    fn with_timezones(&self, columns: Vec<ColumnInfo>) -> Vec<ColumnInfo> {
+        let timezone = self.effective_timezone();
+        columns.into_iter().map(|column| column.with_timezone(timezone)).collect()
+    }
+    
+    /// Drops the cached schemas in `scope` and fetches them again right
+    /// away. A table scope is fetched even if it wasn't cached. Without a
+    /// schema cache there is nothing to refresh.
+    pub async fn refresh_metadata(&self, scope: CacheScope) -> Result<Vec<RefreshedSchema>, ClickHouseError> {
+        match &scope {
+            CacheScope::All => {}
+            CacheScope::Database(database) => self.validate_identifier(database)?,
+            CacheScope::Table { database, table } => {
+                self.validate_identifier(database)?;
+                self.validate_identifier(table)?;
+            }
+        }
+        let Some(cache) = &self.schema_cache else {
+            return Ok(Vec::new());
+        };
+        
+        let mut targets: Vec<(String, String, bool)> = cache
+            .invalidate(&scope)
+            .into_iter()
+            .map(|(database, table)| (database, table, true))
+            .collect();
+        if let CacheScope::Table { database, table } = &scope {
+            if targets.is_empty() {
+                targets.push((database.clone(), table.clone(), false));
+            }
+        }
+        info!("Refreshing {} cached schemas", targets.len());
+        
+        let mut refreshed = Vec::with_capacity(targets.len());
+        for (database, table, was_cached) in targets {
+            let outcome = self
+                .table_schema(&database, &table)
+                .await
+                .map(|(database, table, columns)| CachedSchema::new(&database, &table, columns));
+            refreshed.push(RefreshedSchema { database, table, was_cached, outcome });
+        }
+        Ok(refreshed)
+    }
+
+    /// Fetches the columns of several tables with a single query. Results
+    /// are keyed by `database.table`; tables that don't exist or have invalid
+    /// names get an error entry instead of failing the whole call. Names are
+    /// matched exactly.
+    pub async fn get_table_schemas(
+        &self,
+        tables: &[(String, String)],
+    ) -> Result<BTreeMap<String, Result<Vec<ColumnInfo>, ClickHouseError>>, ClickHouseError> {
+        if tables.is_empty() || tables.len() > MAX_TABLE_SCHEMAS {
+            return Err(ClickHouseError::InvalidArgument {
+                message: format!("Between 1 and {} tables can be fetched at once, got {}", MAX_TABLE_SCHEMAS, tables.len()),
+            });
+        }
+        info!("Getting schemas for {} tables", tables.len());
+        
+        let mut schemas: BTreeMap<String, Result<Vec<ColumnInfo>, ClickHouseError>> = BTreeMap::new();
+        let mut valid: Vec<(&str, &str)> = Vec::new();
+        for (database, table) in tables {
+            let key = format!("{}.{}", database, table);
+            match self.validate_identifier(database).and_then(|_| self.validate_identifier(table)) {
+                Ok(()) => {
+                    if !valid.contains(&(database.as_str(), table.as_str())) {
+                        valid.push((database, table));
+                    }
+                    schemas.insert(key, Err(ClickHouseError::TableNotFound {
+                        database: database.clone(),
+                        table: table.clone(),
+                        raw: None,
+                    }));
+                }
+                Err(e) => {
+                    schemas.insert(key, Err(e));
+                }
+            }
+        }
+        
+        if !valid.is_empty() {
+            let sql = build_table_schemas_query(valid.len());
+            let columns: Vec<TableColumnInfo> = self.with_retry(|| async {
+                valid.iter().fold(self.query(&sql), |query, pair| query.bind(pair)).fetch_all().await
+            }).await?;
+            
+            let timezone = self.effective_timezone();
+            for column in columns {
+                let Some(entry) = schemas.get_mut(&column.qualified_name) else {
+                    continue;
+                };
+                let column = ColumnInfo::from(column).with_timezone(timezone);
+                match entry {
+                    Ok(columns) => columns.push(column),
+                    entry => *entry = Ok(vec![column]),
+                }
+            }
+        }
+        
+        debug!("Found {} of {} tables", schemas.values().filter(|s| s.is_ok()).count(), schemas.len());
+        Ok(schemas)
+    }
+
+    pub async fn describe_table(&self, database: &str, table: &str) -> Result<TableDocumentation, ClickHouseError> {
+        let (database, table, columns) = self.table_schema(database, table).await?;
+        let metadata = self.table_metadata(&database, &table).await?;
+        
+        Ok(TableDocumentation { table: metadata, columns })
+    }
+    
+    /// Reads the engine and keys of a table whose name is already resolved.
+    async fn table_metadata(&self, database: &str, table: &str) -> Result<TableMetadata, ClickHouseError> {
+        self.with_retry(|| async {
+            self.query("SELECT name, engine, comment, sorting_key, partition_key, primary_key FROM system.tables WHERE database = ? AND name = ?")
+                .bind(database)
+                .bind(table)
+                .fetch_one()
+                .await
+        }).await
+    }
+    
+    /// Suggests an `ORDER BY` and `PARTITION BY` for a MergeTree-family
+    /// table from the cardinality of its columns, measured with `uniq()` in
+    /// one query over up to [`MAX_ORDERING_COLUMNS`] key candidates. See
+    /// [`advise_ordering`] for the rules.
+    ///
+    /// For a Distributed table the advice is for its local table, with the
+    /// cardinalities measured through the Distributed table, so across the
+    /// whole cluster.
+    pub async fn recommend_ordering(&self, database: &str, table: &str) -> Result<OrderingAdvice, ClickHouseError> {
+        info!("Recommending ordering for table '{}.{}'", database, table);
+        
+        let (database, table, columns) = self.table_schema(database, table).await?;
+        let (database, table) = (database.as_str(), table.as_str());
+        let mut metadata = self.table_metadata(database, table).await?;
+        let mut distributed = None;
+        if metadata.engine == "Distributed" {
+            if let Some(target) = self.distributed_target(database, table).await? {
+                metadata = self.table_metadata(&target.database, &target.table).await?;
+                distributed = Some(target);
+            }
+        }
+        if !metadata.engine.ends_with("MergeTree") {
+            return Err(ClickHouseError::InvalidArgument {
+                message: format!(
+                    "table '{}.{}' uses the {} engine; ordering advice only applies to MergeTree-family tables",
+                    database, table, metadata.engine
+                ),
+            });
+        }
+        
+        let measured: Vec<&str> = columns
+            .iter()
+            .filter(|c| ordering::is_key_candidate(&c.r#type))
+            .take(MAX_ORDERING_COLUMNS)
+            .map(|c| c.name.as_str())
+            .collect();
+        let sql = build_ordering_stats_query(database, table, &measured);
+        let stats: OrderingStats = self.with_retry(|| async {
+            self.guarded_query(&sql).fetch_one().await
+        }).await?;
+        
+        let cardinalities = columns
+            .iter()
+            .map(|c| ColumnCardinality {
+                name: c.name.clone(),
+                r#type: c.r#type.clone(),
+                distinct: measured
+                    .iter()
+                    .position(|name| *name == c.name)
+                    .and_then(|i| stats.distinct.get(i).copied()),
+            })
+            .collect();
+        debug!("Measured {} columns of '{}.{}' over {} rows", measured.len(), database, table, stats.rows);
+        let mut advice = advise_ordering(&metadata.engine, &metadata.sorting_key, &metadata.partition_key, stats.rows, cardinalities);
+        if let Some(target) = distributed {
+            advice.rationale.insert(
+                0,
+                format!(
+                    "{}.{} is Distributed, so this is for its local table {}.{}; cardinalities are cluster-wide across cluster '{}'",
+                    database, table, target.database, target.table, target.cluster
+                ),
+            );
+        }
+        Ok(advice)
+    }
+
+    pub async fn describe_database(&self, database: &str) -> Result<DatabaseDocumentation, ClickHouseError> {
+        self.validate_identifier(database)?;
+        info!("Describing database '{}'", database);
+        
+        let database = self.resolve_database_name(database).await?;
+        let database = database.as_str();
+        
+        let comment: String = self.with_retry(|| async {
+            self.query("SELECT comment FROM system.databases WHERE name = ?")
+                .bind(database)
+                .fetch_one()
+                .await
+        }).await?;
+        
+        let tables: Vec<TableMetadata> = self.with_retry(|| async {
+            self.query("SELECT name, engine, comment, sorting_key, partition_key, primary_key FROM system.tables WHERE database = ? ORDER BY name")
+                .bind(database)
+                .fetch_all()
+                .await
+        }).await?;
+        
+        // Read all columns of the database at once rather than one query per table
+        let columns: Vec<TableColumnInfo> = self.with_retry(|| async {
+            self.query("SELECT concat(database, '.', table) AS qualified_name, name, type, default_kind as default_type, default_expression, comment, is_in_partition_key, is_in_sorting_key, is_in_primary_key, is_in_sampling_key FROM system.columns WHERE database = ? ORDER BY table, position")
+                .bind(database)
+                .fetch_all()
+                .await
+        }).await?;
+        
+        let mut tables: Vec<TableDocumentation> = tables
+            .into_iter()
+            .map(|table| TableDocumentation { table, columns: Vec::new() })
+            .collect();
+        let timezone = self.effective_timezone();
+        for column in columns {
+            let table = column.qualified_name.strip_prefix(database).and_then(|name| name.strip_prefix('.'));
+            if let Some(doc) = tables.iter_mut().find(|doc| Some(doc.table.name.as_str()) == table) {
+                doc.columns.push(ColumnInfo::from(column).with_timezone(timezone));
+            }
+        }
+        
+        debug!("Described {} tables in database '{}'", tables.len(), database);
+        Ok(DatabaseDocumentation {
+            name: database.to_string(),
+            comment,
+            tables,
+        })
+    }
+
+    pub async fn list_projections(&self, database: &str, table: &str) -> Result<Vec<ProjectionInfo>, ClickHouseError> {
+        self.validate_identifier(database)?;
+        self.validate_identifier(table)?;
+        info!("Listing projections for table '{}.{}'", database, table);
+        
+        let (database, table) = self.resolve_table_name(database, table).await?;
+        let (database, table) = (database.as_str(), table.as_str());
+        
+        // Projections are part of the table definition, so read them from the
+        // CREATE statement rather than system.projection_parts, which only
+        // knows about projections that have materialized parts.
+        let create_query: String = self.with_retry(|| async {
+            self.query("SELECT create_table_query FROM system.tables WHERE database = ? AND name = ?")
+                .bind(database)
+                .bind(table)
+                .fetch_one()
+                .await
+        }).await?;
+        
+        let projections = parse_projections(&create_query);
+        
+        debug!("Found {} projections in table '{}.{}'", projections.len(), database, table);
+        Ok(projections)
+    }
+
+    pub async fn table_dependencies(&self, database: &str, table: &str) -> Result<Dependencies, ClickHouseError> {
+        self.validate_identifier(database)?;
+        self.validate_identifier(table)?;
+        info!("Listing dependencies of table '{}.{}'", database, table);
+        
+        let (database, table) = self.resolve_table_name(database, table).await?;
+        let (database, table) = (database.as_str(), table.as_str());
+        
+        // system.tables records dependencies on the source side: the row of a
+        // table lists the views that read from it.
+        let mut dependents: Vec<String> = self.with_retry(|| async {
+            self.query("SELECT arrayMap((d, t) -> concat(d, '.', t), dependencies_database, dependencies_table) FROM system.tables WHERE database = ? AND name = ?")
+                .bind(database)
+                .bind(table)
+                .fetch_one()
+                .await
+        }).await?;
+        dependents.sort();
+        
+        let depends_on: Vec<String> = self.with_retry(|| async {
+            self.query("SELECT concat(database, '.', name) FROM system.tables WHERE arrayExists((d, t) -> d = ? AND t = ?, dependencies_database, dependencies_table) ORDER BY database, name")
+                .bind(database)
+                .bind(table)
+                .fetch_all()
+                .await
+        }).await?;
+        
+        debug!(
+            "Table '{}.{}' depends on {} tables and has {} dependents",
+            database, table, depends_on.len(), dependents.len()
+        );
+        Ok(Dependencies { depends_on, dependents })
+    }
+
+    /// Traces each column of `database.table` back through the materialized
+    /// views writing into it with `TO`, or through the view itself if
+    /// `table` is a materialized view storing its own data. Views whose
+    /// definition has no SELECT to parse are skipped.
+    pub async fn column_lineage(&self, database: &str, table: &str) -> Result<Vec<ColumnLineage>, ClickHouseError> {
+        let (database, table, columns) = self.table_schema(database, table).await?;
+        info!("Tracing column lineage of table '{}.{}'", database, table);
+        
+        // The TO table is only in the CREATE statement, so every view is read
+        let definitions: Vec<(String, String, String)> = self.with_retry(|| async {
+            self.query("SELECT database, name, create_table_query FROM system.tables WHERE engine = 'MaterializedView' ORDER BY database, name")
+                .fetch_all()
+                .await
+        }).await?;
+        
+        let target = format!("{}.{}", database, table);
+        let views: Vec<MaterializedView> = definitions
+            .iter()
+            .filter_map(|(database, name, create_query)| parse_materialized_view(database, name, create_query))
+            .filter(|view| match &view.target {
+                Some(view_target) => *view_target == target,
+                None => view.name == target,
+            })
+            .collect();
+        
+        debug!("Table '{}' is written by {} materialized views", target, views.len());
+        let names: Vec<String> = columns.into_iter().map(|column| column.name).collect();
+        Ok(column_lineage(&names, &views))
+    }
+
+    pub async fn column_histogram(
+        &self,
+        database: &str,
+        table: &str,
+        column: &str,
+        buckets: u32,
+        sample_ratio: Option<f64>,
+    ) -> Result<ColumnHistogram, ClickHouseError> {
+        self.validate_identifier(column)?;
+        if !(1..=1000).contains(&buckets) {
+            return Err(ClickHouseError::InvalidArgument {
+                message: format!("buckets must be between 1 and 1000, got {}", buckets),
+            });
+        }
+        if let Some(ratio) = sample_ratio {
+            if !(ratio > 0.0 && ratio <= 1.0) {
+                return Err(ClickHouseError::InvalidArgument {
+                    message: format!("sample_ratio must be in (0, 1], got {}", ratio),
+                });
+            }
+        }
+        info!("Computing histogram of '{}' in table '{}.{}'", column, database, table);
+        
+        let (database, table, columns) = self.table_schema(database, table).await?;
+        let (database, table) = (database.as_str(), table.as_str());
+        
+        let column_info = find_column(&columns, database, table, column)?;
+        
+        if !is_numeric_type(&column_info.r#type) {
+            let candidates: Vec<&str> = columns
+                .iter()
+                .filter(|c| is_numeric_type(&c.r#type))
+                .map(|c| c.name.as_str())
+                .collect();
+            return Err(ClickHouseError::InvalidArgument {
+                message: format!(
+                    "column '{}' has non-numeric type {}; numeric columns are: {}",
+                    column,
+                    column_info.r#type,
+                    if candidates.is_empty() { "(none)".to_string() } else { candidates.join(", ") }
+                ),
+            });
+        }
+        
+        if sample_ratio.is_some() && !columns.iter().any(|c| c.is_in_sampling_key == 1) {
+            return Err(ClickHouseError::InvalidArgument {
+                message: format!("table '{}.{}' has no sampling key; omit sample_ratio", database, table),
+            });
+        }
+        
+        let sql = build_histogram_query(database, table, column, buckets, sample_ratio);
+        let raw: Vec<(f64, f64, f64)> = self.with_retry(|| async {
+            self.guarded_query(&sql).fetch_one().await
+        }).await?;
+        
+        debug!("Histogram of '{}.{}.{}' has {} buckets", database, table, column, raw.len());
+        Ok(ColumnHistogram {
+            column: column.to_string(),
+            column_type: column_info.r#type.clone(),
+            buckets: raw
+                .into_iter()
+                .map(|(lower, upper, height)| HistogramBucket { lower, upper, height })
+                .collect(),
+            sample_ratio,
+        })
+    }
+
+    /// Returns the first `limit` rows of a table rendered in `format`, with
+    /// the columns `SELECT *` would return.
+    ///
+    /// Rows are formatted server-side with `formatRow`, so CSV quoting and
+    /// escaping follow ClickHouse's own `CSV` output format.
+    pub async fn sample_rows(
+        &self,
+        database: &str,
+        table: &str,
+        limit: u64,
+        format: ResponseFormat,
+    ) -> Result<SampledRows, ClickHouseError> {
+        if !(1..=1000).contains(&limit) {
+            return Err(ClickHouseError::InvalidArgument {
+                message: format!("limit must be between 1 and 1000, got {}", limit),
+            });
+        }
+        info!("Sampling {} rows from table '{}.{}' as {:?}", limit, database, table, format);
+        
+        let (database, table, columns) = self.table_schema(database, table).await?;
+        let (database, table) = (database.as_str(), table.as_str());
+        // The header and the values come from the same list of columns
+        let columns: Vec<&str> = columns.iter().filter(|c| c.is_ordinary()).map(|c| c.name.as_str()).collect();
+        let sql = build_sample_rows_query(database, table, &columns, limit, format);
+        
+        match format {
+            ResponseFormat::Json => {
+                let lines: Vec<String> = self.with_retry(|| async {
+                    self.guarded_query(&sql).fetch_all().await
+                }).await?;
+                
+                let rows = parse_json_rows(&lines)?;
+                
+                debug!("Sampled {} rows from '{}.{}'", rows.len(), database, table);
+                let text = serde_json::to_string_pretty(&rows).map_err(|e| ClickHouseError::InternalError {
+                    message: e.to_string(),
+                })?;
+                Ok(SampledRows { text, row_count: rows.len() })
+            }
+            ResponseFormat::Csv => {
+                // Let ClickHouse quote the header too by formatting the column
+                // names as a CSV row of bound string values.
+                let placeholders = vec!["?"; columns.len()].join(", ");
+                let header_sql = format!("SELECT formatRowNoNewline('CSV', {})", placeholders);
+                let header: String = self.with_retry(|| async {
+                    columns
+                        .iter()
+                        .fold(self.query(&header_sql), |query, column| query.bind(*column))
+                        .fetch_one()
+                        .await
+                }).await?;
+                
+                let lines: Vec<String> = self.with_retry(|| async {
+                    self.guarded_query(&sql).fetch_all().await
+                }).await?;
+                
+                debug!("Sampled {} rows from '{}.{}'", lines.len(), database, table);
+                let mut csv = header;
+                csv.push('\n');
+                for line in &lines {
+                    csv.push_str(line);
+                }
+                Ok(SampledRows { text: csv, row_count: lines.len() })
+            }
+        }
+    }
+
+    /// Lists the child nodes of a Keeper/ZooKeeper path.
+    pub async fn keeper_children(&self, path: &str) -> Result<Vec<KeeperNode>, ClickHouseError> {
+        Self::validate_keeper_path(path)?;
+        info!("Listing Keeper nodes under '{}'", path);
+        
+        let nodes: Vec<KeeperNode> = self.with_retry(|| async {
+            self.query(&format!(
+                "SELECT name, value, numChildren, dataLength, {} FROM system.zookeeper WHERE path = ? ORDER BY name",
+                utc_timestamp_sql("mtime")
+            ))
+                .bind(path)
+                .fetch_all()
+                .await
+        }).await?;
+        
+        debug!("Found {} Keeper nodes under '{}'", nodes.len(), path);
+        Ok(nodes)
+    }
+    
+    /// Reads a curated set of memory, query and CPU metrics.
+    pub async fn server_metrics(&self) -> Result<ServerMetrics, ClickHouseError> {
+        info!("Reading server metrics");
+        
+        let values: Vec<MetricValue> = self.with_retry(|| async {
+            self.query(
+                "SELECT metric, toFloat64(value) AS value FROM system.metrics WHERE metric IN ('MemoryTracking', 'Query') \
+                 UNION ALL \
+                 SELECT metric, value FROM system.asynchronous_metrics WHERE metric IN ('OSMemoryTotal', 'LoadAverage1', 'LoadAverage5', 'LoadAverage15', 'OSUserTimeNormalized', 'OSSystemTimeNormalized', 'Uptime')",
+            )
+            .fetch_all()
+            .await
+        }).await?;
+        
+        debug!("Read {} metrics", values.len());
+        Ok(ServerMetrics::from_values(&values))
+    }
+
+    /// Summarizes insert pressure for one table, one database (`table` is
+    /// `None`) or the whole server (both `None`): part counts against the
+    /// merge tree insert thresholds, the delayed/rejected insert counters and
+    /// merge pool usage. At most [`MAX_PRESSURE_TABLES`] tables are reported.
+    ///
+    /// A Distributed table has no parts, so its local table's parts are read
+    /// on every replica of the cluster with `clusterAllReplicas()`, or on
+    /// this server only if that is denied; [`InsertPressure::distributed`]
+    /// says which.
+    pub async fn insert_pressure(&self, database: Option<&str>, table: Option<&str>) -> Result<InsertPressure, ClickHouseError> {
+        let mut distributed = None;
+        let (filter, binds) = match (database, table) {
+            (Some(database), Some(table)) => {
+                self.validate_identifier(database)?;
+                self.validate_identifier(table)?;
+                let (database, table) = self.resolve_table_name(database, table).await?;
+                match self.distributed_target(&database, &table).await? {
+                    Some(target) => {
+                        let binds = vec![target.database.clone(), target.table.clone()];
+                        distributed = Some(DistributedScope {
+                            table: format!("{}.{}", database, table),
+                            target,
+                            cluster_wide: true,
+                        });
+                        ("AND database = ? AND table = ? ", binds)
+                    }
+                    None => ("AND database = ? AND table = ? ", vec![database, table]),
+                }
+            }
+            (Some(database), None) => {
+                self.validate_identifier(database)?;
+                ("AND database = ? ", vec![self.resolve_database_name(database).await?])
+            }
+            (None, Some(_)) => {
+                return Err(ClickHouseError::InvalidArgument {
+                    message: "A table needs its database".to_string(),
+                })
+            }
+            (None, None) => ("", Vec::new()),
+        };
+        info!("Reading insert pressure ({})", if binds.is_empty() { "all tables".to_string() } else { binds.join(".") });
+        
+        let counts = match distributed.as_mut() {
+            Some(scope) => {
+                let cluster_binds: Vec<String> = std::iter::once(scope.target.cluster.clone()).chain(binds.iter().cloned()).collect();
+                match self.part_counts(true, filter, &cluster_binds).await {
+                    Err(ClickHouseError::PermissionDenied { .. }) => {
+                        warn!("Reading parts across cluster '{}' was denied, reading this server's parts only", scope.target.cluster);
+                        scope.cluster_wide = false;
+                        self.part_counts(false, filter, &binds).await?
                     }
-                } else {
-                    ClickHouseError::QueryFailed { message: error_msg }
+                    counts => counts?,
                 }
+            }
+            None => self.part_counts(false, filter, &binds).await?,
+        };
+        
+        let defaults: Vec<(String, u64)> = self.with_retry(|| async {
+            self.query("SELECT name, toUInt64OrZero(value) FROM system.merge_tree_settings WHERE name IN ('parts_to_delay_insert', 'parts_to_throw_insert')")
+                .fetch_all()
+                .await
+        }).await?;
+        let default = |name: &str| defaults.iter().find(|(n, _)| n == name).map(|(_, v)| *v);
+        let (default_delay, default_throw) = match (default("parts_to_delay_insert"), default("parts_to_throw_insert")) {
+            (Some(delay), Some(throw)) => (delay, throw),
+            _ => {
+                return Err(ClickHouseError::InternalError {
+                    message: "system.merge_tree_settings has no parts_to_delay_insert/parts_to_throw_insert".to_string(),
+                })
+            }
+        };
+        
+        // Table-level overrides live in the SETTINGS clause of the CREATE statement
+        let create_queries: Vec<(String, String, String)> = if counts.is_empty() {
+            Vec::new()
+        } else {
+            let sql = format!(
+                "SELECT database, name, create_table_query FROM system.tables WHERE (database, name) IN ({})",
+                vec!["?"; counts.len()].join(", ")
+            );
+            self.with_retry(|| async {
+                counts
+                    .iter()
+                    .fold(self.query(&sql), |query, count| query.bind((count.database.as_str(), count.table.as_str())))
+                    .fetch_all()
+                    .await
+            }).await?
+        };
+        
+        let tables = counts
+            .into_iter()
+            .map(|count| {
+                let settings = create_queries
+                    .iter()
+                    .find(|(database, name, _)| *database == count.database && *name == count.table)
+                    .map(|(_, _, create_query)| parse_table_settings(create_query))
+                    .unwrap_or_default();
+                let setting = |name: &str, default: u64| {
+                    settings.iter().find(|(n, _)| n == name).and_then(|(_, v)| v.parse().ok()).unwrap_or(default)
+                };
+                TablePartPressure {
+                    parts_to_delay_insert: setting("parts_to_delay_insert", default_delay),
+                    parts_to_throw_insert: setting("parts_to_throw_insert", default_throw),
+                    database: count.database,
+                    table: count.table,
+                    active_parts: count.active_parts,
+                    max_partition_parts: count.max_partition_parts,
+                }
+            })
+            .collect();
+        
+        let values: Vec<MetricValue> = self.with_retry(|| async {
+            self.query(
+                "SELECT metric, toFloat64(value) AS value FROM system.metrics WHERE metric IN ('BackgroundMergesAndMutationsPoolTask', 'BackgroundMergesAndMutationsPoolSize') \
+                 UNION ALL \
+                 SELECT event AS metric, toFloat64(value) AS value FROM system.events WHERE event IN ('DelayedInserts', 'RejectedInserts')",
+            )
+            .fetch_all()
+            .await
+        }).await?;
+        let get = |name: &str| values.iter().find(|v| v.metric == name).map(|v| v.value as u64);
+        
+        Ok(InsertPressure {
+            tables,
+            delayed_inserts: get("DelayedInserts").unwrap_or(0),
+            rejected_inserts: get("RejectedInserts").unwrap_or(0),
+            merge_pool_tasks: get("BackgroundMergesAndMutationsPoolTask"),
+            merge_pool_size: get("BackgroundMergesAndMutationsPoolSize"),
+            distributed,
+        })
+    }
+    
+    /// Active part counts per table, see [`build_part_counts_query`], with
+    /// `binds` filling its placeholders in order.
+    async fn part_counts(&self, across_cluster: bool, filter: &str, binds: &[String]) -> Result<Vec<TablePartCount>, ClickHouseError> {
+        let sql = build_part_counts_query(across_cluster, filter);
+        self.with_retry(|| async {
+            binds.iter().fold(self.query(&sql), |query, value| query.bind(value.as_str())).fetch_all().await
+        }).await
+    }
+    
+    /// The local table behind `database.table` if it is a Distributed table,
+    /// parsed from `engine_full` in `system.tables`.
+    pub async fn distributed_target(&self, database: &str, table: &str) -> Result<Option<DistributedTarget>, ClickHouseError> {
+        let (engine, engine_full): (String, String) = self.with_retry(|| async {
+            self.query("SELECT engine, engine_full FROM system.tables WHERE database = ? AND name = ?")
+                .bind(database)
+                .bind(table)
+                .fetch_one()
+                .await
+        }).await?;
+        if engine != "Distributed" {
+            return Ok(None);
+        }
+        
+        match parse_distributed_engine(&engine_full, database) {
+            Some(target) => {
+                debug!("'{}.{}' is Distributed over '{}.{}' on cluster '{}'", database, table, target.database, target.table, target.cluster);
+                Ok(Some(target))
+            }
+            None => Err(ClickHouseError::InternalError {
+                message: format!("Cannot parse the Distributed engine parameters of '{}.{}': {}", database, table, engine_full),
+            }),
+        }
+    }
+
+    /// Reads the ZooKeeper/Keeper client counters (session expirations,
+    /// exceptions, ...) from `system.events`.
+    pub async fn keeper_events(&self) -> Result<Vec<KeeperEventCount>, ClickHouseError> {
+        info!("Reading Keeper event counters");
+        
+        self.with_retry(|| async {
+            self.query("SELECT event, value FROM system.events WHERE event LIKE 'ZooKeeper%' ORDER BY event")
+                .fetch_all()
+                .await
+        }).await
+    }
+
+    /// Returns the query plan of a read-only `SELECT` under the given
+    /// settings, preceded by the settings that were applied.
+    pub async fn explain_with_settings(
+        &self,
+        sql: &str,
+        settings: &[(String, String)],
+    ) -> Result<String, ClickHouseError> {
+        let sql = sql::prepare_select(sql)?;
+        sql::validate_settings(settings)?;
+        info!("Explaining query with {} settings", settings.len());
+        
+        let explain = format!("EXPLAIN PLAN indexes = 1 {}", sql);
+        let lines: Vec<String> = self.with_retry(|| async {
+            settings
+                .iter()
+                .fold(self.guarded_query(&explain), |query, (name, value)| query.with_option(name, value))
+                .fetch_all()
+                .await
+        }).await?;
+        
+        let applied = if settings.is_empty() {
+            "(none)".to_string()
+        } else {
+            settings
+                .iter()
+                .map(|(name, value)| format!("{} = {}", name, value))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        
+        let mut result = format!("Applied settings: {}\n\n", applied);
+        for line in lines {
+            result.push_str(&line);
+            result.push('\n');
+        }
+        Ok(result)
+    }
+
+    /// Runs a read-only SELECT and returns its rows as JSON objects, reading
+    /// at most `limits.max_rows` of them. Results can be large, so only
+    /// error responses are retried, see [`RetryPolicy::LightOnly`].
+    pub async fn execute_select(&self, sql: &str, limits: &SelectLimits) -> Result<SelectResult, ClickHouseError> {
+        let query = build_select_query(sql, limits)?;
+        info!("Executing SELECT with a limit of {} rows", limits.max_rows);
+        
+        let lines: Vec<String> = self.with_retry_policy(RetryPolicy::LightOnly, || async {
+            let mut query = self.guarded_query(&query);
+            if let Some(timeout) = limits.timeout {
+                query = query.with_option("max_execution_time", timeout.as_secs().max(1).to_string());
+            }
+            if let Some(bytes) = limits.max_result_bytes {
+                query = query.with_option("max_result_bytes", bytes.to_string());
+            }
+            query.fetch_all().await
+        }).await?;
+        
+        // One row past the limit is read to tell whether there were more
+        let truncated = lines.len() as u64 > limits.max_rows;
+        let rows = parse_json_rows(&lines[..lines.len().min(limits.max_rows as usize)])?;
+        let columns = lines.first().map(|line| results::row_columns(line)).unwrap_or_default();
+        debug!("SELECT returned {} rows{}", rows.len(), if truncated { " (truncated)" } else { "" });
+        Ok(SelectResult { columns, rows, truncated })
+    }
+
+    /// Runs `statements` one after another, stopping at the first failure.
+    ///
+    /// This is not a transaction: most ClickHouse engines cannot roll back,
+    /// so statements that ran before a failure keep their effects, and the
+    /// [`ClickHouseError::ScriptFailed`] error lists them. Every statement is
+    /// checked before any runs, and none is retried, as retrying an `INSERT`
+    /// could apply it twice.
+    pub async fn run_script(&self, statements: Vec<String>) -> Result<Vec<ExecResult>, ClickHouseError> {
+        if !self.writes_enabled {
+            return Err(ClickHouseError::WritesDisabled);
+        }
+        if statements.is_empty() || statements.len() > MAX_SCRIPT_STATEMENTS {
+            return Err(ClickHouseError::InvalidArgument {
+                message: format!(
+                    "A script must have between 1 and {} statements, got {}",
+                    MAX_SCRIPT_STATEMENTS,
+                    statements.len()
+                ),
+            });
+        }
+        let prepared = statements
+            .iter()
+            .enumerate()
+            .map(|(index, statement)| {
+                sql::prepare_statement(statement).map_err(|e| ClickHouseError::InvalidArgument {
+                    message: format!("statement {}: {}", index + 1, e),
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        info!("Running a script of {} statements", prepared.len());
+        
+        let total = prepared.len();
+        let mut completed = Vec::with_capacity(total);
+        for (index, (kind, sql)) in prepared.into_iter().enumerate() {
+            debug!("Running statement {} of {}: {}", index + 1, total, kind);
+            let started = Instant::now();
+            let outcome = self.guarded_query(&sql).execute().await;
+            let elapsed = started.elapsed();
+            if let Some(metrics) = &self.metrics {
+                metrics.record_query(elapsed);
+            }
+            
+            if let Err(error) = outcome {
+                return Err(ClickHouseError::ScriptFailed {
+                    index,
+                    total,
+                    completed,
+                    source: Box::new(self.convert_clickhouse_error(error)),
+                });
+            }
+            self.invalidate_changed_schema(&sql);
+            completed.push(ExecResult { statement: sql, kind, elapsed });
+        }
+        Ok(completed)
+    }
+
+    /// Drops the cached schema of the table a statement this client ran may
+    /// have changed, found by [`sql::statement_table`], or every cached
+    /// schema when the statement doesn't name a single table.
+    fn invalidate_changed_schema(&self, sql: &str) {
+        let Some(cache) = &self.schema_cache else {
+            return;
+        };
+        let scope = match sql::statement_table(sql) {
+            Some((database, table)) => CacheScope::Table {
+                database: database.unwrap_or_else(|| self.default_database.clone()),
+                table,
             },
-            _ => ClickHouseError::InternalError {
-                message: error.to_string(),
-            },
+            None => CacheScope::All,
+        };
+        let dropped = cache.invalidate(&scope);
+        debug!("Statement invalidated {} cached schemas ({:?})", dropped.len(), scope);
+    }
+
+    /// Runs one arbitrary statement, for administrators: anything but the
+    /// [`sql::ADMIN_DENIED_STATEMENTS`], regardless of
+    /// [`with_writes_enabled`](Self::with_writes_enabled). A SELECT returns
+    /// its first rows, up to [`Self::with_max_raw_rows`]; `SHOW`,
+    /// `DESCRIBE`, `EXISTS` and `EXPLAIN` are refused, as their results
+    /// can't be read generically.
+    /// Other statements run once, without retries.
+    pub async fn execute_raw(&self, sql: &str) -> Result<RawResult, ClickHouseError> {
+        let (kind, prepared) = sql::check_admin_statement(sql)?;
+        info!("Executing raw {} statement", kind);
+        
+        match kind {
+            StatementKind::Select => {
+                let limits = SelectLimits { max_rows: self.max_raw_rows, ..SelectLimits::default() };
+                self.execute_select(&prepared, &limits).await.map(RawResult::Rows)
+            }
+            StatementKind::Other(_) => {
+                let started = Instant::now();
+                let outcome = self.guarded_query(&prepared).execute().await;
+                let elapsed = started.elapsed();
+                if let Some(metrics) = &self.metrics {
+                    metrics.record_query(elapsed);
+                }
+                outcome.map_err(|e| self.convert_clickhouse_error(e))?;
+                self.invalidate_changed_schema(&prepared);
+                Ok(RawResult::Executed(ExecResult { statement: prepared, kind, elapsed }))
+            }
+            kind => Err(ClickHouseError::InvalidArgument {
+                message: format!("{} results can't be returned; query the system tables with a SELECT instead", kind),
+            }),
+        }
+    }
+
+    /// Estimates how many rows, parts and marks a read-only SELECT would
+    /// read, using `EXPLAIN ESTIMATE` without running the query.
+    pub async fn estimate_cost(&self, sql: &str) -> Result<CostEstimate, ClickHouseError> {
+        let sql = sql::prepare_select(sql)?;
+        self.require_feature(Feature::ExplainEstimate).await?;
+        info!("Estimating query cost");
+        
+        let explain = format!("EXPLAIN ESTIMATE {}", sql);
+        let tables: Vec<TableEstimate> = self.with_retry(|| async {
+            self.guarded_query(&explain).fetch_all().await
+        }).await?;
+        
+        debug!("Estimated reads from {} tables", tables.len());
+        Ok(CostEstimate::from_tables(tables))
+    }
+
+    /// Shows whether a read-only SELECT can use the primary key and skip
+    /// indexes of the tables it reads, and how many granules they leave to
+    /// read, from `EXPLAIN indexes = 1` without running the query.
+    pub async fn analyze_index_usage(&self, sql: &str) -> Result<IndexUsage, ClickHouseError> {
+        let sql = sql::prepare_select(sql)?;
+        info!("Analyzing index usage");
+        
+        let explain = format!("EXPLAIN indexes = 1 {}", sql);
+        // Building the plan may still evaluate constant expressions, so it
+        // runs read-only and briefly, like a column default preview
+        let lines: Vec<String> = self.with_retry(|| async {
+            self.guarded_query(&explain)
+                .with_option("readonly", "2")
+                .with_option("max_execution_time", PRUNING_EXPLAIN_TIMEOUT.as_secs().to_string())
+                .fetch_all()
+                .await
+        }).await?;
+        
+        let usage = IndexUsage::from_explain(&lines);
+        debug!("Query reads {} of {} granules", usage.granules_selected, usage.granules_total);
+        Ok(usage)
+    }
+
+    /// Checks whether filtering a MergeTree-family table with `condition`
+    /// lets ClickHouse skip partitions, from `EXPLAIN indexes = 1` of
+    /// `SELECT 1 FROM <table> WHERE <condition>` compared against the active
+    /// parts in `system.parts`. Nothing is read from the table; `SELECT 1`
+    /// rather than `count()` keeps the minmax count projection from
+    /// answering without a read to explain. The condition is checked with
+    /// [`sql::check_where_expression`] first.
+    pub async fn check_partition_pruning(&self, database: &str, table: &str, condition: &str) -> Result<PartitionPruning, ClickHouseError> {
+        self.validate_identifier(database)?;
+        self.validate_identifier(table)?;
+        let condition = sql::check_where_expression(condition)?;
+        info!("Checking partition pruning of table '{}.{}'", database, table);
+        
+        let (database, table) = self.resolve_table_name(database, table).await?;
+        let (database, table) = (database.as_str(), table.as_str());
+        let metadata = self.table_metadata(database, table).await?;
+        if !metadata.engine.ends_with("MergeTree") {
+            return Err(ClickHouseError::InvalidArgument {
+                message: format!(
+                    "table '{}.{}' uses the {} engine, which has no partitions to prune; check a MergeTree-family table instead",
+                    database, table, metadata.engine
+                ),
+            });
+        }
+        
+        let explain = format!(
+            "EXPLAIN indexes = 1 SELECT 1 FROM {}.{} WHERE {}",
+            quote_identifier(database),
+            quote_identifier(table),
+            condition
+        );
+        // Building the plan may still evaluate constant expressions, so it
+        // runs read-only and briefly, like a column default preview
+        let lines: Vec<String> = self.with_retry(|| async {
+            self.guarded_query(&explain)
+                .with_option("readonly", "2")
+                .with_option("max_execution_time", PRUNING_EXPLAIN_TIMEOUT.as_secs().to_string())
+                .fetch_all()
+                .await
+        }).await?;
+        let totals: PartTotals = self.with_retry(|| async {
+            self.query("SELECT count() AS parts, toUInt64(sum(rows)) AS rows FROM system.parts WHERE database = ? AND table = ? AND active")
+                .bind(database)
+                .bind(table)
+                .fetch_one()
+                .await
+        }).await?;
+        
+        let pruning = PartitionPruning::from_explain(&format!("{}.{}", database, table), &lines, &metadata.partition_key, totals);
+        debug!("Filter on '{}.{}' reads {} of {} parts", database, table, pruning.parts_after_partition_pruning, pruning.parts_total);
+        Ok(pruning)
+    }
+
+    /// Pretty-prints a statement with ClickHouse's own formatter. The
+    /// statement is only parsed, never executed; still, only read-only ones
+    /// are accepted unless [`with_writes_enabled`](Self::with_writes_enabled).
+    pub async fn format_query(&self, sql: &str) -> Result<String, ClickHouseError> {
+        let kind = sql::classify_statement(sql)?;
+        if !kind.is_read_only() && !self.writes_enabled {
+            return Err(ClickHouseError::InvalidArgument {
+                message: format!("Only read-only statements can be formatted while writes are disabled, got {}", kind),
+            });
+        }
+        
+        let formatter = *self.query_formatter.get_or_try_init(|| self.probe_query_formatter()).await?;
+        debug!("Formatting query with {:?}", formatter);
+        
+        let function = match formatter {
+            QueryFormatter::FormatQuery => "formatQuery",
+            QueryFormatter::FormatQuerySingleLine => "formatQuerySingleLine",
+            QueryFormatter::ExplainSyntax => {
+                let explain = format!("EXPLAIN SYNTAX {}", sql::prepare_select(sql)?);
+                let lines: Vec<String> = self.with_retry(|| async {
+                    self.query(&explain).fetch_all().await
+                }).await?;
+                return Ok(lines.join("\n"));
+            }
+        };
+        
+        self.with_retry(|| async {
+            self.query(&format!("SELECT {}(?)", function))
+                .bind(sql.trim())
+                .fetch_one::<String>()
+                .await
+        }).await
+    }
+    
+    async fn probe_query_formatter(&self) -> Result<QueryFormatter, ClickHouseError> {
+        let functions: Vec<String> = self.with_retry(|| async {
+            self.query("SELECT name FROM system.functions WHERE name IN ('formatQuery', 'formatQuerySingleLine')")
+                .fetch_all()
+                .await
+        }).await?;
+        
+        let formatter = if functions.iter().any(|f| f == "formatQuery") {
+            QueryFormatter::FormatQuery
+        } else if functions.iter().any(|f| f == "formatQuerySingleLine") {
+            QueryFormatter::FormatQuerySingleLine
+        } else {
+            QueryFormatter::ExplainSyntax
+        };
+        info!("Using {:?} to format queries", formatter);
+        Ok(formatter)
+    }
+
+    /// Looks up the first row whose `key_column` equals `key_value`. The value
+    /// is coerced for the column type with [`coerce_bound_value`], so date and
+    /// time keys may be ISO-8601 strings or Unix epochs.
+    pub async fn get_by_key(
+        &self,
+        database: &str,
+        table: &str,
+        key_column: &str,
+        key_value: &serde_json::Value,
+    ) -> Result<Option<serde_json::Value>, ClickHouseError> {
+        self.validate_identifier(key_column)?;
+        info!("Looking up row in table '{}.{}' by '{}'", database, table, key_column);
+        
+        let (database, table, columns) = self.table_schema(database, table).await?;
+        let (database, table) = (database.as_str(), table.as_str());
+        let column = find_column(&columns, database, table, key_column)?;
+        let key = sql::coerce_bound_value(&column.r#type, key_value)?;
+        
+        let sql = format!(
+            "SELECT formatRowNoNewline('JSONEachRow', *) FROM {}.{} WHERE {} = {} LIMIT 1",
+            quote_identifier(database),
+            quote_identifier(table),
+            quote_identifier(key_column),
+            key.placeholder
+        );
+        let line: Option<String> = self.with_retry(|| async {
+            self.guarded_query(&sql).bind(key.value.as_str()).fetch_optional().await
+        }).await?;
+        
+        line.map(|line| {
+            serde_json::from_str(&line).map_err(|e| ClickHouseError::InternalError {
+                message: format!("ClickHouse returned invalid JSON row: {}", e),
+            })
+        })
+        .transpose()
+    }
+
+    /// The columns of a table with a default expression, each evaluated for
+    /// a row of the table: `SELECT <expression> FROM (SELECT <columns> FROM
+    /// table LIMIT 1)`, read-only, with every column but the EPHEMERAL ones,
+    /// which aren't stored. Expressions without column references are
+    /// evaluated on their own when the table is empty. Expressions
+    /// [`sql::check_preview_expression`] rejects and ones that fail get an
+    /// `error` instead of a `value`; only reading the schema fails the call.
+    pub async fn explain_column_defaults(
+        &self,
+        database: &str,
+        table: &str,
+    ) -> Result<Vec<ColumnDefaultPreview>, ClickHouseError> {
+        info!("Evaluating column defaults of table '{}.{}'", database, table);
+        
+        let (database, table, columns) = self.table_schema(database, table).await?;
+        let stored: Vec<&str> = columns.iter().filter(|c| c.default_type != "EPHEMERAL").map(|c| c.name.as_str()).collect();
+        let mut previews = Vec::new();
+        for column in columns.iter().filter(|c| !c.default_type.is_empty()) {
+            let evaluated = if column.default_expression.is_empty() {
+                Err("no expression".to_string())
+            } else {
+                self.evaluate_default(&database, &table, &stored, &column.default_expression).await.map_err(|e| match e {
+                    ClickHouseError::InvalidArgument { message } => message,
+                    e => e.to_string(),
+                })
+            };
+            if let Err(e) = &evaluated {
+                debug!("Could not evaluate default of column '{}': {}", column.name, e);
+            }
+            let (value, error) = match evaluated {
+                Ok(value) => (Some(value), None),
+                Err(e) => (None, Some(e)),
+            };
+            previews.push(ColumnDefaultPreview {
+                column: column.name.clone(),
+                default_type: column.default_type.clone(),
+                expression: column.default_expression.clone(),
+                value,
+                error,
+            });
+        }
+        Ok(previews)
+    }
+    
+    /// Evaluates one default expression of `database.table` for
+    /// [`Self::explain_column_defaults`], over a row of `columns`.
+    async fn evaluate_default(&self, database: &str, table: &str, columns: &[&str], expression: &str) -> Result<String, ClickHouseError> {
+        let expression = sql::check_preview_expression(expression)?;
+        // readonly=2 forbids writes but, unlike 1, still lets the guard
+        // settings of the request apply
+        let preview = |sql: String| async move {
+            let row = self.with_retry(|| async {
+                self.guarded_query(&sql)
+                    .with_option("readonly", "2")
+                    .with_option("max_result_rows", "1")
+                    .fetch_optional::<DefaultValue>()
+                    .await
+            }).await?;
+            Ok::<_, ClickHouseError>(row.map(|row| row.value))
+        };
+        
+        let sampled = preview(build_default_preview_query(Some((database, table, columns)), &expression)).await?;
+        let value = match sampled {
+            Some(value) => value,
+            // Only expressions without column references work without a row
+            None => preview(build_default_preview_query(None, &expression)).await.ok().flatten().ok_or_else(|| ClickHouseError::InvalidArgument {
+                message: "the table has no rows to evaluate the expression against".to_string(),
+            })?,
+        };
+        Ok(value.unwrap_or_else(|| "NULL".to_string()))
+    }
+
+    /// The exact number of rows of a table, from `count()`. MergeTree tables
+    /// answer from part metadata, but other engines and tables with pending
+    /// lightweight deletes are scanned, which can be slow on huge tables.
+    pub async fn count_rows(&self, database: &str, table: &str) -> Result<u64, ClickHouseError> {
+        self.validate_identifier(database)?;
+        self.validate_identifier(table)?;
+        info!("Counting rows of table '{}.{}'", database, table);
+        
+        let (database, table) = self.resolve_table_name(database, table).await?;
+        let sql = format!("SELECT count() FROM {}.{}", quote_identifier(&database), quote_identifier(&table));
+        self.with_retry(|| async {
+            self.guarded_query(&sql).fetch_one::<u64>().await
+        }).await
+    }
+    
+    /// Estimates the rows of a MergeTree-family table as the sum of `rows`
+    /// over its active parts in `system.parts`, without reading any data.
+    /// Rows deleted by lightweight deletes or not yet collapsed by a
+    /// Replacing/Collapsing merge are still counted. Other engines have no
+    /// parts and are `InvalidArgument`.
+    ///
+    /// A Distributed table is estimated from the parts of its local table
+    /// on one replica of every shard, read with `cluster()`, or from this
+    /// server's parts only if that is denied; [`RowEstimate`] says which.
+    pub async fn count_rows_approx(&self, database: &str, table: &str) -> Result<RowEstimate, ClickHouseError> {
+        self.validate_identifier(database)?;
+        self.validate_identifier(table)?;
+        info!("Estimating rows of table '{}.{}'", database, table);
+        
+        let (database, table) = self.resolve_table_name(database, table).await?;
+        let (database, table) = (database.as_str(), table.as_str());
+        let mut metadata = self.table_metadata(database, table).await?;
+        let mut distributed = None;
+        if metadata.engine == "Distributed" {
+            if let Some(target) = self.distributed_target(database, table).await? {
+                metadata = self.table_metadata(&target.database, &target.table).await?;
+                distributed = Some(target);
+            }
+        }
+        if !metadata.engine.ends_with("MergeTree") {
+            return Err(ClickHouseError::InvalidArgument {
+                message: format!(
+                    "table '{}.{}' uses the {} engine, which has no parts to estimate rows from; count exactly instead",
+                    database, table, metadata.engine
+                ),
+            });
+        }
+        
+        let Some(target) = distributed else {
+            let rows = self.part_rows(None, database, table).await?;
+            return Ok(RowEstimate { rows, distributed: None, cluster_wide: false });
+        };
+        match self.part_rows(Some(&target.cluster), &target.database, &target.table).await {
+            Err(ClickHouseError::PermissionDenied { .. }) => {
+                warn!("Reading parts across cluster '{}' was denied, estimating from this server's parts only", target.cluster);
+                let rows = self.part_rows(None, &target.database, &target.table).await?;
+                Ok(RowEstimate { rows, distributed: Some(target), cluster_wide: false })
+            }
+            rows => Ok(RowEstimate { rows: rows?, distributed: Some(target), cluster_wide: true }),
+        }
+    }
+    
+    /// The sum of `rows` over the active parts of `database.table`, on this
+    /// server or on one replica of every shard of `cluster`.
+    async fn part_rows(&self, cluster: Option<&str>, database: &str, table: &str) -> Result<u64, ClickHouseError> {
+        let source = if cluster.is_some() { "cluster(?, system.parts)" } else { "system.parts" };
+        let sql = format!("SELECT toUInt64(sum(rows)) FROM {} WHERE database = ? AND table = ? AND active", source);
+        self.with_retry(|| async {
+            cluster
+                .into_iter()
+                .chain([database, table])
+                .fold(self.query(&sql), |query, value| query.bind(value))
+                .fetch_one::<u64>()
+                .await
+        }).await
+    }
+    
+    /// The share of NULLs in a column, from 0 to 1. Columns that can't hold
+    /// NULL, and empty tables, give 0 (the former without querying).
+    pub async fn null_fraction(&self, database: &str, table: &str, column: &str) -> Result<f64, ClickHouseError> {
+        self.validate_identifier(column)?;
+        info!("Computing null fraction of '{}' in table '{}.{}'", column, database, table);
+        
+        let (database, table, columns) = self.table_schema(database, table).await?;
+        let (database, table) = (database.as_str(), table.as_str());
+        let column_info = find_column(&columns, database, table, column)?;
+        if !is_nullable_type(&column_info.r#type) {
+            debug!("Column '{}' has non-nullable type {}", column, column_info.r#type);
+            return Ok(0.0);
+        }
+        
+        let sql = format!(
+            "SELECT if(count() = 0, 0, countIf(isNull({column})) / count()) FROM {}.{}",
+            quote_identifier(database),
+            quote_identifier(table),
+            column = quote_identifier(column)
+        );
+        self.with_retry(|| async {
+            self.guarded_query(&sql).fetch_one::<f64>().await
+        }).await
+    }
+
+    /// The compressed and uncompressed size of each column of a table,
+    /// largest uncompressed first. Only MergeTree-family tables track column
+    /// sizes; other engines report 0 for every column.
+    pub async fn column_compression(&self, database: &str, table: &str) -> Result<Vec<ColumnCompression>, ClickHouseError> {
+        self.validate_identifier(database)?;
+        self.validate_identifier(table)?;
+        info!("Getting column compression of table '{}.{}'", database, table);
+        
+        let (database, table) = self.resolve_table_name(database, table).await?;
+        let (database, table) = (database.as_str(), table.as_str());
+        let rows: Vec<(String, u64, u64)> = self.with_retry(|| async {
+            self.query(
+                "SELECT name, data_compressed_bytes, data_uncompressed_bytes FROM system.columns \
+                 WHERE database = ? AND table = ? ORDER BY data_uncompressed_bytes DESC, position"
+            )
+                .bind(database)
+                .bind(table)
+                .fetch_all()
+                .await
+        }).await?;
+        
+        Ok(rows
+            .into_iter()
+            .map(|(column, compressed, uncompressed)| ColumnCompression {
+                column,
+                compressed,
+                uncompressed,
+                ratio: (compressed > 0).then(|| uncompressed as f64 / compressed as f64),
+            })
+            .collect())
+    }
+
+    /// The `CREATE` statement of every table, view and dictionary of
+    /// `database` as `(table, ddl)` pairs, each table after the tables it
+    /// reads from or writes into where that can be told from the statements,
+    /// see [`order_ddl`].
+    pub async fn dump_database_ddl(&self, database: &str) -> Result<Vec<(String, String)>, ClickHouseError> {
+        self.validate_identifier(database)?;
+        info!("Dumping DDL of database '{}'", database);
+        
+        let database = self.resolve_database_name(database).await?;
+        let database = database.as_str();
+        let names: Vec<String> = self.with_system_fallback(
+            &self.system_table_fallback.tables,
+            || self.with_retry(|| async {
+                self.query("SELECT name FROM system.tables WHERE database = ? ORDER BY name")
+                    .bind(database)
+                    .fetch_all()
+                    .await
+            }),
+            || self.show_tables(database),
+        ).await?;
+        
+        let mut tables = Vec::with_capacity(names.len());
+        for name in names {
+            let sql = format!("SHOW CREATE TABLE {}.{}", quote_identifier(database), quote_identifier(&name));
+            let ddl: String = self.with_retry(|| async { self.query(&sql).fetch_one().await }).await?;
+            tables.push((name, ddl));
+        }
+        Ok(order_ddl(database, tables))
+    }
+
+    /// Runs a fixed set of data-quality checks in one query: row count,
+    /// duplicates among `key_columns` (estimated with `uniq`), nulls per
+    /// nullable column and the range of the first date column. With
+    /// `sample_ratio`, tables with a sampling key are sampled.
+    pub async fn table_quality_check(
+        &self,
+        database: &str,
+        table: &str,
+        key_columns: &[String],
+        sample_ratio: Option<f64>,
+    ) -> Result<QualityReport, ClickHouseError> {
+        if key_columns.len() > MAX_QUALITY_CHECK_COLUMNS {
+            return Err(ClickHouseError::InvalidArgument {
+                message: format!("At most {} key columns can be checked, got {}", MAX_QUALITY_CHECK_COLUMNS, key_columns.len()),
+            });
+        }
+        if let Some(ratio) = sample_ratio {
+            if !(ratio > 0.0 && ratio <= 1.0) {
+                return Err(ClickHouseError::InvalidArgument {
+                    message: format!("sample_ratio must be in (0, 1], got {}", ratio),
+                });
+            }
+        }
+        info!("Checking data quality of table '{}.{}'", database, table);
+        
+        let (database, table, columns) = self.table_schema(database, table).await?;
+        let (database, table) = (database.as_str(), table.as_str());
+        
+        for key in key_columns {
+            find_column(&columns, database, table, key)?;
+        }
+        let keys: Vec<&str> = key_columns.iter().map(String::as_str).collect();
+        
+        let nullable: Vec<&str> = columns
+            .iter()
+            .filter(|c| is_nullable_type(&c.r#type))
+            .map(|c| c.name.as_str())
+            .collect();
+        let (checked, skipped) = nullable.split_at(nullable.len().min(MAX_QUALITY_CHECK_COLUMNS));
+        
+        let date_column = columns
+            .iter()
+            .find(|c| unwrap_type_modifiers(&c.r#type).starts_with("Date"))
+            .map(|c| c.name.as_str());
+        
+        let has_sampling_key = columns.iter().any(|c| c.is_in_sampling_key == 1);
+        let applied_ratio = sample_ratio.filter(|_| has_sampling_key);
+        
+        let sql = build_quality_check_query(database, table, &keys, checked, date_column, applied_ratio);
+        let QualityCheckRow { total_rows, distinct_keys, null_counts, min_date, max_date } =
+            self.with_retry(|| async { self.guarded_query(&sql).fetch_one().await }).await?;
+        
+        let mut checks = vec![QualityCheck {
+            name: "row_count".to_string(),
+            status: if total_rows == 0 { CheckStatus::Warn } else { CheckStatus::Pass },
+            detail: format!("{} rows", human_count(total_rows)),
+        }];
+        
+        if !keys.is_empty() {
+            // Allow for uniq()'s estimation error once it stops being exact
+            let tolerance = if total_rows > UNIQ_EXACT_LIMIT { total_rows / 100 } else { 0 };
+            let duplicates = total_rows.saturating_sub(distinct_keys);
+            checks.push(QualityCheck {
+                name: "duplicate_keys".to_string(),
+                status: if duplicates > tolerance { CheckStatus::Warn } else { CheckStatus::Pass },
+                detail: format!(
+                    "{} distinct ({}) of {} rows, about {} duplicates{}",
+                    human_count(distinct_keys),
+                    keys.join(", "),
+                    human_count(total_rows),
+                    human_count(duplicates),
+                    if total_rows > UNIQ_EXACT_LIMIT { " (approximate)" } else { "" }
+                ),
+            });
+        }
+        
+        for (column, nulls) in checked.iter().zip(null_counts) {
+            let share = if total_rows == 0 { 0.0 } else { nulls as f64 / total_rows as f64 * 100.0 };
+            checks.push(QualityCheck {
+                name: format!("nulls:{}", column),
+                status: if nulls > 0 { CheckStatus::Warn } else { CheckStatus::Pass },
+                detail: format!("{} nulls ({:.2}%)", human_count(nulls), share),
+            });
+        }
+        
+        if let (Some(column), true) = (date_column, total_rows > 0) {
+            let today = chrono::Utc::now().format("%Y-%m-%d").to_string();
+            // 1970-01-01 is what empty or unparseable dates default to
+            let suspicious = min_date.starts_with("1970-01-01") || max_date.get(..10).is_some_and(|day| day > today.as_str());
+            checks.push(QualityCheck {
+                name: format!("date_range:{}", column),
+                status: if suspicious { CheckStatus::Warn } else { CheckStatus::Pass },
+                detail: format!("{} to {}", min_date, max_date),
+            });
+        }
+        
+        debug!("Ran {} quality checks on '{}.{}'", checks.len(), database, table);
+        Ok(QualityReport {
+            total_rows,
+            sample_ratio: applied_ratio,
+            checks,
+            skipped_columns: skipped.iter().map(|c| c.to_string()).collect(),
+            sampling_unavailable: sample_ratio.is_some() && !has_sampling_key,
+        })
+    }
+
+    /// Counts rows per time bucket, with empty buckets filled with zero.
+    pub async fn row_counts_over_time(
+        &self,
+        database: &str,
+        table: &str,
+        time_column: &str,
+        interval: TimeInterval,
+        last_n: Option<u32>,
+    ) -> Result<Vec<TimeBucketCount>, ClickHouseError> {
+        self.validate_identifier(time_column)?;
+        if let Some(n) = last_n {
+            if !(1..=1000).contains(&n) {
+                return Err(ClickHouseError::InvalidArgument {
+                    message: format!("last_n must be between 1 and 1000, got {}", n),
+                });
+            }
+        }
+        info!("Counting rows per {} in table '{}.{}' by '{}'", interval, database, table, time_column);
+        
+        let (database, table, columns) = self.table_schema(database, table).await?;
+        let (database, table) = (database.as_str(), table.as_str());
+        let column_info = find_time_column(&columns, database, table, time_column)?;
+        
+        let base_type = unwrap_type_modifiers(&column_info.r#type);
+        if interval == TimeInterval::Hour && !base_type.starts_with("DateTime") {
+            return Err(ClickHouseError::InvalidArgument {
+                message: format!("column '{}' has type {} which cannot be bucketed by hour", time_column, column_info.r#type),
+            });
         }
-    }
-    
-    pub async fn health_check(&self) -> Result<(), ClickHouseError> {
-        info!("Performing ClickHouse health check");
         
-        self.with_retry(|| async {
-            self.client
-                .query("SELECT 1")
-                .fetch_one::<u8>()
-                .await
+        let sql = build_row_counts_query(database, table, time_column, interval, last_n);
+        let buckets: Vec<TimeBucketCount> = self.with_retry(|| async {
+            self.guarded_query(&sql).fetch_all().await
         }).await?;
         
-        info!("ClickHouse health check passed");
-        Ok(())
+        debug!("Found {} time buckets in table '{}.{}'", buckets.len(), database, table);
+        Ok(buckets)
     }
 
-    pub async fn list_databases(&self) -> Result<Vec<DatabaseInfo>, ClickHouseError> {
-        info!("Listing databases");
+    /// The rows of a table whose `time_column` is later than `since`,
+    /// oldest first and at most `limit` of them, for following a log-like
+    /// table: pass the returned [`PolledRows::max_timestamp`] as `since` on
+    /// the next call. `since` is compared as ClickHouse parses it for the
+    /// column's type, e.g. `2024-01-31 12:00:00`.
+    ///
+    /// Rows are only returned once their timestamp is later than the last
+    /// one seen, so rows sharing the last timestamp of a full page, or
+    /// inserted late with an older timestamp, are not returned.
+    pub async fn poll_new_rows(
+        &self,
+        database: &str,
+        table: &str,
+        time_column: &str,
+        since: String,
+        limit: u64,
+    ) -> Result<PolledRows, ClickHouseError> {
+        self.validate_identifier(time_column)?;
+        if !(1..=MAX_POLL_ROWS).contains(&limit) {
+            return Err(ClickHouseError::InvalidArgument {
+                message: format!("limit must be between 1 and {}, got {}", MAX_POLL_ROWS, limit),
+            });
+        }
+        info!("Polling table '{}.{}' for rows after '{}' by '{}'", database, table, since, time_column);
         
-        let databases = self.with_retry(|| async {
-            self.client
-                .query("SELECT name FROM system.databases ORDER BY name")
-                .fetch_all()
-                .await
+        let (database, table, columns) = self.table_schema(database, table).await?;
+        let (database, table) = (database.as_str(), table.as_str());
+        find_time_column(&columns, database, table, time_column)?;
+        
+        let sql = build_poll_query(database, table, time_column, limit);
+        let lines: Vec<(String, String)> = self.with_retry(|| async {
+            self.guarded_query(&sql).bind(since.as_str()).fetch_all().await
         }).await?;
         
-        debug!("Found {} databases", databases.len());
-        Ok(databases)
+        let max_timestamp = lines.last().map(|(timestamp, _)| timestamp.clone());
+        let lines: Vec<String> = lines.into_iter().map(|(_, row)| row).collect();
+        let rows = parse_json_rows(&lines)?;
+        let columns = lines.first().map(|line| results::row_columns(line)).unwrap_or_default();
+        
+        debug!("Found {} new rows in table '{}.{}'", rows.len(), database, table);
+        Ok(PolledRows { columns, rows, max_timestamp })
     }
 
-    pub async fn list_tables(&self, database: &str) -> Result<Vec<TableInfo>, ClickHouseError> {
-        Self::validate_identifier(database)?;
-        info!("Listing tables in database '{}'", database);
+    /// Applies each of `functions`, from [`AGGREGATE_FUNCTIONS`], to
+    /// `column` in one query over the whole table, returning each function's
+    /// result by its name. `count` counts the non-NULL values, and `sum`,
+    /// `avg` and `median` need a numeric column. Functions given twice are
+    /// applied once.
+    pub async fn aggregate(
+        &self,
+        database: &str,
+        table: &str,
+        column: &str,
+        functions: Vec<String>,
+    ) -> Result<serde_json::Map<String, serde_json::Value>, ClickHouseError> {
+        self.validate_identifier(column)?;
+        if functions.is_empty() {
+            return Err(ClickHouseError::InvalidArgument {
+                message: format!("functions must name at least one of {}", AGGREGATE_FUNCTIONS.join(", ")),
+            });
+        }
+        let mut applied: Vec<&str> = Vec::new();
+        for function in &functions {
+            let Some(known) = AGGREGATE_FUNCTIONS.iter().find(|known| **known == function.as_str()) else {
+                return Err(ClickHouseError::InvalidArgument {
+                    message: format!("unknown aggregate function '{}', expected one of {}", function, AGGREGATE_FUNCTIONS.join(", ")),
+                });
+            };
+            if !applied.contains(known) {
+                applied.push(known);
+            }
+        }
+        info!("Aggregating '{}' in table '{}.{}' with {}", column, database, table, applied.join(", "));
         
-        // First check if the database exists
-        let db_exists: u8 = self.with_retry(|| async {
-            self.client
-                .query("SELECT count(*) > 0 FROM system.databases WHERE name = ?")
-                .bind(database)
+        let (database, table, columns) = self.table_schema(database, table).await?;
+        let (database, table) = (database.as_str(), table.as_str());
+        let column_info = find_column(&columns, database, table, column)?;
+        if let Some(function) = applied.iter().find(|function| NUMERIC_AGGREGATE_FUNCTIONS.contains(function)) {
+            if !is_numeric_type(&column_info.r#type) {
+                return Err(ClickHouseError::InvalidArgument {
+                    message: format!("{} needs a numeric column, but '{}' has type {}", function, column, column_info.r#type),
+                });
+            }
+        }
+        
+        let sql = build_aggregate_query(database, table, column, &applied);
+        let line: String = self.with_retry(|| async {
+            self.guarded_query(&sql)
+                .with_option("output_format_json_quote_64bit_integers", "0")
                 .fetch_one()
                 .await
         }).await?;
         
-        if db_exists == 0 {
-            return Err(ClickHouseError::DatabaseNotFound {
-                database: database.to_string(),
+        let results: serde_json::Map<String, serde_json::Value> = serde_json::from_str(&line).map_err(|e| ClickHouseError::InternalError {
+            message: format!("ClickHouse returned invalid JSON row: {}", e),
+        })?;
+        Ok(results
+            .into_iter()
+            .map(|(alias, value)| (alias.strip_prefix(AGGREGATE_ALIAS_PREFIX).map(str::to_string).unwrap_or(alias), value))
+            .collect())
+    }
+
+    /// Counts rows per distinct value of `column`, returning the `limit`
+    /// largest groups. Compound columns and columns with more than
+    /// [`MAX_GROUP_COUNT_CARDINALITY`] distinct values are rejected.
+    pub async fn group_count(
+        &self,
+        database: &str,
+        table: &str,
+        column: &str,
+        limit: u32,
+        time_filter: Option<&TimeFilter>,
+    ) -> Result<GroupCounts, ClickHouseError> {
+        self.validate_identifier(column)?;
+        if !(1..=MAX_GROUP_COUNT_LIMIT).contains(&limit) {
+            return Err(ClickHouseError::InvalidArgument {
+                message: format!("limit must be between 1 and {}, got {}", MAX_GROUP_COUNT_LIMIT, limit),
             });
         }
+        if let Some(filter) = time_filter {
+            self.validate_identifier(&filter.column)?;
+            if !(1..=3650).contains(&filter.last_n_days) {
+                return Err(ClickHouseError::InvalidArgument {
+                    message: format!("last_n_days must be between 1 and 3650, got {}", filter.last_n_days),
+                });
+            }
+        }
+        info!("Counting rows per value of '{}' in table '{}.{}'", column, database, table);
         
-        let tables = self.with_retry(|| async {
-            self.client
-                .query("SELECT name, database, engine FROM system.tables WHERE database = ? ORDER BY name")
-                .bind(database)
-                .fetch_all()
-                .await
-        }).await.map_err(|e| {
-            if let ClickHouseError::QueryFailed { message } = &e {
-                if message.contains("doesn't exist") {
-                    return ClickHouseError::DatabaseNotFound {
-                        database: database.to_string(),
-                    };
-                }
+        let (database, table, columns) = self.table_schema(database, table).await?;
+        let (database, table) = (database.as_str(), table.as_str());
+        let column_info = find_column(&columns, database, table, column)?;
+        
+        let base_type = unwrap_type_modifiers(&column_info.r#type);
+        if ["Array(", "Map(", "Tuple(", "Nested(", "Object(", "JSON", "AggregateFunction("]
+            .iter()
+            .any(|prefix| base_type.starts_with(prefix))
+        {
+            return Err(ClickHouseError::InvalidArgument {
+                message: format!(
+                    "column '{}' has compound type {} and cannot be grouped by value; group by a scalar column instead",
+                    column, column_info.r#type
+                ),
+            });
+        }
+        if let Some(filter) = time_filter {
+            let time_info = find_column(&columns, database, table, &filter.column)?;
+            if !unwrap_type_modifiers(&time_info.r#type).starts_with("Date") {
+                return Err(ClickHouseError::InvalidArgument {
+                    message: format!(
+                        "time filter column '{}' has type {}, expected a Date or DateTime column",
+                        filter.column, time_info.r#type
+                    ),
+                });
             }
-            e
-        })?;
+        }
         
-        debug!("Found {} tables in database '{}'", tables.len(), database);
-        Ok(tables)
-    }
-
-    pub async fn get_table_schema(&self, database: &str, table: &str) -> Result<Vec<ColumnInfo>, ClickHouseError> {
-        Self::validate_identifier(database)?;
-        Self::validate_identifier(table)?;
-        info!("Getting schema for table '{}.{}'", database, table);
+        let time_column = time_filter.map(|f| f.column.as_str());
+        let bind_days = |query: transport::Query| match time_filter {
+            Some(filter) => query.bind(filter.last_n_days),
+            None => query,
+        };
         
-        // First check if the database exists
-        let db_exists: u8 = self.with_retry(|| async {
-            self.client
-                .query("SELECT count(*) > 0 FROM system.databases WHERE name = ?")
-                .bind(database)
-                .fetch_one()
-                .await
+        let probe = build_group_count_probe_query(database, table, column, time_column);
+        let (total_rows, distinct_values): (u64, u64) = self.with_retry(|| async {
+            bind_days(self.guarded_query(&probe)).fetch_one().await
         }).await?;
-        
-        if db_exists == 0 {
-            return Err(ClickHouseError::DatabaseNotFound {
-                database: database.to_string(),
+        if distinct_values > MAX_GROUP_COUNT_CARDINALITY {
+            return Err(ClickHouseError::InvalidArgument {
+                message: format!(
+                    "column '{}' has about {} distinct values, more than the {} that can be grouped; \
+                     use column_histogram for numeric columns or pick a lower-cardinality column",
+                    column, distinct_values, MAX_GROUP_COUNT_CARDINALITY
+                ),
             });
         }
         
-        // Then check if the table exists
-        let table_exists: u8 = self.with_retry(|| async {
-            self.client
-                .query("SELECT count(*) > 0 FROM system.tables WHERE database = ? AND name = ?")
-                .bind(database)
-                .bind(table)
-                .fetch_one()
-                .await
+        let sql = build_group_count_query(database, table, column, limit, time_column);
+        let groups: Vec<GroupCount> = self.with_retry(|| async {
+            bind_days(self.guarded_query(&sql)).fetch_all().await
         }).await?;
         
-        if table_exists == 0 {
-            return Err(ClickHouseError::TableNotFound {
-                database: database.to_string(),
-                table: table.to_string(),
+        debug!("Found {} groups of '{}' in table '{}.{}'", groups.len(), column, database, table);
+        Ok(GroupCounts {
+            column: column.to_string(),
+            column_type: column_info.r#type.clone(),
+            total_rows,
+            distinct_values,
+            groups,
+        })
+    }
+    
+    /// Lists the `limit` tables whose data changed most recently, by the
+    /// newest modification time of their active parts in `system.parts`.
+    /// Tables without parts (views, Memory, Log, ...) are not listed. Without
+    /// a database, the `hidden_databases` are left out; the
+    /// [`SYSTEM_DATABASES`] log tables change all the time.
+    pub async fn recently_modified_tables(
+        &self,
+        database: Option<&str>,
+        limit: u32,
+        hidden_databases: &[&str],
+    ) -> Result<RecentModifications, ClickHouseError> {
+        if !(1..=MAX_RECENTLY_MODIFIED_LIMIT).contains(&limit) {
+            return Err(ClickHouseError::InvalidArgument {
+                message: format!("limit must be between 1 and {}, got {}", MAX_RECENTLY_MODIFIED_LIMIT, limit),
             });
         }
+        let database = match database {
+            Some(database) => {
+                self.validate_identifier(database)?;
+                Some(self.resolve_database_name(database).await?)
+            }
+            None => None,
+        };
+        info!("Listing recently modified tables in {}", database.as_deref().unwrap_or("all databases"));
         
-        let columns = self.with_retry(|| async {
-            self.client
-                .query("SELECT name, type, default_kind as default_type, default_expression, comment, is_in_partition_key, is_in_sorting_key, is_in_primary_key, is_in_sampling_key FROM system.columns WHERE database = ? AND table = ? ORDER BY position")
-                .bind(database)
-                .bind(table)
-                .fetch_all()
-                .await
-        }).await.map_err(|e| {
-            if let ClickHouseError::QueryFailed { message } = &e {
-                if message.contains("doesn't exist") {
-                    return ClickHouseError::TableNotFound {
-                        database: database.to_string(),
-                        table: table.to_string(),
-                    };
+        // One more than the limit tells whether there are more
+        let sql = build_recently_modified_query(database.is_some(), limit + 1);
+        let mut tables: Vec<TableModification> = self.with_retry(|| async {
+            let query = self.guarded_query(&sql);
+            match &database {
+                Some(database) => query.bind(database.as_str()),
+                None => query.bind(hidden_databases),
+            }
+            .fetch_all()
+            .await
+        }).await?;
+        
+        let truncated = tables.len() > limit as usize;
+        tables.truncate(limit as usize);
+        debug!("Found {} recently modified tables", tables.len());
+        Ok(RecentModifications { tables, truncated })
+    }
+}
+
+/// Extracts `PROJECTION name (query)` clauses from a `CREATE TABLE` statement.
+pub fn parse_projections(create_query: &str) -> Vec<ProjectionInfo> {
+    const KEYWORD: &str = "PROJECTION";
+    
+    let mut projections = Vec::new();
+    let bytes = create_query.as_bytes();
+    let mut quote: Option<u8> = None;
+    let mut i = 0;
+    
+    while i < bytes.len() {
+        let c = bytes[i];
+        
+        if let Some(q) = quote {
+            if c == b'\\' {
+                i += 2;
+                continue;
+            }
+            if c == q {
+                quote = None;
+            }
+            i += 1;
+            continue;
+        }
+        
+        if c == b'\'' || c == b'`' || c == b'"' {
+            quote = Some(c);
+            i += 1;
+            continue;
+        }
+        
+        let at_word_start = i == 0 || !(bytes[i - 1].is_ascii_alphanumeric() || bytes[i - 1] == b'_');
+        if at_word_start && bytes[i..].starts_with(KEYWORD.as_bytes()) {
+            let rest = &create_query[i + KEYWORD.len()..];
+            if let Some((projection, consumed)) = parse_projection_clause(rest) {
+                projections.push(projection);
+                i += KEYWORD.len() + consumed;
+                continue;
+            }
+        }
+        
+        i += 1;
+    }
+    
+    projections
+}
+
+/// Extracts the table-level `SETTINGS name = value, ...` of a `CREATE TABLE`
+/// statement, with string values unquoted. Column-level settings inside the
+/// column list are not included.
+pub fn parse_table_settings(create_query: &str) -> Vec<(String, String)> {
+    const KEYWORD: &str = "SETTINGS";
+    
+    let bytes = create_query.as_bytes();
+    let mut quote: Option<u8> = None;
+    let mut depth = 0usize;
+    let mut i = 0;
+    
+    while i < bytes.len() {
+        let c = bytes[i];
+        
+        if let Some(q) = quote {
+            if c == b'\\' {
+                i += 2;
+                continue;
+            }
+            if c == q {
+                quote = None;
+            }
+            i += 1;
+            continue;
+        }
+        
+        match c {
+            b'\'' | b'`' | b'"' => quote = Some(c),
+            b'(' => depth += 1,
+            b')' => depth = depth.saturating_sub(1),
+            _ => {
+                let at_word_start = i == 0 || !(bytes[i - 1].is_ascii_alphanumeric() || bytes[i - 1] == b'_');
+                let at_word_end = bytes.get(i + KEYWORD.len()).is_none_or(|c| !(c.is_ascii_alphanumeric() || *c == b'_'));
+                if depth == 0 && at_word_start && at_word_end && bytes[i..].starts_with(KEYWORD.as_bytes()) {
+                    return parse_settings_list(&create_query[i + KEYWORD.len()..]);
                 }
             }
-            e
-        })?;
+        }
         
-        if columns.is_empty() {
-            return Err(ClickHouseError::TableNotFound {
-                database: database.to_string(),
-                table: table.to_string(),
-            });
+        i += 1;
+    }
+    
+    Vec::new()
+}
+
+fn parse_settings_list(mut rest: &str) -> Vec<(String, String)> {
+    let mut settings = Vec::new();
+    
+    loop {
+        let trimmed = rest.trim_start();
+        let name_len = trimmed.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_')).unwrap_or(trimmed.len());
+        let (name, after_name) = trimmed.split_at(name_len);
+        let Some(after_eq) = after_name.trim_start().strip_prefix('=') else {
+            return settings;
+        };
+        if name.is_empty() {
+            return settings;
         }
         
-        debug!("Found {} columns in table '{}.{}'", columns.len(), database, table);
-        Ok(columns)
+        let value_start = after_eq.trim_start();
+        let (value, after_value) = if let Some(quoted) = value_start.strip_prefix('\'') {
+            let Some(end) = quoted.find('\'') else {
+                return settings;
+            };
+            (quoted[..end].to_string(), &quoted[end + 1..])
+        } else {
+            let end = value_start.find(|c: char| c == ',' || c.is_whitespace()).unwrap_or(value_start.len());
+            (value_start[..end].to_string(), &value_start[end..])
+        };
+        settings.push((name.to_string(), value));
+        
+        match after_value.trim_start().strip_prefix(',') {
+            Some(next) => rest = next,
+            None => return settings,
+        }
+    }
+}
+
+fn parse_projection_clause(rest: &str) -> Option<(ProjectionInfo, usize)> {
+    let trimmed = rest.trim_start();
+    if trimmed.len() == rest.len() {
+        return None;
+    }
+    let mut offset = rest.len() - trimmed.len();
+    
+    let (name, name_len) = if let Some(stripped) = trimmed.strip_prefix('`') {
+        let end = stripped.find('`')?;
+        (stripped[..end].to_string(), end + 2)
+    } else {
+        let end = trimmed
+            .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+            .unwrap_or(trimmed.len());
+        (trimmed[..end].to_string(), end)
+    };
+    if name.is_empty() {
+        return None;
+    }
+    offset += name_len;
+    
+    let after_name = &rest[offset..];
+    let body_start = after_name.len() - after_name.trim_start().len();
+    if !after_name[body_start..].starts_with('(') {
+        return None;
+    }
+    offset += body_start;
+    
+    let body = &rest[offset..];
+    let mut depth = 0;
+    let mut quote: Option<char> = None;
+    let mut escaped = false;
+    for (pos, c) in body.char_indices() {
+        if let Some(q) = quote {
+            if escaped {
+                escaped = false;
+            } else if c == '\\' {
+                escaped = true;
+            } else if c == q {
+                quote = None;
+            }
+            continue;
+        }
+        match c {
+            '\'' | '`' | '"' => quote = Some(c),
+            '(' => depth += 1,
+            ')' => {
+                depth -= 1;
+                if depth == 0 {
+                    let query = body[1..pos].trim().to_string();
+                    return Some((ProjectionInfo { name, query }, offset + pos + 1));
+                }
+            }
+            _ => {}
+        }
+    }
+    
+    None
+}
+
+/// Masks the password in a URL's user info and in a `password` query
+/// parameter so the URL can be logged.
+pub fn redact_url(url: &str) -> String {
+    let mut redacted = url.to_string();
+    
+    let authority_start = redacted.find("://").map(|i| i + 3).unwrap_or(0);
+    let authority_end = redacted[authority_start..]
+        .find(['/', '?', '#'])
+        .map(|i| authority_start + i)
+        .unwrap_or(redacted.len());
+    if let Some(at) = redacted[authority_start..authority_end].rfind('@') {
+        let user_info = &redacted[authority_start..authority_start + at];
+        if let Some(colon) = user_info.find(':') {
+            redacted.replace_range(authority_start + colon + 1..authority_start + at, "***");
+        }
+    }
+    
+    if let Some(query_start) = redacted.find('?') {
+        let (base, query) = redacted.split_at(query_start + 1);
+        let query = query
+            .split('&')
+            .map(|pair| match pair.split_once('=') {
+                Some((key, _)) if key.eq_ignore_ascii_case("password") => format!("{}=***", key),
+                _ => pair.to_string(),
+            })
+            .collect::<Vec<_>>()
+            .join("&");
+        redacted = format!("{}{}", base, query);
+    }
+    
+    redacted
+}
+
+/// Whether `c`, a non-ASCII character, may appear in identifiers. Letters
+/// of any script, emoji and their joiners are accepted, since names are
+/// always bound or quoted; whitespace, control characters, zero-width
+/// spaces and the bidirectional formatting characters that make a name
+/// display differently from what it is are not. Names are compared as given, never normalized.
+pub fn is_identifier_unicode(c: char) -> bool {
+    !c.is_ascii()
+        && !c.is_whitespace()
+        && !c.is_control()
+        && !matches!(c, '\u{200B}' | '\u{2060}' | '\u{200E}' | '\u{200F}' | '\u{202A}'..='\u{202E}' | '\u{2066}'..='\u{2069}' | '\u{FEFF}')
+}
+
+/// Quotes an identifier with backticks so it can be interpolated into SQL.
+pub fn quote_identifier(identifier: &str) -> String {
+    format!("`{}`", identifier.replace('\\', "\\\\").replace('`', "\\`"))
+}
+
+/// Strips `Nullable(...)` and `LowCardinality(...)` wrappers from a type name.
+pub fn unwrap_type_modifiers(type_name: &str) -> &str {
+    let mut inner = type_name.trim();
+    loop {
+        let stripped = ["Nullable(", "LowCardinality("]
+            .iter()
+            .find_map(|prefix| inner.strip_prefix(prefix).and_then(|rest| rest.strip_suffix(')')));
+        match stripped {
+            Some(rest) => inner = rest.trim(),
+            None => return inner,
+        }
+    }
+}
+
+/// A type name split by [`parse_type`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParsedType<'a> {
+    /// The name without parameters, e.g. `Decimal` for `Decimal(18, 2)`.
+    pub name: &'a str,
+    /// The top-level parameters as written, trimmed: string literals keep
+    /// their quotes and nested types their parameters, see
+    /// [`parse_string_literal`]. Empty without parentheses.
+    pub parameters: Vec<&'a str>,
+}
+
+/// Splits a type name such as `DateTime64(3, 'UTC')` into its name and
+/// top-level parameters. Commas and parentheses inside string literals,
+/// with their `\'` and `''` escapes, and inside nested types don't split.
+/// Wrappers such as `Nullable` are parsed like any other type; see
+/// [`unwrap_type_modifiers`]. `None` for malformed types: unbalanced
+/// parentheses or quotes, or text after the closing parenthesis.
+pub fn parse_type(type_name: &str) -> Option<ParsedType<'_>> {
+    let type_name = type_name.trim();
+    let Some(open) = type_name.find('(') else {
+        return Some(ParsedType { name: type_name, parameters: Vec::new() });
+    };
+    let body = &type_name[open + 1..];
+
+    let mut parameters = Vec::new();
+    let (mut depth, mut quoted, mut escaped, mut start) = (0usize, false, false, 0);
+    for (i, c) in body.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '\'' => quoted = !quoted,
+            _ if quoted => {}
+            '(' => depth += 1,
+            ',' if depth == 0 => {
+                parameters.push(body[start..i].trim());
+                start = i + 1;
+            }
+            ')' if depth == 0 => {
+                if !body[i + 1..].trim().is_empty() {
+                    return None;
+                }
+                let last = body[start..i].trim();
+                if !last.is_empty() || !parameters.is_empty() {
+                    parameters.push(last);
+                }
+                return Some(ParsedType { name: type_name[..open].trim(), parameters });
+            }
+            ')' => depth -= 1,
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Reads the string literal `text` starts with, unescaping `\'`, `''` and
+/// the other backslash escapes, and returns it with the rest of `text`.
+fn split_string_literal(text: &str) -> Option<(String, &str)> {
+    let body = text.trim_start().strip_prefix('\'')?;
+    let mut chars = body.char_indices().peekable();
+    let mut value = String::new();
+    loop {
+        match chars.next()? {
+            (_, '\\') => value.push(match chars.next()?.1 {
+                'n' => '\n',
+                't' => '\t',
+                'r' => '\r',
+                '0' => '\0',
+                other => other,
+            }),
+            (_, '\'') if chars.next_if(|(_, c)| *c == '\'').is_some() => value.push('\''),
+            (i, '\'') => return Some((value, &body[i + 1..])),
+            (_, c) => value.push(c),
+        }
+    }
+}
+
+/// The value of a type parameter that is one string literal, such as
+/// `'Europe/Stockholm'` in `DateTime('Europe/Stockholm')`, unescaped.
+/// `None` for other parameters.
+pub fn parse_string_literal(parameter: &str) -> Option<String> {
+    let (value, rest) = split_string_literal(parameter)?;
+    rest.trim().is_empty().then_some(value)
+}
+
+/// The names and values of an `Enum8(...)` or `Enum16(...)` type, possibly
+/// wrapped in `Nullable`/`LowCardinality`, in declaration order. Names are
+/// unescaped (`\'` and `''` become `'`). `None` for other types and for
+/// declarations that don't parse.
+pub fn parse_enum_values(type_name: &str) -> Option<Vec<(String, i16)>> {
+    let parsed = parse_type(unwrap_type_modifiers(type_name))?;
+    if !matches!(parsed.name, "Enum8" | "Enum16") || parsed.parameters.is_empty() {
+        return None;
+    }
+    parsed
+        .parameters
+        .iter()
+        .map(|parameter| {
+            let (name, rest) = split_string_literal(parameter)?;
+            let value = rest.trim().strip_prefix('=')?.trim().parse::<i16>().ok()?;
+            Some((name, value))
+        })
+        .collect()
+}
+
+/// A `DateTime` or `DateTime64` type, see [`parse_datetime_type`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DateTimeType {
+    /// The digits after the second of a `DateTime64`; `None` for `DateTime`.
+    pub precision: Option<u8>,
+    /// The time zone the type names, e.g. `Europe/Stockholm` for
+    /// `DateTime64(3, 'Europe/Stockholm')`. Without one, values are shown in
+    /// the session's time zone, the server's by default.
+    pub timezone: Option<String>,
+}
+
+/// Parses a `DateTime`, `DateTime('tz')`, `DateTime64(p)` or
+/// `DateTime64(p, 'tz')` type, possibly wrapped in `Nullable`/
+/// `LowCardinality`. `None` for other types, `Date` and `Date32` included.
+pub fn parse_datetime_type(type_name: &str) -> Option<DateTimeType> {
+    let parsed = parse_type(unwrap_type_modifiers(type_name))?;
+    let (precision, timezone) = match (parsed.name, parsed.parameters.as_slice()) {
+        ("DateTime", []) => (None, None),
+        ("DateTime", [timezone]) => (None, Some(*timezone)),
+        ("DateTime64", [precision]) => (Some(*precision), None),
+        ("DateTime64", [precision, timezone]) => (Some(*precision), Some(*timezone)),
+        _ => return None,
+    };
+    let precision = match precision {
+        Some(precision) => Some(precision.parse::<u8>().ok()?),
+        None => None,
+    };
+    let timezone = match timezone {
+        Some(timezone) => Some(parse_string_literal(timezone)?),
+        None => None,
+    };
+    Some(DateTimeType { precision, timezone })
+}
+
+/// A SQL expression rendering the `DateTime` `expression` as ISO-8601 in
+/// UTC with a `Z`, e.g. `2024-05-01T12:00:00Z`, whatever the server's or the
+/// column's time zone.
+pub fn utc_timestamp_sql(expression: &str) -> String {
+    format!("concat(replaceOne(toString({}, 'UTC'), ' ', 'T'), 'Z')", expression)
+}
+
+/// Whether a column of this type can hold NULL.
+pub fn is_nullable_type(type_name: &str) -> bool {
+    type_name.starts_with("Nullable(") || type_name.starts_with("LowCardinality(Nullable(")
+}
+
+pub fn is_numeric_type(type_name: &str) -> bool {
+    let base = unwrap_type_modifiers(type_name);
+    // Require the width digits so e.g. `IntervalDay` isn't treated as `Int*`
+    let sized = |prefix: &str| {
+        base.strip_prefix(prefix)
+            .is_some_and(|rest| rest.starts_with(|c: char| c.is_ascii_digit()))
+    };
+    sized("Int") || sized("UInt") || sized("Float") || base.starts_with("Decimal")
+}
+
+pub fn build_histogram_query(
+    database: &str,
+    table: &str,
+    column: &str,
+    buckets: u32,
+    sample_ratio: Option<f64>,
+) -> String {
+    let mut sql = format!(
+        "SELECT histogram({})(toFloat64({})) FROM {}.{}",
+        buckets,
+        quote_identifier(column),
+        quote_identifier(database),
+        quote_identifier(table)
+    );
+    if let Some(ratio) = sample_ratio {
+        sql.push_str(&format!(" SAMPLE {}", ratio));
+    }
+    sql
+}
+
+/// Builds the query [`ClickHouseClient::execute_select`] sends for `sql`:
+/// the statement as a subquery, each row formatted as JSON and limited to
+/// one row past `max_rows`.
+pub fn build_select_query(sql: &str, limits: &SelectLimits) -> Result<String, ClickHouseError> {
+    if limits.max_rows == 0 {
+        return Err(ClickHouseError::InvalidArgument {
+            message: "max_rows must be at least 1".to_string(),
+        });
+    }
+    
+    let sql = sql::prepare_select(sql)?;
+    Ok(format!(
+        "SELECT formatRowNoNewline('JSONEachRow', *) FROM ({}) LIMIT {}",
+        sql,
+        limits.max_rows.saturating_add(1)
+    ))
+}
+
+fn parse_json_rows(lines: &[String]) -> Result<Vec<serde_json::Value>, ClickHouseError> {
+    lines
+        .iter()
+        .map(|line| serde_json::from_str(line))
+        .collect::<Result<Vec<serde_json::Value>, _>>()
+        .map_err(|e| ClickHouseError::InternalError {
+            message: format!("ClickHouse returned invalid JSON row: {}", e),
+        })
+}
+
+/// Builds the single query behind [`ClickHouseClient::table_quality_check`],
+/// returning `(rows, distinct keys, [nulls per column], min date, max date)`.
+pub fn build_quality_check_query(
+    database: &str,
+    table: &str,
+    key_columns: &[&str],
+    nullable_columns: &[&str],
+    date_column: Option<&str>,
+    sample_ratio: Option<f64>,
+) -> String {
+    let quote_all = |columns: &[&str]| columns.iter().map(|c| quote_identifier(c)).collect::<Vec<_>>();
+    
+    let distinct = if key_columns.is_empty() {
+        "toUInt64(0)".to_string()
+    } else {
+        format!("uniq({})", quote_all(key_columns).join(", "))
+    };
+    let nulls = quote_all(nullable_columns)
+        .iter()
+        .map(|c| format!("countIf(isNull({}))", c))
+        .collect::<Vec<_>>();
+    let nulls = format!("CAST([{}] AS Array(UInt64))", nulls.join(", "));
+    let (min_date, max_date) = match date_column {
+        Some(column) => (
+            format!("toString(min({}))", quote_identifier(column)),
+            format!("toString(max({}))", quote_identifier(column)),
+        ),
+        None => ("''".to_string(), "''".to_string()),
+    };
+    
+    let mut sql = format!(
+        "SELECT count() AS total_rows, {} AS distinct_keys, {} AS null_counts, {} AS min_date, {} AS max_date FROM {}.{}",
+        distinct,
+        nulls,
+        min_date,
+        max_date,
+        quote_identifier(database),
+        quote_identifier(table)
+    );
+    if let Some(ratio) = sample_ratio {
+        sql.push_str(&format!(" SAMPLE {}", ratio));
+    }
+    sql
+}
+
+/// HTTP statuses worth retrying: rate limiting, and server or proxy
+/// failures that are usually transient (e.g. 503 while overloaded).
+pub fn is_retryable_status(status: u16) -> bool {
+    matches!(status, 429 | 500 | 502 | 503 | 504)
+}
+
+/// Finds the HTTP status in a `BadResponse` message. The driver reports
+/// `503 Service Unavailable` when it can't read the body, and proxies in
+/// front of ClickHouse answer with a bare reason phrase or an HTML page
+/// titled with the status. ClickHouse's own exceptions carry none.
+pub fn http_status_from_message(message: &str) -> Option<u16> {
+    const REASONS: &[(u16, &str)] = &[
+        (400, "Bad Request"),
+        (401, "Unauthorized"),
+        (403, "Forbidden"),
+        (404, "Not Found"),
+        (429, "Too Many Requests"),
+        (500, "Internal Server Error"),
+        (502, "Bad Gateway"),
+        (503, "Service Unavailable"),
+        (504, "Gateway Timeout"),
+    ];
+    
+    let message = message.trim();
+    let text = match message.find("<title>") {
+        Some(start) => &message[start + "<title>".len()..],
+        None => message,
+    };
+    
+    let digits = text.chars().take_while(|c| c.is_ascii_digit()).count();
+    let followed_by_reason = text[digits..].is_empty() || text[digits..].starts_with(' ');
+    if digits == 3 && followed_by_reason {
+        return text[..3].parse().ok().filter(|status| (100..600).contains(status));
+    }
+    
+    REASONS.iter().find(|(_, reason)| message.eq_ignore_ascii_case(reason)).map(|(status, _)| *status)
+}
+
+/// Finds a `Retry-After` in a `BadResponse` message, as delay seconds or an
+/// HTTP date (a date in the past means right away). This is a best effort:
+/// the driver neither exposes response headers nor lets its HTTP client be
+/// wrapped (`HttpClient` is sealed), so a `Retry-After` is only seen when a
+/// proxy repeats it in the body of its 429 response.
+pub fn retry_after_from_message(message: &str) -> Option<Duration> {
+    const FIELD: &str = "retry-after:";
+    
+    let start = message.to_ascii_lowercase().find(FIELD)? + FIELD.len();
+    let value = message[start..].lines().next()?.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    Some((date.with_timezone(&chrono::Utc) - chrono::Utc::now()).to_std().unwrap_or(Duration::ZERO))
+}
+
+/// The names equal to `name` ignoring the case of ASCII letters, sorted.
+/// Other letters must match exactly, as with ClickHouse's `lower()`.
+fn matching_ignoring_case(mut names: Vec<String>, name: &str) -> Vec<String> {
+    names.retain(|candidate| candidate.eq_ignore_ascii_case(name));
+    names.sort();
+    names
+}
+
+fn find_column<'a>(
+    columns: &'a [ColumnInfo],
+    database: &str,
+    table: &str,
+    column: &str,
+) -> Result<&'a ColumnInfo, ClickHouseError> {
+    columns.iter().find(|c| c.name == column).ok_or_else(|| ClickHouseError::ColumnNotFound {
+        database: database.to_string(),
+        table: table.to_string(),
+        column: column.to_string(),
+    })
+}
+
+/// The column `time_column` of `database.table`, which must be a Date or
+/// DateTime column; otherwise the error names the columns that are.
+fn find_time_column<'a>(
+    columns: &'a [ColumnInfo],
+    database: &str,
+    table: &str,
+    time_column: &str,
+) -> Result<&'a ColumnInfo, ClickHouseError> {
+    let column_info = find_column(columns, database, table, time_column)?;
+    if !unwrap_type_modifiers(&column_info.r#type).starts_with("Date") {
+        let candidates: Vec<&str> = columns
+            .iter()
+            .filter(|c| unwrap_type_modifiers(&c.r#type).starts_with("Date"))
+            .map(|c| c.name.as_str())
+            .collect();
+        return Err(ClickHouseError::InvalidArgument {
+            message: format!(
+                "column '{}' has type {}, expected a Date or DateTime column; candidates are: {}",
+                time_column,
+                column_info.r#type,
+                if candidates.is_empty() { "(none)".to_string() } else { candidates.join(", ") }
+            ),
+        });
+    }
+    Ok(column_info)
+}
+
+/// Builds the `get_table_schemas` query for `count` bound `(database, table)`
+/// pairs. The `database.table` key gets its own alias: one named `table`
+/// would replace the column in the `WHERE` clause.
+pub fn build_table_schemas_query(count: usize) -> String {
+    format!(
+        "SELECT concat(database, '.', table) AS qualified_name, name, type, default_kind as default_type, default_expression, comment, is_in_partition_key, is_in_sorting_key, is_in_primary_key, is_in_sampling_key FROM system.columns WHERE (database, table) IN ({}) ORDER BY database, table, position",
+        vec!["?"; count].join(", ")
+    )
+}
+
+/// Builds the `sample_rows` query, formatting the first `limit` rows of
+/// `columns` as one string each: a JSON object, or a CSV line ending in a
+/// newline.
+pub fn build_sample_rows_query(database: &str, table: &str, columns: &[&str], limit: u64, format: ResponseFormat) -> String {
+    let columns = columns.iter().map(|c| quote_identifier(c)).collect::<Vec<_>>().join(", ");
+    let function = match format {
+        ResponseFormat::Json => "formatRowNoNewline('JSONEachRow'",
+        ResponseFormat::Csv => "formatRow('CSV'",
+    };
+    format!(
+        "SELECT {}, {}) FROM {}.{} LIMIT {}",
+        function,
+        columns,
+        quote_identifier(database),
+        quote_identifier(table),
+        limit
+    )
+}
+
+/// Builds the query evaluating a default expression, checked by
+/// [`sql::check_preview_expression`], for a row of `(database, table,
+/// columns)`, or on its own without a table. The row's columns are listed
+/// by name, as `*` leaves out MATERIALIZED and ALIAS columns.
+pub fn build_default_preview_query(table: Option<(&str, &str, &[&str])>, expression: &str) -> String {
+    let value = format!("SELECT toNullable(toString({})) AS value", expression);
+    match table {
+        Some((database, table, columns)) => format!(
+            "{} FROM (SELECT {} FROM {}.{} LIMIT 1) LIMIT 1",
+            value,
+            columns.iter().map(|column| quote_identifier(column)).collect::<Vec<_>>().join(", "),
+            quote_identifier(database),
+            quote_identifier(table)
+        ),
+        None => value,
+    }
+}
+
+/// Builds the `poll_new_rows` query, returning each row as its time column
+/// and a JSON line. The lower bound is bound as a parameter.
+pub fn build_poll_query(database: &str, table: &str, time_column: &str, limit: u64) -> String {
+    let time_column = quote_identifier(time_column);
+    format!(
+        "SELECT toString({column}), formatRowNoNewline('JSONEachRow', *) FROM {}.{} WHERE {column} > ? ORDER BY {column} LIMIT {}",
+        quote_identifier(database),
+        quote_identifier(table),
+        limit,
+        column = time_column
+    )
+}
+
+/// Builds the `row_counts_over_time` query. Missing buckets are zero-filled
+/// by ClickHouse through `ORDER BY ... WITH FILL`.
+pub fn build_row_counts_query(
+    database: &str,
+    table: &str,
+    time_column: &str,
+    interval: TimeInterval,
+    last_n: Option<u32>,
+) -> String {
+    let unit = interval.sql_unit();
+    let bucket = format!("toStartOfInterval(toDateTime({}), INTERVAL 1 {})", quote_identifier(time_column), unit);
+    let current = format!("toStartOfInterval(now(), INTERVAL 1 {})", unit);
+    
+    let mut inner = format!(
+        "SELECT {} AS bucket, count() AS count FROM {}.{}",
+        bucket,
+        quote_identifier(database),
+        quote_identifier(table)
+    );
+    let fill = match last_n {
+        Some(n) => {
+            let start = format!("{} - INTERVAL {} {}", current, n - 1, unit);
+            inner.push_str(&format!(" WHERE bucket >= {}", start));
+            format!(" WITH FILL FROM {} TO {} + INTERVAL 1 {} STEP INTERVAL 1 {}", start, current, unit, unit)
+        }
+        None => format!(" WITH FILL STEP INTERVAL 1 {}", unit),
+    };
+    inner.push_str(&format!(" GROUP BY bucket ORDER BY bucket{}", fill));
+    
+    format!("SELECT toString(bucket), count FROM ({}) ORDER BY bucket", inner)
+}
+
+/// The `FROM` and, with a time column, `WHERE` clause shared by the
+/// group count queries. The day count is left as a `?` to bind.
+fn group_count_source(database: &str, table: &str, time_column: Option<&str>) -> String {
+    let mut source = format!("FROM {}.{}", quote_identifier(database), quote_identifier(table));
+    if let Some(time_column) = time_column {
+        source.push_str(&format!(
+            " WHERE toDateTime({}) >= now() - toIntervalDay(?)",
+            quote_identifier(time_column)
+        ));
     }
-}
\ No newline at end of file
+    source
+}
+
+/// Builds the query counting active parts per table for
+/// [`ClickHouseClient::insert_pressure`], restricted by `filter`, a list of
+/// `AND` conditions. Across a cluster, `system.parts` of every replica is
+/// read with `clusterAllReplicas()`, whose cluster name is the first
+/// placeholder. Parts are counted per server and partition, so the fullest
+/// partition is that of a single replica.
+pub fn build_part_counts_query(across_cluster: bool, filter: &str) -> String {
+    let source = if across_cluster { "clusterAllReplicas(?, system.parts)" } else { "system.parts" };
+    format!(
+        "SELECT database, table, sum(parts) AS active_parts, max(parts) AS max_partition_parts \
+         FROM (SELECT database, table, partition_id, count() AS parts FROM {} WHERE active {}GROUP BY hostName(), database, table, partition_id) \
+         GROUP BY database, table ORDER BY max_partition_parts DESC, database, table LIMIT {}",
+        source, filter, MAX_PRESSURE_TABLES
+    )
+}
+
+/// Builds the query [`ClickHouseClient::recommend_ordering`] measures
+/// `columns` with: the row count and an array of `uniq()` estimates in the
+/// order of `columns`.
+pub fn build_ordering_stats_query(database: &str, table: &str, columns: &[&str]) -> String {
+    let distinct = if columns.is_empty() {
+        "CAST([] AS Array(UInt64))".to_string()
+    } else {
+        let uniqs: Vec<String> = columns.iter().map(|c| format!("uniq({})", quote_identifier(c))).collect();
+        format!("[{}]", uniqs.join(", "))
+    };
+    format!(
+        "SELECT count() AS rows, {} AS distinct FROM {}.{}",
+        distinct,
+        quote_identifier(database),
+        quote_identifier(table)
+    )
+}
+
+/// Builds the query counting the rows and estimating the distinct values
+/// of `column` before grouping by it.
+pub fn build_group_count_probe_query(database: &str, table: &str, column: &str, time_column: Option<&str>) -> String {
+    format!(
+        "SELECT count(), uniq({}) {}",
+        quote_identifier(column),
+        group_count_source(database, table, time_column)
+    )
+}
+
+/// Builds the query for [`ClickHouseClient::recently_modified_tables`]. Its
+/// one placeholder is the database, or with `in_database` unset, the list of
+/// databases to leave out.
+pub fn build_recently_modified_query(in_database: bool, limit: u32) -> String {
+    let filter = if in_database { "database = ?" } else { "NOT has(?, database)" };
+    format!(
+        "SELECT database, table, {} AS last_modified FROM system.parts \
+         WHERE active AND {} GROUP BY database, table ORDER BY max(modification_time) DESC, database, table LIMIT {}",
+        utc_timestamp_sql("max(modification_time)"), filter, limit
+    )
+}
+
+/// Builds the query behind [`ClickHouseClient::aggregate`]: one JSON object
+/// with each function's result under its name. Functions must come from
+/// [`AGGREGATE_FUNCTIONS`].
+pub fn build_aggregate_query(database: &str, table: &str, column: &str, functions: &[&str]) -> String {
+    let column = quote_identifier(column);
+    let aggregates: Vec<String> = functions
+        .iter()
+        .map(|function| format!("{}({}) AS {}{}", function, column, AGGREGATE_ALIAS_PREFIX, function))
+        .collect();
+    format!(
+        "SELECT formatRowNoNewline('JSONEachRow', {}) FROM {}.{}",
+        aggregates.join(", "),
+        quote_identifier(database),
+        quote_identifier(table)
+    )
+}
+
+/// Builds the query for the `limit` most frequent values of `column`, as
+/// nullable strings with their row counts.
+pub fn build_group_count_query(
+    database: &str,
+    table: &str,
+    column: &str,
+    limit: u32,
+    time_column: Option<&str>,
+) -> String {
+    let column = quote_identifier(column);
+    format!(
+        "SELECT toNullable(toString({column})) AS value, count() AS count {} GROUP BY {column} ORDER BY count DESC, value LIMIT {}",
+        group_count_source(database, table, time_column),
+        limit
+    )
+}
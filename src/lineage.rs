@@ -0,0 +1,628 @@
+//! Column lineage through materialized views. A materialized view `TO` a
+//! table inserts the result of its SELECT by column name, so each column of
+//! the target comes from the SELECT item of the same name. The SELECT is read
+//! with a pragmatic parser rather than ClickHouse's own: items and tables it
+//! can't pin down are reported as unresolved instead of guessed.
+
+use crate::distributed::unquote;
+use serde::Serialize;
+use std::collections::BTreeSet;
+
+/// How a target column was traced back to its source.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SourceKind {
+    /// A column of the source table, copied as is.
+    Column,
+    /// Computed by an expression, e.g. an aggregate state or `if()`.
+    Expression,
+    /// The parser couldn't tell where the values come from, see
+    /// [`ColumnSource::reason`].
+    Unresolved,
+}
+
+/// One item of a SELECT list.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SelectItem {
+    /// The name of the result column: the alias, the name of a plain column
+    /// reference, or else the expression itself.
+    pub name: String,
+    pub expression: String,
+}
+
+/// A table in the FROM and JOIN clauses of a view's SELECT.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SourceTable {
+    /// As `database.table`; `None` for a subquery or table function.
+    pub table: Option<String>,
+    pub alias: Option<String>,
+}
+
+/// What lineage needs from the definition of a materialized view.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct MaterializedView {
+    /// As `database.view`.
+    pub name: String,
+    /// The table of the `TO` clause as `database.table`; `None` when the view
+    /// stores its data itself.
+    pub target: Option<String>,
+    pub sources: Vec<SourceTable>,
+    pub items: Vec<SelectItem>,
+}
+
+/// Where one view gets the values of a target column from.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ColumnSource {
+    /// The view, as `database.view`.
+    pub view: String,
+    pub kind: SourceKind,
+    /// The SELECT expression of the column, if it is in the SELECT list.
+    pub expression: Option<String>,
+    /// The table read, when the values come from a single one.
+    pub source_table: Option<String>,
+    /// The columns the expression reads, as `database.table.column`.
+    pub source_columns: Vec<String>,
+    /// Why the source couldn't be resolved.
+    pub reason: Option<String>,
+}
+
+/// The sources of one target column, one per view writing into the table.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ColumnLineage {
+    pub column: String,
+    pub sources: Vec<ColumnSource>,
+}
+
+/// Words ending the FROM clause, or the SELECT list of a query without one.
+const CLAUSE_ENDS: &[&str] = &[
+    "WHERE", "PREWHERE", "GROUP", "ORDER", "LIMIT", "HAVING", "SETTINGS", "UNION", "WINDOW", "QUALIFY", "FORMAT",
+    "INTERSECT", "EXCEPT",
+];
+
+/// Words that may follow a table in the FROM clause and so aren't an alias.
+const TABLE_KEYWORDS: &[&str] = &[
+    "FINAL", "SAMPLE", "JOIN", "LEFT", "RIGHT", "INNER", "OUTER", "FULL", "CROSS", "ANY", "ALL", "ASOF", "SEMI",
+    "ANTI", "GLOBAL", "ARRAY", "ON", "USING", "PASTE",
+];
+
+/// Bare words in expressions that aren't columns.
+const EXPRESSION_KEYWORDS: &[&str] = &[
+    "AND", "OR", "NOT", "IN", "IS", "NULL", "CASE", "WHEN", "THEN", "ELSE", "END", "LIKE", "ILIKE", "BETWEEN",
+    "DISTINCT", "TRUE", "FALSE", "INTERVAL", "GLOBAL", "INF", "NAN",
+];
+
+/// Parses the `create_table_query` of the materialized view
+/// `database.name`: the `TO` table, the tables read and the SELECT list.
+/// Unqualified tables resolve to `database`. Returns `None` if there is no
+/// `AS SELECT` to read.
+pub fn parse_materialized_view(database: &str, name: &str, create_query: &str) -> Option<MaterializedView> {
+    let words = top_level_words(create_query);
+    let query_word = words
+        .windows(2)
+        .position(|pair| is_keyword(pair[0].1, "AS") && (is_keyword(pair[1].1, "SELECT") || is_keyword(pair[1].1, "WITH")))?
+        + 1;
+
+    let target = words[..query_word]
+        .iter()
+        .find(|(_, word)| is_keyword(word, "TO"))
+        .and_then(|(at, word)| parse_name(&create_query[at + word.len()..]))
+        .and_then(|(parts, _)| qualify(&parts, database));
+
+    let query = &create_query[words[query_word].0..];
+    let words = top_level_words(query);
+    // A WITH clause comes first; its subqueries are in parentheses
+    let select = words.iter().position(|(_, word)| is_keyword(word, "SELECT"))?;
+    let mut list_start = words[select].0 + words[select].1.len();
+    if words.get(select + 1).is_some_and(|(_, word)| is_keyword(word, "DISTINCT")) {
+        list_start = words[select + 1].0 + words[select + 1].1.len();
+    }
+
+    let end_of = |from: usize| {
+        words[from..]
+            .iter()
+            .find(|(_, word)| CLAUSE_ENDS.iter().any(|end| is_keyword(word, end)))
+            .map_or(query.len(), |&(at, _)| at)
+    };
+    let from = words[select..].iter().position(|(_, word)| is_keyword(word, "FROM")).map(|i| i + select);
+    let (list_end, sources) = match from {
+        Some(from) => {
+            let (from_at, word) = words[from];
+            (from_at, parse_sources(&query[from_at + word.len()..end_of(from)], database))
+        }
+        None => (end_of(select), Vec::new()),
+    };
+    let items = split_top_level(&query[list_start..list_end]).into_iter().map(parse_item).collect();
+
+    Some(MaterializedView { name: format!("{}.{}", database, name), target, sources, items })
+}
+
+/// Traces each of `columns` of a table back through the `views` writing into
+/// it.
+pub fn column_lineage(columns: &[String], views: &[MaterializedView]) -> Vec<ColumnLineage> {
+    columns
+        .iter()
+        .map(|column| ColumnLineage {
+            column: column.clone(),
+            sources: views.iter().map(|view| trace(view, column)).collect(),
+        })
+        .collect()
+}
+
+fn trace(view: &MaterializedView, column: &str) -> ColumnSource {
+    let unresolved = |expression: Option<&str>, reason: String| ColumnSource {
+        view: view.name.clone(),
+        kind: SourceKind::Unresolved,
+        expression: expression.map(str::to_string),
+        source_table: None,
+        source_columns: Vec::new(),
+        reason: Some(reason),
+    };
+
+    let Some(index) = view.items.iter().position(|item| item.name == column) else {
+        if view.items.iter().any(|item| item.expression == "*" || item.expression.ends_with(".*")) {
+            return unresolved(None, format!("selected by `*`, presumably as the column `{}` of the source", column));
+        }
+        return unresolved(None, "not in the SELECT list, so it gets its default value".to_string());
+    };
+    let item = &view.items[index];
+
+    let mut references = Vec::new();
+    item_references(&view.items, index, &mut vec![index], &mut references);
+
+    let mut source_columns = Vec::new();
+    let mut tables = BTreeSet::new();
+    for (qualifier, name) in references {
+        match resolve_reference(view, qualifier.as_deref(), &name) {
+            Ok((table, name)) => {
+                let source_column = format!("{}.{}", table, name);
+                if !source_columns.contains(&source_column) {
+                    source_columns.push(source_column);
+                }
+                tables.insert(table);
+            }
+            Err(reason) => return unresolved(Some(&item.expression), reason),
+        }
+    }
+    // Constants and aggregates without arguments, e.g. `countState()`, still
+    // read the FROM table
+    if let ([], [SourceTable { table: Some(table), .. }]) = (source_columns.as_slice(), view.sources.as_slice()) {
+        tables.insert(table.clone());
+    }
+
+    // A name that is the alias of another item is computed, not copied
+    let plain = column_reference(&item.expression).is_some_and(|parts| {
+        parts.len() > 1 || !view.items.iter().enumerate().any(|(j, other)| j != index && other.name == parts[0])
+    });
+    ColumnSource {
+        view: view.name.clone(),
+        kind: if plain { SourceKind::Column } else { SourceKind::Expression },
+        expression: Some(item.expression.clone()),
+        source_table: if tables.len() == 1 { tables.pop_first() } else { None },
+        source_columns,
+        reason: None,
+    }
+}
+
+/// The columns read by item `index`, with references to the aliases of other
+/// items replaced by what those read, as ClickHouse substitutes aliases.
+fn item_references(items: &[SelectItem], index: usize, seen: &mut Vec<usize>, out: &mut Vec<(Option<String>, String)>) {
+    for (qualifier, name) in references(&items[index].expression) {
+        let alias = match qualifier {
+            None => items.iter().position(|item| item.name == name).filter(|j| !seen.contains(j)),
+            Some(_) => None,
+        };
+        match alias {
+            Some(j) => {
+                seen.push(j);
+                item_references(items, j, seen, out);
+            }
+            None if !out.contains(&(qualifier.clone(), name.clone())) => out.push((qualifier, name)),
+            None => {}
+        }
+    }
+}
+
+/// Finds the table a column reference reads from, returning the table and
+/// the column name, or why it can't be told.
+fn resolve_reference(view: &MaterializedView, qualifier: Option<&str>, name: &str) -> Result<(String, String), String> {
+    let table_of = |source: &SourceTable| source.table.clone().ok_or_else(|| "reads from a subquery or table function".to_string());
+
+    match qualifier {
+        Some(qualifier) => {
+            let matching = view.sources.iter().find(|source| {
+                source.alias.as_deref() == Some(qualifier)
+                    || source.table.as_deref().is_some_and(|table| {
+                        table == qualifier || table.split_once('.').is_some_and(|(_, table)| table == qualifier)
+                    })
+            });
+            match (matching, view.sources.as_slice()) {
+                (Some(source), _) => Ok((table_of(source)?, name.to_string())),
+                // With a single table, a dotted name is a subcolumn, e.g. of a Nested column
+                (None, [source]) => Ok((table_of(source)?, format!("{}.{}", qualifier, name))),
+                (None, _) => Err(format!("`{}` is not a table of the FROM clause", qualifier)),
+            }
+        }
+        None => match view.sources.as_slice() {
+            [source] => Ok((table_of(source)?, name.to_string())),
+            [] => Err(format!("`{}` isn't read from any table", name)),
+            sources => {
+                let tables: Vec<&str> = sources.iter().map(|source| source.table.as_deref().unwrap_or("a subquery")).collect();
+                Err(format!("can't tell which of the joined tables {} `{}` comes from", tables.join(", "), name))
+            }
+        },
+    }
+}
+
+/// Parses one item of a SELECT list, `expression [AS alias]`.
+fn parse_item(text: &str) -> SelectItem {
+    let alias = top_level_words(text).into_iter().rev().find(|(_, word)| is_keyword(word, "AS"));
+    if let Some((at, word)) = alias {
+        let alias = text[at + word.len()..].trim();
+        let name = match parse_name(alias) {
+            Some((mut parts, rest)) if parts.len() == 1 && rest.trim().is_empty() => parts.remove(0),
+            _ => alias.to_string(),
+        };
+        return SelectItem { name, expression: text[..at].trim().to_string() };
+    }
+
+    let name = column_reference(text).and_then(|mut parts| parts.pop()).unwrap_or_else(|| text.to_string());
+    SelectItem { name, expression: text.to_string() }
+}
+
+/// The tables of a FROM clause (without the `FROM`): the first one and one
+/// per `JOIN`. `ARRAY JOIN` reads no table.
+fn parse_sources(clause: &str, database: &str) -> Vec<SourceTable> {
+    let words = top_level_words(clause);
+    let joins = words.iter().enumerate().filter(|&(i, (_, word))| {
+        is_keyword(word, "JOIN") && !(i > 0 && is_keyword(words[i - 1].1, "ARRAY"))
+    });
+
+    std::iter::once(0)
+        .chain(joins.map(|(_, (at, word))| at + word.len()))
+        .map(|start| parse_source(&clause[start..], database))
+        .collect()
+}
+
+/// Parses the table at the start of `text` and its alias.
+fn parse_source(text: &str, database: &str) -> SourceTable {
+    let text = text.trim_start();
+    let (table, rest) = if text.starts_with('(') {
+        (None, after_group(text))
+    } else {
+        match parse_name(text) {
+            // A table function, e.g. `remote(...)`
+            Some((_, rest)) if rest.trim_start().starts_with('(') => (None, after_group(rest.trim_start())),
+            Some((parts, rest)) => (qualify(&parts, database), rest),
+            None => return SourceTable { table: None, alias: None },
+        }
+    };
+
+    let rest = rest.trim_start();
+    let rest = strip_word(rest, "FINAL").map_or(rest, str::trim_start);
+    let alias = match strip_word(rest, "AS") {
+        Some(after) => parse_name(after),
+        None => parse_name(rest).filter(|_| {
+            // A bare word after the table is an alias unless it is a keyword
+            let word = rest.split(|c: char| !is_ident_char(c)).next().unwrap_or("");
+            rest.starts_with(['`', '"']) || !TABLE_KEYWORDS.iter().chain(CLAUSE_ENDS).any(|keyword| is_keyword(word, keyword))
+        }),
+    };
+    let alias = alias.filter(|(parts, _)| parts.len() == 1).map(|(mut parts, _)| parts.remove(0));
+
+    SourceTable { table, alias }
+}
+
+/// `database.table` from the parts of a table name, with `database` for an
+/// unqualified one.
+fn qualify(parts: &[String], database: &str) -> Option<String> {
+    match parts {
+        [table] => Some(format!("{}.{}", database, table)),
+        [database, table] => Some(format!("{}.{}", database, table)),
+        _ => None,
+    }
+}
+
+/// The parts of `text` if it is nothing but a possibly qualified name.
+fn column_reference(text: &str) -> Option<Vec<String>> {
+    parse_name(text.trim()).filter(|(_, rest)| rest.trim().is_empty()).map(|(parts, _)| parts)
+}
+
+/// Parses a possibly qualified name such as `db.t` or `` `my db`.t `` at the
+/// start of `text`, returning its unquoted parts and the rest of the text.
+fn parse_name(text: &str) -> Option<(Vec<String>, &str)> {
+    let mut parts = Vec::new();
+    let mut rest = text.trim_start();
+    loop {
+        let (part, after) = match rest.chars().next()? {
+            quote @ ('`' | '"') => {
+                let end = closing_quote(rest, quote)?;
+                (unquote(&rest[..=end], quote)?, &rest[end + 1..])
+            }
+            c if is_ident_char(c) => {
+                let end = rest.find(|c: char| !is_ident_char(c)).unwrap_or(rest.len());
+                (rest[..end].to_string(), &rest[end..])
+            }
+            _ => return None,
+        };
+        parts.push(part);
+        match after.strip_prefix('.') {
+            Some(next) => rest = next,
+            None => return Some((parts, after)),
+        }
+    }
+}
+
+/// Tokens of an expression, as far as finding the columns it reads needs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    /// A bare word, which may be a keyword.
+    Word(String),
+    /// A quoted identifier, unquoted.
+    Quoted(String),
+    /// A string or number.
+    Literal,
+    /// `->` of a lambda.
+    Arrow,
+    /// `::` of a cast.
+    Cast,
+    Punct(char),
+}
+
+fn tokenize(expression: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut rest = expression;
+    while let Some(c) = rest.chars().next() {
+        let len = match c {
+            c if c.is_whitespace() => c.len_utf8(),
+            '\'' => {
+                tokens.push(Token::Literal);
+                closing_quote(rest, c).map_or(rest.len(), |end| end + 1)
+            }
+            '`' | '"' => {
+                let len = closing_quote(rest, c).map_or(rest.len(), |end| end + 1);
+                tokens.push(Token::Quoted(unquote(&rest[..len], c).unwrap_or_default()));
+                len
+            }
+            c if c.is_ascii_digit() => {
+                tokens.push(Token::Literal);
+                rest.find(|c: char| !is_ident_char(c) && c != '.').unwrap_or(rest.len())
+            }
+            c if is_ident_char(c) => {
+                let len = rest.find(|c: char| !is_ident_char(c)).unwrap_or(rest.len());
+                tokens.push(Token::Word(rest[..len].to_string()));
+                len
+            }
+            _ if rest.starts_with("->") => {
+                tokens.push(Token::Arrow);
+                2
+            }
+            _ if rest.starts_with("::") => {
+                tokens.push(Token::Cast);
+                2
+            }
+            c => {
+                tokens.push(Token::Punct(c));
+                c.len_utf8()
+            }
+        };
+        rest = &rest[len..];
+    }
+    tokens
+}
+
+fn identifier(token: &Token) -> Option<String> {
+    match token {
+        Token::Word(word) | Token::Quoted(word) => Some(word.clone()),
+        _ => None,
+    }
+}
+
+fn is_word(token: &Token, keyword: &str) -> bool {
+    matches!(token, Token::Word(word) if is_keyword(word, keyword))
+}
+
+/// The columns an expression reads, as an optional qualifier and a name.
+/// Functions, keywords, lambda parameters and type names are skipped.
+fn references(expression: &str) -> Vec<(Option<String>, String)> {
+    let tokens = tokenize(expression);
+    let parameters = lambda_parameters(&tokens);
+    let mut references = Vec::new();
+
+    let mut i = 0;
+    while i < tokens.len() {
+        // The type of `CAST(x AS T)` or `x::T`
+        if tokens[i] == Token::Cast || is_word(&tokens[i], "AS") {
+            i = skip_type(&tokens, i + 1);
+            continue;
+        }
+        let mut parts = match &tokens[i] {
+            Token::Word(word) if EXPRESSION_KEYWORDS.iter().any(|keyword| is_keyword(word, keyword)) => Vec::new(),
+            token => identifier(token).into_iter().collect(),
+        };
+        if parts.is_empty() {
+            i += 1;
+            continue;
+        }
+
+        let mut end = i + 1;
+        while tokens.get(end) == Some(&Token::Punct('.')) {
+            match tokens.get(end + 1).and_then(identifier) {
+                Some(part) => {
+                    parts.push(part);
+                    end += 2;
+                }
+                None => break,
+            }
+        }
+
+        let is_function = tokens.get(end) == Some(&Token::Punct('('));
+        // The unit of `INTERVAL 1 DAY`
+        let is_unit = i >= 2 && is_word(&tokens[i - 2], "INTERVAL") && tokens[i - 1] == Token::Literal;
+        let is_parameter = parts.len() == 1 && parameters.contains(&parts[0]);
+        if !is_function && !is_unit && !is_parameter {
+            let name = parts.pop().unwrap_or_default();
+            let reference = ((!parts.is_empty()).then(|| parts.join(".")), name);
+            if !references.contains(&reference) {
+                references.push(reference);
+            }
+        }
+        i = end;
+    }
+    references
+}
+
+/// The parameters of the lambdas in an expression, `x -> ...` or
+/// `(x, y) -> ...`.
+fn lambda_parameters(tokens: &[Token]) -> BTreeSet<String> {
+    let mut parameters = BTreeSet::new();
+    for (i, _) in tokens.iter().enumerate().filter(|(_, token)| **token == Token::Arrow) {
+        match tokens[..i].last() {
+            Some(Token::Punct(')')) => {
+                let open = tokens[..i].iter().rposition(|token| *token == Token::Punct('(')).unwrap_or(0);
+                parameters.extend(tokens[open..i].iter().filter_map(identifier));
+            }
+            Some(token) => parameters.extend(identifier(token)),
+            None => {}
+        }
+    }
+    parameters
+}
+
+/// Skips the type name starting at `i`, with its parameters if any.
+fn skip_type(tokens: &[Token], i: usize) -> usize {
+    if tokens.get(i).and_then(identifier).is_none() {
+        return i;
+    }
+    if tokens.get(i + 1) != Some(&Token::Punct('(')) {
+        return i + 1;
+    }
+
+    let mut depth = 0;
+    for (j, token) in tokens.iter().enumerate().skip(i + 1) {
+        match token {
+            Token::Punct('(') => depth += 1,
+            Token::Punct(')') => {
+                depth -= 1;
+                if depth == 0 {
+                    return j + 1;
+                }
+            }
+            _ => {}
+        }
+    }
+    tokens.len()
+}
+
+fn is_ident_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+fn is_keyword(word: &str, keyword: &str) -> bool {
+    word.eq_ignore_ascii_case(keyword)
+}
+
+/// The rest of `text` after `word` and a word boundary, if it starts with it.
+fn strip_word<'a>(text: &'a str, word: &str) -> Option<&'a str> {
+    let head = text.get(..word.len())?;
+    let rest = &text[word.len()..];
+    (is_keyword(head, word) && !rest.starts_with(is_ident_char)).then_some(rest)
+}
+
+/// The index of the quote closing the one at the start of `text`, skipping
+/// backslash escapes and doubled quotes.
+fn closing_quote(text: &str, quote: char) -> Option<usize> {
+    let mut chars = text.char_indices().skip(1).peekable();
+    while let Some((i, c)) = chars.next() {
+        if c == '\\' {
+            chars.next();
+        } else if c == quote {
+            if chars.peek().is_some_and(|&(_, next)| next == quote) {
+                chars.next();
+            } else {
+                return Some(i);
+            }
+        }
+    }
+    None
+}
+
+/// The bracket depth at each byte of `sql`, `None` inside quotes. Brackets
+/// are at the depth outside them.
+fn depths(sql: &str) -> Vec<Option<usize>> {
+    let mut depths = vec![None; sql.len()];
+    let mut depth = 0usize;
+    let mut quote: Option<char> = None;
+    let mut escaped = false;
+
+    for (i, c) in sql.char_indices() {
+        if let Some(q) = quote {
+            if escaped {
+                escaped = false;
+            } else if c == '\\' {
+                escaped = true;
+            } else if c == q {
+                quote = None;
+            }
+            continue;
+        }
+        match c {
+            '\'' | '"' | '`' => {
+                quote = Some(c);
+                continue;
+            }
+            ')' | ']' => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+        depths[i] = Some(depth);
+        if matches!(c, '(' | '[') {
+            depth += 1;
+        }
+    }
+    depths
+}
+
+/// The words of `sql` outside quotes and brackets, with their offsets.
+fn top_level_words(sql: &str) -> Vec<(usize, &str)> {
+    let depths = depths(sql);
+    let mut words = Vec::new();
+    let mut start = None;
+    for (i, c) in sql.char_indices() {
+        let in_word = depths[i] == Some(0) && is_ident_char(c);
+        match (in_word, start) {
+            (true, None) => start = Some(i),
+            (false, Some(s)) => {
+                words.push((s, &sql[s..i]));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some(s) = start {
+        words.push((s, &sql[s..]));
+    }
+    words
+}
+
+/// Splits `sql` at commas outside quotes and brackets.
+fn split_top_level(sql: &str) -> Vec<&str> {
+    let depths = depths(sql);
+    let mut parts = Vec::new();
+    let mut start = 0;
+    for (i, c) in sql.char_indices() {
+        if c == ',' && depths[i] == Some(0) {
+            parts.push(sql[start..i].trim());
+            start = i + 1;
+        }
+    }
+    parts.push(sql[start..].trim());
+    parts.retain(|part| !part.is_empty());
+    parts
+}
+
+/// The rest of `text` after the bracketed group it starts with.
+fn after_group(text: &str) -> &str {
+    let depths = depths(text);
+    text.char_indices()
+        .find(|&(i, c)| c == ')' && depths[i] == Some(0))
+        .map_or("", |(i, _)| &text[i + 1..])
+}
@@ -1,430 +1,104 @@
 use anyhow::Result;
-use log::{debug, error, info, warn};
-use mcp_test::{ClickHouseClient, ClickHouseError};
-use serde::{Deserialize, Serialize};
-use serde_json::Value;
-use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader as AsyncBufReader};
+use log::info;
+use mcp_test::cli::{version_string, Command, USAGE};
+use mcp_test::{doctor, is_local_backend, read_transcript, redact_url, replay, ClickHouseConfig, CliOptions, McpServer};
 
-#[derive(Debug, Serialize, Deserialize)]
-struct JsonRpcRequest {
-    jsonrpc: String,
-    method: String,
-    params: Option<Value>,
-    id: Option<Value>,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-struct JsonRpcResponse {
-    jsonrpc: String,
-    result: Option<Value>,
-    error: Option<Value>,
-    id: Option<Value>,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-struct InitializeParams {
-    #[serde(rename = "protocolVersion")]
-    protocol_version: String,
-    capabilities: Value,
-    #[serde(rename = "clientInfo")]
-    client_info: Value,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-struct ToolCallParams {
-    name: String,
-    arguments: Option<Value>,
-}
-
-
-struct McpServer {
-    initialized: bool,
-    clickhouse_client: Option<ClickHouseClient>,
-}
-
-impl McpServer {
-    fn new() -> Self {
-        debug!("Creating new MCP server instance");
-        Self {
-            initialized: false,
-            clickhouse_client: None,
+#[tokio::main]
+async fn main() -> Result<()> {
+    env_logger::init();
+    
+    let options = match CliOptions::parse(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("error: {}\n\nRun with --help for usage.", e);
+            std::process::exit(2);
         }
+    };
+    if options.show_help {
+        print!("{}", USAGE);
+        return Ok(());
     }
-
-    async fn connect_clickhouse(&mut self) -> Result<()> {
-        let url = std::env::var("CLICKHOUSE_URL").unwrap_or_else(|_| "http://localhost:8123".to_string());
-        let database = std::env::var("CLICKHOUSE_DATABASE").unwrap_or_else(|_| "default".to_string());
-        let username = std::env::var("CLICKHOUSE_USERNAME").unwrap_or_else(|_| "default".to_string());
-        let password = std::env::var("CLICKHOUSE_PASSWORD").unwrap_or_else(|_| "".to_string());
-        
-        info!("Connecting to ClickHouse at {} with database {}", url, database);
-        
-        let client = ClickHouseClient::new(&url, &database, &username, &password)
-            .with_retry_config(3, std::time::Duration::from_millis(100));
-        
-        // Perform health check
-        match client.health_check().await {
-            Ok(_) => {
-                info!("ClickHouse connection established successfully");
-                self.clickhouse_client = Some(client);
-                Ok(())
-            }
-            Err(e) => {
-                error!("ClickHouse connection failed: {}", e);
-                Err(anyhow::anyhow!("ClickHouse connection failed: {}", e))
-            }
-        }
+    if options.show_version {
+        println!("{}", version_string());
+        return Ok(());
     }
-
-    async fn handle_request(&mut self, request: JsonRpcRequest) -> Result<JsonRpcResponse> {
-        debug!("Handling request: method={}, id={:?}", request.method, request.id);
-        
-        match request.method.as_str() {
-            "initialize" => self.handle_initialize(request).await,
-            "initialized" => self.handle_initialized(request).await,
-            "tools/list" => self.handle_tools_list(request).await,
-            "tools/call" => self.handle_tools_call(request).await,
-            _ => {
-                warn!("Unknown method requested: {}", request.method);
-                Ok(JsonRpcResponse {
-                    jsonrpc: "2.0".to_string(),
-                    result: None,
-                    error: Some(serde_json::json!({
-                        "code": -32601,
-                        "message": "Method not found"
-                    })),
-                    id: request.id,
-                })
-            }
+    
+    info!("Starting MCP server v{}", env!("CARGO_PKG_VERSION"));
+    
+    let config = ClickHouseConfig::load(&options)?;
+    
+    if options.print_config {
+        for (key, value, source) in config.describe() {
+            println!("{} = {} ({})", key, value, source);
         }
+        for warning in config.warnings() {
+            println!("warning: {}", warning);
+        }
+        return Ok(());
     }
-
-    async fn handle_initialize(&mut self, request: JsonRpcRequest) -> Result<JsonRpcResponse> {
-        info!("Initializing MCP server");
-        
-        let response = JsonRpcResponse {
-            jsonrpc: "2.0".to_string(),
-            result: Some(serde_json::json!({
-                "protocolVersion": "2024-11-05",
-                "capabilities": {
-                    "tools": {
-                        "listChanged": false
-                    },
-                    "resources": {},
-                    "prompts": {}
-                },
-                "serverInfo": {
-                    "name": "mcp-test",
-                    "version": "0.1.0"
-                }
-            })),
-            error: None,
-            id: request.id,
-        };
-        
-        debug!("Sent initialize response");
-        Ok(response)
+    
+    if options.command == Command::Doctor {
+        let report = doctor::run(&config).await;
+        print!("{}", report);
+        std::process::exit(if report.passed() { 0 } else { 1 });
     }
-
-    async fn handle_initialized(&mut self, request: JsonRpcRequest) -> Result<JsonRpcResponse> {
-        self.initialized = true;
-        info!("MCP server initialization completed");
-        
-        if let Err(e) = self.connect_clickhouse().await {
-            warn!("Failed to connect to ClickHouse: {}", e);
-        }
-        
-        Ok(JsonRpcResponse {
-            jsonrpc: "2.0".to_string(),
-            result: Some(serde_json::json!({})),
-            error: None,
-            id: request.id,
-        })
+    
+    // Recorded calls run again, so only against a live server on request
+    if options.command == Command::Replay && !options.against_live && !is_local_backend(&config.url) {
+        return Err(anyhow::anyhow!(
+            "Refusing to replay against {}, which is not on this machine; pass --against-live to replay against it anyway",
+            redact_url(&config.url)
+        ));
     }
-
-    async fn handle_tools_list(&self, request: JsonRpcRequest) -> Result<JsonRpcResponse> {
-        debug!("Listing available tools");
-        
-        let tools = vec![
-            serde_json::json!({
-                "name": "list_databases",
-                "description": "List all databases in the ClickHouse instance",
-                "inputSchema": {
-                    "type": "object",
-                    "properties": {},
-                    "required": []
-                }
-            }),
-            serde_json::json!({
-                "name": "list_tables",
-                "description": "List all tables in a specific database",
-                "inputSchema": {
-                    "type": "object",
-                    "properties": {
-                        "database": {
-                            "type": "string",
-                            "description": "The database name to list tables from"
-                        }
-                    },
-                    "required": ["database"]
-                }
-            }),
-            serde_json::json!({
-                "name": "get_table_schema",
-                "description": "Get the schema (columns) of a specific table",
-                "inputSchema": {
-                    "type": "object",
-                    "properties": {
-                        "database": {
-                            "type": "string",
-                            "description": "The database name"
-                        },
-                        "table": {
-                            "type": "string",
-                            "description": "The table name"
-                        }
-                    },
-                    "required": ["database", "table"]
-                }
-            })
-        ];
-        
-        Ok(JsonRpcResponse {
-            jsonrpc: "2.0".to_string(),
-            result: Some(serde_json::json!({"tools": tools})),
-            error: None,
-            id: request.id,
-        })
+    
+    let mut server = McpServer::new()
+        .with_max_line_length(config.max_line_length)
+        .with_max_structured_bytes(config.max_structured_bytes)
+        .with_reconnect_deadline(config.reconnect_deadline)
+        .with_schema_cache_ttl(config.schema_cache_ttl)
+        .with_result_store(config.result_ttl, config.max_stored_results)
+        .with_max_admin_rows(config.admin_max_rows)
+        .with_max_identifier_length(config.max_identifier_length)
+        .with_user_agent(config.user_agent.clone())
+        .with_verbose_errors(config.verbose_errors)
+        .with_query_comments(config.query_tagging)
+        .with_config(config)
+        .with_writes_enabled(options.allow_writes)
+        .with_ascii_identifiers_only(options.ascii_identifiers_only)
+        .with_admin_enabled(options.enable_admin)
+        .with_hide_system_databases(!options.show_system_databases);
+    if let Some(mode) = options.unavailable_tools {
+        server = server.with_unavailable_tools(mode);
     }
-
-    async fn handle_tools_call(&self, request: JsonRpcRequest) -> Result<JsonRpcResponse> {
-        let params: ToolCallParams = serde_json::from_value(request.params.unwrap_or_default())?;
-        debug!("Calling tool: {}", params.name);
-        
-        let result = match params.name.as_str() {
-            "list_databases" => {
-                self.list_databases().await.map_err(|e| anyhow::anyhow!(e))
-            },
-            "list_tables" => {
-                let args = params.arguments.unwrap_or_default();
-                let database = args.get("database")
-                    .and_then(|v| v.as_str())
-                    .ok_or_else(|| anyhow::anyhow!("Missing database argument"))?;
-                self.list_tables(database).await.map_err(|e| anyhow::anyhow!(e))
-            },
-            "get_table_schema" => {
-                let args = params.arguments.unwrap_or_default();
-                let database = args.get("database")
-                    .and_then(|v| v.as_str())
-                    .ok_or_else(|| anyhow::anyhow!("Missing database argument"))?;
-                let table = args.get("table")
-                    .and_then(|v| v.as_str())
-                    .ok_or_else(|| anyhow::anyhow!("Missing table argument"))?;
-                self.get_table_schema(database, table).await.map_err(|e| anyhow::anyhow!(e))
-            },
-            _ => Err(anyhow::anyhow!("Unknown tool: {}", params.name)),
-        };
-        
-        match result {
-            Ok(content) => Ok(JsonRpcResponse {
-                jsonrpc: "2.0".to_string(),
-                result: Some(serde_json::json!({
-                    "content": [{
-                        "type": "text",
-                        "text": content
-                    }]
-                })),
-                error: None,
-                id: request.id,
-            }),
-            Err(e) => {
-                error!("Tool call '{}' failed: {}", params.name, e);
-                
-                // Determine appropriate error code based on error type
-                let (code, message) = if let Some(clickhouse_error) = e.downcast_ref::<ClickHouseError>() {
-                    match clickhouse_error {
-                        ClickHouseError::InvalidIdentifier { .. } => (-32602, format!("Invalid params: {}", e)),
-                        ClickHouseError::DatabaseNotFound { .. } => (-32600, format!("Database not found: {}", e)),
-                        ClickHouseError::TableNotFound { .. } => (-32600, format!("Table not found: {}", e)),
-                        ClickHouseError::PermissionDenied { .. } => (-32600, format!("Permission denied: {}", e)),
-                        ClickHouseError::ServiceUnavailable { .. } => (-32603, format!("Service unavailable: {}", e)),
-                        ClickHouseError::AuthenticationFailed { .. } => (-32600, format!("Authentication failed: {}", e)),
-                        _ => (-32603, format!("Internal error: {}", e)),
-                    }
-                } else {
-                    (-32603, format!("Tool execution failed: {}", e))
-                };
-                
-                Ok(JsonRpcResponse {
-                    jsonrpc: "2.0".to_string(),
-                    result: None,
-                    error: Some(serde_json::json!({
-                        "code": code,
-                        "message": message
-                    })),
-                    id: request.id,
-                })
-            }
-        }
+    if let Some(compression) = options.ch_compression {
+        server = server.with_compression(compression);
     }
-
-    async fn list_databases(&self) -> Result<String, ClickHouseError> {
-        let client = self.clickhouse_client.as_ref()
-            .ok_or_else(|| ClickHouseError::ServiceUnavailable {
-                message: "ClickHouse client not connected".to_string(),
-            })?;
-        
-        let databases = client.list_databases().await?;
-        
-        let mut result = String::from("Available databases:\n");
-        for db in databases {
-            result.push_str(&format!("- {}\n", db.name));
-        }
-        
-        Ok(result)
+    if let Some(interval) = options.health_interval {
+        server = server.with_health_interval(interval);
     }
-
-    async fn list_tables(&self, database: &str) -> Result<String, ClickHouseError> {
-        let client = self.clickhouse_client.as_ref()
-            .ok_or_else(|| ClickHouseError::ServiceUnavailable {
-                message: "ClickHouse client not connected".to_string(),
-            })?;
-        
-        let tables = client.list_tables(database).await?;
-        
-        let mut result = format!("Tables in database '{}':\n", database);
-        for table in tables {
-            result.push_str(&format!("- {} (Engine: {})\n", table.name, table.engine));
-        }
-        
-        Ok(result)
+    if let Some(interval) = options.keepalive_interval {
+        server = server.with_keepalive_interval(interval);
     }
-
-    async fn get_table_schema(&self, database: &str, table: &str) -> Result<String, ClickHouseError> {
-        let client = self.clickhouse_client.as_ref()
-            .ok_or_else(|| ClickHouseError::ServiceUnavailable {
-                message: "ClickHouse client not connected".to_string(),
-            })?;
-        
-        let columns = client.get_table_schema(database, table).await?;
-        
-        let mut result = format!("Schema for table '{}.{}':\n", database, table);
-        result.push_str("\nColumns:\n");
-        
-        for col in columns {
-            result.push_str(&format!("- {}: {}", col.name, col.r#type));
-            
-            if !col.comment.is_empty() {
-                result.push_str(&format!(" -- {}", col.comment));
-            }
-            
-            let mut key_info = Vec::new();
-            if col.is_in_primary_key == 1 {
-                key_info.push("PRIMARY KEY");
-            }
-            if col.is_in_sorting_key == 1 {
-                key_info.push("SORTING KEY");
-            }
-            if col.is_in_partition_key == 1 {
-                key_info.push("PARTITION KEY");
-            }
-            if col.is_in_sampling_key == 1 {
-                key_info.push("SAMPLING KEY");
-            }
-            
-            if !key_info.is_empty() {
-                result.push_str(&format!(" [{}]", key_info.join(", ")));
-            }
-            
-            result.push('\n');
-        }
-        
-        Ok(result)
+    if let Some(timezone) = &options.timezone {
+        server = server.with_session_timezone(timezone);
     }
-
-    async fn run(&mut self) -> Result<()> {
-        info!("Starting MCP server main loop");
-        
-        let stdin = tokio::io::stdin();
-        let mut reader = AsyncBufReader::new(stdin);
-        let mut stdout = tokio::io::stdout();
-        
-        let mut line = String::new();
-        
-        loop {
-            line.clear();
-            let bytes_read = reader.read_line(&mut line).await?;
-            
-            if bytes_read == 0 {
-                info!("End of input reached, shutting down server");
-                break;
-            }
-            
-            let line = line.trim();
-            if line.is_empty() {
-                continue;
-            }
-            
-            debug!("Received line: {}", line);
-            
-            match serde_json::from_str::<JsonRpcRequest>(line) {
-                Ok(request) => {
-                    match self.handle_request(request).await {
-                        Ok(response) => {
-                            let response_json = serde_json::to_string(&response)?;
-                            debug!("Sending response: {}", response_json);
-                            stdout.write_all(response_json.as_bytes()).await?;
-                            stdout.write_all(b"\n").await?;
-                            stdout.flush().await?;
-                        }
-                        Err(e) => {
-                            error!("Request handling failed: {}", e);
-                            let error_response = JsonRpcResponse {
-                                jsonrpc: "2.0".to_string(),
-                                result: None,
-                                error: Some(serde_json::json!({
-                                    "code": -32603,
-                                    "message": format!("Internal error: {}", e)
-                                })),
-                                id: None,
-                            };
-                            let response_json = serde_json::to_string(&error_response)?;
-                            stdout.write_all(response_json.as_bytes()).await?;
-                            stdout.write_all(b"\n").await?;
-                            stdout.flush().await?;
-                        }
-                    }
-                }
-                Err(e) => {
-                    error!("Failed to parse JSON-RPC request: {} - Input: {}", e, line);
-                    let error_response = JsonRpcResponse {
-                        jsonrpc: "2.0".to_string(),
-                        result: None,
-                        error: Some(serde_json::json!({
-                            "code": -32700,
-                            "message": "Parse error"
-                        })),
-                        id: None,
-                    };
-                    let response_json = serde_json::to_string(&error_response)?;
-                    stdout.write_all(response_json.as_bytes()).await?;
-                    stdout.write_all(b"\n").await?;
-                    stdout.flush().await?;
-                }
-            }
-        }
-        
-        Ok(())
+    if let Some(prefix) = &options.tool_prefix {
+        server = server.with_tool_prefix(prefix)?;
     }
-}
-
-#[tokio::main]
-async fn main() -> Result<()> {
-    env_logger::init();
     
-    info!("Starting MCP server v{}", env!("CARGO_PKG_VERSION"));
+    if let (Command::Replay, Some(file)) = (options.command, &options.replay_file) {
+        let entries = read_transcript(file)?;
+        let report = replay(server, &entries).await?;
+        print!("{}", report);
+        std::process::exit(if report.passed() { 0 } else { 1 });
+    }
     
-    let mut server = McpServer::new();
+    if let Some(dir) = &options.record_session {
+        server = server.with_session_recording(dir)?;
+    }
+    if let Some(address) = options.metrics_listen {
+        server.start_metrics_listener(address).await?;
+    }
     server.run().await?;
     Ok(())
 }
@@ -0,0 +1,147 @@
+use crate::connection::ConnectionState;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Upper bounds of the query latency buckets, in seconds.
+pub const LATENCY_BUCKETS: [f64; 11] = [0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+/// ClickHouse query latencies, bucketed by [`LATENCY_BUCKETS`]. Queries
+/// slower than the last bucket only count towards `count` and `sum_seconds`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LatencyHistogram {
+    pub buckets: [u64; LATENCY_BUCKETS.len()],
+    pub count: u64,
+    pub sum_seconds: f64,
+}
+
+impl LatencyHistogram {
+    fn observe(&mut self, latency: Duration) {
+        let seconds = latency.as_secs_f64();
+        if let Some(bucket) = LATENCY_BUCKETS.iter().position(|&bound| seconds <= bound) {
+            self.buckets[bucket] += 1;
+        }
+        self.count += 1;
+        self.sum_seconds += seconds;
+    }
+}
+
+/// A point-in-time copy of the counters in [`Metrics`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MetricsSnapshot {
+    /// JSON-RPC requests by method.
+    pub requests: BTreeMap<String, u64>,
+    /// Tool calls by tool name and outcome (`success` or `error`).
+    pub tool_calls: BTreeMap<(String, &'static str), u64>,
+    /// Bytes of JSON-RPC responses to tool calls, by tool name, as
+    /// serialized JSON. Not bytes on the wire to or from ClickHouse, which
+    /// the driver doesn't expose, so `--ch-compression` doesn't change them.
+    pub tool_response_bytes: BTreeMap<String, u64>,
+    pub query_latency: LatencyHistogram,
+    pub retries: u64,
+    /// Filled in by the server; the registry does not track the connection.
+    pub connection_state: Option<ConnectionState>,
+}
+
+/// The counters behind the `/metrics` endpoint and the `server_stats` tool,
+/// so both always agree. Share one registry between the server and its
+/// client with `McpServer::with_metrics` and `ClickHouseClient::with_metrics`.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    counters: Mutex<MetricsSnapshot>,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record_request(&self, method: &str) {
+        *self.counters.lock().unwrap().requests.entry(method.to_string()).or_default() += 1;
+    }
+
+    pub fn record_tool_call(&self, tool: &str, success: bool) {
+        let outcome = if success { "success" } else { "error" };
+        *self.counters.lock().unwrap().tool_calls.entry((tool.to_string(), outcome)).or_default() += 1;
+    }
+
+    /// Adds the serialized size of a tool call response; see
+    /// [`MetricsSnapshot::tool_response_bytes`].
+    pub fn record_tool_response_bytes(&self, tool: &str, bytes: usize) {
+        *self.counters.lock().unwrap().tool_response_bytes.entry(tool.to_string()).or_default() += bytes as u64;
+    }
+
+    pub fn record_query(&self, latency: Duration) {
+        self.counters.lock().unwrap().query_latency.observe(latency);
+    }
+
+    pub fn record_retry(&self) {
+        self.counters.lock().unwrap().retries += 1;
+    }
+
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        self.counters.lock().unwrap().clone()
+    }
+}
+
+impl MetricsSnapshot {
+    /// Renders the snapshot in the Prometheus text exposition format.
+    pub fn to_prometheus(&self) -> String {
+        let mut out = String::new();
+
+        out.push_str("# HELP mcp_requests_total JSON-RPC requests handled, by method.\n");
+        out.push_str("# TYPE mcp_requests_total counter\n");
+        for (method, count) in &self.requests {
+            let _ = writeln!(out, "mcp_requests_total{{method=\"{}\"}} {}", escape_label(method), count);
+        }
+
+        out.push_str("# HELP mcp_tool_calls_total Tool calls, by tool and outcome.\n");
+        out.push_str("# TYPE mcp_tool_calls_total counter\n");
+        for ((tool, outcome), count) in &self.tool_calls {
+            let _ = writeln!(out, "mcp_tool_calls_total{{tool=\"{}\",outcome=\"{}\"}} {}", escape_label(tool), outcome, count);
+        }
+
+        out.push_str("# HELP mcp_tool_response_bytes_total Bytes of tool call responses as serialized JSON, by tool; not ClickHouse transfer sizes.\n");
+        out.push_str("# TYPE mcp_tool_response_bytes_total counter\n");
+        for (tool, bytes) in &self.tool_response_bytes {
+            let _ = writeln!(out, "mcp_tool_response_bytes_total{{tool=\"{}\"}} {}", escape_label(tool), bytes);
+        }
+
+        out.push_str("# HELP mcp_clickhouse_query_duration_seconds Latency of ClickHouse query attempts.\n");
+        out.push_str("# TYPE mcp_clickhouse_query_duration_seconds histogram\n");
+        let mut cumulative = 0;
+        for (bound, count) in LATENCY_BUCKETS.iter().zip(self.query_latency.buckets) {
+            cumulative += count;
+            let _ = writeln!(out, "mcp_clickhouse_query_duration_seconds_bucket{{le=\"{}\"}} {}", bound, cumulative);
+        }
+        let _ = writeln!(out, "mcp_clickhouse_query_duration_seconds_bucket{{le=\"+Inf\"}} {}", self.query_latency.count);
+        let _ = writeln!(out, "mcp_clickhouse_query_duration_seconds_sum {}", self.query_latency.sum_seconds);
+        let _ = writeln!(out, "mcp_clickhouse_query_duration_seconds_count {}", self.query_latency.count);
+
+        out.push_str("# HELP mcp_clickhouse_retries_total ClickHouse operations retried after a failure.\n");
+        out.push_str("# TYPE mcp_clickhouse_retries_total counter\n");
+        let _ = writeln!(out, "mcp_clickhouse_retries_total {}", self.retries);
+
+        out.push_str("# HELP mcp_clickhouse_connection_state Current ClickHouse connection state (1 for the active state).\n");
+        out.push_str("# TYPE mcp_clickhouse_connection_state gauge\n");
+        for state in [ConnectionState::Connected, ConnectionState::Degraded, ConnectionState::Disconnected] {
+            let active = u8::from(self.connection_state == Some(state));
+            let _ = writeln!(out, "mcp_clickhouse_connection_state{{state=\"{}\"}} {}", state_label(state), active);
+        }
+
+        out
+    }
+}
+
+pub fn state_label(state: ConnectionState) -> &'static str {
+    match state {
+        ConnectionState::Connected => "connected",
+        ConnectionState::Degraded => "degraded",
+        ConnectionState::Disconnected => "disconnected",
+    }
+}
+
+fn escape_label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
@@ -0,0 +1,152 @@
+use crate::{is_nullable_type, quote_identifier, unwrap_type_modifiers};
+use serde::Serialize;
+
+/// Columns with more distinct values than this are not suggested as
+/// leading sorting key columns.
+pub const MAX_SORT_KEY_CARDINALITY: u64 = 10_000;
+
+/// Tables with fewer rows than this are not worth partitioning.
+pub const MIN_PARTITIONED_ROWS: u64 = 10_000_000;
+
+/// The most leading columns suggested before the time column.
+const MAX_LEADING_COLUMNS: usize = 3;
+
+/// A column and its estimated number of distinct values, `None` when it
+/// wasn't measured, e.g. because it can't be part of a key.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ColumnCardinality {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub r#type: String,
+    pub distinct: Option<u64>,
+}
+
+/// Suggested `ORDER BY` and `PARTITION BY` clauses for a MergeTree table.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct OrderingAdvice {
+    pub engine: String,
+    pub total_rows: u64,
+    pub current_order_by: String,
+    pub current_partition_by: String,
+    pub order_by: Vec<String>,
+    pub partition_by: Option<String>,
+    /// Why each part of the suggestion was made, or left out.
+    pub rationale: Vec<String>,
+    pub columns: Vec<ColumnCardinality>,
+}
+
+impl OrderingAdvice {
+    /// The suggestion as the clauses of a `CREATE TABLE` statement.
+    pub fn ddl(&self) -> String {
+        let mut ddl = match self.order_by.as_slice() {
+            [] => "ORDER BY tuple()".to_string(),
+            [column] => format!("ORDER BY {}", column),
+            columns => format!("ORDER BY ({})", columns.join(", ")),
+        };
+        if let Some(partition_by) = &self.partition_by {
+            ddl.push_str(&format!("\nPARTITION BY {}", partition_by));
+        }
+        ddl
+    }
+}
+
+/// Whether a column of this type can be measured and used in a key.
+/// Compound types, floats and Nullable columns are left out.
+pub fn is_key_candidate(type_name: &str) -> bool {
+    let base = unwrap_type_modifiers(type_name);
+    let compound = ["Array(", "Map(", "Tuple(", "Nested(", "Object(", "JSON", "AggregateFunction("]
+        .iter()
+        .any(|prefix| base.starts_with(prefix));
+    !compound && !base.starts_with("Float") && !is_nullable_type(type_name)
+}
+
+/// A column name as written in DDL, quoted only when needed.
+fn key_identifier(name: &str) -> String {
+    let plain = name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if plain {
+        name.to_string()
+    } else {
+        quote_identifier(name)
+    }
+}
+
+/// Derives the advice from the table's current keys and its columns'
+/// cardinalities:
+///
+/// - columns with at most [`MAX_SORT_KEY_CARDINALITY`] distinct values and
+///   at least ten rows per value lead the sorting key, lowest cardinality
+///   first, as that compresses best and lets filters on them skip granules;
+/// - the first non-Nullable Date/DateTime column comes last, so time ranges
+///   within those prefixes are contiguous;
+/// - tables of at least [`MIN_PARTITIONED_ROWS`] rows with a time column
+///   are partitioned by month.
+pub fn advise_ordering(
+    engine: &str,
+    current_order_by: &str,
+    current_partition_by: &str,
+    total_rows: u64,
+    columns: Vec<ColumnCardinality>,
+) -> OrderingAdvice {
+    let mut rationale = Vec::new();
+    let time_column = columns
+        .iter()
+        .find(|c| unwrap_type_modifiers(&c.r#type).starts_with("Date") && !is_nullable_type(&c.r#type));
+
+    let mut leading: Vec<(&ColumnCardinality, u64)> = columns
+        .iter()
+        .filter(|c| Some(c.name.as_str()) != time_column.map(|t| t.name.as_str()))
+        .filter_map(|c| c.distinct.map(|distinct| (c, distinct)))
+        .filter(|(_, distinct)| *distinct > 1 && *distinct <= MAX_SORT_KEY_CARDINALITY && distinct * 10 <= total_rows)
+        .collect();
+    // Stable, so ties keep the schema order
+    leading.sort_by_key(|(_, distinct)| *distinct);
+    leading.truncate(MAX_LEADING_COLUMNS);
+
+    let mut order_by: Vec<String> = Vec::new();
+    for (column, distinct) in &leading {
+        order_by.push(key_identifier(&column.name));
+        rationale.push(format!("{} has {} distinct values, a good key prefix", column.name, distinct));
+    }
+    if let Some(time) = time_column {
+        order_by.push(key_identifier(&time.name));
+        rationale.push(format!("{} is the time column, last so time ranges within each prefix are contiguous", time.name));
+    }
+    if total_rows == 0 {
+        rationale.push("the table is empty, so no column could be measured; revisit once it has data".to_string());
+    } else if order_by.is_empty() {
+        rationale.push("no low-cardinality or time columns found; order by the columns queries filter on most".to_string());
+    }
+
+    let skipped: Vec<&str> = columns.iter().filter(|c| c.distinct.is_none()).map(|c| c.name.as_str()).collect();
+    if !skipped.is_empty() {
+        rationale.push(format!("not measured, so not considered: {}", skipped.join(", ")));
+    }
+
+    let partition_by = match time_column {
+        Some(time) if total_rows >= MIN_PARTITIONED_ROWS => {
+            rationale.push(format!("monthly partitions by {} keep part counts manageable and make dropping old data cheap", time.name));
+            Some(format!("toYYYYMM({})", key_identifier(&time.name)))
+        }
+        Some(_) => {
+            rationale.push(format!("{} rows is too few to benefit from partitioning", total_rows));
+            None
+        }
+        None => None,
+    };
+
+    if order_by.join(", ") == current_order_by {
+        rationale.push("the suggested ORDER BY matches the current one".to_string());
+    }
+
+    OrderingAdvice {
+        engine: engine.to_string(),
+        total_rows,
+        current_order_by: current_order_by.to_string(),
+        current_partition_by: current_partition_by.to_string(),
+        order_by,
+        partition_by,
+        rationale,
+        columns,
+    }
+}
@@ -0,0 +1,7 @@
+//! The types most programs embedding the client or the server need, for
+//! `use mcp_test::prelude::*;`. See the `examples/` directory.
+
+pub use crate::{
+    with_query_comment, with_query_id, with_retry_budget, ClickHouseClient, ClickHouseConfig, ClickHouseError,
+    ColumnInfo, ConnectionManager, DatabaseInfo, McpServer, SelectLimits, SelectResult, TableInfo,
+};
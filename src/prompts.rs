@@ -0,0 +1,104 @@
+use serde_json::{json, Map, Value};
+
+/// An argument a prompt template expects.
+#[derive(Debug, Clone, Copy)]
+pub struct PromptArgument {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub required: bool,
+}
+
+/// A built-in prompt served through `prompts/list` and `prompts/get`.
+/// Templates refer to arguments as `{name}` and to tool names as
+/// `{tool:name}`, so advertised tool prefixes are applied.
+#[derive(Debug, Clone, Copy)]
+pub struct Prompt {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub arguments: &'static [PromptArgument],
+    template: &'static str,
+}
+
+pub const PROMPTS: &[Prompt] = &[
+    Prompt {
+        name: "explore_database",
+        description: "Explore a database: its tables, their columns and how they relate",
+        arguments: &[PromptArgument {
+            name: "database",
+            description: "The database to explore",
+            required: true,
+        }],
+        template: "Explore the ClickHouse database '{database}'.\n\n\
+            1. Call `{tool:list_tables}` with database '{database}' to see which tables it contains.\n\
+            2. Call `{tool:get_table_schema}` for the tables that look most relevant, noting their sorting and partition keys.\n\
+            3. Summarize what data the database holds, which columns link the tables together, \
+            and which tables are best suited for common questions.",
+    },
+    Prompt {
+        name: "optimize_query",
+        description: "Review a query against the table layout and suggest how to make it read less data",
+        arguments: &[PromptArgument {
+            name: "query",
+            description: "The SELECT query to optimize",
+            required: true,
+        }],
+        template: "Help me optimize this ClickHouse query:\n\n```sql\n{query}\n```\n\n\
+            1. Call `{tool:get_table_schema}` for every table the query reads, paying attention to the sorting key, \
+            partition key and primary key.\n\
+            2. Call `{tool:explain_with_settings}` with the query to see which indexes and parts it uses.\n\
+            3. Suggest changes that let ClickHouse skip more data, such as filtering on key columns, \
+            avoiding functions on key columns in WHERE, or using PREWHERE, and explain the expected effect of each.",
+    },
+];
+
+impl Prompt {
+    pub fn find(name: &str) -> Option<&'static Prompt> {
+        PROMPTS.iter().find(|prompt| prompt.name == name)
+    }
+
+    /// The prompt as listed by `prompts/list`.
+    pub fn definition(&self) -> Value {
+        let arguments: Vec<Value> = self
+            .arguments
+            .iter()
+            .map(|arg| json!({"name": arg.name, "description": arg.description, "required": arg.required}))
+            .collect();
+        json!({"name": self.name, "description": self.description, "arguments": arguments})
+    }
+
+    /// Fills in the template. Fails with a message naming the argument if a
+    /// required one is missing or an argument is not a string.
+    pub fn render(&self, arguments: &Map<String, Value>, tool_prefix: &str) -> Result<String, String> {
+        for arg in self.arguments {
+            match arguments.get(arg.name) {
+                Some(Value::String(_)) => {}
+                None if !arg.required => {}
+                None => return Err(format!("Missing required argument '{}'", arg.name)),
+                Some(_) => return Err(format!("Argument '{}' must be a string", arg.name)),
+            }
+        }
+
+        // Substitute in one pass so argument values are never expanded again
+        let mut rendered = String::with_capacity(self.template.len());
+        let mut rest = self.template;
+        while let Some(start) = rest.find('{') {
+            rendered.push_str(&rest[..start]);
+            let Some(end) = rest[start..].find('}').map(|end| start + end) else {
+                break;
+            };
+
+            let placeholder = &rest[start + 1..end];
+            match placeholder.strip_prefix("tool:") {
+                Some(tool) => {
+                    rendered.push_str(tool_prefix);
+                    rendered.push_str(tool);
+                }
+                None => rendered.push_str(arguments.get(placeholder).and_then(Value::as_str).unwrap_or_default()),
+            }
+            rest = &rest[end + 1..];
+        }
+        rendered.push_str(rest);
+
+        Ok(rendered)
+    }
+}
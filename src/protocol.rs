@@ -0,0 +1,112 @@
+//! MCP result types shared by all tools, serialized exactly as the MCP
+//! schema's `CallToolResult` and content blocks.
+
+use crate::humanize::human_bytes;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// The default cap on the serialized size of a result's
+/// `structuredContent`; see [`ToolResult::limit_structured`].
+pub const DEFAULT_MAX_STRUCTURED_BYTES: usize = 1024 * 1024;
+
+/// A content block of a tool result or prompt message.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Content {
+    Text { text: String },
+}
+
+impl Content {
+    pub fn text(text: impl Into<String>) -> Self {
+        Content::Text { text: text.into() }
+    }
+}
+
+/// The result of a `tools/call`. Failures of the tool itself (a missing
+/// table, ClickHouse being down) are results with `isError` set, so clients
+/// show them to the model instead of treating them as protocol errors.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ToolResult {
+    pub content: Vec<Content>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub structured_content: Option<Value>,
+    #[serde(default)]
+    pub is_error: bool,
+    /// Set when `structuredContent` was left out for being too large; the
+    /// text content is complete either way.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub structured_omitted: Option<StructuredOmission>,
+}
+
+/// The `structuredOmitted` marker of a [`ToolResult`]: the serialized size
+/// of the structured content that was left out and the limit it exceeded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StructuredOmission {
+    pub bytes: usize,
+    pub limit: usize,
+}
+
+impl ToolResult {
+    /// A successful result with a single text block.
+    pub fn text(text: impl Into<String>) -> Self {
+        Self {
+            content: vec![Content::text(text)],
+            structured_content: None,
+            is_error: false,
+            structured_omitted: None,
+        }
+    }
+
+    /// A failed tool call, described by `message`.
+    pub fn error(message: impl Into<String>) -> Self {
+        Self {
+            is_error: true,
+            ..Self::text(message)
+        }
+    }
+
+    /// A successful result with both a text rendering and the same data as
+    /// JSON for clients that read `structuredContent`.
+    pub fn with_structured(text: impl Into<String>, structured: Value) -> Self {
+        Self {
+            structured_content: Some(structured),
+            ..Self::text(text)
+        }
+    }
+
+    /// Drops the structured content if it serializes to more than
+    /// `max_bytes`, so a huge payload (a schema of thousands of columns)
+    /// doesn't get the whole response rejected by clients with a message
+    /// size limit. Sets `structuredOmitted` to the size and the limit and
+    /// adds a text block with both and how to get less data. Returns the
+    /// size of what was dropped.
+    pub fn limit_structured(&mut self, max_bytes: usize) -> Option<usize> {
+        let size = serde_json::to_vec(self.structured_content.as_ref()?).map_or(0, |json| json.len());
+        if size <= max_bytes {
+            return None;
+        }
+        self.structured_content = None;
+        self.structured_omitted = Some(StructuredOmission { bytes: size, limit: max_bytes });
+        self.content.push(Content::text(format!(
+            "Structured content omitted: {} exceeds the {} limit. Request less data, e.g. a page at a time with limit/offset or get_table_schema with compact: true.",
+            human_bytes(size as u64),
+            human_bytes(max_bytes as u64)
+        )));
+        Some(size)
+    }
+}
+
+/// The line every tool appends when it leaves out part of its output, e.g.
+/// `[TRUNCATED shown=20 total=135 reason=limit]`, so clients can detect
+/// truncation the same way everywhere. `total` is left out when unknown.
+/// Reasons in use: `row_limit` (a fixed cap of the server), `limit` (the
+/// tool's `limit` argument), `column_limit` and `byte_limit` (a size cap
+/// on the text returned), and `stored` (the rest is a resource, see
+/// [`crate::results`]).
+pub fn truncation_notice(shown: usize, total: Option<usize>, reason: &str) -> String {
+    match total {
+        Some(total) => format!("[TRUNCATED shown={} total={} reason={}]", shown, total, reason),
+        None => format!("[TRUNCATED shown={} reason={}]", shown, reason),
+    }
+}
@@ -0,0 +1,168 @@
+//! Whether a filter lets ClickHouse skip partitions, read from the plan text
+//! of `EXPLAIN indexes = 1`. Partition pruning shows up as the `MinMax` and
+//! `Partition` indexes, which come before the primary key:
+//!
+//! ```text
+//! ReadFromMergeTree (shop.events)
+//! Indexes:
+//!   MinMax
+//!     Keys:
+//!       date
+//!     Condition: (date in [19723, +Inf))
+//!     Parts: 3/412
+//!     Granules: 30/41200
+//!   Partition
+//!     Keys:
+//!       toYYYYMM(date)
+//!     Condition: (toYYYYMM(date) in [202401, +Inf))
+//!     Parts: 3/3
+//!     Granules: 30/30
+//! ```
+//!
+//! Servers before 22.x print `ReadFromMergeTree` without the table name.
+
+use crate::index_usage::{IndexStep, IndexUsage};
+use clickhouse::Row;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// The `max_execution_time` of the `EXPLAIN`, which only builds a plan.
+pub const PRUNING_EXPLAIN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Below this share of parts read, pruning counts as good.
+pub const GOOD_PRUNING_RATIO: f64 = 0.5;
+
+/// The active parts of a table, from `system.parts`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, Row)]
+pub struct PartTotals {
+    pub parts: u64,
+    pub rows: u64,
+}
+
+/// How well a filter prunes partitions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PruningVerdict {
+    /// The table has no active parts, so nothing is read either way.
+    EmptyTable,
+    /// The table has no partition key, so only the primary key can narrow
+    /// the read.
+    NotPartitioned,
+    /// No part matches the filter.
+    NothingRead,
+    /// At most [`GOOD_PRUNING_RATIO`] of the parts are read.
+    Good,
+    /// Some parts are skipped, but more than [`GOOD_PRUNING_RATIO`] of
+    /// them are read.
+    Partial,
+    /// Every part is read.
+    FullScan,
+}
+
+/// What `EXPLAIN indexes = 1` says a filter reads of one table.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PartitionPruning {
+    pub partition_key: String,
+    /// Active parts of the table, from `system.parts`.
+    pub parts_total: u64,
+    pub rows_total: u64,
+    /// Parts left after the partition key (`MinMax` and `Partition`).
+    pub parts_after_partition_pruning: u64,
+    /// Parts left after all indexes, the primary key and skip indexes
+    /// included.
+    pub parts_read: u64,
+    pub granules_read: u64,
+    pub granules_total: u64,
+    /// The rows of the table scaled by the share of granules read; granules
+    /// hold about the same number of rows, so this is an estimate.
+    pub rows_estimate: u64,
+    /// Whether a condition of the filter was checked against the partition
+    /// key.
+    pub partition_key_used: bool,
+    pub verdict: PruningVerdict,
+    pub indexes: Vec<IndexStep>,
+}
+
+impl PartitionPruning {
+    /// Reads the pruning of `table` (as `database.table`) from the lines of
+    /// `EXPLAIN indexes = 1`. Reads of other tables, such as those of a
+    /// subquery, are left out. Parts are compared against `totals` rather
+    /// than the plan, which only lists the parts it considered.
+    pub fn from_explain(table: &str, lines: &[String], partition_key: &str, totals: PartTotals) -> Self {
+        let indexes: Vec<IndexStep> = IndexUsage::from_explain(lines)
+            .indexes
+            .into_iter()
+            .filter(|step| step.table.is_empty() || step.table == table)
+            .collect();
+        let partition_steps: Vec<&IndexStep> = indexes.iter().filter(|step| step.kind == "MinMax" || step.kind == "Partition").collect();
+
+        let parts_total = totals.parts;
+        let parts_after_partition_pruning = partition_steps.last().map_or(parts_total, |step| step.parts_selected);
+        let parts_read = indexes.last().map_or(parts_total, |step| step.parts_selected);
+        let granules_total = indexes.first().map_or(0, |step| step.granules_total);
+        let granules_read = indexes.last().map_or(0, |step| step.granules_selected);
+        let rows_estimate = if granules_total == 0 {
+            totals.rows
+        } else {
+            (totals.rows as u128 * granules_read as u128 / granules_total as u128) as u64
+        };
+
+        let verdict = if parts_total == 0 {
+            PruningVerdict::EmptyTable
+        } else if partition_key.is_empty() {
+            PruningVerdict::NotPartitioned
+        } else if parts_after_partition_pruning == 0 {
+            PruningVerdict::NothingRead
+        } else if parts_after_partition_pruning >= parts_total {
+            PruningVerdict::FullScan
+        } else if parts_after_partition_pruning as f64 <= parts_total as f64 * GOOD_PRUNING_RATIO {
+            PruningVerdict::Good
+        } else {
+            PruningVerdict::Partial
+        };
+
+        Self {
+            partition_key: partition_key.to_string(),
+            parts_total,
+            rows_total: totals.rows,
+            parts_after_partition_pruning,
+            parts_read,
+            granules_read,
+            granules_total,
+            rows_estimate,
+            partition_key_used: partition_steps.iter().any(|step| step.is_used()),
+            verdict,
+            indexes,
+        }
+    }
+
+    /// The share of parts left after partition pruning, from 0 to 1.
+    pub fn ratio(&self) -> f64 {
+        if self.parts_total == 0 {
+            0.0
+        } else {
+            self.parts_after_partition_pruning.min(self.parts_total) as f64 / self.parts_total as f64
+        }
+    }
+
+    /// One line summing up the verdict, e.g. `reads 3 of 412 parts — good`.
+    pub fn summary(&self) -> String {
+        match self.verdict {
+            PruningVerdict::EmptyTable => "the table has no parts yet, so there is nothing to prune".to_string(),
+            PruningVerdict::NotPartitioned => format!(
+                "the table has no partition key, so all {} parts are considered; only the primary key can narrow the read",
+                self.parts_total
+            ),
+            PruningVerdict::NothingRead => format!("reads 0 of {} parts — no partition matches the filter", self.parts_total),
+            PruningVerdict::Good => format!("reads {} of {} parts — good", self.parts_after_partition_pruning, self.parts_total),
+            PruningVerdict::Partial => format!(
+                "reads {} of {} parts — partly pruned; a narrower condition on the partition key {} would skip more",
+                self.parts_after_partition_pruning, self.parts_total, self.partition_key
+            ),
+            PruningVerdict::FullScan if self.partition_key_used => {
+                format!("full scan — the condition on the partition key {} matches every part", self.partition_key)
+            }
+            PruningVerdict::FullScan => format!("full scan — the filter doesn't use the partition key {}", self.partition_key),
+        }
+    }
+}
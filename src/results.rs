@@ -0,0 +1,234 @@
+//! Query results kept on the server and read as resources, so a tool
+//! returning many rows can put a preview inline and link to the rest
+//! instead of filling the model's context.
+//!
+//! Results live in memory under `clickhouse-result://<uuid>` until their
+//! TTL runs out or newer results push them out of the [`ResultStore`].
+//! `resources/read` renders them as JSON lines, or as CSV with
+//! `?format=csv`.
+
+use serde::de::{Deserializer, IgnoredAny, MapAccess, Visitor};
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::time::Instant;
+use uuid::Uuid;
+
+/// What the URIs of stored results start with.
+pub const RESULT_URI_PREFIX: &str = "clickhouse-result://";
+
+/// How many rows of a stored result a tool returns inline.
+pub const RESULT_PREVIEW_ROWS: usize = 50;
+
+/// How long results are kept unless told otherwise.
+pub const DEFAULT_RESULT_TTL: Duration = Duration::from_secs(15 * 60);
+
+/// How many results are kept unless told otherwise; the oldest is dropped
+/// to make room.
+pub const DEFAULT_MAX_RESULTS: usize = 20;
+
+/// How a stored result is rendered by `resources/read`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ResultFormat {
+    /// One JSON object per line.
+    #[default]
+    JsonLines,
+    /// A header of column names, then one line per row.
+    Csv,
+}
+
+impl ResultFormat {
+    /// Parses `jsonl` or `csv`, ignoring case.
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.to_ascii_lowercase().as_str() {
+            "jsonl" => Some(Self::JsonLines),
+            "csv" => Some(Self::Csv),
+            _ => None,
+        }
+    }
+
+    pub fn mime_type(self) -> &'static str {
+        match self {
+            Self::JsonLines => "application/jsonl",
+            Self::Csv => "text/csv",
+        }
+    }
+}
+
+/// Splits a result URI into the result id and the format asked for with
+/// `?format=`. `None` for URIs of other resources, an error message for a
+/// format other than `jsonl` or `csv`.
+pub fn parse_result_uri(uri: &str) -> Option<Result<(&str, ResultFormat), String>> {
+    let rest = uri.strip_prefix(RESULT_URI_PREFIX)?;
+    let (id, query) = rest.split_once('?').unwrap_or((rest, ""));
+    let mut format = ResultFormat::default();
+    for parameter in query.split('&').filter(|p| !p.is_empty()) {
+        match parameter.split_once('=') {
+            Some(("format", value)) => match ResultFormat::parse(value) {
+                Some(parsed) => format = parsed,
+                None => return Some(Err(format!("format must be jsonl or csv, got '{}'", value))),
+            },
+            _ => return Some(Err(format!("unknown parameter '{}'", parameter))),
+        }
+    }
+    Some(Ok((id, format)))
+}
+
+/// The keys of a JSON object line in the order they appear, which a
+/// parsed [`Value`] doesn't keep (its keys are sorted by name): the column
+/// order of a `JSONEachRow` row. Empty if `line` isn't a JSON object.
+pub fn row_columns(line: &str) -> Vec<String> {
+    struct Keys(Vec<String>);
+
+    impl<'de> Deserialize<'de> for Keys {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            struct KeysVisitor;
+
+            impl<'de> Visitor<'de> for KeysVisitor {
+                type Value = Keys;
+
+                fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                    formatter.write_str("a JSON object")
+                }
+
+                fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Keys, A::Error> {
+                    let mut keys = Vec::new();
+                    while let Some(key) = map.next_key::<String>()? {
+                        map.next_value::<IgnoredAny>()?;
+                        keys.push(key);
+                    }
+                    Ok(Keys(keys))
+                }
+            }
+
+            deserializer.deserialize_map(KeysVisitor)
+        }
+    }
+
+    serde_json::from_str::<Keys>(line).map(|keys| keys.0).unwrap_or_default()
+}
+
+/// Renders `rows`, JSON objects, in `format`. CSV columns are `columns`, in
+/// the query's order as [`row_columns`] reads it; nested values are
+/// written as JSON and nulls as empty fields.
+pub fn render_rows(rows: &[Value], columns: &[String], format: ResultFormat) -> String {
+    match format {
+        ResultFormat::JsonLines => rows.iter().map(|row| format!("{}\n", row)).collect(),
+        ResultFormat::Csv => {
+            let mut csv = csv_line(columns.iter().cloned());
+            for row in rows {
+                csv.push_str(&csv_line(columns.iter().map(|column| match &row[column.as_str()] {
+                    Value::Null => String::new(),
+                    Value::String(text) => text.clone(),
+                    other => other.to_string(),
+                })));
+            }
+            csv
+        }
+    }
+}
+
+/// One CSV line, quoting fields as RFC 4180 asks.
+fn csv_line(fields: impl Iterator<Item = String>) -> String {
+    let fields: Vec<String> = fields
+        .map(|field| {
+            if field.contains([',', '"', '\n', '\r']) {
+                format!("\"{}\"", field.replace('"', "\"\""))
+            } else {
+                field
+            }
+        })
+        .collect();
+    fields.join(",") + "\n"
+}
+
+/// A result as stored, see [`ResultStore::insert`].
+#[derive(Debug, Clone)]
+pub struct StoredResult {
+    pub id: String,
+    /// The tool that produced it.
+    pub tool: String,
+    /// The column names in the query's order.
+    pub columns: Arc<Vec<String>>,
+    pub rows: Arc<Vec<Value>>,
+    stored: Instant,
+}
+
+impl StoredResult {
+    pub fn uri(&self) -> String {
+        format!("{}{}", RESULT_URI_PREFIX, self.id)
+    }
+
+    pub fn age(&self) -> Duration {
+        self.stored.elapsed()
+    }
+}
+
+/// The results of a session, newest last. Results expire after the TTL,
+/// and the oldest is dropped when more than the maximum are stored.
+#[derive(Debug)]
+pub struct ResultStore {
+    ttl: Duration,
+    max_results: usize,
+    entries: Mutex<VecDeque<StoredResult>>,
+}
+
+impl Default for ResultStore {
+    fn default() -> Self {
+        Self::new(DEFAULT_RESULT_TTL, DEFAULT_MAX_RESULTS)
+    }
+}
+
+impl ResultStore {
+    pub fn new(ttl: Duration, max_results: usize) -> Self {
+        Self { ttl, max_results: max_results.max(1), entries: Mutex::new(VecDeque::new()) }
+    }
+
+    pub fn ttl(&self) -> Duration {
+        self.ttl
+    }
+
+    pub fn max_results(&self) -> usize {
+        self.max_results
+    }
+
+    /// Stores `rows` with their `columns` under a new id, dropping expired
+    /// results and, if that isn't enough, the oldest ones to make room.
+    pub fn insert(&self, tool: &str, columns: Vec<String>, rows: Vec<Value>) -> StoredResult {
+        let stored = StoredResult {
+            id: Uuid::new_v4().to_string(),
+            tool: tool.to_string(),
+            columns: Arc::new(columns),
+            rows: Arc::new(rows),
+            stored: Instant::now(),
+        };
+        let mut entries = self.entries.lock().unwrap();
+        self.drop_expired(&mut entries);
+        while entries.len() >= self.max_results {
+            entries.pop_front();
+        }
+        entries.push_back(stored.clone());
+        stored
+    }
+
+    /// The result stored under `id`, unless it expired or was dropped.
+    pub fn get(&self, id: &str) -> Option<StoredResult> {
+        let mut entries = self.entries.lock().unwrap();
+        self.drop_expired(&mut entries);
+        entries.iter().find(|entry| entry.id == id).cloned()
+    }
+
+    /// The results that haven't expired, newest first.
+    pub fn live(&self) -> Vec<StoredResult> {
+        let mut entries = self.entries.lock().unwrap();
+        self.drop_expired(&mut entries);
+        entries.iter().rev().cloned().collect()
+    }
+
+    fn drop_expired(&self, entries: &mut VecDeque<StoredResult>) {
+        entries.retain(|entry| entry.age() < self.ttl);
+    }
+}
@@ -0,0 +1,147 @@
+//! Compact summaries of very wide tables, whose full column list is too
+//! long to be useful: key columns, columns grouped by type with generated
+//! column families collapsed into prefixes, and the first columns verbatim.
+
+use crate::{unwrap_type_modifiers, ColumnInfo};
+use serde::Serialize;
+
+/// How many columns a summary lists verbatim unless told otherwise.
+pub const DEFAULT_VERBATIM_COLUMNS: usize = 20;
+
+/// The fewest columns sharing a prefix that are collapsed into a family.
+pub const MIN_FAMILY_SIZE: usize = 3;
+
+/// Segments of a name looked at when splitting families, e.g. `metric_cpu_`.
+const MAX_PREFIX_SEGMENTS: usize = 3;
+
+/// Columns of one type whose names share a prefix, e.g. `cpu_*`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ColumnFamily {
+    /// The shared prefix followed by `*`.
+    pub pattern: String,
+    pub count: usize,
+}
+
+/// The columns of one base type, with `Nullable`, `LowCardinality` and
+/// type parameters dropped.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TypeGroup {
+    pub base_type: String,
+    pub count: usize,
+    /// Families, the largest first.
+    pub families: Vec<ColumnFamily>,
+    /// Columns in no family, in table order.
+    pub others: Vec<String>,
+}
+
+/// A summary of a table's columns, see [`summarize_schema`].
+#[derive(Debug, Clone, Serialize)]
+pub struct SchemaSummary {
+    pub total_columns: usize,
+    /// Columns in the primary, sorting, partition or sampling key, in table
+    /// order.
+    pub key_columns: Vec<ColumnInfo>,
+    /// The largest group first.
+    pub type_groups: Vec<TypeGroup>,
+    /// The first columns of the table.
+    pub leading_columns: Vec<ColumnInfo>,
+}
+
+/// Summarizes `columns`, listing at most `max_columns` of them verbatim.
+pub fn summarize_schema(columns: &[ColumnInfo], max_columns: usize) -> SchemaSummary {
+    let key_columns = columns
+        .iter()
+        .filter(|c| c.is_in_primary_key == 1 || c.is_in_sorting_key == 1 || c.is_in_partition_key == 1 || c.is_in_sampling_key == 1)
+        .cloned()
+        .collect();
+
+    let mut by_type: Vec<(String, Vec<&str>)> = Vec::new();
+    for column in columns {
+        let base = base_type(&column.r#type);
+        match by_type.iter_mut().find(|(name, _)| *name == base) {
+            Some((_, names)) => names.push(&column.name),
+            None => by_type.push((base.to_string(), vec![&column.name])),
+        }
+    }
+    let mut type_groups: Vec<TypeGroup> = by_type
+        .into_iter()
+        .map(|(base_type, names)| {
+            let (mut families, others) = cluster_names(&names, 1);
+            families.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.pattern.cmp(&b.pattern)));
+            TypeGroup { base_type, count: names.len(), families, others }
+        })
+        .collect();
+    type_groups.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.base_type.cmp(&b.base_type)));
+
+    SchemaSummary {
+        total_columns: columns.len(),
+        key_columns,
+        type_groups,
+        leading_columns: columns.iter().take(max_columns).cloned().collect(),
+    }
+}
+
+/// The type a column is grouped under, e.g. `Decimal` for
+/// `Nullable(Decimal(18, 2))`.
+pub fn base_type(type_name: &str) -> &str {
+    let inner = unwrap_type_modifiers(type_name);
+    inner.split('(').next().unwrap_or(inner).trim()
+}
+
+/// The first `segments` segments of `name` ending in `_` or `.`, e.g.
+/// `cpu_` for `cpu_user_p99`. A name without another separator ending in
+/// digits, like `col12`, has its letters as the prefix. `None` if the name
+/// has no such prefix or is nothing but the prefix.
+pub fn name_prefix(name: &str, segments: usize) -> Option<&str> {
+    let mut ends = name.match_indices(['_', '.']).map(|(index, _)| index + 1).filter(|end| *end > 1);
+    match ends.nth(segments - 1) {
+        Some(end) if end < name.len() => Some(&name[..end]),
+        Some(_) => None,
+        None if segments == 1 => {
+            let letters = name.trim_end_matches(|c: char| c.is_ascii_digit());
+            (!letters.is_empty() && letters.len() < name.len()).then_some(letters)
+        }
+        None => None,
+    }
+}
+
+/// Groups `names` by their prefix of `segments` segments into families of
+/// at least [`MIN_FAMILY_SIZE`]. If they all share one prefix, the next
+/// segment is tried, so `metric_cpu_*` and `metric_mem_*` aren't lumped
+/// together as `metric_*`.
+fn cluster_names<'a>(names: &[&'a str], segments: usize) -> (Vec<ColumnFamily>, Vec<String>) {
+    let mut prefixes: Vec<(&str, Vec<&'a str>)> = Vec::new();
+    let mut unprefixed = Vec::new();
+    for &name in names {
+        match name_prefix(name, segments) {
+            Some(prefix) => match prefixes.iter_mut().find(|(p, _)| *p == prefix) {
+                Some((_, members)) => members.push(name),
+                None => prefixes.push((prefix, vec![name])),
+            },
+            None => unprefixed.push(name),
+        }
+    }
+
+    if let [(prefix, members)] = prefixes.as_slice() {
+        if unprefixed.is_empty() && segments < MAX_PREFIX_SEGMENTS {
+            let (families, others) = cluster_names(members, segments + 1);
+            if families.len() > 1 {
+                return (families, others);
+            }
+            if members.len() >= MIN_FAMILY_SIZE {
+                return (vec![ColumnFamily { pattern: format!("{}*", prefix), count: members.len() }], Vec::new());
+            }
+        }
+    }
+
+    let mut families = Vec::new();
+    let mut in_family: Vec<&str> = Vec::new();
+    for (prefix, members) in &prefixes {
+        if members.len() >= MIN_FAMILY_SIZE {
+            families.push(ColumnFamily { pattern: format!("{}*", prefix), count: members.len() });
+            in_family.extend(members);
+        }
+    }
+    let others = names.iter().filter(|name| !in_family.contains(name)).map(|name| name.to_string()).collect();
+    (families, others)
+}
@@ -0,0 +1,4674 @@
+use anyhow::Result;
+use chrono::SecondsFormat;
+use log::{debug, error, info, warn};
+use crate::capabilities::{apply_capabilities, Capabilities, UnavailableTools, TOOL_REQUIREMENTS};
+use crate::codec::{encode_frame, Frame, LineReader, DEFAULT_MAX_LINE_LENGTH};
+use crate::config::ClickHouseConfig;
+use crate::connection::{HealthProbe, StateTransition};
+use crate::format::{
+    format_all_tables, format_columns, format_databases, format_schema_summary, format_table_schema, format_tables, markdown_cell,
+};
+use crate::history::{QueryHistory, QueryHistoryEntry, MAX_QUERY_HISTORY};
+use crate::humanize::{human_bytes, human_count, human_duration, with_raw_numbers};
+use crate::information_schema::{self, json_lines};
+use crate::metrics::{state_label, Metrics, MetricsSnapshot};
+use crate::prompts::{Prompt, PROMPTS};
+use crate::protocol::{truncation_notice, Content, ToolResult, DEFAULT_MAX_STRUCTURED_BYTES};
+use crate::results::{parse_result_uri, render_rows, ResultFormat, ResultStore, StoredResult, RESULT_PREVIEW_ROWS};
+use crate::schema_summary::{summarize_schema, DEFAULT_VERBATIM_COLUMNS};
+use crate::sql::{check_admin_confirmation, ADMIN_CONFIRMATION};
+use crate::transcript::SessionRecorder;
+use crate::visibility::DatabaseVisibility;
+use crate::workspace::{RecentTables, LAST_TABLE};
+use crate::{explain_error, redact_url, suggest_joins, query_id_prefix, with_query_comment, with_query_id, with_retry_budget, CacheScope, CheckStatus, ClickHouseClient, ClickHouseError, ColumnInfo, ConnectionManager, ConnectionState, RawResult, RecentModifications, ResponseFormat, SourceKind, TimeFilter, TimeInterval, TransferCompression, DEFAULT_MAX_IDENTIFIER_LENGTH, MAX_POLL_ROWS, MAX_PRESSURE_TABLES, MAX_QUALITY_CHECK_COLUMNS};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::future::Future;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncWrite, AsyncWriteExt, BufReader as AsyncBufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{broadcast, mpsc, oneshot, OnceCell};
+use tokio::task::{AbortHandle, JoinHandle, JoinSet};
+use uuid::Uuid;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct JsonRpcRequest {
+    /// Some lenient clients leave it out, so a missing version is taken to
+    /// be "2.0"; any other version is rejected.
+    #[serde(default = "default_jsonrpc_version")]
+    pub jsonrpc: String,
+    pub method: String,
+    pub params: Option<Value>,
+    pub id: Option<Value>,
+}
+
+fn default_jsonrpc_version() -> String {
+    "2.0".to_string()
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct JsonRpcResponse {
+    pub jsonrpc: String,
+    pub result: Option<Value>,
+    pub error: Option<Value>,
+    pub id: Option<Value>,
+}
+
+impl JsonRpcResponse {
+    fn error(id: Option<Value>, code: i64, message: &str) -> Self {
+        Self {
+            jsonrpc: "2.0".to_string(),
+            result: None,
+            error: Some(serde_json::json!({
+                "code": code,
+                "message": message
+            })),
+            id,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ToolCallParams {
+    name: String,
+    arguments: Option<Value>,
+}
+
+/// Default total retry time per tool call.
+pub const DEFAULT_RETRY_BUDGET: Duration = Duration::from_secs(10);
+
+/// Tools answered by the server itself, which don't need ClickHouse.
+const LOCAL_TOOLS: &[&str] = &["clear_context", "query_history", "recent_tables", "server_stats"];
+
+/// Default time a tool call waits for a disconnected ClickHouse to come back.
+pub const DEFAULT_RECONNECT_DEADLINE: Duration = Duration::from_secs(2);
+
+/// Default time table schemas stay cached.
+pub const DEFAULT_SCHEMA_CACHE_TTL: Duration = Duration::from_secs(300);
+
+/// The tool running arbitrary statements, only offered with
+/// [`McpServer::with_admin_enabled`].
+const ADMIN_TOOL: &str = "admin_execute";
+
+/// The table a call uses, as its `database` argument if given and `table`
+/// argument, for `recent_tables`. `table_exists` only asks about a name.
+fn used_table(tool: &str, arguments: Option<&Value>) -> Option<(Option<String>, String)> {
+    if tool == "table_exists" || !is_builtin_tool(tool) {
+        return None;
+    }
+    let arguments = arguments?;
+    let table = arguments.get("table")?.as_str()?;
+    let database = arguments.get("database").and_then(Value::as_str);
+    Some((database.map(str::to_string), table.to_string()))
+}
+
+/// The SQL a call of one of the [`QUERY_TOOLS`] submits, if `tool` is one
+/// and the argument is there.
+fn submitted_sql(tool: &str, arguments: Option<&Value>) -> Option<String> {
+    let (_, argument) = QUERY_TOOLS.iter().find(|(name, _)| *name == tool)?;
+    match arguments?.get(*argument)? {
+        Value::String(sql) => Some(sql.clone()),
+        Value::Array(statements) => {
+            let statements: Vec<&str> = statements.iter().filter_map(Value::as_str).collect();
+            Some(statements.join(";\n"))
+        }
+        _ => None,
+    }
+}
+
+/// How a probe turned out, for `refresh_metadata`: which tools with
+/// requirements can run, and what the others lack.
+fn capabilities_summary(capabilities: &Capabilities) -> String {
+    let available = capabilities.available_tools();
+    let mut summary = format!(
+        "Server capabilities probed again: {} of {} tools with requirements available\n",
+        available.len(),
+        TOOL_REQUIREMENTS.len()
+    );
+    for (tool, _) in TOOL_REQUIREMENTS {
+        let missing = capabilities.missing(tool);
+        if !missing.is_empty() {
+            let missing: Vec<String> = missing.iter().map(ToString::to_string).collect();
+            summary.push_str(&format!("- {}: unavailable, needs {}\n", tool, missing.join(", ")));
+        }
+    }
+    summary
+}
+
+/// The tools running SQL given by the client, whose calls are recorded in
+/// the session's query history, with the argument holding the SQL.
+const QUERY_TOOLS: &[(&str, &str)] = &[
+    ("admin_execute", "sql"),
+    ("analyze_index_usage", "query"),
+    ("estimate_cost", "query"),
+    ("explain_with_settings", "sql"),
+    ("run_script", "statements"),
+];
+
+/// What query tools answer instead of a result without rows, see
+/// [`McpServer::with_empty_result_message`].
+pub const EMPTY_RESULT_MESSAGE: &str = "Query returned 0 rows\n";
+
+/// How many queries `recent_queries` returns unless told otherwise.
+const DEFAULT_RECENT_QUERIES: u64 = 20;
+
+/// How many queries `query_history` returns unless told otherwise.
+const DEFAULT_QUERY_HISTORY_LIMIT: usize = 20;
+
+/// The tools whose text has sizes, counts or durations made readable by
+/// `crate::humanize`, which accept `raw: true` for plain numbers.
+const HUMANIZED_TOOLS: &[&str] = &[
+    "admin_execute",
+    "column_compression",
+    "count_rows",
+    "estimate_cost",
+    "group_count",
+    "insert_pressure",
+    "keeper_info",
+    "query_history",
+    "recent_queries",
+    "recommend_ordering",
+    "row_counts_over_time",
+    "run_script",
+    "server_metrics",
+    "server_stats",
+    "table_quality_check",
+];
+
+/// How many bytes of statements `dump_database_ddl` returns unless told
+/// otherwise.
+pub const DEFAULT_DDL_RESULT_BYTES: usize = 256 * 1024;
+
+/// The resource holding the current catalog version.
+pub const CATALOG_VERSION_URI: &str = "clickhouse://catalog/version";
+
+/// A tool call running in the background, cancellable via
+/// `notifications/cancelled`.
+struct InFlightRequest {
+    abort: AbortHandle,
+    query_id_prefix: String,
+}
+
+/// The handler of a tool registered with [`McpServer::register_tool`].
+pub type ToolHandler = Arc<dyn Fn(Value) -> Pin<Box<dyn Future<Output = Result<String>> + Send>> + Send + Sync>;
+
+/// A tool added by the embedding application.
+#[derive(Clone)]
+struct CustomTool {
+    /// The definition as listed, without the prefixed name.
+    definition: Value,
+    handler: ToolHandler,
+}
+
+#[derive(Clone)]
+pub struct McpServer {
+    initialized: bool,
+    /// Shared with the clones running tool calls, so a connection made
+    /// lazily by one of them is used by all.
+    connection: Arc<OnceCell<Arc<ConnectionManager>>>,
+    max_line_length: usize,
+    /// Results with larger `structuredContent` go out with the text only.
+    max_structured_bytes: usize,
+    tool_prefix: String,
+    retry_budget: Duration,
+    reconnect_deadline: Duration,
+    metrics: Arc<Metrics>,
+    config: Option<ClickHouseConfig>,
+    session_id: String,
+    query_comments: bool,
+    /// Tool calls of this session so far, numbering their query ids.
+    tool_call_count: Arc<AtomicU64>,
+    writes_enabled: bool,
+    admin_enabled: bool,
+    unavailable_tools: UnavailableTools,
+    database_visibility: DatabaseVisibility,
+    /// Whether query tools answer [`EMPTY_RESULT_MESSAGE`] for no rows.
+    empty_result_message: bool,
+    compression: Option<TransferCompression>,
+    user_agent: Option<String>,
+    schema_cache_ttl: Duration,
+    max_identifier_length: usize,
+    /// The most rows `admin_execute` reads of a SELECT.
+    max_admin_rows: Option<u64>,
+    ascii_identifiers_only: bool,
+    verbose_errors: bool,
+    session_timezone: Option<String>,
+    /// How often to probe ClickHouse in the background, if at all.
+    health_interval: Option<Duration>,
+    /// After how long without output `serve` sends the client a `ping`, if
+    /// at all.
+    keepalive_interval: Option<Duration>,
+    /// Where `serve` records the messages of the session, if anywhere.
+    recorder: Option<Arc<SessionRecorder>>,
+    health_probe: Arc<Mutex<Option<HealthProbe>>>,
+    /// The index in `LOG_LEVELS` of the least severe `notifications/message`
+    /// sent, set by `logging/setLevel`.
+    log_level: Arc<AtomicUsize>,
+    in_flight: Arc<Mutex<HashMap<String, InFlightRequest>>>,
+    /// The queries of this session's calls of the [`QUERY_TOOLS`].
+    query_history: Arc<QueryHistory>,
+    /// The tables this session's tool calls used, for `recent_tables` and
+    /// `table: "@last"`.
+    recent_tables: Arc<RecentTables>,
+    /// Results too long to return inline, read as resources.
+    results: Arc<ResultStore>,
+    custom_tools: Arc<BTreeMap<String, CustomTool>>,
+}
+
+impl Default for McpServer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl McpServer {
+    pub fn new() -> Self {
+        debug!("Creating new MCP server instance");
+        Self {
+            initialized: false,
+            connection: Arc::new(OnceCell::new()),
+            max_line_length: DEFAULT_MAX_LINE_LENGTH,
+            max_structured_bytes: DEFAULT_MAX_STRUCTURED_BYTES,
+            tool_prefix: String::new(),
+            retry_budget: DEFAULT_RETRY_BUDGET,
+            reconnect_deadline: DEFAULT_RECONNECT_DEADLINE,
+            metrics: Arc::new(Metrics::new()),
+            config: None,
+            session_id: Uuid::new_v4().simple().to_string(),
+            query_comments: true,
+            tool_call_count: Arc::new(AtomicU64::new(0)),
+            writes_enabled: false,
+            admin_enabled: false,
+            unavailable_tools: UnavailableTools::default(),
+            database_visibility: DatabaseVisibility::default(),
+            empty_result_message: true,
+            compression: None,
+            user_agent: None,
+            schema_cache_ttl: DEFAULT_SCHEMA_CACHE_TTL,
+            max_identifier_length: DEFAULT_MAX_IDENTIFIER_LENGTH,
+            max_admin_rows: None,
+            ascii_identifiers_only: false,
+            verbose_errors: false,
+            session_timezone: None,
+            health_interval: None,
+            keepalive_interval: None,
+            recorder: None,
+            health_probe: Arc::new(Mutex::new(None)),
+            log_level: Arc::new(AtomicUsize::new(DEFAULT_LOG_LEVEL)),
+            in_flight: Arc::new(Mutex::new(HashMap::new())),
+            query_history: Arc::new(QueryHistory::new()),
+            recent_tables: Arc::new(RecentTables::new()),
+            results: Arc::new(ResultStore::default()),
+            custom_tools: Arc::new(BTreeMap::new()),
+        }
+    }
+
+    /// Bounds the total time a single tool call spends retrying failed
+    /// ClickHouse operations, across all the queries it makes.
+    pub fn with_retry_budget(mut self, budget: Duration) -> Self {
+        self.retry_budget = budget;
+        self
+    }
+
+    /// How long a tool call waits for ClickHouse to become reachable when the
+    /// server is not connected, re-attempting the connection meanwhile. Zero
+    /// fails such calls right away.
+    pub fn with_reconnect_deadline(mut self, deadline: Duration) -> Self {
+        self.reconnect_deadline = deadline;
+        self
+    }
+
+    /// Records into `metrics` instead of a registry of its own. Pass the same
+    /// registry to the client of a connection given to `with_connection` to
+    /// have its query latencies and retries included.
+    pub fn with_metrics(mut self, metrics: Arc<Metrics>) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
+    /// Whether queries are tagged with the tool call that issued them: a
+    /// `/* mcp-test session=<id> request=<id> tool=<name> */` comment, the
+    /// same text as their `log_comment` setting, and a `query_id` of
+    /// `mcp-<session>-<call>:<n>`. On by default; when off, query ids are
+    /// random and carry nothing about the session.
+    pub fn with_query_comments(mut self, enabled: bool) -> Self {
+        self.query_comments = enabled;
+        self
+    }
+
+    /// Lets the `run_script` tool change data and schema. Off by default;
+    /// applies to the connection made from the configuration.
+    pub fn with_writes_enabled(mut self, enabled: bool) -> Self {
+        self.writes_enabled = enabled;
+        self
+    }
+
+    /// Offers the `admin_execute` tool, which runs arbitrary statements
+    /// other than the [`crate::sql::ADMIN_DENIED_STATEMENTS`] after an
+    /// explicit confirmation, and audits every call on stderr. Off by
+    /// default, in which case the tool is neither listed nor callable.
+    pub fn with_admin_enabled(mut self, enabled: bool) -> Self {
+        self.admin_enabled = enabled;
+        self
+    }
+
+    /// What `tools/list` does with tools the server lacks the system tables
+    /// or functions for, once they were probed: hides them (the default) or
+    /// lists them marked as unavailable. Calling such a tool fails either
+    /// way.
+    pub fn with_unavailable_tools(mut self, mode: UnavailableTools) -> Self {
+        self.unavailable_tools = mode;
+        self
+    }
+
+    /// Whether listings leave out the system databases unless a call
+    /// passes `include_system: true`; on by default.
+    pub fn with_hide_system_databases(mut self, hide: bool) -> Self {
+        self.database_visibility = DatabaseVisibility::new(hide);
+        self
+    }
+
+    /// Whether query tools answer [`EMPTY_RESULT_MESSAGE`] when a query
+    /// returns no rows, rather than an empty listing or `[]` that models
+    /// tend to misread; on by default. CSV output keeps its header either way.
+    pub fn with_empty_result_message(mut self, enabled: bool) -> Self {
+        self.empty_result_message = enabled;
+        self
+    }
+
+    /// Sets how the connection made from the configuration compresses
+    /// transfers with ClickHouse, instead of the client's default (LZ4).
+    pub fn with_compression(mut self, compression: TransferCompression) -> Self {
+        self.compression = Some(compression);
+        self
+    }
+
+    /// Sets the `User-Agent` the connection made from the configuration
+    /// sends, instead of the client's default (`mcp-test/<version>`).
+    pub fn with_user_agent(mut self, user_agent: impl Into<String>) -> Self {
+        self.user_agent = Some(user_agent.into());
+        self
+    }
+
+    /// How long the connection made from the configuration caches table
+    /// schemas; zero turns caching off. `refresh_metadata` clears the cache.
+    pub fn with_schema_cache_ttl(mut self, ttl: Duration) -> Self {
+        self.schema_cache_ttl = ttl;
+        self
+    }
+
+    /// Sets the longest identifier, in bytes, that the connection made from
+    /// the configuration accepts.
+    pub fn with_max_identifier_length(mut self, max_length: usize) -> Self {
+        self.max_identifier_length = max_length;
+        self
+    }
+
+    /// Sets the most rows `admin_execute` reads of a SELECT on the
+    /// connection made from the configuration, see
+    /// [`ClickHouseClient::with_max_raw_rows`].
+    pub fn with_max_admin_rows(mut self, max_rows: u64) -> Self {
+        self.max_admin_rows = Some(max_rows);
+        self
+    }
+
+    /// Makes the connection made from the configuration reject non-ASCII
+    /// identifiers, see [`ClickHouseClient::with_ascii_identifiers_only`].
+    pub fn with_ascii_identifiers_only(mut self, enabled: bool) -> Self {
+        self.ascii_identifiers_only = enabled;
+        self
+    }
+
+    /// Makes the connection made from the configuration run queries over
+    /// user data in `timezone`, see
+    /// [`ClickHouseClient::with_session_timezone`].
+    pub fn with_session_timezone(mut self, timezone: impl Into<String>) -> Self {
+        self.session_timezone = Some(timezone.into());
+        self
+    }
+
+    /// Makes the connection made from the configuration keep ClickHouse's
+    /// own error messages, see [`ClickHouseClient::with_verbose_errors`];
+    /// failed tool calls then show them after the mapped error.
+    pub fn with_verbose_errors(mut self, enabled: bool) -> Self {
+        self.verbose_errors = enabled;
+        self
+    }
+
+    /// Runs the health check every `interval` in the background once
+    /// connected, so an unreachable ClickHouse is logged and reported to the
+    /// client as a `notifications/message` without waiting for a tool call.
+    pub fn with_health_interval(mut self, interval: Duration) -> Self {
+        self.health_interval = Some(interval);
+        self
+    }
+
+    /// Sends the client a `ping` request whenever nothing was written to it
+    /// for `interval`, for transports whose connections proxies close when
+    /// idle. Off by default; the answers are ignored.
+    pub fn with_keepalive_interval(mut self, interval: Duration) -> Self {
+        self.keepalive_interval = Some(interval);
+        self
+    }
+
+    /// Keeps results too long to return inline for `ttl`, and at most
+    /// `max_results` of them, by default
+    /// [`DEFAULT_RESULT_TTL`](crate::results::DEFAULT_RESULT_TTL) and
+    /// [`DEFAULT_MAX_RESULTS`](crate::results::DEFAULT_MAX_RESULTS).
+    pub fn with_result_store(mut self, ttl: Duration, max_results: usize) -> Self {
+        self.results = Arc::new(ResultStore::new(ttl, max_results));
+        self
+    }
+
+    /// Records every message `serve` reads and writes to a transcript in
+    /// `dir`, see [`crate::transcript`]. Call after [`Self::with_config`] so
+    /// the password and bearer token of the configuration are redacted.
+    pub fn with_session_recording(mut self, dir: &std::path::Path) -> Result<Self> {
+        let secrets = self.config.as_ref().map_or_else(Vec::new, |config| vec![config.password.clone(), config.bearer_token.clone()]);
+        let recorder = SessionRecorder::create(dir, &self.session_id, secrets)?;
+        info!("Recording the session to {}", recorder.path().display());
+        self.recorder = Some(Arc::new(recorder));
+        Ok(self)
+    }
+
+    /// Connects with the given settings on `initialized` instead of reading
+    /// them from the environment.
+    pub fn with_config(mut self, config: ClickHouseConfig) -> Self {
+        self.config = Some(config);
+        self
+    }
+
+    /// Uses an existing ClickHouse connection instead of connecting from the
+    /// environment on `initialized`.
+    pub fn with_connection(mut self, connection: Arc<ConnectionManager>) -> Self {
+        self.connection = Arc::new(OnceCell::new_with(Some(connection)));
+        self
+    }
+
+    /// Prefixes every advertised tool name, e.g. `clickhouse_` to keep tools
+    /// distinct from other MCP servers. Calls may use prefixed or bare names.
+    pub fn with_tool_prefix(mut self, prefix: &str) -> Result<Self> {
+        let builtin = tool_definitions().into_iter().map(|tool| tool["name"].as_str().unwrap_or_default().to_string());
+        for tool in builtin.chain(self.custom_tools.keys().cloned()) {
+            let name = format!("{}{}", prefix, tool);
+            if !is_valid_tool_name(&name) {
+                return Err(anyhow::anyhow!(
+                    "Tool prefix '{}' produces invalid tool name '{}' (allowed: letters, digits, '_' and '-', at most 64 characters)",
+                    prefix,
+                    name
+                ));
+            }
+        }
+        
+        self.tool_prefix = prefix.to_string();
+        Ok(self)
+    }
+
+    /// Adds a tool of the embedding application, listed and called like the
+    /// built-in ones (with the tool prefix, if any). `definition` is the tool
+    /// as listed without its name, e.g. `{"description": .., "inputSchema":
+    /// ..}`. `handler` gets the call's arguments, `{}` if none were sent, and
+    /// its text is the result; errors are reported like those of built-in
+    /// tools, so a [`ClickHouseError`] becomes an `isError` result and
+    /// anything else invalid params. Custom tools don't wait for ClickHouse
+    /// to be reachable.
+    pub fn register_tool<F, Fut>(&mut self, name: &str, definition: Value, handler: F) -> Result<()>
+    where
+        F: Fn(Value) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<String>> + Send + 'static,
+    {
+        if is_builtin_tool(name) || self.custom_tools.contains_key(name) {
+            return Err(anyhow::anyhow!("A tool named '{}' already exists", name));
+        }
+        let prefixed = format!("{}{}", self.tool_prefix, name);
+        if !is_valid_tool_name(&prefixed) {
+            return Err(anyhow::anyhow!(
+                "Invalid tool name '{}' (allowed: letters, digits, '_' and '-', at most 64 characters)",
+                prefixed
+            ));
+        }
+        let Value::Object(mut definition) = definition else {
+            return Err(anyhow::anyhow!("The definition of tool '{}' must be a JSON object", name));
+        };
+        definition.remove("name");
+        definition.entry("inputSchema").or_insert_with(|| serde_json::json!({"type": "object", "properties": {}}));
+        
+        let handler: ToolHandler = Arc::new(move |arguments| Box::pin(handler(arguments)));
+        Arc::make_mut(&mut self.custom_tools).insert(name.to_string(), CustomTool { definition: Value::Object(definition), handler });
+        info!("Registered custom tool '{}'", name);
+        Ok(())
+    }
+
+    /// Caps the size of a single input line; longer lines are discarded and
+    /// answered with a parse error.
+    pub fn with_max_line_length(mut self, max_line_length: usize) -> Self {
+        self.max_line_length = max_line_length;
+        self
+    }
+
+    /// Caps the serialized size of a tool result's `structuredContent`;
+    /// larger payloads are left out, see [`ToolResult::limit_structured`].
+    pub fn with_max_structured_bytes(mut self, max_bytes: usize) -> Self {
+        self.max_structured_bytes = max_bytes;
+        self
+    }
+
+    async fn connect_clickhouse(&mut self) -> Result<()> {
+        let config = match self.config.take() {
+            Some(config) => config,
+            None => ClickHouseConfig::from_env()?,
+        };
+        let config = self.config.insert(config);
+        
+        info!("Connecting to ClickHouse at {} with database {}", redact_url(&config.url), config.database);
+        
+        let mut client = ClickHouseClient::from_config(config)
+            .with_retry_config(3, std::time::Duration::from_millis(100))
+            .with_case_insensitive_resolution(true)
+            .with_writes_enabled(self.writes_enabled)
+            .with_max_identifier_length(self.max_identifier_length)
+            .with_ascii_identifiers_only(self.ascii_identifiers_only)
+            .with_verbose_errors(self.verbose_errors)
+            .with_metrics(Arc::clone(&self.metrics));
+        if let Some(compression) = self.compression {
+            client = client.with_compression(compression);
+        }
+        if let Some(user_agent) = &self.user_agent {
+            client = client.with_user_agent(user_agent.as_str());
+        }
+        if let Some(max_rows) = self.max_admin_rows {
+            client = client.with_max_raw_rows(max_rows);
+        }
+        if let Some(timezone) = &self.session_timezone {
+            client = client.with_session_timezone(timezone.as_str());
+        }
+        if !self.schema_cache_ttl.is_zero() {
+            client = client.with_schema_cache(self.schema_cache_ttl);
+        }
+        
+        // A concurrent tool call may have connected first
+        let connection = Arc::clone(self.connection.get_or_init(|| async { Arc::new(ConnectionManager::new(client)) }).await);
+        self.ensure_health_probe();
+        
+        // Perform health check
+        let connected = connection.connect().await;
+        self.log_effective_config();
+        
+        match connected {
+            Ok(_) => {
+                info!("ClickHouse connection established successfully");
+                if let Ok(client) = connection.client().await {
+                    if let Err(e) = client.probe_capabilities().await {
+                        warn!("Failed to probe server capabilities, listing every tool: {}", e);
+                    }
+                    // Schemas show it for date and time columns without a time zone
+                    if let Err(e) = client.server_timezone().await {
+                        warn!("Could not read the server's time zone: {}", e);
+                    }
+                }
+                Ok(())
+            }
+            Err(e) => {
+                error!("ClickHouse connection failed: {}", e);
+                connection.ensure_background_reconnect();
+                Err(anyhow::anyhow!("ClickHouse connection failed: {}", e))
+            }
+        }
+    }
+
+    /// Gives a server without a working connection one more chance before a
+    /// tool call, so a ClickHouse that came up after the server is picked up
+    /// without a restart. Failures are left for the tool call to report.
+    async fn reconnect_if_needed(&mut self) {
+        if self.reconnect_deadline.is_zero() {
+            return;
+        }
+        
+        let connection = match self.connection.get() {
+            Some(connection) if connection.state() != ConnectionState::Disconnected => return,
+            Some(connection) => Arc::clone(connection),
+            None => {
+                info!("No ClickHouse connection yet, connecting before tool call");
+                if self.connect_clickhouse().await.is_ok() {
+                    return;
+                }
+                match self.connection.get() {
+                    Some(connection) => Arc::clone(connection),
+                    None => return,
+                }
+            }
+        };
+        
+        if let Err(e) = connection.wait_until_ready(self.reconnect_deadline).await {
+            debug!("Reconnect before tool call failed: {}", e);
+        }
+    }
+
+    /// The current metrics, with the connection state filled in.
+    pub fn metrics_snapshot(&self) -> MetricsSnapshot {
+        snapshot_with_state(&self.metrics, &self.connection)
+    }
+
+    /// Serves the metrics in the Prometheus text format at `/metrics` on
+    /// `address` from a background task. Fails if the address can't be
+    /// bound; returns the bound address, useful with port 0.
+    pub async fn start_metrics_listener(&self, address: SocketAddr) -> Result<SocketAddr> {
+        let listener = TcpListener::bind(address)
+            .await
+            .map_err(|e| anyhow::anyhow!("Cannot listen for metrics on {}: {}", address, e))?;
+        let bound = listener.local_addr()?;
+        info!("Serving Prometheus metrics at http://{}/metrics", bound);
+        
+        let metrics = Arc::clone(&self.metrics);
+        let connection = Arc::clone(&self.connection);
+        tokio::spawn(async move {
+            loop {
+                let (stream, peer) = match listener.accept().await {
+                    Ok(accepted) => accepted,
+                    Err(e) => {
+                        warn!("Failed to accept metrics connection: {}", e);
+                        continue;
+                    }
+                };
+                let snapshot = snapshot_with_state(&metrics, &connection);
+                tokio::spawn(async move {
+                    if let Err(e) = serve_metrics_request(stream, snapshot).await {
+                        debug!("Metrics request from {} failed: {}", peer, e);
+                    }
+                });
+            }
+        });
+        
+        Ok(bound)
+    }
+
+    /// Logs the configuration the server is actually running with, so
+    /// operators can spot misconfiguration. Secrets are never logged.
+    pub fn log_effective_config(&self) {
+        let source = |key: &str| {
+            self.config
+                .as_ref()
+                .and_then(|config| config.source(key))
+                .map_or_else(String::new, |source| format!(" (from {})", source))
+        };
+        info!("Effective configuration:");
+        if let Some(connection) = self.connection.get() {
+            for (key, value) in connection.client_config() {
+                // The client's raw row limit is the admin_max_rows setting
+                let setting = if key == "max_raw_rows" { "admin_max_rows" } else { key };
+                info!("  clickhouse.{}: {}{}", key, value, source(setting));
+            }
+            info!("  clickhouse.connection_state: {:?}", connection.state());
+        } else {
+            info!("  clickhouse: not connected");
+        }
+        info!("  tool_prefix: {:?}", self.tool_prefix);
+        info!("  max_line_length: {}{}", self.max_line_length, source("max_line_length"));
+        info!("  max_structured_bytes: {}{}", self.max_structured_bytes, source("max_structured_bytes"));
+        info!("  retry_budget: {:?}", self.retry_budget);
+        info!("  reconnect_deadline: {:?}{}", self.reconnect_deadline, source("reconnect_deadline_ms"));
+        info!("  writes_enabled: {}", self.writes_enabled);
+        info!("  admin_enabled: {}", self.admin_enabled);
+        info!("  unavailable_tools: {}", self.unavailable_tools.as_str());
+        info!("  hide_system_databases: {}", self.database_visibility.hide_system);
+        info!("  empty_result_message: {}", self.empty_result_message);
+        info!("  schema_cache_ttl: {:?}{}", self.schema_cache_ttl, source("schema_cache_ttl_secs"));
+        info!("  result_ttl: {:?}{}", self.results.ttl(), source("result_ttl_secs"));
+        info!("  max_stored_results: {}{}", self.results.max_results(), source("max_stored_results"));
+        info!("  query_tagging: {}{}", self.query_comments, source("query_tagging"));
+        info!("  health_interval: {:?}", self.health_interval);
+        info!("  keepalive_interval: {:?}", self.keepalive_interval);
+        info!("  default_response_format: {:?}", ResponseFormat::default());
+    }
+
+    async fn client(&self) -> Result<&ClickHouseClient, ClickHouseError> {
+        let connection = self.connection.get()
+            .ok_or_else(|| ClickHouseError::ServiceUnavailable {
+                message: "ClickHouse client not connected".to_string(),
+            })?;
+        
+        connection.client().await
+    }
+
+    pub async fn handle_request(&mut self, request: JsonRpcRequest) -> Result<JsonRpcResponse> {
+        debug!("Handling request: method={}, id={:?}", request.method, request.id);
+        
+        // Unknown methods are counted together to bound the label values
+        let method = if KNOWN_METHODS.contains(&request.method.as_str()) { request.method.as_str() } else { "unknown" };
+        self.metrics.record_request(method);
+        
+        match request.method.as_str() {
+            "initialize" => self.handle_initialize(request).await,
+            "initialized" => self.handle_initialized(request).await,
+            "ping" => self.handle_ping(request),
+            "tools/list" => self.handle_tools_list(request).await,
+            "tools/call" => {
+                let tool = self.called_tool(&request);
+                if tool.as_deref().is_some_and(|tool| is_builtin_tool(tool) && !LOCAL_TOOLS.contains(&tool)) {
+                    self.reconnect_if_needed().await;
+                }
+                let comment = self.query_comment(&request);
+                let call = with_retry_budget(self.retry_budget, self.handle_tools_call(request));
+                let call = async {
+                    match comment {
+                        Some(comment) => with_query_comment(&comment, call).await,
+                        None => call.await,
+                    }
+                };
+                // Calls read from a stream already got their ids in spawn_tool_call
+                let response = match query_id_prefix() {
+                    Some(_) => call.await,
+                    None => with_query_id(self.next_query_id_prefix(), call).await,
+                };
+                let success = matches!(
+                    &response,
+                    Ok(response) if response.error.is_none()
+                        && response.result.as_ref().and_then(|r| r.get("isError")) != Some(&Value::Bool(true))
+                );
+                let tool = tool.as_deref().unwrap_or("unknown");
+                self.metrics.record_tool_call(tool, success);
+                if let Ok(response) = &response {
+                    // Measured as serialized on the wire, without the trailing newline
+                    let bytes = serde_json::to_vec(response).map(|bytes| bytes.len()).unwrap_or_default();
+                    self.metrics.record_tool_response_bytes(tool, bytes);
+                }
+                response
+            }
+            "notifications/cancelled" => self.handle_cancelled(request).await,
+            "prompts/list" => self.handle_prompts_list(request).await,
+            "prompts/get" => self.handle_prompts_get(request).await,
+            "resources/list" => self.handle_resources_list(request).await,
+            "resources/read" => self.handle_resources_read(request).await,
+            "logging/setLevel" => self.handle_logging_set_level(request).await,
+            _ => {
+                warn!("Unknown method requested: {}", request.method);
+                Ok(JsonRpcResponse {
+                    jsonrpc: "2.0".to_string(),
+                    result: None,
+                    error: Some(serde_json::json!({
+                        "code": -32601,
+                        "message": "Method not found"
+                    })),
+                    id: request.id,
+                })
+            }
+        }
+    }
+
+    async fn handle_initialize(&mut self, request: JsonRpcRequest) -> Result<JsonRpcResponse> {
+        info!("Initializing MCP server");
+        
+        let response = JsonRpcResponse {
+            jsonrpc: "2.0".to_string(),
+            result: Some(serde_json::json!({
+                "protocolVersion": "2024-11-05",
+                "capabilities": {
+                    "tools": {
+                        "listChanged": false
+                    },
+                    "resources": {},
+                    "prompts": {
+                        "listChanged": false
+                    },
+                    "logging": {}
+                },
+                "serverInfo": {
+                    "name": "mcp-test",
+                    "version": "0.1.0"
+                }
+            })),
+            error: None,
+            id: request.id,
+        };
+        
+        debug!("Sent initialize response");
+        Ok(response)
+    }
+
+    /// Answers at once with an empty result, before `initialize` too; hosts
+    /// send it to check the server is still there.
+    fn handle_ping(&self, request: JsonRpcRequest) -> Result<JsonRpcResponse> {
+        Ok(JsonRpcResponse {
+            jsonrpc: "2.0".to_string(),
+            result: Some(serde_json::json!({})),
+            error: None,
+            id: request.id,
+        })
+    }
+
+    async fn handle_initialized(&mut self, request: JsonRpcRequest) -> Result<JsonRpcResponse> {
+        self.initialized = true;
+        info!("MCP server initialization completed");
+        
+        if !self.connection.initialized() {
+            if let Err(e) = self.connect_clickhouse().await {
+                warn!("Failed to connect to ClickHouse: {}", e);
+            }
+        }
+        
+        Ok(JsonRpcResponse {
+            jsonrpc: "2.0".to_string(),
+            result: Some(serde_json::json!({})),
+            error: None,
+            id: request.id,
+        })
+    }
+
+    async fn handle_logging_set_level(&self, request: JsonRpcRequest) -> Result<JsonRpcResponse> {
+        let level = request.params.as_ref().and_then(|p| p.get("level")).and_then(|v| v.as_str()).unwrap_or_default();
+        let Some(index) = LOG_LEVELS.iter().position(|known| *known == level) else {
+            let message = format!("Invalid params: unknown log level '{}', expected one of {}", level, LOG_LEVELS.join(", "));
+            return Ok(JsonRpcResponse::error(request.id, -32602, &message));
+        };
+        
+        info!("Client log level set to {}", level);
+        self.log_level.store(index, Ordering::SeqCst);
+        Ok(JsonRpcResponse {
+            jsonrpc: "2.0".to_string(),
+            result: Some(serde_json::json!({})),
+            error: None,
+            id: request.id,
+        })
+    }
+
+    async fn handle_cancelled(&self, request: JsonRpcRequest) -> Result<JsonRpcResponse> {
+        let request_id = request.params.as_ref().and_then(|p| p.get("requestId")).cloned();
+        
+        let cancelled = request_id
+            .as_ref()
+            .and_then(|id| self.in_flight.lock().unwrap().remove(&id.to_string()));
+        
+        match cancelled {
+            Some(in_flight) => {
+                info!("Cancelling request {}", request_id.unwrap_or_default());
+                in_flight.abort.abort();
+                
+                // Dropping the HTTP request does not stop the query on the
+                // server, so kill whatever the tool call had started.
+                if let Some(connection) = self.connection.get() {
+                    if let Ok(client) = connection.client().await {
+                        if let Err(e) = client.kill_queries(&in_flight.query_id_prefix).await {
+                            warn!("Failed to kill queries of cancelled request: {}", e);
+                        }
+                    }
+                }
+            }
+            None => debug!("Ignoring cancellation of unknown or finished request {:?}", request_id),
+        }
+        
+        Ok(JsonRpcResponse {
+            jsonrpc: "2.0".to_string(),
+            result: Some(serde_json::json!({})),
+            error: None,
+            id: request.id,
+        })
+    }
+
+    async fn handle_tools_list(&self, request: JsonRpcRequest) -> Result<JsonRpcResponse> {
+        debug!("Listing available tools");
+        
+        let custom = self.custom_tools.iter().map(|(name, tool)| {
+            let mut definition = serde_json::json!({"name": name});
+            if let (Value::Object(definition), Value::Object(fields)) = (&mut definition, &tool.definition) {
+                definition.extend(fields.clone());
+            }
+            definition
+        });
+        let admin = self.admin_enabled.then(admin_tool_definition);
+        let builtin = tool_definitions().into_iter().chain(admin).collect();
+        let tools: Vec<Value> = apply_capabilities(builtin, self.capabilities().as_ref(), self.unavailable_tools)
+            .into_iter()
+            .chain(custom)
+            .map(|mut tool| {
+                if let Some(name) = tool["name"].as_str() {
+                    tool["name"] = Value::String(format!("{}{}", self.tool_prefix, name));
+                }
+                tool
+            })
+            .collect();
+        
+        Ok(JsonRpcResponse {
+            jsonrpc: "2.0".to_string(),
+            result: Some(serde_json::json!({"tools": tools})),
+            error: None,
+            id: request.id,
+        })
+    }
+
+    async fn handle_prompts_list(&self, request: JsonRpcRequest) -> Result<JsonRpcResponse> {
+        debug!("Listing available prompts");
+        
+        let prompts: Vec<Value> = PROMPTS.iter().map(Prompt::definition).collect();
+        
+        Ok(JsonRpcResponse {
+            jsonrpc: "2.0".to_string(),
+            result: Some(serde_json::json!({"prompts": prompts})),
+            error: None,
+            id: request.id,
+        })
+    }
+
+    async fn handle_prompts_get(&self, request: JsonRpcRequest) -> Result<JsonRpcResponse> {
+        let params = request.params.unwrap_or_default();
+        let name = params.get("name").and_then(|v| v.as_str()).unwrap_or_default();
+        let arguments = params.get("arguments").and_then(|v| v.as_object()).cloned().unwrap_or_default();
+        debug!("Getting prompt: {}", name);
+        
+        let rendered = match Prompt::find(name) {
+            Some(prompt) => prompt
+                .render(&arguments, &self.tool_prefix)
+                .map(|text| (prompt, text))
+                .map_err(|e| format!("Invalid params: {}", e)),
+            None => Err(format!("Invalid params: Unknown prompt: {}", name)),
+        };
+        
+        Ok(match rendered {
+            Ok((prompt, text)) => JsonRpcResponse {
+                jsonrpc: "2.0".to_string(),
+                result: Some(serde_json::json!({
+                    "description": prompt.description,
+                    "messages": [{
+                        "role": "user",
+                        "content": Content::text(text)
+                    }]
+                })),
+                error: None,
+                id: request.id,
+            },
+            Err(message) => JsonRpcResponse {
+                jsonrpc: "2.0".to_string(),
+                result: None,
+                error: Some(serde_json::json!({
+                    "code": -32602,
+                    "message": message
+                })),
+                id: request.id,
+            },
+        })
+    }
+
+    async fn handle_resources_list(&self, request: JsonRpcRequest) -> Result<JsonRpcResponse> {
+        debug!("Listing available resources");
+        
+        let mut resources = vec![serde_json::json!({
+            "uri": CATALOG_VERSION_URI,
+            "name": "catalog_version",
+            "description": "The current catalog version token and table count; changes whenever a table is created, altered or dropped",
+            "mimeType": "application/json"
+        })];
+        for stored in self.results.live() {
+            resources.push(serde_json::json!({
+                "uri": stored.uri(),
+                "name": format!("{} result", stored.tool),
+                "description": format!(
+                    "{} rows stored {} ago, expiring in {}; add ?format=csv for CSV",
+                    human_count(stored.rows.len() as u64),
+                    human_duration(stored.age()),
+                    human_duration(self.results.ttl().saturating_sub(stored.age()))
+                ),
+                "mimeType": ResultFormat::JsonLines.mime_type()
+            }));
+        }
+        
+        Ok(JsonRpcResponse {
+            jsonrpc: "2.0".to_string(),
+            result: Some(serde_json::json!({ "resources": resources })),
+            error: None,
+            id: request.id,
+        })
+    }
+
+    async fn handle_resources_read(&self, request: JsonRpcRequest) -> Result<JsonRpcResponse> {
+        let uri = request.params.as_ref().and_then(|params| params.get("uri")).and_then(|v| v.as_str()).unwrap_or_default();
+        debug!("Reading resource: {}", uri);
+        
+        if let Some(parsed) = parse_result_uri(uri) {
+            return Ok(self.read_result(request.id, uri, parsed));
+        }
+        if uri != CATALOG_VERSION_URI {
+            return Ok(JsonRpcResponse::error(request.id, -32602, &format!("Invalid params: Unknown resource: {}", uri)));
+        }
+        let version = match self.client().await {
+            Ok(client) => client.catalog_version(self.database_visibility.hidden_databases(None)).await,
+            Err(e) => Err(e),
+        };
+        Ok(match version {
+            Ok(version) => JsonRpcResponse {
+                jsonrpc: "2.0".to_string(),
+                result: Some(serde_json::json!({
+                    "contents": [{
+                        "uri": CATALOG_VERSION_URI,
+                        "mimeType": "application/json",
+                        "text": serde_json::to_string(&version)?
+                    }]
+                })),
+                error: None,
+                id: request.id,
+            },
+            Err(e) => JsonRpcResponse::error(request.id, -32603, &format!("Internal error: {}", e)),
+        })
+    }
+
+    /// The `resources/read` response for a stored result.
+    fn read_result(&self, id: Option<Value>, uri: &str, parsed: std::result::Result<(&str, ResultFormat), String>) -> JsonRpcResponse {
+        let (result_id, format) = match parsed {
+            Ok(parsed) => parsed,
+            Err(message) => return JsonRpcResponse::error(id, -32602, &format!("Invalid params: {}", message)),
+        };
+        let Some(stored) = self.results.get(result_id) else {
+            return JsonRpcResponse::error(
+                id,
+                -32602,
+                &format!("Invalid params: Unknown resource: {} (results expire after {})", uri, human_duration(self.results.ttl())),
+            );
+        };
+        JsonRpcResponse {
+            jsonrpc: "2.0".to_string(),
+            result: Some(serde_json::json!({
+                "contents": [{
+                    "uri": uri,
+                    "mimeType": format.mime_type(),
+                    "text": render_rows(&stored.rows, &stored.columns, format)
+                }]
+            })),
+            error: None,
+            id,
+        }
+    }
+
+    /// Keeps `rows` with their `columns` in the result store when there are
+    /// more than [`RESULT_PREVIEW_ROWS`], returning the rows to show inline
+    /// and the stored result, if any.
+    fn store_long_result(&self, tool: &str, columns: Vec<String>, rows: Vec<Value>) -> (Vec<Value>, Option<StoredResult>) {
+        if rows.len() <= RESULT_PREVIEW_ROWS {
+            return (rows, None);
+        }
+        let preview = rows[..RESULT_PREVIEW_ROWS].to_vec();
+        let stored = self.results.insert(tool, columns, rows);
+        debug!("Stored {} rows of {} as {}", stored.rows.len(), tool, stored.uri());
+        (preview, Some(stored))
+    }
+
+    /// The lines following the preview of a stored result.
+    fn stored_result_notice(&self, stored: &StoredResult) -> String {
+        format!(
+            "{}\nThe full result is the resource {}, as JSON lines, or as CSV with ?format=csv; read it with resources/read within {}\n",
+            truncation_notice(RESULT_PREVIEW_ROWS, Some(stored.rows.len()), "stored"),
+            stored.uri(),
+            human_duration(self.results.ttl())
+        )
+    }
+
+    /// What the last capability probe of the connection found, if any.
+    fn capabilities(&self) -> Option<Capabilities> {
+        self.connection.get()?.capabilities()
+    }
+
+    /// The error for calling `tool` on a server lacking what it needs.
+    fn unavailable_error(&self, tool: &str) -> Option<ClickHouseError> {
+        let missing = self.capabilities()?.missing(tool);
+        (!missing.is_empty()).then(|| ClickHouseError::ToolUnavailable {
+            tool: tool.to_string(),
+            missing: missing.iter().map(ToString::to_string).collect::<Vec<_>>().join(", "),
+        })
+    }
+
+    /// Accepts both the advertised prefixed name and the bare name.
+    fn bare_tool_name<'a>(&self, name: &'a str) -> &'a str {
+        match name.strip_prefix(self.tool_prefix.as_str()) {
+            Some(bare) if !self.tool_prefix.is_empty() && self.is_known_tool(bare) => bare,
+            _ => name,
+        }
+    }
+
+    fn is_known_tool(&self, name: &str) -> bool {
+        (is_builtin_tool(name) && (name != ADMIN_TOOL || self.admin_enabled)) || self.custom_tools.contains_key(name)
+    }
+
+    /// The bare name of the tool a `tools/call` request calls, if it exists.
+    fn called_tool(&self, request: &JsonRpcRequest) -> Option<String> {
+        let name = request.params.as_ref()?.get("name")?.as_str()?;
+        Some(self.bare_tool_name(name)).filter(|name| self.is_known_tool(name)).map(str::to_string)
+    }
+
+    /// The audit comment for the queries of a `tools/call` request.
+    fn query_comment(&self, request: &JsonRpcRequest) -> Option<String> {
+        if !self.query_comments {
+            return None;
+        }
+        
+        let name = request.params.as_ref()?.get("name")?.as_str()?;
+        let name = self.bare_tool_name(name);
+        let tool = if self.is_known_tool(name) { name } else { "unknown" };
+        // Anything but a plain token would blur where the id ends
+        let request_id: String = match &request.id {
+            Some(id) => id.to_string().chars().map(|c| if c.is_ascii_alphanumeric() || "-_.".contains(c) { c } else { '_' }).collect(),
+            None => "none".to_string(),
+        };
+        Some(format!("{} session={} request={} tool={}", env!("CARGO_PKG_NAME"), self.session_id, request_id, tool))
+    }
+
+    /// The `query_id` prefix of the queries of the next tool call.
+    fn next_query_id_prefix(&self) -> String {
+        if !self.query_comments {
+            return format!("mcp-{}", Uuid::new_v4().simple());
+        }
+        let call = self.tool_call_count.fetch_add(1, Ordering::SeqCst) + 1;
+        format!("{}{}", self.own_query_id_prefix(), call)
+    }
+
+    /// What the query ids of this session start with while tagging is on.
+    fn own_query_id_prefix(&self) -> String {
+        format!("mcp-{}-", self.session_id)
+    }
+
+    async fn handle_tools_call(&self, request: JsonRpcRequest) -> Result<JsonRpcResponse> {
+        let params: ToolCallParams = serde_json::from_value(request.params.unwrap_or_default())?;
+        debug!("Calling tool: {}", params.name);
+        
+        let name = self.bare_tool_name(&params.name);
+        
+        let raw = params.arguments.as_ref().and_then(|args| args.get("raw")).and_then(Value::as_bool).unwrap_or(false);
+        let sql = submitted_sql(name, params.arguments.as_ref());
+        let started = Instant::now();
+        let result = match self.resolve_last_table(name, params.arguments) {
+            Ok(arguments) => {
+                let used = used_table(name, arguments.as_ref());
+                let result = with_raw_numbers(raw, self.dispatch_tool(name, &params.name, arguments)).await;
+                if let (Ok(ToolResult { is_error: false, .. }), Some((database, table))) = (&result, used) {
+                    self.remember_table(database.as_deref(), &table).await;
+                }
+                result
+            }
+            Err(e) => Err(anyhow::anyhow!(e)),
+        };
+        if let Some(sql) = sql {
+            let error = match &result {
+                Ok(result) if result.is_error => {
+                    Some(result.content.iter().map(|Content::Text { text }| text.as_str()).collect::<String>())
+                }
+                Ok(_) => None,
+                Err(e) => Some(e.to_string()),
+            };
+            self.query_history.record(QueryHistoryEntry::new(name, sql, started.elapsed(), error));
+        }
+        
+        match result {
+            Ok(mut result) => {
+                if let Some(size) = result.limit_structured(self.max_structured_bytes) {
+                    warn!(
+                        "Omitted {} bytes of structured content from '{}', over the limit of {}",
+                        size, params.name, self.max_structured_bytes
+                    );
+                }
+                Ok(JsonRpcResponse {
+                    jsonrpc: "2.0".to_string(),
+                    result: Some(serde_json::to_value(result)?),
+                    error: None,
+                    id: request.id,
+                })
+            }
+            Err(e) => {
+                error!("Tool call '{}' failed: {}", params.name, e);
+                
+                let Some(clickhouse_error) = e.downcast_ref::<ClickHouseError>() else {
+                    // Unknown tools and missing or malformed arguments
+                    return Ok(JsonRpcResponse::error(request.id, -32602, &format!("Invalid params: {}", e)));
+                };
+                if let Some(connection) = self.connection.get() {
+                    connection.report_error(clickhouse_error);
+                    connection.ensure_background_reconnect();
+                }
+                
+                // Bad arguments are protocol errors; everything else is a
+                // failure of the tool, reported as an isError result
+                let message = match clickhouse_error {
+                    ClickHouseError::InvalidIdentifier { .. } | ClickHouseError::InvalidArgument { .. } => {
+                        return Ok(JsonRpcResponse::error(request.id, -32602, &format!("Invalid params: {}", e)));
+                    }
+                    ClickHouseError::DatabaseNotFound { .. } => format!("Database not found: {}", e),
+                    ClickHouseError::TableNotFound { .. } => format!("Table not found: {}", e),
+                    ClickHouseError::ColumnNotFound { .. } => format!("Column not found: {}", e),
+                    ClickHouseError::KeeperNotConfigured { .. } => format!("Not supported: {}", e),
+                    ClickHouseError::UnsupportedVersion { .. } => format!("Not supported: {}", e),
+                    ClickHouseError::ToolUnavailable { .. } => format!("Not supported: {}", e),
+                    ClickHouseError::RetryBudgetExhausted { .. } => format!("Service unavailable: {}", e),
+                    ClickHouseError::PermissionDenied { .. } => format!("Permission denied: {}", e),
+                    ClickHouseError::ServiceUnavailable { .. } => format!("Service unavailable: {}", e),
+                    ClickHouseError::AuthenticationFailed { .. } => format!("Authentication failed: {}", e),
+                    ClickHouseError::QuotaExceeded { resets_at: Some(resets_at), .. } => {
+                        format!("Quota exhausted, resets at {} (server time): {}", resets_at.format("%H:%M"), e)
+                    }
+                    ClickHouseError::QuotaExceeded { resets_at: None, .. } => format!("Quota exhausted: {}", e),
+                    _ => format!("Internal error: {}", e),
+                };
+                
+                // Guidance for well-known errors goes after the message and,
+                // separately, into the structured content
+                let mut result = match explain_error(clickhouse_error) {
+                    Some(hint) => ToolResult {
+                        structured_content: Some(serde_json::json!({
+                            "error": message,
+                            "code": hint.code,
+                            "name": hint.name,
+                            "hint": hint.hint,
+                        })),
+                        ..ToolResult::error(format!("{}\nHint: {}", message, hint.hint))
+                    },
+                    None => ToolResult::error(message.clone()),
+                };
+                if let Some(raw) = clickhouse_error.raw() {
+                    if let Some(Content::Text { text }) = result.content.first_mut() {
+                        text.push_str(&format!("\nClickHouse error: {}", raw));
+                    }
+                    result
+                        .structured_content
+                        .get_or_insert_with(|| serde_json::json!({ "error": message }))["raw"] = Value::String(raw.to_string());
+                }
+                
+                Ok(JsonRpcResponse {
+                    jsonrpc: "2.0".to_string(),
+                    result: Some(serde_json::to_value(result)?),
+                    error: None,
+                    id: request.id,
+                })
+            }
+        }
+    }
+
+    /// Runs the tool `name`, where `called` is the name as sent by the client.
+    /// Missing or malformed arguments are errors, like failures of the tool.
+    async fn dispatch_tool(&self, name: &str, called: &str, arguments: Option<Value>) -> Result<ToolResult> {
+        if let Some(e) = self.unavailable_error(name) {
+            return Err(anyhow::anyhow!(e));
+        }
+        
+        match name {
+            "list_databases" => {
+                let args = arguments.unwrap_or_default();
+                let include_system = args.get("include_system").and_then(|v| v.as_bool());
+                self.list_databases(include_system).await.map(ToolResult::text).map_err(|e| anyhow::anyhow!(e))
+            },
+            "list_formats" => {
+                self.list_formats().await.map_err(|e| anyhow::anyhow!(e))
+            },
+            "current_grants" => {
+                self.current_grants().await.map(ToolResult::text).map_err(|e| anyhow::anyhow!(e))
+            },
+            "server_stats" => {
+                Ok(ToolResult::text(self.server_stats()))
+            },
+            "recent_tables" => {
+                self.recent_tables().map_err(|e| anyhow::anyhow!(e))
+            },
+            "clear_context" => {
+                Ok(ToolResult::text(self.clear_context()))
+            },
+            "recent_queries" => {
+                let args = arguments.unwrap_or_default();
+                let limit = args.get("limit").and_then(|v| v.as_u64()).unwrap_or(DEFAULT_RECENT_QUERIES);
+                let mine = args.get("find_my_queries").and_then(|v| v.as_bool()).unwrap_or(false);
+                self.recent_queries(limit, mine).await.map_err(|e| anyhow::anyhow!(e))
+            },
+            "query_history" => {
+                let args = arguments.unwrap_or_default();
+                let limit = args.get("limit")
+                    .and_then(|v| v.as_u64())
+                    .map_or(DEFAULT_QUERY_HISTORY_LIMIT, |n| n.min(MAX_QUERY_HISTORY as u64) as usize);
+                self.query_history(limit).map_err(|e| anyhow::anyhow!(e))
+            },
+            "database_exists" => {
+                let args = arguments.unwrap_or_default();
+                let database = args.get("database")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| anyhow::anyhow!("Missing database argument"))?;
+                self.database_exists(database).await.map_err(|e| anyhow::anyhow!(e))
+            },
+            "table_exists" => {
+                let args = arguments.unwrap_or_default();
+                let database = args.get("database").and_then(|v| v.as_str());
+                let table = args.get("table")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| anyhow::anyhow!("Missing table argument"))?;
+                self.table_exists(database, table).await.map_err(|e| anyhow::anyhow!(e))
+            },
+            "list_tables" => {
+                let args = arguments.unwrap_or_default();
+                let database = args.get("database").and_then(|v| v.as_str());
+                let information_schema = args.get("information_schema").and_then(|v| v.as_bool()).unwrap_or(false);
+                self.list_tables(database, information_schema).await.map_err(|e| anyhow::anyhow!(e))
+            },
+            "list_all_tables" => {
+                let args = arguments.unwrap_or_default();
+                let include_system = args.get("include_system").and_then(|v| v.as_bool());
+                self.list_all_tables(include_system).await.map(ToolResult::text).map_err(|e| anyhow::anyhow!(e))
+            },
+            "recently_modified_tables" => {
+                let args = arguments.unwrap_or_default();
+                let database = args.get("database").and_then(|v| v.as_str());
+                let limit = args.get("limit")
+                    .and_then(|v| v.as_u64())
+                    .unwrap_or(20)
+                    .min(u32::MAX as u64) as u32;
+                self.recently_modified_tables(database, limit).await.map(ToolResult::text).map_err(|e| anyhow::anyhow!(e))
+            },
+            "get_table_schema" => {
+                let args = arguments.unwrap_or_default();
+                let database = args.get("database").and_then(|v| v.as_str());
+                let table = args.get("table")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| anyhow::anyhow!("Missing table argument"))?;
+                let compact = args.get("compact").and_then(|v| v.as_bool()).unwrap_or(false).then(|| {
+                    args.get("max_columns")
+                        .and_then(|v| v.as_u64())
+                        .map_or(DEFAULT_VERBATIM_COLUMNS, |n| n.min(usize::MAX as u64) as usize)
+                });
+                let information_schema = args.get("information_schema").and_then(|v| v.as_bool()).unwrap_or(false);
+                self.get_table_schema(database, table, compact, information_schema).await.map_err(|e| anyhow::anyhow!(e))
+            },
+            "get_table_schemas" => {
+                let args = arguments.unwrap_or_default();
+                let tables = args.get("tables")
+                    .and_then(|v| v.as_array())
+                    .ok_or_else(|| anyhow::anyhow!("Missing tables argument"))?
+                    .iter()
+                    .map(|entry| {
+                        let database = entry.get("database").and_then(|v| v.as_str());
+                        let table = entry.get("table")
+                            .and_then(|v| v.as_str())
+                            .ok_or_else(|| anyhow::anyhow!("Every entry in tables needs a table"))?;
+                        Ok((database, table))
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                self.get_table_schemas(&tables).await.map(ToolResult::text).map_err(|e| anyhow::anyhow!(e))
+            },
+            "suggest_joins" => {
+                let args = arguments.unwrap_or_default();
+                let left_database = args.get("left_database").and_then(|v| v.as_str());
+                let left_table = args.get("left_table")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| anyhow::anyhow!("Missing left_table argument"))?;
+                let right_database = args.get("right_database").and_then(|v| v.as_str());
+                let right_table = args.get("right_table")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| anyhow::anyhow!("Missing right_table argument"))?;
+                self.suggest_joins((left_database, left_table), (right_database, right_table)).await.map(ToolResult::text).map_err(|e| anyhow::anyhow!(e))
+            },
+            "list_projections" => {
+                let args = arguments.unwrap_or_default();
+                let database = args.get("database")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| anyhow::anyhow!("Missing database argument"))?;
+                let table = args.get("table")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| anyhow::anyhow!("Missing table argument"))?;
+                self.list_projections(database, table).await.map(ToolResult::text).map_err(|e| anyhow::anyhow!(e))
+            },
+            "table_dependencies" => {
+                let args = arguments.unwrap_or_default();
+                let database = args.get("database")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| anyhow::anyhow!("Missing database argument"))?;
+                let table = args.get("table")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| anyhow::anyhow!("Missing table argument"))?;
+                self.table_dependencies(database, table).await.map(ToolResult::text).map_err(|e| anyhow::anyhow!(e))
+            },
+            "count_rows" => {
+                let args = arguments.unwrap_or_default();
+                let database = args.get("database")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| anyhow::anyhow!("Missing database argument"))?;
+                let table = args.get("table")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| anyhow::anyhow!("Missing table argument"))?;
+                let approximate = args.get("approximate").and_then(|v| v.as_bool()).unwrap_or(false);
+                self.count_rows(database, table, approximate).await.map(ToolResult::text).map_err(|e| anyhow::anyhow!(e))
+            },
+            "null_fraction" => {
+                let args = arguments.unwrap_or_default();
+                let database = args.get("database")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| anyhow::anyhow!("Missing database argument"))?;
+                let table = args.get("table")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| anyhow::anyhow!("Missing table argument"))?;
+                let column = args.get("column")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| anyhow::anyhow!("Missing column argument"))?;
+                self.null_fraction(database, table, column).await.map(ToolResult::text).map_err(|e| anyhow::anyhow!(e))
+            },
+            "column_compression" => {
+                let args = arguments.unwrap_or_default();
+                let database = args.get("database")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| anyhow::anyhow!("Missing database argument"))?;
+                let table = args.get("table")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| anyhow::anyhow!("Missing table argument"))?;
+                self.column_compression(database, table).await.map_err(|e| anyhow::anyhow!(e))
+            },
+            "dump_database_ddl" => {
+                let args = arguments.unwrap_or_default();
+                let database = args.get("database")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| anyhow::anyhow!("Missing database argument"))?;
+                let max_bytes = args.get("max_result_bytes")
+                    .and_then(|v| v.as_u64())
+                    .map_or(DEFAULT_DDL_RESULT_BYTES, |n| n.min(usize::MAX as u64) as usize);
+                self.dump_database_ddl(database, max_bytes).await.map_err(|e| anyhow::anyhow!(e))
+            },
+            "get_row_by_key" => {
+                let args = arguments.unwrap_or_default();
+                let database = args.get("database")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| anyhow::anyhow!("Missing database argument"))?;
+                let table = args.get("table")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| anyhow::anyhow!("Missing table argument"))?;
+                let key_column = args.get("key_column")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| anyhow::anyhow!("Missing key_column argument"))?;
+                // Numbers are accepted as well, e.g. numeric keys or epochs
+                let key_value = args.get("key_value")
+                    .filter(|v| v.is_string() || v.is_number())
+                    .ok_or_else(|| anyhow::anyhow!("Missing key_value argument"))?;
+                self.get_row_by_key(database, table, key_column, key_value).await.map(ToolResult::text).map_err(|e| anyhow::anyhow!(e))
+            },
+            "column_histogram" => {
+                let args = arguments.unwrap_or_default();
+                let database = args.get("database")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| anyhow::anyhow!("Missing database argument"))?;
+                let table = args.get("table")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| anyhow::anyhow!("Missing table argument"))?;
+                let column = args.get("column")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| anyhow::anyhow!("Missing column argument"))?;
+                let buckets = match args.get("buckets") {
+                    Some(value) => value.as_u64().and_then(|n| u32::try_from(n).ok()).ok_or_else(|| ClickHouseError::InvalidArgument {
+                        message: format!("buckets must be a positive integer, got {}", value),
+                    })?,
+                    None => 20,
+                };
+                let sample_ratio = args.get("sample_ratio").and_then(|v| v.as_f64());
+                self.column_histogram(database, table, column, buckets, sample_ratio).await.map(ToolResult::text).map_err(|e| anyhow::anyhow!(e))
+            },
+            "refresh_metadata" => {
+                let args = arguments.unwrap_or_default();
+                let database = args.get("database").and_then(|v| v.as_str());
+                let table = args.get("table").and_then(|v| v.as_str());
+                let scope = match (database, table) {
+                    (None, None) => CacheScope::All,
+                    (Some(database), None) => CacheScope::Database(database.to_string()),
+                    (Some(database), Some(table)) => CacheScope::Table { database: database.to_string(), table: table.to_string() },
+                    (None, Some(_)) => return Err(anyhow::anyhow!("Missing database argument")),
+                };
+                self.refresh_metadata(scope).await.map(ToolResult::text).map_err(|e| anyhow::anyhow!(e))
+            },
+            "run_script" => {
+                let args = arguments.unwrap_or_default();
+                let statements = args.get("statements")
+                    .and_then(|v| v.as_array())
+                    .ok_or_else(|| anyhow::anyhow!("Missing statements argument"))?
+                    .iter()
+                    .map(|v| v.as_str().map(str::to_string))
+                    .collect::<Option<Vec<_>>>()
+                    .ok_or_else(|| anyhow::anyhow!("statements must be an array of strings"))?;
+                self.run_script(statements).await.map(ToolResult::text).map_err(|e| anyhow::anyhow!(e))
+            },
+            "group_count" => {
+                let args = arguments.unwrap_or_default();
+                let database = args.get("database")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| anyhow::anyhow!("Missing database argument"))?;
+                let table = args.get("table")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| anyhow::anyhow!("Missing table argument"))?;
+                let column = args.get("column")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| anyhow::anyhow!("Missing column argument"))?;
+                let limit = args.get("limit")
+                    .and_then(|v| v.as_u64())
+                    .unwrap_or(20)
+                    .min(u32::MAX as u64) as u32;
+                let time_filter = match args.get("time_filter") {
+                    Some(filter) => Some(TimeFilter {
+                        column: filter.get("column")
+                            .and_then(|v| v.as_str())
+                            .ok_or_else(|| anyhow::anyhow!("Missing time_filter.column argument"))?
+                            .to_string(),
+                        last_n_days: filter.get("last_n_days")
+                            .and_then(|v| v.as_u64())
+                            .ok_or_else(|| anyhow::anyhow!("Missing time_filter.last_n_days argument"))?
+                            .min(u32::MAX as u64) as u32,
+                    }),
+                    None => None,
+                };
+                self.group_count(database, table, column, limit, time_filter.as_ref()).await.map(ToolResult::text).map_err(|e| anyhow::anyhow!(e))
+            },
+            "aggregate" => {
+                let args = arguments.unwrap_or_default();
+                let database = args.get("database")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| anyhow::anyhow!("Missing database argument"))?;
+                let table = args.get("table")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| anyhow::anyhow!("Missing table argument"))?;
+                let column = args.get("column")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| anyhow::anyhow!("Missing column argument"))?;
+                let functions = args.get("functions")
+                    .and_then(|v| v.as_array())
+                    .ok_or_else(|| anyhow::anyhow!("Missing functions argument"))?
+                    .iter()
+                    .map(|v| v.as_str().map(str::to_string).ok_or_else(|| anyhow::anyhow!("functions must be an array of strings")))
+                    .collect::<Result<Vec<String>>>()?;
+                self.aggregate(database, table, column, functions).await.map_err(|e| anyhow::anyhow!(e))
+            },
+            "table_quality_check" => {
+                let args = arguments.unwrap_or_default();
+                let database = args.get("database")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| anyhow::anyhow!("Missing database argument"))?;
+                let table = args.get("table")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| anyhow::anyhow!("Missing table argument"))?;
+                let key_columns = match args.get("key_columns") {
+                    Some(Value::Array(columns)) => columns
+                        .iter()
+                        .map(|column| column.as_str().map(str::to_string).ok_or_else(|| anyhow::anyhow!("key_columns must be strings")))
+                        .collect::<Result<Vec<_>>>()?,
+                    Some(_) => return Err(anyhow::anyhow!("key_columns must be an array of column names")),
+                    None => Vec::new(),
+                };
+                let sample_ratio = args.get("sample_ratio").and_then(|v| v.as_f64());
+                self.table_quality_check(database, table, &key_columns, sample_ratio).await.map(ToolResult::text).map_err(|e| anyhow::anyhow!(e))
+            },
+            "sample_rows" => {
+                let args = arguments.unwrap_or_default();
+                let database = args.get("database").and_then(|v| v.as_str());
+                let table = args.get("table")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| anyhow::anyhow!("Missing table argument"))?;
+                let limit = args.get("limit").and_then(|v| v.as_u64()).unwrap_or(10);
+                let format = match args.get("format").and_then(|v| v.as_str()) {
+                    Some(format) => format.parse::<ResponseFormat>()?,
+                    None => ResponseFormat::default(),
+                };
+                self.sample_rows(database, table, limit, format).await.map(ToolResult::text).map_err(|e| anyhow::anyhow!(e))
+            },
+            "row_counts_over_time" => {
+                let args = arguments.unwrap_or_default();
+                let database = args.get("database")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| anyhow::anyhow!("Missing database argument"))?;
+                let table = args.get("table")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| anyhow::anyhow!("Missing table argument"))?;
+                let time_column = args.get("time_column")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| anyhow::anyhow!("Missing time_column argument"))?;
+                let interval = args.get("interval")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| anyhow::anyhow!("Missing interval argument"))?
+                    .parse::<TimeInterval>()?;
+                let last_n = args.get("last_n")
+                    .and_then(|v| v.as_u64())
+                    .map(|n| n.min(u32::MAX as u64) as u32);
+                self.row_counts_over_time(database, table, time_column, interval, last_n).await.map(ToolResult::text).map_err(|e| anyhow::anyhow!(e))
+            },
+            "explain_column_defaults" => {
+                let args = arguments.unwrap_or_default();
+                let database = args.get("database").and_then(|v| v.as_str());
+                let table = args.get("table")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| anyhow::anyhow!("Missing table argument"))?;
+                self.explain_column_defaults(database, table).await.map_err(|e| anyhow::anyhow!(e))
+            },
+            "poll_new_rows" => {
+                let args = arguments.unwrap_or_default();
+                let database = args.get("database").and_then(|v| v.as_str());
+                let table = args.get("table")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| anyhow::anyhow!("Missing table argument"))?;
+                let time_column = args.get("time_column")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| anyhow::anyhow!("Missing time_column argument"))?;
+                let since = args.get("since")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| anyhow::anyhow!("Missing since argument"))?;
+                let limit = args.get("limit").and_then(|v| v.as_u64()).unwrap_or(100);
+                self.poll_new_rows(database, table, time_column, since, limit).await.map_err(|e| anyhow::anyhow!(e))
+            },
+            "server_metrics" => {
+                self.server_metrics().await.map(ToolResult::text).map_err(|e| anyhow::anyhow!(e))
+            },
+            "catalog_version" => {
+                self.catalog_version().await.map_err(|e| anyhow::anyhow!(e))
+            },
+            "export_catalog" => {
+                let args = arguments.unwrap_or_default();
+                let since_version = args.get("since_version").and_then(|v| v.as_str());
+                self.export_catalog(since_version).await.map_err(|e| anyhow::anyhow!(e))
+            },
+            "server_time" => {
+                self.server_time().await.map(ToolResult::text).map_err(|e| anyhow::anyhow!(e))
+            },
+            "recommend_ordering" => {
+                let args = arguments.unwrap_or_default();
+                let database = args.get("database")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| anyhow::anyhow!("Missing database argument"))?;
+                let table = args.get("table")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| anyhow::anyhow!("Missing table argument"))?;
+                self.recommend_ordering(database, table).await.map_err(|e| anyhow::anyhow!(e))
+            },
+            "insert_pressure" => {
+                let args = arguments.unwrap_or_default();
+                let database = args.get("database").and_then(|v| v.as_str());
+                let table = args.get("table").and_then(|v| v.as_str());
+                self.insert_pressure(database, table).await.map_err(|e| anyhow::anyhow!(e))
+            },
+            "keeper_info" => {
+                let args = arguments.unwrap_or_default();
+                let path = args.get("path").and_then(|v| v.as_str()).unwrap_or("/clickhouse");
+                let summary = args.get("summary").and_then(|v| v.as_bool()).unwrap_or(false);
+                self.keeper_info(path, summary).await.map(ToolResult::text).map_err(|e| anyhow::anyhow!(e))
+            },
+            "estimate_cost" => {
+                let args = arguments.unwrap_or_default();
+                let query = args.get("query")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| anyhow::anyhow!("Missing query argument"))?;
+                self.estimate_cost(query).await.map(ToolResult::text).map_err(|e| anyhow::anyhow!(e))
+            },
+            "analyze_index_usage" => {
+                let args = arguments.unwrap_or_default();
+                let query = args.get("query")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| anyhow::anyhow!("Missing query argument"))?;
+                self.analyze_index_usage(query).await.map_err(|e| anyhow::anyhow!(e))
+            },
+            "check_partition_pruning" => {
+                let args = arguments.unwrap_or_default();
+                let database = args.get("database")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| anyhow::anyhow!("Missing database argument"))?;
+                let table = args.get("table")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| anyhow::anyhow!("Missing table argument"))?;
+                let condition = args.get("where")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| anyhow::anyhow!("Missing where argument"))?;
+                self.check_partition_pruning(database, table, condition).await.map_err(|e| anyhow::anyhow!(e))
+            },
+            "column_lineage" => {
+                let args = arguments.unwrap_or_default();
+                let database = args.get("database")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| anyhow::anyhow!("Missing database argument"))?;
+                let table = args.get("table")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| anyhow::anyhow!("Missing table argument"))?;
+                self.column_lineage(database, table).await.map_err(|e| anyhow::anyhow!(e))
+            },
+            "explain_with_settings" => {
+                let args = arguments.unwrap_or_default();
+                let sql = args.get("sql")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| anyhow::anyhow!("Missing sql argument"))?;
+                let settings = match args.get("settings") {
+                    Some(Value::Object(settings)) => settings
+                        .iter()
+                        .map(|(name, value)| {
+                            let value = match value {
+                                Value::String(value) => value.clone(),
+                                Value::Bool(value) => (*value as u8).to_string(),
+                                Value::Number(value) => value.to_string(),
+                                _ => return Err(anyhow::anyhow!("Setting '{}' must be a string, number or boolean", name)),
+                            };
+                            Ok((name.clone(), value))
+                        })
+                        .collect::<Result<Vec<_>>>()?,
+                    Some(_) => return Err(anyhow::anyhow!("settings must be an object")),
+                    None => Vec::new(),
+                };
+                self.explain_with_settings(sql, &settings).await.map(ToolResult::text).map_err(|e| anyhow::anyhow!(e))
+            },
+            "format_query" => {
+                let args = arguments.unwrap_or_default();
+                let sql = args.get("sql")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| anyhow::anyhow!("Missing sql argument"))?;
+                self.format_query(sql).await.map(ToolResult::text).map_err(|e| anyhow::anyhow!(e))
+            },
+            "describe" => {
+                let args = arguments.unwrap_or_default();
+                let database = args.get("database").and_then(|v| v.as_str());
+                let table = args.get("table").and_then(|v| v.as_str());
+                self.describe(database, table).await.map(ToolResult::text).map_err(|e| anyhow::anyhow!(e))
+            },
+            ADMIN_TOOL if self.admin_enabled => {
+                let args = arguments.unwrap_or_default();
+                let sql = args.get("sql")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| anyhow::anyhow!("Missing sql argument"))?;
+                let confirm = args.get("confirm").and_then(|v| v.as_str());
+                self.admin_execute(sql, confirm).await.map(ToolResult::text).map_err(|e| anyhow::anyhow!(e))
+            },
+            _ if self.custom_tools.contains_key(name) => {
+                let handler = self.custom_tools[name].handler.clone();
+                handler(arguments.unwrap_or_else(|| serde_json::json!({}))).await.map(ToolResult::text)
+            },
+            _ if !self.tool_prefix.is_empty() => Err(anyhow::anyhow!(
+                "Unknown tool: {} (tools are named with the prefix '{}')",
+                called,
+                self.tool_prefix
+            )),
+            _ => Err(anyhow::anyhow!("Unknown tool: {}", called)),
+        }
+    }
+
+    async fn recent_queries(&self, limit: u64, mine: bool) -> Result<ToolResult, ClickHouseError> {
+        if mine && !self.query_comments {
+            return Err(ClickHouseError::InvalidArgument {
+                message: "find_my_queries needs query tagging, which is turned off on this server".to_string(),
+            });
+        }
+        let client = self.client().await?;
+        
+        let prefix = mine.then(|| self.own_query_id_prefix());
+        let queries = client.recent_queries(limit, prefix.as_deref()).await?;
+        let structured = serde_json::to_value(&queries).map_err(|e| ClickHouseError::InternalError {
+            message: e.to_string(),
+        })?;
+        let whose = if mine { "of this session" } else { "on the server" };
+        if queries.is_empty() {
+            let text = format!("No finished queries {} in system.query_log since yesterday\n", whose);
+            return Ok(ToolResult::with_structured(text, structured));
+        }
+        
+        let mut result = format!("Latest {} finished queries {}, newest first:\n", queries.len(), whose);
+        for query in &queries {
+            result.push_str(&format!(
+                "- {} {} ({}, {}, {} rows read)\n    {}\n",
+                query.event_time,
+                query.query_id,
+                query.r#type,
+                human_duration(Duration::from_millis(query.duration_ms)),
+                human_count(query.read_rows),
+                query.query.split_whitespace().collect::<Vec<_>>().join(" ")
+            ));
+            if !query.exception.is_empty() {
+                result.push_str(&format!("    Error: {}\n", query.exception.lines().next().unwrap_or_default()));
+            }
+        }
+        
+        Ok(ToolResult::with_structured(result, structured))
+    }
+
+    /// Replaces a `table` argument of [`LAST_TABLE`] with the most recently
+    /// used table, of the `database` argument if there is one, and sets the
+    /// `database` argument to its database. `table_exists` and tools the
+    /// embedding application registered take names as they are.
+    fn resolve_last_table(&self, tool: &str, arguments: Option<Value>) -> Result<Option<Value>, ClickHouseError> {
+        let Some(Value::Object(mut args)) = arguments else { return Ok(arguments) };
+        if tool == "table_exists" || !is_builtin_tool(tool) || args.get("table").and_then(Value::as_str) != Some(LAST_TABLE) {
+            return Ok(Some(Value::Object(args)));
+        }
+        
+        let last = self.recent_tables.last(args.get("database").and_then(Value::as_str))?;
+        debug!("Resolved table \"{}\" to '{}.{}'", LAST_TABLE, last.database, last.table);
+        args.insert("database".to_string(), Value::String(last.database));
+        args.insert("table".to_string(), Value::String(last.table));
+        Ok(Some(Value::Object(args)))
+    }
+    
+    /// Records a use of a table for `recent_tables`, under the names it
+    /// resolved to; without a database argument, the table is in the
+    /// client's default database.
+    async fn remember_table(&self, database: Option<&str>, table: &str) {
+        let Ok(client) = self.client().await else { return };
+        let (database, table) = client.actual_table_name(database.unwrap_or(client.default_database()), table);
+        self.recent_tables.touch(&database, &table);
+    }
+    
+    fn recent_tables(&self) -> Result<ToolResult, ClickHouseError> {
+        let tables = self.recent_tables.recent();
+        let structured = serde_json::to_value(&tables).map_err(|e| ClickHouseError::InternalError {
+            message: e.to_string(),
+        })?;
+        if tables.is_empty() {
+            return Ok(ToolResult::with_structured("No tables used in this session yet\n", structured));
+        }
+        
+        let mut result = format!("Tables used in this session, most recent first (\"{}\" is the first):\n", LAST_TABLE);
+        for table in &tables {
+            result.push_str(&format!(
+                "- {}.{}: {} {}, last at {}\n",
+                table.database,
+                table.table,
+                table.access_count,
+                if table.access_count == 1 { "use" } else { "uses" },
+                table.last_used
+            ));
+        }
+        Ok(ToolResult::with_structured(result, structured))
+    }
+    
+    fn clear_context(&self) -> String {
+        match self.recent_tables.clear() {
+            0 => "No tables were remembered\n".to_string(),
+            count => format!("Forgot {} recently used tables\n", count),
+        }
+    }
+
+    fn query_history(&self, limit: usize) -> Result<ToolResult, ClickHouseError> {
+        let entries = self.query_history.recent(limit);
+        let structured = serde_json::to_value(&entries).map_err(|e| ClickHouseError::InternalError {
+            message: e.to_string(),
+        })?;
+        if entries.is_empty() {
+            return Ok(ToolResult::with_structured("No queries run in this session yet\n", structured));
+        }
+        
+        let mut result = format!("Last {} queries of this session, newest first:\n", entries.len());
+        for entry in &entries {
+            let status = match &entry.error {
+                Some(error) => format!("failed: {}", error),
+                None => "ok".to_string(),
+            };
+            result.push_str(&format!(
+                "- {} at {}, {} ({}):\n",
+                entry.tool,
+                entry.finished_at,
+                human_duration(entry.elapsed()),
+                status
+            ));
+            for line in entry.sql.lines() {
+                result.push_str(&format!("    {}\n", line));
+            }
+        }
+        let total = self.query_history.len();
+        if entries.len() < total {
+            result.push_str(&format!("{}\n", truncation_notice(entries.len(), Some(total), "limit")));
+        }
+        
+        Ok(ToolResult::with_structured(result, structured))
+    }
+
+    fn server_stats(&self) -> String {
+        let stats = self.metrics_snapshot();
+        
+        let mut result = "Server statistics:\n".to_string();
+        let state = stats.connection_state.map(state_label).unwrap_or("not connected");
+        result.push_str(&format!("- ClickHouse connection: {}\n", state));
+        if let Some(timezone) = self.connection.get().and_then(|connection| connection.timezone()) {
+            result.push_str(&format!("- Time zone: {}\n", timezone));
+        }
+        
+        let requests: Vec<String> = stats.requests.iter().map(|(method, count)| format!("{} {}", method, count)).collect();
+        result.push_str(&format!("- Requests: {}\n", if requests.is_empty() { "none".to_string() } else { requests.join(", ") }));
+        
+        if stats.tool_calls.is_empty() {
+            result.push_str("- Tool calls: none\n");
+        } else {
+            result.push_str("- Tool calls:\n");
+            let mut tools: BTreeMap<&str, (u64, u64)> = BTreeMap::new();
+            for ((tool, outcome), count) in &stats.tool_calls {
+                let entry = tools.entry(tool.as_str()).or_default();
+                match *outcome {
+                    "success" => entry.0 += count,
+                    _ => entry.1 += count,
+                }
+            }
+            for (tool, (succeeded, failed)) in tools {
+                let bytes = stats.tool_response_bytes.get(tool).copied().unwrap_or_default();
+                result.push_str(&format!(
+                    "  - {}: {} succeeded, {} failed, {} returned\n",
+                    tool,
+                    human_count(succeeded),
+                    human_count(failed),
+                    human_bytes(bytes)
+                ));
+            }
+        }
+        
+        let latency = &stats.query_latency;
+        if latency.count > 0 {
+            result.push_str(&format!(
+                "- ClickHouse queries: {}, average {}\n",
+                human_count(latency.count),
+                human_duration(Duration::from_secs_f64(latency.sum_seconds / latency.count as f64))
+            ));
+        } else {
+            result.push_str("- ClickHouse queries: 0\n");
+        }
+        result.push_str(&format!("- Retries: {}\n", human_count(stats.retries)));
+        
+        match self.connection.get().map(|connection| connection.schema_cache_stats()) {
+            Some(Some(cache)) => {
+                let hit_rate = cache
+                    .hit_rate()
+                    .map(|rate| format!("{:.1}%", rate * 100.0))
+                    .unwrap_or_else(|| "n/a".to_string());
+                result.push_str(&format!(
+                    "- Schema cache: {} entries, hit rate {} ({} hits, {} misses), {} invalidated",
+                    cache.entries, hit_rate, cache.hits, cache.misses, cache.invalidations
+                ));
+                if let Some(age) = cache.oldest_entry_age {
+                    result.push_str(&format!(", oldest entry {}s old", age.as_secs()));
+                }
+                result.push('\n');
+            }
+            Some(None) => result.push_str("- Schema cache: disabled\n"),
+            None => result.push_str("- Schema cache: not connected\n"),
+        }
+        
+        result
+    }
+
+    async fn list_databases(&self, include_system: Option<bool>) -> Result<String, ClickHouseError> {
+        let client = self.client().await?;
+        
+        let mut databases = client.list_databases().await?;
+        let total = databases.len();
+        databases.retain(|database| self.database_visibility.shows(&database.name, include_system));
+        
+        let mut result = format_databases(&databases);
+        if databases.len() < total {
+            result.push_str(&format!(
+                "\n{} system databases not listed; pass include_system: true to list them\n",
+                total - databases.len()
+            ));
+        }
+        Ok(result)
+    }
+
+    async fn database_exists(&self, database: &str) -> Result<ToolResult, ClickHouseError> {
+        let client = self.client().await?;
+        let exists = client.database_exists(database).await?;
+        let text = if exists {
+            format!("Database '{}' exists\n", database)
+        } else {
+            format!("Database '{}' does not exist\n", database)
+        };
+        Ok(ToolResult::with_structured(text, serde_json::json!({ "database": database, "exists": exists })))
+    }
+
+    async fn table_exists(&self, database: Option<&str>, table: &str) -> Result<ToolResult, ClickHouseError> {
+        let client = self.client().await?;
+        let database = client.resolve_database(database);
+        let exists = client.table_exists(database, table).await?;
+        let text = if exists {
+            format!("Table '{}.{}' exists\n", database, table)
+        } else {
+            format!("Table '{}.{}' does not exist\n", database, table)
+        };
+        Ok(ToolResult::with_structured(text, serde_json::json!({ "database": database, "table": table, "exists": exists })))
+    }
+
+    async fn list_formats(&self) -> Result<ToolResult, ClickHouseError> {
+        let client = self.client().await?;
+        
+        let formats = client.list_formats().await?;
+        let input: Vec<&str> = formats.iter().filter(|f| f.is_input).map(|f| f.name.as_str()).collect();
+        let output: Vec<&str> = formats.iter().filter(|f| f.is_output).map(|f| f.name.as_str()).collect();
+        
+        let mut result = String::new();
+        for (heading, names) in [("Input formats, for INSERT", &input), ("Output formats, for SELECT", &output)] {
+            let listed = if names.is_empty() { "(none)".to_string() } else { names.join(", ") };
+            result.push_str(&format!("{} ({}):\n{}\n", heading, names.len(), listed));
+        }
+        
+        Ok(ToolResult::with_structured(result, serde_json::json!({ "input": input, "output": output })))
+    }
+
+    async fn current_grants(&self) -> Result<String, ClickHouseError> {
+        let client = self.client().await?;
+        
+        let grants = client.current_grants().await?;
+        if grants.is_empty() {
+            return Ok("The current user has no grants\n".to_string());
+        }
+        
+        let mut result = format!("Grants of the current user ({}):\n", grants.len());
+        for grant in &grants {
+            result.push_str(grant);
+            result.push('\n');
+        }
+        Ok(result)
+    }
+
+    /// With `information_schema`, the tables go out as
+    /// `INFORMATION_SCHEMA.TABLES` rows, one JSON object per line and as
+    /// structured content.
+    async fn list_tables(&self, database: Option<&str>, information_schema: bool) -> Result<ToolResult, ClickHouseError> {
+        let client = self.client().await?;
+        let database = client.resolve_database(database);
+        
+        let tables = client.list_tables(database).await?;
+        if information_schema {
+            let rows = information_schema::table_rows(&tables);
+            return Ok(ToolResult::with_structured(json_lines(&rows), serde_json::json!({ "tables": rows })));
+        }
+        
+        Ok(ToolResult::text(format_tables(database, &tables)))
+    }
+
+    async fn list_all_tables(&self, include_system: Option<bool>) -> Result<String, ClickHouseError> {
+        let client = self.client().await?;
+        
+        let listing = client.list_all_tables(self.database_visibility.shows_system(include_system)).await?;
+        
+        Ok(format_all_tables(&listing.tables, listing.truncated))
+    }
+
+    async fn recently_modified_tables(&self, database: Option<&str>, limit: u32) -> Result<String, ClickHouseError> {
+        let client = self.client().await?;
+        
+        let RecentModifications { tables, truncated } =
+            client.recently_modified_tables(database, limit, self.database_visibility.hidden_databases(None)).await?;
+        let scope = match database {
+            Some(database) => format!("database '{}'", database),
+            None => "all databases".to_string(),
+        };
+        if tables.is_empty() {
+            return Ok(format!("No tables with data parts found in {}\n", scope));
+        }
+        
+        let mut result = format!("Most recently modified tables in {} (server time):\n", scope);
+        for table in &tables {
+            result.push_str(&format!("- {}.{}: {}\n", table.database, table.table, table.last_modified));
+        }
+        if truncated {
+            result.push_str(&format!("{}\n", truncation_notice(tables.len(), None, "limit")));
+        }
+        
+        Ok(result)
+    }
+
+    /// With `information_schema`, the columns go out as
+    /// `INFORMATION_SCHEMA.COLUMNS` rows, one JSON object per line and as
+    /// structured content. Otherwise, with `compact`, wide tables are
+    /// summarized, listing that many columns verbatim, and without it the
+    /// columns also go out as structured content.
+    async fn get_table_schema(
+        &self,
+        database: Option<&str>,
+        table: &str,
+        compact: Option<usize>,
+        information_schema: bool,
+    ) -> Result<ToolResult, ClickHouseError> {
+        let client = self.client().await?;
+        let database = client.resolve_database(database);
+        let format = |columns: &[ColumnInfo], note: String| match compact {
+            _ if information_schema => {
+                let rows = information_schema::column_rows(database, table, columns);
+                ToolResult::with_structured(json_lines(&rows) + &note, serde_json::json!({ "columns": rows }))
+            }
+            Some(max_columns) => {
+                ToolResult::text(format_schema_summary(database, table, &summarize_schema(columns, max_columns)) + &note)
+            }
+            None => ToolResult::with_structured(
+                format_table_schema(database, table, columns) + &note,
+                serde_json::json!({ "database": database, "table": table, "columns": columns }),
+            ),
+        };
+        
+        match client.get_table_schema(database, table).await {
+            Ok(columns) => Ok(format(&columns, String::new())),
+            // A schema cached earlier beats no answer while the quota lasts
+            Err(ClickHouseError::QuotaExceeded { resets_at, message }) => {
+                let Some(cached) = client.cached_table_schema(database, table) else {
+                    return Err(ClickHouseError::QuotaExceeded { resets_at, message });
+                };
+                let resets = match resets_at {
+                    Some(resets_at) => format!(", resets at {} (server time)", resets_at.format("%H:%M")),
+                    None => String::new(),
+                };
+                let note = format!(
+                    "\nNote: quota exhausted{}; this is the cached schema fetched at {}, which may be out of date.\n",
+                    resets,
+                    cached.fetched_at.format("%Y-%m-%d %H:%M:%S UTC")
+                );
+                Ok(format(&cached.columns, note))
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn suggest_joins(
+        &self,
+        left: (Option<&str>, &str),
+        right: (Option<&str>, &str),
+    ) -> Result<String, ClickHouseError> {
+        let client = self.client().await?;
+        let (left_database, left_table) = (client.resolve_database(left.0), left.1);
+        let (right_database, right_table) = (client.resolve_database(right.0), right.1);
+        
+        let left_columns = client.get_table_schema(left_database, left_table).await?;
+        let right_columns = client.get_table_schema(right_database, right_table).await?;
+        let candidates = suggest_joins(left_table, &left_columns, right_table, &right_columns);
+        
+        let mut result = format!(
+            "Join candidates between '{}.{}' and '{}.{}':\n",
+            left_database, left_table, right_database, right_table
+        );
+        if candidates.is_empty() {
+            result.push_str("No candidates found among integer, string and UUID columns\n");
+        }
+        for candidate in &candidates {
+            result.push_str(&format!(
+                "- [{}] {}.{} = {}.{} ({})\n",
+                candidate.confidence, left_table, candidate.left_column, right_table, candidate.right_column, candidate.reason
+            ));
+        }
+        result.push_str("\nThese are guesses from column names and types only; check them against the data before relying on them.\n");
+        
+        Ok(result)
+    }
+
+    async fn get_table_schemas(&self, tables: &[(Option<&str>, &str)]) -> Result<String, ClickHouseError> {
+        let client = self.client().await?;
+        
+        let tables: Vec<(String, String)> = tables
+            .iter()
+            .map(|(database, table)| (client.resolve_database(*database).to_string(), table.to_string()))
+            .collect();
+        let mut schemas = client.get_table_schemas(&tables).await?;
+        
+        // Sections follow the order tables were asked for
+        let mut sections = Vec::new();
+        for (database, table) in &tables {
+            let key = format!("{}.{}", database, table);
+            let section = match schemas.remove(&key) {
+                Some(Ok(columns)) => format!("=== {} ===\n{}", key, format_columns(&columns)),
+                Some(Err(e)) => format!("=== {} ===\nError: {}\n", key, e),
+                // Already shown for an earlier duplicate
+                None => continue,
+            };
+            sections.push(section);
+        }
+        
+        Ok(sections.join("\n"))
+    }
+
+    async fn list_projections(&self, database: &str, table: &str) -> Result<String, ClickHouseError> {
+        let client = self.client().await?;
+        
+        let projections = client.list_projections(database, table).await?;
+        
+        if projections.is_empty() {
+            return Ok(format!("No projections defined on table '{}.{}'\n", database, table));
+        }
+        
+        let mut result = format!("Projections on table '{}.{}':\n", database, table);
+        for projection in projections {
+            result.push_str(&format!("- {}: {}\n", projection.name, projection.query));
+        }
+        
+        Ok(result)
+    }
+
+    async fn table_dependencies(&self, database: &str, table: &str) -> Result<String, ClickHouseError> {
+        let client = self.client().await?;
+        
+        let dependencies = client.table_dependencies(database, table).await?;
+        
+        let mut result = format!("Dependencies of table '{}.{}':\n", database, table);
+        
+        result.push_str("\nReads from:\n");
+        if dependencies.depends_on.is_empty() {
+            result.push_str("  (none)\n");
+        }
+        for name in &dependencies.depends_on {
+            result.push_str(&format!("  - {}\n", name));
+        }
+        
+        result.push_str("\nDepended on by:\n");
+        if dependencies.dependents.is_empty() {
+            result.push_str("  (none)\n");
+        }
+        for name in &dependencies.dependents {
+            result.push_str(&format!("  - {}\n", name));
+        }
+        
+        if !dependencies.dependents.is_empty() {
+            result.push_str("\nDropping or altering this table will break the dependents listed above.\n");
+        }
+        
+        Ok(result)
+    }
+
+    async fn count_rows(&self, database: &str, table: &str, approximate: bool) -> Result<String, ClickHouseError> {
+        let client = self.client().await?;
+        
+        if approximate {
+            let estimate = client.count_rows_approx(database, table).await?;
+            let source = match &estimate.distributed {
+                None => "the active parts in system.parts".to_string(),
+                Some(target) if estimate.cluster_wide => format!(
+                    "the active parts of {}.{} on every shard of cluster '{}'",
+                    target.database, target.table, target.cluster
+                ),
+                Some(target) => format!(
+                    "the active parts of {}.{} on this server's shard only, as reading the other shards of cluster '{}' was denied",
+                    target.database, target.table, target.cluster
+                ),
+            };
+            Ok(format!(
+                "Table '{}.{}' has about {} rows (estimated from {}; rows pending deletion or deduplication are included)\n",
+                database, table, human_count(estimate.rows), source
+            ))
+        } else {
+            let rows = client.count_rows(database, table).await?;
+            Ok(format!("Table '{}.{}' has {} rows (exact count)\n", database, table, human_count(rows)))
+        }
+    }
+
+    async fn null_fraction(&self, database: &str, table: &str, column: &str) -> Result<String, ClickHouseError> {
+        let client = self.client().await?;
+        
+        let fraction = client.null_fraction(database, table, column).await?;
+        
+        Ok(format!(
+            "{:.2}% of the values in column '{}' of table '{}.{}' are NULL\n",
+            fraction * 100.0,
+            column,
+            database,
+            table
+        ))
+    }
+
+    async fn column_compression(&self, database: &str, table: &str) -> Result<ToolResult, ClickHouseError> {
+        let client = self.client().await?;
+        
+        let columns = client.column_compression(database, table).await?;
+        let structured = serde_json::to_value(&columns).map_err(|e| ClickHouseError::InternalError {
+            message: e.to_string(),
+        })?;
+        let compressed: u64 = columns.iter().map(|c| c.compressed).sum();
+        let uncompressed: u64 = columns.iter().map(|c| c.uncompressed).sum();
+        if uncompressed == 0 {
+            let text = format!(
+                "Table '{}.{}' has no column data on disk (it is empty, or its engine doesn't track column sizes)\n",
+                database, table
+            );
+            return Ok(ToolResult::with_structured(text, structured));
+        }
+        
+        let ratio = |compressed: u64, uncompressed: u64| match compressed {
+            0 => "-".to_string(),
+            _ => format!("{:.2}x", uncompressed as f64 / compressed as f64),
+        };
+        let mut result = format!(
+            "Column compression of table '{}.{}': {} compressed, {} uncompressed ({}), largest uncompressed first:\n",
+            database,
+            table,
+            human_bytes(compressed),
+            human_bytes(uncompressed),
+            ratio(compressed, uncompressed)
+        );
+        for column in &columns {
+            result.push_str(&format!(
+                "- {}: {} compressed, {} uncompressed ({})\n",
+                column.column,
+                human_bytes(column.compressed),
+                human_bytes(column.uncompressed),
+                ratio(column.compressed, column.uncompressed)
+            ));
+        }
+        
+        Ok(ToolResult::with_structured(result, structured))
+    }
+
+    async fn dump_database_ddl(&self, database: &str, max_bytes: usize) -> Result<ToolResult, ClickHouseError> {
+        let client = self.client().await?;
+        
+        let tables = client.dump_database_ddl(database).await?;
+        if tables.is_empty() {
+            let text = format!("Database '{}' has no tables\n", database);
+            return Ok(ToolResult::with_structured(text, serde_json::json!({ "database": database, "tables": [] })));
+        }
+        
+        let mut result = format!(
+            "DDL of database '{}' ({} tables, each after the tables it depends on):\n",
+            database,
+            tables.len()
+        );
+        let mut shown = Vec::new();
+        let mut bytes = 0;
+        for (table, ddl) in &tables {
+            let statement = format!("\n{};\n", ddl.trim_end().trim_end_matches(';'));
+            bytes += statement.len();
+            if bytes > max_bytes {
+                break;
+            }
+            result.push_str(&statement);
+            shown.push(serde_json::json!({ "table": table, "ddl": ddl }));
+        }
+        let truncated = shown.len() < tables.len();
+        if truncated {
+            result.push_str(&format!("\n{}\n", truncation_notice(shown.len(), Some(tables.len()), "byte_limit")));
+        }
+        
+        let structured = serde_json::json!({ "database": database, "tables": shown, "truncated": truncated });
+        Ok(ToolResult::with_structured(result, structured))
+    }
+
+    async fn refresh_metadata(&self, scope: CacheScope) -> Result<String, ClickHouseError> {
+        let client = self.client().await?;
+        
+        // Capabilities are server-wide, so only a full refresh probes again
+        let full = scope == CacheScope::All;
+        let mut result = self.refresh_schemas(client, scope).await?;
+        if full {
+            match client.probe_capabilities().await {
+                Ok(capabilities) => result.push_str(&capabilities_summary(&capabilities)),
+                Err(e) => result.push_str(&format!("Server capabilities not probed again: {}\n", e)),
+            }
+        }
+        Ok(result)
+    }
+
+    async fn refresh_schemas(&self, client: &ClickHouseClient, scope: CacheScope) -> Result<String, ClickHouseError> {
+        if client.schema_cache_stats().is_none() {
+            return Ok("Schema caching is disabled; every tool call reads the current schema\n".to_string());
+        }
+        let refreshed = client.refresh_metadata(scope).await?;
+        if refreshed.is_empty() {
+            return Ok("No cached schemas matched; nothing to refresh\n".to_string());
+        }
+        
+        let invalidated = refreshed.iter().filter(|r| r.was_cached).count();
+        let mut result = format!("Invalidated {} cached schemas, refreshed:\n", invalidated);
+        for schema in &refreshed {
+            match &schema.outcome {
+                Ok(fresh) => result.push_str(&format!(
+                    "- {}.{}: {} columns, fetched at {}\n",
+                    fresh.database,
+                    fresh.table,
+                    fresh.columns.len(),
+                    fresh.fetched_at.to_rfc3339_opts(SecondsFormat::Secs, true)
+                )),
+                Err(e) => result.push_str(&format!("- {}.{}: not refreshed: {}\n", schema.database, schema.table, e)),
+            }
+        }
+        Ok(result)
+    }
+
+    async fn run_script(&self, statements: Vec<String>) -> Result<String, ClickHouseError> {
+        let client = self.client().await?;
+        
+        let results = client.run_script(statements).await?;
+        
+        let mut result = format!("Ran {} statements:\n", results.len());
+        for (number, exec) in results.iter().enumerate() {
+            result.push_str(&format!(
+                "{}. {} ({})\n",
+                number + 1,
+                exec.kind,
+                human_duration(exec.elapsed)
+            ));
+        }
+        Ok(result)
+    }
+
+    /// Runs `sql` for `admin_execute`, auditing the call on stderr whatever
+    /// the outcome and log level.
+    async fn admin_execute(&self, sql: &str, confirm: Option<&str>) -> Result<String, ClickHouseError> {
+        let result = match check_admin_confirmation(confirm) {
+            Ok(()) => match self.client().await {
+                Ok(client) => client.execute_raw(sql).await,
+                Err(e) => Err(e),
+            },
+            Err(e) => Err(e),
+        };
+        let outcome = match &result {
+            Ok(_) => "ok".to_string(),
+            Err(e) => format!("error: {}", e),
+        };
+        warn!(target: "audit", "{}", admin_audit_entry(&self.session_id, sql, &outcome));
+        
+        match result? {
+            RawResult::Rows(select) => {
+                let total = select.rows.len();
+                let mut text = format!("{} rows:\n", human_count(total as u64));
+                let (shown, stored) = self.store_long_result(ADMIN_TOOL, select.columns, select.rows);
+                for row in &shown {
+                    text.push_str(&format!("{}\n", row));
+                }
+                if let Some(stored) = &stored {
+                    text.push_str(&self.stored_result_notice(stored));
+                }
+                if select.truncated {
+                    text.push_str(&format!("{}\n", truncation_notice(total, None, "row_limit")));
+                }
+                Ok(self.wrap_empty_result(total, text))
+            }
+            RawResult::Executed(exec) => Ok(format!(
+                "Executed {} statement ({}); the number of affected rows isn't reported\n",
+                exec.kind,
+                human_duration(exec.elapsed)
+            )),
+        }
+    }
+
+    /// The results go out as one JSON object from function to value, as
+    /// text and as structured content.
+    async fn aggregate(&self, database: &str, table: &str, column: &str, functions: Vec<String>) -> Result<ToolResult, ClickHouseError> {
+        let client = self.client().await?;
+        
+        let results = client.aggregate(database, table, column, functions).await?;
+        let results = Value::Object(results);
+        Ok(ToolResult::with_structured(format!("{}\n", results), results))
+    }
+
+    async fn group_count(
+        &self,
+        database: &str,
+        table: &str,
+        column: &str,
+        limit: u32,
+        time_filter: Option<&TimeFilter>,
+    ) -> Result<String, ClickHouseError> {
+        const BAR_WIDTH: f64 = 40.0;
+        
+        let client = self.client().await?;
+        
+        let counts = client.group_count(database, table, column, limit, time_filter).await?;
+        
+        let mut result = format!(
+            "Row counts per value of '{}' ({}) in table '{}.{}'",
+            counts.column, counts.column_type, database, table
+        );
+        if let Some(filter) = time_filter {
+            result.push_str(&format!(" over the last {} days of '{}'", filter.last_n_days, filter.column));
+        }
+        result.push_str(&format!(
+            ": {} rows, about {} distinct values\n",
+            human_count(counts.total_rows),
+            human_count(counts.distinct_values)
+        ));
+        
+        if counts.groups.is_empty() {
+            result.push_str("No rows found\n");
+            return Ok(result);
+        }
+        
+        let labels: Vec<&str> = counts.groups.iter().map(|g| g.value.as_deref().unwrap_or("NULL")).collect();
+        let label_width = labels.iter().map(|l| l.chars().count()).max().unwrap_or(0).min(40);
+        let max_count = counts.groups.iter().map(|g| g.count).max().unwrap_or(0);
+        for (group, label) in counts.groups.iter().zip(labels) {
+            let bar_len = if max_count > 0 {
+                (group.count as f64 / max_count as f64 * BAR_WIDTH).round() as usize
+            } else {
+                0
+            };
+            result.push_str(&format!(
+                "{:<label_width$} {:<bar_width$} {} ({:.1}%)\n",
+                label,
+                "#".repeat(bar_len),
+                human_count(group.count),
+                counts.percentage(group),
+                bar_width = BAR_WIDTH as usize
+            ));
+        }
+        if (counts.groups.len() as u64) < counts.distinct_values {
+            let shown: u64 = counts.groups.iter().map(|g| g.count).sum();
+            result.push_str(&format!(
+                "Other values: {} rows\n{}\n",
+                human_count(counts.total_rows.saturating_sub(shown)),
+                truncation_notice(counts.groups.len(), Some(counts.distinct_values as usize), "limit")
+            ));
+        }
+        
+        Ok(result)
+    }
+
+    async fn get_row_by_key(
+        &self,
+        database: &str,
+        table: &str,
+        key_column: &str,
+        key_value: &Value,
+    ) -> Result<String, ClickHouseError> {
+        let client = self.client().await?;
+        
+        match client.get_by_key(database, table, key_column, key_value).await? {
+            Some(row) => serde_json::to_string_pretty(&row).map_err(|e| ClickHouseError::InternalError {
+                message: e.to_string(),
+            }),
+            None => Ok("No row found\n".to_string()),
+        }
+    }
+
+    async fn column_histogram(
+        &self,
+        database: &str,
+        table: &str,
+        column: &str,
+        buckets: u32,
+        sample_ratio: Option<f64>,
+    ) -> Result<String, ClickHouseError> {
+        const BAR_WIDTH: f64 = 40.0;
+        
+        let client = self.client().await?;
+        
+        let histogram = client.column_histogram(database, table, column, buckets, sample_ratio).await?;
+        
+        let mut result = format!(
+            "Histogram of '{}' ({}) in table '{}.{}'",
+            histogram.column, histogram.column_type, database, table
+        );
+        if let Some(ratio) = histogram.sample_ratio {
+            result.push_str(&format!(" sampled at {}", ratio));
+        }
+        result.push_str(":\n");
+        
+        if histogram.buckets.is_empty() {
+            result.push_str("No non-NULL values found\n");
+            return Ok(result);
+        }
+        
+        let max_height = histogram.buckets.iter().map(|b| b.height).fold(0.0, f64::max);
+        for bucket in &histogram.buckets {
+            let bar_len = if max_height > 0.0 {
+                (bucket.height / max_height * BAR_WIDTH).round() as usize
+            } else {
+                0
+            };
+            result.push_str(&format!(
+                "[{:>12.2}, {:>12.2}) {:<width$} {:.0}\n",
+                bucket.lower,
+                bucket.upper,
+                "#".repeat(bar_len),
+                bucket.height,
+                width = BAR_WIDTH as usize
+            ));
+        }
+        
+        Ok(result)
+    }
+
+    async fn sample_rows(
+        &self,
+        database: Option<&str>,
+        table: &str,
+        limit: u64,
+        format: ResponseFormat,
+    ) -> Result<String, ClickHouseError> {
+        let client = self.client().await?;
+        let database = client.resolve_database(database);
+        
+        let sample = client.sample_rows(database, table, limit, format).await?;
+        // CSV is meant for parsing and keeps its header
+        if format == ResponseFormat::Csv {
+            return Ok(sample.text);
+        }
+        Ok(self.wrap_empty_result(sample.row_count, sample.text))
+    }
+
+    /// `rendered`, the output of a query result with `row_count` rows, or
+    /// [`EMPTY_RESULT_MESSAGE`] for no rows unless that is turned off.
+    fn wrap_empty_result(&self, row_count: usize, rendered: String) -> String {
+        if row_count == 0 && self.empty_result_message {
+            EMPTY_RESULT_MESSAGE.to_string()
+        } else {
+            rendered
+        }
+    }
+
+    async fn row_counts_over_time(
+        &self,
+        database: &str,
+        table: &str,
+        time_column: &str,
+        interval: TimeInterval,
+        last_n: Option<u32>,
+    ) -> Result<String, ClickHouseError> {
+        const SPARK: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+        
+        let client = self.client().await?;
+        
+        let buckets = client.row_counts_over_time(database, table, time_column, interval, last_n).await?;
+        
+        let mut result = format!(
+            "Row counts per {} of '{}' in table '{}.{}':\n",
+            interval, time_column, database, table
+        );
+        
+        if buckets.is_empty() {
+            result.push_str("No rows found\n");
+            return Ok(result);
+        }
+        
+        let max_count = buckets.iter().map(|b| b.count).max().unwrap_or(0);
+        let sparkline: String = buckets
+            .iter()
+            .map(|b| {
+                let level = (b.count * (SPARK.len() as u64 - 1)).checked_div(max_count).unwrap_or(0);
+                SPARK[level as usize]
+            })
+            .collect();
+        result.push_str(&format!("Trend: {}\n\n", sparkline));
+        
+        for bucket in &buckets {
+            result.push_str(&format!("{}  {}\n", bucket.bucket, human_count(bucket.count)));
+        }
+        
+        Ok(result)
+    }
+
+    async fn explain_column_defaults(&self, database: Option<&str>, table: &str) -> Result<ToolResult, ClickHouseError> {
+        let client = self.client().await?;
+        let database = client.resolve_database(database);
+        
+        let previews = client.explain_column_defaults(database, table).await?;
+        
+        let mut result = if previews.is_empty() {
+            format!("Table '{}.{}' has no columns with default expressions\n", database, table)
+        } else {
+            format!("Column defaults of table '{}.{}', evaluated for a sampled row:\n", database, table)
+        };
+        for preview in &previews {
+            result.push_str(&format!("- {} {} {}\n", preview.column, preview.default_type, preview.expression));
+            match (&preview.value, &preview.error) {
+                (Some(value), _) => result.push_str(&format!("  value: {}\n", value)),
+                (None, error) => result.push_str(&format!("  could not evaluate: {}\n", error.as_deref().unwrap_or("unknown error"))),
+            }
+        }
+        
+        Ok(ToolResult::with_structured(
+            result,
+            serde_json::json!({ "database": database, "table": table, "columns": previews }),
+        ))
+    }
+
+    async fn poll_new_rows(
+        &self,
+        database: Option<&str>,
+        table: &str,
+        time_column: &str,
+        since: &str,
+        limit: u64,
+    ) -> Result<ToolResult, ClickHouseError> {
+        let client = self.client().await?;
+        let database = client.resolve_database(database);
+        
+        let mut polled = client.poll_new_rows(database, table, time_column, since.to_string(), limit).await?;
+        let total = polled.rows.len();
+        let stored;
+        (polled.rows, stored) = self.store_long_result("poll_new_rows", polled.columns.clone(), polled.rows);
+        
+        let mut result = match &polled.max_timestamp {
+            Some(max_timestamp) => format!(
+                "{} new rows in table '{}.{}' after '{}'; poll again with since: '{}'\n",
+                total, database, table, since, max_timestamp
+            ),
+            None => format!("No new rows in table '{}.{}' after '{}'; poll again with the same since\n", database, table, since),
+        };
+        for row in &polled.rows {
+            result.push_str(&row.to_string());
+            result.push('\n');
+        }
+        if let Some(stored) = &stored {
+            result.push_str(&self.stored_result_notice(stored));
+        }
+        if total as u64 == limit {
+            result.push_str(&truncation_notice(total, None, "limit"));
+            result.push('\n');
+        }
+        
+        let mut structured = serde_json::to_value(&polled).map_err(|e| ClickHouseError::InternalError {
+            message: e.to_string(),
+        })?;
+        if let Some(stored) = &stored {
+            structured["result_uri"] = Value::String(stored.uri());
+        }
+        Ok(ToolResult::with_structured(result, structured))
+    }
+
+    async fn describe(&self, database: Option<&str>, table: Option<&str>) -> Result<String, ClickHouseError> {
+        let client = self.client().await?;
+        let database = client.resolve_database(database);
+        
+        let (mut result, tables) = match table {
+            Some(table) => {
+                let doc = client.describe_table(database, table).await?;
+                (String::new(), vec![doc])
+            }
+            None => {
+                let doc = client.describe_database(database).await?;
+                let mut result = format!("# Database `{}`\n\n", doc.name);
+                if doc.comment.trim().is_empty() {
+                    result.push_str("_No database comment_\n\n");
+                } else {
+                    result.push_str(&format!("{}\n\n", doc.comment.trim()));
+                }
+                if doc.tables.is_empty() {
+                    result.push_str("No tables found\n");
+                }
+                (result, doc.tables)
+            }
+        };
+        
+        let heading = if table.is_some() { "#" } else { "##" };
+        let mut missing = Vec::new();
+        let mut total_columns = 0;
+        let mut commented_columns = 0;
+        
+        for doc in &tables {
+            let name = format!("{}.{}", database, doc.table.name);
+            result.push_str(&format!("{} Table `{}`\n\n", heading, name));
+            if doc.table.comment.trim().is_empty() {
+                result.push_str("_No table comment_\n\n");
+                missing.push(format!("table `{}`", name));
+            } else {
+                result.push_str(&format!("{}\n\n", doc.table.comment.trim()));
+            }
+            
+            result.push_str(&format!("- Engine: {}\n", doc.table.engine));
+            for (label, key) in [
+                ("Sorting key", &doc.table.sorting_key),
+                ("Partition key", &doc.table.partition_key),
+                ("Primary key", &doc.table.primary_key),
+            ] {
+                if !key.is_empty() {
+                    result.push_str(&format!("- {}: `{}`\n", label, key));
+                }
+            }
+            result.push('\n');
+            
+            if !doc.columns.is_empty() {
+                result.push_str("| Column | Type | Comment |\n|---|---|---|\n");
+            }
+            for column in &doc.columns {
+                let comment = column.comment.trim();
+                if comment.is_empty() {
+                    missing.push(format!("column `{}.{}`", name, column.name));
+                }
+                result.push_str(&format!(
+                    "| `{}` | {} | {} |\n",
+                    column.name,
+                    column.r#type,
+                    if comment.is_empty() { "_missing_".to_string() } else { markdown_cell(comment) }
+                ));
+            }
+            result.push('\n');
+            
+            total_columns += doc.columns.len();
+            commented_columns += doc.commented_columns();
+        }
+        
+        result.push_str("## Documentation coverage\n\n");
+        if total_columns > 0 {
+            result.push_str(&format!(
+                "{} of {} columns commented ({:.1}%)\n",
+                commented_columns,
+                total_columns,
+                commented_columns as f64 * 100.0 / total_columns as f64
+            ));
+        }
+        if missing.is_empty() {
+            result.push_str("Nothing is missing a comment\n");
+        } else {
+            result.push_str("\nMissing comments:\n");
+            for item in &missing {
+                result.push_str(&format!("- {}\n", item));
+            }
+        }
+        
+        Ok(result)
+    }
+
+    async fn explain_with_settings(&self, sql: &str, settings: &[(String, String)]) -> Result<String, ClickHouseError> {
+        let client = self.client().await?;
+        
+        client.explain_with_settings(sql, settings).await
+    }
+
+    async fn table_quality_check(
+        &self,
+        database: &str,
+        table: &str,
+        key_columns: &[String],
+        sample_ratio: Option<f64>,
+    ) -> Result<String, ClickHouseError> {
+        let client = self.client().await?;
+        
+        let report = client.table_quality_check(database, table, key_columns, sample_ratio).await?;
+        
+        let mut result = format!("Quality checks for table '{}.{}'", database, table);
+        if let Some(ratio) = report.sample_ratio {
+            result.push_str(&format!(" sampled at {}", ratio));
+        }
+        result.push_str(":\n");
+        for check in &report.checks {
+            let status = match check.status {
+                CheckStatus::Pass => "PASS",
+                CheckStatus::Warn => "WARN",
+            };
+            result.push_str(&format!("- [{}] {}: {}\n", status, check.name, check.detail));
+        }
+        if report.sampling_unavailable {
+            result.push_str("\nThe table has no sampling key, so all rows were scanned\n");
+        }
+        if !report.skipped_columns.is_empty() {
+            result.push_str(&format!(
+                "\nNot checked for nulls (limit of {} columns): {}\n{}\n",
+                MAX_QUALITY_CHECK_COLUMNS,
+                report.skipped_columns.join(", "),
+                truncation_notice(
+                    MAX_QUALITY_CHECK_COLUMNS,
+                    Some(MAX_QUALITY_CHECK_COLUMNS + report.skipped_columns.len()),
+                    "column_limit"
+                )
+            ));
+        }
+        if !key_columns.is_empty() {
+            result.push_str("\nDistinct keys are counted with uniq(), which is exact up to 65536 values and approximate (within about 1%) beyond\n");
+        }
+        
+        Ok(result)
+    }
+
+    async fn estimate_cost(&self, query: &str) -> Result<String, ClickHouseError> {
+        let client = self.client().await?;
+        
+        let estimate = client.estimate_cost(query).await?;
+        if estimate.tables.is_empty() {
+            return Ok("The query reads no MergeTree tables, so there is nothing to estimate\n".to_string());
+        }
+        
+        let mut result = format!(
+            "Estimated to read {} rows in {} parts ({} marks):\n",
+            human_count(estimate.rows),
+            human_count(estimate.parts),
+            human_count(estimate.marks)
+        );
+        for table in &estimate.tables {
+            result.push_str(&format!(
+                "- {}.{}: {} rows, {} parts, {} marks\n",
+                table.database,
+                table.table,
+                human_count(table.rows),
+                human_count(table.parts),
+                human_count(table.marks)
+            ));
+        }
+        
+        Ok(result)
+    }
+
+    async fn analyze_index_usage(&self, query: &str) -> Result<ToolResult, ClickHouseError> {
+        let client = self.client().await?;
+        
+        let usage = client.analyze_index_usage(query).await?;
+        let structured = serde_json::to_value(&usage).map_err(|e| ClickHouseError::InternalError {
+            message: e.to_string(),
+        })?;
+        if usage.indexes.is_empty() {
+            let text = "The query reads no MergeTree tables with indexes, so there is no index usage to show\n";
+            return Ok(ToolResult::with_structured(text, structured));
+        }
+        
+        let share = if usage.granules_total == 0 {
+            0.0
+        } else {
+            usage.granules_selected as f64 * 100.0 / usage.granules_total as f64
+        };
+        let mut result = format!(
+            "Primary key used: {}\nGranules to read: {} of {} ({:.1}%)\n",
+            if usage.pk_used { "yes" } else { "no" },
+            usage.granules_selected,
+            usage.granules_total,
+            share
+        );
+        if !usage.skip_indexes_used.is_empty() {
+            result.push_str(&format!("Skip indexes used: {}\n", usage.skip_indexes_used.join(", ")));
+        }
+        
+        result.push_str("\nIndexes:\n");
+        for step in &usage.indexes {
+            let name = match (&step.name, step.keys.is_empty()) {
+                (Some(name), _) => format!(" {}", name),
+                (None, false) => format!(" ({})", step.keys.join(", ")),
+                (None, true) => String::new(),
+            };
+            result.push_str(&format!(
+                "- {} {}{}: parts {}/{}, granules {}/{}",
+                step.table, step.kind, name, step.parts_selected, step.parts_total, step.granules_selected, step.granules_total
+            ));
+            if let Some(condition) = &step.condition {
+                result.push_str(&format!(", condition {}", condition));
+            }
+            result.push('\n');
+        }
+        
+        if !usage.pk_used {
+            result.push_str("\nThe query has no condition on a prefix of the primary key, so it can't skip granules by key; filter on the leading primary key columns to read less\n");
+        }
+        
+        Ok(ToolResult::with_structured(result, structured))
+    }
+
+    async fn check_partition_pruning(&self, database: &str, table: &str, condition: &str) -> Result<ToolResult, ClickHouseError> {
+        let client = self.client().await?;
+        
+        let pruning = client.check_partition_pruning(database, table, condition).await?;
+        let structured = serde_json::to_value(&pruning).map_err(|e| ClickHouseError::InternalError {
+            message: e.to_string(),
+        })?;
+        
+        let mut result = format!("Partition pruning of '{}.{}' for WHERE {}: {}\n", database, table, condition.trim(), pruning.summary());
+        if !pruning.partition_key.is_empty() {
+            result.push_str(&format!("Partition key: {}\n", pruning.partition_key));
+        }
+        if pruning.parts_total > 0 {
+            result.push_str(&format!(
+                "Parts: {} of {} after the partition key ({:.1}%), {} after all indexes\n",
+                pruning.parts_after_partition_pruning,
+                pruning.parts_total,
+                pruning.ratio() * 100.0,
+                pruning.parts_read
+            ));
+            result.push_str(&format!(
+                "Granules: {} of {}, about {} of {} rows\n",
+                pruning.granules_read, pruning.granules_total, pruning.rows_estimate, pruning.rows_total
+            ));
+        }
+        
+        Ok(ToolResult::with_structured(result, structured))
+    }
+
+    async fn column_lineage(&self, database: &str, table: &str) -> Result<ToolResult, ClickHouseError> {
+        let client = self.client().await?;
+        
+        let lineage = client.column_lineage(database, table).await?;
+        let structured = serde_json::to_value(&lineage).map_err(|e| ClickHouseError::InternalError {
+            message: e.to_string(),
+        })?;
+        if lineage.iter().all(|column| column.sources.is_empty()) {
+            let text = format!("No materialized views write into '{}.{}'\n", database, table);
+            return Ok(ToolResult::with_structured(text, structured));
+        }
+        
+        let mut result = format!("Column lineage of table '{}.{}':\n", database, table);
+        for column in &lineage {
+            result.push_str(&format!("\n{}:\n", column.column));
+            for source in &column.sources {
+                let expression = source.expression.as_deref().unwrap_or("");
+                let line = match source.kind {
+                    SourceKind::Column => source.source_columns.join(", "),
+                    SourceKind::Expression if source.source_columns.is_empty() => match &source.source_table {
+                        Some(table) => format!("{} over {}", expression, table),
+                        None => expression.to_string(),
+                    },
+                    SourceKind::Expression => format!("{} from {}", expression, source.source_columns.join(", ")),
+                    SourceKind::Unresolved => {
+                        let reason = source.reason.as_deref().unwrap_or("");
+                        if expression.is_empty() {
+                            format!("UNRESOLVED: {}", reason)
+                        } else {
+                            format!("UNRESOLVED {}: {}", expression, reason)
+                        }
+                    }
+                };
+                result.push_str(&format!("  - {}: {}\n", source.view, line));
+            }
+        }
+        
+        Ok(ToolResult::with_structured(result, structured))
+    }
+
+    async fn format_query(&self, sql: &str) -> Result<String, ClickHouseError> {
+        let client = self.client().await?;
+        
+        let formatted = client.format_query(sql).await?;
+        Ok(format!("{}\n", formatted.trim_end()))
+    }
+
+    async fn server_metrics(&self) -> Result<String, ClickHouseError> {
+        let client = self.client().await?;
+        
+        let metrics = client.server_metrics().await?;
+        
+        let mut result = "Server metrics:\n".to_string();
+        match client.server_version().await {
+            Ok(version) => result.push_str(&format!("- Version: {}\n", version)),
+            Err(e) => warn!("Could not read server version: {}", e),
+        }
+        let memory = human_bytes(metrics.memory_tracking_bytes.max(0) as u64);
+        match metrics.memory_total_bytes {
+            Some(total) if total > 0.0 => result.push_str(&format!(
+                "- Memory: {} of {} ({:.1}%)\n",
+                memory,
+                human_bytes(total as u64),
+                metrics.memory_tracking_bytes as f64 / total * 100.0
+            )),
+            _ => result.push_str(&format!("- Memory: {}\n", memory)),
+        }
+        result.push_str(&format!("- Running queries: {}\n", metrics.running_queries));
+        if let Some(cpu) = metrics.cpu_usage {
+            result.push_str(&format!("- CPU usage: {:.1}%\n", cpu * 100.0));
+        }
+        if let Some((one, five, fifteen)) = metrics.load_average {
+            result.push_str(&format!("- Load average: {:.2}, {:.2}, {:.2}\n", one, five, fifteen));
+        }
+        if let Some(uptime) = metrics.uptime_seconds {
+            result.push_str(&format!("- Uptime: {}\n", human_duration(Duration::from_secs_f64(uptime.max(0.0)))));
+        }
+        
+        Ok(result)
+    }
+
+    async fn catalog_version(&self) -> Result<ToolResult, ClickHouseError> {
+        let client = self.client().await?;
+        
+        let version = client.catalog_version(self.database_visibility.hidden_databases(None)).await?;
+        
+        let text = format!(
+            "Catalog version: {}\n{} tables. Pass the version as since_version to export_catalog to get only what changed since.\n",
+            version.version, version.tables
+        );
+        Ok(ToolResult::with_structured(text, serde_json::json!(version)))
+    }
+
+    async fn export_catalog(&self, since_version: Option<&str>) -> Result<ToolResult, ClickHouseError> {
+        let client = self.client().await?;
+        
+        let export = client.export_catalog(since_version, self.database_visibility.hidden_databases(None)).await?;
+        
+        let mut result = match since_version {
+            Some(_) if export.tables.is_empty() && export.deleted.is_empty() => "No changes since the given version\n".to_string(),
+            Some(_) => format!(
+                "Catalog changes since the given version: {} tables created or altered, {} dropped\n",
+                export.tables.len(),
+                export.deleted.len()
+            ),
+            None => format!("Catalog of {} tables:\n", export.tables.len()),
+        };
+        if since_version.is_some() && !export.tables.is_empty() {
+            result.push_str("\nCreated or altered:\n");
+        }
+        for table in &export.tables {
+            result.push_str(&format!("- {}.{} ({}, {} columns)\n", table.database, table.name, table.engine, table.columns.len()));
+        }
+        if !export.deleted.is_empty() {
+            result.push_str("\nDropped:\n");
+            for table in &export.deleted {
+                result.push_str(&format!("- {}.{}\n", table.database, table.table));
+            }
+        }
+        result.push_str(&format!("\nVersion: {}\n", export.version));
+        
+        Ok(ToolResult::with_structured(result, serde_json::json!(export)))
+    }
+
+    async fn server_time(&self) -> Result<String, ClickHouseError> {
+        let client = self.client().await?;
+        
+        let time = client.server_time().await?;
+        
+        // A session time zone from --timezone overrides the server's in queries
+        let timezone = client.effective_timezone().unwrap_or(&time.timezone);
+        let mut result = format!(
+            "Server time: {} ({})\nDate and time functions without an explicit time zone use {}.\n",
+            time.now, time.timezone, timezone
+        );
+        if timezone != time.timezone {
+            result.push_str(&format!("This server runs queries in {} instead of the server's time zone.\n", timezone));
+        }
+        result.push_str("Rows returned as JSON show date and time values in UTC, e.g. 2024-05-01T12:00:00Z.\n");
+        Ok(result)
+    }
+
+    async fn insert_pressure(&self, database: Option<&str>, table: Option<&str>) -> Result<ToolResult, ClickHouseError> {
+        let client = self.client().await?;
+        
+        let pressure = client.insert_pressure(database, table).await?;
+        
+        let scope = match (database, table) {
+            (Some(database), Some(table)) => format!("table '{}.{}'", database, table),
+            (Some(database), None) => format!("database '{}'", database),
+            _ => "all tables".to_string(),
+        };
+        let mut result = format!("Insert pressure for {}:\nVerdict: {}\n", scope, pressure.verdict());
+        if let Some(distributed) = &pressure.distributed {
+            result.push_str(&format!("Note: {}\n", distributed.label()));
+        }
+        
+        if pressure.tables.is_empty() {
+            result.push_str("\nNo active parts found\n");
+        } else {
+            result.push_str("\nParts (fullest partition against the insert thresholds):\n");
+            for table in &pressure.tables {
+                result.push_str(&format!(
+                    "- {}.{}: {} parts in the fullest partition, {} active in total (delay at {}, reject at {})\n",
+                    table.database,
+                    table.table,
+                    table.max_partition_parts,
+                    table.active_parts,
+                    table.parts_to_delay_insert,
+                    table.parts_to_throw_insert
+                ));
+            }
+            if pressure.tables.len() == MAX_PRESSURE_TABLES && table.is_none() {
+                result.push_str(&format!(
+                    "{}\nOnly the tables with the fullest partitions are listed\n",
+                    truncation_notice(MAX_PRESSURE_TABLES, None, "row_limit")
+                ));
+            }
+        }
+        
+        result.push_str("\nServer-wide:\n");
+        result.push_str(&format!("- Delayed inserts since start: {}\n", human_count(pressure.delayed_inserts)));
+        result.push_str(&format!("- Rejected inserts since start: {}\n", human_count(pressure.rejected_inserts)));
+        match (pressure.merge_pool_tasks, pressure.merge_pool_size, pressure.merge_pool_saturation()) {
+            (Some(tasks), Some(size), Some(saturation)) => result.push_str(&format!(
+                "- Merge pool: {} of {} slots busy ({:.0}%)\n",
+                tasks,
+                size,
+                saturation * 100.0
+            )),
+            _ => result.push_str("- Merge pool: unknown\n"),
+        }
+        
+        let mut structured = serde_json::to_value(&pressure).map_err(|e| ClickHouseError::InternalError {
+            message: e.to_string(),
+        })?;
+        structured["verdict"] = Value::String(pressure.verdict());
+        Ok(ToolResult::with_structured(result, structured))
+    }
+
+    async fn recommend_ordering(&self, database: &str, table: &str) -> Result<ToolResult, ClickHouseError> {
+        let client = self.client().await?;
+        
+        let advice = client.recommend_ordering(database, table).await?;
+        
+        let mut result = format!(
+            "Ordering advice for table '{}.{}' ({}, {} rows):\n",
+            database, table, advice.engine, human_count(advice.total_rows)
+        );
+        let current = |key: &str| if key.is_empty() { "(none)".to_string() } else { key.to_string() };
+        result.push_str(&format!(
+            "Current: ORDER BY {}, PARTITION BY {}\n",
+            current(&advice.current_order_by),
+            current(&advice.current_partition_by)
+        ));
+        result.push_str(&format!("\nSuggested:\n{}\n", advice.ddl()));
+        
+        result.push_str("\nRationale:\n");
+        for reason in &advice.rationale {
+            result.push_str(&format!("- {}\n", reason));
+        }
+        
+        result.push_str("\nColumn cardinality:\n");
+        for column in &advice.columns {
+            match column.distinct {
+                Some(distinct) => {
+                    result.push_str(&format!("- {} ({}): ~{} distinct\n", column.name, column.r#type, human_count(distinct)))
+                }
+                None => result.push_str(&format!("- {} ({}): not measured\n", column.name, column.r#type)),
+            }
+        }
+        result.push_str("\nChanging ORDER BY or PARTITION BY means creating a new table and copying the data into it.\n");
+        
+        let mut structured = serde_json::to_value(&advice).map_err(|e| ClickHouseError::InternalError {
+            message: e.to_string(),
+        })?;
+        structured["ddl"] = Value::String(advice.ddl());
+        Ok(ToolResult::with_structured(result, structured))
+    }
+
+    async fn keeper_info(&self, path: &str, summary: bool) -> Result<String, ClickHouseError> {
+        const MAX_VALUE_CHARS: usize = 200;
+        
+        let client = self.client().await?;
+        
+        if summary {
+            let events = client.keeper_events().await?;
+            if events.is_empty() {
+                return Ok("No ZooKeeper events recorded since server start\n".to_string());
+            }
+            
+            let mut result = "ZooKeeper event counters since server start:\n".to_string();
+            for event in events {
+                result.push_str(&format!("- {}: {}\n", event.event, event.value));
+            }
+            return Ok(result);
+        }
+        
+        let nodes = client.keeper_children(path).await?;
+        if nodes.is_empty() {
+            return Ok(format!("No child nodes under '{}'\n", path));
+        }
+        
+        let mut result = format!("Child nodes of '{}':\n", path);
+        for node in nodes {
+            result.push_str(&format!(
+                "- {} (children: {}, {}, modified {})",
+                node.name,
+                human_count(node.num_children as u64),
+                human_bytes(node.data_length as u64),
+                node.mtime
+            ));
+            if !node.value.is_empty() {
+                let value: String = node.value.chars().take(MAX_VALUE_CHARS).collect();
+                let ellipsis = if node.value.chars().count() > MAX_VALUE_CHARS { "..." } else { "" };
+                result.push_str(&format!(": {}{}", value.replace('\n', " "), ellipsis));
+            }
+            result.push('\n');
+        }
+        
+        Ok(result)
+    }
+
+    /// Handles one line of input and returns the serialized reply, if any.
+    ///
+    /// A line holds either a single JSON-RPC message or a batch (JSON array)
+    /// of messages. Notifications never produce a reply, so a batch made up
+    /// only of notifications returns `None`. Replies are compact JSON and
+    /// never contain a raw newline.
+    pub async fn handle_line(&mut self, line: &str) -> Result<Option<String>> {
+        let message = match serde_json::from_str::<Value>(line) {
+            Ok(message) => message,
+            Err(e) => {
+                error!("Failed to parse JSON-RPC request: {} - Input: {}", e, line);
+                let error_response = JsonRpcResponse::error(None, -32700, "Parse error");
+                return Ok(Some(serde_json::to_string(&error_response)?));
+            }
+        };
+        
+        match message {
+            Value::Array(batch) if batch.is_empty() => {
+                warn!("Received empty JSON-RPC batch");
+                let error_response = JsonRpcResponse::error(None, -32600, "Invalid Request");
+                Ok(Some(serde_json::to_string(&error_response)?))
+            }
+            Value::Array(batch) => {
+                debug!("Processing JSON-RPC batch of {} messages", batch.len());
+                let mut responses = Vec::new();
+                for message in batch {
+                    if let Some(response) = self.process_message(message).await {
+                        responses.push(response);
+                    }
+                }
+                
+                if responses.is_empty() {
+                    Ok(None)
+                } else {
+                    Ok(Some(serde_json::to_string(&responses)?))
+                }
+            }
+            message => match self.process_message(message).await {
+                Some(response) => Ok(Some(serde_json::to_string(&response)?)),
+                None => Ok(None),
+            },
+        }
+    }
+
+    async fn process_message(&mut self, message: Value) -> Option<JsonRpcResponse> {
+        // The client answering a request of ours, such as a keepalive ping
+        if message.get("method").is_none() && (message.get("result").is_some() || message.get("error").is_some()) {
+            debug!("Ignoring response from client: {}", message);
+            return None;
+        }
+        let request = match serde_json::from_value::<JsonRpcRequest>(message.clone()) {
+            Ok(request) => request,
+            Err(e) => {
+                error!("Invalid JSON-RPC request: {} - Input: {}", e, message);
+                let id = message.get("id").cloned().filter(|id| !id.is_null());
+                return Some(JsonRpcResponse::error(id, -32600, "Invalid Request"));
+            }
+        };
+        if request.jsonrpc != "2.0" {
+            warn!("Rejecting request with unsupported JSON-RPC version '{}'", request.jsonrpc);
+            let message = format!("Invalid Request: unsupported jsonrpc version '{}', expected '2.0'", request.jsonrpc);
+            return Some(JsonRpcResponse::error(request.id, -32600, &message));
+        }
+        
+        let id = request.id.clone();
+        let response = match self.handle_request(request).await {
+            Ok(response) => response,
+            Err(e) => {
+                error!("Request handling failed: {}", e);
+                JsonRpcResponse::error(id.clone(), -32603, &format!("Internal error: {}", e))
+            }
+        };
+        
+        if id.is_none() {
+            debug!("Not responding to notification");
+            None
+        } else {
+            Some(response)
+        }
+    }
+
+    pub async fn run(&mut self) -> Result<()> {
+        info!("Starting MCP server main loop");
+        
+        let stdin = AsyncBufReader::new(tokio::io::stdin());
+        self.serve(stdin, tokio::io::stdout()).await
+    }
+
+    /// Serves newline-delimited JSON-RPC from `reader` until end of input.
+    ///
+    /// Tool calls, also those in a batch, run as background tasks so that a
+    /// `notifications/cancelled` arriving while they are in flight can abort
+    /// them; all other messages are handled in order. Pending tool calls are awaited before returning.
+    pub async fn serve<R, W>(&mut self, reader: R, writer: W) -> Result<()>
+    where
+        R: AsyncBufRead + Unpin,
+        W: AsyncWrite + Unpin + Send + 'static,
+    {
+        let mut reader = LineReader::new(reader).with_max_line_length(self.max_line_length);
+        if self.recorder.is_some() {
+            reader = reader.with_raw_capture();
+        }
+        let (responses, mut outgoing) = mpsc::unbounded_channel::<String>();
+        
+        let keepalive_interval = self.keepalive_interval;
+        let recorder = self.recorder.clone();
+        let writer_task = tokio::spawn(async move {
+            let mut writer = writer;
+            let mut pings = 0u64;
+            loop {
+                let response_json = match keepalive_interval {
+                    Some(interval) => match tokio::time::timeout(interval, outgoing.recv()).await {
+                        Ok(response_json) => response_json,
+                        Err(_) => {
+                            pings += 1;
+                            Some(keepalive_ping(pings))
+                        }
+                    },
+                    None => outgoing.recv().await,
+                };
+                let Some(response_json) = response_json else { break };
+                if let Some(recorder) = &recorder {
+                    recorder.record_outbound(&response_json);
+                }
+                debug!("Sending response: {}", response_json);
+                // One write per response so a line is never interleaved or split
+                writer.write_all(&encode_frame(&response_json)).await?;
+                writer.flush().await?;
+            }
+            Ok::<(), std::io::Error>(())
+        });
+        
+        let mut tasks = JoinSet::new();
+        self.ensure_health_probe();
+        let mut state_notifications = self.forward_state_notifications(&responses);
+        
+        while let Some(frame) = reader.next_frame().await? {
+            if let Some(recorder) = &self.recorder {
+                match (&frame, reader.last_raw()) {
+                    (Frame::TooLong { length }, _) => recorder.record_discarded(*length),
+                    (_, Some(raw)) => recorder.record_inbound(raw),
+                    (_, None) => {}
+                }
+            }
+            let response_json = match frame {
+                Frame::Line(line) => {
+                    let line = line.trim();
+                    if line.is_empty() {
+                        continue;
+                    }
+                    
+                    debug!("Received line: {}", line);
+                    match serde_json::from_str::<Value>(line) {
+                        Ok(Value::Array(batch)) if batch.iter().any(|message| tool_call_id(message).is_some()) => {
+                            self.spawn_batch(&mut tasks, batch, &responses).await;
+                            continue;
+                        }
+                        Ok(message) => {
+                            if let Some(id) = tool_call_id(&message) {
+                                let responses = responses.clone();
+                                self.spawn_tool_call(&mut tasks, id, line.to_string(), move |response_json| {
+                                    let _ = responses.send(response_json);
+                                });
+                                continue;
+                            }
+                        }
+                        Err(_) => {}
+                    }
+                    self.handle_line(line).await?
+                }
+                Frame::InvalidUtf8(lossy) => {
+                    error!("Received line that is not valid UTF-8: {}", lossy);
+                    // The id is usually intact when only a string value is broken
+                    let error_response = JsonRpcResponse::error(
+                        serde_json::from_str::<Value>(&lossy).ok().as_ref().and_then(request_id),
+                        -32700,
+                        "Parse error: invalid UTF-8",
+                    );
+                    Some(serde_json::to_string(&error_response)?)
+                }
+                Frame::TooLong { length } => {
+                    error!("Discarded {} byte line exceeding the {} byte limit", length, self.max_line_length);
+                    let message = format!("Parse error: line exceeds {} bytes", self.max_line_length);
+                    let error_response = JsonRpcResponse::error(None, -32700, &message);
+                    Some(serde_json::to_string(&error_response)?)
+                }
+            };
+            
+            if let Some(response_json) = response_json {
+                if responses.send(response_json).is_err() {
+                    break;
+                }
+            }
+            
+            while tasks.try_join_next().is_some() {}
+            if state_notifications.is_none() {
+                state_notifications = self.forward_state_notifications(&responses);
+            }
+        }
+        
+        if let Some(forwarder) = state_notifications {
+            forwarder.abort();
+        }
+        let probe = self.health_probe.lock().unwrap().take();
+        if let Some(probe) = probe {
+            probe.stop().await;
+        }
+        if !tasks.is_empty() {
+            info!("End of input reached, waiting for {} in-flight requests", tasks.len());
+        }
+        while tasks.join_next().await.is_some() {}
+        
+        drop(responses);
+        writer_task.await??;
+        
+        info!("End of input reached, shutting down server");
+        Ok(())
+    }
+
+    /// Starts the background health probe if one is configured and the
+    /// connection exists, unless it is already running.
+    fn ensure_health_probe(&self) {
+        let (Some(interval), Some(connection)) = (self.health_interval, self.connection.get()) else {
+            return;
+        };
+        let mut probe = self.health_probe.lock().unwrap();
+        if probe.is_none() {
+            info!("Probing ClickHouse health every {:?}", interval);
+            *probe = Some(connection.start_health_probe(interval));
+        }
+    }
+
+    /// Once the client has initialized and the connection exists, starts a
+    /// task sending each connection state change to the client as a
+    /// `notifications/message` at or above the client's log level.
+    fn forward_state_notifications(&self, responses: &mpsc::UnboundedSender<String>) -> Option<JoinHandle<()>> {
+        if !self.initialized {
+            return None;
+        }
+        let mut transitions = self.connection.get()?.subscribe();
+        let log_level = Arc::clone(&self.log_level);
+        let responses = responses.clone();
+        
+        Some(tokio::spawn(async move {
+            loop {
+                let transition = match transitions.recv().await {
+                    Ok(transition) => transition,
+                    Err(broadcast::error::RecvError::Lagged(missed)) => {
+                        warn!("Dropped {} connection state notifications", missed);
+                        continue;
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                };
+                let (level, notification) = state_notification(&transition);
+                if level < log_level.load(Ordering::SeqCst) {
+                    continue;
+                }
+                if responses.send(notification.to_string()).is_err() {
+                    break;
+                }
+            }
+        }))
+    }
+
+    /// Runs the tool call on `line` as a background task registered in
+    /// `in_flight`, passing its reply to `reply`. A call is never answered if
+    /// it gets cancelled. A second call with the id of one still in flight is
+    /// rejected, as it couldn't be cancelled on its own.
+    fn spawn_tool_call<F>(&self, tasks: &mut JoinSet<()>, id: Value, line: String, reply: F)
+    where
+        F: FnOnce(String) + Send + 'static,
+    {
+        let key = id.to_string();
+        
+        // Hold the lock until the request is registered so a fast task cannot
+        // try to deregister itself first.
+        let mut registry = self.in_flight.lock().unwrap();
+        if registry.contains_key(&key) {
+            warn!("Rejecting tool call {} while another with the same id is in flight", key);
+            let message = format!("Invalid Request: request {} is already in flight", key);
+            if let Ok(response_json) = serde_json::to_string(&JsonRpcResponse::error(Some(id), -32600, &message)) {
+                reply(response_json);
+            }
+            return;
+        }
+        
+        let query_id_prefix = self.next_query_id_prefix();
+        let mut worker = self.clone();
+        let in_flight = Arc::clone(&self.in_flight);
+        let task_key = key.clone();
+        let task_prefix = query_id_prefix.clone();
+        
+        let abort = tasks.spawn(async move {
+            let response = with_query_id(task_prefix, worker.handle_line(&line)).await;
+            in_flight.lock().unwrap().remove(&task_key);
+            
+            match response {
+                Ok(Some(response_json)) => reply(response_json),
+                Ok(None) => {}
+                Err(e) => {
+                    error!("Tool call {} failed: {}", task_key, e);
+                    let error_response = JsonRpcResponse::error(Some(id), -32603, &format!("Internal error: {}", e));
+                    if let Ok(response_json) = serde_json::to_string(&error_response) {
+                        reply(response_json);
+                    }
+                }
+            }
+        });
+        registry.insert(key, InFlightRequest { abort, query_id_prefix });
+    }
+    
+    /// Handles a batch holding tool calls. Those run as background tasks
+    /// like single ones, so a slow call doesn't hold up reading input and a
+    /// cancellation can reach it; the other messages are handled here in
+    /// order. The array of replies is sent once all of them are in, leaving
+    /// out cancelled calls and notifications.
+    async fn spawn_batch(&mut self, tasks: &mut JoinSet<()>, batch: Vec<Value>, responses: &mpsc::UnboundedSender<String>) {
+        debug!("Processing JSON-RPC batch of {} messages", batch.len());
+        let mut replies = Vec::with_capacity(batch.len());
+        for message in batch {
+            let (reply, receiver) = oneshot::channel::<String>();
+            match tool_call_id(&message) {
+                Some(id) => self.spawn_tool_call(tasks, id, message.to_string(), move |response_json| {
+                    let _ = reply.send(response_json);
+                }),
+                None => {
+                    if let Some(response) = self.process_message(message).await {
+                        match serde_json::to_string(&response) {
+                            Ok(response_json) => {
+                                let _ = reply.send(response_json);
+                            }
+                            Err(e) => error!("Failed to serialize batch response: {}", e),
+                        }
+                    }
+                }
+            }
+            replies.push(receiver);
+        }
+        
+        let responses = responses.clone();
+        tasks.spawn(async move {
+            let mut answered = Vec::with_capacity(replies.len());
+            for receiver in replies {
+                if let Ok(response_json) = receiver.await {
+                    answered.push(response_json);
+                }
+            }
+            if !answered.is_empty() {
+                let _ = responses.send(format!("[{}]", answered.join(",")));
+            }
+        });
+    }
+}
+
+/// The `ping` request `serve` sends after idling for the keepalive interval,
+/// the `count`th of the session.
+fn keepalive_ping(count: u64) -> String {
+    serde_json::json!({
+        "jsonrpc": "2.0",
+        "id": format!("keepalive-{}", count),
+        "method": "ping"
+    })
+    .to_string()
+}
+
+/// The `notifications/message` severities in increasing order, as used by
+/// `logging/setLevel`.
+const LOG_LEVELS: &[&str] = &["debug", "info", "notice", "warning", "error", "critical", "alert", "emergency"];
+
+/// `info`: state changes are sent unless the client asks for less.
+const DEFAULT_LOG_LEVEL: usize = 1;
+
+/// The `notifications/message` for a connection state change, with the index
+/// of its level in `LOG_LEVELS`: `warning` when the connection got worse,
+/// `info` when it recovered.
+fn state_notification(transition: &StateTransition) -> (usize, Value) {
+    let (from, to) = (state_label(transition.from), state_label(transition.to));
+    let (level, message) = match &transition.error {
+        Some(error) => (3, format!("ClickHouse connection {} -> {}: {}", from, to, error)),
+        None => (1, format!("ClickHouse connection {} -> {}", from, to)),
+    };
+    let notification = serde_json::json!({
+        "jsonrpc": "2.0",
+        "method": "notifications/message",
+        "params": {
+            "level": LOG_LEVELS[level],
+            "logger": "clickhouse",
+            "data": {
+                "message": message,
+                "from": from,
+                "to": to,
+                "at": transition.at.to_rfc3339_opts(SecondsFormat::Millis, true),
+                "error": transition.error,
+            }
+        }
+    });
+    (level, notification)
+}
+
+/// The methods `handle_request` answers; others are counted as `unknown`.
+const KNOWN_METHODS: &[&str] = &[
+    "initialize",
+    "initialized",
+    "ping",
+    "tools/list",
+    "tools/call",
+    "notifications/cancelled",
+    "prompts/list",
+    "prompts/get",
+    "resources/list",
+    "resources/read",
+    "logging/setLevel",
+];
+
+fn snapshot_with_state(metrics: &Metrics, connection: &OnceCell<Arc<ConnectionManager>>) -> MetricsSnapshot {
+    let mut snapshot = metrics.snapshot();
+    snapshot.connection_state = connection.get().map(|connection| connection.state());
+    snapshot
+}
+
+/// Answers one HTTP request: the metrics for `GET /metrics`, 404 otherwise.
+async fn serve_metrics_request(stream: TcpStream, snapshot: MetricsSnapshot) -> std::io::Result<()> {
+    let mut stream = AsyncBufReader::new(stream);
+    let mut request_line = String::new();
+    stream.read_line(&mut request_line).await?;
+    // Skip the headers; there is no body to read for GET
+    let mut header = String::new();
+    while stream.read_line(&mut header).await? > 2 {
+        header.clear();
+    }
+    
+    let mut parts = request_line.split_whitespace();
+    let (status, content_type, body) = match (parts.next(), parts.next()) {
+        (Some("GET"), Some("/metrics")) => ("200 OK", "text/plain; version=0.0.4", snapshot.to_prometheus()),
+        _ => ("404 Not Found", "text/plain", "Not found\n".to_string()),
+    };
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    );
+    stream.get_mut().write_all(response.as_bytes()).await?;
+    stream.get_mut().shutdown().await
+}
+
+/// Returns the id of a `tools/call` request, which is run in the background
+/// so it can be cancelled.
+fn tool_call_id(message: &Value) -> Option<Value> {
+    if message.get("method")? != "tools/call" {
+        return None;
+    }
+    request_id(message)
+}
+
+/// The id of a request, if it has one that isn't null.
+fn request_id(message: &Value) -> Option<Value> {
+    message.get("id").filter(|id| !id.is_null()).cloned()
+}
+
+/// The audit log line of an `admin_execute` call, as JSON: when, which
+/// session, the statement as given and `ok` or the error.
+pub fn admin_audit_entry(session_id: &str, statement: &str, outcome: &str) -> String {
+    serde_json::json!({
+        "audit": ADMIN_TOOL,
+        "time": chrono::Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+        "session": session_id,
+        "statement": statement,
+        "outcome": outcome,
+    })
+    .to_string()
+}
+
+/// The definition of `admin_execute`, listed only when it's enabled.
+fn admin_tool_definition() -> Value {
+    let mut tool = serde_json::json!({
+        "name": ADMIN_TOOL,
+        "description": "Run one arbitrary SQL statement, e.g. SYSTEM FLUSH LOGS, for administrators. Returns the first rows of a SELECT, or confirms other statements ran. DROP DATABASE, TRUNCATE of more than one table and GRANT are refused, and every call is audited",
+        "inputSchema": {
+            "type": "object",
+            "properties": {
+                "sql": {
+                    "type": "string",
+                    "description": "The statement to run"
+                },
+                "confirm": {
+                    "type": "string",
+                    "description": format!("Must be exactly \"{}\"", ADMIN_CONFIRMATION)
+                }
+            },
+            "required": ["sql", "confirm"]
+        }
+    });
+    add_raw_argument(&mut tool);
+    tool
+}
+
+/// Definitions of the built-in tools, advertised without the tool prefix.
+fn tool_definitions() -> Vec<Value> {
+    let mut tools = vec![
+        serde_json::json!({
+            "name": "list_databases",
+            "description": "List all databases in the ClickHouse instance",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "include_system": {
+                        "type": "boolean",
+                        "description": "Include the system and INFORMATION_SCHEMA databases (default false unless the server shows them)"
+                    }
+                },
+                "required": []
+            }
+        }),
+        serde_json::json!({
+            "name": "list_formats",
+            "description": "List the data formats this ClickHouse server supports, separately for input (INSERT ... FORMAT) and output (SELECT ... FORMAT), e.g. to pick one for an export",
+            "inputSchema": {
+                "type": "object",
+                "properties": {},
+                "required": []
+            }
+        }),
+        serde_json::json!({
+            "name": "current_grants",
+            "description": "Show the grants of the user this server connects as, one GRANT statement per line, to check what is permitted before trying it",
+            "inputSchema": {
+                "type": "object",
+                "properties": {},
+                "required": []
+            }
+        }),
+        serde_json::json!({
+            "name": "list_tables",
+            "description": "List all tables in a specific database",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "database": {
+                        "type": "string",
+                        "description": "The database name to list tables from (defaults to the configured CLICKHOUSE_DATABASE)"
+                    },
+                    "information_schema": {
+                        "type": "boolean",
+                        "description": "Return INFORMATION_SCHEMA.TABLES rows (TABLE_CATALOG, TABLE_SCHEMA, TABLE_NAME, TABLE_TYPE) as JSON, for tools that read that shape (default false)"
+                    }
+                },
+                "required": []
+            }
+        }),
+        serde_json::json!({
+            "name": "list_all_tables",
+            "description": "List the tables of all databases in one call, grouped by database",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "include_system": {
+                        "type": "boolean",
+                        "description": "Include the system and INFORMATION_SCHEMA databases (default false unless the server shows them)"
+                    }
+                }
+            }
+        }),
+        serde_json::json!({
+            "name": "recently_modified_tables",
+            "description": "List the tables whose data changed most recently, by the newest modification time of their parts",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "database": {
+                        "type": "string",
+                        "description": "Only tables of this database (default: all listed databases, without the system databases unless the server shows them)"
+                    },
+                    "limit": {
+                        "type": "integer",
+                        "description": "How many tables to list (default 20, at most 1000)"
+                    }
+                }
+            }
+        }),
+        serde_json::json!({
+            "name": "get_table_schema",
+            "description": "Get the schema (columns) of a specific table",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "database": {
+                        "type": "string",
+                        "description": "The database name (defaults to the configured CLICKHOUSE_DATABASE)"
+                    },
+                    "table": {
+                        "type": "string",
+                        "description": "The table name, or \"@last\" for the table this session used most recently"
+                    },
+                    "compact": {
+                        "type": "boolean",
+                        "description": "Summarize instead of listing every column, for very wide tables: key columns, columns grouped by type with name prefixes like cpu_*, and the first columns (default false)"
+                    },
+                    "max_columns": {
+                        "type": "integer",
+                        "description": "With compact, how many of the first columns to list verbatim (default 20)"
+                    },
+                    "information_schema": {
+                        "type": "boolean",
+                        "description": "Return INFORMATION_SCHEMA.COLUMNS rows as JSON, with types mapped to standard SQL names (String is VARCHAR, UInt64 is BIGINT UNSIGNED, ...); types without a standard name keep their ClickHouse name with TYPE_MAPPED false. Takes precedence over compact (default false)"
+                    }
+                },
+                "required": ["table"]
+            }
+        }),
+        serde_json::json!({
+            "name": "database_exists",
+            "description": "Check whether a database exists. Answers true or false instead of failing, to branch on",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "database": {
+                        "type": "string",
+                        "description": "The database name, matched exactly"
+                    }
+                },
+                "required": ["database"]
+            }
+        }),
+        serde_json::json!({
+            "name": "table_exists",
+            "description": "Check whether a table exists. Answers true or false instead of failing, to branch on; false as well if the database doesn't exist",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "database": {
+                        "type": "string",
+                        "description": "The database name (defaults to the configured CLICKHOUSE_DATABASE)"
+                    },
+                    "table": {
+                        "type": "string",
+                        "description": "The table name, matched exactly"
+                    }
+                },
+                "required": ["table"]
+            }
+        }),
+        serde_json::json!({
+            "name": "get_table_schemas",
+            "description": "Get the schemas of several tables at once, e.g. when planning a join. Tables that don't exist are reported individually",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "tables": {
+                        "type": "array",
+                        "description": "The tables to describe (at most 20)",
+                        "items": {
+                            "type": "object",
+                            "properties": {
+                                "database": {
+                                    "type": "string",
+                                    "description": "The database name (defaults to the configured CLICKHOUSE_DATABASE)"
+                                },
+                                "table": {
+                                    "type": "string",
+                                    "description": "The table name"
+                                }
+                            },
+                            "required": ["table"]
+                        }
+                    }
+                },
+                "required": ["tables"]
+            }
+        }),
+        serde_json::json!({
+            "name": "suggest_joins",
+            "description": "Suggest columns to join two tables on, guessed from their schemas: matching names and types, and <table>_id columns referencing id",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "left_database": {
+                        "type": "string",
+                        "description": "Database of the first table (defaults to the configured CLICKHOUSE_DATABASE)"
+                    },
+                    "left_table": {
+                        "type": "string",
+                        "description": "The first table"
+                    },
+                    "right_database": {
+                        "type": "string",
+                        "description": "Database of the second table (defaults to the configured CLICKHOUSE_DATABASE)"
+                    },
+                    "right_table": {
+                        "type": "string",
+                        "description": "The second table"
+                    }
+                },
+                "required": ["left_table", "right_table"]
+            }
+        }),
+        serde_json::json!({
+            "name": "list_projections",
+            "description": "List the projections defined on a specific table",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "database": {
+                        "type": "string",
+                        "description": "The database name"
+                    },
+                    "table": {
+                        "type": "string",
+                        "description": "The table name, or \"@last\" for the table this session used most recently"
+                    }
+                },
+                "required": ["database", "table"]
+            }
+        }),
+        serde_json::json!({
+            "name": "table_dependencies",
+            "description": "Show which tables a table reads from and which tables (e.g. materialized views) depend on it",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "database": {
+                        "type": "string",
+                        "description": "The database name"
+                    },
+                    "table": {
+                        "type": "string",
+                        "description": "The table name, or \"@last\" for the table this session used most recently"
+                    }
+                },
+                "required": ["database", "table"]
+            }
+        }),
+        serde_json::json!({
+            "name": "count_rows",
+            "description": "Count the rows of a table exactly with count(), or with approximate=true estimate them from the table's parts without scanning (MergeTree-family tables and Distributed tables over them)",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "database": {
+                        "type": "string",
+                        "description": "The database name"
+                    },
+                    "table": {
+                        "type": "string",
+                        "description": "The table name, or \"@last\" for the table this session used most recently"
+                    },
+                    "approximate": {
+                        "type": "boolean",
+                        "description": "Estimate from system.parts instead of counting (default false)"
+                    }
+                },
+                "required": ["database", "table"]
+            }
+        }),
+        serde_json::json!({
+            "name": "column_compression",
+            "description": "Show the compressed and uncompressed size of each column of a table and its compression ratio, largest uncompressed first, to find the biggest and worst-compressing columns",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "database": {
+                        "type": "string",
+                        "description": "The database name"
+                    },
+                    "table": {
+                        "type": "string",
+                        "description": "The table name, or \"@last\" for the table this session used most recently"
+                    }
+                },
+                "required": ["database", "table"]
+            }
+        }),
+        serde_json::json!({
+            "name": "dump_database_ddl",
+            "description": "Show the CREATE statements of all tables, views and dictionaries of a database, ordered so each comes after the tables it reads from or writes into",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "database": {
+                        "type": "string",
+                        "description": "The database name"
+                    },
+                    "max_result_bytes": {
+                        "type": "integer",
+                        "description": "The most bytes of statements to return; the rest are left out with a notice (default 262144)"
+                    }
+                },
+                "required": ["database"]
+            }
+        }),
+        serde_json::json!({
+            "name": "null_fraction",
+            "description": "Show the percentage of NULL values in a column (0% for columns that are not Nullable)",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "database": {
+                        "type": "string",
+                        "description": "The database name"
+                    },
+                    "table": {
+                        "type": "string",
+                        "description": "The table name, or \"@last\" for the table this session used most recently"
+                    },
+                    "column": {
+                        "type": "string",
+                        "description": "The column to check"
+                    }
+                },
+                "required": ["database", "table", "column"]
+            }
+        }),
+        serde_json::json!({
+            "name": "refresh_metadata",
+            "description": "Clear cached table schemas and fetch them again, e.g. after adding a column. Without arguments every cached schema is refreshed and the server's optional features are checked again",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "database": {
+                        "type": "string",
+                        "description": "Only refresh schemas of tables in this database"
+                    },
+                    "table": {
+                        "type": "string",
+                        "description": "Only refresh this table's schema (requires database)"
+                    }
+                }
+            }
+        }),
+        serde_json::json!({
+            "name": "run_script",
+            "description": "Run several SQL statements in order, e.g. to set up tables, stopping at the first failure. Not a transaction: statements that ran before a failure are not rolled back. Only available when the server was started with --allow-writes",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "statements": {
+                        "type": "array",
+                        "items": {"type": "string"},
+                        "description": "The statements to run, one SQL statement per item (at most 100)"
+                    }
+                },
+                "required": ["statements"]
+            }
+        }),
+        serde_json::json!({
+            "name": "group_count",
+            "description": "Count rows per distinct value of a column and show the most frequent values with their share of the rows",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "database": {
+                        "type": "string",
+                        "description": "The database name"
+                    },
+                    "table": {
+                        "type": "string",
+                        "description": "The table name, or \"@last\" for the table this session used most recently"
+                    },
+                    "column": {
+                        "type": "string",
+                        "description": "The column to group by; must not be an Array, Map or Tuple"
+                    },
+                    "limit": {
+                        "type": "integer",
+                        "description": "How many of the most frequent values to show (default 20, max 1000)"
+                    },
+                    "time_filter": {
+                        "type": "object",
+                        "description": "Only count rows from the last days of a Date or DateTime column",
+                        "properties": {
+                            "column": {
+                                "type": "string",
+                                "description": "The Date or DateTime column to filter on"
+                            },
+                            "last_n_days": {
+                                "type": "integer",
+                                "description": "How many days back to count, between 1 and 3650"
+                            }
+                        },
+                        "required": ["column", "last_n_days"]
+                    }
+                },
+                "required": ["database", "table", "column"]
+            }
+        }),
+        serde_json::json!({
+            "name": "aggregate",
+            "description": "Compute aggregate statistics of a column over a whole table in one query, returned as a JSON object from function name to value, e.g. {\"avg\": 12.5, \"max\": 99}",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "database": {
+                        "type": "string",
+                        "description": "The database name"
+                    },
+                    "table": {
+                        "type": "string",
+                        "description": "The table name, or \"@last\" for the table this session used most recently"
+                    },
+                    "column": {
+                        "type": "string",
+                        "description": "The column to aggregate"
+                    },
+                    "functions": {
+                        "type": "array",
+                        "items": {
+                            "type": "string",
+                            "enum": ["count", "sum", "avg", "min", "max", "uniq", "median"]
+                        },
+                        "description": "The functions to apply: count (non-NULL values), sum, avg and median (numeric columns only), min, max and uniq (approximate distinct count)"
+                    }
+                },
+                "required": ["database", "table", "column", "functions"]
+            }
+        }),
+        serde_json::json!({
+            "name": "get_row_by_key",
+            "description": "Fetch a single row from a table by the value of a key column",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "database": {
+                        "type": "string",
+                        "description": "The database name"
+                    },
+                    "table": {
+                        "type": "string",
+                        "description": "The table name, or \"@last\" for the table this session used most recently"
+                    },
+                    "key_column": {
+                        "type": "string",
+                        "description": "The column to match, usually the primary key"
+                    },
+                    "key_value": {
+                        "type": ["string", "number"],
+                        "description": "The value to look up; Date/DateTime keys accept ISO-8601 (e.g. 2024-01-01T00:00:00Z), DateTime keys also a Unix epoch in seconds"
+                    }
+                },
+                "required": ["database", "table", "key_column", "key_value"]
+            }
+        }),
+        serde_json::json!({
+            "name": "column_histogram",
+            "description": "Show the distribution of a numeric column as histogram buckets",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "database": {
+                        "type": "string",
+                        "description": "The database name"
+                    },
+                    "table": {
+                        "type": "string",
+                        "description": "The table name, or \"@last\" for the table this session used most recently"
+                    },
+                    "column": {
+                        "type": "string",
+                        "description": "The numeric column to build the histogram for"
+                    },
+                    "buckets": {
+                        "type": "integer",
+                        "description": "Number of histogram buckets (default 20)"
+                    },
+                    "sample_ratio": {
+                        "type": "number",
+                        "description": "Fraction of rows to sample, e.g. 0.1 (requires a sampling key)"
+                    }
+                },
+                "required": ["database", "table", "column"]
+            }
+        }),
+        serde_json::json!({
+            "name": "table_quality_check",
+            "description": "Run quick data-quality checks on a table in one query: row count, approximate duplicate keys, nulls per nullable column and the range of the first date column",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "database": {
+                        "type": "string",
+                        "description": "The database name"
+                    },
+                    "table": {
+                        "type": "string",
+                        "description": "The table name, or \"@last\" for the table this session used most recently"
+                    },
+                    "key_columns": {
+                        "type": "array",
+                        "items": {"type": "string"},
+                        "description": "Columns that should uniquely identify a row; duplicates are estimated with uniq()"
+                    },
+                    "sample_ratio": {
+                        "type": "number",
+                        "description": "Fraction of rows to sample, e.g. 0.1 (ignored without a sampling key)"
+                    }
+                },
+                "required": ["database", "table"]
+            }
+        }),
+        serde_json::json!({
+            "name": "sample_rows",
+            "description": "Return the first rows of a table as JSON or CSV",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "database": {
+                        "type": "string",
+                        "description": "The database name (defaults to the configured CLICKHOUSE_DATABASE)"
+                    },
+                    "table": {
+                        "type": "string",
+                        "description": "The table name, or \"@last\" for the table this session used most recently"
+                    },
+                    "limit": {
+                        "type": "integer",
+                        "description": "Number of rows to return (default 10, max 1000)"
+                    },
+                    "format": {
+                        "type": "string",
+                        "enum": ["json", "csv"],
+                        "description": "Output format (default json); csv includes a header row"
+                    }
+                },
+                "required": ["table"]
+            }
+        }),
+        serde_json::json!({
+            "name": "explain_column_defaults",
+            "description": "List the columns of a table with a DEFAULT, MATERIALIZED, ALIAS or EPHEMERAL expression, each with the value it gives for a sampled row of the table. Expressions that call functions with side effects, contain subqueries or fail are reported as not evaluated",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "database": {
+                        "type": "string",
+                        "description": "The database name (optional, uses default if not specified)"
+                    },
+                    "table": {
+                        "type": "string",
+                        "description": "The table name, or \"@last\" for the table this session used most recently"
+                    }
+                },
+                "required": ["table"]
+            }
+        }),
+        serde_json::json!({
+            "name": "poll_new_rows",
+            "description": format!("Follow a log-like table: the rows whose Date/DateTime column is later than `since`, oldest first, at most {} per call. Pass the returned max_timestamp as `since` on the next call to get only rows added since", MAX_POLL_ROWS),
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "database": {
+                        "type": "string",
+                        "description": "The database name (optional, uses default if not specified)"
+                    },
+                    "table": {
+                        "type": "string",
+                        "description": "The table name, or \"@last\" for the table this session used most recently"
+                    },
+                    "time_column": {
+                        "type": "string",
+                        "description": "The Date or DateTime column rows are ordered by"
+                    },
+                    "since": {
+                        "type": "string",
+                        "description": "Only return rows later than this, e.g. '2024-01-31 12:00:00' or the max_timestamp of the previous call"
+                    },
+                    "limit": {
+                        "type": "integer",
+                        "description": "Maximum number of rows to return (default: 100)",
+                        "default": 100
+                    }
+                },
+                "required": ["table", "time_column", "since"]
+            }
+        }),
+        serde_json::json!({
+            "name": "row_counts_over_time",
+            "description": "Count rows per hour, day, week or month of a Date/DateTime column, with empty periods shown as zero",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "database": {
+                        "type": "string",
+                        "description": "The database name"
+                    },
+                    "table": {
+                        "type": "string",
+                        "description": "The table name, or \"@last\" for the table this session used most recently"
+                    },
+                    "time_column": {
+                        "type": "string",
+                        "description": "The Date or DateTime column to bucket rows by"
+                    },
+                    "interval": {
+                        "type": "string",
+                        "enum": ["hour", "day", "week", "month"],
+                        "description": "Bucket width"
+                    },
+                    "last_n": {
+                        "type": "integer",
+                        "description": "Only count the last N periods up to now"
+                    }
+                },
+                "required": ["database", "table", "time_column", "interval"]
+            }
+        }),
+        serde_json::json!({
+            "name": "server_metrics",
+            "description": "Show a snapshot of the ClickHouse server's memory usage, running queries, CPU usage and load average",
+            "inputSchema": {
+                "type": "object",
+                "properties": {}
+            }
+        }),
+        serde_json::json!({
+            "name": "catalog_version",
+            "description": "Get an opaque token identifying the current set of tables and their metadata modification times; it changes whenever a table is created, altered or dropped",
+            "inputSchema": {
+                "type": "object",
+                "properties": {}
+            }
+        }),
+        serde_json::json!({
+            "name": "export_catalog",
+            "description": "Export all tables of the listed databases (without the system databases unless the server shows them) with their engines and columns, or with since_version only the tables created or altered since that version plus the dropped ones. The result includes the new version",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "since_version": {
+                        "type": "string",
+                        "description": "A version from catalog_version or an earlier export_catalog"
+                    }
+                }
+            }
+        }),
+        serde_json::json!({
+            "name": "server_time",
+            "description": "Show the ClickHouse server's current time (ISO-8601) and time zone, which date and time functions use by default; check it before writing date filters",
+            "inputSchema": {
+                "type": "object",
+                "properties": {}
+            }
+        }),
+        serde_json::json!({
+            "name": "recommend_ordering",
+            "description": "Suggest ORDER BY and PARTITION BY clauses for a MergeTree-family table from the cardinality of its columns, with the rationale for each choice",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "database": {
+                        "type": "string",
+                        "description": "The database name"
+                    },
+                    "table": {
+                        "type": "string",
+                        "description": "The table name, or \"@last\" for the table this session used most recently"
+                    }
+                },
+                "required": ["database", "table"]
+            }
+        }),
+        serde_json::json!({
+            "name": "insert_pressure",
+            "description": "Check whether inserts are being delayed or rejected: parts per partition against parts_to_delay_insert/parts_to_throw_insert, delayed/rejected insert counters and merge pool usage, with a verdict",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "database": {
+                        "type": "string",
+                        "description": "Limit to this database (default: all databases)"
+                    },
+                    "table": {
+                        "type": "string",
+                        "description": "Limit to this table; requires database"
+                    }
+                }
+            }
+        }),
+        serde_json::json!({
+            "name": "server_stats",
+            "description": "Show this MCP server's own statistics: requests by method, tool calls by outcome, ClickHouse query latency, retries and connection state",
+            "inputSchema": {
+                "type": "object",
+                "properties": {}
+            }
+        }),
+        serde_json::json!({
+            "name": "recent_queries",
+            "description": "Show the latest finished or failed queries from system.query_log, newest first, with their duration, rows read and errors. With find_my_queries, only the queries this MCP session issued",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "limit": {
+                        "type": "integer",
+                        "description": "How many queries to show (default 20, at most 100)"
+                    },
+                    "find_my_queries": {
+                        "type": "boolean",
+                        "description": "Only show queries issued by this session's tool calls, found by their query_id (default false)"
+                    }
+                }
+            }
+        }),
+        serde_json::json!({
+            "name": "query_history",
+            "description": "Show the SQL this session ran through the query tools (run_script, estimate_cost, analyze_index_usage, explain_with_settings, admin_execute), newest first, with whether it succeeded and how long it took. The last 100 queries are kept",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "limit": {
+                        "type": "integer",
+                        "description": "How many of the latest queries to show (default 20, at most 100)"
+                    }
+                }
+            }
+        }),
+        serde_json::json!({
+            "name": "recent_tables",
+            "description": "List the tables this session's tool calls used, most recent first, with how often and when each was last used. Pass table \"@last\" to a table tool to mean the first one. The last 20 tables are kept in memory for this session only",
+            "inputSchema": {
+                "type": "object",
+                "properties": {}
+            }
+        }),
+        serde_json::json!({
+            "name": "clear_context",
+            "description": "Forget the tables this session used, as listed by recent_tables, so table \"@last\" refers to nothing until a table is used again",
+            "inputSchema": {
+                "type": "object",
+                "properties": {}
+            }
+        }),
+        serde_json::json!({
+            "name": "keeper_info",
+            "description": "Inspect Keeper/ZooKeeper for replication debugging: list the child nodes of a path, or summarize session expiration and exception counters",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "path": {
+                        "type": "string",
+                        "description": "Absolute Keeper path to list (default: /clickhouse)"
+                    },
+                    "summary": {
+                        "type": "boolean",
+                        "description": "Report ZooKeeper event counters instead of listing nodes"
+                    }
+                }
+            }
+        }),
+        serde_json::json!({
+            "name": "explain_with_settings",
+            "description": "Show the query plan of a read-only SELECT under different settings (e.g. optimize_read_in_order = 0) to compare optimizer behavior",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "sql": {
+                        "type": "string",
+                        "description": "A single SELECT query"
+                    },
+                    "settings": {
+                        "type": "object",
+                        "description": "Settings to apply, e.g. {\"optimize_read_in_order\": 0}. Only optimizer settings are allowed",
+                        "additionalProperties": {
+                            "type": ["string", "number", "boolean"]
+                        }
+                    }
+                },
+                "required": ["sql"]
+            }
+        }),
+        serde_json::json!({
+            "name": "estimate_cost",
+            "description": "Estimate how many rows, parts and marks a read-only SELECT would read, without running it. Use this before queries that might scan large tables",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "query": {
+                        "type": "string",
+                        "description": "The SELECT query to estimate"
+                    }
+                },
+                "required": ["query"]
+            }
+        }),
+        serde_json::json!({
+            "name": "analyze_index_usage",
+            "description": "Show whether a read-only SELECT can use the primary key and skip indexes, and how many granules they leave to read, without running it. Use this to find out why a query is slow",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "query": {
+                        "type": "string",
+                        "description": "The SELECT query to analyze"
+                    }
+                },
+                "required": ["query"]
+            }
+        }),
+        serde_json::json!({
+            "name": "check_partition_pruning",
+            "description": "Check whether a WHERE condition lets ClickHouse skip partitions of a MergeTree table, without running a query: how many of the table's parts it reads, and a verdict such as 'reads 3 of 412 parts — good' or 'full scan — the filter doesn't use the partition key'. Use this before handing a user a query over a large table",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "database": {
+                        "type": "string",
+                        "description": "The database containing the table"
+                    },
+                    "table": {
+                        "type": "string",
+                        "description": "The table the query filters"
+                    },
+                    "where": {
+                        "type": "string",
+                        "description": "The condition after WHERE, e.g. date >= '2024-01-01'. Subqueries may read tables but not call table functions such as url() or s3()"
+                    }
+                },
+                "required": ["database", "table", "where"]
+            }
+        }),
+        serde_json::json!({
+            "name": "column_lineage",
+            "description": "Show where each column of a table comes from: the materialized views writing into it and, per column, the source table and the column or expression of the view's SELECT. Expressions the parser can't trace are marked UNRESOLVED",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "database": {
+                        "type": "string",
+                        "description": "The database name"
+                    },
+                    "table": {
+                        "type": "string",
+                        "description": "The table the views write into"
+                    }
+                },
+                "required": ["database", "table"]
+            }
+        }),
+        serde_json::json!({
+            "name": "format_query",
+            "description": "Pretty-print a SQL statement using ClickHouse's own formatter; only read-only statements unless writes are enabled. The statement is not executed",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "sql": {
+                        "type": "string",
+                        "description": "A single SQL statement"
+                    }
+                },
+                "required": ["sql"]
+            }
+        }),
+        serde_json::json!({
+            "name": "describe",
+            "description": "Markdown documentation for a database or table: comments, engines, keys and columns, with a report of missing comments",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "database": {
+                        "type": "string",
+                        "description": "The database name (defaults to the configured CLICKHOUSE_DATABASE)"
+                    },
+                    "table": {
+                        "type": "string",
+                        "description": "Document only this table instead of the whole database"
+                    }
+                }
+            }
+        })
+    ];
+    for tool in &mut tools {
+        if tool["name"].as_str().is_some_and(|name| HUMANIZED_TOOLS.contains(&name)) {
+            add_raw_argument(tool);
+        }
+    }
+    tools
+}
+
+/// Adds the `raw` argument, which turns off human-readable numbers.
+fn add_raw_argument(tool: &mut Value) {
+    tool["inputSchema"]["properties"]["raw"] = serde_json::json!({
+        "type": "boolean",
+        "description": "Plain numbers instead of human-readable sizes, counts and durations, for parsing the text (default false)"
+    });
+}
+
+/// Whether `name` is a built-in tool, counting `admin_execute` even when it
+/// isn't enabled, so custom tools can't take its name.
+fn is_builtin_tool(name: &str) -> bool {
+    name == ADMIN_TOOL || tool_definitions().iter().any(|tool| tool["name"] == name)
+}
+
+/// MCP clients expect tool names matching `^[a-zA-Z0-9_-]{1,64}$`.
+fn is_valid_tool_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 64
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
@@ -0,0 +1,617 @@
+use crate::{unwrap_type_modifiers, ClickHouseError};
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+use serde_json::Value;
+
+/// Settings callers may override when running queries on their behalf.
+/// Limited to optimizer and execution knobs that cannot change data,
+/// permissions or resource limits beyond a single query.
+pub const ALLOWED_SETTINGS: &[&str] = &[
+    "allow_experimental_analyzer",
+    "distributed_product_mode",
+    "enable_optimize_predicate_expression",
+    "force_index_by_date",
+    "force_optimize_projection",
+    "force_primary_key",
+    "join_algorithm",
+    "join_use_nulls",
+    "max_block_size",
+    "max_threads",
+    "optimize_aggregation_in_order",
+    "optimize_functions_to_subcolumns",
+    "optimize_move_to_prewhere",
+    "optimize_move_to_prewhere_if_final",
+    "optimize_read_in_order",
+    "optimize_skip_unused_shards",
+    "optimize_trivial_count_query",
+    "optimize_use_implicit_projections",
+    "optimize_use_projections",
+    "prefer_localhost_replica",
+    "query_plan_enable_optimizations",
+    "query_plan_filter_push_down",
+    "use_index_for_in_with_subqueries",
+    "use_skip_indexes",
+];
+
+/// The kind of a single SQL statement, decided by its leading keyword.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StatementKind {
+    Select,
+    Show,
+    Describe,
+    Explain,
+    Exists,
+    /// Anything else, e.g. `INSERT`, `ALTER` or `DROP`, with the keyword.
+    Other(String),
+}
+
+impl StatementKind {
+    pub fn is_read_only(&self) -> bool {
+        !matches!(self, StatementKind::Other(_))
+    }
+}
+
+impl std::fmt::Display for StatementKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StatementKind::Select => write!(f, "SELECT"),
+            StatementKind::Show => write!(f, "SHOW"),
+            StatementKind::Describe => write!(f, "DESCRIBE"),
+            StatementKind::Explain => write!(f, "EXPLAIN"),
+            StatementKind::Exists => write!(f, "EXISTS"),
+            StatementKind::Other(keyword) => write!(f, "{}", keyword),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SegmentKind {
+    Code,
+    Quoted,
+    Comment,
+}
+
+/// Splits SQL into code, quoted (strings and quoted identifiers, including
+/// the quotes) and comment segments.
+fn segments(sql: &str) -> Result<Vec<(SegmentKind, &str)>, ClickHouseError> {
+    let bytes = sql.as_bytes();
+    let mut segments = Vec::new();
+    let mut start = 0;
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let end = match bytes[i] {
+            quote @ (b'\'' | b'"' | b'`') => {
+                let mut j = i + 1;
+                loop {
+                    match bytes.get(j) {
+                        None => {
+                            return Err(ClickHouseError::InvalidArgument {
+                                message: "SQL contains an unterminated quote".to_string(),
+                            })
+                        }
+                        Some(b'\\') => j += 2,
+                        Some(&c) if c == quote => {
+                            // A doubled quote is an escaped quote
+                            if bytes.get(j + 1) == Some(&quote) {
+                                j += 2;
+                            } else {
+                                break j + 1;
+                            }
+                        }
+                        Some(_) => j += 1,
+                    }
+                }
+            }
+            b'-' if bytes.get(i + 1) == Some(&b'-') => {
+                sql[i..].find('\n').map(|n| i + n + 1).unwrap_or(bytes.len())
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'*') => match sql[i + 2..].find("*/") {
+                Some(n) => i + 2 + n + 2,
+                None => {
+                    return Err(ClickHouseError::InvalidArgument {
+                        message: "SQL contains an unterminated comment".to_string(),
+                    })
+                }
+            },
+            _ => {
+                i += 1;
+                continue;
+            }
+        };
+
+        if start < i {
+            segments.push((SegmentKind::Code, &sql[start..i]));
+        }
+        let kind = if bytes[i] == b'-' || bytes[i] == b'/' {
+            SegmentKind::Comment
+        } else {
+            SegmentKind::Quoted
+        };
+        segments.push((kind, &sql[i..end]));
+        start = end;
+        i = end;
+    }
+
+    if start < bytes.len() {
+        segments.push((SegmentKind::Code, &sql[start..]));
+    }
+    Ok(segments)
+}
+
+/// Classifies a single statement. A trailing `;` is allowed, but several
+/// statements are rejected.
+pub fn classify_statement(sql: &str) -> Result<StatementKind, ClickHouseError> {
+    let code: String = segments(sql)?
+        .into_iter()
+        .map(|(kind, text)| match kind {
+            SegmentKind::Code => text,
+            SegmentKind::Quoted => "''",
+            SegmentKind::Comment => " ",
+        })
+        .collect();
+
+    let code = code.trim().trim_end_matches(';').trim_end();
+    if code.contains(';') {
+        return Err(ClickHouseError::InvalidArgument {
+            message: "Only a single SQL statement is allowed".to_string(),
+        });
+    }
+
+    let keyword: String = code
+        .trim_start_matches(|c: char| c == '(' || c.is_whitespace())
+        .chars()
+        .take_while(|c| c.is_ascii_alphabetic())
+        .collect::<String>()
+        .to_ascii_uppercase();
+
+    Ok(match keyword.as_str() {
+        "" => {
+            return Err(ClickHouseError::InvalidArgument {
+                message: "SQL statement is empty".to_string(),
+            })
+        }
+        "SELECT" | "WITH" => StatementKind::Select,
+        "SHOW" => StatementKind::Show,
+        "DESCRIBE" | "DESC" => StatementKind::Describe,
+        "EXPLAIN" => StatementKind::Explain,
+        "EXISTS" => StatementKind::Exists,
+        _ => StatementKind::Other(keyword),
+    })
+}
+
+/// Checks that `sql` is a single `SELECT` (or `WITH ... SELECT`) and
+/// returns it ready to be embedded in a larger query: trailing `;` removed
+/// and `?` characters escaped so the client does not treat them as bind
+/// placeholders.
+pub fn prepare_select(sql: &str) -> Result<String, ClickHouseError> {
+    match prepare_statement(sql)? {
+        (StatementKind::Select, prepared) => Ok(prepared),
+        (kind, _) => Err(ClickHouseError::InvalidArgument {
+            message: format!("Only read-only SELECT queries are allowed, got {}", kind),
+        }),
+    }
+}
+
+/// Classifies a single statement of any kind and returns it ready to be
+/// sent as is, prepared like [`prepare_select`] does.
+pub fn prepare_statement(sql: &str) -> Result<(StatementKind, String), ClickHouseError> {
+    let kind = classify_statement(sql)?;
+
+    let mut prepared = String::with_capacity(sql.len());
+    for (kind, text) in segments(sql)? {
+        match kind {
+            SegmentKind::Code if text.contains('?') => {
+                return Err(ClickHouseError::InvalidArgument {
+                    message: "The '?' operator is not supported, use if() instead".to_string(),
+                })
+            }
+            SegmentKind::Quoted => prepared.push_str(&text.replace('?', "\\x3F")),
+            SegmentKind::Code => prepared.push_str(text),
+            // Line comments would swallow anything appended after the query
+            SegmentKind::Comment => prepared.push(' '),
+        }
+    }
+
+    Ok((kind, prepared.trim().trim_end_matches(';').trim_end().to_string()))
+}
+
+/// A word, quoted identifier or punctuation of a statement, as read by
+/// [`statement_table`].
+#[derive(Debug, PartialEq)]
+enum Token {
+    Word(String),
+    Identifier(String),
+    Dot,
+    Other,
+}
+
+fn tokens(sql: &str) -> Result<Vec<Token>, ClickHouseError> {
+    let mut tokens = Vec::new();
+    for (kind, text) in segments(sql)? {
+        match kind {
+            SegmentKind::Code => {
+                let mut rest = text;
+                while let Some(c) = rest.chars().next() {
+                    if c.is_whitespace() {
+                        rest = &rest[c.len_utf8()..];
+                    } else if c.is_alphanumeric() || c == '_' {
+                        let end = rest.find(|c: char| !c.is_alphanumeric() && c != '_').unwrap_or(rest.len());
+                        tokens.push(Token::Word(rest[..end].to_string()));
+                        rest = &rest[end..];
+                    } else {
+                        tokens.push(if c == '.' { Token::Dot } else { Token::Other });
+                        rest = &rest[c.len_utf8()..];
+                    }
+                }
+            }
+            SegmentKind::Quoted if !text.starts_with('\'') => {
+                let quote = &text[..1];
+                let name = text[1..text.len() - 1].replace(&quote.repeat(2), quote).replace(&format!("\\{}", quote), quote);
+                tokens.push(Token::Identifier(name));
+            }
+            SegmentKind::Quoted => tokens.push(Token::Other),
+            SegmentKind::Comment => {}
+        }
+    }
+    Ok(tokens)
+}
+
+/// Leading keywords of statements that act on the one table named right
+/// after them. Optional words, e.g. `IF EXISTS`, are skipped.
+const SINGLE_TABLE_STATEMENTS: &[&[&str]] = &[
+    &["ALTER", "TABLE"],
+    &["CREATE", "TABLE"],
+    &["CREATE", "TEMPORARY", "TABLE"],
+    &["CREATE", "OR", "REPLACE", "TABLE"],
+    &["REPLACE", "TABLE"],
+    &["DROP", "TABLE"],
+    &["TRUNCATE", "TABLE"],
+    &["TRUNCATE"],
+    &["INSERT", "INTO", "TABLE"],
+    &["INSERT", "INTO"],
+    &["OPTIMIZE", "TABLE"],
+];
+
+/// The `(database, table)` a statement acts on, for the statements in
+/// [`SINGLE_TABLE_STATEMENTS`], with `None` for the database when it isn't
+/// named. `None` for other statements, e.g. `RENAME TABLE` or `SYSTEM`, or
+/// when the statement can't be read.
+pub fn statement_table(sql: &str) -> Option<(Option<String>, String)> {
+    let tokens = tokens(sql).ok()?;
+    let keyword = |index: usize| match tokens.get(index) {
+        Some(Token::Word(word)) => Some(word.to_ascii_uppercase()),
+        _ => None,
+    };
+    let matched = SINGLE_TABLE_STATEMENTS
+        .iter()
+        .find(|keywords| keywords.iter().enumerate().all(|(index, expected)| keyword(index).as_deref() == Some(*expected)))?;
+    let mut index = matched.len();
+    for optional in [&["IF", "EXISTS"][..], &["IF", "NOT", "EXISTS"]] {
+        if optional.iter().enumerate().all(|(offset, expected)| keyword(index + offset).as_deref() == Some(*expected)) {
+            index += optional.len();
+        }
+    }
+    let name = |index: usize| match tokens.get(index) {
+        Some(Token::Word(name) | Token::Identifier(name)) => Some(name.clone()),
+        _ => None,
+    };
+    if keyword(index).as_deref() == Some("FUNCTION") {
+        return None;
+    }
+    let first = name(index)?;
+    if tokens.get(index + 1) == Some(&Token::Dot) {
+        return Some((Some(first), name(index + 2)?));
+    }
+    Some((None, first))
+}
+
+/// The `confirm` argument `admin_execute` requires, verbatim.
+pub const ADMIN_CONFIRMATION: &str = "I understand this runs arbitrary SQL";
+
+/// Statements `admin_execute` refuses even with confirmation, by their
+/// leading keywords. `TRUNCATE` is allowed only for a single table.
+pub const ADMIN_DENIED_STATEMENTS: &[&str] = &["DROP DATABASE", "TRUNCATE ALL TABLES", "TRUNCATE DATABASE", "GRANT"];
+
+/// Checks the `confirm` argument of `admin_execute` against
+/// [`ADMIN_CONFIRMATION`]; nothing but an exact match passes.
+pub fn check_admin_confirmation(confirm: Option<&str>) -> Result<(), ClickHouseError> {
+    if confirm == Some(ADMIN_CONFIRMATION) {
+        return Ok(());
+    }
+    Err(ClickHouseError::InvalidArgument {
+        message: format!("admin_execute requires confirm: \"{}\", verbatim", ADMIN_CONFIRMATION),
+    })
+}
+
+/// Checks a statement for `admin_execute`: a single statement not in
+/// [`ADMIN_DENIED_STATEMENTS`], returned prepared like [`prepare_statement`]
+/// does. Keywords are matched case-insensitively, skipping comments and
+/// whitespace, so `drop /* x */ DATABASE` is refused too.
+pub fn check_admin_statement(sql: &str) -> Result<(StatementKind, String), ClickHouseError> {
+    let prepared = prepare_statement(sql)?;
+
+    let mut words = Vec::new();
+    for (kind, text) in segments(sql)? {
+        match kind {
+            SegmentKind::Code => words.extend(
+                text.split(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                    .filter(|word| !word.is_empty())
+                    .map(str::to_ascii_uppercase),
+            ),
+            SegmentKind::Quoted => words.push(String::new()),
+            SegmentKind::Comment => {}
+        }
+    }
+    for denied in ADMIN_DENIED_STATEMENTS {
+        let keywords: Vec<&str> = denied.split(' ').collect();
+        if words.len() >= keywords.len() && words.iter().zip(&keywords).all(|(word, keyword)| word == keyword) {
+            return Err(ClickHouseError::InvalidArgument {
+                message: format!("{} statements are not allowed through admin_execute", denied),
+            });
+        }
+    }
+
+    Ok(prepared)
+}
+
+/// Functions a column default preview refuses to evaluate: they wait, fail
+/// on purpose, read from outside the table or run programs. Matched
+/// case-insensitively.
+pub const UNSAFE_PREVIEW_FUNCTIONS: &[&str] = &[
+    "sleep",
+    "sleepEachRow",
+    "throwIf",
+    "file",
+    "fileCluster",
+    "url",
+    "urlCluster",
+    "s3",
+    "s3Cluster",
+    "gcs",
+    "oss",
+    "cosn",
+    "hdfs",
+    "hdfsCluster",
+    "hive",
+    "remote",
+    "remoteSecure",
+    "cluster",
+    "clusterAllReplicas",
+    "merge",
+    "mysql",
+    "postgresql",
+    "mongodb",
+    "sqlite",
+    "redis",
+    "jdbc",
+    "odbc",
+    "executable",
+    "input",
+    "generateRandom",
+    "hasColumnInTable",
+    "catboostEvaluate",
+    "modelEvaluate",
+];
+
+/// Prefixes of function families a preview refuses as a whole, such as
+/// `dictGetString` or `icebergS3Cluster`. Matched case-insensitively.
+pub const UNSAFE_PREVIEW_FUNCTION_FAMILIES: &[&str] = &[
+    "dict",
+    "joinGet",
+    "azureBlobStorage",
+    "iceberg",
+    "deltaLake",
+    "hudi",
+    "paimon",
+];
+
+/// Keywords that would make a preview expression more than an expression.
+const PREVIEW_KEYWORDS: &[&str] = &["SELECT", "FROM", "JOIN", "UNION", "SETTINGS", "FORMAT", "INTO"];
+
+/// Keywords that would end a `WHERE` expression, go on with the query or
+/// start a subquery.
+const WHERE_KEYWORDS: &[&str] = &[
+    "SELECT", "FROM", "JOIN", "WHERE", "PREWHERE", "GROUP", "HAVING", "ORDER", "LIMIT", "UNION", "EXCEPT", "INTERSECT",
+    "SETTINGS", "FORMAT", "INTO",
+];
+
+/// Checks that an expression has balanced parentheses, calls none of the
+/// [`UNSAFE_PREVIEW_FUNCTIONS`] or [`UNSAFE_PREVIEW_FUNCTION_FAMILIES`] and
+/// has none of `keywords`, outside parentheses only if `subqueries` are
+/// allowed.
+fn check_expression(expression: &str, keywords: &[&str], subqueries: bool) -> Result<(), ClickHouseError> {
+    let unsafe_expression = |reason: String| ClickHouseError::InvalidArgument {
+        message: format!("the expression {}", reason),
+    };
+
+    let mut depth = 0i32;
+    for (kind, text) in segments(expression)? {
+        if kind != SegmentKind::Code {
+            continue;
+        }
+        let is_word = |c: char| c.is_ascii_alphanumeric() || c == '_';
+        let mut rest = text;
+        while !rest.is_empty() {
+            let Some(start) = rest.find(|c: char| is_word(c) || c == '(' || c == ')') else { break };
+            let c = rest[start..].chars().next().unwrap_or_default();
+            if c == '(' || c == ')' {
+                depth += if c == '(' { 1 } else { -1 };
+                if depth < 0 {
+                    return Err(unsafe_expression("has unbalanced parentheses".to_string()));
+                }
+                rest = &rest[start + 1..];
+                continue;
+            }
+            let end = rest[start..].find(|c: char| !is_word(c)).map_or(rest.len(), |n| start + n);
+            let word = &rest[start..end];
+            rest = &rest[end..];
+            if (depth == 0 || !subqueries) && keywords.iter().any(|keyword| word.eq_ignore_ascii_case(keyword)) {
+                return Err(unsafe_expression(format!("contains {}", word.to_ascii_uppercase())));
+            }
+            // Only calls count: a column may well be named `url`
+            if !rest.trim_start().starts_with('(') {
+                continue;
+            }
+            let lower = word.to_ascii_lowercase();
+            if let Some(function) = UNSAFE_PREVIEW_FUNCTIONS.iter().find(|function| lower == function.to_ascii_lowercase()) {
+                return Err(unsafe_expression(format!("calls {}", function)));
+            }
+            if UNSAFE_PREVIEW_FUNCTION_FAMILIES.iter().any(|family| lower.starts_with(&family.to_ascii_lowercase())) {
+                return Err(unsafe_expression(format!("calls {}", word)));
+            }
+        }
+    }
+    if depth != 0 {
+        return Err(unsafe_expression("has unbalanced parentheses".to_string()));
+    }
+    Ok(())
+}
+
+/// Checks a `DEFAULT`, `MATERIALIZED` or `ALIAS` expression from
+/// `system.columns` before a preview evaluates it inside a `SELECT`: a
+/// single expression with balanced parentheses, no subqueries and no calls
+/// of [`UNSAFE_PREVIEW_FUNCTIONS`] or [`UNSAFE_PREVIEW_FUNCTION_FAMILIES`].
+/// Returns it ready to embed, prepared like [`prepare_select`] does.
+pub fn check_preview_expression(expression: &str) -> Result<String, ClickHouseError> {
+    check_expression(expression, PREVIEW_KEYWORDS, false)?;
+
+    let prepared = prepare_select(&format!("SELECT {}", expression))?;
+    Ok(prepared["SELECT ".len()..].trim().to_string())
+}
+
+/// Checks a condition to embed after `WHERE`, such as the filter whose
+/// partition pruning is checked: a single expression that doesn't continue
+/// the query past the `WHERE` clause. Subqueries are refused, since
+/// `EXPLAIN` runs scalar subqueries and builds `IN` sets, and so are calls
+/// of [`UNSAFE_PREVIEW_FUNCTIONS`] and [`UNSAFE_PREVIEW_FUNCTION_FAMILIES`],
+/// which wait, fail or reach outside the server.
+/// Returns it ready to embed, prepared like [`prepare_select`] does.
+pub fn check_where_expression(expression: &str) -> Result<String, ClickHouseError> {
+    if expression.trim().is_empty() {
+        return Err(ClickHouseError::InvalidArgument {
+            message: "the WHERE expression is empty".to_string(),
+        });
+    }
+    check_expression(expression, WHERE_KEYWORDS, false)?;
+
+    let prepared = prepare_select(&format!("SELECT 1 WHERE {}", expression))?;
+    Ok(prepared["SELECT 1 WHERE ".len()..].trim().to_string())
+}
+
+/// Checks setting names against [`ALLOWED_SETTINGS`] and that values are
+/// plain numbers or words.
+pub fn validate_settings(settings: &[(String, String)]) -> Result<(), ClickHouseError> {
+    for (name, value) in settings {
+        if !ALLOWED_SETTINGS.contains(&name.as_str()) {
+            return Err(ClickHouseError::InvalidArgument {
+                message: format!("Setting '{}' is not allowed; allowed settings are: {}", name, ALLOWED_SETTINGS.join(", ")),
+            });
+        }
+
+        let valid_value = !value.is_empty()
+            && value.len() <= 64
+            && value.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == '-');
+        if !valid_value {
+            return Err(ClickHouseError::InvalidArgument {
+                message: format!("Invalid value '{}' for setting '{}'", value, name),
+            });
+        }
+    }
+
+    Ok(())
+}
+
+/// A JSON value converted for comparison with a column: the SQL expression
+/// to compare against, with a single `?` placeholder, and the value to bind.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BoundValue {
+    pub placeholder: &'static str,
+    pub value: String,
+}
+
+impl BoundValue {
+    fn plain(value: String) -> Self {
+        Self { placeholder: "?", value }
+    }
+}
+
+/// A point in time given by the caller, either absolute (an epoch or an
+/// ISO-8601 string with an offset) or a wall-clock time without a zone.
+enum Timestamp {
+    Absolute(DateTime<Utc>),
+    Local(NaiveDateTime),
+}
+
+/// Converts `value` to the form ClickHouse expects when binding it against
+/// a column of type `column_type`.
+///
+/// `Date`, `Date32`, `DateTime` and `DateTime64` columns accept ISO-8601
+/// strings (`2024-01-01T00:00:00Z`, `2024-01-01 12:30:00`, `2024-01-01`);
+/// `DateTime` and `DateTime64` columns also take Unix epochs in seconds, as
+/// numbers or numeric strings. Dates don't, so `20240101` isn't read as
+/// seconds. Absolute times are compared as instants in UTC; times without
+/// an offset are left to ClickHouse to read in the column's time zone.
+/// Other columns take strings and numbers as they are.
+pub fn coerce_bound_value(column_type: &str, value: &Value) -> Result<BoundValue, ClickHouseError> {
+    let base = unwrap_type_modifiers(column_type);
+    let is_date = base == "Date" || base == "Date32";
+    if !is_date && !base.starts_with("DateTime") {
+        return match value {
+            Value::String(value) => Ok(BoundValue::plain(value.clone())),
+            Value::Number(value) => Ok(BoundValue::plain(value.to_string())),
+            other => Err(ClickHouseError::InvalidArgument {
+                message: format!("Expected a string or number for a {} column, got {}", column_type, other),
+            }),
+        };
+    }
+
+    let timestamp = parse_timestamp(value, !is_date).ok_or_else(|| ClickHouseError::InvalidArgument {
+        message: if is_date {
+            format!("Cannot read {} as a {} value; use an ISO-8601 date (e.g. 2024-01-01)", value, column_type)
+        } else {
+            format!(
+                "Cannot read {} as a {} value; use ISO-8601 (e.g. 2024-01-01T00:00:00Z) or a Unix epoch in seconds",
+                value, column_type
+            )
+        },
+    })?;
+    Ok(match (is_date, timestamp) {
+        (true, Timestamp::Absolute(time)) => BoundValue::plain(time.format("%Y-%m-%d").to_string()),
+        (true, Timestamp::Local(time)) => BoundValue::plain(time.format("%Y-%m-%d").to_string()),
+        (false, Timestamp::Absolute(time)) => BoundValue {
+            placeholder: "toDateTime64(?, 9, 'UTC')",
+            value: time.format("%Y-%m-%d %H:%M:%S%.9f").to_string(),
+        },
+        (false, Timestamp::Local(time)) => BoundValue::plain(time.format("%Y-%m-%d %H:%M:%S%.f").to_string()),
+    })
+}
+
+/// Reads an ISO-8601 time, or with `allow_epoch` a Unix epoch in seconds.
+fn parse_timestamp(value: &Value, allow_epoch: bool) -> Option<Timestamp> {
+    let text = match value {
+        Value::Number(number) if allow_epoch => return from_epoch(number.as_f64()?),
+        Value::String(text) => text.trim(),
+        _ => return None,
+    };
+
+    if !text.is_empty() && text.chars().all(|c| c.is_ascii_digit() || c == '.') {
+        return if allow_epoch { from_epoch(text.parse().ok()?) } else { None };
+    }
+    if let Ok(time) = DateTime::parse_from_rfc3339(text) {
+        return Some(Timestamp::Absolute(time.with_timezone(&Utc)));
+    }
+    ["%Y-%m-%dT%H:%M:%S%.f", "%Y-%m-%d %H:%M:%S%.f"]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(text, format).ok())
+        .or_else(|| NaiveDate::parse_from_str(text, "%Y-%m-%d").ok().and_then(|date| date.and_hms_opt(0, 0, 0)))
+        .map(Timestamp::Local)
+}
+
+fn from_epoch(seconds: f64) -> Option<Timestamp> {
+    if !seconds.is_finite() || seconds < 0.0 {
+        return None;
+    }
+    let whole = seconds.trunc();
+    let nanos = ((seconds - whole) * 1e9).round().min(999_999_999.0) as u32;
+    DateTime::from_timestamp(whole as i64, nanos).map(Timestamp::Absolute)
+}
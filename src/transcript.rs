@@ -0,0 +1,381 @@
+//! Session transcripts: `--record-session` writes every message a session
+//! reads and writes to a JSONL file, and `mcp-test replay` feeds the
+//! inbound ones to a server again and compares what it answers with what
+//! was recorded, to reproduce what a client saw.
+//!
+//! Inbound lines are kept byte for byte as read, `\r`, BOM and invalid
+//! UTF-8 included, so framing problems replay too. Lines over the length
+//! limit were never buffered and only their length is kept.
+
+use crate::server::McpServer;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tokio::io::{AsyncBufReadExt, BufReader as AsyncBufReader};
+
+/// What recorded secrets are replaced with.
+pub const REDACTED: &str = "***";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    /// Read from the client.
+    In,
+    /// Written to the client.
+    Out,
+}
+
+/// One line of a transcript.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TranscriptEntry {
+    /// When the message was read or written, as RFC 3339 UTC.
+    pub at: String,
+    pub direction: Direction,
+    /// The message with its line terminator, when it is UTF-8.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub raw: Option<String>,
+    /// The bytes of an inbound line that is not UTF-8, in hex.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub raw_hex: Option<String>,
+    /// The length of an inbound line over the line limit, whose bytes were
+    /// discarded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub discarded_length: Option<usize>,
+    /// Whether secrets were replaced with [`REDACTED`], so `raw` is not
+    /// what was sent.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub redacted: bool,
+}
+
+impl TranscriptEntry {
+    fn new(direction: Direction) -> Self {
+        Self {
+            at: chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+            direction,
+            raw: None,
+            raw_hex: None,
+            discarded_length: None,
+            redacted: false,
+        }
+    }
+
+    /// The bytes to feed a server to replay this entry. A discarded line is
+    /// replaced by as many filler bytes, which the server discards the same
+    /// way.
+    pub fn bytes(&self) -> Vec<u8> {
+        if let Some(raw) = &self.raw {
+            return raw.as_bytes().to_vec();
+        }
+        if let Some(hex) = &self.raw_hex {
+            return (0..hex.len() / 2).filter_map(|i| u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).ok()).collect();
+        }
+        let mut filler = vec![b' '; self.discarded_length.unwrap_or_default()];
+        filler.push(b'\n');
+        filler
+    }
+}
+
+/// Appends the messages of one session to `<dir>/session-<id>.jsonl`,
+/// replacing the secrets it was given. Write errors are logged once and
+/// recording stops; the session goes on.
+#[derive(Debug)]
+pub struct SessionRecorder {
+    path: PathBuf,
+    secrets: Vec<String>,
+    file: Mutex<Option<File>>,
+}
+
+impl SessionRecorder {
+    /// Creates `dir` if needed and the transcript file of `session_id` in
+    /// it. Non-empty `secrets`, such as the ClickHouse password, are
+    /// replaced with [`REDACTED`] wherever they appear, as is or escaped
+    /// inside a JSON string.
+    pub fn create(dir: &Path, session_id: &str, secrets: Vec<String>) -> Result<Self> {
+        std::fs::create_dir_all(dir)
+            .map_err(|e| anyhow::anyhow!("Cannot create session directory {}: {}", dir.display(), e))?;
+        let path = dir.join(format!("session-{}.jsonl", session_id));
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .map_err(|e| anyhow::anyhow!("Cannot create session transcript {}: {}", path.display(), e))?;
+        Ok(Self {
+            path,
+            secrets: secrets.into_iter().filter(|secret| !secret.is_empty()).flat_map(|secret| [json_escaped(&secret), secret]).collect(),
+            file: Mutex::new(Some(file)),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Records a line read from the client, as read.
+    pub fn record_inbound(&self, raw: &[u8]) {
+        let mut entry = TranscriptEntry::new(Direction::In);
+        match std::str::from_utf8(raw) {
+            Ok(raw) => self.set_raw(&mut entry, raw),
+            Err(_) => {
+                entry.raw_hex = Some(raw.iter().map(|byte| format!("{:02x}", byte)).collect());
+            }
+        }
+        self.write(&entry);
+    }
+
+    /// Records that a line of `length` bytes was discarded as too long.
+    pub fn record_discarded(&self, length: usize) {
+        let mut entry = TranscriptEntry::new(Direction::In);
+        entry.discarded_length = Some(length);
+        self.write(&entry);
+    }
+
+    /// Records a message written to the client, without its terminator.
+    pub fn record_outbound(&self, message: &str) {
+        let mut entry = TranscriptEntry::new(Direction::Out);
+        self.set_raw(&mut entry, &format!("{}\n", message));
+        self.write(&entry);
+    }
+
+    fn set_raw(&self, entry: &mut TranscriptEntry, raw: &str) {
+        let redacted = self.secrets.iter().fold(raw.to_string(), |text, secret| text.replace(secret.as_str(), REDACTED));
+        entry.redacted = redacted != raw;
+        entry.raw = Some(redacted);
+    }
+
+    fn write(&self, entry: &TranscriptEntry) {
+        let mut file = self.file.lock().unwrap();
+        let Some(writer) = file.as_mut() else { return };
+        let line = serde_json::to_string(entry).unwrap_or_default();
+        if let Err(e) = writeln!(writer, "{}", line) {
+            log::warn!("Stopped recording the session to {}: {}", self.path.display(), e);
+            *file = None;
+        }
+    }
+}
+
+/// `text` as it appears inside a JSON string, e.g. `a\"b` for `a"b`.
+fn json_escaped(text: &str) -> String {
+    let quoted = serde_json::to_string(text).unwrap_or_default();
+    quoted[1..quoted.len() - 1].to_string()
+}
+
+/// Reads a transcript written by [`SessionRecorder`].
+pub fn read_transcript(path: &Path) -> Result<Vec<TranscriptEntry>> {
+    let file = File::open(path).map_err(|e| anyhow::anyhow!("Cannot open transcript {}: {}", path.display(), e))?;
+    let mut entries = Vec::new();
+    for (index, line) in BufReader::new(file).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let entry = serde_json::from_str(&line)
+            .map_err(|e| anyhow::anyhow!("{} line {}: not a transcript entry: {}", path.display(), index + 1, e))?;
+        entries.push(entry);
+    }
+    Ok(entries)
+}
+
+/// An outbound message that replayed differently.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ReplayMismatch {
+    /// Which message: `id <id>` for responses, `message <n> without id`
+    /// for the others in order.
+    pub message: String,
+    /// Where the two first differ, as a JSON pointer, when both are JSON.
+    pub path: Option<String>,
+    pub recorded: Option<String>,
+    pub replayed: Option<String>,
+}
+
+/// The result of replaying a transcript.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ReplayReport {
+    /// How many inbound messages were fed to the server.
+    pub inbound: usize,
+    /// How many recorded responses were compared.
+    pub compared: usize,
+    pub mismatches: Vec<ReplayMismatch>,
+}
+
+impl ReplayReport {
+    pub fn passed(&self) -> bool {
+        self.mismatches.is_empty()
+    }
+}
+
+impl fmt::Display for ReplayReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Replayed {} inbound messages, compared {} responses", self.inbound, self.compared)?;
+        for mismatch in &self.mismatches {
+            match &mismatch.path {
+                Some(path) => writeln!(f, "- {}: differs at {}", mismatch.message, path)?,
+                None => writeln!(f, "- {}: differs", mismatch.message)?,
+            }
+            writeln!(f, "  recorded: {}", mismatch.recorded.as_deref().unwrap_or("(nothing)"))?;
+            writeln!(f, "  replayed: {}", mismatch.replayed.as_deref().unwrap_or("(nothing)"))?;
+        }
+        if self.passed() {
+            writeln!(f, "All responses match")
+        } else {
+            writeln!(f, "{} response(s) differ", self.mismatches.len())
+        }
+    }
+}
+
+/// Whether `url` points at a ClickHouse on this machine, such as a mock or
+/// a test container, which `replay` may run against without
+/// `--against-live`.
+pub fn is_local_backend(url: &str) -> bool {
+    let authority = url.split_once("://").map_or(url, |(_, rest)| rest);
+    let authority = authority.split(['/', '?']).next().unwrap_or_default();
+    let host_port = authority.rsplit_once('@').map_or(authority, |(_, host)| host);
+    let host = match host_port.strip_prefix('[') {
+        Some(bracketed) => bracketed.split(']').next().unwrap_or_default(),
+        None => host_port.split(':').next().unwrap_or_default(),
+    };
+    host.eq_ignore_ascii_case("localhost") || host.parse::<std::net::IpAddr>().is_ok_and(|ip| ip.is_loopback())
+}
+
+/// Feeds the inbound messages of `entries` to `server` in one go and
+/// compares its output with the recorded outbound messages, see
+/// [`diff_outputs`]. Timing is not replayed, so a cancellation that raced
+/// a call may land differently.
+///
+/// The recorded calls run against whatever ClickHouse `server` talks to,
+/// so writes and `admin_execute` are turned off whatever `server` was built
+/// with: a replayed `run_script` or `admin_execute` fails instead of
+/// changing data again, and shows up as a difference.
+pub async fn replay(server: McpServer, entries: &[TranscriptEntry]) -> Result<ReplayReport> {
+    let mut server = server.with_writes_enabled(false).with_admin_enabled(false);
+    let inbound: Vec<&TranscriptEntry> = entries.iter().filter(|entry| entry.direction == Direction::In).collect();
+    let input: Vec<u8> = inbound.iter().flat_map(|entry| entry.bytes()).collect();
+    let recorded: Vec<String> = entries
+        .iter()
+        .filter(|entry| entry.direction == Direction::Out)
+        .filter_map(|entry| entry.raw.as_deref().map(|raw| raw.trim_end_matches('\n').to_string()))
+        .collect();
+
+    let (server_output, output) = tokio::io::duplex(64 * 1024);
+    let collector = tokio::spawn(async move {
+        let mut lines = AsyncBufReader::new(output).lines();
+        let mut replayed = Vec::new();
+        while let Some(line) = lines.next_line().await? {
+            replayed.push(line);
+        }
+        Ok::<_, std::io::Error>(replayed)
+    });
+    server.serve(AsyncBufReader::new(input.as_slice()), server_output).await?;
+    let replayed = collector.await??;
+
+    let mismatches = diff_outputs(&recorded, &replayed);
+    Ok(ReplayReport {
+        inbound: inbound.len(),
+        compared: recorded.iter().filter(|message| !is_server_initiated(message)).count(),
+        mismatches,
+    })
+}
+
+/// Compares recorded and replayed outbound messages. Responses are paired
+/// by `id`, since tool calls may finish in any order; other messages, such
+/// as parse errors without an id, are paired in order. Requests and
+/// notifications the server sent on its own, like connection state changes
+/// and keepalive pings, depend on timing and are left out. Messages are
+/// compared as JSON when both parse, so formatting doesn't matter.
+pub fn diff_outputs(recorded: &[String], replayed: &[String]) -> Vec<ReplayMismatch> {
+    let (recorded_by_id, recorded_rest) = pair_keys(recorded);
+    let (mut replayed_by_id, replayed_rest) = pair_keys(replayed);
+
+    let mut mismatches = Vec::new();
+    for (id, recorded) in recorded_by_id {
+        let replayed = replayed_by_id.entry(id.clone()).or_default();
+        for recorded in recorded {
+            compare(&mut mismatches, format!("id {}", id), Some(recorded), replayed.pop_front());
+        }
+    }
+    for (id, replayed) in replayed_by_id {
+        for replayed in replayed {
+            compare(&mut mismatches, format!("id {}", id), None, Some(replayed));
+        }
+    }
+    for index in 0..recorded_rest.len().max(replayed_rest.len()) {
+        compare(
+            &mut mismatches,
+            format!("message {} without id", index + 1),
+            recorded_rest.get(index).cloned(),
+            replayed_rest.get(index).cloned(),
+        );
+    }
+    mismatches
+}
+
+/// Splits messages into responses by id and the rest, leaving out what
+/// [`is_server_initiated`].
+fn pair_keys(messages: &[String]) -> (BTreeMap<String, VecDeque<String>>, Vec<String>) {
+    let mut by_id: BTreeMap<String, VecDeque<String>> = BTreeMap::new();
+    let mut rest = Vec::new();
+    for message in messages.iter().filter(|message| !is_server_initiated(message)) {
+        let id = serde_json::from_str::<Value>(message)
+            .ok()
+            .and_then(|value| value.get("id").filter(|id| !id.is_null()).map(Value::to_string));
+        match id {
+            Some(id) => by_id.entry(id).or_default().push_back(message.clone()),
+            None => rest.push(message.clone()),
+        }
+    }
+    (by_id, rest)
+}
+
+fn is_server_initiated(message: &str) -> bool {
+    serde_json::from_str::<Value>(message).is_ok_and(|value| value.get("method").is_some())
+}
+
+fn compare(mismatches: &mut Vec<ReplayMismatch>, message: String, recorded: Option<String>, replayed: Option<String>) {
+    let path = match (&recorded, &replayed) {
+        (Some(recorded), Some(replayed)) => {
+            match (serde_json::from_str::<Value>(recorded), serde_json::from_str::<Value>(replayed)) {
+                (Ok(recorded), Ok(replayed)) => match first_difference(&recorded, &replayed, "") {
+                    Some(path) => Some(if path.is_empty() { "/".to_string() } else { path }),
+                    None => return,
+                },
+                _ if recorded == replayed => return,
+                _ => None,
+            }
+        }
+        _ => None,
+    };
+    mismatches.push(ReplayMismatch { message, path, recorded, replayed });
+}
+
+/// The JSON pointer of the first place `a` and `b` differ, `""` for the
+/// values themselves, or `None` if they are equal.
+pub fn first_difference(a: &Value, b: &Value, path: &str) -> Option<String> {
+    match (a, b) {
+        (Value::Object(a), Value::Object(b)) => {
+            let keys: BTreeSet<&String> = a.keys().chain(b.keys()).collect();
+            keys.into_iter().find_map(|key| {
+                let child = format!("{}/{}", path, key.replace('~', "~0").replace('/', "~1"));
+                match (a.get(key), b.get(key)) {
+                    (Some(a), Some(b)) => first_difference(a, b, &child),
+                    _ => Some(child),
+                }
+            })
+        }
+        (Value::Array(a), Value::Array(b)) => {
+            (0..a.len().max(b.len())).find_map(|index| {
+                let child = format!("{}/{}", path, index);
+                match (a.get(index), b.get(index)) {
+                    (Some(a), Some(b)) => first_difference(a, b, &child),
+                    _ => Some(child),
+                }
+            })
+        }
+        (a, b) if a == b => None,
+        _ => Some(path.to_string()),
+    }
+}
@@ -0,0 +1,129 @@
+//! How queries reach ClickHouse: the HTTP driver by default, or gRPC with the
+//! `grpc` feature. [`ClickHouseClient`](crate::ClickHouseClient) builds every
+//! query through [`Transport::query`], and retries, timeouts and error
+//! mapping sit above it, so they apply to both.
+
+use clickhouse::error::Error;
+use clickhouse::sql::Bind;
+use clickhouse::{Client, Compression, Row};
+use serde::de::DeserializeOwned;
+use std::future::Future;
+use std::pin::Pin;
+
+#[cfg(feature = "grpc")]
+use crate::grpc::{GrpcQuery, GrpcTransport};
+
+#[derive(Clone)]
+pub(crate) enum Transport {
+    Http(Client),
+    #[cfg(feature = "grpc")]
+    Grpc(GrpcTransport),
+}
+
+impl Transport {
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            Self::Http(_) => "http",
+            #[cfg(feature = "grpc")]
+            Self::Grpc(_) => "grpc",
+        }
+    }
+
+    pub(crate) fn query(&self, sql: &str) -> Query {
+        match self {
+            Self::Http(client) => Query::Http(client.query(sql)),
+            #[cfg(feature = "grpc")]
+            Self::Grpc(transport) => Query::Grpc(transport.query(sql)),
+        }
+    }
+
+    pub(crate) fn with_database(self, database: &str) -> Self {
+        match self {
+            Self::Http(client) => Self::Http(client.with_database(database)),
+            #[cfg(feature = "grpc")]
+            Self::Grpc(transport) => Self::Grpc(transport.with_database(database)),
+        }
+    }
+
+    /// Sets the `User-Agent` sent with every request.
+    pub(crate) fn with_user_agent(self, user_agent: &str) -> Self {
+        match self {
+            Self::Http(client) => Self::Http(client.with_header("User-Agent", user_agent)),
+            #[cfg(feature = "grpc")]
+            Self::Grpc(transport) => Self::Grpc(transport.with_user_agent(user_agent)),
+        }
+    }
+
+    /// Only HTTP transfers are compressed; gRPC ones are sent as they are.
+    pub(crate) fn with_compression(self, compression: Compression) -> Self {
+        match self {
+            Self::Http(client) => Self::Http(client.with_compression(compression)),
+            #[cfg(feature = "grpc")]
+            grpc => grpc,
+        }
+    }
+}
+
+/// The result of running a [`Query`]. Boxed, so the futures of the
+/// client's methods don't grow by a query future each, which deep call
+/// chains would feel in stack size.
+pub(crate) type QueryFuture<T> = Pin<Box<dyn Future<Output = Result<T, Error>> + Send>>;
+
+/// A query over either transport, with the methods of
+/// [`clickhouse::query::Query`] the client uses.
+#[derive(Clone)]
+pub(crate) enum Query {
+    Http(clickhouse::query::Query),
+    #[cfg(feature = "grpc")]
+    Grpc(GrpcQuery),
+}
+
+impl Query {
+    pub(crate) fn bind(self, value: impl Bind) -> Self {
+        match self {
+            Self::Http(query) => Self::Http(query.bind(value)),
+            #[cfg(feature = "grpc")]
+            Self::Grpc(query) => Self::Grpc(query.bind(value)),
+        }
+    }
+
+    pub(crate) fn with_option(self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        match self {
+            Self::Http(query) => Self::Http(query.with_option(name, value)),
+            #[cfg(feature = "grpc")]
+            Self::Grpc(query) => Self::Grpc(query.with_option(name, value)),
+        }
+    }
+
+    pub(crate) fn execute(self) -> QueryFuture<()> {
+        match self {
+            Self::Http(query) => Box::pin(query.execute()),
+            #[cfg(feature = "grpc")]
+            Self::Grpc(query) => Box::pin(query.execute()),
+        }
+    }
+
+    pub(crate) fn fetch_all<T: Row + DeserializeOwned + Send + 'static>(self) -> QueryFuture<Vec<T>> {
+        match self {
+            Self::Http(query) => Box::pin(query.fetch_all()),
+            #[cfg(feature = "grpc")]
+            Self::Grpc(query) => Box::pin(query.fetch_all()),
+        }
+    }
+
+    pub(crate) fn fetch_one<T: Row + DeserializeOwned + Send + 'static>(self) -> QueryFuture<T> {
+        match self {
+            Self::Http(query) => Box::pin(query.fetch_one()),
+            #[cfg(feature = "grpc")]
+            Self::Grpc(query) => Box::pin(query.fetch_one()),
+        }
+    }
+
+    pub(crate) fn fetch_optional<T: Row + DeserializeOwned + Send + 'static>(self) -> QueryFuture<Option<T>> {
+        match self {
+            Self::Http(query) => Box::pin(query.fetch_optional()),
+            #[cfg(feature = "grpc")]
+            Self::Grpc(query) => Box::pin(query.fetch_optional()),
+        }
+    }
+}
@@ -0,0 +1,89 @@
+use serde::Serialize;
+use std::fmt;
+
+/// A ClickHouse server version as reported by `version()`, e.g. `23.8.2.7`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+pub struct Version {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+    pub build: u32,
+}
+
+impl Version {
+    pub const fn new(major: u32, minor: u32) -> Self {
+        Self { major, minor, patch: 0, build: 0 }
+    }
+
+    /// Parses `major.minor[.patch[.build]]`. Anything after the numeric
+    /// components is ignored, so vendor and cloud builds such as
+    /// `23.8.16.42.altinitystable` or `24.3.1.2672-cloud` are accepted.
+    pub fn parse(version: &str) -> Option<Self> {
+        let mut numbers = Vec::with_capacity(4);
+        for component in version.trim().split('.').take(4) {
+            let digits: &str = &component[..component.find(|c: char| !c.is_ascii_digit()).unwrap_or(component.len())];
+            if digits.is_empty() {
+                break;
+            }
+            numbers.push(digits.parse().ok()?);
+            if digits.len() < component.len() {
+                break;
+            }
+        }
+
+        match numbers[..] {
+            [major, minor, ref rest @ ..] => Some(Self {
+                major,
+                minor,
+                patch: rest.first().copied().unwrap_or(0),
+                build: rest.get(1).copied().unwrap_or(0),
+            }),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}.{}", self.major, self.minor, self.patch, self.build)
+    }
+}
+
+/// Server features that only exist from some version on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Feature {
+    ExplainEstimate,
+    Backups,
+    KafkaConsumers,
+    FormatQuery,
+    SessionTimezone,
+}
+
+impl Feature {
+    /// The first release that ships the feature.
+    pub fn min_version(self) -> Version {
+        match self {
+            Feature::ExplainEstimate => Version::new(21, 9),
+            Feature::Backups => Version::new(22, 8),
+            Feature::KafkaConsumers => Version::new(23, 8),
+            Feature::FormatQuery => Version::new(23, 11),
+            Feature::SessionTimezone => Version::new(23, 6),
+        }
+    }
+
+    pub fn is_supported_by(self, version: Version) -> bool {
+        version >= self.min_version()
+    }
+}
+
+impl fmt::Display for Feature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Feature::ExplainEstimate => write!(f, "EXPLAIN ESTIMATE"),
+            Feature::Backups => write!(f, "system.backups"),
+            Feature::KafkaConsumers => write!(f, "system.kafka_consumers"),
+            Feature::FormatQuery => write!(f, "formatQuery()"),
+            Feature::SessionTimezone => write!(f, "session_timezone (--timezone)"),
+        }
+    }
+}
@@ -0,0 +1,56 @@
+//! Which databases the listing tools show. Models given `system` and
+//! `INFORMATION_SCHEMA` in a listing tend to spend turns exploring them, so
+//! listings leave the [`SYSTEM_DATABASES`] out unless asked. Tools naming a
+//! database directly are not affected: `get_table_schema` on
+//! `system.query_log` works either way.
+//!
+//! Every listing decides through [`DatabaseVisibility`], so rules about
+//! which databases to show belong here rather than in the tools.
+
+use crate::SYSTEM_DATABASES;
+
+/// The policy for which databases listings show.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DatabaseVisibility {
+    /// Whether listings leave out the [`SYSTEM_DATABASES`] unless a call
+    /// passes `include_system: true`.
+    pub hide_system: bool,
+}
+
+impl Default for DatabaseVisibility {
+    fn default() -> Self {
+        Self { hide_system: true }
+    }
+}
+
+impl DatabaseVisibility {
+    pub fn new(hide_system: bool) -> Self {
+        Self { hide_system }
+    }
+
+    /// Whether `database` holds ClickHouse's own metadata.
+    pub fn is_system(database: &str) -> bool {
+        SYSTEM_DATABASES.contains(&database)
+    }
+
+    /// Whether a listing shows the system databases, given the call's
+    /// `include_system` argument, if any.
+    pub fn shows_system(&self, include_system: Option<bool>) -> bool {
+        include_system.unwrap_or(!self.hide_system)
+    }
+
+    /// The databases a listing leaves out, given the call's `include_system`
+    /// argument, if any. Listings filtering in SQL bind this list.
+    pub fn hidden_databases(&self, include_system: Option<bool>) -> &'static [&'static str] {
+        if self.shows_system(include_system) {
+            &[]
+        } else {
+            SYSTEM_DATABASES
+        }
+    }
+
+    /// Whether a listing shows `database`.
+    pub fn shows(&self, database: &str, include_system: Option<bool>) -> bool {
+        self.shows_system(include_system) || !Self::is_system(database)
+    }
+}
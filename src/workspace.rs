@@ -0,0 +1,99 @@
+//! The tables a session worked with lately, so the model can look them up
+//! again and pass `table: "@last"` instead of repeating names. Kept in memory
+//! per [`McpServer`](crate::McpServer), never written anywhere.
+
+use crate::ClickHouseError;
+use serde::Serialize;
+use std::sync::Mutex;
+
+/// The most tables remembered per session; the least recently used one is
+/// forgotten first.
+pub const MAX_RECENT_TABLES: usize = 20;
+
+/// The `table` argument that stands for the most recently used table. The
+/// `@` is not allowed in identifiers, so no table can be named like this.
+pub const LAST_TABLE: &str = "@last";
+
+/// A table a tool call used.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RecentTable {
+    pub database: String,
+    pub table: String,
+    /// How many successful tool calls used it in this session.
+    pub access_count: u64,
+    /// When it was last used, as RFC 3339 UTC.
+    pub last_used: String,
+}
+
+/// The last [`MAX_RECENT_TABLES`] tables of a session, most recent first.
+#[derive(Debug, Default)]
+pub struct RecentTables {
+    tables: Mutex<Vec<RecentTable>>,
+}
+
+impl RecentTables {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records a use of `database.table`, moving it to the front.
+    pub fn touch(&self, database: &str, table: &str) {
+        let mut tables = self.tables.lock().unwrap();
+        let access_count = match tables.iter().position(|t| t.database == database && t.table == table) {
+            Some(i) => tables.remove(i).access_count + 1,
+            None => 1,
+        };
+        tables.insert(
+            0,
+            RecentTable {
+                database: database.to_string(),
+                table: table.to_string(),
+                access_count,
+                last_used: chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+            },
+        );
+        tables.truncate(MAX_RECENT_TABLES);
+    }
+
+    /// The remembered tables, most recently used first.
+    pub fn recent(&self) -> Vec<RecentTable> {
+        self.tables.lock().unwrap().clone()
+    }
+
+    /// The most recently used table, of `database` if given.
+    pub fn last(&self, database: Option<&str>) -> Result<RecentTable, ClickHouseError> {
+        let tables = self.tables.lock().unwrap();
+        tables
+            .iter()
+            .find(|t| database.is_none_or(|database| t.database == database))
+            .cloned()
+            .ok_or_else(|| ClickHouseError::InvalidArgument {
+                message: match database {
+                    Some(database) => format!(
+                        "table \"{}\" refers to the most recently used table of database '{}', but no table of it has been used in this session yet",
+                        LAST_TABLE, database
+                    ),
+                    None => format!(
+                        "table \"{}\" refers to the most recently used table, but no table has been used in this session yet",
+                        LAST_TABLE
+                    ),
+                },
+            })
+    }
+
+    /// Forgets all tables, returning how many there were.
+    pub fn clear(&self) -> usize {
+        let mut tables = self.tables.lock().unwrap();
+        let count = tables.len();
+        tables.clear();
+        count
+    }
+
+    pub fn len(&self) -> usize {
+        self.tables.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
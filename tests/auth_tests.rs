@@ -0,0 +1,74 @@
+mod common;
+
+use common::recording_clickhouse;
+use mcp_test::{ClickHouseClient, ClickHouseConfig, ConfigLayer, ConfigSource, DEFAULT_USER_AGENT};
+
+fn header<'a>(request: &'a str, name: &str) -> Option<&'a str> {
+    request.lines().find_map(|line| {
+        let (key, value) = line.split_once(':')?;
+        key.eq_ignore_ascii_case(name).then(|| value.trim())
+    })
+}
+
+#[tokio::test]
+async fn test_bearer_token_is_sent_instead_of_credentials() {
+    let (url, requests) = recording_clickhouse().await;
+    let client = ClickHouseClient::with_bearer_token(&url, "web", "t0ken");
+
+    client.health_check().await.unwrap();
+
+    let requests = requests.lock().unwrap();
+    assert_eq!(requests.len(), 1);
+    assert_eq!(header(&requests[0], "authorization"), Some("Bearer t0ken"));
+    assert_eq!(header(&requests[0], "x-clickhouse-user"), None);
+    assert_eq!(header(&requests[0], "x-clickhouse-key"), None);
+
+    let config = client.effective_config();
+    assert!(config.iter().all(|(_, value)| !value.contains("t0ken")));
+    assert!(config.contains(&("bearer_token", "<set>".to_string())));
+}
+
+#[tokio::test]
+async fn test_config_prefers_bearer_token_over_password() {
+    let (url, requests) = recording_clickhouse().await;
+    let env = |token: &str| {
+        ConfigLayer::from_env(ConfigSource::Environment, |name| match name {
+            "CLICKHOUSE_URL" => Some(url.clone()),
+            "CLICKHOUSE_PASSWORD" => Some("s3cret".to_string()),
+            "CLICKHOUSE_BEARER_TOKEN" => Some(token.to_string()),
+            _ => None,
+        })
+        .unwrap()
+    };
+
+    let config = ClickHouseConfig::resolve(&[env("t0ken")]).unwrap();
+    assert!(config.describe().contains(&("bearer_token", "<set>".to_string(), ConfigSource::Environment)));
+    ClickHouseClient::from_config(&config).health_check().await.unwrap();
+
+    // An empty token falls back to the username and password
+    let config = ClickHouseConfig::resolve(&[env("")]).unwrap();
+    ClickHouseClient::from_config(&config).health_check().await.unwrap();
+
+    let requests = requests.lock().unwrap();
+    assert_eq!(header(&requests[0], "authorization"), Some("Bearer t0ken"));
+    assert_eq!(header(&requests[1], "authorization"), None);
+    assert_eq!(header(&requests[1], "x-clickhouse-key"), Some("s3cret"));
+}
+
+#[tokio::test]
+async fn test_user_agent_header() {
+    let (url, requests) = recording_clickhouse().await;
+
+    ClickHouseClient::new(&url, "default", "default", "").health_check().await.unwrap();
+    let client = ClickHouseClient::with_bearer_token(&url, "web", "t0ken").with_user_agent("acme-analytics/2.1");
+    client.health_check().await.unwrap();
+
+    let requests = requests.lock().unwrap();
+    assert_eq!(header(&requests[0], "user-agent"), Some(DEFAULT_USER_AGENT));
+    assert_eq!(DEFAULT_USER_AGENT, concat!("mcp-test/", env!("CARGO_PKG_VERSION")));
+    // Replaces the default rather than adding a second header
+    assert_eq!(requests[1].lines().filter(|line| line.to_ascii_lowercase().starts_with("user-agent:")).count(), 1);
+    assert_eq!(header(&requests[1], "user-agent"), Some("acme-analytics/2.1"));
+    assert_eq!(header(&requests[1], "authorization"), Some("Bearer t0ken"));
+    assert!(client.effective_config().contains(&("user_agent", "acme-analytics/2.1".to_string())));
+}
@@ -0,0 +1,164 @@
+mod common;
+
+use clickhouse::test::{handlers, Mock};
+use common::column;
+use mcp_test::{CacheScope, CachedSchema, ClickHouseClient, ConnectionManager, JsonRpcRequest, McpServer, SchemaCache};
+use serde_json::json;
+use std::sync::Arc;
+use std::time::Duration;
+
+fn schema(database: &str, table: &str) -> CachedSchema {
+    CachedSchema::new(database, table, vec![column("id", "UInt64")])
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_schema_cache_expiry_and_stats() {
+    let cache = SchemaCache::new(Duration::from_secs(60));
+    assert_eq!(cache.stats().hit_rate(), None);
+
+    assert!(cache.get("shop", "orders").is_none());
+    cache.insert(("shop", "orders"), schema("shop", "orders"));
+    tokio::time::advance(Duration::from_secs(30)).await;
+    assert_eq!(cache.get("shop", "orders").unwrap().columns.len(), 1);
+
+    let stats = cache.stats();
+    assert_eq!((stats.entries, stats.hits, stats.misses), (1, 1, 1));
+    assert_eq!(stats.hit_rate(), Some(0.5));
+    assert_eq!(stats.oldest_entry_age, Some(Duration::from_secs(30)));
+
+    tokio::time::advance(Duration::from_secs(30)).await;
+    assert!(cache.get("shop", "orders").is_none());
+    assert_eq!(cache.stats().entries, 0);
+    // Kept around for when ClickHouse can't be asked
+    assert_eq!(cache.get_stale("shop", "orders").unwrap().columns.len(), 1);
+    assert_eq!(cache.stats().misses, 2);
+}
+
+#[test]
+fn test_schema_cache_invalidation_scopes() {
+    let cache = SchemaCache::new(Duration::from_secs(60));
+    cache.insert(("shop", "orders"), schema("shop", "orders"));
+    // Requested with other casing, resolved to the same table
+    cache.insert(("SHOP", "Orders"), schema("shop", "orders"));
+    cache.insert(("shop", "users"), schema("shop", "users"));
+    cache.insert(("web", "events"), schema("web", "events"));
+
+    let table = CacheScope::Table { database: "shop".to_string(), table: "orders".to_string() };
+    assert_eq!(cache.invalidate(&table), [("shop".to_string(), "orders".to_string())]);
+    assert_eq!(cache.stats().entries, 2);
+
+    assert_eq!(cache.invalidate(&CacheScope::Database("shop".to_string())), [("shop".to_string(), "users".to_string())]);
+    assert_eq!(cache.invalidate(&CacheScope::All), [("web".to_string(), "events".to_string())]);
+    assert_eq!(cache.stats().invalidations, 3);
+}
+
+fn call(name: &str, arguments: serde_json::Value, id: u64) -> JsonRpcRequest {
+    serde_json::from_value(json!({
+        "jsonrpc": "2.0",
+        "method": "tools/call",
+        "params": {"name": name, "arguments": arguments},
+        "id": id
+    }))
+    .unwrap()
+}
+
+async fn text(server: &mut McpServer, request: JsonRpcRequest) -> String {
+    let response = server.handle_request(request).await.unwrap();
+    response.result.unwrap()["content"][0]["text"].as_str().unwrap().to_string()
+}
+
+#[tokio::test]
+async fn test_refresh_metadata_replaces_stale_schema() {
+    let mock = Mock::new();
+    let client = ClickHouseClient::new(mock.url(), "shop", "default", "").with_schema_cache(Duration::from_secs(300));
+    let manager = Arc::new(ConnectionManager::new(client));
+    mock.add(handlers::provide(vec![1u8]));
+    manager.connect().await.unwrap();
+    let mut server = McpServer::new().with_connection(manager);
+
+    let get_schema = || call("get_table_schema", json!({"database": "shop", "table": "orders"}), 1);
+    mock.add(handlers::provide(vec![1u8]));
+    mock.add(handlers::provide(vec![1u8]));
+    mock.add(handlers::provide(vec![column("id", "UInt64")]));
+    assert!(!text(&mut server, get_schema()).await.contains("discount"));
+
+    // A column added meanwhile stays invisible while the schema is cached
+    assert!(!text(&mut server, get_schema()).await.contains("discount"));
+
+    mock.add(handlers::provide(vec![1u8]));
+    mock.add(handlers::provide(vec![1u8]));
+    mock.add(handlers::provide(vec![column("id", "UInt64"), column("discount", "Decimal(9, 2)")]));
+    let refreshed = text(&mut server, call("refresh_metadata", json!({"database": "shop"}), 2)).await;
+    assert!(refreshed.starts_with("Invalidated 1 cached schemas, refreshed:\n- shop.orders: 2 columns, fetched at "), "{}", refreshed);
+
+    // Served from the refreshed cache entry, without another query
+    assert!(text(&mut server, get_schema()).await.contains("discount"));
+
+    let stats = text(&mut server, call("server_stats", json!({}), 3)).await;
+    assert!(stats.contains("- Schema cache: 1 entries, hit rate 50.0% (2 hits, 2 misses), 1 invalidated"), "{}", stats);
+}
+
+const QUOTA_EXCEEDED: &str = "Code: 201. DB::Exception: Quota for user `default` for 3600s has been exceeded: \
+    read_rows = 1000001/1000000. Interval will end at 2024-05-01 14:00:00. (QUOTA_EXCEEDED) (version 24.3.1.1 (official build))\n";
+
+#[tokio::test]
+async fn test_quota_exhausted_falls_back_to_cached_schema() {
+    let mock = Mock::new();
+    // Every lookup goes to ClickHouse, but schemas are still kept
+    let client = ClickHouseClient::new(mock.url(), "shop", "default", "").with_schema_cache(Duration::ZERO);
+    let manager = Arc::new(ConnectionManager::new(client));
+    mock.add(handlers::provide(vec![1u8]));
+    manager.connect().await.unwrap();
+    let mut server = McpServer::new().with_connection(manager);
+
+    mock.add(handlers::provide(vec![1u8]));
+    mock.add(handlers::provide(vec![1u8]));
+    mock.add(handlers::provide(vec![column("id", "UInt64")]));
+    let get_schema = |table: &str| call("get_table_schema", json!({"database": "shop", "table": table}), 1);
+    assert!(!text(&mut server, get_schema("orders")).await.contains("quota"));
+
+    // The exception arrives in the response body, as ClickHouse sends it
+    mock.add(handlers::provide(QUOTA_EXCEEDED.bytes()));
+    let fallback = text(&mut server, get_schema("orders")).await;
+    assert!(fallback.starts_with("Schema for table 'shop.orders':\n\nColumns:\n- id: UInt64"), "{}", fallback);
+    assert!(fallback.contains("Note: quota exhausted, resets at 14:00 (server time); this is the cached schema fetched at "), "{}", fallback);
+
+    // Nothing cached to fall back to
+    mock.add(handlers::provide(QUOTA_EXCEEDED.bytes()));
+    let response = server.handle_request(get_schema("users")).await.unwrap();
+    let result = response.result.unwrap();
+    assert_eq!(result["isError"], true);
+    let message = result["content"][0]["text"].as_str().unwrap();
+    assert!(message.starts_with("Quota exhausted, resets at 14:00 (server time): Quota exceeded: Code: 201."), "{}", message);
+}
+
+#[tokio::test]
+async fn test_statements_run_by_the_server_invalidate_schemas() {
+    let mock = Mock::new();
+    let client = ClickHouseClient::new(mock.url(), "shop", "default", "")
+        .with_schema_cache(Duration::from_secs(300))
+        .with_writes_enabled(true);
+    for table in ["orders", "users"] {
+        mock.add(handlers::provide(vec![1u8]));
+        mock.add(handlers::provide(vec![1u8]));
+        mock.add(handlers::provide(vec![column("id", "UInt64")]));
+        client.get_table_schema("shop", table).await.unwrap();
+    }
+    assert_eq!(client.schema_cache_stats().unwrap().entries, 2);
+
+    // Only the altered table, named without its database
+    mock.add(handlers::record_ddl());
+    client.run_script(vec!["ALTER TABLE orders ADD COLUMN discount Decimal(9, 2)".to_string()]).await.unwrap();
+    let stats = client.schema_cache_stats().unwrap();
+    assert_eq!((stats.entries, stats.invalidations), (1, 1));
+
+    mock.add(handlers::provide(vec![1u8]));
+    mock.add(handlers::provide(vec![1u8]));
+    mock.add(handlers::provide(vec![column("id", "UInt64"), column("discount", "Decimal(9, 2)")]));
+    assert_eq!(client.get_table_schema("shop", "orders").await.unwrap().len(), 2);
+
+    // Statements not naming one table drop everything
+    mock.add(handlers::record_ddl());
+    client.execute_raw("RENAME TABLE shop.users TO shop.customers").await.unwrap();
+    assert_eq!(client.schema_cache_stats().unwrap().entries, 0);
+}
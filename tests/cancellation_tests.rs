@@ -0,0 +1,154 @@
+mod common;
+
+use common::read_request;
+use mcp_test::{ClickHouseClient, ConnectionManager, McpServer};
+use serde_json::Value;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpListener;
+use tokio::sync::mpsc;
+
+/// A fake ClickHouse that answers the health check, never answers the next
+/// query, and answers everything after that with an empty response. Every
+/// request is forwarded to the returned channel.
+async fn hanging_clickhouse() -> (String, mpsc::UnboundedReceiver<String>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let (requests, received) = mpsc::unbounded_channel();
+    let counter = Arc::new(AtomicUsize::new(0));
+
+    tokio::spawn(async move {
+        loop {
+            let (stream, _) = listener.accept().await.unwrap();
+            let requests = requests.clone();
+            let counter = Arc::clone(&counter);
+            tokio::spawn(async move {
+                let mut stream = BufReader::new(stream);
+                while let Some(request) = read_request(&mut stream).await {
+                    let n = counter.fetch_add(1, Ordering::SeqCst);
+                    let _ = requests.send(request);
+                    let response: &[u8] = match n {
+                        0 => b"HTTP/1.1 200 OK\r\nContent-Length: 1\r\n\r\n\x01",
+                        1 => std::future::pending().await,
+                        _ => b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n",
+                    };
+                    stream.get_mut().write_all(response).await.unwrap();
+                }
+            });
+        }
+    });
+
+    (url, received)
+}
+
+fn query_id_prefix(request: &str) -> String {
+    let start = request.find("query_id=").expect("query is tagged with a query_id") + "query_id=".len();
+    let query_id = &request[start..];
+    let end = query_id.find("%3A").expect("query_id has a per-call prefix");
+    query_id[..end].to_string()
+}
+
+#[tokio::test]
+async fn test_cancellation_aborts_in_flight_tool_call() {
+    let (url, mut requests) = hanging_clickhouse().await;
+
+    let manager = Arc::new(ConnectionManager::new(ClickHouseClient::new(&url, "default", "default", "")));
+    manager.connect().await.unwrap();
+    assert!(requests.recv().await.is_some());
+
+    let (mut input, server_input) = tokio::io::duplex(4096);
+    let (server_output, output) = tokio::io::duplex(4096);
+    let mut output = BufReader::new(output).lines();
+
+    let mut server = McpServer::new().with_connection(manager);
+    let serving = tokio::spawn(async move { server.serve(BufReader::new(server_input), server_output).await });
+
+    input
+        .write_all(b"{\"jsonrpc\": \"2.0\", \"method\": \"tools/call\", \"params\": {\"name\": \"list_tables\", \"arguments\": {\"database\": \"web\"}}, \"id\": 7}\n")
+        .await
+        .unwrap();
+
+    // The tool call is now stuck waiting on ClickHouse
+    let stuck = tokio::time::timeout(Duration::from_secs(5), requests.recv()).await.unwrap().unwrap();
+    let prefix = query_id_prefix(&stuck);
+
+    input
+        .write_all(b"{\"jsonrpc\": \"2.0\", \"method\": \"notifications/cancelled\", \"params\": {\"requestId\": 7}}\n")
+        .await
+        .unwrap();
+
+    let kill = tokio::time::timeout(Duration::from_secs(5), requests.recv()).await.unwrap().unwrap();
+    assert!(kill.contains("KILL QUERY"));
+    assert!(kill.contains(&prefix));
+
+    // Later requests are still served and the cancelled one never answers
+    input
+        .write_all(b"{\"jsonrpc\": \"2.0\", \"method\": \"tools/list\", \"id\": 8}\n")
+        .await
+        .unwrap();
+    drop(input);
+
+    let line = tokio::time::timeout(Duration::from_secs(5), output.next_line())
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap();
+    let response: Value = serde_json::from_str(&line).unwrap();
+    assert_eq!(response["id"], 8);
+
+    tokio::time::timeout(Duration::from_secs(5), serving).await.unwrap().unwrap().unwrap();
+    assert!(output.next_line().await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn test_batched_tool_call_can_be_cancelled() {
+    let (url, mut requests) = hanging_clickhouse().await;
+
+    let manager = Arc::new(ConnectionManager::new(ClickHouseClient::new(&url, "default", "default", "")));
+    manager.connect().await.unwrap();
+    assert!(requests.recv().await.is_some());
+
+    let (mut input, server_input) = tokio::io::duplex(4096);
+    let (server_output, output) = tokio::io::duplex(4096);
+    let mut output = BufReader::new(output).lines();
+
+    let mut server = McpServer::new().with_connection(manager);
+    let serving = tokio::spawn(async move { server.serve(BufReader::new(server_input), server_output).await });
+
+    input
+        .write_all(b"[{\"jsonrpc\": \"2.0\", \"method\": \"tools/call\", \"params\": {\"name\": \"list_tables\", \"arguments\": {\"database\": \"web\"}}, \"id\": 7}, {\"jsonrpc\": \"2.0\", \"method\": \"ping\", \"id\": 8}]\n")
+        .await
+        .unwrap();
+    let stuck = tokio::time::timeout(Duration::from_secs(5), requests.recv()).await.unwrap().unwrap();
+    let prefix = query_id_prefix(&stuck);
+
+    // Another call with the id of the stuck one is rejected, not queued
+    input
+        .write_all(b"{\"jsonrpc\": \"2.0\", \"method\": \"tools/call\", \"params\": {\"name\": \"list_databases\", \"arguments\": {}}, \"id\": 7}\n")
+        .await
+        .unwrap();
+    let line = tokio::time::timeout(Duration::from_secs(5), output.next_line()).await.unwrap().unwrap().unwrap();
+    let response: Value = serde_json::from_str(&line).unwrap();
+    assert_eq!((&response["id"], &response["error"]["code"]), (&Value::from(7), &Value::from(-32600)), "{}", response);
+
+    // The read loop isn't blocked by the batch, so the cancellation gets through
+    input
+        .write_all(b"{\"jsonrpc\": \"2.0\", \"method\": \"notifications/cancelled\", \"params\": {\"requestId\": 7}}\n")
+        .await
+        .unwrap();
+    let kill = tokio::time::timeout(Duration::from_secs(5), requests.recv()).await.unwrap().unwrap();
+    assert!(kill.contains("KILL QUERY"));
+    assert!(kill.contains(&prefix));
+    drop(input);
+
+    // The batch is answered without the cancelled call
+    let line = tokio::time::timeout(Duration::from_secs(5), output.next_line()).await.unwrap().unwrap().unwrap();
+    let response: Value = serde_json::from_str(&line).unwrap();
+    assert_eq!(response.as_array().unwrap().len(), 1, "{}", response);
+    assert_eq!(response[0]["id"], 8);
+
+    tokio::time::timeout(Duration::from_secs(5), serving).await.unwrap().unwrap().unwrap();
+    assert!(output.next_line().await.unwrap().is_none());
+}
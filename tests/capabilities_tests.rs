@@ -0,0 +1,139 @@
+use clickhouse::test::{handlers, Mock};
+use mcp_test::capabilities::{probed_functions, probed_system_tables, tool_requirements, UNAVAILABLE_SUFFIX};
+use mcp_test::{
+    apply_capabilities, Capabilities, ClickHouseClient, ConnectionManager, JsonRpcRequest, McpServer, Requirement, UnavailableTools,
+    TOOL_REQUIREMENTS,
+};
+use serde_json::{json, Value};
+use std::sync::Arc;
+
+fn capabilities(system_tables: &[&str], functions: &[&str]) -> Capabilities {
+    Capabilities {
+        system_tables: system_tables.iter().map(|t| t.to_string()).collect(),
+        functions: functions.iter().map(|f| f.to_string()).collect(),
+    }
+}
+
+fn tools() -> Vec<Value> {
+    ["list_databases", "keeper_info", "column_histogram"]
+        .iter()
+        .map(|name| json!({"name": name, "description": format!("The {} tool", name)}))
+        .collect()
+}
+
+fn names(tools: &[Value]) -> Vec<&str> {
+    tools.iter().map(|tool| tool["name"].as_str().unwrap()).collect()
+}
+
+#[test]
+fn test_requirements_and_probe_lists() {
+    assert_eq!(tool_requirements("keeper_info"), [Requirement::SystemTable("zookeeper")]);
+    assert!(tool_requirements("list_databases").is_empty());
+    assert_eq!(Requirement::SystemTable("zookeeper").to_string(), "system.zookeeper");
+    assert_eq!(Requirement::Function("histogram").to_string(), "histogram()");
+
+    assert_eq!(probed_system_tables(), ["asynchronous_metrics", "merge_tree_settings", "query_log", "zookeeper"]);
+    assert_eq!(probed_functions(), ["histogram"]);
+}
+
+#[test]
+fn test_apply_capabilities_hides_or_describes_unavailable_tools() {
+    // Before a probe, nothing is known to be missing
+    assert_eq!(apply_capabilities(tools(), None, UnavailableTools::Hide), tools());
+
+    let everything = capabilities(&["asynchronous_metrics", "merge_tree_settings", "query_log", "zookeeper"], &["histogram"]);
+    assert_eq!(apply_capabilities(tools(), Some(&everything), UnavailableTools::Hide), tools());
+    assert!(everything.missing("keeper_info").is_empty());
+
+    let no_keeper = capabilities(&["asynchronous_metrics", "merge_tree_settings"], &["histogram"]);
+    assert_eq!(no_keeper.missing("keeper_info"), [Requirement::SystemTable("zookeeper")]);
+    let hidden = apply_capabilities(tools(), Some(&no_keeper), UnavailableTools::Hide);
+    assert_eq!(names(&hidden), ["list_databases", "column_histogram"]);
+    let described = apply_capabilities(tools(), Some(&no_keeper), UnavailableTools::Describe);
+    assert_eq!(names(&described), ["list_databases", "keeper_info", "column_histogram"]);
+    assert_eq!(described[1]["description"], format!("The keeper_info tool{}", UNAVAILABLE_SUFFIX));
+    assert_eq!(described[2]["description"], "The column_histogram tool");
+
+    let bare = Capabilities::default();
+    assert_eq!(names(&apply_capabilities(tools(), Some(&bare), UnavailableTools::Hide)), ["list_databases"]);
+    assert!(bare.available_tools().is_empty());
+    assert_eq!(no_keeper.available_tools(), ["column_histogram", "insert_pressure", "server_metrics"]);
+}
+
+#[test]
+fn test_unavailable_tools_parse() {
+    assert_eq!(UnavailableTools::parse("Describe"), Some(UnavailableTools::Describe));
+    assert_eq!(UnavailableTools::parse("hide"), Some(UnavailableTools::Hide));
+    assert_eq!(UnavailableTools::parse("show"), None);
+    assert_eq!(UnavailableTools::default(), UnavailableTools::Hide);
+}
+
+fn request(method: &str, params: Value) -> JsonRpcRequest {
+    serde_json::from_value(json!({"jsonrpc": "2.0", "method": method, "params": params, "id": 1})).unwrap()
+}
+
+async fn listed_tools(server: &mut McpServer) -> Vec<Value> {
+    let response = server.handle_request(request("tools/list", json!({}))).await.unwrap();
+    response.result.unwrap()["tools"].as_array().unwrap().clone()
+}
+
+#[tokio::test]
+async fn test_requirements_name_builtin_tools() {
+    let tools = listed_tools(&mut McpServer::new()).await;
+    for (tool, _) in TOOL_REQUIREMENTS {
+        assert!(tools.iter().any(|listed| listed["name"] == *tool), "{}", tool);
+    }
+}
+
+#[tokio::test]
+async fn test_tools_follow_probed_capabilities() {
+    let mock = Mock::new();
+    let manager = Arc::new(ConnectionManager::new(ClickHouseClient::new(mock.url(), "default", "default", "")));
+    mock.add(handlers::provide(vec![1u8]));
+    manager.connect().await.unwrap();
+    let mut server = McpServer::new().with_connection(Arc::clone(&manager));
+    let listed = |tools: &[Value], name: &str| tools.iter().any(|tool| tool["name"] == name);
+
+    // Unprobed: every tool is listed
+    assert!(listed(&listed_tools(&mut server).await, "keeper_info"));
+
+    mock.add(handlers::provide(vec!["asynchronous_metrics".to_string(), "merge_tree_settings".to_string()]));
+    mock.add(handlers::provide(vec!["histogram".to_string()]));
+    let probed = manager.client().await.unwrap().probe_capabilities().await.unwrap();
+    assert_eq!(probed, capabilities(&["asynchronous_metrics", "merge_tree_settings"], &["histogram"]));
+    let tools = listed_tools(&mut server).await;
+    assert!(!listed(&tools, "keeper_info"));
+    assert!(listed(&tools, "server_metrics"));
+
+    let response = server.handle_request(request("tools/call", json!({"name": "keeper_info", "arguments": {}}))).await.unwrap();
+    let result = response.result.unwrap();
+    assert_eq!(result["isError"], true);
+    assert_eq!(
+        result["content"][0]["text"],
+        "Not supported: Tool 'keeper_info' is unavailable on this server, which lacks system.zookeeper"
+    );
+
+    let mut describing = server.clone().with_unavailable_tools(UnavailableTools::Describe);
+    let tools = listed_tools(&mut describing).await;
+    let keeper = tools.iter().find(|tool| tool["name"] == "keeper_info").unwrap();
+    assert!(keeper["description"].as_str().unwrap().ends_with(UNAVAILABLE_SUFFIX));
+
+    // A full refresh probes again, here finding Keeper configured meanwhile
+    mock.add(handlers::provide(vec![
+        "asynchronous_metrics".to_string(),
+        "merge_tree_settings".to_string(),
+        "zookeeper".to_string(),
+    ]));
+    mock.add(handlers::provide(Vec::<String>::new()));
+    let response = server.handle_request(request("tools/call", json!({"name": "refresh_metadata", "arguments": {}}))).await.unwrap();
+    assert_eq!(
+        response.result.unwrap()["content"][0]["text"],
+        "Schema caching is disabled; every tool call reads the current schema\n\
+         Server capabilities probed again: 3 of 5 tools with requirements available\n\
+         - column_histogram: unavailable, needs histogram()\n\
+         - recent_queries: unavailable, needs system.query_log\n"
+    );
+    let tools = listed_tools(&mut server).await;
+    assert!(listed(&tools, "keeper_info"));
+    assert!(!listed(&tools, "column_histogram"));
+}
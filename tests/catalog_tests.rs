@@ -0,0 +1,188 @@
+use clickhouse::test::{handlers, Mock};
+use mcp_test::catalog::{catalog_hash, MAX_CATALOG_SNAPSHOT_BYTES};
+use mcp_test::{
+    decode_catalog_version, diff_catalog, encode_catalog_version, CatalogEntry, ClickHouseClient, ClickHouseError, ConnectionManager,
+    JsonRpcRequest, McpServer, TableRef,
+};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use serde_json::json;
+use std::sync::Arc;
+
+fn entry(database: &str, table: &str, modified: u32) -> CatalogEntry {
+    CatalogEntry { database: database.to_string(), table: table.to_string(), modified }
+}
+
+fn table_ref(database: &str, table: &str) -> TableRef {
+    TableRef { database: database.to_string(), table: table.to_string() }
+}
+
+#[test]
+fn test_catalog_versions_carry_their_snapshot() {
+    let entries = vec![entry("web", "events", 1714572000), entry("аналитика", "отчёт \"q\"", 1), entry("web", "users", 1714571000)];
+    let token = encode_catalog_version(&entries);
+    assert!(token.starts_with("cv3."));
+    assert!(token.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_')), "{}", token);
+    let mut sorted = entries.clone();
+    sorted.sort();
+    assert_eq!(decode_catalog_version(&token).unwrap(), sorted);
+
+    // Independent of order, different for any change
+    let reversed: Vec<CatalogEntry> = entries.iter().rev().cloned().collect();
+    assert_eq!(encode_catalog_version(&reversed), token);
+    assert_eq!(catalog_hash(&reversed), catalog_hash(&entries));
+    assert_ne!(encode_catalog_version(&entries[..2]), token);
+    assert_ne!(encode_catalog_version(&[entry("web", "events", 1714572001)]), encode_catalog_version(&[entry("web", "events", 1714572000)]));
+    assert_eq!(decode_catalog_version(&encode_catalog_version(&[])).unwrap(), []);
+
+    // Similar names compress well
+    let large: Vec<CatalogEntry> = (0..5000).map(|i| entry("web", &format!("table_{}", i), 1714572000)).collect();
+    let token = encode_catalog_version(&large);
+    assert!(token.len() < 5000 * 10, "{}", token.len());
+    assert_eq!(decode_catalog_version(&token).unwrap().len(), 5000);
+}
+
+#[test]
+fn test_damaged_catalog_versions_are_rejected() {
+    let token = encode_catalog_version(&[entry("web", "users", 100), entry("web", "events", 100)]);
+    let error = |token: &str| match decode_catalog_version(token) {
+        Err(ClickHouseError::InvalidArgument { message }) => message,
+        other => panic!("Expected InvalidArgument, got: {:?}", other),
+    };
+    assert!(error("").contains("expected a token returned by catalog_version"));
+    assert!(error("cv3.0000000000000000").contains("expected a token returned by catalog_version"));
+    assert!(error(&token.replacen("cv3", "cv2", 1)).contains("unsupported format 'cv2'"));
+    assert!(error(&format!("{}!", token)).contains("malformed encoding"));
+
+    let (head, _) = token.rsplit_once('.').unwrap();
+    let other = encode_catalog_version(&[entry("web", "users", 200), entry("web", "events", 100)]);
+    let (_, other_payload) = other.rsplit_once('.').unwrap();
+    assert!(error(&format!("{}.{}", head, other_payload)).contains("checksum mismatch"));
+
+    // A size prefix past the limit is refused before decompressing
+    let mut bomb = ((MAX_CATALOG_SNAPSHOT_BYTES + 1) as u32).to_le_bytes().to_vec();
+    bomb.extend_from_slice(&[0x1f, 0x30]);
+    let bomb = format!("cv3.0000000000000000.{}", URL_SAFE_NO_PAD.encode(bomb));
+    assert!(error(&bomb).contains("malformed snapshot"));
+}
+
+#[test]
+fn test_diff_catalog() {
+    let previous = [entry("web", "events", 100), entry("web", "users", 100), entry("web", "old_name", 100), entry("tmp", "scratch", 100)];
+    let current = [entry("web", "events", 100), entry("web", "users", 200), entry("web", "new_name", 100), entry("shop", "orders", 300)];
+
+    let diff = diff_catalog(&previous, &current);
+    assert_eq!(diff.changed, [entry("shop", "orders", 300), entry("web", "new_name", 100), entry("web", "users", 200)]);
+    assert_eq!(diff.deleted, [table_ref("tmp", "scratch"), table_ref("web", "old_name")]);
+    assert_eq!(diff.databases().into_iter().collect::<Vec<_>>(), ["shop", "tmp", "web"]);
+
+    assert!(diff_catalog(&current, &current).changed.is_empty());
+    assert_eq!(diff_catalog(&[], &current).changed.len(), 4);
+    assert_eq!(diff_catalog(&current, &[]).deleted.len(), 4);
+}
+
+fn call(name: &str, arguments: serde_json::Value) -> JsonRpcRequest {
+    serde_json::from_value(json!({"jsonrpc": "2.0", "method": "tools/call", "params": {"name": name, "arguments": arguments}, "id": 1}))
+        .unwrap()
+}
+
+fn table_row(database: &str, table: &str, engine: &str, modified: u32) -> (String, String, String, u32) {
+    (database.to_string(), table.to_string(), engine.to_string(), modified)
+}
+
+fn column_row(database: &str, table: &str, name: &str, r#type: &str) -> (String, String, String, String) {
+    (database.to_string(), table.to_string(), name.to_string(), r#type.to_string())
+}
+
+#[tokio::test]
+async fn test_export_catalog_since_version() {
+    let mock = Mock::new();
+    let manager = Arc::new(ConnectionManager::new(ClickHouseClient::new(mock.url(), "web", "default", "")));
+    mock.add(handlers::provide(vec![1u8]));
+    manager.connect().await.unwrap();
+    let mut server = McpServer::new().with_connection(manager);
+
+    mock.add(handlers::provide(vec![table_row("web", "events", "MergeTree", 100), table_row("web", "users", "MergeTree", 100)]));
+    mock.add(handlers::provide(vec![
+        column_row("web", "events", "id", "UInt64"),
+        column_row("web", "events", "ts", "DateTime"),
+        column_row("web", "users", "id", "UInt64"),
+    ]));
+    let response = server.handle_request(call("export_catalog", json!({}))).await.unwrap();
+    let export = response.result.unwrap()["structuredContent"].clone();
+    assert_eq!(export["tables"].as_array().unwrap().len(), 2);
+    assert_eq!(export["tables"][0]["columns"], json!([{"name": "id", "type": "UInt64"}, {"name": "ts", "type": "DateTime"}]));
+    assert_eq!(export["deleted"], json!([]));
+    let version = export["version"].as_str().unwrap().to_string();
+
+    // users was altered, events dropped and sessions created
+    mock.add(handlers::provide(vec![table_row("web", "sessions", "Memory", 300), table_row("web", "users", "MergeTree", 200)]));
+    mock.add(handlers::provide(vec![
+        column_row("web", "sessions", "id", "UUID"),
+        column_row("web", "users", "id", "UInt64"),
+        column_row("web", "users", "email", "String"),
+    ]));
+    let response = server.handle_request(call("export_catalog", json!({"since_version": version}))).await.unwrap();
+    let result = response.result.unwrap();
+    let text = result["content"][0]["text"].as_str().unwrap();
+    assert!(text.starts_with("Catalog changes since the given version: 2 tables created or altered, 1 dropped\n"), "{}", text);
+    assert!(text.contains("- web.users (MergeTree, 2 columns)\n"), "{}", text);
+    assert!(text.contains("\nDropped:\n- web.events\n"), "{}", text);
+    let export = &result["structuredContent"];
+    assert_eq!(export["deleted"], json!([{"database": "web", "table": "events"}]));
+    let new_version = export["version"].as_str().unwrap().to_string();
+    assert_ne!(new_version, version);
+
+    // Nothing changed: no column query is needed
+    mock.add(handlers::provide(vec![table_row("web", "sessions", "Memory", 300), table_row("web", "users", "MergeTree", 200)]));
+    let response = server.handle_request(call("export_catalog", json!({"since_version": new_version}))).await.unwrap();
+    let result = response.result.unwrap();
+    assert!(result["content"][0]["text"].as_str().unwrap().starts_with("No changes since the given version\n"));
+
+    // Unreadable versions are bad arguments
+    let response = server.handle_request(call("export_catalog", json!({"since_version": "nope"}))).await.unwrap();
+    assert_eq!(response.error.unwrap()["code"], -32602);
+
+    // Tokens need no state on the server, so one from before a restart works
+    let manager = Arc::new(ConnectionManager::new(ClickHouseClient::new(mock.url(), "web", "default", "")));
+    mock.add(handlers::provide(vec![1u8]));
+    manager.connect().await.unwrap();
+    let mut restarted = McpServer::new().with_connection(manager);
+    mock.add(handlers::provide(vec![table_row("web", "users", "MergeTree", 200)]));
+    let response = restarted.handle_request(call("export_catalog", json!({"since_version": new_version}))).await.unwrap();
+    let text = response.result.unwrap()["content"][0]["text"].as_str().unwrap().to_string();
+    assert!(text.contains("0 tables created or altered, 1 dropped\n") && text.contains("- web.sessions\n"), "{}", text);
+}
+
+#[tokio::test]
+async fn test_catalog_version_tool_and_resource_agree() {
+    let mock = Mock::new();
+    let manager = Arc::new(ConnectionManager::new(ClickHouseClient::new(mock.url(), "web", "default", "")));
+    mock.add(handlers::provide(vec![1u8]));
+    manager.connect().await.unwrap();
+    let mut server = McpServer::new().with_connection(manager);
+    let tables = || vec![table_row("web", "events", "MergeTree", 100)];
+
+    mock.add(handlers::provide(tables()));
+    let response = server.handle_request(call("catalog_version", json!({}))).await.unwrap();
+    let from_tool = response.result.unwrap()["structuredContent"].clone();
+    assert_eq!(from_tool["tables"], 1);
+    assert_eq!(from_tool["version"], encode_catalog_version(&[entry("web", "events", 100)]));
+
+    let request = |method: &str, params: serde_json::Value| -> JsonRpcRequest {
+        serde_json::from_value(json!({"jsonrpc": "2.0", "method": method, "params": params, "id": 2})).unwrap()
+    };
+    let response = server.handle_request(request("resources/list", json!({}))).await.unwrap();
+    let uri = response.result.unwrap()["resources"][0]["uri"].as_str().unwrap().to_string();
+    assert_eq!(uri, "clickhouse://catalog/version");
+
+    mock.add(handlers::provide(tables()));
+    let response = server.handle_request(request("resources/read", json!({"uri": uri}))).await.unwrap();
+    let contents = &response.result.unwrap()["contents"][0];
+    assert_eq!(contents["mimeType"], "application/json");
+    let from_resource: serde_json::Value = serde_json::from_str(contents["text"].as_str().unwrap()).unwrap();
+    assert_eq!(from_resource, from_tool);
+
+    let response = server.handle_request(request("resources/read", json!({"uri": "clickhouse://nothing"}))).await.unwrap();
+    assert_eq!(response.error.unwrap()["code"], -32602);
+}
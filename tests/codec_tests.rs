@@ -0,0 +1,81 @@
+use mcp_test::{encode_frame, Frame, LineReader, McpServer};
+use serde_json::Value;
+use tokio::io::BufReader;
+
+async fn frames(input: &[u8], max_line_length: usize) -> Vec<Frame> {
+    // A tiny buffer forces lines to span several reads
+    let mut reader = LineReader::new(BufReader::with_capacity(4, input)).with_max_line_length(max_line_length);
+    let mut frames = Vec::new();
+    while let Some(frame) = reader.next_frame().await.unwrap() {
+        frames.push(frame);
+    }
+    frames
+}
+
+fn line(text: &str) -> Frame {
+    Frame::Line(text.to_string())
+}
+
+#[tokio::test]
+async fn test_crlf_line_endings_are_stripped() {
+    let input = b"{\"id\": 1}\r\n{\"id\": 2}\r\n";
+
+    assert_eq!(frames(input, 1024).await, vec![line("{\"id\": 1}"), line("{\"id\": 2}")]);
+}
+
+#[tokio::test]
+async fn test_leading_bom_is_stripped() {
+    let input = b"\xEF\xBB\xBF{\"id\": 1}\r\n{\"id\": 2}\n";
+
+    assert_eq!(frames(input, 1024).await, vec![line("{\"id\": 1}"), line("{\"id\": 2}")]);
+}
+
+#[tokio::test]
+async fn test_final_line_without_newline_is_returned() {
+    assert_eq!(frames(b"{\"id\": 1}", 1024).await, vec![line("{\"id\": 1}")]);
+    assert!(frames(b"", 1024).await.is_empty());
+}
+
+#[tokio::test]
+async fn test_oversized_line_is_discarded_and_reading_continues() {
+    let mut input = vec![b'x'; 100];
+    input.extend_from_slice(b"\n{\"id\": 2}\n");
+
+    assert_eq!(
+        frames(&input, 16).await,
+        vec![Frame::TooLong { length: 100 }, line("{\"id\": 2}")]
+    );
+}
+
+#[tokio::test]
+async fn test_invalid_utf8_line_is_reported_and_reading_continues() {
+    let input = b"{\"name\": \"\xFF\xFE\"}\n{\"id\": 2}\n";
+
+    let frames = frames(input, 1024).await;
+    assert_eq!(frames.len(), 2);
+    match &frames[0] {
+        Frame::InvalidUtf8(lossy) => assert!(lossy.contains('\u{FFFD}')),
+        other => panic!("Expected InvalidUtf8, got: {:?}", other),
+    }
+    assert_eq!(frames[1], line("{\"id\": 2}"));
+}
+
+#[tokio::test]
+async fn test_responses_are_framed_as_single_compact_lines() {
+    let mut server = McpServer::new();
+
+    let response = server
+        .handle_line(r#"{"jsonrpc": "2.0", "method": "no/such/method", "id": "line one\nline two"}"#)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(!response.contains('\n'));
+    assert!(!response.contains(": "));
+
+    let frame = encode_frame(&response);
+    assert_eq!(frame.iter().filter(|&&b| b == b'\n').count(), 1);
+    assert_eq!(frame.last(), Some(&b'\n'));
+
+    let decoded: Value = serde_json::from_slice(&frame).unwrap();
+    assert_eq!(decoded["id"], "line one\nline two");
+}
@@ -0,0 +1,177 @@
+//! Helpers for tests that talk to a fake ClickHouse over raw TCP, for cases
+//! the `clickhouse::test::Mock` can't cover (hanging or inspecting requests),
+//! and fixtures shared by several test files.
+
+use clickhouse::test::{handlers, Mock};
+use mcp_test::{ClickHouseClient, ColumnInfo, ConnectionManager};
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+
+/// Reads one HTTP request (head and body) from the stream.
+pub async fn read_request(stream: &mut BufReader<TcpStream>) -> Option<String> {
+    let mut head = String::new();
+    loop {
+        let mut line = String::new();
+        if stream.read_line(&mut line).await.ok()? == 0 {
+            return None;
+        }
+        head.push_str(&line);
+        if line == "\r\n" {
+            break;
+        }
+    }
+
+    let content_length = head
+        .lines()
+        .find_map(|l| l.to_ascii_lowercase().strip_prefix("content-length:").map(|v| v.trim().to_string()))
+        .and_then(|v| v.parse::<usize>().ok())
+        .unwrap_or(0);
+    let mut body = vec![0; content_length];
+    stream.read_exact(&mut body).await.ok()?;
+
+    Some(format!("{}{}", head, String::from_utf8_lossy(&body)))
+}
+
+/// Decodes a form-urlencoded string such as the `query` URL parameter.
+#[allow(dead_code)]
+pub fn url_decode(encoded: &str) -> String {
+    let bytes = encoded.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => decoded.push(b' '),
+            b'%' => match encoded.get(i + 1..i + 3).and_then(|hex| u8::from_str_radix(hex, 16).ok()) {
+                Some(byte) => {
+                    decoded.push(byte);
+                    i += 2;
+                }
+                None => decoded.push(b'%'),
+            },
+            c => decoded.push(c),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// A fake ClickHouse that answers every query with a single `1` and keeps
+/// the requests it got.
+#[allow(dead_code)]
+pub async fn recording_clickhouse() -> (String, Arc<Mutex<Vec<String>>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let requests = Arc::new(Mutex::new(Vec::new()));
+
+    let recorded = Arc::clone(&requests);
+    tokio::spawn(async move {
+        loop {
+            let (stream, _) = listener.accept().await.unwrap();
+            let recorded = Arc::clone(&recorded);
+            tokio::spawn(async move {
+                let mut stream = BufReader::new(stream);
+                while let Some(request) = read_request(&mut stream).await {
+                    recorded.lock().unwrap().push(request);
+                    stream.get_mut().write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 1\r\n\r\n\x01").await.unwrap();
+                }
+            });
+        }
+    });
+
+    (url, requests)
+}
+
+/// The SQL of a request read by [`read_request`]: the `query` URL parameter
+/// of a GET, or the body of a POST.
+#[allow(dead_code)]
+pub fn query_of(request: &str) -> String {
+    let target = request.split_whitespace().nth(1).unwrap_or_default();
+    target
+        .split(['?', '&'])
+        .find_map(|pair| pair.strip_prefix("query="))
+        .map(url_decode)
+        .unwrap_or_else(|| request.split("\r\n\r\n").nth(1).unwrap_or_default().to_string())
+}
+
+/// Encodes rows of strings in RowBinary.
+#[allow(dead_code)]
+pub fn rowbinary_strings(values: &[&str]) -> Vec<u8> {
+    let mut out = Vec::new();
+    for value in values {
+        let mut len = value.len();
+        loop {
+            let byte = (len & 0x7f) as u8;
+            len >>= 7;
+            if len == 0 {
+                out.push(byte);
+                break;
+            }
+            out.push(byte | 0x80);
+        }
+        out.extend_from_slice(value.as_bytes());
+    }
+    out
+}
+
+/// A fake ClickHouse answering each query with the status line and body
+/// `answer` gives for its SQL. Returns its URL and the SQL it got.
+#[allow(dead_code)]
+pub async fn answering_clickhouse<F>(answer: F) -> (String, Arc<Mutex<Vec<String>>>)
+where
+    F: Fn(&str) -> (&'static str, Vec<u8>) + Send + Sync + 'static,
+{
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let queries = Arc::new(Mutex::new(Vec::new()));
+    let answer = Arc::new(answer);
+
+    let received = Arc::clone(&queries);
+    tokio::spawn(async move {
+        loop {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (received, answer) = (Arc::clone(&received), Arc::clone(&answer));
+            tokio::spawn(async move {
+                let mut stream = BufReader::new(stream);
+                while let Some(request) = read_request(&mut stream).await {
+                    let query = query_of(&request);
+                    received.lock().unwrap().push(query.clone());
+                    let (status, body) = answer(&query);
+                    let head = format!("HTTP/1.1 {}\r\nContent-Length: {}\r\n\r\n", status, body.len());
+                    stream.get_mut().write_all(head.as_bytes()).await.unwrap();
+                    stream.get_mut().write_all(&body).await.unwrap();
+                }
+            });
+        }
+    });
+
+    (url, queries)
+}
+
+/// A column of the given type with no default, comment or key membership.
+#[allow(dead_code)]
+pub fn column(name: &str, r#type: &str) -> ColumnInfo {
+    ColumnInfo {
+        name: name.to_string(),
+        r#type: r#type.to_string(),
+        default_type: "".to_string(),
+        default_expression: "".to_string(),
+        comment: "".to_string(),
+        is_in_partition_key: 0,
+        is_in_sorting_key: 0,
+        is_in_primary_key: 0,
+        is_in_sampling_key: 0,
+        enum_values: None,
+        timezone: None,
+    }
+}
+
+/// A connection to `mock` as `username`, with `database` as the default,
+/// connected through the first handler of the mock.
+#[allow(dead_code)]
+pub async fn connected_manager(mock: &Mock, database: &str, username: &str) -> Arc<ConnectionManager> {
+    let manager = Arc::new(ConnectionManager::new(ClickHouseClient::new(mock.url(), database, username, "")));
+    mock.add(handlers::provide(vec![1u8]));
+    manager.connect().await.unwrap();
+    manager
+}
@@ -0,0 +1,271 @@
+mod common;
+
+use clickhouse::test::{handlers, status, Mock};
+use common::read_request;
+use mcp_test::{
+    ClickHouseClient, ClickHouseError, ConnectionManager, ConnectionState, DatabaseInfo, JsonRpcRequest, McpServer,
+    StateTransition,
+};
+use serde_json::{json, Value};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, DuplexStream, Lines};
+use tokio::net::TcpListener;
+use tokio::sync::broadcast;
+
+fn manager_for(mock: &Mock) -> ConnectionManager {
+    let client = ClickHouseClient::new(mock.url(), "default", "default", "")
+        .with_retry_config(0, Duration::from_millis(1));
+    ConnectionManager::new(client)
+}
+
+#[tokio::test]
+async fn test_tool_calls_recover_after_server_comes_back() {
+    let mock = Mock::new();
+    let manager = manager_for(&mock);
+
+    // Initial connection at startup fails
+    mock.add(handlers::failure(status::SERVICE_UNAVAILABLE));
+    assert!(manager.connect().await.is_err());
+    assert_eq!(manager.state(), ConnectionState::Disconnected);
+
+    // A tool call while the server is still down reports when it went away
+    mock.add(handlers::failure(status::SERVICE_UNAVAILABLE));
+    match manager.client().await {
+        Err(ClickHouseError::ServiceUnavailable { message }) => {
+            assert!(message.starts_with("ClickHouse unreachable since "));
+            assert!(message.contains("last error:"));
+        }
+        Err(other) => panic!("Expected ServiceUnavailable, got: {:?}", other),
+        Ok(_) => panic!("Expected the connection attempt to fail"),
+    }
+    assert!(manager.status().unreachable_since.is_some());
+
+    // Once the server recovers the next tool call reconnects transparently
+    mock.add(handlers::provide(vec![1u8]));
+    mock.add(handlers::provide(vec![DatabaseInfo { name: "default".to_string() }]));
+    let client = manager.client().await.unwrap();
+    let databases = client.list_databases().await.unwrap();
+    assert_eq!(databases.len(), 1);
+    assert_eq!(manager.state(), ConnectionState::Connected);
+    assert!(manager.status().last_error.is_none());
+}
+
+#[tokio::test]
+async fn test_connectivity_errors_degrade_connection() {
+    let mock = Mock::new();
+    let manager = manager_for(&mock);
+
+    mock.add(handlers::provide(vec![1u8]));
+    manager.connect().await.unwrap();
+    assert_eq!(manager.state(), ConnectionState::Connected);
+
+    // Data errors leave the connection alone
+    manager.report_error(&ClickHouseError::DatabaseNotFound { database: "missing".to_string(), raw: None });
+    assert_eq!(manager.state(), ConnectionState::Connected);
+
+    manager.report_error(&ClickHouseError::NetworkError { message: "connection reset".to_string() });
+    assert_eq!(manager.state(), ConnectionState::Degraded);
+
+    // Degraded connections still hand out the client without probing first
+    assert!(manager.client().await.is_ok());
+}
+
+#[tokio::test]
+async fn test_background_reconnect_restores_connection() {
+    let mock = Mock::new();
+    let manager = Arc::new(
+        manager_for(&mock).with_reconnect_backoff(Duration::from_millis(10), Duration::from_millis(20)),
+    );
+
+    mock.add(handlers::failure(status::SERVICE_UNAVAILABLE));
+    assert!(manager.connect().await.is_err());
+
+    mock.add(handlers::failure(status::SERVICE_UNAVAILABLE));
+    mock.add(handlers::provide(vec![1u8]));
+    manager.ensure_background_reconnect();
+
+    for _ in 0..100 {
+        if manager.state() == ConnectionState::Connected {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    assert_eq!(manager.state(), ConnectionState::Connected);
+}
+
+fn list_databases_call() -> JsonRpcRequest {
+    serde_json::from_value(json!({"jsonrpc": "2.0", "method": "tools/call", "params": {"name": "list_databases"}, "id": 1}))
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_first_tool_call_reconnects_after_startup_failure() {
+    let mock = Mock::new();
+    let manager = Arc::new(
+        manager_for(&mock).with_reconnect_backoff(Duration::from_millis(10), Duration::from_millis(20)),
+    );
+
+    // ClickHouse was not up yet when the server started
+    mock.add(handlers::failure(status::SERVICE_UNAVAILABLE));
+    assert!(manager.connect().await.is_err());
+
+    // It comes up while the first tool call waits for it
+    mock.add(handlers::failure(status::SERVICE_UNAVAILABLE));
+    mock.add(handlers::provide(vec![1u8]));
+    mock.add(handlers::provide(vec![DatabaseInfo { name: "default".to_string() }]));
+
+    let mut server = McpServer::new()
+        .with_connection(Arc::clone(&manager))
+        .with_reconnect_deadline(Duration::from_secs(5));
+    let response = server.handle_request(list_databases_call()).await.unwrap();
+
+    assert!(response.error.is_none(), "unexpected error: {:?}", response.error);
+    assert_eq!(manager.state(), ConnectionState::Connected);
+}
+
+#[tokio::test]
+async fn test_tool_call_reports_unreachable_after_reconnect_deadline() {
+    let mock = Mock::new();
+    let manager = Arc::new(
+        manager_for(&mock).with_reconnect_backoff(Duration::from_millis(200), Duration::from_millis(200)),
+    );
+
+    mock.add(handlers::failure(status::SERVICE_UNAVAILABLE));
+    assert!(manager.connect().await.is_err());
+
+    // One attempt within the deadline and the usual attempt of the tool call
+    mock.add(handlers::failure(status::SERVICE_UNAVAILABLE));
+    mock.add(handlers::failure(status::SERVICE_UNAVAILABLE));
+
+    let mut server = McpServer::new()
+        .with_connection(manager)
+        .with_reconnect_deadline(Duration::from_millis(50));
+    let response = server.handle_request(list_databases_call()).await.unwrap();
+
+    let result = response.result.unwrap();
+    assert_eq!(result["isError"], true);
+    let message = result["content"][0]["text"].as_str().unwrap().to_string();
+    assert!(message.contains("ClickHouse unreachable since"), "{}", message);
+}
+
+/// A fake ClickHouse whose health check succeeds while the returned flag is
+/// set and fails with 503 otherwise.
+async fn toggled_clickhouse() -> (String, Arc<AtomicBool>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let healthy = Arc::new(AtomicBool::new(true));
+
+    let flag = Arc::clone(&healthy);
+    tokio::spawn(async move {
+        loop {
+            let (stream, _) = listener.accept().await.unwrap();
+            let flag = Arc::clone(&flag);
+            tokio::spawn(async move {
+                let mut stream = BufReader::new(stream);
+                while read_request(&mut stream).await.is_some() {
+                    let response: &[u8] = if flag.load(Ordering::SeqCst) {
+                        b"HTTP/1.1 200 OK\r\nContent-Length: 1\r\n\r\n\x01"
+                    } else {
+                        b"HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\n\r\n"
+                    };
+                    stream.get_mut().write_all(response).await.unwrap();
+                }
+            });
+        }
+    });
+
+    (url, healthy)
+}
+
+async fn next_transition(transitions: &mut broadcast::Receiver<StateTransition>) -> StateTransition {
+    tokio::time::timeout(Duration::from_secs(5), transitions.recv()).await.unwrap().unwrap()
+}
+
+async fn next_message(output: &mut Lines<BufReader<DuplexStream>>) -> Value {
+    let line = tokio::time::timeout(Duration::from_secs(5), output.next_line()).await.unwrap().unwrap().unwrap();
+    serde_json::from_str(&line).unwrap()
+}
+
+fn toggled_manager(url: &str) -> Arc<ConnectionManager> {
+    let client = ClickHouseClient::new(url, "default", "default", "").with_retry_config(0, Duration::from_millis(1));
+    Arc::new(ConnectionManager::new(client))
+}
+
+#[tokio::test]
+async fn test_health_probe_records_transitions() {
+    let (url, healthy) = toggled_clickhouse().await;
+    let manager = toggled_manager(&url);
+    manager.connect().await.unwrap();
+
+    let mut transitions = manager.subscribe();
+    let probe = manager.start_health_probe(Duration::from_millis(10));
+
+    // Noticed without any tool call
+    healthy.store(false, Ordering::SeqCst);
+    let down = next_transition(&mut transitions).await;
+    assert_eq!((down.from, down.to), (ConnectionState::Connected, ConnectionState::Disconnected));
+    assert!(down.error.is_some());
+
+    healthy.store(true, Ordering::SeqCst);
+    let up = next_transition(&mut transitions).await;
+    assert_eq!((up.from, up.to), (ConnectionState::Disconnected, ConnectionState::Connected));
+    assert!(up.at >= down.at);
+
+    tokio::time::timeout(Duration::from_secs(5), probe.stop()).await.unwrap();
+
+    // The startup connection and the two changes seen by the probe
+    let states: Vec<_> = manager.transitions().iter().map(|t| (t.from, t.to)).collect();
+    assert_eq!(
+        states,
+        [
+            (ConnectionState::Disconnected, ConnectionState::Connected),
+            (ConnectionState::Connected, ConnectionState::Disconnected),
+            (ConnectionState::Disconnected, ConnectionState::Connected),
+        ]
+    );
+
+    // A stopped probe checks nothing
+    healthy.store(false, Ordering::SeqCst);
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert_eq!(manager.state(), ConnectionState::Connected);
+}
+
+#[tokio::test]
+async fn test_health_probe_notifies_client() {
+    let (url, healthy) = toggled_clickhouse().await;
+    let manager = toggled_manager(&url);
+    manager.connect().await.unwrap();
+
+    let (mut input, server_input) = tokio::io::duplex(4096);
+    let (server_output, output) = tokio::io::duplex(4096);
+    let mut output = BufReader::new(output).lines();
+
+    let mut server = McpServer::new().with_connection(manager).with_health_interval(Duration::from_millis(10));
+    let serving = tokio::spawn(async move { server.serve(BufReader::new(server_input), server_output).await });
+
+    input.write_all(b"{\"jsonrpc\": \"2.0\", \"method\": \"initialize\", \"id\": 1}\n").await.unwrap();
+    assert_eq!(next_message(&mut output).await["result"]["capabilities"]["logging"], json!({}));
+    input.write_all(b"{\"jsonrpc\": \"2.0\", \"method\": \"initialized\"}\n").await.unwrap();
+    input
+        .write_all(b"{\"jsonrpc\": \"2.0\", \"method\": \"logging/setLevel\", \"params\": {\"level\": \"loud\"}, \"id\": 2}\n")
+        .await
+        .unwrap();
+    assert_eq!(next_message(&mut output).await["error"]["code"], -32602);
+
+    healthy.store(false, Ordering::SeqCst);
+    let down = next_message(&mut output).await;
+    assert_eq!(down["method"], "notifications/message");
+    assert_eq!(down["params"]["level"], "warning");
+    assert_eq!(down["params"]["data"]["to"], "disconnected");
+    assert!(down["params"]["data"]["message"].as_str().unwrap().starts_with("ClickHouse connection connected -> disconnected: "));
+
+    healthy.store(true, Ordering::SeqCst);
+    let up = next_message(&mut output).await;
+    assert_eq!((up["params"]["level"].as_str(), up["params"]["data"]["to"].as_str()), (Some("info"), Some("connected")));
+
+    // End of input stops the probe and the server
+    drop(input);
+    tokio::time::timeout(Duration::from_secs(5), serving).await.unwrap().unwrap().unwrap();
+}
@@ -0,0 +1,45 @@
+use mcp_test::{ddl_dependencies, order_ddl};
+
+fn names(tables: &[(String, String)]) -> Vec<&str> {
+    tables.iter().map(|(name, _)| name.as_str()).collect()
+}
+
+fn table(name: &str, ddl: &str) -> (String, String) {
+    (name.to_string(), ddl.to_string())
+}
+
+#[test]
+fn test_ddl_dependencies() {
+    let view = "CREATE MATERIALIZED VIEW web.daily TO web.daily_totals AS SELECT day, count() AS hits FROM events JOIN other.users USING user_id GROUP BY day";
+    assert_eq!(ddl_dependencies("web", "daily", view).into_iter().collect::<Vec<_>>(), ["daily_totals", "events"]);
+
+    let distributed = "CREATE TABLE web.events_all (`id` UInt64) ENGINE = Distributed('main', currentDatabase(), 'events', rand())";
+    assert_eq!(ddl_dependencies("web", "events_all", distributed).into_iter().collect::<Vec<_>>(), ["events"]);
+
+    let plain = "CREATE TABLE web.events (`id` UInt64) ENGINE = MergeTree ORDER BY id";
+    assert!(ddl_dependencies("web", "events", plain).is_empty());
+}
+
+#[test]
+fn test_order_ddl_puts_dependencies_first() {
+    let tables = vec![
+        table("a_view", "CREATE VIEW web.a_view AS SELECT * FROM web.z_events"),
+        table("b_mv", "CREATE MATERIALIZED VIEW web.b_mv TO web.c_totals AS SELECT count() AS n FROM web.z_events"),
+        table("c_totals", "CREATE TABLE web.c_totals (`n` UInt64) ENGINE = SummingMergeTree ORDER BY tuple()"),
+        table("d_all", "CREATE TABLE web.d_all (`id` UInt64) ENGINE = Distributed('main', 'web', 'z_events')"),
+        table("z_events", "CREATE TABLE web.z_events (`id` UInt64) ENGINE = MergeTree ORDER BY id"),
+    ];
+    assert_eq!(names(&order_ddl("web", tables)), ["c_totals", "z_events", "a_view", "b_mv", "d_all"]);
+}
+
+#[test]
+fn test_order_ddl_keeps_name_order_otherwise() {
+    let tables = vec![
+        table("b", "CREATE TABLE web.b (`id` UInt64) ENGINE = Memory"),
+        table("a", "CREATE VIEW web.a AS SELECT * FROM other.events"),
+        // A cycle can't be ordered; its tables come last by name
+        table("d", "CREATE VIEW web.d AS SELECT * FROM web.c"),
+        table("c", "CREATE VIEW web.c AS SELECT * FROM web.d"),
+    ];
+    assert_eq!(names(&order_ddl("web", tables)), ["a", "b", "c", "d"]);
+}
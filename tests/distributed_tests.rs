@@ -0,0 +1,152 @@
+use clickhouse::test::{handlers, Mock};
+use mcp_test::{
+    build_part_counts_query, parse_distributed_engine, ClickHouseClient, DistributedScope, DistributedTarget, MetricValue,
+    TableMetadata, TablePartCount,
+};
+
+fn target(cluster: &str, database: &str, table: &str, sharding_key: Option<&str>) -> DistributedTarget {
+    DistributedTarget {
+        cluster: cluster.to_string(),
+        database: database.to_string(),
+        table: table.to_string(),
+        sharding_key: sharding_key.map(str::to_string),
+    }
+}
+
+#[test]
+fn test_parse_distributed_engine_parameter_forms() {
+    let parse = |engine_full: &str| parse_distributed_engine(engine_full, "web");
+
+    assert_eq!(parse("Distributed('main', 'web', 'events_local')"), Some(target("main", "web", "events_local", None)));
+    assert_eq!(
+        parse("Distributed(main, analytics, events_local, rand())"),
+        Some(target("main", "analytics", "events_local", Some("rand()")))
+    );
+    assert_eq!(
+        parse("Distributed(`main`, \"web\", `events local`)"),
+        Some(target("main", "web", "events local", None))
+    );
+    // The database of the Distributed table itself
+    assert_eq!(parse("Distributed('main', currentDatabase(), 'events_local')"), Some(target("main", "web", "events_local", None)));
+}
+
+#[test]
+fn test_parse_distributed_engine_sharding_key_and_settings() {
+    let parse = |engine_full: &str| parse_distributed_engine(engine_full, "web");
+
+    // Commas inside calls, brackets and strings don't split parameters
+    assert_eq!(
+        parse("Distributed('main', 'web', 'events_local', cityHash64(user_id, [1, 2], 'a,b'), 'hot')"),
+        Some(target("main", "web", "events_local", Some("cityHash64(user_id, [1, 2], 'a,b')")))
+    );
+    assert_eq!(
+        parse("Distributed('main', 'web', 'events_local', sipHash64(')')) SETTINGS fsync_after_insert = 0, bytes_to_delay_insert = 1"),
+        Some(target("main", "web", "events_local", Some("sipHash64(')')")))
+    );
+}
+
+#[test]
+fn test_parse_distributed_engine_escapes() {
+    let parse = |engine_full: &str| parse_distributed_engine(engine_full, "web");
+
+    assert_eq!(parse(r"Distributed('it\'s', 'web', 'a\\b')"), Some(target("it's", "web", r"a\b", None)));
+    assert_eq!(parse("Distributed('it''s', 'web', `a``b`)"), Some(target("it's", "web", "a`b", None)));
+    assert_eq!(parse("Distributed('дом', 'web', 'события')"), Some(target("дом", "web", "события", None)));
+}
+
+#[test]
+fn test_parse_distributed_engine_rejects_other_input() {
+    let parse = |engine_full: &str| parse_distributed_engine(engine_full, "web");
+
+    assert_eq!(parse("MergeTree ORDER BY id"), None);
+    assert_eq!(parse("DistributedSomething('main', 'web', 'events')"), None);
+    assert_eq!(parse("Distributed('main', 'web')"), None);
+    assert_eq!(parse("Distributed('main', 'web', 'events'"), None);
+    assert_eq!(parse("Distributed('main', 'web', 'events)"), None);
+    assert_eq!(parse("Distributed('main', concat('w', 'eb'), 'events')"), None);
+    assert_eq!(parse("Distributed()"), None);
+}
+
+#[test]
+fn test_distributed_scope_label() {
+    let mut scope = DistributedScope {
+        table: "web.events".to_string(),
+        target: target("main", "web", "events_local", None),
+        cluster_wide: true,
+    };
+    assert_eq!(
+        scope.label(),
+        "web.events is Distributed; cluster-wide figures for web.events_local across all replicas of cluster 'main'"
+    );
+    scope.cluster_wide = false;
+    assert!(scope.label().contains("on this server's shard only"));
+}
+
+#[test]
+fn test_build_part_counts_query() {
+    let local = build_part_counts_query(false, "AND database = ? ");
+    assert!(local.contains("FROM system.parts WHERE active AND database = ? GROUP BY hostName(), "), "{}", local);
+
+    let cluster = build_part_counts_query(true, "");
+    assert!(cluster.contains("FROM clusterAllReplicas(?, system.parts) WHERE active GROUP BY "), "{}", cluster);
+}
+
+#[tokio::test]
+async fn test_insert_pressure_reads_local_table_across_cluster() {
+    let mock = Mock::new();
+    let client = ClickHouseClient::new(mock.url(), "web", "default", "");
+
+    mock.add(handlers::provide(vec![1u8]));
+    mock.add(handlers::provide(vec![1u8]));
+    mock.add(handlers::provide(vec![(
+        "Distributed".to_string(),
+        "Distributed('main', 'web', 'events_local', rand())".to_string(),
+    )]));
+    mock.add(handlers::provide(vec![TablePartCount {
+        database: "web".to_string(),
+        table: "events_local".to_string(),
+        active_parts: 40,
+        max_partition_parts: 12,
+    }]));
+    mock.add(handlers::provide(vec![
+        ("parts_to_delay_insert".to_string(), 1000u64),
+        ("parts_to_throw_insert".to_string(), 3000u64),
+    ]));
+    mock.add(handlers::provide(Vec::<(String, String, String)>::new()));
+    mock.add(handlers::provide(vec![MetricValue { metric: "DelayedInserts".to_string(), value: 0.0 }]));
+
+    let pressure = client.insert_pressure(Some("web"), Some("events")).await.unwrap();
+    assert_eq!((pressure.tables[0].table.as_str(), pressure.tables[0].max_partition_parts), ("events_local", 12));
+    let scope = pressure.distributed.unwrap();
+    assert_eq!(scope.target, target("main", "web", "events_local", Some("rand()")));
+    assert!(scope.cluster_wide);
+}
+
+#[tokio::test]
+async fn test_count_rows_approx_reads_local_table_across_cluster() {
+    let mock = Mock::new();
+    let client = ClickHouseClient::new(mock.url(), "web", "default", "");
+    let metadata = |name: &str, engine: &str| TableMetadata {
+        name: name.to_string(),
+        engine: engine.to_string(),
+        comment: "".to_string(),
+        sorting_key: "".to_string(),
+        partition_key: "".to_string(),
+        primary_key: "".to_string(),
+    };
+
+    mock.add(handlers::provide(vec![1u8]));
+    mock.add(handlers::provide(vec![1u8]));
+    mock.add(handlers::provide(vec![metadata("events", "Distributed")]));
+    mock.add(handlers::provide(vec![(
+        "Distributed".to_string(),
+        "Distributed('main', 'web', 'events_local', rand())".to_string(),
+    )]));
+    mock.add(handlers::provide(vec![metadata("events_local", "ReplicatedMergeTree")]));
+    mock.add(handlers::provide(vec![3_000_000u64]));
+
+    let estimate = client.count_rows_approx("web", "events").await.unwrap();
+    assert_eq!(estimate.rows, 3_000_000);
+    assert_eq!(estimate.distributed, Some(target("main", "web", "events_local", Some("rand()"))));
+    assert!(estimate.cluster_wide);
+}
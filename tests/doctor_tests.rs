@@ -0,0 +1,104 @@
+use clickhouse::test::{handlers, Mock};
+use mcp_test::cli::Command;
+use mcp_test::doctor::run_checks;
+use mcp_test::{CheckOutcome, ClickHouseClient, CliOptions, DatabaseInfo, DoctorCheck, DoctorReport};
+
+fn report(checks: Vec<DoctorCheck>) -> DoctorReport {
+    DoctorReport { target: "ClickHouse at http://ch:8123 as reader".to_string(), checks }
+}
+
+#[test]
+fn test_report_lists_checks_with_hints() {
+    let report = report(vec![
+        DoctorCheck::pass("connection", true, "SELECT 1 succeeded"),
+        DoctorCheck::fail("default database", true, "'analytics' not found", "create it"),
+        DoctorCheck::fail("system.columns", false, "not readable", "GRANT SELECT ON system.columns TO reader"),
+        DoctorCheck::skipped("latency", false),
+    ]);
+
+    assert_eq!(
+        report.to_string(),
+        "Checking ClickHouse at http://ch:8123 as reader\n\
+         \n\
+         [PASS] connection: SELECT 1 succeeded\n\
+         [FAIL] default database: 'analytics' not found\n\
+         \x20      hint: create it\n\
+         [WARN] system.columns: not readable\n\
+         \x20      hint: GRANT SELECT ON system.columns TO reader\n\
+         [SKIP] latency: skipped, an earlier check failed\n\
+         \n\
+         1 critical check(s) failed\n"
+    );
+    assert!(!report.passed());
+}
+
+#[test]
+fn test_report_summary_and_exit_status() {
+    let healthy = report(vec![DoctorCheck::pass("connection", true, "SELECT 1 succeeded")]);
+    assert!(healthy.passed());
+    assert!(healthy.to_string().ends_with("\nAll checks passed\n"));
+
+    // Failures of checks that are not critical don't fail the run
+    let warned = report(vec![
+        DoctorCheck::pass("connection", true, "SELECT 1 succeeded"),
+        DoctorCheck::fail("latency", false, "900 ms round trip", "run closer"),
+    ]);
+    assert!(warned.passed());
+    assert!(warned.to_string().ends_with("\nAll critical checks passed, 1 warning(s)\n"));
+
+    // Nor do skipped ones, unless they are critical
+    let skipped = report(vec![DoctorCheck::skipped("latency", false)]);
+    assert!(skipped.passed());
+    let skipped = report(vec![DoctorCheck::skipped("databases", true)]);
+    assert!(!skipped.passed());
+    assert!(skipped.to_string().ends_with("\nCritical checks were skipped\n"));
+}
+
+#[tokio::test]
+async fn test_run_checks_against_mock() {
+    let mock = Mock::new();
+    let client = ClickHouseClient::new(mock.url(), "analytics", "reader", "");
+
+    mock.add(handlers::provide(vec![1u8]));
+    mock.add(handlers::provide(vec![1u8]));
+    mock.add(handlers::provide(vec![
+        DatabaseInfo { name: "default".to_string() },
+        DatabaseInfo { name: "system".to_string() },
+    ]));
+    mock.add(handlers::provide(vec![1u8]));
+
+    let report = run_checks(&client).await;
+    let outcomes: Vec<(&str, CheckOutcome)> = report.checks.iter().map(|c| (c.name.as_str(), c.outcome)).collect();
+    assert_eq!(
+        outcomes,
+        [
+            ("connection", CheckOutcome::Pass),
+            ("latency", CheckOutcome::Pass),
+            ("databases", CheckOutcome::Pass),
+            ("default database", CheckOutcome::Fail),
+            ("system.columns", CheckOutcome::Pass),
+        ]
+    );
+    assert_eq!(report.checks[2].detail, "2 databases visible");
+    assert_eq!(
+        report.checks[3].hint.as_deref(),
+        Some("create it, or set CLICKHOUSE_DATABASE (--database) to one of: default, system")
+    );
+    assert!(!report.passed());
+}
+
+#[test]
+fn test_cli_parses_commands() {
+    let parse = |args: &[&str]| CliOptions::parse(args.iter().map(|a| a.to_string()));
+
+    assert_eq!(parse(&[]).unwrap().command, Command::Serve);
+    assert_eq!(parse(&["serve"]).unwrap().command, Command::Serve);
+
+    let options = parse(&["doctor", "--url", "http://ch:8123"]).unwrap();
+    assert_eq!(options.command, Command::Doctor);
+    assert_eq!(options.url.as_deref(), Some("http://ch:8123"));
+    assert_eq!(parse(&["--database=web", "doctor"]).unwrap().command, Command::Doctor);
+
+    assert!(parse(&["doctor", "serve"]).is_err());
+    assert!(parse(&["check"]).is_err());
+}
@@ -0,0 +1,214 @@
+//! End-to-end tests of the compiled binary: a scripted conversation is
+//! written to its stdin and every line it prints on stdout is checked, so
+//! the framing and wire format clients see are covered, not only the
+//! handlers. ClickHouse is a fake answering by the SQL it gets.
+
+mod common;
+
+use common::{read_request, url_decode};
+use serde_json::{json, Value};
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpListener;
+use tokio::process::Command;
+
+/// How long a whole conversation may take before the test fails.
+const CONVERSATION_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// A string as ClickHouse's RowBinary encodes it.
+fn row_binary_string(value: &str) -> Vec<u8> {
+    let mut encoded = Vec::new();
+    let mut length = value.len();
+    loop {
+        let byte = (length & 0x7f) as u8;
+        length >>= 7;
+        if length == 0 {
+            encoded.push(byte);
+            break;
+        }
+        encoded.push(byte | 0x80);
+    }
+    encoded.extend_from_slice(value.as_bytes());
+    encoded
+}
+
+/// A `system.columns` row as `get_table_schema` reads it.
+fn column_row(name: &str, r#type: &str, primary_key: bool) -> Vec<u8> {
+    let mut row: Vec<u8> = [name, r#type, "", "", ""].iter().flat_map(|value| row_binary_string(value)).collect();
+    row.extend_from_slice(&[0, u8::from(primary_key), u8::from(primary_key), 0]);
+    row
+}
+
+/// A fake ClickHouse with databases `default` and `shop`, where
+/// `shop.orders` is the only table. Every query gets the body of the first
+/// entry whose text it contains, and no rows otherwise.
+async fn scripted_clickhouse() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let script: Vec<(&str, Vec<u8>)> = vec![
+        ("SELECT name FROM system.databases ORDER BY name", [row_binary_string("default"), row_binary_string("shop")].concat()),
+        ("FROM system.databases WHERE name = 'shop'", vec![1]),
+        ("FROM system.databases WHERE name", vec![0]),
+        ("FROM system.tables WHERE database = 'shop' AND name = 'orders'", vec![1]),
+        ("FROM system.tables WHERE database = 'shop' AND name", vec![0]),
+        ("FROM system.columns", [column_row("id", "UInt64", true), column_row("status", "Enum8('new' = 1, 'paid' = 2)", false)].concat()),
+        ("SELECT 1", vec![1]),
+    ];
+
+    tokio::spawn(async move {
+        loop {
+            let Ok((stream, _)) = listener.accept().await else { return };
+            let script = script.clone();
+            tokio::spawn(async move {
+                let mut stream = BufReader::new(stream);
+                while let Some(request) = read_request(&mut stream).await {
+                    let request = url_decode(&request);
+                    let body = script
+                        .iter()
+                        .find(|(needle, _)| request.contains(needle))
+                        .map_or(&[][..], |(_, body)| body.as_slice());
+                    let head = format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n", body.len());
+                    if stream.get_mut().write_all(&[head.as_bytes(), body].concat()).await.is_err() {
+                        return;
+                    }
+                }
+            });
+        }
+    });
+
+    url
+}
+
+/// Runs the binary against `clickhouse_url`, writes `lines` to its stdin,
+/// closes it and returns what it printed on stdout, line by line. The
+/// binary is killed if it hasn't finished within [`CONVERSATION_TIMEOUT`].
+async fn converse(clickhouse_url: &str, lines: &[String]) -> Vec<String> {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_mcp-test"))
+        .args(["--ch-compression", "none"])
+        .env("CLICKHOUSE_URL", clickhouse_url)
+        .env("CLICKHOUSE_DATABASE", "shop")
+        .env("RUST_LOG", "off")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .kill_on_drop(true)
+        .spawn()
+        .unwrap();
+    let mut stdin = child.stdin.take().unwrap();
+    let stdout = child.stdout.take().unwrap();
+
+    let conversation = async {
+        for line in lines {
+            stdin.write_all(line.as_bytes()).await.unwrap();
+            stdin.write_all(b"\n").await.unwrap();
+        }
+        drop(stdin);
+
+        let mut output = Vec::new();
+        let mut stdout = BufReader::new(stdout).lines();
+        while let Some(line) = stdout.next_line().await.unwrap() {
+            output.push(line);
+        }
+        assert!(child.wait().await.unwrap().success(), "the server exited with an error");
+        output
+    };
+    tokio::time::timeout(CONVERSATION_TIMEOUT, conversation)
+        .await
+        .unwrap_or_else(|_| panic!("the server didn't answer within {:?}", CONVERSATION_TIMEOUT))
+}
+
+fn request(id: Value, method: &str, params: Value) -> String {
+    json!({"jsonrpc": "2.0", "id": id, "method": method, "params": params}).to_string()
+}
+
+fn tool_call(id: Value, name: &str, arguments: Value) -> String {
+    request(id, "tools/call", json!({"name": name, "arguments": arguments}))
+}
+
+/// The text of a successful tool call's single content block.
+fn tool_text(response: &Value) -> &str {
+    assert_eq!(response["result"]["isError"], false, "{}", response);
+    response["result"]["content"][0]["text"].as_str().unwrap()
+}
+
+#[tokio::test]
+async fn test_scripted_conversation_over_stdio() {
+    let url = scripted_clickhouse().await;
+    let lines = [
+        request(json!(1), "initialize", json!({"protocolVersion": "2024-11-05", "capabilities": {}, "clientInfo": {"name": "e2e", "version": "1"}})),
+        json!({"jsonrpc": "2.0", "method": "notifications/initialized"}).to_string(),
+        request(json!(2), "tools/list", json!({})),
+        tool_call(json!("three"), "list_databases", json!({})),
+        tool_call(json!(4), "get_table_schema", json!({"table": "orders"})),
+        tool_call(json!(5), "table_exists", json!({"database": "shop", "table": "missing"})),
+        tool_call(json!(6), "get_table_schema", json!({"database": "shop"})),
+        r#"{"jsonrpc": "2.0", "id": 7, "method": "tools/list""#.to_string(),
+        tool_call(json!(8), "no_such_tool", json!({})),
+    ];
+    let output = converse(&url, &lines).await;
+
+    let messages: Vec<Value> = output
+        .iter()
+        .map(|line| serde_json::from_str(line).unwrap_or_else(|e| panic!("not JSON ({}): {}", e, line)))
+        .collect();
+    for message in &messages {
+        assert_eq!(message["jsonrpc"], "2.0", "{}", message);
+    }
+    // Server notifications (log messages) may come in between; everything
+    // else answers a request, once, and never the notification
+    let responses: Vec<&Value> = messages.iter().filter(|message| message.get("method").is_none()).collect();
+    let ids: Vec<&Value> = responses.iter().map(|response| &response["id"]).collect();
+    assert_eq!(responses.len(), 8, "{:#?}", responses);
+    for id in [json!(1), json!(2), json!("three"), json!(4), json!(5), json!(6), json!(8)] {
+        assert_eq!(ids.iter().filter(|response_id| ***response_id == id).count(), 1, "{} in {:?}", id, ids);
+    }
+    let response = |id: Value| *responses.iter().find(|response| response["id"] == id).unwrap();
+
+    let initialize = response(json!(1));
+    assert_eq!(initialize["result"]["serverInfo"]["name"], "mcp-test", "{}", initialize);
+    assert!(initialize["result"]["capabilities"]["tools"].is_object(), "{}", initialize);
+
+    let tools = response(json!(2))["result"]["tools"].as_array().unwrap();
+    for name in ["list_databases", "get_table_schema", "table_exists"] {
+        assert!(tools.iter().any(|tool| tool["name"] == name && tool["inputSchema"]["type"] == "object"), "{} not listed", name);
+    }
+
+    assert_eq!(tool_text(response(json!("three"))), "Available databases:\n- default\n- shop\n");
+
+    let schema = response(json!(4));
+    let text = tool_text(schema);
+    assert!(text.starts_with("Schema for table 'shop.orders':\n"), "{}", text);
+    assert!(text.contains("- id: UInt64 [PRIMARY KEY, SORTING KEY]\n"), "{}", text);
+    assert!(text.contains("  values: \"new\" = 1, \"paid\" = 2\n"), "{}", text);
+    assert_eq!(schema["result"]["structuredContent"]["columns"][1]["enum_values"], json!([["new", 1], ["paid", 2]]));
+
+    assert_eq!(tool_text(response(json!(5))), "Table 'shop.missing' does not exist\n");
+
+    // A missing argument and an unknown tool are protocol errors
+    assert_eq!(response(json!(6))["error"]["code"], -32602);
+    assert_eq!(response(json!(8))["error"]["code"], -32602);
+    assert!(response(json!(8))["error"]["message"].as_str().unwrap().contains("no_such_tool"));
+
+    // The cut-off line can't be attributed to a request
+    let parse_error = response(Value::Null);
+    assert_eq!(parse_error["error"]["code"], -32700, "{}", parse_error);
+}
+
+#[tokio::test]
+async fn test_every_response_is_one_line() {
+    let url = scripted_clickhouse().await;
+    // Text ending in newlines must stay inside a single JSON line
+    let lines = [
+        request(json!(1), "initialize", json!({})),
+        tool_call(json!(2), "table_exists", json!({"database": "shop", "table": "orders"})),
+        tool_call(json!(3), "database_exists", json!({"database": "archive"})),
+    ];
+    let output = converse(&url, &lines).await;
+
+    let responses: Vec<Value> = output.iter().map(|line| serde_json::from_str(line).unwrap()).collect();
+    let text = |id: i64| responses.iter().find(|response| response["id"] == id).map(tool_text).unwrap().to_string();
+    assert_eq!(output.len(), 3, "{:#?}", output);
+    assert_eq!(text(2), "Table 'shop.orders' exists\n");
+    assert_eq!(text(3), "Database 'archive' does not exist\n");
+}
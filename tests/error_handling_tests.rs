@@ -1,5 +1,17 @@
-use mcp_test::{ClickHouseClient, ClickHouseError};
-use std::time::Duration;
+mod common;
+
+use clickhouse::test::{handlers, status, Mock};
+use common::answering_clickhouse;
+use mcp_test::{
+    http_status_from_message, is_retryable_status, retry_after_from_message, with_retry_budget, ClickHouseClient, ClickHouseError, ConnectionManager,
+    DatabaseInfo, JsonRpcRequest, McpServer, RetryPolicy, SelectLimits, TableInfo, DEFAULT_MAX_IDENTIFIER_LENGTH,
+};
+use serde_json::json;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpListener;
 
 #[tokio::test]
 async fn test_invalid_identifier_validation() {
@@ -24,24 +36,40 @@ async fn test_invalid_identifier_validation() {
 
 #[tokio::test]
 async fn test_long_identifier_validation() {
-    let client = ClickHouseClient::new(
-        "http://localhost:8123",
-        "default",
-        "default",
-        ""
-    );
+    // Nothing listens here, so names that pass validation fail to connect
+    let client = |max_length: Option<usize>| {
+        let client = ClickHouseClient::new("http://127.0.0.1:1", "default", "default", "")
+            .with_retry_config(0, Duration::from_millis(1));
+        match max_length {
+            Some(max_length) => client.with_max_identifier_length(max_length),
+            None => client,
+        }
+    };
+    let rejection = |result: Result<Vec<TableInfo>, ClickHouseError>| match result {
+        Err(ClickHouseError::InvalidIdentifier { reason, .. }) => Some(reason),
+        _ => None,
+    };
 
-    // Test identifier that's too long
-    let long_name = "a".repeat(65);
-    let result = client.list_tables(&long_name).await;
-    assert!(result.is_err());
-    match result.unwrap_err() {
+    // The default limit, right at the boundary
+    assert_eq!(DEFAULT_MAX_IDENTIFIER_LENGTH, 206);
+    assert_eq!(rejection(client(None).list_tables(&"a".repeat(206)).await), None);
+    let long_name = "a".repeat(207);
+    match client(None).list_tables(&long_name).await.unwrap_err() {
         ClickHouseError::InvalidIdentifier { identifier, reason } => {
             assert_eq!(identifier, long_name);
-            assert!(reason.contains("longer than 64 characters"));
+            assert_eq!(reason, "Identifier is 207 bytes long, the limit is 206 bytes");
         }
-        _ => panic!("Expected InvalidIdentifier error"),
+        e => panic!("Expected InvalidIdentifier error, got {:?}", e),
     }
+
+    // A configured limit, counted in bytes
+    assert_eq!(rejection(client(Some(64)).list_tables(&"a".repeat(64)).await), None);
+    assert_eq!(
+        rejection(client(Some(64)).list_tables(&"a".repeat(65)).await).as_deref(),
+        Some("Identifier is 65 bytes long, the limit is 64 bytes")
+    );
+    assert_eq!(rejection(client(Some(64)).list_tables(&"я".repeat(32)).await), None);
+    assert!(rejection(client(Some(64)).list_tables(&"я".repeat(33)).await).is_some());
 }
 
 #[tokio::test]
@@ -110,13 +138,35 @@ async fn test_valid_identifiers() {
     }
 }
 
+#[tokio::test]
+async fn test_strict_identifiers_reject_hyphens() {
+    let mock = Mock::new();
+    let permissive = ClickHouseClient::new(mock.url(), "default", "default", "");
+    let strict = ClickHouseClient::new(mock.url(), "default", "default", "").with_strict_identifiers(true);
+
+    mock.add(handlers::provide(vec![1u8]));
+    mock.add(handlers::provide(Vec::<TableInfo>::new()));
+    assert!(permissive.list_tables("valid-name").await.unwrap().is_empty());
+
+    for name in ["valid-name", "café", "1table"] {
+        match strict.list_tables(name).await {
+            Err(ClickHouseError::InvalidIdentifier { identifier, .. }) => assert_eq!(identifier, name),
+            other => panic!("Identifier '{}' should be invalid in strict mode, got: {:?}", name, other),
+        }
+    }
+
+    mock.add(handlers::provide(vec![1u8]));
+    mock.add(handlers::provide(Vec::<TableInfo>::new()));
+    assert!(strict.list_tables("_my_table2").await.is_ok());
+}
+
 #[tokio::test]
 async fn test_error_display_formatting() {
     let errors = vec![
         ClickHouseError::ConnectionFailed { message: "timeout".to_string() },
-        ClickHouseError::DatabaseNotFound { database: "test_db".to_string() },
-        ClickHouseError::TableNotFound { database: "test_db".to_string(), table: "test_table".to_string() },
-        ClickHouseError::PermissionDenied { operation: "SELECT".to_string() },
+        ClickHouseError::DatabaseNotFound { database: "test_db".to_string(), raw: None },
+        ClickHouseError::TableNotFound { database: "test_db".to_string(), table: "test_table".to_string(), raw: None },
+        ClickHouseError::PermissionDenied { operation: "SELECT".to_string(), raw: None },
         ClickHouseError::QueryTimeout { timeout: 30 },
         ClickHouseError::InvalidIdentifier { identifier: "123invalid".to_string(), reason: "starts with digit".to_string() },
     ];
@@ -126,10 +176,10 @@ async fn test_error_display_formatting() {
         assert!(!error_string.is_empty());
         // Each error should contain meaningful information
         match error {
-            ClickHouseError::DatabaseNotFound { database } => {
+            ClickHouseError::DatabaseNotFound { database, .. } => {
                 assert!(error_string.contains(&database));
             }
-            ClickHouseError::TableNotFound { database, table } => {
+            ClickHouseError::TableNotFound { database, table, .. } => {
                 assert!(error_string.contains(&database));
                 assert!(error_string.contains(&table));
             }
@@ -202,9 +252,369 @@ async fn test_nonexistent_database_error() {
     
     // Should be a DatabaseNotFound error
     match result.unwrap_err() {
-        ClickHouseError::DatabaseNotFound { database } => {
+        ClickHouseError::DatabaseNotFound { database, .. } => {
             assert_eq!(database, "nonexistent_database_12345");
         }
         other => panic!("Expected DatabaseNotFound, got: {:?}", other),
     }
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_readonly_violation_maps_to_write_permission_denied() {
+    let client = ClickHouseClient::new(
+        "http://localhost:8123",
+        "default",
+        "default",
+        ""
+    );
+
+    let error = clickhouse::error::Error::BadResponse(
+        "Code: 164. DB::Exception: reader: Cannot execute query in readonly mode. (READONLY) (version 24.3.1.1)".to_string(),
+    );
+    match client.convert_clickhouse_error(error) {
+        ClickHouseError::PermissionDenied { operation, raw } => assert_eq!((operation.as_str(), raw), ("write", None)),
+        other => panic!("Expected PermissionDenied, got: {:?}", other),
+    }
+
+    // Plain access errors keep the generic operation
+    let error = clickhouse::error::Error::BadResponse(
+        "Code: 497. DB::Exception: reader: Not enough privileges. Access denied".to_string(),
+    );
+    match client.convert_clickhouse_error(error) {
+        ClickHouseError::PermissionDenied { operation, .. } => assert_eq!(operation, "query"),
+        other => panic!("Expected PermissionDenied, got: {:?}", other),
+    }
+}
+
+#[test]
+fn test_missing_keeper_maps_to_keeper_not_configured() {
+    let client = ClickHouseClient::new(
+        "http://localhost:8123",
+        "default",
+        "default",
+        ""
+    );
+
+    let error = clickhouse::error::Error::BadResponse(
+        "Code: 225. DB::Exception: There is no ZooKeeper configuration in server config. (NO_ZOOKEEPER)".to_string(),
+    );
+    let error = client.convert_clickhouse_error(error);
+    assert!(matches!(error, ClickHouseError::KeeperNotConfigured { raw: None }));
+    assert_eq!(error.to_string(), "This server has no Keeper/ZooKeeper configured");
+}
+
+#[test]
+fn test_verbose_errors_keep_the_raw_message() {
+    let client = ClickHouseClient::new("http://localhost:8123", "default", "default", "").with_verbose_errors(true);
+    let messages = [
+        "Code: 164. DB::Exception: reader: Cannot execute query in readonly mode. (READONLY) (version 24.3.1.1)",
+        "Code: 497. DB::Exception: reader: Not enough privileges. To execute this query, it's necessary to have the grant SELECT(id) ON shop.orders. (ACCESS_DENIED)",
+        "Code: 81. DB::Exception: Database shopp doesn't exist. (UNKNOWN_DATABASE) (version 24.3.1.1)",
+        "Code: 60. DB::Exception: Table shop.order doesn't exist. (UNKNOWN_TABLE) (version 24.3.1.1)",
+        "Code: 225. DB::Exception: There is no ZooKeeper configuration in server config. (NO_ZOOKEEPER)",
+    ];
+
+    for message in messages {
+        let error = client.convert_clickhouse_error(clickhouse::error::Error::BadResponse(message.to_string()));
+        assert_eq!(error.raw(), Some(message), "{:?}", error);
+        // The mapped message itself is unchanged
+        assert!(!error.to_string().contains("DB::Exception"), "{}", error);
+
+        let wrapped = ClickHouseError::RetryBudgetExhausted { attempts: 3, operations: 1, last_error: Box::new(error) };
+        assert_eq!(wrapped.raw(), Some(message));
+    }
+
+    // Off by default
+    let quiet = ClickHouseClient::new("http://localhost:8123", "default", "default", "");
+    let error = quiet.convert_clickhouse_error(clickhouse::error::Error::BadResponse(messages[1].to_string()));
+    assert!(matches!(error, ClickHouseError::PermissionDenied { raw: None, .. }));
+    assert_eq!(error.raw(), None);
+}
+
+#[tokio::test]
+async fn test_verbose_errors_in_tool_results() {
+    const ACCESS_DENIED: &str = "Code: 497. DB::Exception: reader: Not enough privileges. To execute this query, it's necessary to have the grant SHOW DATABASES ON *.*. (ACCESS_DENIED) (version 24.3.1.1)\n";
+    let (url, _) = answering_clickhouse(|query| {
+        if query.starts_with("SELECT 1 ") {
+            ("200 OK", vec![1])
+        } else {
+            ("500 Internal Server Error", ACCESS_DENIED.as_bytes().to_vec())
+        }
+    })
+    .await;
+
+    for verbose in [false, true] {
+        let client = ClickHouseClient::new(&url, "default", "reader", "").with_verbose_errors(verbose);
+        let manager = Arc::new(ConnectionManager::new(client));
+        manager.connect().await.unwrap();
+        let mut server = McpServer::new().with_connection(manager);
+
+        let request: JsonRpcRequest = serde_json::from_value(json!({
+            "jsonrpc": "2.0",
+            "method": "tools/call",
+            "params": {"name": "list_databases"},
+            "id": 1
+        }))
+        .unwrap();
+        let result = server.handle_request(request).await.unwrap().result.unwrap();
+
+        assert_eq!(result["isError"], true);
+        let text = result["content"][0]["text"].as_str().unwrap();
+        assert!(text.starts_with("Permission denied: Permission denied for operation: query"), "{}", text);
+        if verbose {
+            assert!(text.ends_with("\nClickHouse error: Code: 497. DB::Exception: reader: Not enough privileges. To execute this query, it's necessary to have the grant SHOW DATABASES ON *.*. (ACCESS_DENIED) (version 24.3.1.1)"), "{}", text);
+            let data = &result["structuredContent"];
+            assert_eq!(data["error"], "Permission denied: Permission denied for operation: query");
+            assert!(data["raw"].as_str().unwrap().contains("the grant SHOW DATABASES ON *.*"));
+        } else {
+            assert!(!text.contains("ClickHouse error:"), "{}", text);
+            assert!(result.get("structuredContent").is_none());
+        }
+    }
+}
+
+#[test]
+fn test_quota_exceeded_maps_to_quota_error_with_reset_time() {
+    let client = ClickHouseClient::new(
+        "http://localhost:8123",
+        "default",
+        "default",
+        ""
+    );
+
+    let message = "Code: 201. DB::Exception: Quota for user `reader` for 3600s has been exceeded: read_rows = 1000001/1000000. \
+        Interval will end at 2024-05-01 14:00:00. Name of quota template: `hourly`. (QUOTA_EXCEEDED) (version 24.3.1.1 (official build))";
+    let error = clickhouse::error::Error::BadResponse(message.to_string());
+    // Retrying can't help before the interval ends
+    assert!(!client.is_retryable_error(&error));
+    match client.convert_clickhouse_error(error) {
+        ClickHouseError::QuotaExceeded { message, resets_at } => {
+            assert!(message.contains("read_rows = 1000001/1000000"));
+            assert_eq!(resets_at.unwrap().to_string(), "2024-05-01 14:00:00");
+        }
+        other => panic!("Expected QuotaExceeded, got: {:?}", other),
+    }
+
+    let error = clickhouse::error::Error::BadResponse(
+        "Code: 201. DB::Exception: Quota for user `reader` has been exceeded: queries = 101/100. (QUOTA_EXCEEDED)".to_string(),
+    );
+    assert!(matches!(client.convert_clickhouse_error(error), ClickHouseError::QuotaExceeded { resets_at: None, .. }));
+}
+
+#[test]
+fn test_bad_responses_are_retried_by_http_status() {
+    let client = ClickHouseClient::new(
+        "http://localhost:8123",
+        "default",
+        "default",
+        ""
+    );
+    let retryable = |message: &str| client.is_retryable_error(&clickhouse::error::Error::BadResponse(message.to_string()));
+
+    // Transient statuses, as reported by the driver, a proxy's reason phrase or its error page
+    for message in [
+        "429 Too Many Requests",
+        "500 Internal Server Error",
+        "502 Bad Gateway",
+        "503 Service Unavailable",
+        "504 Gateway Timeout",
+        "Service Unavailable",
+        "<html><head><title>503 Service Temporarily Unavailable</title></head></html>",
+    ] {
+        assert!(retryable(message), "{} should be retried", message);
+    }
+
+    // Client errors and ClickHouse exceptions fail immediately
+    for message in [
+        "400 Bad Request",
+        "401 Unauthorized",
+        "403 Forbidden",
+        "404 Not Found",
+        "Forbidden",
+        "<html><head><title>403 Forbidden</title></head></html>",
+        "Code: 516. DB::Exception: default: Authentication failed. (AUTHENTICATION_FAILED)",
+        "Code: 62. DB::Exception: Syntax error: failed at position 1. (SYNTAX_ERROR)",
+    ] {
+        assert!(!retryable(message), "{} should not be retried", message);
+    }
+
+    assert_eq!(http_status_from_message("  503 Service Unavailable "), Some(503));
+    assert_eq!(http_status_from_message("5030 items"), None);
+    assert!(is_retryable_status(503) && !is_retryable_status(403));
+}
+
+#[tokio::test]
+async fn test_service_unavailable_is_retried() {
+    let mock = Mock::new();
+    let client = ClickHouseClient::new(mock.url(), "default", "default", "")
+        .with_retry_config(1, Duration::from_millis(1));
+
+    mock.add(handlers::failure(status::SERVICE_UNAVAILABLE));
+    mock.add(handlers::provide(vec![DatabaseInfo { name: "default".to_string() }]));
+    assert_eq!(client.list_databases().await.unwrap()[0].name, "default");
+
+    mock.add(handlers::failure(status::FORBIDDEN));
+    mock.add(handlers::provide(vec![DatabaseInfo { name: "default".to_string() }]));
+    assert!(client.list_databases().await.is_err());
+    // The handler left over from the unretried call answers the next one
+    assert_eq!(client.list_databases().await.unwrap()[0].name, "default");
+}
+
+fn unreachable_url() -> String {
+    // Bind and release a port so connections to it are refused
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    format!("http://{}", listener.local_addr().unwrap())
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_retry_budget_is_shared_across_operations() {
+    let client = ClickHouseClient::new(&unreachable_url(), "default", "default", "")
+        .with_retry_config(10, Duration::from_millis(100));
+
+    let result = with_retry_budget(Duration::from_secs(1), async {
+        // Retries after 100, 200 and 400ms; the next 800ms delay would overrun the budget
+        let first = client.list_databases().await;
+        // The remaining 300ms allow one retry after 100ms; the next 200ms delay would overrun
+        let second = client.list_databases().await;
+        (first, second)
+    })
+    .await;
+
+    match result {
+        (
+            Err(ClickHouseError::RetryBudgetExhausted { attempts: 4, operations: 1, last_error }),
+            Err(ClickHouseError::RetryBudgetExhausted { attempts: 6, operations: 2, .. }),
+        ) => assert!(matches!(*last_error, ClickHouseError::NetworkError { .. })),
+        other => panic!("Expected exhausted retry budgets, got: {:?}", other),
+    }
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_without_retry_budget_retries_are_unchanged() {
+    let client = ClickHouseClient::new(&unreachable_url(), "default", "default", "")
+        .with_retry_config(2, Duration::from_secs(60));
+
+    let started = tokio::time::Instant::now();
+    match client.list_databases().await {
+        Err(ClickHouseError::NetworkError { .. }) => {}
+        other => panic!("Expected NetworkError, got: {:?}", other),
+    }
+    assert!(started.elapsed() >= Duration::from_secs(180));
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_selects_are_not_retried_after_network_errors() {
+    let client = ClickHouseClient::new(&unreachable_url(), "default", "default", "")
+        .with_retry_config(2, Duration::from_secs(60));
+
+    let started = tokio::time::Instant::now();
+    match client.execute_select("SELECT * FROM web.events", &SelectLimits::default()).await {
+        Err(ClickHouseError::NetworkError { .. }) => {}
+        other => panic!("Expected NetworkError, got: {:?}", other),
+    }
+    assert!(started.elapsed() < Duration::from_secs(60));
+}
+
+#[tokio::test]
+async fn test_selects_are_retried_after_error_responses() {
+    let mock = Mock::new();
+    let client = ClickHouseClient::new(mock.url(), "default", "default", "")
+        .with_retry_config(1, Duration::from_millis(1));
+
+    mock.add(handlers::failure(status::SERVICE_UNAVAILABLE));
+    mock.add(handlers::provide(vec!["{\"id\":1}".to_string()]));
+    let result = client.execute_select("SELECT id FROM web.events", &SelectLimits::default()).await.unwrap();
+    assert_eq!(result.rows.len(), 1);
+}
+
+#[test]
+fn test_retry_policy_allows() {
+    let response = clickhouse::error::Error::BadResponse("503 Service Unavailable".to_string());
+    let network = clickhouse::error::Error::Network("connection reset".into());
+    assert!(RetryPolicy::Always.allows(&response) && RetryPolicy::Always.allows(&network));
+    assert!(RetryPolicy::LightOnly.allows(&response));
+    assert!(!RetryPolicy::LightOnly.allows(&network));
+    assert_eq!(RetryPolicy::default(), RetryPolicy::Always);
+}
+
+#[test]
+fn test_retry_after_from_message() {
+    assert_eq!(retry_after_from_message("429 Too Many Requests\nRetry-After: 5"), Some(Duration::from_secs(5)));
+    assert_eq!(retry_after_from_message("<p>Slow down</p>\nretry-after:  12 \n"), Some(Duration::from_secs(12)));
+    assert_eq!(retry_after_from_message("Retry-After: Wed, 21 Oct 2015 07:28:00 GMT"), Some(Duration::ZERO));
+    let later = (chrono::Utc::now() + chrono::Duration::seconds(90)).to_rfc2822();
+    let delay = retry_after_from_message(&format!("Retry-After: {}", later)).unwrap();
+    assert!(delay > Duration::from_secs(80) && delay <= Duration::from_secs(90), "{:?}", delay);
+
+    assert_eq!(retry_after_from_message("429 Too Many Requests"), None);
+    assert_eq!(retry_after_from_message("Retry-After: soon"), None);
+}
+
+/// A fake ClickHouse behind a proxy that rejects the first query with a 429
+/// whose body repeats its `Retry-After`, then lists one database.
+async fn rate_limited_clickhouse(retry_after: &str) -> (String, Arc<AtomicUsize>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let requests = Arc::new(AtomicUsize::new(0));
+
+    let counted = Arc::clone(&requests);
+    let retry_after = retry_after.to_string();
+    let body = format!("429 Too Many Requests\nRetry-After: {}", retry_after);
+    tokio::spawn(async move {
+        loop {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (counted, retry_after, body) = (Arc::clone(&counted), retry_after.clone(), body.clone());
+            tokio::spawn(async move {
+                let mut stream = BufReader::new(stream);
+                loop {
+                    // Queries are short GET requests: read up to the blank line
+                    let mut line = String::new();
+                    while line != "\r\n" {
+                        line.clear();
+                        if stream.read_line(&mut line).await.unwrap_or(0) == 0 {
+                            return;
+                        }
+                    }
+                    let response = if counted.fetch_add(1, Ordering::SeqCst) == 0 {
+                        format!(
+                            "HTTP/1.1 429 Too Many Requests\r\nRetry-After: {}\r\nContent-Length: {}\r\n\r\n{}",
+                            retry_after,
+                            body.len(),
+                            body
+                        )
+                        .into_bytes()
+                    } else {
+                        b"HTTP/1.1 200 OK\r\nContent-Length: 8\r\n\r\n\x07default".to_vec()
+                    };
+                    stream.get_mut().write_all(&response).await.unwrap();
+                }
+            });
+        }
+    });
+
+    (url, requests)
+}
+
+#[tokio::test]
+async fn test_rate_limited_query_waits_for_retry_after() {
+    let (url, requests) = rate_limited_clickhouse("1").await;
+    // The backoff alone would retry after 1ms
+    let client = ClickHouseClient::new(&url, "default", "default", "").with_retry_config(3, Duration::from_millis(1));
+
+    let started = Instant::now();
+    assert_eq!(client.list_databases().await.unwrap()[0].name, "default");
+    assert!(started.elapsed() >= Duration::from_secs(1), "{:?}", started.elapsed());
+    assert_eq!(requests.load(Ordering::SeqCst), 2);
+
+    // Waits are capped by the maximum delay
+    let (url, requests) = rate_limited_clickhouse("3600").await;
+    let client = ClickHouseClient::new(&url, "default", "default", "")
+        .with_retry_config(3, Duration::from_millis(1))
+        .with_max_retry_delay(Duration::from_millis(200));
+
+    let started = Instant::now();
+    assert_eq!(client.list_databases().await.unwrap()[0].name, "default");
+    let elapsed = started.elapsed();
+    assert!(elapsed >= Duration::from_millis(200) && elapsed < Duration::from_secs(10), "{:?}", elapsed);
+    assert_eq!(requests.load(Ordering::SeqCst), 2);
+}
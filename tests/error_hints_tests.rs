@@ -0,0 +1,132 @@
+mod common;
+
+use common::{read_request, url_decode};
+use mcp_test::error_hints::{error_code, hint_for_code};
+use mcp_test::{explain_error, ClickHouseClient, ClickHouseError, ConnectionManager, JsonRpcRequest, McpServer, ERROR_HINTS};
+use serde_json::json;
+use std::sync::Arc;
+use tokio::io::{AsyncWriteExt, BufReader};
+use tokio::net::TcpListener;
+
+const MEMORY_LIMIT: &str = "Code: 241. DB::Exception: Memory limit (for query) exceeded: would use 9.31 GiB \
+     (attempt to allocate chunk of 4194304 bytes), maximum: 9.31 GiB. (MEMORY_LIMIT_EXCEEDED) (version 24.3.1.1)\n";
+
+fn query_failed(message: &str) -> ClickHouseError {
+    ClickHouseError::QueryFailed { message: format!("bad response: {}", message) }
+}
+
+#[test]
+fn test_error_hints_cover_the_curated_codes() {
+    let codes: Vec<(u32, &str)> = ERROR_HINTS.iter().map(|hint| (hint.code, hint.name)).collect();
+    assert_eq!(
+        codes,
+        [
+            (43, "ILLEGAL_TYPE_OF_ARGUMENT"),
+            (53, "TYPE_MISMATCH"),
+            (159, "TIMEOUT_EXCEEDED"),
+            (164, "READONLY"),
+            (202, "TOO_MANY_SIMULTANEOUS_QUERIES"),
+            (241, "MEMORY_LIMIT_EXCEEDED"),
+            (252, "TOO_MANY_PARTS"),
+            (386, "NO_COMMON_TYPE"),
+        ]
+    );
+
+    // Each entry is found from a message in ClickHouse's own format
+    for hint in ERROR_HINTS {
+        let error = query_failed(&format!("Code: {}. DB::Exception: something. ({}) (version 24.3.1.1)", hint.code, hint.name));
+        assert_eq!(explain_error(&error), Some(hint), "{}", hint.name);
+        assert!(!hint.hint.is_empty() && !hint.hint.ends_with('.'), "{}", hint.name);
+    }
+}
+
+#[test]
+fn test_error_code_parsing() {
+    assert_eq!(error_code(MEMORY_LIMIT), Some(241));
+    assert_eq!(error_code("bad response: Code: 62. DB::Exception: Syntax error"), Some(62));
+    assert_eq!(error_code("Code: . DB::Exception"), None);
+    assert_eq!(error_code("Service Unavailable"), None);
+
+    assert_eq!(hint_for_code(62), None);
+    assert_eq!(explain_error(&query_failed("Code: 62. DB::Exception: Syntax error")), None);
+}
+
+#[test]
+fn test_explain_error_by_kind() {
+    assert_eq!(explain_error(&ClickHouseError::QueryTimeout { timeout: 30 }).unwrap().code, 159);
+    assert_eq!(explain_error(&ClickHouseError::PermissionDenied { operation: "write".to_string(), raw: None }).unwrap().code, 164);
+    assert_eq!(explain_error(&ClickHouseError::PermissionDenied { operation: "query".to_string(), raw: None }), None);
+
+    // Wrapped errors keep their code in the message
+    let exhausted = ClickHouseError::RetryBudgetExhausted {
+        attempts: 3,
+        operations: 1,
+        last_error: Box::new(query_failed("Code: 202. DB::Exception: Too many simultaneous queries. Maximum: 100")),
+    };
+    assert_eq!(explain_error(&exhausted).unwrap().name, "TOO_MANY_SIMULTANEOUS_QUERIES");
+}
+
+/// A fake ClickHouse that answers the health check and fails everything
+/// else with a memory limit error.
+async fn memory_starved_clickhouse() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+
+    tokio::spawn(async move {
+        loop {
+            let (stream, _) = listener.accept().await.unwrap();
+            tokio::spawn(async move {
+                let mut stream = BufReader::new(stream);
+                while let Some(request) = read_request(&mut stream).await {
+                    let target = request.split_whitespace().nth(1).unwrap_or_default().to_string();
+                    let query = target
+                        .split(['?', '&'])
+                        .find_map(|pair| pair.strip_prefix("query="))
+                        .map(url_decode)
+                        .unwrap_or_default();
+
+                    let (status, body) = if query.contains("SELECT 1") {
+                        ("200 OK", vec![1])
+                    } else {
+                        ("500 Internal Server Error", MEMORY_LIMIT.as_bytes().to_vec())
+                    };
+                    let head = format!("HTTP/1.1 {}\r\nContent-Length: {}\r\n\r\n", status, body.len());
+                    stream.get_mut().write_all(head.as_bytes()).await.unwrap();
+                    stream.get_mut().write_all(&body).await.unwrap();
+                }
+            });
+        }
+    });
+
+    url
+}
+
+#[tokio::test]
+async fn test_tool_errors_carry_hints() {
+    let url = memory_starved_clickhouse().await;
+    let client = ClickHouseClient::new(&url, "default", "default", "").with_retry_config(0, std::time::Duration::from_millis(1));
+    let manager = Arc::new(ConnectionManager::new(client));
+    manager.connect().await.unwrap();
+    let mut server = McpServer::new().with_connection(manager);
+
+    let request: JsonRpcRequest = serde_json::from_value(json!({
+        "jsonrpc": "2.0",
+        "method": "tools/call",
+        "params": {"name": "list_databases"},
+        "id": 1
+    }))
+    .unwrap();
+    let result = server.handle_request(request).await.unwrap().result.unwrap();
+
+    assert_eq!(result["isError"], true);
+    let text = result["content"][0]["text"].as_str().unwrap();
+    // The original message stays intact, with the hint on its own line
+    assert!(text.contains("Code: 241. DB::Exception: Memory limit (for query) exceeded"), "{}", text);
+    assert!(text.ends_with("\nHint: the query used too much memory; add a WHERE filter on the partition key, select fewer columns or group by fewer keys"), "{}", text);
+
+    let data = &result["structuredContent"];
+    assert_eq!(data["code"], 241);
+    assert_eq!(data["name"], "MEMORY_LIMIT_EXCEEDED");
+    assert!(data["error"].as_str().unwrap().contains("Memory limit (for query) exceeded"));
+    assert!(!data["error"].as_str().unwrap().contains("Hint:"));
+}
@@ -0,0 +1,140 @@
+//! Golden tests for tool text output. Each formatter's output is compared
+//! with a file in tests/golden/; after an intended format change, bless the
+//! new output with `UPDATE_GOLDEN=1 cargo test --test format_tests` and
+//! review the diff of tests/golden/ before committing.
+
+mod common;
+
+use mcp_test::{
+    format_all_tables, format_databases, format_schema_summary, format_table_schema, format_tables, summarize_schema, ColumnInfo, DatabaseInfo,
+    TableInfo,
+};
+use std::path::PathBuf;
+
+fn assert_golden(name: &str, actual: &str) {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/golden").join(format!("{}.txt", name));
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        std::fs::write(&path, actual).unwrap();
+        return;
+    }
+
+    let expected = std::fs::read_to_string(&path)
+        .unwrap_or_else(|e| panic!("can't read {}: {}; bless it with UPDATE_GOLDEN=1", path.display(), e));
+    assert!(
+        actual == expected,
+        "output differs from {}; if the change is intended, bless it with UPDATE_GOLDEN=1\n--- expected\n{}\n--- actual\n{}",
+        path.display(),
+        expected,
+        actual
+    );
+}
+
+fn database(name: &str) -> DatabaseInfo {
+    DatabaseInfo { name: name.to_string() }
+}
+
+fn table(database: &str, name: &str, engine: &str) -> TableInfo {
+    TableInfo { name: name.to_string(), database: database.to_string(), engine: engine.to_string() }
+}
+
+fn column(name: &str, r#type: &str, comment: &str, keys: [u8; 4]) -> ColumnInfo {
+    let [primary, sorting, partition, sampling] = keys;
+    ColumnInfo {
+        comment: comment.to_string(),
+        is_in_partition_key: partition,
+        is_in_sorting_key: sorting,
+        is_in_primary_key: primary,
+        is_in_sampling_key: sampling,
+        ..common::column(name, r#type)
+    }
+    .with_enum_values()
+    .with_timezone(Some("America/New_York"))
+}
+
+#[test]
+fn test_list_databases_golden() {
+    assert_golden(
+        "list_databases",
+        &format_databases(&[database("default"), database("system"), database("аналитика"), database("my db")]),
+    );
+    assert_golden("list_databases_empty", &format_databases(&[]));
+}
+
+#[test]
+fn test_list_tables_golden() {
+    assert_golden(
+        "list_tables",
+        &format_tables(
+            "web",
+            &[
+                table("web", "events", "MergeTree"),
+                table("web", "events_all", "Distributed"),
+                // Listed without access to system.tables
+                table("web", "legacy", ""),
+                table("web", "события", "ReplacingMergeTree"),
+            ],
+        ),
+    );
+    assert_golden("list_tables_empty", &format_tables("empty", &[]));
+}
+
+#[test]
+fn test_list_all_tables_golden() {
+    assert_golden(
+        "list_all_tables",
+        &format_all_tables(&[
+            table("analytics", "daily", "SummingMergeTree"),
+            table("analytics", "sessions", "MergeTree"),
+            table("web", "events", "MergeTree"),
+            table("аналитика", "отчёт", "Log"),
+        ], false),
+    );
+    assert_golden("list_all_tables_empty", &format_all_tables(&[], false));
+    assert_golden(
+        "list_all_tables_truncated",
+        &format_all_tables(&[table("analytics", "daily", "SummingMergeTree"), table("web", "events", "MergeTree")], true),
+    );
+}
+
+#[test]
+fn test_get_table_schema_golden() {
+    let long_comment = "Identifier of the user who triggered the event. ".repeat(8);
+    assert_golden(
+        "get_table_schema",
+        &format_table_schema(
+            "web",
+            "events",
+            &[
+                column("id", "UInt64", "", [1, 1, 1, 1]),
+                column("created_at", "DateTime64(3, 'UTC')", "When the event happened", [0, 1, 1, 0]),
+                column("user_id", "UInt64", long_comment.trim_end(), [0, 0, 0, 1]),
+                column("страна", "LowCardinality(String)", "Код страны 🌍", [1, 0, 0, 0]),
+                column("payload", "Nullable(String)", "", [0, 0, 0, 0]),
+                column("status", "Enum8('active' = 1, 'it\\'s paused' = 2, 'closed' = -1)", "", [0, 0, 0, 0]),
+                column("shipped_at", "Nullable(DateTime)", "", [0, 0, 0, 0]),
+                column("delivered_at", "DateTime64(6, 'Europe/Stockholm')", "", [0, 0, 0, 0]),
+                column("due_on", "Date", "", [0, 0, 0, 0]),
+            ],
+        ),
+    );
+    assert_golden("get_table_schema_empty", &format_table_schema("web", "empty", &[]));
+}
+
+#[test]
+fn test_get_table_schema_compact_golden() {
+    let mut columns = vec![
+        column("ts", "DateTime", "", [1, 1, 1, 0]),
+        column("host", "LowCardinality(String)", "Reporting host", [1, 1, 0, 0]),
+    ];
+    for metric in ["cpu", "mem", "disk"] {
+        for i in 0..40 {
+            columns.push(column(&format!("{}_{}", metric, i), "Float64", "", [0, 0, 0, 0]));
+        }
+    }
+    for name in ["a", "b", "c", "d", "e", "f", "g", "h", "i", "j"] {
+        columns.push(column(name, "Nullable(String)", "", [0, 0, 0, 0]));
+    }
+    assert_golden("get_table_schema_compact", &format_schema_summary("metrics", "hosts", &summarize_schema(&columns, 4)));
+    // Everything fits
+    assert_golden("get_table_schema_compact_small", &format_schema_summary("web", "tiny", &summarize_schema(&columns[..2], 20)));
+}
@@ -0,0 +1,182 @@
+#![cfg(feature = "grpc")]
+
+use mcp_test::grpc::{decode_rows, exception_error, status_error, GrpcTransport, QueryException, QueryInfo, QueryResult};
+use mcp_test::{ClickHouseClient, ClickHouseError, DatabaseInfo, DEFAULT_USER_AGENT};
+use std::collections::VecDeque;
+use std::convert::Infallible;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tonic::codec::ProstCodec;
+use tonic::codegen::{http, BoxFuture, Context, Poll, Service};
+
+/// A fake ClickHouse gRPC interface answering `ExecuteQuery` with queued
+/// replies and recording the queries it got.
+#[derive(Clone, Default)]
+struct FakeClickHouse {
+    replies: Arc<Mutex<VecDeque<Result<QueryResult, tonic::Status>>>>,
+    queries: Arc<Mutex<Vec<QueryInfo>>>,
+    user_agents: Arc<Mutex<Vec<String>>>,
+}
+
+impl tonic::server::NamedService for FakeClickHouse {
+    const NAME: &'static str = "clickhouse.grpc.ClickHouse";
+}
+
+impl tonic::server::UnaryService<QueryInfo> for FakeClickHouse {
+    type Response = QueryResult;
+    type Future = BoxFuture<tonic::Response<QueryResult>, tonic::Status>;
+
+    fn call(&mut self, request: tonic::Request<QueryInfo>) -> Self::Future {
+        let user_agent = request.metadata().get("user-agent").and_then(|value| value.to_str().ok()).unwrap_or_default();
+        self.user_agents.lock().unwrap().push(user_agent.to_string());
+        self.queries.lock().unwrap().push(request.into_inner());
+        let reply = self.replies.lock().unwrap().pop_front().expect("no reply queued");
+        Box::pin(async move { reply.map(tonic::Response::new) })
+    }
+}
+
+impl Service<http::Request<tonic::body::BoxBody>> for FakeClickHouse {
+    type Response = http::Response<tonic::body::BoxBody>;
+    type Error = Infallible;
+    type Future = BoxFuture<Self::Response, Infallible>;
+
+    fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Infallible>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: http::Request<tonic::body::BoxBody>) -> Self::Future {
+        let service = self.clone();
+        Box::pin(async move {
+            let mut grpc = tonic::server::Grpc::new(ProstCodec::<QueryResult, QueryInfo>::default());
+            Ok(grpc.unary(service, request).await)
+        })
+    }
+}
+
+impl FakeClickHouse {
+    fn reply(&self, output: &str) {
+        self.replies.lock().unwrap().push_back(Ok(QueryResult { output: output.as_bytes().to_vec(), ..QueryResult::default() }));
+    }
+
+    fn fail(&self, reply: Result<QueryResult, tonic::Status>) {
+        self.replies.lock().unwrap().push_back(reply);
+    }
+
+    /// Serves on a free local port, returning the endpoint.
+    async fn start(&self) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let endpoint = format!("http://{}", listener.local_addr().unwrap());
+        let incoming = tonic::codegen::tokio_stream::wrappers::TcpListenerStream::new(listener);
+        let router = tonic::transport::Server::builder().add_service(self.clone());
+        tokio::spawn(router.serve_with_incoming(incoming));
+        endpoint
+    }
+}
+
+#[tokio::test]
+async fn test_introspection_over_grpc() {
+    let fake = FakeClickHouse::default();
+    let endpoint = fake.start().await;
+    let client = ClickHouseClient::new_grpc(&endpoint, "shop", "reader", "s3cret");
+
+    fake.reply("[1]\n");
+    client.health_check().await.unwrap();
+    fake.reply("[\"default\"]\n[\"shop\"]\n");
+    let databases: Vec<String> = client.list_databases().await.unwrap().into_iter().map(|DatabaseInfo { name }| name).collect();
+    assert_eq!(databases, ["default", "shop"]);
+
+    let queries = fake.queries.lock().unwrap();
+    let query = &queries[1];
+    assert_eq!(query.query, "SELECT name FROM system.databases ORDER BY name");
+    assert_eq!((query.database.as_str(), query.user_name.as_str(), query.password.as_str()), ("shop", "reader", "s3cret"));
+    assert_eq!(query.output_format, "JSONCompactEachRow");
+    assert_eq!(query.settings.get("readonly").map(String::as_str), Some("2"));
+
+    let config = client.effective_config();
+    assert!(config.contains(&("transport", "grpc".to_string())));
+    assert!(config.contains(&("compression", "none".to_string())));
+    assert!(config.iter().all(|(_, value)| !value.contains("s3cret")));
+    // tonic appends its own name to the user agent
+    assert!(fake.user_agents.lock().unwrap().iter().all(|user_agent| user_agent.starts_with(DEFAULT_USER_AGENT)));
+}
+
+#[tokio::test]
+async fn test_user_agent_over_grpc() {
+    let fake = FakeClickHouse::default();
+    let endpoint = fake.start().await;
+    let client = ClickHouseClient::new_grpc(&endpoint, "default", "default", "").with_user_agent("acme-analytics/2.1");
+
+    fake.reply("[1]\n");
+    client.health_check().await.unwrap();
+    assert!(fake.user_agents.lock().unwrap()[0].starts_with("acme-analytics/2.1"));
+
+    let client = ClickHouseClient::new_grpc(&endpoint, "default", "default", "").with_user_agent("bad\nagent");
+    assert!(matches!(client.health_check().await, Err(ClickHouseError::QueryFailed { .. })));
+}
+
+#[tokio::test]
+async fn test_grpc_failures_go_through_retries() {
+    let fake = FakeClickHouse::default();
+    let endpoint = fake.start().await;
+    let client = ClickHouseClient::new_grpc(&endpoint, "default", "default", "").with_retry_config(2, Duration::from_millis(1));
+
+    // An unavailable server is retried
+    fake.fail(Err(tonic::Status::unavailable("restarting")));
+    fake.reply("[\"default\"]\n");
+    assert_eq!(client.list_databases().await.unwrap().len(), 1);
+    assert_eq!(fake.queries.lock().unwrap().len(), 2);
+
+    // A server error is not, and maps as it would over HTTP
+    let exception = QueryException { code: 81, name: "DB::Exception".to_string(), display_text: "Database nope doesn't exist".to_string() };
+    fake.fail(Ok(QueryResult { exception: Some(exception), ..QueryResult::default() }));
+    assert!(matches!(client.list_databases().await, Err(ClickHouseError::DatabaseNotFound { .. })));
+    assert_eq!(fake.queries.lock().unwrap().len(), 3);
+}
+
+#[tokio::test]
+async fn test_unreachable_grpc_endpoint_is_a_network_error() {
+    let client = ClickHouseClient::new_grpc("http://127.0.0.1:1", "default", "default", "").with_retry_config(1, Duration::from_millis(1));
+    assert!(matches!(client.health_check().await, Err(ClickHouseError::NetworkError { .. })));
+
+    let client = ClickHouseClient::new_grpc("not a uri", "default", "default", "");
+    assert!(matches!(client.health_check().await, Err(ClickHouseError::QueryFailed { .. })));
+}
+
+#[test]
+fn test_decode_rows() {
+    let rows: Vec<(String, u64, Option<String>)> = decode_rows(b"[\"a\",1,null]\n[\"b\",18446744073709551615,\"x\"]\n").unwrap();
+    assert_eq!(rows, [("a".to_string(), 1, None), ("b".to_string(), u64::MAX, Some("x".to_string()))]);
+
+    let databases: Vec<DatabaseInfo> = decode_rows(b"[\"default\"]\n").unwrap();
+    assert_eq!(databases[0].name, "default");
+    // Single values are unwrapped
+    assert_eq!(decode_rows::<u8>(b"[1]\n").unwrap(), [1]);
+    assert!(decode_rows::<u8>(b"").unwrap().is_empty());
+    assert!(decode_rows::<u8>(b"[\"one\"]\n").is_err());
+}
+
+#[test]
+fn test_binding_matches_http() {
+    let transport = GrpcTransport::new("http://ch:9100", "default", "default", "");
+    let query = transport.query("SELECT * FROM t WHERE name = ? AND n < ?").bind("it's").bind(5);
+    assert_eq!(query.sql().unwrap(), "SELECT * FROM t WHERE name = 'it\\'s' AND n < 5");
+
+    assert!(matches!(transport.query("SELECT ?").sql(), Err(clickhouse::error::Error::InvalidParams(_))));
+    assert!(matches!(transport.query("SELECT 1").bind(1).sql(), Err(clickhouse::error::Error::InvalidParams(_))));
+}
+
+#[test]
+fn test_errors_map_to_driver_errors() {
+    let client = ClickHouseClient::new("http://localhost:8123", "default", "default", "");
+    let exception = QueryException { code: 60, name: "DB::Exception".to_string(), display_text: "Table shop.x doesn't exist".to_string() };
+    let error = exception_error(&exception);
+    assert_eq!(error.to_string(), "bad response: Code: 60. DB::Exception: Table shop.x doesn't exist");
+    assert!(!client.is_retryable_error(&error));
+
+    let already_worded = QueryException { display_text: "Code: 60. DB::Exception: Table shop.x doesn't exist".to_string(), ..exception };
+    assert_eq!(exception_error(&already_worded).to_string(), error.to_string());
+
+    assert!(client.is_retryable_error(&status_error(tonic::Status::unavailable("down"))));
+    assert!(matches!(status_error(tonic::Status::deadline_exceeded("slow")), clickhouse::error::Error::TimedOut));
+    assert!(matches!(status_error(tonic::Status::unauthenticated("who")), clickhouse::error::Error::BadResponse(_)));
+}
@@ -0,0 +1,73 @@
+use clickhouse::test::{handlers, Mock};
+use mcp_test::{ClickHouseClient, ConnectionManager, JsonRpcRequest, McpServer, QueryHistory, QueryHistoryEntry, MAX_QUERY_HISTORY};
+use serde_json::{json, Value};
+use std::sync::Arc;
+use std::time::Duration;
+
+#[test]
+fn test_query_history_keeps_the_latest_entries() {
+    let history = QueryHistory::new();
+    assert!(history.is_empty());
+    for i in 0..MAX_QUERY_HISTORY + 5 {
+        history.record(QueryHistoryEntry::new("estimate_cost", format!("SELECT {}", i), Duration::from_millis(3), None));
+    }
+    assert_eq!(history.len(), MAX_QUERY_HISTORY);
+
+    let recent = history.recent(2);
+    assert_eq!(recent[0].sql, format!("SELECT {}", MAX_QUERY_HISTORY + 4));
+    assert_eq!(recent[1].sql, format!("SELECT {}", MAX_QUERY_HISTORY + 3));
+    assert_eq!(history.recent(usize::MAX).last().unwrap().sql, "SELECT 5");
+
+    let failed = QueryHistoryEntry::new("run_script", "DROP TABLE t".to_string(), Duration::from_secs(1), Some("boom".to_string()));
+    assert_eq!((failed.status, failed.elapsed_ms), ("error", 1000));
+    assert!(failed.finished_at.ends_with('Z'));
+}
+
+fn call(name: &str, arguments: Value) -> JsonRpcRequest {
+    serde_json::from_value(json!({
+        "jsonrpc": "2.0",
+        "method": "tools/call",
+        "params": {"name": name, "arguments": arguments},
+        "id": 1
+    }))
+    .unwrap()
+}
+
+#[tokio::test]
+async fn test_query_history_tool_lists_query_tool_calls() {
+    let mock = Mock::new();
+    let manager = Arc::new(ConnectionManager::new(ClickHouseClient::new(mock.url(), "default", "default", "")));
+    mock.add(handlers::provide(vec![1u8]));
+    manager.connect().await.unwrap();
+    let mut server = McpServer::new().with_connection(manager);
+
+    let response = server.handle_request(call("query_history", json!({}))).await.unwrap();
+    assert_eq!(response.result.unwrap()["content"][0]["text"], "No queries run in this session yet\n");
+
+    mock.add(handlers::provide(vec!["ReadFromMergeTree (web.events)".to_string()]));
+    let response = server.handle_request(call("explain_with_settings", json!({"sql": "SELECT * FROM web.events"}))).await.unwrap();
+    assert!(response.result.is_some());
+    let response = server.handle_request(call("explain_with_settings", json!({"sql": "DROP TABLE web.events"}))).await.unwrap();
+    assert!(response.error.is_some());
+    // Tools not running client SQL aren't recorded
+    server.handle_request(call("server_stats", json!({}))).await.unwrap();
+
+    let response = server.handle_request(call("query_history", json!({"raw": true}))).await.unwrap();
+    let result = response.result.unwrap();
+    let text = result["content"][0]["text"].as_str().unwrap();
+    assert!(text.starts_with("Last 2 queries of this session, newest first:\n- explain_with_settings at "), "{}", text);
+    assert!(text.contains("(failed: Invalid argument: "), "{}", text);
+    assert!(text.contains(" ms (ok):\n    SELECT * FROM web.events\n"), "{}", text);
+    let entries = result["structuredContent"].as_array().unwrap();
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[0]["sql"], "DROP TABLE web.events");
+    assert_eq!(entries[0]["status"], "error");
+    assert_eq!(entries[1]["sql"], "SELECT * FROM web.events");
+    assert_eq!(entries[1]["status"], "ok");
+    assert_eq!(entries[1].get("error"), None);
+
+    let response = server.handle_request(call("query_history", json!({"limit": 1}))).await.unwrap();
+    let text = response.result.unwrap()["content"][0]["text"].as_str().unwrap().to_string();
+    assert!(text.starts_with("Last 1 queries"), "{}", text);
+    assert!(text.ends_with("[TRUNCATED shown=1 total=2 reason=limit]\n"), "{}", text);
+}
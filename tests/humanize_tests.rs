@@ -0,0 +1,60 @@
+use mcp_test::{human_bytes, human_count, human_duration, with_raw_numbers};
+use std::time::Duration;
+
+#[test]
+fn test_human_bytes() {
+    assert_eq!(human_bytes(0), "0 B");
+    assert_eq!(human_bytes(1), "1 B");
+    assert_eq!(human_bytes(1023), "1023 B");
+    assert_eq!(human_bytes(1024), "1.0 KiB");
+    assert_eq!(human_bytes(1536), "1.5 KiB");
+    // Just below a unit, rounding moves to the next one instead of 1024.0
+    assert_eq!(human_bytes(1024 * 1024 - 1), "1.0 MiB");
+    assert_eq!(human_bytes(1024 * 1024 - 52), "1023.9 KiB");
+    assert_eq!(human_bytes(193_862_482_391), "180.5 GiB");
+    assert_eq!(human_bytes(1 << 50), "1.0 PiB");
+    assert_eq!(human_bytes(u64::MAX), "16.0 EiB");
+}
+
+#[test]
+fn test_human_count() {
+    assert_eq!(human_count(0), "0");
+    assert_eq!(human_count(999), "999");
+    assert_eq!(human_count(1000), "1,000");
+    assert_eq!(human_count(1023), "1,023");
+    assert_eq!(human_count(1024), "1,024");
+    assert_eq!(human_count(100_000), "100,000");
+    assert_eq!(human_count(1_000_000), "1,000,000");
+    assert_eq!(human_count(193_862_482_391), "193,862,482,391");
+    assert_eq!(human_count(u64::MAX), "18,446,744,073,709,551,615");
+}
+
+#[test]
+fn test_human_duration() {
+    assert_eq!(human_duration(Duration::ZERO), "0 ms");
+    assert_eq!(human_duration(Duration::from_micros(999_999)), "999 ms");
+    assert_eq!(human_duration(Duration::from_secs(1)), "1.0 s");
+    assert_eq!(human_duration(Duration::from_millis(4249)), "4.2 s");
+    assert_eq!(human_duration(Duration::from_millis(59_949)), "59.9 s");
+    // Rounds up into the next unit rather than showing 60.0 s
+    assert_eq!(human_duration(Duration::from_millis(59_960)), "1 min 0 s");
+    assert_eq!(human_duration(Duration::from_secs(200)), "3 min 20 s");
+    assert_eq!(human_duration(Duration::from_secs(3600)), "1 h 0 min");
+    assert_eq!(human_duration(Duration::from_secs(5 * 3600 + 12 * 60 + 59)), "5 h 12 min");
+    assert_eq!(human_duration(Duration::from_secs(90061)), "1 d 1 h");
+    assert_eq!(human_duration(Duration::MAX), "213503982334601 d 7 h");
+}
+
+#[tokio::test]
+async fn test_raw_numbers() {
+    let raw = with_raw_numbers(true, async {
+        (human_bytes(193_862_482_391), human_count(u64::MAX), human_duration(Duration::from_secs(200)))
+    })
+    .await;
+    assert_eq!(raw, ("193862482391 bytes".to_string(), "18446744073709551615".to_string(), "200000 ms".to_string()));
+
+    let humanized = with_raw_numbers(false, async { human_count(1_000_000) }).await;
+    assert_eq!(humanized, "1,000,000");
+    // Outside a tool call, numbers are humanized
+    assert_eq!(human_bytes(2048), "2.0 KiB");
+}
@@ -0,0 +1,103 @@
+use clickhouse::test::{handlers, Mock};
+use mcp_test::{ClickHouseClient, ClickHouseError, IndexUsage};
+
+fn lines(text: &str) -> Vec<String> {
+    text.lines().map(str::to_string).collect()
+}
+
+const FILTERED_BY_KEY: &str = "\
+Expression ((Projection + Before ORDER BY))
+  Filter (WHERE)
+    ReadFromMergeTree (web.events)
+    Indexes:
+      MinMax
+        Keys:
+          created_at
+        Condition: (created_at in [1704067200, +Inf))
+        Parts: 10/12
+        Granules: 1300/1480
+      Partition
+        Keys:
+          toYYYYMM(created_at)
+        Condition: (toYYYYMM(created_at) in [202401, +Inf))
+        Parts: 10/10
+        Granules: 1300/1300
+      PrimaryKey
+        Keys:
+          user_id
+          created_at
+        Condition: and((user_id in [42, 42]), (created_at in [1704067200, +Inf)))
+        Parts: 3/10
+        Granules: 5/1300
+      Skip
+        Name: idx_url
+        Description: bloom_filter GRANULARITY 1
+        Parts: 2/3
+        Granules: 2/5";
+
+#[test]
+fn test_index_usage_from_explain() {
+    let usage = IndexUsage::from_explain(&lines(FILTERED_BY_KEY));
+
+    assert!(usage.pk_used);
+    assert_eq!((usage.granules_selected, usage.granules_total), (2, 1480));
+    assert_eq!(usage.skip_indexes_used, ["idx_url"]);
+
+    let kinds: Vec<&str> = usage.indexes.iter().map(|step| step.kind.as_str()).collect();
+    assert_eq!(kinds, ["MinMax", "Partition", "PrimaryKey", "Skip"]);
+    let primary = &usage.indexes[2];
+    assert_eq!(primary.table, "web.events");
+    assert_eq!(primary.keys, ["user_id", "created_at"]);
+    assert_eq!((primary.parts_selected, primary.parts_total), (3, 10));
+    assert_eq!(usage.indexes[3].name.as_deref(), Some("idx_url"));
+    assert!(!usage.indexes[3].is_used());
+}
+
+#[test]
+fn test_index_usage_without_key_condition() {
+    // A primary key checked against `true` reads every granule
+    let full_scan = "\
+Expression ((Projection + Before ORDER BY))
+  ReadFromMergeTree (web.events)
+  Indexes:
+    PrimaryKey
+      Condition: true
+      Parts: 12/12
+      Granules: 1480/1480
+Expression
+  ReadFromMergeTree (web.users)
+  Indexes:
+    PrimaryKey
+      Keys:
+        id
+      Condition: (id in [1, 10])
+      Parts: 1/1
+      Granules: 1/4";
+    let usage = IndexUsage::from_explain(&lines(full_scan));
+    assert!(usage.pk_used);
+    assert_eq!((usage.granules_selected, usage.granules_total), (1481, 1484));
+    assert!(!usage.indexes[0].is_used());
+    assert!(usage.indexes[0].keys.is_empty());
+
+    let usage = IndexUsage::from_explain(&lines(&full_scan[..full_scan.find("\nExpression\n").unwrap()]));
+    assert!(!usage.pk_used);
+    assert!(usage.skip_indexes_used.is_empty());
+
+    // Tables that aren't MergeTree have no indexes
+    let usage = IndexUsage::from_explain(&lines("Expression ((Projection + Before ORDER BY))\n  ReadFromStorage (Memory)"));
+    assert_eq!(usage, IndexUsage::default());
+}
+
+#[tokio::test]
+async fn test_analyze_index_usage_against_mock() {
+    let mock = Mock::new();
+    let client = ClickHouseClient::new(mock.url(), "web", "default", "");
+
+    mock.add(handlers::provide(lines(FILTERED_BY_KEY)));
+    let usage = client.analyze_index_usage("SELECT * FROM events WHERE user_id = 42").await.unwrap();
+    assert_eq!((usage.pk_used, usage.granules_selected, usage.granules_total), (true, 2, 1480));
+
+    // Only read-only SELECTs are explained
+    let error = client.analyze_index_usage("DROP TABLE events").await.unwrap_err();
+    assert!(matches!(error, ClickHouseError::InvalidArgument { .. }), "{:?}", error);
+}
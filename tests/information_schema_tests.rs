@@ -0,0 +1,142 @@
+mod common;
+
+use clickhouse::test::{handlers, Mock};
+use common::column;
+use mcp_test::information_schema::{clickhouse_type, column_rows, json_lines, sql_type, table_rows, SqlType, TYPE_NAMES};
+use mcp_test::{ClickHouseClient, ConnectionManager, JsonRpcRequest, McpServer, TableInfo};
+use serde_json::{json, Value};
+use std::sync::Arc;
+
+#[test]
+fn test_type_names_map_both_ways() {
+    for (clickhouse, standard) in TYPE_NAMES {
+        let mapped = sql_type(clickhouse);
+        assert_eq!(mapped.data_type, *standard, "{}", clickhouse);
+        assert!(mapped.mapped, "{}", clickhouse);
+        assert_eq!(clickhouse_type(&mapped).as_deref(), Some(*clickhouse), "{}", standard);
+    }
+}
+
+#[test]
+fn test_parameterized_types_map_both_ways() {
+    let cases = [
+        ("FixedString(16)", SqlType { data_type: "CHAR".to_string(), character_maximum_length: Some(16), mapped: true, ..SqlType::default() }),
+        ("Decimal(18, 4)", SqlType { data_type: "DECIMAL".to_string(), numeric_precision: Some(18), numeric_scale: Some(4), mapped: true, ..SqlType::default() }),
+        ("DateTime64(3)", SqlType { data_type: "TIMESTAMP".to_string(), datetime_precision: Some(3), mapped: true, ..SqlType::default() }),
+        ("Array(String)", SqlType { data_type: "Array(String)".to_string(), ..SqlType::default() }),
+        ("Map(String, UInt64)", SqlType { data_type: "Map(String, UInt64)".to_string(), ..SqlType::default() }),
+    ];
+    for (clickhouse, standard) in cases {
+        assert_eq!(sql_type(clickhouse), standard, "{}", clickhouse);
+        assert_eq!(clickhouse_type(&standard).as_deref(), Some(clickhouse));
+    }
+}
+
+#[test]
+fn test_types_mapped_to_a_shared_standard_type() {
+    let mapped = |clickhouse: &str| {
+        let sql = sql_type(clickhouse);
+        assert!(sql.mapped, "{}", clickhouse);
+        (sql.data_type.clone(), clickhouse_type(&sql).unwrap())
+    };
+    assert_eq!(mapped("Decimal32(2)"), ("DECIMAL".to_string(), "Decimal(9, 2)".to_string()));
+    assert_eq!(mapped("Decimal256(10)"), ("DECIMAL".to_string(), "Decimal(76, 10)".to_string()));
+    assert_eq!(mapped("Decimal(10)"), ("DECIMAL".to_string(), "Decimal(10, 0)".to_string()));
+    assert_eq!(mapped("DateTime('Europe/Berlin')"), ("TIMESTAMP".to_string(), "DateTime".to_string()));
+    assert_eq!(mapped("DateTime64(6, 'UTC')"), ("TIMESTAMP".to_string(), "DateTime64(6)".to_string()));
+    assert_eq!(mapped("DateTime64(0)"), ("TIMESTAMP".to_string(), "DateTime".to_string()));
+    assert_eq!(mapped("Date32"), ("DATE".to_string(), "Date".to_string()));
+    assert_eq!(mapped("Enum8('a' = 1, 'b, c' = 2)"), ("VARCHAR".to_string(), "String".to_string()));
+
+    // Wrappers are unwrapped
+    assert_eq!(mapped("Nullable(UInt64)"), ("BIGINT UNSIGNED".to_string(), "UInt64".to_string()));
+    assert_eq!(mapped("LowCardinality(Nullable(String))"), ("VARCHAR".to_string(), "String".to_string()));
+}
+
+#[test]
+fn test_unmappable_types_keep_their_name() {
+    for clickhouse in ["UInt128", "IPv4", "Tuple(a String, b UInt8)", "Nested(x UInt8)", "Decimal", "Decimal512(2)", "DateTime64(x)", "JSON"] {
+        let sql = sql_type(clickhouse);
+        assert_eq!(sql.data_type, clickhouse);
+        assert!(!sql.mapped, "{}", clickhouse);
+    }
+    assert_eq!(sql_type("Nullable(IPv6)").data_type, "IPv6");
+
+    // Standard types without a ClickHouse counterpart, or their parameters
+    let standard = |data_type: &str| SqlType { data_type: data_type.to_string(), mapped: true, ..SqlType::default() };
+    assert_eq!(clickhouse_type(&standard("INTERVAL")), None);
+    assert_eq!(clickhouse_type(&standard("CHAR")), None);
+    assert_eq!(clickhouse_type(&standard("decimal")), None);
+    assert_eq!(clickhouse_type(&standard("bigint")).as_deref(), Some("Int64"));
+}
+
+#[test]
+fn test_column_rows() {
+    let mut amount = column("amount", "Nullable(Decimal(9, 2))");
+    amount.default_expression = "0".to_string();
+    amount.comment = "In euros".to_string();
+    let rows = column_rows("shop", "orders", &[column("id", "UInt64"), amount, column("ip", "IPv4")]);
+
+    assert_eq!(
+        serde_json::to_value(&rows[1]).unwrap(),
+        json!({
+            "TABLE_CATALOG": "shop",
+            "TABLE_SCHEMA": "shop",
+            "TABLE_NAME": "orders",
+            "COLUMN_NAME": "amount",
+            "ORDINAL_POSITION": 2,
+            "COLUMN_DEFAULT": "0",
+            "IS_NULLABLE": "YES",
+            "DATA_TYPE": "DECIMAL",
+            "CHARACTER_MAXIMUM_LENGTH": null,
+            "NUMERIC_PRECISION": 9,
+            "NUMERIC_SCALE": 2,
+            "DATETIME_PRECISION": null,
+            "COLUMN_COMMENT": "In euros",
+            "CLICKHOUSE_TYPE": "Nullable(Decimal(9, 2))",
+            "TYPE_MAPPED": true,
+        })
+    );
+    assert_eq!((rows[0].is_nullable, rows[0].column_default.as_deref()), ("NO", None));
+    assert_eq!((rows[2].data_type.as_str(), rows[2].type_mapped), ("IPv4", false));
+}
+
+#[test]
+fn test_table_rows() {
+    let table = |name: &str, engine: &str| TableInfo { name: name.to_string(), database: "shop".to_string(), engine: engine.to_string() };
+    let rows = table_rows(&[table("orders", "MergeTree"), table("daily", "MaterializedView"), table("recent", "View")]);
+    let types: Vec<&str> = rows.iter().map(|row| row.table_type).collect();
+    assert_eq!(types, ["BASE TABLE", "VIEW", "VIEW"]);
+    assert_eq!(
+        json_lines(&rows[..1]),
+        "{\"TABLE_CATALOG\":\"shop\",\"TABLE_SCHEMA\":\"shop\",\"TABLE_NAME\":\"orders\",\"TABLE_TYPE\":\"BASE TABLE\",\"ENGINE\":\"MergeTree\"}\n"
+    );
+}
+
+#[tokio::test]
+async fn test_get_table_schema_in_information_schema_shape() {
+    let mock = Mock::new();
+    let manager = Arc::new(ConnectionManager::new(ClickHouseClient::new(mock.url(), "shop", "default", "")));
+    mock.add(handlers::provide(vec![1u8]));
+    manager.connect().await.unwrap();
+    let mut server = McpServer::new().with_connection(manager);
+
+    mock.add(handlers::provide(vec![1u8]));
+    mock.add(handlers::provide(vec![1u8]));
+    mock.add(handlers::provide(vec![column("id", "UInt64"), column("tags", "Array(String)")]));
+    let request: JsonRpcRequest = serde_json::from_value(json!({
+        "jsonrpc": "2.0",
+        "method": "tools/call",
+        "params": {"name": "get_table_schema", "arguments": {"table": "orders", "information_schema": true, "compact": true}},
+        "id": 1
+    }))
+    .unwrap();
+    let result = server.handle_request(request).await.unwrap().result.unwrap();
+
+    let columns = result["structuredContent"]["columns"].as_array().unwrap();
+    let types: Vec<(&Value, &Value)> = columns.iter().map(|c| (&c["DATA_TYPE"], &c["TYPE_MAPPED"])).collect();
+    assert_eq!(types, [(&json!("BIGINT UNSIGNED"), &json!(true)), (&json!("Array(String)"), &json!(false))]);
+    let text = result["content"][0]["text"].as_str().unwrap();
+    let lines: Vec<Value> = text.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+    assert_eq!(&lines, columns);
+}
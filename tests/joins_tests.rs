@@ -0,0 +1,83 @@
+mod common;
+
+use common::column;
+use mcp_test::{suggest_joins, JoinConfidence};
+
+fn pairs(candidates: &[mcp_test::JoinCandidate]) -> Vec<(&str, &str, JoinConfidence)> {
+    candidates
+        .iter()
+        .map(|c| (c.left_column.as_str(), c.right_column.as_str(), c.confidence))
+        .collect()
+}
+
+#[test]
+fn test_same_name_matches_ignore_wrappers() {
+    let orders = vec![
+        column("order_id", "UInt64"),
+        column("country", "LowCardinality(String)"),
+        column("customer_ref", "Nullable(UUID)"),
+    ];
+    let shipments = vec![
+        column("order_id", "UInt64"),
+        column("country", "String"),
+        column("customer_ref", "UUID"),
+    ];
+
+    let candidates = suggest_joins("orders", &orders, "shipments", &shipments);
+    assert_eq!(
+        pairs(&candidates),
+        vec![
+            ("order_id", "order_id", JoinConfidence::High),
+            ("country", "country", JoinConfidence::High),
+            ("customer_ref", "customer_ref", JoinConfidence::High),
+        ]
+    );
+    assert_eq!(candidates[0].reason, "same name");
+    assert!(candidates[2].reason.contains("Nullable on one side only"));
+}
+
+#[test]
+fn test_integer_width_mismatch_is_flagged() {
+    let events = vec![column("user_id", "UInt32"), column("session", "String")];
+    let sessions = vec![column("user_id", "UInt64"), column("session", "UInt64")];
+
+    let candidates = suggest_joins("events", &events, "sessions", &sessions);
+    // String and integer columns of the same name don't match
+    assert_eq!(pairs(&candidates), vec![("user_id", "user_id", JoinConfidence::Medium)]);
+    assert_eq!(candidates[0].reason, "same name; UInt32 vs UInt64, cast to a common type");
+}
+
+#[test]
+fn test_table_id_convention() {
+    let orders = vec![column("id", "UInt64"), column("user_id", "UInt64"), column("category_id", "UInt16")];
+    let users = vec![column("id", "UInt64"), column("name", "String")];
+    let categories = vec![column("id", "UInt16")];
+
+    let candidates = suggest_joins("orders", &orders, "users", &users);
+    assert_eq!(
+        pairs(&candidates),
+        vec![("user_id", "id", JoinConfidence::Medium), ("id", "id", JoinConfidence::Low)]
+    );
+    assert!(candidates[0].reason.contains("orders.user_id looks like a reference to users.id"));
+
+    // Works in either direction and singularizes -ies
+    let candidates = suggest_joins("categories", &categories, "orders", &orders);
+    assert_eq!(
+        pairs(&candidates),
+        vec![("id", "category_id", JoinConfidence::Medium), ("id", "id", JoinConfidence::Low)]
+    );
+}
+
+#[test]
+fn test_dates_and_floats_are_not_keys() {
+    let columns = || {
+        vec![
+            column("event_date", "Date"),
+            column("created_at", "DateTime64(3)"),
+            column("price", "Float64"),
+            column("tags", "Array(String)"),
+        ]
+    };
+
+    assert!(suggest_joins("a", &columns(), "b", &columns()).is_empty());
+}
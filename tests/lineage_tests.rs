@@ -0,0 +1,162 @@
+use clickhouse::test::{handlers, Mock};
+use mcp_test::{
+    column_lineage, parse_materialized_view, ClickHouseClient, ColumnInfo, ColumnSource, MaterializedView, SourceKind, SourceTable,
+};
+
+const DAILY_SUMMARY_MV: &str = "CREATE MATERIALIZED VIEW web.daily_summary_mv TO web.daily_summary \
+    (`day` Date, `country` LowCardinality(String), `visits` AggregateFunction(count), \
+    `revenue` AggregateFunction(sum, Decimal(18, 2)), `errors` UInt64, `platform` String) \
+    AS SELECT toDate(created_at) AS day, e.country, countState() AS visits, \
+    sumState(toDecimal64(amount, 2)) AS revenue, countIf(status = 'error, timeout') AS errors, \
+    if(is_mobile AND NOT is_tablet, 'mobile', 'desktop') AS `platform` \
+    FROM web.events AS e WHERE e.kind != 'bot' GROUP BY day, e.country, platform";
+
+const ORDER_FACTS_MV: &str = "CREATE MATERIALIZED VIEW web.order_facts_mv TO web.order_facts \
+    AS SELECT o.id AS order_id, u.country AS country, o.amount * r.rate AS amount_usd, \
+    multiIf(total > 100, 'big', 'small') AS size, toStartOfHour(o.created_at) AS hour, \
+    toStartOfDay(hour) AS day \
+    FROM web.orders AS o INNER JOIN web.users AS u ON o.user_id = u.id \
+    LEFT JOIN (SELECT currency, rate FROM web.rates) AS r ON o.currency = r.currency";
+
+const RAW_COPY_MV: &str = "CREATE MATERIALIZED VIEW `my db`.`raw copy` ENGINE = MergeTree ORDER BY ts \
+    AS SELECT *, arrayMap(x -> x * 2, `values`) AS doubled, CAST(code AS Nullable(String)) AS code_text, \
+    ts::Date AS day, ts + INTERVAL 1 DAY AS next_day, attrs.name AS attr_names \
+    FROM `my db`.raw FINAL";
+
+fn view(database: &str, name: &str, create_query: &str) -> MaterializedView {
+    parse_materialized_view(database, name, create_query).unwrap()
+}
+
+fn trace(view: &MaterializedView, column: &str) -> ColumnSource {
+    let mut lineage = column_lineage(&[column.to_string()], std::slice::from_ref(view));
+    lineage.remove(0).sources.remove(0)
+}
+
+#[test]
+fn test_parse_materialized_view() {
+    let summary = view("web", "daily_summary_mv", DAILY_SUMMARY_MV);
+    assert_eq!(summary.name, "web.daily_summary_mv");
+    assert_eq!(summary.target.as_deref(), Some("web.daily_summary"));
+    assert_eq!(summary.sources, [SourceTable { table: Some("web.events".to_string()), alias: Some("e".to_string()) }]);
+    let names: Vec<&str> = summary.items.iter().map(|item| item.name.as_str()).collect();
+    assert_eq!(names, ["day", "country", "visits", "revenue", "errors", "platform"]);
+    assert_eq!(summary.items[4].expression, "countIf(status = 'error, timeout')");
+
+    let facts = view("web", "order_facts_mv", ORDER_FACTS_MV);
+    let sources: Vec<(Option<&str>, Option<&str>)> =
+        facts.sources.iter().map(|source| (source.table.as_deref(), source.alias.as_deref())).collect();
+    assert_eq!(sources, [(Some("web.orders"), Some("o")), (Some("web.users"), Some("u")), (None, Some("r"))]);
+
+    let raw = view("my db", "raw copy", RAW_COPY_MV);
+    assert_eq!(raw.name, "my db.raw copy");
+    assert_eq!(raw.target, None);
+    assert_eq!(raw.sources, [SourceTable { table: Some("my db.raw".to_string()), alias: None }]);
+
+    // Tables have no SELECT to parse
+    assert!(parse_materialized_view("web", "events", "CREATE TABLE web.events (`id` UInt64) ENGINE = MergeTree ORDER BY id").is_none());
+}
+
+#[test]
+fn test_lineage_through_aggregations() {
+    let summary = view("web", "daily_summary_mv", DAILY_SUMMARY_MV);
+    let columns = |source: &ColumnSource| source.source_columns.clone();
+
+    let day = trace(&summary, "day");
+    assert_eq!((day.kind, day.expression.as_deref()), (SourceKind::Expression, Some("toDate(created_at)")));
+    assert_eq!(day.source_table.as_deref(), Some("web.events"));
+    assert_eq!(columns(&day), ["web.events.created_at"]);
+
+    let country = trace(&summary, "country");
+    assert_eq!(country.kind, SourceKind::Column);
+    assert_eq!(columns(&country), ["web.events.country"]);
+
+    // Aggregates without arguments still read the FROM table
+    let visits = trace(&summary, "visits");
+    assert_eq!((visits.kind, visits.source_table.as_deref()), (SourceKind::Expression, Some("web.events")));
+    assert!(visits.source_columns.is_empty());
+
+    assert_eq!(columns(&trace(&summary, "revenue")), ["web.events.amount"]);
+    assert_eq!(columns(&trace(&summary, "errors")), ["web.events.status"]);
+    let platform = trace(&summary, "platform");
+    assert_eq!(platform.expression.as_deref(), Some("if(is_mobile AND NOT is_tablet, 'mobile', 'desktop')"));
+    assert_eq!(columns(&platform), ["web.events.is_mobile", "web.events.is_tablet"]);
+
+    let missing = trace(&summary, "user_count");
+    assert_eq!((missing.kind, missing.expression), (SourceKind::Unresolved, None));
+    assert!(missing.reason.unwrap().contains("default value"));
+}
+
+#[test]
+fn test_lineage_through_joins() {
+    let facts = view("web", "order_facts_mv", ORDER_FACTS_MV);
+
+    let order_id = trace(&facts, "order_id");
+    assert_eq!((order_id.kind, order_id.source_table.as_deref()), (SourceKind::Column, Some("web.orders")));
+    assert_eq!(order_id.source_columns, ["web.orders.id"]);
+    assert_eq!(trace(&facts, "country").source_columns, ["web.users.country"]);
+
+    // Aliases of other items are followed to what they read
+    let day = trace(&facts, "day");
+    assert_eq!((day.kind, day.expression.as_deref()), (SourceKind::Expression, Some("toStartOfDay(hour)")));
+    assert_eq!(day.source_columns, ["web.orders.created_at"]);
+
+    let amount = trace(&facts, "amount_usd");
+    assert_eq!((amount.kind, amount.expression.as_deref()), (SourceKind::Unresolved, Some("o.amount * r.rate")));
+    assert!(amount.reason.unwrap().contains("subquery"));
+
+    let size = trace(&facts, "size");
+    assert_eq!(size.kind, SourceKind::Unresolved);
+    assert!(size.reason.unwrap().contains("`total`"));
+}
+
+#[test]
+fn test_lineage_skips_functions_types_and_lambdas() {
+    let raw = view("my db", "raw copy", RAW_COPY_MV);
+    let columns = |column: &str| trace(&raw, column).source_columns;
+
+    assert_eq!(columns("doubled"), ["my db.raw.values"]);
+    assert_eq!(columns("code_text"), ["my db.raw.code"]);
+    assert_eq!(columns("day"), ["my db.raw.ts"]);
+    assert_eq!(columns("next_day"), ["my db.raw.ts"]);
+    // A dotted name that doesn't name a table is a subcolumn
+    assert_eq!(columns("attr_names"), ["my db.raw.attrs.name"]);
+
+    let star = trace(&raw, "ts");
+    assert_eq!(star.kind, SourceKind::Unresolved);
+    assert!(star.reason.unwrap().contains("`*`"));
+}
+
+#[tokio::test]
+async fn test_column_lineage_against_mock() {
+    let mock = Mock::new();
+    let client = ClickHouseClient::new(mock.url(), "web", "default", "");
+    let column = |name: &str, r#type: &str| ColumnInfo {
+        name: name.to_string(),
+        r#type: r#type.to_string(),
+        default_type: "".to_string(),
+        default_expression: "".to_string(),
+        comment: "".to_string(),
+        is_in_partition_key: 0,
+        is_in_sorting_key: 0,
+        is_in_primary_key: 0,
+        is_in_sampling_key: 0,
+        enum_values: None,
+        timezone: None,
+    };
+
+    mock.add(handlers::provide(vec![1u8]));
+    mock.add(handlers::provide(vec![1u8]));
+    mock.add(handlers::provide(vec![column("day", "Date"), column("visits", "AggregateFunction(count)")]));
+    mock.add(handlers::provide(vec![
+        ("web".to_string(), "daily_summary_mv".to_string(), DAILY_SUMMARY_MV.to_string()),
+        ("web".to_string(), "order_facts_mv".to_string(), ORDER_FACTS_MV.to_string()),
+    ]));
+
+    let lineage = client.column_lineage("web", "daily_summary").await.unwrap();
+    let columns: Vec<&str> = lineage.iter().map(|column| column.column.as_str()).collect();
+    assert_eq!(columns, ["day", "visits"]);
+    // Only the view writing into the table is traced
+    assert_eq!(lineage[0].sources.len(), 1);
+    assert_eq!(lineage[0].sources[0].view, "web.daily_summary_mv");
+    assert_eq!(lineage[0].sources[0].source_columns, ["web.events.created_at"]);
+}
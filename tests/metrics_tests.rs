@@ -0,0 +1,121 @@
+use clickhouse::test::{handlers, Mock};
+use mcp_test::{ClickHouseClient, CliOptions, ConnectionManager, DatabaseInfo, JsonRpcRequest, McpServer, Metrics};
+use serde_json::json;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+fn request(value: serde_json::Value) -> JsonRpcRequest {
+    serde_json::from_value(value).unwrap()
+}
+
+async fn scrape(address: std::net::SocketAddr, path: &str) -> String {
+    let mut stream = TcpStream::connect(address).await.unwrap();
+    stream
+        .write_all(format!("GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path).as_bytes())
+        .await
+        .unwrap();
+    let mut response = String::new();
+    tokio::time::timeout(Duration::from_secs(5), stream.read_to_string(&mut response))
+        .await
+        .unwrap()
+        .unwrap();
+    response
+}
+
+#[tokio::test]
+async fn test_metrics_endpoint_exposes_request_series() {
+    let mock = Mock::new();
+    let metrics = Arc::new(Metrics::new());
+    let client = ClickHouseClient::new(mock.url(), "default", "default", "").with_metrics(Arc::clone(&metrics));
+    let manager = Arc::new(ConnectionManager::new(client));
+
+    mock.add(handlers::provide(vec![1u8]));
+    manager.connect().await.unwrap();
+
+    let mut server = McpServer::new().with_connection(manager).with_metrics(metrics);
+    let address = server.start_metrics_listener("127.0.0.1:0".parse().unwrap()).await.unwrap();
+
+    mock.add(handlers::provide(vec![DatabaseInfo { name: "default".to_string() }]));
+    server.handle_request(request(json!({"jsonrpc": "2.0", "method": "tools/list", "id": 1}))).await.unwrap();
+    let calls = [
+        json!({"jsonrpc": "2.0", "method": "tools/call", "params": {"name": "list_databases"}, "id": 2}),
+        json!({"jsonrpc": "2.0", "method": "tools/call", "params": {"name": "drop_everything"}, "id": 3}),
+    ];
+    for call in calls {
+        server.handle_request(request(call)).await.unwrap();
+    }
+
+    let response = scrape(address, "/metrics").await;
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{}", response);
+    for series in [
+        "mcp_requests_total{method=\"tools/list\"} 1",
+        "mcp_requests_total{method=\"tools/call\"} 2",
+        "mcp_tool_calls_total{tool=\"list_databases\",outcome=\"success\"} 1",
+        "mcp_tool_calls_total{tool=\"unknown\",outcome=\"error\"} 1",
+        "mcp_clickhouse_query_duration_seconds_count 2",
+        "mcp_clickhouse_query_duration_seconds_bucket{le=\"+Inf\"} 2",
+        "mcp_clickhouse_retries_total 0",
+        "mcp_clickhouse_connection_state{state=\"connected\"} 1",
+        "mcp_clickhouse_connection_state{state=\"disconnected\"} 0",
+    ] {
+        assert!(response.contains(series), "missing {} in:\n{}", series, response);
+    }
+
+    // The server_stats tool reads the same registry
+    let stats = server
+        .handle_request(request(json!({"jsonrpc": "2.0", "method": "tools/call", "params": {"name": "server_stats"}, "id": 4})))
+        .await
+        .unwrap();
+    let text = stats.result.unwrap()["content"][0]["text"].as_str().unwrap().to_string();
+    assert!(text.contains("- ClickHouse connection: connected"), "{}", text);
+    assert!(text.contains("  - list_databases: 1 succeeded, 0 failed, "), "{}", text);
+    assert!(text.contains("- ClickHouse queries: 2"), "{}", text);
+
+    assert!(scrape(address, "/other").await.starts_with("HTTP/1.1 404 Not Found\r\n"));
+}
+
+#[tokio::test]
+async fn test_large_responses_are_counted_per_tool() {
+    let mock = Mock::new();
+    let metrics = Arc::new(Metrics::new());
+    let client = ClickHouseClient::new(mock.url(), "default", "default", "");
+    let manager = Arc::new(ConnectionManager::new(client));
+    mock.add(handlers::provide(vec![1u8]));
+    manager.connect().await.unwrap();
+    let mut server = McpServer::new().with_connection(manager).with_metrics(Arc::clone(&metrics));
+
+    let databases: Vec<DatabaseInfo> = (0..20_000).map(|i| DatabaseInfo { name: format!("tenant_{:05}", i) }).collect();
+    mock.add(handlers::provide(databases));
+    let response = server
+        .handle_request(request(json!({"jsonrpc": "2.0", "method": "tools/call", "params": {"name": "list_databases"}, "id": 1})))
+        .await
+        .unwrap();
+    let text = response.result.as_ref().unwrap()["content"][0]["text"].as_str().unwrap();
+    assert!(text.ends_with("- tenant_19999\n"));
+
+    let bytes = serde_json::to_vec(&response).unwrap().len() as u64;
+    assert!(bytes > 300_000);
+    assert_eq!(metrics.snapshot().tool_response_bytes["list_databases"], bytes);
+    assert!(metrics
+        .snapshot()
+        .to_prometheus()
+        .contains(&format!("mcp_tool_response_bytes_total{{tool=\"list_databases\"}} {}", bytes)));
+}
+
+#[tokio::test]
+async fn test_metrics_listener_bind_failure_is_an_error() {
+    let taken = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let server = McpServer::new();
+    assert!(server.start_metrics_listener(taken.local_addr().unwrap()).await.is_err());
+}
+
+#[test]
+fn test_cli_parses_metrics_listen() {
+    let parse = |args: &[&str]| CliOptions::parse(args.iter().map(|a| a.to_string()));
+
+    let options = parse(&["--metrics-listen", "127.0.0.1:9464"]).unwrap();
+    assert_eq!(options.metrics_listen, Some("127.0.0.1:9464".parse().unwrap()));
+    assert!(parse(&["--metrics-listen", "localhost"]).is_err());
+}
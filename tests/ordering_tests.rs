@@ -0,0 +1,126 @@
+mod common;
+
+use clickhouse::test::{handlers, Mock};
+use clickhouse::Row;
+use common::column;
+use mcp_test::{
+    advise_ordering, build_ordering_stats_query, ClickHouseClient, ClickHouseError, ColumnCardinality,
+    TableMetadata,
+};
+use serde::Serialize;
+
+fn cardinality(name: &str, r#type: &str, distinct: Option<u64>) -> ColumnCardinality {
+    ColumnCardinality { name: name.to_string(), r#type: r#type.to_string(), distinct }
+}
+
+fn metadata(engine: &str, sorting_key: &str) -> TableMetadata {
+    TableMetadata {
+        name: "events".to_string(),
+        engine: engine.to_string(),
+        comment: "".to_string(),
+        sorting_key: sorting_key.to_string(),
+        partition_key: "".to_string(),
+        primary_key: sorting_key.to_string(),
+    }
+}
+
+#[derive(Serialize, Row)]
+struct OrderingStats {
+    rows: u64,
+    distinct: Vec<u64>,
+}
+
+#[test]
+fn test_advise_ordering_picks_low_cardinality_prefix_and_time() {
+    let advice = advise_ordering(
+        "MergeTree",
+        "id",
+        "",
+        50_000_000,
+        vec![
+            cardinality("id", "UInt64", Some(49_000_000)),
+            cardinality("event_type", "LowCardinality(String)", Some(12)),
+            cardinality("country", "String", Some(200)),
+            cardinality("constant", "UInt8", Some(1)),
+            cardinality("user agent", "String", Some(8_000)),
+            cardinality("payload", "Map(String, String)", None),
+            cardinality("created_at", "DateTime", Some(40_000_000)),
+        ],
+    );
+
+    assert_eq!(advice.order_by, ["event_type", "country", "`user agent`", "created_at"]);
+    assert_eq!(advice.partition_by.as_deref(), Some("toYYYYMM(created_at)"));
+    assert_eq!(
+        advice.ddl(),
+        "ORDER BY (event_type, country, `user agent`, created_at)\nPARTITION BY toYYYYMM(created_at)"
+    );
+    assert!(advice.rationale.contains(&"not measured, so not considered: payload".to_string()));
+}
+
+#[test]
+fn test_advise_ordering_small_and_empty_tables() {
+    // Too few rows per value for a prefix, and too few rows to partition
+    let small = advise_ordering(
+        "ReplacingMergeTree",
+        "ts",
+        "",
+        500,
+        vec![cardinality("status", "String", Some(100)), cardinality("ts", "DateTime64(3)", Some(500))],
+    );
+    assert_eq!(small.ddl(), "ORDER BY ts");
+    assert!(small.rationale.contains(&"500 rows is too few to benefit from partitioning".to_string()));
+    assert!(small.rationale.contains(&"the suggested ORDER BY matches the current one".to_string()));
+
+    let empty = advise_ordering("MergeTree", "tuple()", "", 0, vec![cardinality("name", "String", Some(0))]);
+    assert_eq!(empty.ddl(), "ORDER BY tuple()");
+    assert!(empty.rationale[0].starts_with("the table is empty"));
+}
+
+#[test]
+fn test_build_ordering_stats_query() {
+    assert_eq!(
+        build_ordering_stats_query("web", "events", &["event_type", "created_at"]),
+        "SELECT count() AS rows, [uniq(`event_type`), uniq(`created_at`)] AS distinct FROM `web`.`events`"
+    );
+    assert_eq!(
+        build_ordering_stats_query("web", "events", &[]),
+        "SELECT count() AS rows, CAST([] AS Array(UInt64)) AS distinct FROM `web`.`events`"
+    );
+}
+
+#[tokio::test]
+async fn test_recommend_ordering_against_mock() {
+    let mock = Mock::new();
+    let client = ClickHouseClient::new(mock.url(), "web", "default", "");
+
+    mock.add(handlers::provide(vec![1u8]));
+    mock.add(handlers::provide(vec![1u8]));
+    mock.add(handlers::provide(vec![
+        column("event_type", "LowCardinality(String)"),
+        column("score", "Float64"),
+        column("created_at", "DateTime"),
+    ]));
+    mock.add(handlers::provide(vec![metadata("MergeTree", "created_at")]));
+    mock.add(handlers::provide(vec![OrderingStats { rows: 1_000, distinct: vec![5, 900] }]));
+
+    let advice = client.recommend_ordering("web", "events").await.unwrap();
+    assert_eq!(advice.order_by, ["event_type", "created_at"]);
+    assert_eq!(advice.columns[0].distinct, Some(5));
+    assert_eq!(advice.columns[1].distinct, None);
+    assert_eq!(advice.columns[2].distinct, Some(900));
+}
+
+#[tokio::test]
+async fn test_recommend_ordering_rejects_other_engines() {
+    let mock = Mock::new();
+    let client = ClickHouseClient::new(mock.url(), "web", "default", "");
+
+    mock.add(handlers::provide(vec![1u8]));
+    mock.add(handlers::provide(vec![1u8]));
+    mock.add(handlers::provide(vec![column("id", "UInt64")]));
+    mock.add(handlers::provide(vec![metadata("Log", "")]));
+
+    let error = client.recommend_ordering("web", "events").await.unwrap_err();
+    assert!(matches!(error, ClickHouseError::InvalidArgument { .. }), "{:?}", error);
+    assert!(error.to_string().contains("uses the Log engine"));
+}
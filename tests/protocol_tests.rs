@@ -0,0 +1,201 @@
+use clickhouse::test::{handlers, Mock};
+use mcp_test::{
+    format_table_schema, truncation_notice, ClickHouseClient, ColumnInfo, ConnectionManager, Content, JsonRpcRequest, McpServer, StructuredOmission,
+    ToolResult,
+};
+use serde_json::json;
+use std::sync::Arc;
+
+/// `count` columns with long generated names, as in very wide tables.
+fn wide_columns(count: usize) -> Vec<ColumnInfo> {
+    (0..count)
+        .map(|i| ColumnInfo {
+            name: format!("metric_{:04}_value_per_region_and_device", i),
+            r#type: "Nullable(Float64)".to_string(),
+            default_type: "".to_string(),
+            default_expression: "".to_string(),
+            comment: format!("Generated metric number {}", i),
+            is_in_partition_key: 0,
+            is_in_sorting_key: 0,
+            is_in_primary_key: 0,
+            is_in_sampling_key: 0,
+            enum_values: None,
+            timezone: None,
+        })
+        .collect()
+}
+
+#[test]
+fn test_text_result_wire_format() {
+    let result = ToolResult::text("Available databases:\n- default\n");
+
+    assert_eq!(
+        serde_json::to_value(&result).unwrap(),
+        json!({
+            "content": [{"type": "text", "text": "Available databases:\n- default\n"}],
+            "isError": false
+        })
+    );
+}
+
+#[test]
+fn test_truncation_notice_format() {
+    assert_eq!(truncation_notice(20, Some(135), "limit"), "[TRUNCATED shown=20 total=135 reason=limit]");
+    assert_eq!(truncation_notice(1000, None, "row_limit"), "[TRUNCATED shown=1000 reason=row_limit]");
+}
+
+#[test]
+fn test_error_result_wire_format() {
+    let result = ToolResult::error("Table not found: shop.orders");
+
+    assert_eq!(
+        serde_json::to_value(&result).unwrap(),
+        json!({
+            "content": [{"type": "text", "text": "Table not found: shop.orders"}],
+            "isError": true
+        })
+    );
+}
+
+#[test]
+fn test_structured_result_wire_format() {
+    let result = ToolResult::with_structured("3 rows", json!({"rows": 3}));
+
+    assert_eq!(
+        serde_json::to_value(&result).unwrap(),
+        json!({
+            "content": [{"type": "text", "text": "3 rows"}],
+            "structuredContent": {"rows": 3},
+            "isError": false
+        })
+    );
+}
+
+#[test]
+fn test_results_round_trip() {
+    for result in [
+        ToolResult::text("ok"),
+        ToolResult::error("failed"),
+        ToolResult::with_structured("ok", json!({"verdict": "healthy", "tables": []})),
+    ] {
+        let wire = serde_json::to_string(&result).unwrap();
+        assert_eq!(serde_json::from_str::<ToolResult>(&wire).unwrap(), result);
+    }
+
+    // isError is optional on the wire and defaults to false
+    let parsed: ToolResult = serde_json::from_value(json!({"content": [{"type": "text", "text": "ok"}]})).unwrap();
+    assert_eq!(parsed, ToolResult::text("ok"));
+
+    // Content blocks must name their type
+    assert!(serde_json::from_value::<Content>(json!({"text": "ok"})).is_err());
+    assert!(serde_json::from_value::<Content>(json!({"type": "image", "data": ""})).is_err());
+}
+
+#[test]
+fn test_oversized_structured_content_is_omitted() {
+    let columns = wide_columns(1500);
+    let text = format_table_schema("metrics", "wide", &columns);
+    let mut result = ToolResult::with_structured(text.clone(), json!({"database": "metrics", "table": "wide", "columns": columns}));
+
+    let size = result.limit_structured(64 * 1024).unwrap();
+    assert!(size > 64 * 1024);
+    assert_eq!(result.structured_content, None);
+    assert_eq!(result.structured_omitted, Some(StructuredOmission { bytes: size, limit: 64 * 1024 }));
+    // The text is complete, with a note about what was left out after it
+    assert!(matches!(&result.content[..], [Content::Text { text: schema }, Content::Text { text: note }]
+        if *schema == text
+            && schema.contains("metric_1499_value_per_region_and_device")
+            && note.starts_with("Structured content omitted: ")
+            && note.contains("exceeds the 64.0 KiB limit")
+            && note.contains("compact: true")));
+
+    let wire = serde_json::to_value(&result).unwrap();
+    assert_eq!(wire["structuredOmitted"], json!({"bytes": size, "limit": 64 * 1024}));
+    assert!(wire.get("structuredContent").is_none());
+    assert_eq!(serde_json::from_value::<ToolResult>(wire).unwrap(), result);
+}
+
+#[test]
+fn test_structured_content_within_limit_is_kept() {
+    let mut result = ToolResult::with_structured("3 rows", json!({"rows": 3}));
+    assert_eq!(result.limit_structured(10), None);
+    assert_eq!(result, ToolResult::with_structured("3 rows", json!({"rows": 3})));
+    assert!(serde_json::to_value(&result).unwrap().get("structuredOmitted").is_none());
+
+    // Results without structured content have nothing to omit
+    let mut result = ToolResult::text(format_table_schema("metrics", "wide", &wide_columns(1500)));
+    assert_eq!(result.limit_structured(0), None);
+    assert_eq!(result.structured_omitted, None);
+}
+
+#[tokio::test]
+async fn test_wide_schema_structured_content_is_capped() {
+    let mock = Mock::new();
+    let manager = Arc::new(ConnectionManager::new(ClickHouseClient::new(mock.url(), "default", "default", "")));
+    mock.add(handlers::provide(vec![1u8]));
+    manager.connect().await.unwrap();
+    let call = || -> JsonRpcRequest {
+        serde_json::from_value(json!({
+            "jsonrpc": "2.0",
+            "method": "tools/call",
+            "params": {"name": "get_table_schema", "arguments": {"database": "metrics", "table": "wide"}},
+            "id": 1
+        }))
+        .unwrap()
+    };
+
+    let mut server = McpServer::new().with_connection(manager.clone());
+    mock.add(handlers::provide(vec![1u8]));
+    mock.add(handlers::provide(vec![1u8]));
+    mock.add(handlers::provide(wide_columns(300)));
+    let result: ToolResult = serde_json::from_value(server.handle_request(call()).await.unwrap().result.unwrap()).unwrap();
+    assert_eq!(result.structured_content.unwrap()["columns"].as_array().unwrap().len(), 300);
+    assert_eq!(result.structured_omitted, None);
+
+    let mut server = McpServer::new().with_connection(manager).with_max_structured_bytes(16 * 1024);
+    mock.add(handlers::provide(vec![1u8]));
+    mock.add(handlers::provide(vec![1u8]));
+    mock.add(handlers::provide(wide_columns(300)));
+    let result: ToolResult = serde_json::from_value(server.handle_request(call()).await.unwrap().result.unwrap()).unwrap();
+    assert!(!result.is_error);
+    assert_eq!(result.structured_omitted.unwrap().limit, 16 * 1024);
+    assert_eq!(result.structured_content, None);
+    assert!(matches!(&result.content[..], [Content::Text { text }, Content::Text { .. }]
+        if text.lines().filter(|line| line.starts_with("- metric_")).count() == 300));
+}
+
+#[tokio::test]
+async fn test_tool_failures_are_error_results() {
+    let mock = Mock::new();
+    let manager = Arc::new(ConnectionManager::new(ClickHouseClient::new(mock.url(), "default", "default", "")));
+    mock.add(handlers::provide(vec![1u8]));
+    manager.connect().await.unwrap();
+    let mut server = McpServer::new().with_connection(manager);
+
+    let call = |name: &str, arguments: serde_json::Value| -> JsonRpcRequest {
+        serde_json::from_value(json!({
+            "jsonrpc": "2.0",
+            "method": "tools/call",
+            "params": {"name": name, "arguments": arguments},
+            "id": 1
+        }))
+        .unwrap()
+    };
+
+    // A missing table is a failure of the tool, not of the request
+    mock.add(handlers::provide(vec![1u8]));
+    mock.add(handlers::provide(vec![0u8]));
+    let response = server.handle_request(call("get_table_schema", json!({"database": "shop", "table": "orders"}))).await.unwrap();
+    assert!(response.error.is_none());
+    let result: ToolResult = serde_json::from_value(response.result.unwrap()).unwrap();
+    assert!(result.is_error);
+    assert!(matches!(&result.content[..], [Content::Text { text }] if text.starts_with("Table not found")));
+
+    // Bad arguments stay protocol errors
+    let response = server.handle_request(call("get_table_schema", json!({"database": "shop"}))).await.unwrap();
+    assert_eq!(response.error.unwrap()["code"], -32602);
+
+    let stats = server.handle_request(call("server_stats", json!({}))).await.unwrap();
+    let text = stats.result.unwrap()["content"][0]["text"].as_str().unwrap().to_string();
+    assert!(text.contains("  - get_table_schema: 0 succeeded, 2 failed"), "{}", text);
+}
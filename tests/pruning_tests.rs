@@ -0,0 +1,196 @@
+mod common;
+
+use common::{answering_clickhouse, rowbinary_strings};
+use mcp_test::{ClickHouseClient, ClickHouseError, PartTotals, PartitionPruning, PruningVerdict};
+
+fn lines(text: &str) -> Vec<String> {
+    text.lines().map(str::to_string).collect()
+}
+
+const TOTALS: PartTotals = PartTotals { parts: 412, rows: 41_200_000 };
+
+/// `date >= '2024-01-01'` on 21.8, which doesn't name the table read.
+const EXPLAIN_21_8: &str = "\
+Expression ((Projection + Before ORDER BY))
+  SettingQuotaAndLimits (Set limits and quota after reading from storage)
+    Filter (WHERE)
+      ReadFromMergeTree
+      Indexes:
+        MinMax
+          Keys:
+            date
+          Condition: (date in [19723, +inf))
+          Parts: 3/412
+          Granules: 30/41200
+        Partition
+          Keys:
+            toYYYYMM(date)
+          Condition: (toYYYYMM(date) in [202401, +inf))
+          Parts: 3/3
+          Granules: 30/30
+        PrimaryKey
+          Condition: true
+          Parts: 3/3
+          Granules: 30/30";
+
+/// `date BETWEEN '2024-01-01' AND '2024-02-01' AND user_id = 42` on 24.8,
+/// with the analyzer's step names and `Ranges:`.
+const EXPLAIN_24_8: &str = "\
+Expression ((Project names + Projection))
+  Expression
+    ReadFromMergeTree (shop.events)
+    Indexes:
+      MinMax
+        Keys:
+          date
+        Condition: and((date in (-Inf, 19754]), (date in [19723, +Inf)))
+        Parts: 250/412
+        Granules: 25000/41200
+      Partition
+        Keys:
+          toYYYYMM(date)
+        Condition: and((toYYYYMM(date) in (-Inf, 202402]), (toYYYYMM(date) in [202401, +Inf)))
+        Parts: 250/250
+        Granules: 25000/25000
+      PrimaryKey
+        Keys:
+          user_id
+        Condition: (user_id in [42, 42])
+        Parts: 40/250
+        Granules: 41/25000
+    Ranges: 40";
+
+/// `user_id IN (SELECT id FROM shop.vip_users)` on 23.8: the partition key
+/// isn't filtered, and the subquery reads another table.
+const EXPLAIN_23_8_FULL_SCAN: &str = "\
+CreatingSets (Create sets before main query execution)
+  Expression ((Projection + Before ORDER BY))
+    ReadFromMergeTree (shop.events)
+    Indexes:
+      MinMax
+        Condition: true
+        Parts: 412/412
+        Granules: 41200/41200
+      Partition
+        Condition: true
+        Parts: 412/412
+        Granules: 41200/41200
+      PrimaryKey
+        Keys:
+          user_id
+        Condition: (user_id in 1-element set)
+        Parts: 380/412
+        Granules: 2000/41200
+  CreatingSet (Create set for subquery)
+    Expression ((Projection + Before ORDER BY))
+      ReadFromMergeTree (shop.vip_users)
+      Indexes:
+        PrimaryKey
+          Condition: true
+          Parts: 1/1
+          Granules: 1/1";
+
+#[test]
+fn test_pruning_from_explain_on_21_8() {
+    let pruning = PartitionPruning::from_explain("shop.events", &lines(EXPLAIN_21_8), "toYYYYMM(date)", TOTALS);
+
+    assert_eq!(pruning.verdict, PruningVerdict::Good);
+    assert!(pruning.partition_key_used);
+    assert_eq!((pruning.parts_after_partition_pruning, pruning.parts_read, pruning.parts_total), (3, 3, 412));
+    assert_eq!((pruning.granules_read, pruning.granules_total), (30, 41200));
+    assert_eq!(pruning.rows_estimate, 30_000);
+    assert_eq!(pruning.summary(), "reads 3 of 412 parts — good");
+}
+
+#[test]
+fn test_pruning_from_explain_on_24_8() {
+    let pruning = PartitionPruning::from_explain("shop.events", &lines(EXPLAIN_24_8), "toYYYYMM(date)", TOTALS);
+
+    // The primary key narrows the read further, but partitions are what is judged
+    assert_eq!(pruning.verdict, PruningVerdict::Partial);
+    assert_eq!((pruning.parts_after_partition_pruning, pruning.parts_read), (250, 40));
+    assert_eq!(pruning.granules_read, 41);
+    assert!((pruning.ratio() - 250.0 / 412.0).abs() < 1e-9);
+    assert!(pruning.summary().starts_with("reads 250 of 412 parts — partly pruned"));
+}
+
+#[test]
+fn test_full_scan_ignores_reads_of_other_tables() {
+    let pruning = PartitionPruning::from_explain("shop.events", &lines(EXPLAIN_23_8_FULL_SCAN), "toYYYYMM(date)", TOTALS);
+
+    assert_eq!(pruning.verdict, PruningVerdict::FullScan);
+    assert!(!pruning.partition_key_used);
+    assert!(pruning.indexes.iter().all(|step| step.table == "shop.events"));
+    assert_eq!((pruning.parts_read, pruning.granules_read, pruning.granules_total), (380, 2000, 41200));
+    assert_eq!(pruning.summary(), "full scan — the filter doesn't use the partition key toYYYYMM(date)");
+
+    let matches_all = EXPLAIN_23_8_FULL_SCAN.replacen("Condition: true", "Condition: (date in [0, +Inf))", 1);
+    let pruning = PartitionPruning::from_explain("shop.events", &lines(&matches_all), "toYYYYMM(date)", TOTALS);
+    assert_eq!(pruning.summary(), "full scan — the condition on the partition key toYYYYMM(date) matches every part");
+}
+
+#[test]
+fn test_pruning_edge_cases() {
+    let no_match = EXPLAIN_21_8.replace("Parts: 3/412", "Parts: 0/412").replace("Parts: 3/3", "Parts: 0/0");
+    let pruning = PartitionPruning::from_explain("shop.events", &lines(&no_match), "toYYYYMM(date)", TOTALS);
+    assert_eq!(pruning.verdict, PruningVerdict::NothingRead);
+
+    let unpartitioned = "\
+Expression ((Projection + Before ORDER BY))
+  ReadFromMergeTree (shop.users)
+  Indexes:
+    PrimaryKey
+      Keys:
+        id
+      Condition: (id in [1, 10])
+      Parts: 2/6
+      Granules: 2/600";
+    let totals = PartTotals { parts: 6, rows: 600 * 8192 };
+    let pruning = PartitionPruning::from_explain("shop.users", &lines(unpartitioned), "", totals);
+    assert_eq!(pruning.verdict, PruningVerdict::NotPartitioned);
+    assert_eq!((pruning.parts_after_partition_pruning, pruning.parts_read), (6, 2));
+    assert_eq!(pruning.rows_estimate, 2 * 8192);
+
+    let empty = "Expression ((Projection + Before ORDER BY))\n  ReadFromMergeTree (shop.events)";
+    let pruning = PartitionPruning::from_explain("shop.events", &lines(empty), "toYYYYMM(date)", PartTotals::default());
+    assert_eq!(pruning.verdict, PruningVerdict::EmptyTable);
+    assert_eq!(pruning.ratio(), 0.0);
+}
+
+#[tokio::test]
+async fn test_check_partition_pruning_explains_select_1() {
+    let (url, queries) = answering_clickhouse(|query| {
+        if query.starts_with("SELECT count(*) > 0") {
+            ("200 OK", vec![1])
+        } else if query.starts_with("SELECT name, engine") {
+            let engine = if query.contains("'logs'") { "Log" } else { "MergeTree" };
+            ("200 OK", rowbinary_strings(&["events", engine, "", "user_id", "toYYYYMM(date)", "user_id"]))
+        } else if query.starts_with("EXPLAIN") {
+            ("200 OK", rowbinary_strings(&EXPLAIN_21_8.lines().collect::<Vec<_>>()))
+        } else if query.starts_with("SELECT count() AS parts") {
+            ("200 OK", [412u64.to_le_bytes(), 41_200_000u64.to_le_bytes()].concat())
+        } else {
+            ("400 Bad Request", b"unexpected query".to_vec())
+        }
+    })
+    .await;
+    let client = ClickHouseClient::new(&url, "shop", "default", "");
+
+    let pruning = client.check_partition_pruning("shop", "events", "date >= '2024-01-01' -- this year").await.unwrap();
+    assert_eq!(pruning.verdict, PruningVerdict::Good);
+    assert_eq!((pruning.parts_total, pruning.rows_total), (412, 41_200_000));
+    assert!(queries.lock().unwrap().contains(
+        &"EXPLAIN indexes = 1 SELECT 1 FROM `shop`.`events` WHERE date >= '2024-01-01' FORMAT RowBinary".to_string()
+    ));
+
+    let error = client.check_partition_pruning("shop", "logs", "1").await.unwrap_err();
+    assert!(matches!(error, ClickHouseError::InvalidArgument { .. }), "{:?}", error);
+
+    // Side effects are refused before anything is sent
+    let sent = queries.lock().unwrap().len();
+    for condition in ["id IN (SELECT id FROM url('http://x', CSV, 'id UInt64'))", "sleep(3) = 0", "1 SETTINGS readonly = 0", " "] {
+        let error = client.check_partition_pruning("shop", "events", condition).await.unwrap_err();
+        assert!(matches!(error, ClickHouseError::InvalidArgument { .. }), "{}: {:?}", condition, error);
+    }
+    assert_eq!(queries.lock().unwrap().len(), sent);
+}
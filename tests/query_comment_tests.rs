@@ -0,0 +1,150 @@
+mod common;
+
+use common::{read_request, url_decode};
+use mcp_test::{ClickHouseClient, ConnectionManager, McpServer};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpListener;
+use tokio::sync::mpsc;
+
+/// A fake ClickHouse that answers the health check with `1` and every later
+/// query with no rows, forwarding the decoded SQL and URL of each request.
+async fn recording_clickhouse() -> (String, mpsc::UnboundedReceiver<(String, String)>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let (queries, received) = mpsc::unbounded_channel();
+
+    tokio::spawn(async move {
+        loop {
+            let (stream, _) = listener.accept().await.unwrap();
+            let queries = queries.clone();
+            tokio::spawn(async move {
+                let mut stream = BufReader::new(stream);
+                while let Some(request) = read_request(&mut stream).await {
+                    let target = request.split_whitespace().nth(1).unwrap_or_default().to_string();
+                    let query = target
+                        .split(['?', '&'])
+                        .find_map(|pair| pair.strip_prefix("query="))
+                        .map(url_decode)
+                        .unwrap_or_else(|| request.split("\r\n\r\n").nth(1).unwrap_or_default().to_string());
+
+                    let response: &[u8] = if query.starts_with("SELECT 1") {
+                        b"HTTP/1.1 200 OK\r\nContent-Length: 1\r\n\r\n\x01"
+                    } else {
+                        b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n"
+                    };
+                    let _ = queries.send((query, url_decode(&target)));
+                    stream.get_mut().write_all(response).await.unwrap();
+                }
+            });
+        }
+    });
+
+    (url, received)
+}
+
+async fn call_list_databases(server: McpServer) {
+    call_tool(server, "ch_list_databases", "Available databases").await;
+}
+
+async fn call_tool(server: McpServer, tool: &str, expected: &str) {
+    let (mut input, server_input) = tokio::io::duplex(4096);
+    let (server_output, output) = tokio::io::duplex(4096);
+    let mut server = server;
+    let serving = tokio::spawn(async move { server.serve(BufReader::new(server_input), server_output).await });
+
+    input
+        .write_all(format!("{{\"jsonrpc\": \"2.0\", \"method\": \"tools/call\", \"params\": {{\"name\": \"{}\"}}, \"id\": 1}}\n", tool).as_bytes())
+        .await
+        .unwrap();
+    drop(input);
+
+    let mut output = BufReader::new(output).lines();
+    let line = tokio::time::timeout(Duration::from_secs(5), output.next_line()).await.unwrap().unwrap().unwrap();
+    assert!(line.contains(expected), "unexpected response: {}", line);
+    serving.await.unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn test_tool_queries_carry_audit_comment() {
+    let (url, mut queries) = recording_clickhouse().await;
+    let manager = Arc::new(ConnectionManager::new(ClickHouseClient::new(&url, "default", "default", "")));
+    manager.connect().await.unwrap();
+
+    // Queries outside of a tool call are not tagged
+    let (health_check, url) = queries.recv().await.unwrap();
+    assert!(!health_check.contains("/*"), "unexpected comment in: {}", health_check);
+    assert!(!url.contains("log_comment=") && !url.contains("query_id="), "unexpected tags in: {}", url);
+
+    let server = McpServer::new().with_connection(manager).with_tool_prefix("ch_").unwrap();
+    call_list_databases(server).await;
+
+    let (query, url) = queries.recv().await.unwrap();
+    let comment_start = query.find("/* mcp-test session=").expect("query carries the audit comment");
+    let comment = &query[comment_start..];
+    assert!(comment.contains(" request=1 tool=list_databases */"), "unexpected comment: {}", comment);
+    // The comment is part of the statement, before the client's FORMAT clause
+    assert!(query.starts_with("SELECT name FROM system.databases ORDER BY name /* "));
+
+    // The same text is the log_comment, and the query_id names the session and call
+    let comment = &comment["/* ".len()..comment.find(" */").unwrap()];
+    assert!(url.contains(&format!("log_comment={}", comment)), "{}", url);
+    let session = comment.split_whitespace().nth(1).unwrap().trim_start_matches("session=");
+    assert!(url.contains(&format!("query_id=mcp-{}-1:1", session)), "{}", url);
+}
+
+#[tokio::test]
+async fn test_find_my_queries_filters_by_session_query_ids() {
+    let (url, mut queries) = recording_clickhouse().await;
+    let manager = Arc::new(ConnectionManager::new(ClickHouseClient::new(&url, "default", "default", "")));
+    manager.connect().await.unwrap();
+    queries.recv().await.unwrap();
+
+    let server = McpServer::new().with_connection(manager);
+    let (input, server_input) = tokio::io::duplex(4096);
+    let (server_output, output) = tokio::io::duplex(4096);
+    let mut server = server;
+    let serving = tokio::spawn(async move { server.serve(BufReader::new(server_input), server_output).await });
+    let mut input = input;
+    input
+        .write_all(b"{\"jsonrpc\": \"2.0\", \"method\": \"tools/call\", \"params\": {\"name\": \"recent_queries\", \"arguments\": {\"find_my_queries\": true, \"limit\": 5}}, \"id\": \"a b\"}\n")
+        .await
+        .unwrap();
+    drop(input);
+    let mut output = BufReader::new(output).lines();
+    let line = tokio::time::timeout(Duration::from_secs(5), output.next_line()).await.unwrap().unwrap().unwrap();
+    assert!(line.contains("No finished queries of this session"), "unexpected response: {}", line);
+    serving.await.unwrap().unwrap();
+
+    let (query, url) = queries.recv().await.unwrap();
+    let start = url.find("query_id=mcp-").expect("query carries a query_id") + "query_id=".len();
+    let query_id = url[start..].split('&').next().unwrap();
+    let own_prefix = &query_id[..query_id.rfind('-').unwrap() + 1];
+    assert!(query.contains(&format!("AND startsWith(query_id, '{}')", own_prefix)), "{}", query);
+    assert!(query.contains("LIMIT 5"), "{}", query);
+    // String request ids are sanitized like the rest of the comment
+    assert!(url.contains("request=_a_b_ tool=recent_queries"), "{}", url);
+}
+
+#[tokio::test]
+async fn test_audit_comment_can_be_disabled() {
+    let (url, mut queries) = recording_clickhouse().await;
+    let manager = Arc::new(ConnectionManager::new(ClickHouseClient::new(&url, "default", "default", "")));
+    manager.connect().await.unwrap();
+    queries.recv().await.unwrap();
+
+    let server = McpServer::new()
+        .with_connection(manager)
+        .with_tool_prefix("ch_")
+        .unwrap()
+        .with_query_comments(false);
+    call_list_databases(server).await;
+
+    let (query, url) = queries.recv().await.unwrap();
+    assert!(!query.contains("/*"));
+    assert!(!url.contains("log_comment="), "{}", url);
+    // Query ids stay for cancellation, but no longer name the session
+    assert!(url.contains("query_id=mcp-"), "{}", url);
+    assert!(!url.contains("-1:1"), "{}", url);
+}
@@ -0,0 +1,141 @@
+mod common;
+
+use common::{read_request, url_decode};
+use mcp_test::ClickHouseClient;
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncWriteExt, BufReader};
+use tokio::net::TcpListener;
+
+const ACCESS_DENIED: &str = "Code: 497. DB::Exception: reader: Not enough privileges. To execute this query, \
+     it's necessary to have the grant SELECT(name) ON system.databases. (ACCESS_DENIED) (version 24.3.1.1)\n";
+
+/// Encodes rows of strings in RowBinary.
+fn strings(rows: &[&[&str]]) -> Vec<u8> {
+    let mut out = Vec::new();
+    for value in rows.iter().flat_map(|row| row.iter()) {
+        let mut len = value.len();
+        loop {
+            let byte = (len & 0x7f) as u8;
+            len >>= 7;
+            if len == 0 {
+                out.push(byte);
+                break;
+            }
+            out.push(byte | 0x80);
+        }
+        out.extend_from_slice(value.as_bytes());
+    }
+    out
+}
+
+/// A fake ClickHouse whose user may not read system tables but may run
+/// `SHOW`, `EXISTS` and `DESCRIBE`. Returns its URL and the queries it got.
+async fn restricted_clickhouse() -> (String, Arc<Mutex<Vec<String>>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let queries = Arc::new(Mutex::new(Vec::new()));
+    let received = Arc::clone(&queries);
+
+    tokio::spawn(async move {
+        loop {
+            let (stream, _) = listener.accept().await.unwrap();
+            let queries = Arc::clone(&queries);
+            tokio::spawn(async move {
+                let mut stream = BufReader::new(stream);
+                while let Some(request) = read_request(&mut stream).await {
+                    let target = request.split_whitespace().nth(1).unwrap_or_default().to_string();
+                    let query = target
+                        .split(['?', '&'])
+                        .find_map(|pair| pair.strip_prefix("query="))
+                        .map(url_decode)
+                        .unwrap_or_else(|| request.split("\r\n\r\n").nth(1).unwrap_or_default().to_string());
+                    queries.lock().unwrap().push(query.clone());
+
+                    let (status, body) = if query.contains("system.") {
+                        ("500 Internal Server Error", ACCESS_DENIED.as_bytes().to_vec())
+                    } else if query.starts_with("SHOW DATABASES") {
+                        ("200 OK", strings(&[&["default"], &["shop"]]))
+                    } else if query.starts_with("EXISTS DATABASE `shop`") || query.starts_with("EXISTS TABLE `shop`.`orders`") {
+                        ("200 OK", vec![1])
+                    } else if query.starts_with("EXISTS") {
+                        ("200 OK", vec![0])
+                    } else if query.starts_with("SHOW TABLES FROM `shop`") {
+                        ("200 OK", strings(&[&["orders"], &["users"]]))
+                    } else if query.starts_with("DESCRIBE TABLE `shop`.`orders`") {
+                        (
+                            "200 OK",
+                            strings(&[
+                                &["id", "UInt64", "", "", "Order number", "", ""],
+                                &["created_at", "DateTime", "DEFAULT", "now()", "", "CODEC(Delta(4), ZSTD(1))", ""],
+                                &["total", "Decimal(18, 2)", "MATERIALIZED", "price * quantity", "Incl. tax", "", ""],
+                            ]),
+                        )
+                    } else {
+                        ("400 Bad Request", b"unexpected query".to_vec())
+                    };
+
+                    let head = format!("HTTP/1.1 {}\r\nContent-Length: {}\r\n\r\n", status, body.len());
+                    stream.get_mut().write_all(head.as_bytes()).await.unwrap();
+                    stream.get_mut().write_all(&body).await.unwrap();
+                }
+            });
+        }
+    });
+
+    (url, received)
+}
+
+#[tokio::test]
+async fn test_listing_falls_back_to_show_statements() {
+    let (url, queries) = restricted_clickhouse().await;
+    let client = ClickHouseClient::new(&url, "default", "reader", "");
+
+    let databases = client.list_databases().await.unwrap();
+    assert_eq!(databases.iter().map(|d| d.name.as_str()).collect::<Vec<_>>(), ["default", "shop"]);
+
+    let tables = client.list_tables("shop").await.unwrap();
+    assert_eq!(tables.iter().map(|t| t.name.as_str()).collect::<Vec<_>>(), ["orders", "users"]);
+    assert_eq!(tables[0].database, "shop");
+    assert_eq!(tables[0].engine, "");
+
+    assert!(matches!(
+        client.list_tables("missing").await,
+        Err(mcp_test::ClickHouseError::DatabaseNotFound { .. })
+    ));
+
+    // The denial is remembered, so later calls skip the system tables
+    let denied = queries.lock().unwrap().iter().filter(|q| q.contains("system.")).count();
+    assert_eq!(denied, 2);
+    client.list_databases().await.unwrap();
+    client.list_tables("shop").await.unwrap();
+    assert_eq!(queries.lock().unwrap().iter().filter(|q| q.contains("system.")).count(), denied);
+}
+
+#[tokio::test]
+async fn test_schema_falls_back_to_describe() {
+    let (url, queries) = restricted_clickhouse().await;
+    let client = ClickHouseClient::new(&url, "default", "reader", "");
+
+    let columns = client.get_table_schema("shop", "orders").await.unwrap();
+    assert_eq!(columns.len(), 3);
+
+    assert_eq!((columns[0].name.as_str(), columns[0].r#type.as_str()), ("id", "UInt64"));
+    assert_eq!(columns[0].default_type, "");
+    assert_eq!(columns[0].comment, "Order number");
+
+    assert_eq!(columns[1].default_type, "DEFAULT");
+    assert_eq!(columns[1].default_expression, "now()");
+    assert_eq!(columns[1].comment, "");
+
+    assert_eq!(columns[2].r#type, "Decimal(18, 2)");
+    assert_eq!(columns[2].default_type, "MATERIALIZED");
+    assert_eq!(columns[2].default_expression, "price * quantity");
+    assert_eq!(columns[2].comment, "Incl. tax");
+    assert!(columns.iter().all(|c| c.is_in_primary_key == 0 && c.is_in_sorting_key == 0));
+
+    assert!(matches!(
+        client.get_table_schema("shop", "missing").await,
+        Err(mcp_test::ClickHouseError::TableNotFound { .. })
+    ));
+    assert!(queries.lock().unwrap().iter().any(|q| q.starts_with("EXISTS TABLE `shop`.`missing`")));
+}
@@ -0,0 +1,150 @@
+use clickhouse::test::{handlers, Mock};
+use mcp_test::results::{parse_result_uri, render_rows, row_columns, ResultFormat, ResultStore, RESULT_PREVIEW_ROWS};
+use mcp_test::{ClickHouseClient, ConnectionManager, JsonRpcRequest, McpServer};
+use serde_json::{json, Value};
+use std::sync::Arc;
+use std::time::Duration;
+
+fn request(method: &str, params: Value) -> JsonRpcRequest {
+    serde_json::from_value(json!({"jsonrpc": "2.0", "method": method, "params": params, "id": 1})).unwrap()
+}
+
+fn numbered_rows(count: usize) -> Vec<Value> {
+    (1..=count).map(|n| json!({"n": n})).collect()
+}
+
+fn n_column() -> Vec<String> {
+    vec!["n".to_string()]
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_results_expire_after_the_ttl() {
+    let store = ResultStore::new(Duration::from_secs(60), 10);
+    let first = store.insert("admin_execute", n_column(), numbered_rows(3));
+    tokio::time::advance(Duration::from_secs(30)).await;
+    let second = store.insert("poll_new_rows", n_column(), numbered_rows(1));
+
+    let live: Vec<String> = store.live().iter().map(|stored| stored.id.clone()).collect();
+    assert_eq!(live, [second.id.clone(), first.id.clone()]);
+    assert_eq!(store.get(&first.id).unwrap().age(), Duration::from_secs(30));
+
+    tokio::time::advance(Duration::from_secs(30)).await;
+    assert!(store.get(&first.id).is_none());
+    assert_eq!(store.get(&second.id).unwrap().rows.len(), 1);
+    tokio::time::advance(Duration::from_secs(30)).await;
+    assert!(store.live().is_empty());
+}
+
+#[test]
+fn test_oldest_results_make_room() {
+    let store = ResultStore::new(Duration::from_secs(60), 2);
+    let ids: Vec<String> = (0..3).map(|_| store.insert("admin_execute", n_column(), numbered_rows(1)).id).collect();
+
+    assert!(store.get(&ids[0]).is_none());
+    assert!(store.get(&ids[1]).is_some() && store.get(&ids[2]).is_some());
+    assert!(ids[1].parse::<uuid::Uuid>().is_ok());
+}
+
+#[test]
+fn test_parse_result_uri() {
+    assert_eq!(parse_result_uri("clickhouse-result://abc"), Some(Ok(("abc", ResultFormat::JsonLines))));
+    assert_eq!(parse_result_uri("clickhouse-result://abc?format=CSV"), Some(Ok(("abc", ResultFormat::Csv))));
+    assert_eq!(parse_result_uri("clickhouse-result://abc?format=jsonl"), Some(Ok(("abc", ResultFormat::JsonLines))));
+    assert!(matches!(parse_result_uri("clickhouse-result://abc?format=xml"), Some(Err(message)) if message.contains("jsonl or csv")));
+    assert!(matches!(parse_result_uri("clickhouse-result://abc?limit=10"), Some(Err(_))));
+    assert_eq!(parse_result_uri("clickhouse://catalog/version"), None);
+}
+
+#[test]
+fn test_row_columns() {
+    assert_eq!(row_columns(r#"{"name":"a","id":1,"tags":{"z":1,"a":2},"note":null}"#), ["name", "id", "tags", "note"]);
+    assert!(row_columns("[1,2]").is_empty());
+    assert!(row_columns("not json").is_empty());
+}
+
+#[test]
+fn test_render_rows() {
+    let rows = [json!({"id": 1, "name": "a, \"b\"", "tags": ["x"], "note": null}), json!({"id": 2, "name": "c", "tags": [], "note": "two\nlines"})];
+    let columns = row_columns(r#"{"name":"","id":0,"tags":[],"note":null}"#);
+
+    assert_eq!(render_rows(&rows, &columns, ResultFormat::JsonLines).lines().count(), 2);
+    assert_eq!(
+        render_rows(&rows, &columns, ResultFormat::Csv),
+        "name,id,tags,note\n\"a, \"\"b\"\"\",1,\"[\"\"x\"\"]\",\nc,2,[],\"two\nlines\"\n"
+    );
+    assert_eq!(render_rows(&[], &[], ResultFormat::Csv), "\n");
+}
+
+#[tokio::test]
+async fn test_long_admin_result_is_previewed_and_linked() {
+    let mock = Mock::new();
+    let manager = Arc::new(ConnectionManager::new(ClickHouseClient::new(mock.url(), "default", "default", "")));
+    mock.add(handlers::provide(vec![1u8]));
+    manager.connect().await.unwrap();
+    let mut server = McpServer::new().with_connection(manager).with_admin_enabled(true);
+
+    let lines: Vec<String> = numbered_rows(120).iter().map(Value::to_string).collect();
+    mock.add(handlers::provide(lines.clone()));
+    let arguments = json!({"sql": "SELECT number AS n FROM numbers(1, 120)", "confirm": mcp_test::sql::ADMIN_CONFIRMATION});
+    let response = server.handle_request(request("tools/call", json!({"name": "admin_execute", "arguments": arguments}))).await.unwrap();
+    let text = response.result.unwrap()["content"][0]["text"].as_str().unwrap().to_string();
+
+    assert!(text.starts_with("120 rows:\n{\"n\":1}\n"), "{}", text);
+    assert!(text.contains("{\"n\":50}\n[TRUNCATED shown=50 total=120 reason=stored]\n"), "{}", text);
+    assert!(!text.contains("{\"n\":51}"));
+    let uri = text.split_whitespace().find(|word| word.starts_with("clickhouse-result://")).unwrap().trim_end_matches(',').to_string();
+
+    let response = server.handle_request(request("resources/list", json!({}))).await.unwrap();
+    let resources = response.result.unwrap()["resources"].as_array().unwrap().clone();
+    let listed = resources.iter().find(|resource| resource["uri"] == uri.as_str()).unwrap();
+    assert_eq!(listed["name"], "admin_execute result");
+    assert!(listed["description"].as_str().unwrap().starts_with("120 rows stored "), "{}", listed);
+
+    let response = server.handle_request(request("resources/read", json!({"uri": uri}))).await.unwrap();
+    let contents = &response.result.unwrap()["contents"][0];
+    assert_eq!(contents["mimeType"], "application/jsonl");
+    assert_eq!(contents["text"], lines.join("\n") + "\n");
+
+    let response = server.handle_request(request("resources/read", json!({"uri": format!("{}?format=csv", uri)}))).await.unwrap();
+    let csv = response.result.unwrap()["contents"][0]["text"].as_str().unwrap().to_string();
+    assert_eq!(csv.lines().count(), 121);
+    assert!(csv.starts_with("n\n1\n2\n"));
+
+    // A short result stays inline
+    mock.add(handlers::provide(lines[..RESULT_PREVIEW_ROWS].to_vec()));
+    let response = server.handle_request(request("tools/call", json!({"name": "admin_execute", "arguments": arguments}))).await.unwrap();
+    assert!(!response.result.unwrap()["content"][0]["text"].as_str().unwrap().contains("clickhouse-result://"));
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_reading_unknown_or_expired_results_fails() {
+    let mock = Mock::new();
+    let manager = Arc::new(ConnectionManager::new(ClickHouseClient::new(mock.url(), "default", "default", "")));
+    mock.add(handlers::provide(vec![1u8]));
+    manager.connect().await.unwrap();
+    let mut server = McpServer::new()
+        .with_connection(manager)
+        .with_admin_enabled(true)
+        .with_result_store(Duration::from_secs(60), 5);
+
+    let response = server.handle_request(request("resources/read", json!({"uri": "clickhouse-result://nothing"}))).await.unwrap();
+    let error = response.error.unwrap();
+    assert_eq!(error["code"], -32602);
+    assert!(error["message"].as_str().unwrap().contains("results expire after 1 min"), "{}", error);
+
+    mock.add(handlers::provide(numbered_rows(60).iter().map(Value::to_string).collect::<Vec<_>>()));
+    let arguments = json!({"sql": "SELECT number AS n FROM numbers(1, 60)", "confirm": mcp_test::sql::ADMIN_CONFIRMATION});
+    server.handle_request(request("tools/call", json!({"name": "admin_execute", "arguments": arguments}))).await.unwrap();
+    let response = server.handle_request(request("resources/list", json!({}))).await.unwrap();
+    let uri = response.result.unwrap()["resources"][1]["uri"].as_str().unwrap().to_string();
+
+    let response = server.handle_request(request("resources/read", json!({"uri": format!("{}?format=xml", uri)}))).await.unwrap();
+    assert_eq!(response.error.unwrap()["code"], -32602);
+    assert!(server.handle_request(request("resources/read", json!({"uri": uri}))).await.unwrap().error.is_none());
+
+    tokio::time::advance(Duration::from_secs(61)).await;
+    let response = server.handle_request(request("resources/read", json!({"uri": uri}))).await.unwrap();
+    assert_eq!(response.error.unwrap()["code"], -32602);
+    let response = server.handle_request(request("resources/list", json!({}))).await.unwrap();
+    assert_eq!(response.result.unwrap()["resources"].as_array().unwrap().len(), 1);
+}
@@ -0,0 +1,106 @@
+mod common;
+
+use common::column;
+use mcp_test::schema_summary::{base_type, name_prefix};
+use mcp_test::{summarize_schema, ColumnFamily, ColumnInfo, TypeGroup};
+
+fn family(pattern: &str, count: usize) -> ColumnFamily {
+    ColumnFamily { pattern: pattern.to_string(), count }
+}
+
+/// A metrics table: keys, a few dimensions and generated metric columns.
+fn wide_table() -> Vec<ColumnInfo> {
+    let mut columns = vec![
+        ColumnInfo { is_in_partition_key: 1, is_in_sorting_key: 1, is_in_primary_key: 1, ..column("ts", "DateTime") },
+        ColumnInfo { is_in_sorting_key: 1, is_in_primary_key: 1, ..column("host", "LowCardinality(String)") },
+        column("region", "LowCardinality(String)"),
+        column("notes", "Nullable(String)"),
+    ];
+    for i in 0..200 {
+        columns.push(column(&format!("cpu_core{}_usage", i), "Float64"));
+    }
+    for i in 0..150 {
+        columns.push(column(&format!("mem_{}", i), "Float64"));
+    }
+    columns.push(column("disk_read", "Float64"));
+    columns.push(column("disk_write", "Float64"));
+    for i in 0..12 {
+        columns.push(column(&format!("price{}", i), "Nullable(Decimal(18, 2))"));
+    }
+    columns
+}
+
+#[test]
+fn test_name_prefixes_and_base_types() {
+    assert_eq!(name_prefix("cpu_user_p99", 1), Some("cpu_"));
+    assert_eq!(name_prefix("cpu_user_p99", 2), Some("cpu_user_"));
+    assert_eq!(name_prefix("cpu_user_p99", 3), None);
+    assert_eq!(name_prefix("attrs.name", 1), Some("attrs."));
+    assert_eq!(name_prefix("col12", 1), Some("col"));
+    // Nothing after the separator, or only a leading one
+    assert_eq!(name_prefix("total_", 1), None);
+    assert_eq!(name_prefix("_id", 1), None);
+    assert_eq!(name_prefix("id", 1), None);
+    assert_eq!(name_prefix("2024", 1), None);
+
+    assert_eq!(base_type("Nullable(Decimal(18, 2))"), "Decimal");
+    assert_eq!(base_type("LowCardinality(Nullable(String))"), "String");
+    assert_eq!(base_type("Array(UInt8)"), "Array");
+    assert_eq!(base_type("Float64"), "Float64");
+}
+
+#[test]
+fn test_summary_groups_wide_tables() {
+    let columns = wide_table();
+    let summary = summarize_schema(&columns, 5);
+
+    assert_eq!(summary.total_columns, 368);
+    let keys: Vec<&str> = summary.key_columns.iter().map(|c| c.name.as_str()).collect();
+    assert_eq!(keys, ["ts", "host"]);
+    let leading: Vec<&str> = summary.leading_columns.iter().map(|c| c.name.as_str()).collect();
+    assert_eq!(leading, ["ts", "host", "region", "notes", "cpu_core0_usage"]);
+
+    assert_eq!(
+        summary.type_groups,
+        [
+            TypeGroup {
+                base_type: "Float64".to_string(),
+                count: 352,
+                families: vec![family("cpu_*", 200), family("mem_*", 150)],
+                others: vec!["disk_read".to_string(), "disk_write".to_string()],
+            },
+            TypeGroup { base_type: "Decimal".to_string(), count: 12, families: vec![family("price*", 12)], others: Vec::new() },
+            TypeGroup {
+                base_type: "String".to_string(),
+                count: 3,
+                families: Vec::new(),
+                others: vec!["host".to_string(), "region".to_string(), "notes".to_string()],
+            },
+            TypeGroup { base_type: "DateTime".to_string(), count: 1, families: Vec::new(), others: vec!["ts".to_string()] },
+        ]
+    );
+}
+
+#[test]
+fn test_shared_prefixes_are_split_further() {
+    let mut columns = Vec::new();
+    for metric in ["cpu", "mem", "net"] {
+        for i in 0..4 {
+            columns.push(column(&format!("metric_{}_{}", metric, i), "UInt64"));
+        }
+    }
+    columns.push(column("metric_disk_total", "UInt64"));
+    let summary = summarize_schema(&columns, 0);
+    assert!(summary.leading_columns.is_empty());
+    let group = &summary.type_groups[0];
+    assert_eq!(group.families, [family("metric_cpu_*", 4), family("metric_mem_*", 4), family("metric_net_*", 4)]);
+    assert_eq!(group.others, ["metric_disk_total"]);
+
+    // All alike below the shared prefix: one family
+    let columns: Vec<ColumnInfo> = (0..5).map(|i| column(&format!("event_{}", i), "String")).collect();
+    assert_eq!(summarize_schema(&columns, 0).type_groups[0].families, [family("event_*", 5)]);
+
+    // Too few to form a family
+    let columns = [column("a_x", "String"), column("a_y", "String")];
+    assert_eq!(summarize_schema(&columns, 0).type_groups[0].others, ["a_x", "a_y"]);
+}
@@ -0,0 +1,461 @@
+use mcp_test::{CliOptions, McpServer};
+use serde_json::{json, Value};
+use std::time::Duration;
+
+async fn send(server: &mut McpServer, line: &str) -> Option<Value> {
+    server
+        .handle_line(line)
+        .await
+        .unwrap()
+        .map(|response| serde_json::from_str(&response).unwrap())
+}
+
+#[tokio::test]
+async fn test_single_request_gets_single_response() {
+    let mut server = McpServer::new();
+
+    let response = send(&mut server, r#"{"jsonrpc": "2.0", "method": "tools/list", "id": 1}"#)
+        .await
+        .unwrap();
+
+    assert!(response.is_object());
+    assert_eq!(response["id"], 1);
+    assert!(response["result"]["tools"].is_array());
+}
+
+#[tokio::test]
+async fn test_mixed_batch_responds_to_requests_only() {
+    let mut server = McpServer::new();
+
+    let response = send(
+        &mut server,
+        r#"[
+            {"jsonrpc": "2.0", "method": "tools/list", "id": 1},
+            {"jsonrpc": "2.0", "method": "notifications/progress"},
+            {"jsonrpc": "2.0", "method": "no/such/method", "id": "two"}
+        ]"#,
+    )
+    .await
+    .unwrap();
+
+    let responses = response.as_array().unwrap();
+    assert_eq!(responses.len(), 2);
+
+    let tools = responses.iter().find(|r| r["id"] == 1).unwrap();
+    assert!(tools["result"]["tools"].is_array());
+
+    let unknown = responses.iter().find(|r| r["id"] == "two").unwrap();
+    assert_eq!(unknown["error"]["code"], -32601);
+}
+
+#[tokio::test]
+async fn test_notification_only_batch_gets_no_response() {
+    let mut server = McpServer::new();
+
+    let response = send(
+        &mut server,
+        r#"[
+            {"jsonrpc": "2.0", "method": "notifications/progress"},
+            {"jsonrpc": "2.0", "method": "notifications/cancelled", "params": {"requestId": 3}}
+        ]"#,
+    )
+    .await;
+
+    assert!(response.is_none());
+}
+
+#[tokio::test]
+async fn test_empty_batch_is_invalid_request() {
+    let mut server = McpServer::new();
+
+    let response = send(&mut server, "[]").await.unwrap();
+
+    assert!(response.is_object());
+    assert_eq!(response["error"]["code"], -32600);
+    assert_eq!(response["id"], Value::Null);
+}
+
+#[tokio::test]
+async fn test_batch_with_malformed_element_gets_its_own_error() {
+    let mut server = McpServer::new();
+
+    let response = send(
+        &mut server,
+        r#"[
+            {"jsonrpc": "2.0", "method": "tools/list", "id": 1},
+            {"jsonrpc": "2.0", "id": 2},
+            42
+        ]"#,
+    )
+    .await
+    .unwrap();
+
+    let responses = response.as_array().unwrap();
+    assert_eq!(responses.len(), 3);
+    assert!(responses.iter().any(|r| r["id"] == 1 && r["result"].is_object()));
+    assert!(responses
+        .iter()
+        .any(|r| r["id"] == 2 && r["error"]["code"] == json!(-32600)));
+    assert!(responses
+        .iter()
+        .any(|r| r["id"].is_null() && r["error"]["code"] == json!(-32600)));
+}
+
+#[tokio::test]
+async fn test_jsonrpc_version_defaults_when_missing() {
+    let mut server = McpServer::new();
+
+    let missing = send(&mut server, r#"{"method": "tools/list", "id": 1}"#).await.unwrap();
+    assert_eq!(missing["jsonrpc"], "2.0");
+    assert!(missing["result"]["tools"].is_array());
+
+    let correct = send(&mut server, r#"{"jsonrpc": "2.0", "method": "tools/list", "id": 2}"#).await.unwrap();
+    assert!(correct["result"]["tools"].is_array());
+
+    // Notifications without a version are still not answered
+    assert!(send(&mut server, r#"{"method": "notifications/initialized"}"#).await.is_none());
+}
+
+#[tokio::test]
+async fn test_wrong_jsonrpc_version_is_invalid_request() {
+    let mut server = McpServer::new();
+
+    let response = send(&mut server, r#"{"jsonrpc": "1.0", "method": "tools/list", "id": 7}"#).await.unwrap();
+    assert_eq!(response["error"]["code"], -32600);
+    assert_eq!(response["id"], 7);
+    assert!(response["result"].is_null());
+
+    let response = send(&mut server, r#"{"jsonrpc": 2, "method": "tools/list", "id": 8}"#).await.unwrap();
+    assert_eq!(response["error"]["code"], -32600);
+    assert_eq!(response["id"], 8);
+}
+
+#[tokio::test]
+async fn test_unparseable_line_is_parse_error() {
+    let mut server = McpServer::new();
+
+    let response = send(&mut server, r#"[{"jsonrpc": "2.0", "#).await.unwrap();
+
+    assert_eq!(response["error"]["code"], -32700);
+}
+
+#[tokio::test]
+async fn test_tool_prefix_is_advertised() {
+    let mut server = McpServer::new().with_tool_prefix("clickhouse_").unwrap();
+
+    let response = send(&mut server, r#"{"jsonrpc": "2.0", "method": "tools/list", "id": 1}"#)
+        .await
+        .unwrap();
+
+    let tools = response["result"]["tools"].as_array().unwrap();
+    assert!(tools.iter().any(|t| t["name"] == "clickhouse_list_tables"));
+    assert!(tools
+        .iter()
+        .all(|t| t["name"].as_str().unwrap().starts_with("clickhouse_")));
+}
+
+#[tokio::test]
+async fn test_prefixed_and_bare_tool_names_are_accepted() {
+    let mut server = McpServer::new()
+        .with_tool_prefix("clickhouse_")
+        .unwrap()
+        .with_reconnect_deadline(Duration::ZERO);
+
+    // Without a ClickHouse connection a known tool fails with service
+    // unavailable, as a tool error, rather than as an unknown tool
+    for name in ["clickhouse_list_databases", "list_databases"] {
+        let request = json!({
+            "jsonrpc": "2.0",
+            "method": "tools/call",
+            "params": {"name": name},
+            "id": 1
+        });
+        let response = send(&mut server, &request.to_string()).await.unwrap();
+        assert_eq!(response["result"]["isError"], true, "{}", name);
+        let message = response["result"]["content"][0]["text"].as_str().unwrap();
+        assert!(message.starts_with("Service unavailable"), "{}: {}", name, message);
+    }
+}
+
+#[tokio::test]
+async fn test_unknown_tool_mentions_prefix() {
+    let mut server = McpServer::new().with_tool_prefix("clickhouse_").unwrap();
+
+    let response = send(
+        &mut server,
+        r#"{"jsonrpc": "2.0", "method": "tools/call", "params": {"name": "clickhouse_drop_everything"}, "id": 1}"#,
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(response["error"]["code"], -32602);
+    let message = response["error"]["message"].as_str().unwrap();
+    assert!(message.contains("Unknown tool: clickhouse_drop_everything"));
+    assert!(message.contains("'clickhouse_'"));
+}
+
+#[tokio::test]
+async fn test_custom_tool_is_listed_and_called() {
+    let mut server = McpServer::new();
+    let definition = json!({
+        "description": "Echo the text back",
+        "inputSchema": {"type": "object", "properties": {"text": {"type": "string"}}, "required": ["text"]}
+    });
+    server
+        .register_tool("echo", definition.clone(), |arguments: Value| async move {
+            let text = arguments["text"].as_str().ok_or_else(|| anyhow::anyhow!("Missing text argument"))?;
+            Ok(text.to_string())
+        })
+        .unwrap();
+    // Names are unique among built-in and custom tools
+    assert!(server.register_tool("echo", definition.clone(), |_| async { Ok(String::new()) }).is_err());
+    assert!(server.register_tool("list_tables", definition, |_| async { Ok(String::new()) }).is_err());
+    assert!(server.register_tool("no spaces", json!({}), |_| async { Ok(String::new()) }).is_err());
+    let mut server = server.with_tool_prefix("app_").unwrap();
+
+    let response = send(&mut server, r#"{"jsonrpc": "2.0", "method": "tools/list", "id": 1}"#)
+        .await
+        .unwrap();
+    let tools = response["result"]["tools"].as_array().unwrap();
+    let echo = tools.iter().find(|t| t["name"] == "app_echo").unwrap();
+    assert_eq!(echo["description"], "Echo the text back");
+    assert_eq!(echo["inputSchema"]["required"], json!(["text"]));
+    assert!(tools.iter().any(|t| t["name"] == "app_list_tables"));
+
+    // Called without a ClickHouse connection
+    let call = |name: &str, arguments: Value| {
+        json!({"jsonrpc": "2.0", "method": "tools/call", "params": {"name": name, "arguments": arguments}, "id": 2}).to_string()
+    };
+    let response = send(&mut server, &call("app_echo", json!({"text": "hello"}))).await.unwrap();
+    assert_eq!(response["result"]["content"][0]["text"], "hello");
+    assert_eq!(response["result"]["isError"], false);
+
+    let response = send(&mut server, &call("echo", json!({}))).await.unwrap();
+    assert_eq!(response["error"]["code"], -32602);
+    assert_eq!(response["error"]["message"], "Invalid params: Missing text argument");
+}
+
+#[test]
+fn test_invalid_tool_prefix_is_rejected() {
+    assert!(McpServer::new().with_tool_prefix("click house.").is_err());
+    assert!(McpServer::new().with_tool_prefix(&"x".repeat(60)).is_err());
+}
+
+#[test]
+fn test_cli_parses_tool_prefix() {
+    let parse = |args: &[&str]| CliOptions::parse(args.iter().map(|a| a.to_string()));
+
+    assert_eq!(parse(&[]).unwrap().tool_prefix, None);
+    assert_eq!(parse(&["--tool-prefix", "ch_"]).unwrap().tool_prefix.as_deref(), Some("ch_"));
+    assert_eq!(parse(&["--tool-prefix=ch_"]).unwrap().tool_prefix.as_deref(), Some("ch_"));
+    assert!(parse(&["--tool-prefix"]).is_err());
+    assert!(parse(&["--bogus"]).is_err());
+}
+
+#[test]
+fn test_log_effective_config_without_connection() {
+    McpServer::new().with_tool_prefix("ch_").unwrap().log_effective_config();
+}
+
+#[tokio::test]
+async fn test_prompts_list() {
+    let mut server = McpServer::new();
+
+    let response = send(&mut server, r#"{"jsonrpc": "2.0", "method": "prompts/list", "id": 1}"#)
+        .await
+        .unwrap();
+
+    let prompts = response["result"]["prompts"].as_array().unwrap();
+    let explore = prompts.iter().find(|p| p["name"] == "explore_database").unwrap();
+    assert_eq!(explore["arguments"][0]["name"], "database");
+    assert_eq!(explore["arguments"][0]["required"], true);
+    assert!(prompts.iter().any(|p| p["name"] == "optimize_query"));
+}
+
+#[tokio::test]
+async fn test_prompts_get_substitutes_arguments() {
+    let mut server = McpServer::new().with_tool_prefix("ch_").unwrap();
+
+    let request = json!({
+        "jsonrpc": "2.0",
+        "method": "prompts/get",
+        "params": {"name": "explore_database", "arguments": {"database": "web {query}"}},
+        "id": 1
+    });
+    let response = send(&mut server, &request.to_string()).await.unwrap();
+
+    let message = &response["result"]["messages"][0];
+    assert_eq!(message["role"], "user");
+    let text = message["content"]["text"].as_str().unwrap();
+    assert!(text.contains("database 'web {query}'"));
+    assert!(text.contains("`ch_list_tables`"));
+    assert!(text.contains("`ch_get_table_schema`"));
+}
+
+#[tokio::test]
+async fn test_prompts_get_rejects_unknown_prompt_and_missing_arguments() {
+    let mut server = McpServer::new();
+
+    let response = send(
+        &mut server,
+        r#"{"jsonrpc": "2.0", "method": "prompts/get", "params": {"name": "drop_database"}, "id": 1}"#,
+    )
+    .await
+    .unwrap();
+    assert_eq!(response["error"]["code"], -32602);
+    assert!(response["error"]["message"].as_str().unwrap().contains("drop_database"));
+
+    let response = send(
+        &mut server,
+        r#"{"jsonrpc": "2.0", "method": "prompts/get", "params": {"name": "optimize_query", "arguments": {}}, "id": 2}"#,
+    )
+    .await
+    .unwrap();
+    assert_eq!(response["error"]["code"], -32602);
+    assert!(response["error"]["message"].as_str().unwrap().contains("'query'"));
+}
+
+#[tokio::test]
+async fn test_ping_before_and_after_initialization() {
+    let mut server = McpServer::new();
+
+    let response = send(&mut server, r#"{"jsonrpc": "2.0", "method": "ping", "id": "p1"}"#).await.unwrap();
+    assert_eq!(response["id"], "p1");
+    assert_eq!(response["result"], json!({}));
+
+    send(&mut server, r#"{"jsonrpc": "2.0", "method": "initialize", "params": {}, "id": 1}"#).await.unwrap();
+    assert!(send(&mut server, r#"{"jsonrpc": "2.0", "method": "notifications/initialized"}"#).await.is_none());
+
+    let response = send(&mut server, r#"{"jsonrpc": "2.0", "method": "ping", "id": 2}"#).await.unwrap();
+    assert_eq!(response["id"], 2);
+    assert_eq!(response["result"], json!({}));
+}
+
+#[tokio::test]
+async fn test_responses_from_client_get_no_response() {
+    let mut server = McpServer::new();
+
+    assert!(send(&mut server, r#"{"jsonrpc": "2.0", "result": {}, "id": "keepalive-1"}"#).await.is_none());
+    assert!(send(&mut server, r#"{"jsonrpc": "2.0", "error": {"code": -32601, "message": "nope"}, "id": "keepalive-2"}"#)
+        .await
+        .is_none());
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_keepalive_pings_after_idle_interval() {
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::time::Instant;
+
+    let (mut input, server_input) = tokio::io::duplex(4096);
+    let (server_output, output) = tokio::io::duplex(4096);
+    let mut server = McpServer::new().with_keepalive_interval(Duration::from_secs(30));
+    let serving = tokio::spawn(async move { server.serve(BufReader::new(server_input), server_output).await });
+    let mut output = BufReader::new(output).lines();
+    async fn next_message(output: &mut tokio::io::Lines<BufReader<tokio::io::DuplexStream>>) -> Value {
+        serde_json::from_str(&output.next_line().await.unwrap().unwrap()).unwrap()
+    }
+
+    let start = Instant::now();
+    let ping = next_message(&mut output).await;
+    assert_eq!(ping, json!({"jsonrpc": "2.0", "id": "keepalive-1", "method": "ping"}));
+    assert_eq!(start.elapsed(), Duration::from_secs(30));
+
+    // Output restarts the idle interval; the client's answer gets none
+    tokio::time::sleep(Duration::from_secs(20)).await;
+    input.write_all(b"{\"jsonrpc\": \"2.0\", \"result\": {}, \"id\": \"keepalive-1\"}\n").await.unwrap();
+    input.write_all(b"{\"jsonrpc\": \"2.0\", \"method\": \"ping\", \"id\": 7}\n").await.unwrap();
+    assert_eq!(next_message(&mut output).await["id"], 7);
+    let answered = Instant::now();
+
+    let ping = next_message(&mut output).await;
+    assert_eq!(ping["id"], "keepalive-2");
+    assert_eq!(answered.elapsed(), Duration::from_secs(30));
+
+    drop(input);
+    serving.await.unwrap().unwrap();
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_no_keepalive_by_default() {
+    use tokio::io::{AsyncBufReadExt, BufReader};
+
+    let (input, server_input) = tokio::io::duplex(4096);
+    let (server_output, output) = tokio::io::duplex(4096);
+    let mut server = McpServer::new();
+    let serving = tokio::spawn(async move { server.serve(BufReader::new(server_input), server_output).await });
+
+    let mut output = BufReader::new(output).lines();
+    assert!(tokio::time::timeout(Duration::from_secs(3600), output.next_line()).await.is_err());
+
+    drop(input);
+    serving.await.unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn test_response_ids_echo_request_ids_exactly() {
+    let ids = [
+        json!(0),
+        json!(7),
+        json!(-3),
+        json!(1.5),
+        json!(u64::MAX),
+        json!("7"),
+        json!(""),
+        json!("req-ü-1"),
+        json!("0007"),
+    ];
+    // Success, protocol error, invalid request and internal error paths
+    let requests = [
+        json!({"jsonrpc": "2.0", "method": "ping"}),
+        json!({"jsonrpc": "2.0", "method": "tools/list"}),
+        json!({"jsonrpc": "2.0", "method": "no/such/method"}),
+        json!({"jsonrpc": "2.0", "method": "tools/call", "params": {"name": "no_such_tool"}}),
+        json!({"jsonrpc": "2.0", "method": "tools/call", "params": {"arguments": {}}}),
+        json!({"jsonrpc": "1.0", "method": "ping"}),
+        json!({"jsonrpc": "2.0", "method": 42}),
+    ];
+
+    let mut server = McpServer::new();
+    for id in &ids {
+        for request in &requests {
+            let mut request = request.clone();
+            request["id"] = id.clone();
+            let line = request.to_string();
+
+            let response = server.handle_line(&line).await.unwrap().unwrap();
+            let response: Value = serde_json::from_str(&response).unwrap();
+            assert_eq!(&response["id"], id, "{} -> {}", line, response);
+            // Same type too: 7 and "7", 1.5 and 1 are different ids
+            assert_eq!(response["id"].to_string(), id.to_string(), "{}", line);
+
+            let batch = send(&mut server, &format!("[{}]", line)).await.unwrap();
+            assert_eq!(&batch[0]["id"], id, "{} -> {}", line, batch);
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_invalid_utf8_line_echoes_its_id() {
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+    let (mut input, server_input) = tokio::io::duplex(4096);
+    let (server_output, output) = tokio::io::duplex(4096);
+    let mut server = McpServer::new();
+    let serving = tokio::spawn(async move { server.serve(BufReader::new(server_input), server_output).await });
+
+    input.write_all(b"{\"jsonrpc\": \"2.0\", \"method\": \"ping\", \"params\": {\"x\": \"\xff\"}, \"id\": \"bad-1\"}\n").await.unwrap();
+    input.write_all(b"\xff\xfe\n").await.unwrap();
+    drop(input);
+
+    let mut output = BufReader::new(output).lines();
+    let mut responses = Vec::new();
+    while let Some(line) = output.next_line().await.unwrap() {
+        responses.push(serde_json::from_str::<Value>(&line).unwrap());
+    }
+    serving.await.unwrap().unwrap();
+
+    assert_eq!(responses.len(), 2);
+    assert_eq!(responses[0]["id"], "bad-1");
+    assert_eq!(responses[0]["error"]["code"], -32700);
+    assert!(responses[1]["id"].is_null());
+}
@@ -0,0 +1,229 @@
+use mcp_test::sql::{check_admin_confirmation, check_admin_statement, check_preview_expression, check_where_expression, prepare_select, validate_settings, ADMIN_CONFIRMATION};
+use mcp_test::{classify_statement, coerce_bound_value, statement_table, BoundValue, ClickHouseError, StatementKind};
+use serde_json::json;
+
+#[test]
+fn test_classify_statement() {
+    assert_eq!(classify_statement("SELECT 1").unwrap(), StatementKind::Select);
+    assert_eq!(classify_statement("  with x AS (SELECT 1) SELECT * FROM x;").unwrap(), StatementKind::Select);
+    assert_eq!(classify_statement("(SELECT 1) UNION ALL (SELECT 2)").unwrap(), StatementKind::Select);
+    assert_eq!(classify_statement("-- leading comment\nSHOW TABLES").unwrap(), StatementKind::Show);
+    assert_eq!(classify_statement("desc system.one").unwrap(), StatementKind::Describe);
+    assert_eq!(classify_statement("EXPLAIN SELECT 1").unwrap(), StatementKind::Explain);
+    assert_eq!(
+        classify_statement("INSERT INTO t VALUES (1)").unwrap(),
+        StatementKind::Other("INSERT".to_string())
+    );
+    assert!(!classify_statement("DROP TABLE t").unwrap().is_read_only());
+}
+
+#[test]
+fn test_classify_rejects_multiple_statements() {
+    assert!(matches!(
+        classify_statement("SELECT 1; DROP TABLE t"),
+        Err(ClickHouseError::InvalidArgument { .. })
+    ));
+    // Semicolons in strings and comments don't split statements
+    assert_eq!(classify_statement("SELECT ';' /* ; */ -- ;").unwrap(), StatementKind::Select);
+    assert!(classify_statement("   ").is_err());
+    assert!(classify_statement("SELECT 'unterminated").is_err());
+}
+
+#[test]
+fn test_prepare_select() {
+    assert_eq!(prepare_select("SELECT 1;").unwrap(), "SELECT 1");
+    assert_eq!(prepare_select("SELECT 'a?b' -- why?\n").unwrap(), "SELECT 'a\\x3Fb'");
+    assert_eq!(prepare_select("SELECT 'it''s'").unwrap(), "SELECT 'it''s'");
+
+    match prepare_select("SELECT x > 1 ? 'a' : 'b' FROM t") {
+        Err(ClickHouseError::InvalidArgument { message }) => assert!(message.contains("if()")),
+        other => panic!("Expected InvalidArgument, got: {:?}", other),
+    }
+    match prepare_select("ALTER TABLE t DELETE WHERE 1") {
+        Err(ClickHouseError::InvalidArgument { message }) => assert!(message.contains("ALTER")),
+        other => panic!("Expected InvalidArgument, got: {:?}", other),
+    }
+}
+
+#[test]
+fn test_validate_settings() {
+    let settings = |name: &str, value: &str| vec![(name.to_string(), value.to_string())];
+
+    assert!(validate_settings(&[]).is_ok());
+    assert!(validate_settings(&settings("optimize_read_in_order", "0")).is_ok());
+    assert!(validate_settings(&settings("join_algorithm", "grace_hash")).is_ok());
+
+    assert!(validate_settings(&settings("readonly", "0")).is_err());
+    assert!(validate_settings(&settings("max_memory_usage", "0")).is_err());
+    assert!(validate_settings(&settings("max_threads", "1, readonly = 0")).is_err());
+    assert!(validate_settings(&settings("max_threads", "")).is_err());
+}
+
+#[test]
+fn test_coerce_datetime_from_iso_and_epoch() {
+    let instant = |value: &str| BoundValue {
+        placeholder: "toDateTime64(?, 9, 'UTC')",
+        value: value.to_string(),
+    };
+
+    let midnight = instant("2024-01-01 00:00:00.000000000");
+    assert_eq!(coerce_bound_value("DateTime", &json!("2024-01-01T00:00:00Z")).unwrap(), midnight);
+    assert_eq!(coerce_bound_value("DateTime", &json!("2024-01-01T01:00:00+01:00")).unwrap(), midnight);
+    assert_eq!(coerce_bound_value("DateTime('Europe/Berlin')", &json!(1704067200)).unwrap(), midnight);
+    assert_eq!(coerce_bound_value("Nullable(DateTime)", &json!("1704067200")).unwrap(), midnight);
+    assert_eq!(
+        coerce_bound_value("DateTime64(3)", &json!(1704067200.25)).unwrap(),
+        instant("2024-01-01 00:00:00.250000000")
+    );
+
+    // Without an offset the column's time zone applies, so ClickHouse reads it
+    let local = coerce_bound_value("DateTime", &json!("2024-01-01T12:30:00")).unwrap();
+    assert_eq!(local, BoundValue { placeholder: "?", value: "2024-01-01 12:30:00".to_string() });
+}
+
+#[test]
+fn test_coerce_date_and_other_types() {
+    let plain = |value: &str| BoundValue { placeholder: "?", value: value.to_string() };
+
+    assert_eq!(coerce_bound_value("Date", &json!("2024-01-01T23:00:00Z")).unwrap(), plain("2024-01-01"));
+    assert_eq!(coerce_bound_value("Date32", &json!("2024-01-01")).unwrap(), plain("2024-01-01"));
+    assert_eq!(coerce_bound_value("UInt64", &json!(42)).unwrap(), plain("42"));
+    assert_eq!(coerce_bound_value("String", &json!("2024-01-01T00:00:00Z")).unwrap(), plain("2024-01-01T00:00:00Z"));
+
+    for value in [json!("yesterday"), json!(-1), json!(true)] {
+        match coerce_bound_value("DateTime", &value) {
+            Err(ClickHouseError::InvalidArgument { message }) => assert!(message.contains("ISO-8601"), "{}", message),
+            other => panic!("Expected InvalidArgument, got: {:?}", other),
+        }
+    }
+    assert!(coerce_bound_value("UInt64", &json!(null)).is_err());
+
+    // Dates need ISO-8601; digits alone are not read as an epoch
+    for value in [json!("20240101"), json!("1704067200"), json!(1704067200)] {
+        match coerce_bound_value("Nullable(Date)", &value) {
+            Err(ClickHouseError::InvalidArgument { message }) => assert!(message.contains("ISO-8601 date"), "{}", message),
+            other => panic!("Expected InvalidArgument for {}, got: {:?}", value, other),
+        }
+    }
+}
+
+#[test]
+fn test_admin_confirmation_must_match_verbatim() {
+    assert!(check_admin_confirmation(Some(ADMIN_CONFIRMATION)).is_ok());
+    assert!(check_admin_confirmation(Some("I understand this runs arbitrary SQL")).is_ok());
+
+    for confirm in [None, Some(""), Some("yes"), Some("i understand this runs arbitrary sql"), Some("I understand this runs arbitrary SQL ")] {
+        match check_admin_confirmation(confirm) {
+            Err(ClickHouseError::InvalidArgument { message }) => assert!(message.contains(ADMIN_CONFIRMATION), "{}", message),
+            other => panic!("Expected InvalidArgument for {:?}, got: {:?}", confirm, other),
+        }
+    }
+}
+
+#[test]
+fn test_admin_statement_deny_list() {
+    for sql in [
+        "DROP DATABASE analytics",
+        "drop database if exists analytics",
+        "  DROP /* just this once */ DATABASE analytics;",
+        "-- cleanup\nDrop\n\tDatabase analytics",
+        "TRUNCATE ALL TABLES FROM analytics",
+        "TRUNCATE DATABASE analytics",
+        "GRANT SELECT ON *.* TO intern",
+        "grant all on analytics.* to intern",
+    ] {
+        match check_admin_statement(sql) {
+            Err(ClickHouseError::InvalidArgument { message }) => assert!(message.contains("not allowed"), "{}: {}", sql, message),
+            other => panic!("Expected {:?} to be refused, got: {:?}", sql, other),
+        }
+    }
+
+    for (sql, kind) in [
+        ("SYSTEM FLUSH LOGS", "SYSTEM"),
+        ("TRUNCATE TABLE analytics.events", "TRUNCATE"),
+        ("truncate analytics.events", "TRUNCATE"),
+        ("DROP TABLE analytics.database", "DROP"),
+        ("DROP DICTIONARY analytics.grants", "DROP"),
+        ("OPTIMIZE TABLE events FINAL", "OPTIMIZE"),
+        ("INSERT INTO notes VALUES ('DROP DATABASE x')", "INSERT"),
+    ] {
+        let (statement_kind, _) = check_admin_statement(sql).unwrap_or_else(|e| panic!("{}: {}", sql, e));
+        assert_eq!(statement_kind, StatementKind::Other(kind.to_string()));
+    }
+    assert_eq!(check_admin_statement("SELECT 1;").unwrap(), (StatementKind::Select, "SELECT 1".to_string()));
+    // One statement at a time, so nothing can hide behind an allowed one
+    assert!(check_admin_statement("SYSTEM FLUSH LOGS; DROP DATABASE analytics").is_err());
+}
+
+#[test]
+fn test_statement_table() {
+    let table = |database: Option<&str>, table: &str| Some((database.map(str::to_string), table.to_string()));
+    assert_eq!(statement_table("ALTER TABLE shop.orders ADD COLUMN discount Decimal(9, 2)"), table(Some("shop"), "orders"));
+    assert_eq!(statement_table("alter table orders on cluster main drop column x;"), table(None, "orders"));
+    assert_eq!(statement_table("DROP TABLE IF EXISTS `my db`.`we``ird`"), table(Some("my db"), "we`ird"));
+    assert_eq!(statement_table("CREATE OR REPLACE TABLE \"shop\".staging (id UInt8) ENGINE = Memory"), table(Some("shop"), "staging"));
+    assert_eq!(statement_table("create temporary table if not exists t (id UInt8)"), table(None, "t"));
+    assert_eq!(statement_table("-- load\nINSERT INTO /* x */ shop.orders (id) VALUES (1)"), table(Some("shop"), "orders"));
+    assert_eq!(statement_table("TRUNCATE shop.orders"), table(Some("shop"), "orders"));
+    assert_eq!(statement_table("OPTIMIZE TABLE events FINAL"), table(None, "events"));
+
+    // Several tables, no table or not a table at all
+    for sql in [
+        "RENAME TABLE a TO b",
+        "EXCHANGE TABLES a AND b",
+        "SYSTEM FLUSH LOGS",
+        "DROP DICTIONARY shop.countries",
+        "INSERT INTO FUNCTION remote('host', shop.orders) VALUES (1)",
+        "ALTER TABLE 'orders' DELETE WHERE 1",
+        "ALTER TABLE `unterminated",
+    ] {
+        assert_eq!(statement_table(sql), None, "{}", sql);
+    }
+}
+
+#[test]
+fn test_check_preview_expression() {
+    assert_eq!(check_preview_expression("now()").unwrap(), "now()");
+    assert_eq!(check_preview_expression(" domain(url) -- the host\n").unwrap(), "domain(url)");
+    // Columns may be named like unsafe functions
+    assert_eq!(check_preview_expression("concat(url, file)").unwrap(), "concat(url, file)");
+    assert_eq!(check_preview_expression("'from ?'").unwrap(), "'from \\x3F'");
+
+    let reason = |expression: &str| match check_preview_expression(expression) {
+        Err(ClickHouseError::InvalidArgument { message }) => message,
+        other => panic!("Expected InvalidArgument for {}, got: {:?}", expression, other),
+    };
+    assert_eq!(reason("sleep(3)"), "the expression calls sleep");
+    assert_eq!(reason("plus(1, SLEEPEACHROW (1))"), "the expression calls sleepEachRow");
+    assert_eq!(reason("dictGetString('users', 'name', id)"), "the expression calls dictGetString");
+    assert_eq!(reason("joinGetOrNull('j', 'v', id)"), "the expression calls joinGetOrNull");
+    assert_eq!(reason("(SELECT max(id) FROM secrets)"), "the expression contains SELECT");
+    assert_eq!(reason("1 SETTINGS readonly = 0"), "the expression contains SETTINGS");
+    assert_eq!(reason("1)) FROM system.users"), "the expression has unbalanced parentheses");
+    assert_eq!(reason("toString((1)"), "the expression has unbalanced parentheses");
+    assert!(check_preview_expression("1; DROP TABLE t").is_err());
+}
+
+#[test]
+fn test_check_where_expression() {
+    assert_eq!(check_where_expression("date >= '2024-01-01' -- this year\n").unwrap(), "date >= '2024-01-01'");
+    assert_eq!(check_where_expression("(a = 1 OR b = 2) AND url = 'a?b'").unwrap(), "(a = 1 OR b = 2) AND url = 'a\\x3Fb'");
+
+    let reason = |expression: &str| match check_where_expression(expression) {
+        Err(ClickHouseError::InvalidArgument { message }) => message,
+        other => panic!("Expected InvalidArgument for {}, got: {:?}", expression, other),
+    };
+    // EXPLAIN runs scalar subqueries and builds IN sets
+    assert_eq!(reason("user_id IN (SELECT id FROM shop.vip_users)"), "the expression contains SELECT");
+    assert_eq!(reason("id IN s3Cluster('c', 'https://b/x.csv')"), "the expression calls s3Cluster");
+    for function in ["clusterAllReplicas", "urlCluster", "hdfsCluster", "gcs", "jdbc", "odbc", "mongodb", "sqlite", "redis"] {
+        assert_eq!(reason(&format!("id IN {}('x')", function)), format!("the expression calls {}", function));
+    }
+    assert_eq!(reason("id IN azureBlobStorageCluster('c')"), "the expression calls azureBlobStorageCluster");
+    assert_eq!(reason("sleepEachRow(1) = 0"), "the expression calls sleepEachRow");
+    assert_eq!(reason("1 UNION ALL SELECT 1"), "the expression contains UNION");
+    assert_eq!(reason("1 SETTINGS readonly = 0"), "the expression contains SETTINGS");
+    assert_eq!(reason("1) OR (1"), "the expression has unbalanced parentheses");
+    assert_eq!(reason("  "), "the WHERE expression is empty");
+    assert!(check_where_expression("1; DROP TABLE t").is_err());
+}
@@ -0,0 +1,135 @@
+mod common;
+
+use clickhouse::test::{handlers, Mock};
+use common::{column, recording_clickhouse, url_decode};
+use mcp_test::{format_columns, parse_datetime_type, utc_timestamp_sql, ClickHouseClient, ColumnInfo, DateTimeType};
+use std::time::Duration;
+
+fn datetime(precision: Option<u8>, timezone: Option<&str>) -> Option<DateTimeType> {
+    Some(DateTimeType { precision, timezone: timezone.map(str::to_string) })
+}
+
+#[test]
+fn test_parse_datetime_type() {
+    assert_eq!(parse_datetime_type("DateTime64(3, 'Europe/Stockholm')"), datetime(Some(3), Some("Europe/Stockholm")));
+    assert_eq!(parse_datetime_type("DateTime64(9,'UTC')"), datetime(Some(9), Some("UTC")));
+    assert_eq!(parse_datetime_type("DateTime64(6)"), datetime(Some(6), None));
+    assert_eq!(parse_datetime_type("DateTime"), datetime(None, None));
+    assert_eq!(parse_datetime_type("DateTime('America/Argentina/Buenos_Aires')"), datetime(None, Some("America/Argentina/Buenos_Aires")));
+    assert_eq!(parse_datetime_type("Nullable(DateTime('Etc/GMT+2'))"), datetime(None, Some("Etc/GMT+2")));
+    assert_eq!(parse_datetime_type("LowCardinality(Nullable(DateTime64(3, 'Asia/Kolkata')))"), datetime(Some(3), Some("Asia/Kolkata")));
+
+    for other in ["Date", "Date32", "String", "DateTime64", "DateTime64(x)", "DateTime(3, 'UTC')", "DateTime(UTC)", "Array(DateTime)"] {
+        assert_eq!(parse_datetime_type(other), None, "{}", other);
+    }
+}
+
+#[test]
+fn test_column_timezones_in_schema_output() {
+    let columns: Vec<ColumnInfo> = [
+        ("at", "DateTime64(3, 'Europe/Stockholm')"),
+        ("seen", "Nullable(DateTime)"),
+        ("day", "Date"),
+        ("name", "String"),
+    ]
+    .into_iter()
+    .map(|(name, r#type)| column(name, r#type).with_timezone(Some("UTC")))
+    .collect();
+
+    let timezones: Vec<Option<&str>> = columns.iter().map(|c| c.timezone.as_deref()).collect();
+    assert_eq!(timezones, [Some("Europe/Stockholm"), Some("UTC"), None, None]);
+    assert_eq!(
+        format_columns(&columns),
+        "- at: DateTime64(3, 'Europe/Stockholm')\n  time zone: Europe/Stockholm\n\
+         - seen: Nullable(DateTime)\n  time zone: UTC (default)\n\
+         - day: Date\n\
+         - name: String\n"
+    );
+
+    // Unknown until the server's time zone is read
+    assert_eq!(column("seen", "DateTime").with_timezone(None).timezone, None);
+    assert_eq!(format_columns(&[column("seen", "DateTime").with_timezone(None)]), "- seen: DateTime\n");
+
+    let json = serde_json::to_value(&columns[0]).unwrap();
+    assert_eq!(json["timezone"], "Europe/Stockholm");
+    assert!(serde_json::to_value(&columns[3]).unwrap().get("timezone").is_none());
+}
+
+#[test]
+fn test_utc_timestamp_sql() {
+    assert_eq!(utc_timestamp_sql("mtime"), "concat(replaceOne(toString(mtime, 'UTC'), ' ', 'T'), 'Z')");
+}
+
+#[tokio::test]
+async fn test_server_timezone_is_cached_and_fills_in_schemas() {
+    let mock = Mock::new();
+    let client = ClickHouseClient::new(mock.url(), "shop", "default", "");
+    assert_eq!(client.effective_timezone(), None);
+
+    mock.add(handlers::provide(vec!["Europe/Stockholm".to_string()]));
+    assert_eq!(client.server_timezone().await.unwrap(), "Europe/Stockholm");
+    // Cached: no second query is answered
+    assert_eq!(client.server_timezone().await.unwrap(), "Europe/Stockholm");
+    assert_eq!(client.effective_timezone(), Some("Europe/Stockholm"));
+
+    mock.add(handlers::provide(vec![1u8]));
+    mock.add(handlers::provide(vec![1u8]));
+    mock.add(handlers::provide(vec![column("id", "UInt64"), column("created", "DateTime"), column("paid", "DateTime64(3, 'UTC')")]));
+    let columns = client.get_table_schema("shop", "orders").await.unwrap();
+    let timezones: Vec<Option<&str>> = columns.iter().map(|c| c.timezone.as_deref()).collect();
+    assert_eq!(timezones, [None, Some("Europe/Stockholm"), Some("UTC")]);
+
+    // A session time zone is what values without their own are shown in
+    let session = ClickHouseClient::new(mock.url(), "shop", "default", "").with_session_timezone("UTC");
+    assert_eq!(session.effective_timezone(), Some("UTC"));
+    assert!(session.effective_config().contains(&("session_timezone", "UTC".to_string())));
+}
+
+#[tokio::test]
+async fn test_guarded_queries_set_timezone_settings() {
+    let (url, requests) = recording_clickhouse().await;
+    let target = |index: usize| url_decode(requests.lock().unwrap()[index].split_whitespace().nth(1).unwrap());
+
+    let client = ClickHouseClient::new(&url, "shop", "default", "")
+        .with_session_timezone("Europe/Stockholm")
+        .with_retry_config(0, Duration::from_millis(1));
+    // The fake's single byte isn't a table list; only the request matters
+    let _ = client.list_all_tables(false).await;
+    let guarded = target(0);
+    assert!(guarded.contains("date_time_output_format=iso"), "{}", guarded);
+    assert!(guarded.contains("session_timezone=Europe/Stockholm"), "{}", guarded);
+
+    // Lookups of names aren't data queries
+    assert!(client.database_exists("shop").await.unwrap());
+    let lookup = target(1);
+    assert!(!lookup.contains("date_time_output_format") && !lookup.contains("session_timezone"), "{}", lookup);
+
+    let client = ClickHouseClient::new(&url, "shop", "default", "").with_retry_config(0, Duration::from_millis(1));
+    let _ = client.list_all_tables(false).await;
+    let default = target(2);
+    assert!(default.contains("date_time_output_format=iso") && !default.contains("session_timezone"), "{}", default);
+}
+
+#[tokio::test]
+async fn test_connect_checks_the_server_supports_session_timezone() {
+    let mock = Mock::new();
+    let client = ClickHouseClient::new(mock.url(), "shop", "default", "")
+        .with_session_timezone("UTC")
+        .with_retry_config(0, Duration::from_millis(1));
+    mock.add(handlers::provide(vec![1u8]));
+    mock.add(handlers::provide(vec!["23.3.1.1".to_string()]));
+    let error = client.health_check().await.unwrap_err();
+    assert_eq!(error.to_string(), "session_timezone (--timezone) requires ClickHouse >= 23.6, server is 23.3");
+
+    let client = ClickHouseClient::new(mock.url(), "shop", "default", "")
+        .with_session_timezone("UTC")
+        .with_retry_config(0, Duration::from_millis(1));
+    mock.add(handlers::provide(vec![1u8]));
+    mock.add(handlers::provide(vec!["23.8.1.1".to_string()]));
+    client.health_check().await.unwrap();
+
+    // Without a session time zone the version isn't needed
+    let client = ClickHouseClient::new(mock.url(), "shop", "default", "").with_retry_config(0, Duration::from_millis(1));
+    mock.add(handlers::provide(vec![1u8]));
+    client.health_check().await.unwrap();
+}
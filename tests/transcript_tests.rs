@@ -0,0 +1,211 @@
+use clickhouse::test::{handlers, Mock};
+use mcp_test::transcript::first_difference;
+use mcp_test::{diff_outputs, is_local_backend, read_transcript, replay, ClickHouseClient, ConnectionManager, DatabaseInfo, Direction, McpServer, SessionRecorder};
+use serde_json::json;
+use std::path::Path;
+use std::sync::Arc;
+
+const FIXTURE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/session_transcript.jsonl");
+
+/// A server on a mock ClickHouse that lists `databases`, with the line
+/// limit the fixture was recorded with.
+async fn mock_server(mock: &Mock, databases: &[&str]) -> McpServer {
+    let manager = Arc::new(ConnectionManager::new(ClickHouseClient::new(mock.url(), "shop", "default", "")));
+    mock.add(handlers::provide(vec![1u8]));
+    manager.connect().await.unwrap();
+    mock.add(handlers::provide(databases.iter().map(|name| DatabaseInfo { name: name.to_string() }).collect::<Vec<_>>()));
+    McpServer::new().with_connection(manager).with_max_line_length(128)
+}
+
+#[tokio::test]
+async fn test_fixture_replays_without_differences() {
+    let entries = read_transcript(Path::new(FIXTURE)).unwrap();
+    let mock = Mock::new();
+
+    let report = replay(mock_server(&mock, &["default", "shop"]).await, &entries).await.unwrap();
+
+    assert!(report.passed(), "{}", report);
+    assert_eq!((report.inbound, report.compared), (5, 5));
+    assert!(report.to_string().ends_with("All responses match\n"));
+}
+
+#[tokio::test]
+async fn test_replay_reports_changed_responses() {
+    let entries = read_transcript(Path::new(FIXTURE)).unwrap();
+    let mock = Mock::new();
+
+    let report = replay(mock_server(&mock, &["default"]).await, &entries).await.unwrap();
+
+    assert!(!report.passed());
+    assert_eq!(report.mismatches.len(), 1);
+    let mismatch = &report.mismatches[0];
+    assert_eq!(mismatch.message, "id 2");
+    assert_eq!(mismatch.path.as_deref(), Some("/result/content/0/text"));
+    assert!(mismatch.recorded.as_deref().unwrap().contains("- shop"));
+    assert!(report.to_string().contains("- id 2: differs at /result/content/0/text\n"), "{}", report);
+}
+
+#[test]
+fn test_fixture_keeps_framing_edge_cases() {
+    let entries = read_transcript(Path::new(FIXTURE)).unwrap();
+    let inbound: Vec<Vec<u8>> = entries.iter().filter(|e| e.direction == Direction::In).map(|e| e.bytes()).collect();
+
+    assert!(inbound[0].starts_with(b"\xEF\xBB\xBF{") && inbound[0].ends_with(b"}\r\n"));
+    assert!(inbound[2].windows(3).any(|bytes| bytes == b"\"\xff\""));
+    assert_eq!(inbound[3].len(), 177);
+    // The last line had no terminator
+    assert!(inbound[4].ends_with(b"}"));
+}
+
+#[test]
+fn test_recorder_keeps_raw_lines_and_redacts_secrets() {
+    let dir = std::env::temp_dir().join(format!("mcp-test-transcript-{}", std::process::id()));
+    let recorder = SessionRecorder::create(&dir, "abc", vec!["s3cret".to_string(), String::new()]).unwrap();
+    assert_eq!(recorder.path(), dir.join("session-abc.jsonl"));
+
+    let lines: [&[u8]; 3] = [b"\xEF\xBB\xBF{\"id\": 1}\r\n", b"{\"x\": \"\xfe\"}\n", b"{\"id\": 2}"];
+    for line in lines {
+        recorder.record_inbound(line);
+    }
+    recorder.record_discarded(5000);
+    recorder.record_outbound(r#"{"error":"cannot connect to http://default:s3cret@ch:8123"}"#);
+
+    let entries = read_transcript(recorder.path()).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(entries.len(), 5);
+    for (entry, line) in entries.iter().zip(lines) {
+        assert_eq!(entry.direction, Direction::In);
+        assert_eq!(entry.bytes(), line);
+        assert!(!entry.redacted);
+    }
+    assert_eq!(entries[3].discarded_length, Some(5000));
+    assert_eq!(entries[3].bytes().len(), 5001);
+
+    let outbound = &entries[4];
+    assert_eq!(outbound.direction, Direction::Out);
+    assert_eq!(outbound.raw.as_deref(), Some("{\"error\":\"cannot connect to http://default:***@ch:8123\"}\n"));
+    assert!(outbound.redacted);
+}
+
+#[test]
+fn test_recorder_redacts_secrets_escaped_in_json() {
+    let dir = std::env::temp_dir().join(format!("mcp-test-transcript-escaped-{}", std::process::id()));
+    let password = r#"pa"ss\word"#;
+    let recorder = SessionRecorder::create(&dir, "abc", vec![password.to_string()]).unwrap();
+
+    let message = json!({"error": format!("authentication failed for password {}", password)}).to_string();
+    assert!(!message.contains(password));
+    recorder.record_outbound(&message);
+    recorder.record_inbound(format!("{}\n", password).as_bytes());
+
+    let entries = read_transcript(recorder.path()).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(entries[0].raw.as_deref(), Some("{\"error\":\"authentication failed for password ***\"}\n"));
+    assert!(entries[0].redacted);
+    assert_eq!(entries[1].raw.as_deref(), Some("***\n"));
+}
+
+#[test]
+fn test_diff_pairs_responses_by_id() {
+    let recorded = [
+        r#"{"jsonrpc":"2.0","result":{"a":1},"id":1}"#,
+        r#"{"jsonrpc":"2.0","result":{"b":2},"id":"two"}"#,
+        r#"{"jsonrpc":"2.0","method":"notifications/message","params":{"at":"yesterday"}}"#,
+        r#"{"jsonrpc":"2.0","error":{"code":-32700},"id":null}"#,
+    ]
+    .map(String::from);
+    // Other order, other formatting, a notification of its own
+    let replayed = [
+        r#"{"jsonrpc":"2.0","method":"ping","id":"keepalive-1"}"#,
+        r#"{"id":"two","jsonrpc":"2.0","result":{"b":2}}"#,
+        r#"{"jsonrpc": "2.0", "result": {"a": 1}, "id": 1}"#,
+        r#"{"jsonrpc":"2.0","error":{"code":-32700},"id":null}"#,
+    ]
+    .map(String::from);
+    assert!(diff_outputs(&recorded, &replayed).is_empty());
+
+    // 1 and "1" are different ids
+    let replayed = [
+        r#"{"jsonrpc":"2.0","result":{"a":1},"id":"1"}"#,
+        r#"{"jsonrpc":"2.0","result":{"b":3},"id":"two"}"#,
+    ]
+    .map(String::from);
+    let mismatches = diff_outputs(&recorded, &replayed);
+    let summary: Vec<(&str, Option<&str>, bool, bool)> = mismatches
+        .iter()
+        .map(|m| (m.message.as_str(), m.path.as_deref(), m.recorded.is_some(), m.replayed.is_some()))
+        .collect();
+    assert_eq!(
+        summary,
+        [
+            ("id \"two\"", Some("/result/b"), true, true),
+            ("id 1", None, true, false),
+            ("id \"1\"", None, false, true),
+            ("message 1 without id", None, true, false),
+        ]
+    );
+}
+
+#[test]
+fn test_first_difference() {
+    assert_eq!(first_difference(&json!({"a": [1, 2]}), &json!({"a": [1, 2]}), ""), None);
+    assert_eq!(first_difference(&json!({"a": [1, 2]}), &json!({"a": [1, 3]}), "").as_deref(), Some("/a/1"));
+    assert_eq!(first_difference(&json!({"a": [1]}), &json!({"a": [1, 2]}), "").as_deref(), Some("/a/1"));
+    assert_eq!(first_difference(&json!({"a/b": 1}), &json!({}), "").as_deref(), Some("/a~1b"));
+    assert_eq!(first_difference(&json!(1), &json!("1"), "").as_deref(), Some(""));
+}
+
+#[tokio::test]
+async fn test_replay_never_runs_admin_statements() {
+    let line = |direction: &str, raw: &str| format!("{}\n", json!({"at": "2026-10-17T07:00:00.000Z", "direction": direction, "raw": format!("{}\n", raw)}));
+    let call = json!({
+        "jsonrpc": "2.0",
+        "method": "tools/call",
+        "params": {"name": "admin_execute", "arguments": {"sql": "TRUNCATE TABLE shop.orders", "confirm": "I understand this runs arbitrary SQL"}},
+        "id": 1
+    });
+    let recorded = json!({"jsonrpc": "2.0", "result": {"content": [{"type": "text", "text": "Ran TRUNCATE"}]}, "id": 1});
+    let dir = std::env::temp_dir().join(format!("mcp-test-replay-admin-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("session.jsonl");
+    std::fs::write(&path, line("in", &call.to_string()) + &line("out", &recorded.to_string())).unwrap();
+    let entries = read_transcript(&path).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    // Nothing is queued for the statement; running it would fail differently
+    let mock = Mock::new();
+    let manager = Arc::new(ConnectionManager::new(ClickHouseClient::new(mock.url(), "shop", "default", "").with_writes_enabled(true)));
+    mock.add(handlers::provide(vec![1u8]));
+    manager.connect().await.unwrap();
+    let server = McpServer::new().with_connection(manager).with_writes_enabled(true).with_admin_enabled(true);
+
+    let report = replay(server, &entries).await.unwrap();
+    assert_eq!(report.mismatches.len(), 1, "{}", report);
+    let replayed = report.mismatches[0].replayed.as_deref().unwrap();
+    assert!(replayed.contains("Unknown tool: admin_execute"), "{}", replayed);
+}
+
+#[test]
+fn test_replay_only_runs_against_local_servers_by_default() {
+    for url in ["http://localhost:8123", "http://127.0.0.1:38211", "https://[::1]:8443/ch", "http://u:p@LOCALHOST:8123?x=1"] {
+        assert!(is_local_backend(url), "{}", url);
+    }
+    for url in ["http://ch.prod.internal:8123", "https://10.0.0.5:8443", "http://localhost.example.com:8123", "http://[2001:db8::1]:8123"] {
+        assert!(!is_local_backend(url), "{}", url);
+    }
+
+    let run = |args: &[&str]| {
+        std::process::Command::new(env!("CARGO_BIN_EXE_mcp-test"))
+            .args(args)
+            .env("CLICKHOUSE_URL", "http://ch.prod.internal:8123")
+            .stdin(std::process::Stdio::null())
+            .output()
+            .unwrap()
+    };
+    let output = run(&["replay", FIXTURE]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Refusing to replay against http://ch.prod.internal:8123"), "{}", stderr);
+
+    assert!(mcp_test::CliOptions::parse(["replay", "t.jsonl", "--against-live"].map(String::from)).unwrap().against_live);
+}
@@ -0,0 +1,129 @@
+mod common;
+
+use common::{answering_clickhouse, rowbinary_strings};
+use mcp_test::{build_group_count_query, build_poll_query, is_identifier_unicode, quote_identifier, ClickHouseClient, ClickHouseError};
+
+const ACCESS_DENIED: &str = "Code: 497. DB::Exception: reader: Not enough privileges. (ACCESS_DENIED) (version 24.3.1.1)\n";
+
+/// Names created with backticked DDL by analysts.
+const UNICODE_NAMES: [&str; 6] = ["Продажи", "销售数据", "売上_2024", "📈_метрики", "👩\u{200D}💻", "données-brutes"];
+
+fn rejection(result: Result<bool, ClickHouseError>) -> Option<String> {
+    match result {
+        Err(ClickHouseError::InvalidIdentifier { reason, .. }) => Some(reason),
+        _ => None,
+    }
+}
+
+#[tokio::test]
+async fn test_unicode_identifiers_are_allowed_by_default() {
+    let (url, queries) = answering_clickhouse(|_| ("200 OK", vec![1])).await;
+    let client = ClickHouseClient::new(&url, "default", "default", "");
+    let ascii_only = ClickHouseClient::new(&url, "default", "default", "").with_ascii_identifiers_only(true);
+
+    for name in UNICODE_NAMES {
+        assert!(client.database_exists(name).await.unwrap(), "{}", name);
+        assert_eq!(queries.lock().unwrap().pop().unwrap(), format!("SELECT count(*) > 0 FROM system.databases WHERE name = '{}' FORMAT RowBinary", name));
+
+        let reason = rejection(ascii_only.database_exists(name).await);
+        if name.is_ascii() {
+            assert_eq!(reason, None);
+        } else {
+            assert_eq!(reason.as_deref(), Some("Identifier can only contain ASCII letters, digits, underscore, and hyphen"), "{}", name);
+        }
+    }
+    assert!(ascii_only.database_exists("valid-name").await.unwrap());
+
+    // Invisible and spoofing characters are refused either way
+    for name in ["名\u{3000}字", "a\u{202E}b", "zero\u{200B}width", "bom\u{FEFF}", "tab\tname", "semi;colon"] {
+        let reason = rejection(client.database_exists(name).await);
+        assert!(reason.is_some_and(|reason| reason.contains("can only contain")), "{:?}", name);
+    }
+}
+
+#[test]
+fn test_identifier_unicode_policy() {
+    assert!(UNICODE_NAMES.iter().flat_map(|name| name.chars()).filter(|c| !c.is_ascii()).all(is_identifier_unicode));
+    for c in ['\u{00A0}', '\u{3000}', '\u{0085}', '\u{200E}', '\u{202A}', '\u{2067}', 'a', '_'] {
+        assert!(!is_identifier_unicode(c), "{:?}", c);
+    }
+    // Names are quoted as they are, never normalized: é precomposed and
+    // decomposed stay two different names
+    assert_eq!(quote_identifier("caf\u{E9}"), "`caf\u{E9}`");
+    assert_eq!(quote_identifier("cafe\u{301}"), "`cafe\u{301}`");
+}
+
+#[tokio::test]
+async fn test_unicode_names_are_bound_as_parameters() {
+    let (url, queries) = answering_clickhouse(|_| ("200 OK", vec![1])).await;
+    let client = ClickHouseClient::new(&url, "default", "default", "");
+
+    assert!(client.table_exists("Продажи", "📈_метрики").await.unwrap());
+    assert_eq!(
+        queries.lock().unwrap().as_slice(),
+        ["SELECT count(*) > 0 FROM system.tables WHERE database = 'Продажи' AND name = '📈_метрики' FORMAT RowBinary"]
+    );
+}
+
+#[tokio::test]
+async fn test_unicode_names_are_quoted_where_interpolated() {
+    let (url, queries) = answering_clickhouse(|query| {
+        if query.contains("system.") {
+            ("500 Internal Server Error", ACCESS_DENIED.as_bytes().to_vec())
+        } else if query.starts_with("EXISTS") {
+            ("200 OK", vec![1])
+        } else if query.starts_with("DESCRIBE TABLE") {
+            ("200 OK", rowbinary_strings(&["金额", "Decimal(18, 2)", "", "", "", "", ""]))
+        } else {
+            ("400 Bad Request", b"unexpected query".to_vec())
+        }
+    })
+    .await;
+    let client = ClickHouseClient::new(&url, "default", "reader", "");
+
+    let columns = client.get_table_schema("Продажи", "销售数据").await.unwrap();
+    assert_eq!(columns[0].name, "金额");
+    let queries = queries.lock().unwrap();
+    assert_eq!(
+        queries.iter().filter(|query| !query.contains("system.")).collect::<Vec<_>>(),
+        [
+            "EXISTS DATABASE `Продажи` FORMAT RowBinary",
+            "EXISTS TABLE `Продажи`.`销售数据` FORMAT RowBinary",
+            "DESCRIBE TABLE `Продажи`.`销售数据` FORMAT RowBinary"
+        ]
+    );
+
+    assert_eq!(
+        build_poll_query("Продажи", "销售数据", "时间", 10),
+        "SELECT toString(`时间`), formatRowNoNewline('JSONEachRow', *) FROM `Продажи`.`销售数据` WHERE `时间` > ? ORDER BY `时间` LIMIT 10"
+    );
+    assert_eq!(
+        build_group_count_query("db", "📈", "👩\u{200D}💻", 5, None),
+        "SELECT toNullable(toString(`👩\u{200D}💻`)) AS value, count() AS count FROM `db`.`📈` GROUP BY `👩\u{200D}💻` ORDER BY count DESC, value LIMIT 5"
+    );
+}
+
+#[tokio::test]
+async fn test_case_insensitive_resolution_folds_only_ascii() {
+    let (url, _) = answering_clickhouse(|query| {
+        if query.contains("system.") {
+            ("500 Internal Server Error", ACCESS_DENIED.as_bytes().to_vec())
+        } else if query.starts_with("EXISTS DATABASE `Заказы`") || query.starts_with("EXISTS DATABASE `orders`") {
+            ("200 OK", vec![1])
+        } else if query.starts_with("EXISTS") {
+            ("200 OK", vec![0])
+        } else if query.starts_with("SHOW DATABASES") {
+            ("200 OK", rowbinary_strings(&["orders", "Заказы"]))
+        } else {
+            ("400 Bad Request", b"unexpected query".to_vec())
+        }
+    })
+    .await;
+    let mut client = ClickHouseClient::new(&url, "default", "reader", "").with_case_insensitive_resolution(true);
+
+    client.switch_database("ORDERS").await.unwrap();
+    assert_eq!(client.default_database(), "orders");
+    assert!(matches!(client.switch_database("заказы").await, Err(ClickHouseError::DatabaseNotFound { .. })));
+    client.switch_database("Заказы").await.unwrap();
+    assert_eq!(client.default_database(), "Заказы");
+}
@@ -0,0 +1,41 @@
+use clickhouse::test::{handlers, Mock};
+use mcp_test::{ClickHouseClient, ClickHouseError, Feature, Version};
+
+#[test]
+fn test_version_parsing() {
+    let version = Version::parse("23.8.2.7").unwrap();
+    assert_eq!((version.major, version.minor, version.patch, version.build), (23, 8, 2, 7));
+
+    assert_eq!(Version::parse("24.3.1.2672-cloud"), Version::parse("24.3.1.2672"));
+    assert_eq!(Version::parse("23.8.16.42.altinitystable").unwrap().build, 42);
+    assert_eq!(Version::parse("22.3").unwrap(), Version::new(22, 3));
+    assert_eq!(Version::parse(" 24.10.1.2812\n").unwrap().minor, 10);
+
+    assert!(Version::parse("").is_none());
+    assert!(Version::parse("23").is_none());
+    assert!(Version::parse("v23.8").is_none());
+    assert!(Version::parse("unknown").is_none());
+}
+
+#[test]
+fn test_version_ordering() {
+    assert!(Version::parse("23.10.1.1").unwrap() > Version::parse("23.8.16.42").unwrap());
+    assert!(Feature::KafkaConsumers.is_supported_by(Version::new(23, 8)));
+    assert!(!Feature::KafkaConsumers.is_supported_by(Version::parse("23.7.6.111").unwrap()));
+}
+
+#[tokio::test]
+async fn test_feature_gating_against_mock() {
+    let mock = Mock::new();
+    let client = ClickHouseClient::new(mock.url(), "default", "default", "");
+
+    mock.add(handlers::provide(vec!["22.3.5.5".to_string()]));
+
+    let error = client.require_feature(Feature::KafkaConsumers).await.unwrap_err();
+    assert!(matches!(error, ClickHouseError::UnsupportedVersion { .. }));
+    assert_eq!(error.to_string(), "system.kafka_consumers requires ClickHouse >= 23.8, server is 22.3");
+
+    // The version is cached, so no further queries are made
+    assert!(client.require_feature(Feature::ExplainEstimate).await.is_ok());
+    assert_eq!(client.server_version().await.unwrap(), Version::parse("22.3.5.5").unwrap());
+}
@@ -0,0 +1,106 @@
+mod common;
+
+use clickhouse::test::{handlers, Mock};
+use common::{query_of, recording_clickhouse};
+use mcp_test::{ClickHouseClient, ConnectionManager, DatabaseInfo, DatabaseVisibility, JsonRpcRequest, McpServer, SYSTEM_DATABASES};
+use serde_json::{json, Value};
+use std::sync::Arc;
+
+#[test]
+fn test_system_databases_hidden_unless_included() {
+    let hidden = DatabaseVisibility::default();
+    assert!(hidden.hide_system);
+    assert!(!hidden.shows("system", None));
+    assert!(!hidden.shows("INFORMATION_SCHEMA", None));
+    assert!(!hidden.shows("information_schema", Some(false)));
+    assert!(hidden.shows("shop", None));
+    assert!(hidden.shows("system", Some(true)));
+
+    // With hiding turned off a call can still ask for a listing without them
+    let shown = DatabaseVisibility::new(false);
+    assert!(shown.shows("system", None));
+    assert!(!shown.shows("system", Some(false)));
+    assert!(shown.shows("shop", Some(false)));
+
+    // Listings filtering in SQL bind the databases to leave out
+    assert_eq!(hidden.hidden_databases(None), SYSTEM_DATABASES);
+    assert!(hidden.hidden_databases(Some(true)).is_empty());
+    assert!(shown.hidden_databases(None).is_empty());
+    assert_eq!(shown.hidden_databases(Some(false)), SYSTEM_DATABASES);
+
+    // Only the exact names are system databases
+    assert!(DatabaseVisibility::is_system("system"));
+    assert!(!DatabaseVisibility::is_system("System"));
+    assert!(!DatabaseVisibility::is_system("system_backup"));
+}
+
+fn call(name: &str, arguments: Value) -> JsonRpcRequest {
+    serde_json::from_value(json!({
+        "jsonrpc": "2.0",
+        "method": "tools/call",
+        "params": {"name": name, "arguments": arguments},
+        "id": 1
+    }))
+    .unwrap()
+}
+
+async fn text(server: &mut McpServer, request: JsonRpcRequest) -> String {
+    let response = server.handle_request(request).await.unwrap();
+    response.result.unwrap()["content"][0]["text"].as_str().unwrap().to_string()
+}
+
+fn databases() -> Vec<DatabaseInfo> {
+    ["INFORMATION_SCHEMA", "default", "information_schema", "shop", "system"]
+        .into_iter()
+        .map(|name| DatabaseInfo { name: name.to_string() })
+        .collect()
+}
+
+#[tokio::test]
+async fn test_list_databases_hides_system_databases() {
+    let mock = Mock::new();
+    let manager = Arc::new(ConnectionManager::new(ClickHouseClient::new(mock.url(), "default", "default", "")));
+    mock.add(handlers::provide(vec![1u8]));
+    manager.connect().await.unwrap();
+
+    let mut server = McpServer::new().with_connection(manager.clone());
+    mock.add(handlers::provide(databases()));
+    let listed = text(&mut server, call("list_databases", json!({}))).await;
+    assert!(listed.contains("- shop\n") && listed.contains("- default\n"), "{}", listed);
+    assert!(!listed.contains("- system") && !listed.contains("- information_schema") && !listed.contains("- INFORMATION_SCHEMA"), "{}", listed);
+    assert!(listed.ends_with("\n3 system databases not listed; pass include_system: true to list them\n"), "{}", listed);
+
+    mock.add(handlers::provide(databases()));
+    let listed = text(&mut server, call("list_databases", json!({"include_system": true}))).await;
+    assert!(listed.contains("- system\n") && !listed.contains("not listed"), "{}", listed);
+
+    // Tools naming a system database still reach it
+    mock.add(handlers::provide(vec![1u8]));
+    let exists = text(&mut server, call("table_exists", json!({"database": "system", "table": "query_log"}))).await;
+    assert_eq!(exists, "Table 'system.query_log' exists\n");
+
+    let mut server = McpServer::new().with_connection(manager).with_hide_system_databases(false);
+    mock.add(handlers::provide(databases()));
+    let listed = text(&mut server, call("list_databases", json!({}))).await;
+    assert!(listed.contains("- system\n") && listed.contains("- INFORMATION_SCHEMA\n"), "{}", listed);
+    mock.add(handlers::provide(databases()));
+    let listed = text(&mut server, call("list_databases", json!({"include_system": false}))).await;
+    assert!(!listed.contains("- system\n"), "{}", listed);
+}
+
+#[tokio::test]
+async fn test_sql_side_listings_follow_the_visibility() {
+    let (url, requests) = recording_clickhouse().await;
+    let manager = Arc::new(ConnectionManager::new(ClickHouseClient::new(&url, "default", "default", "")));
+    manager.connect().await.unwrap();
+
+    for (hide, filter) in [(true, "NOT has(['system','INFORMATION_SCHEMA','information_schema'], database)"), (false, "NOT has([], database)")] {
+        let mut server = McpServer::new().with_connection(manager.clone()).with_hide_system_databases(hide);
+        for tool in ["recently_modified_tables", "catalog_version", "export_catalog"] {
+            requests.lock().unwrap().clear();
+            server.handle_request(call(tool, json!({}))).await.unwrap();
+            let queries: Vec<String> = requests.lock().unwrap().iter().map(|request| query_of(request)).collect();
+            assert!(queries.iter().any(|query| query.contains(filter)), "{} (hide: {}): {:?}", tool, hide, queries);
+        }
+    }
+}
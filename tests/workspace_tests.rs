@@ -0,0 +1,135 @@
+use clickhouse::test::{handlers, Mock};
+use mcp_test::workspace::{RecentTables, MAX_RECENT_TABLES};
+use mcp_test::{ClickHouseClient, ConnectionManager, JsonRpcRequest, McpServer};
+use serde_json::{json, Value};
+use std::sync::Arc;
+
+#[test]
+fn test_recent_tables_keep_the_latest_by_use() {
+    let recent = RecentTables::new();
+    assert!(recent.is_empty());
+    assert!(recent.last(None).is_err());
+
+    for i in 0..MAX_RECENT_TABLES + 3 {
+        recent.touch("web", &format!("t{}", i));
+    }
+    recent.touch("web", "t5");
+    assert_eq!(recent.len(), MAX_RECENT_TABLES);
+
+    let tables = recent.recent();
+    assert_eq!((tables[0].table.as_str(), tables[0].access_count), ("t5", 2));
+    assert_eq!(tables[1].table, format!("t{}", MAX_RECENT_TABLES + 2));
+    // The least recently used ones were dropped
+    assert!(tables.iter().all(|t| !["t0", "t1", "t2"].contains(&t.table.as_str())));
+    assert!(tables.iter().any(|t| t.table == "t3"));
+    assert!(tables[0].last_used.ends_with('Z'));
+
+    recent.touch("shop", "orders");
+    assert_eq!(recent.last(None).unwrap().table, "orders");
+    assert_eq!(recent.last(Some("web")).unwrap().table, "t5");
+    assert!(recent.last(Some("billing")).is_err());
+
+    assert_eq!(recent.clear(), MAX_RECENT_TABLES);
+    assert!(recent.last(None).is_err());
+}
+
+fn call(name: &str, arguments: Value) -> JsonRpcRequest {
+    serde_json::from_value(json!({
+        "jsonrpc": "2.0",
+        "method": "tools/call",
+        "params": {"name": name, "arguments": arguments},
+        "id": 1
+    }))
+    .unwrap()
+}
+
+/// Answers a `count_rows` call: the database and table lookups, then the count.
+fn provide_count(mock: &Mock, rows: u64) {
+    mock.add(handlers::provide(vec![1u8]));
+    mock.add(handlers::provide(vec![1u8]));
+    mock.add(handlers::provide(vec![rows]));
+}
+
+fn text(result: &Value) -> &str {
+    result["content"][0]["text"].as_str().unwrap()
+}
+
+#[tokio::test]
+async fn test_recent_tables_and_last_across_tool_calls() {
+    let mock = Mock::new();
+    let manager = Arc::new(ConnectionManager::new(ClickHouseClient::new(mock.url(), "web", "default", "")));
+    mock.add(handlers::provide(vec![1u8]));
+    manager.connect().await.unwrap();
+    let mut server = McpServer::new().with_connection(Arc::clone(&manager));
+
+    // Nothing used yet
+    let response = server.handle_request(call("count_rows", json!({"database": "web", "table": "@last"}))).await.unwrap();
+    let error = response.error.unwrap();
+    assert_eq!(error["code"], -32602);
+    assert!(error["message"].as_str().unwrap().contains("no table of it has been used in this session yet"), "{}", error);
+    let response = server.handle_request(call("recent_tables", json!({}))).await.unwrap();
+    assert_eq!(text(&response.result.unwrap()), "No tables used in this session yet\n");
+
+    provide_count(&mock, 10);
+    server.handle_request(call("count_rows", json!({"database": "web", "table": "events"}))).await.unwrap();
+    provide_count(&mock, 20);
+    server.handle_request(call("count_rows", json!({"database": "shop", "table": "orders"}))).await.unwrap();
+    // `@last` is shop.orders; a database argument narrows it to that database
+    provide_count(&mock, 20);
+    let response = server.handle_request(call("count_rows", json!({"table": "@last"}))).await.unwrap();
+    assert!(text(&response.result.unwrap()).contains("shop.orders"));
+    provide_count(&mock, 10);
+    let response = server.handle_request(call("count_rows", json!({"database": "web", "table": "@last"}))).await.unwrap();
+    assert!(text(&response.result.unwrap()).contains("web.events"));
+    // Failed calls and table_exists don't count as uses; "@last" can't name a table
+    mock.add(handlers::provide(vec![0u8]));
+    server.handle_request(call("count_rows", json!({"database": "web", "table": "gone"}))).await.unwrap();
+    let response = server.handle_request(call("table_exists", json!({"database": "web", "table": "@last"}))).await.unwrap();
+    assert!(response.error.unwrap()["message"].as_str().unwrap().contains("Invalid identifier"));
+
+    let response = server.handle_request(call("recent_tables", json!({}))).await.unwrap();
+    let result = response.result.unwrap();
+    let tables: Vec<(&str, &str, u64)> = result["structuredContent"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|t| (t["database"].as_str().unwrap(), t["table"].as_str().unwrap(), t["access_count"].as_u64().unwrap()))
+        .collect();
+    assert_eq!(tables, [("web", "events", 2), ("shop", "orders", 2)]);
+    assert!(text(&result).starts_with("Tables used in this session, most recent first (\"@last\" is the first):\n- web.events: 2 uses, last at "));
+
+    // Another session has its own tables
+    let mut other = McpServer::new().with_connection(manager);
+    let response = other.handle_request(call("count_rows", json!({"database": "web", "table": "@last"}))).await.unwrap();
+    assert!(response.error.is_some());
+
+    let response = server.handle_request(call("clear_context", json!({}))).await.unwrap();
+    assert_eq!(text(&response.result.unwrap()), "Forgot 2 recently used tables\n");
+    let response = server.handle_request(call("count_rows", json!({"database": "web", "table": "@last"}))).await.unwrap();
+    assert!(response.error.is_some());
+}
+
+#[tokio::test]
+async fn test_recent_tables_remember_resolved_names() {
+    let mock = Mock::new();
+    let client = ClickHouseClient::new(mock.url(), "web", "default", "").with_case_insensitive_resolution(true);
+    let manager = Arc::new(ConnectionManager::new(client));
+    mock.add(handlers::provide(vec![1u8]));
+    manager.connect().await.unwrap();
+    let mut server = McpServer::new().with_connection(manager);
+
+    // EVENTS resolves to events, and is remembered as that table
+    mock.add(handlers::provide(vec![1u8]));
+    mock.add(handlers::provide(vec![0u8]));
+    mock.add(handlers::provide(vec!["events".to_string()]));
+    mock.add(handlers::provide(vec![10u64]));
+    server.handle_request(call("count_rows", json!({"database": "web", "table": "EVENTS"}))).await.unwrap();
+    provide_count(&mock, 10);
+    server.handle_request(call("count_rows", json!({"database": "web", "table": "events"}))).await.unwrap();
+
+    let response = server.handle_request(call("recent_tables", json!({}))).await.unwrap();
+    let result = response.result.unwrap();
+    assert_eq!(result["structuredContent"][0]["table"], "events");
+    assert_eq!(result["structuredContent"][0]["access_count"], 2);
+    assert_eq!(result["structuredContent"].as_array().unwrap().len(), 1);
+}